@@ -0,0 +1,11 @@
+-- Raw JSONL events from the CLI, kept per session when STORE_TRANSCRIPTS=true.
+-- No foreign key: events can land before the session row is written.
+
+CREATE TABLE IF NOT EXISTS transcripts (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    session_id TEXT NOT NULL,
+    event TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_transcripts_session ON transcripts (session_id, id);
@@ -0,0 +1,13 @@
+-- Files uploaded through /v1/files. Contents live on disk under
+-- PROJECT_ROOT/.files/<id>; chat requests reference them by ID.
+
+CREATE TABLE IF NOT EXISTS files (
+    id TEXT PRIMARY KEY,
+    filename TEXT NOT NULL,
+    purpose TEXT NOT NULL,
+    bytes INTEGER NOT NULL,
+    path TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_files_created ON files (created_at);
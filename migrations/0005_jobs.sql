@@ -0,0 +1,14 @@
+-- Chat completions run in the background (`?async=true`), polled via /v1/jobs.
+-- `response` holds the completion, or the OpenAI error object on failure.
+
+CREATE TABLE IF NOT EXISTS jobs (
+    id TEXT PRIMARY KEY,
+    model TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued',
+    response TEXT,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    started_at TEXT,
+    completed_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_jobs_created ON jobs (created_at);
@@ -0,0 +1,3 @@
+-- Guardrail verdict (JSON) for requests checked by GUARDRAIL_MODE.
+
+ALTER TABLE request_log ADD COLUMN guardrail TEXT;
@@ -0,0 +1,18 @@
+-- Retrieval-augmented project context: projects with `rag_enabled` get the
+-- best-matching chunks of their indexed workspace files prepended to prompts.
+-- `embedding` is the chunk's little-endian f32 vector.
+
+ALTER TABLE projects ADD COLUMN rag_enabled INTEGER NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS project_chunks (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    project_id TEXT NOT NULL REFERENCES projects(id),
+    path TEXT NOT NULL,
+    start_line INTEGER NOT NULL,
+    end_line INTEGER NOT NULL,
+    content TEXT NOT NULL,
+    embedding BLOB NOT NULL,
+    indexed_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_project_chunks_project ON project_chunks (project_id);
@@ -0,0 +1,15 @@
+-- Named, versioned system prompt templates (`/v1/prompts`), referenced
+-- from chat requests by `prompt_id` (or name) with `{{variable}}` values.
+-- Versions are immutable; an update inserts the next version.
+
+CREATE TABLE IF NOT EXISTS prompts (
+    id TEXT NOT NULL,
+    version INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL DEFAULT '',
+    template TEXT NOT NULL,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (id, version)
+);
+
+CREATE INDEX IF NOT EXISTS idx_prompts_name ON prompts (name);
@@ -0,0 +1,6 @@
+-- End user (the OpenAI `user` request field) a request was made for,
+-- attributed under its API key for usage reports and per-user budgets.
+
+ALTER TABLE request_log ADD COLUMN user_id TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_request_log_user ON request_log (user_id);
@@ -0,0 +1,16 @@
+-- Subagents defined per project (`/v1/projects/{id}/agents`), written to
+-- `.claude/agents/<name>.md` in the project's workspace before each run.
+-- Projects are workspace IDs, not necessarily rows of `projects`, so there
+-- is no foreign key. `tools` is a JSON array; NULL inherits every tool.
+
+CREATE TABLE IF NOT EXISTS project_agents (
+    project_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT NOT NULL,
+    prompt TEXT NOT NULL,
+    tools TEXT,
+    model TEXT,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    updated_at TEXT NOT NULL DEFAULT (datetime('now')),
+    PRIMARY KEY (project_id, name)
+);
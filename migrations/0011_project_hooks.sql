@@ -0,0 +1,9 @@
+-- Claude Code hooks per project (`/v1/projects/{id}/hooks`), written to the
+-- workspace's `.claude/settings.json` before each run. `hooks` is the
+-- settings' `hooks` object: matchers with commands by event.
+
+CREATE TABLE IF NOT EXISTS project_hooks (
+    project_id TEXT PRIMARY KEY,
+    hooks TEXT NOT NULL,
+    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
@@ -0,0 +1,21 @@
+-- Headless coding tasks (`/v1/tasks`): an instruction run by the CLI in a
+-- throwaway git worktree of the project's workspace, started at
+-- `base_commit`. `result` holds the summary, changed files and usage, or
+-- the OpenAI error object on failure; `diff` the worktree's changes.
+
+CREATE TABLE IF NOT EXISTS tasks (
+    id TEXT PRIMARY KEY,
+    project_id TEXT NOT NULL,
+    instruction TEXT NOT NULL,
+    model TEXT NOT NULL,
+    base_commit TEXT NOT NULL,
+    session_id TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued',
+    result TEXT,
+    diff TEXT,
+    created_at TEXT NOT NULL DEFAULT (datetime('now')),
+    started_at TEXT,
+    completed_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_tasks_created ON tasks (created_at);
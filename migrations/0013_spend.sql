@@ -0,0 +1,7 @@
+-- Cost of all runs per UTC day, for the server-wide budgets
+-- (`DAILY_BUDGET_USD`, `MONTHLY_BUDGET_USD`).
+
+CREATE TABLE IF NOT EXISTS spend (
+    day TEXT PRIMARY KEY,
+    cost REAL NOT NULL DEFAULT 0
+);
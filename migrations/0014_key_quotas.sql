@@ -0,0 +1,15 @@
+-- Monthly token allowances per API key (by hash) and the tokens each key
+-- used per UTC month (`YYYY-MM`); a new month starts from zero.
+
+CREATE TABLE IF NOT EXISTS key_quotas (
+    key_hash TEXT PRIMARY KEY,
+    monthly_tokens INTEGER NOT NULL,
+    updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE TABLE IF NOT EXISTS key_token_usage (
+    key_hash TEXT NOT NULL,
+    month TEXT NOT NULL,
+    tokens INTEGER NOT NULL DEFAULT 0,
+    PRIMARY KEY (key_hash, month)
+);
@@ -0,0 +1,16 @@
+-- Completions recorded with `RECORD_REQUESTS` for debugging: the request
+-- as received, the CLI invocation it was rendered to (prompt, flags) and
+-- the response. `replay_of` links a replay to the recording it re-ran.
+
+CREATE TABLE IF NOT EXISTS recordings (
+    id TEXT PRIMARY KEY,
+    session_id TEXT,
+    model TEXT NOT NULL,
+    request TEXT NOT NULL,
+    invocation TEXT,
+    response TEXT,
+    replay_of TEXT,
+    created_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE INDEX IF NOT EXISTS idx_recordings_created ON recordings (created_at);
@@ -0,0 +1,21 @@
+-- Embeddings of stored messages for semantic search, filled in the
+-- background by `crate::message_index`. `embedding` is the message's
+-- little-endian f32 vector. An embedding goes with its message, and is
+-- dropped when the content changes (summaries, `db encrypt`) so the
+-- message is embedded again.
+
+CREATE TABLE IF NOT EXISTS message_embeddings (
+    message_id INTEGER PRIMARY KEY REFERENCES messages(id),
+    embedding BLOB NOT NULL,
+    indexed_at TEXT NOT NULL DEFAULT (datetime('now'))
+);
+
+CREATE TRIGGER IF NOT EXISTS message_embeddings_on_delete AFTER DELETE ON messages
+BEGIN
+    DELETE FROM message_embeddings WHERE message_id = OLD.id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS message_embeddings_on_update AFTER UPDATE OF content ON messages
+BEGIN
+    DELETE FROM message_embeddings WHERE message_id = OLD.id;
+END;
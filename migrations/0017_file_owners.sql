@@ -0,0 +1,7 @@
+-- The hash of the API key that uploaded each file (NULL without auth).
+-- Files are only visible to, and usable by, the key that uploaded them;
+-- files uploaded before this column existed belong to keyless callers.
+
+ALTER TABLE files ADD COLUMN key_hash TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_files_key ON files (key_hash, created_at);
@@ -0,0 +1,12 @@
+-- The session a background job or cached response answered, so erasing
+-- the session removes them too. Rows stored before this column existed
+-- are linked through the session ID in their response, where it's
+-- readable (not encrypted).
+
+ALTER TABLE jobs ADD COLUMN session_id TEXT;
+UPDATE jobs SET session_id = json_extract(response, '$.session_id') WHERE json_valid(response);
+CREATE INDEX IF NOT EXISTS idx_jobs_session ON jobs (session_id);
+
+ALTER TABLE response_cache ADD COLUMN session_id TEXT;
+UPDATE response_cache SET session_id = json_extract(response, '$.session_id') WHERE json_valid(response);
+CREATE INDEX IF NOT EXISTS idx_response_cache_session ON response_cache (session_id);
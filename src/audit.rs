@@ -0,0 +1,161 @@
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+
+use axum::body::Body;
+use axum::extract::State;
+use axum::http::Request;
+use axum::middleware::Next;
+use axum::response::Response;
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+
+use crate::auth::{extract_api_key, hash_api_key};
+use crate::claude::process::ProcessReport;
+use crate::client_ip::ClientIp;
+use crate::db;
+use crate::guardrails::Verdict;
+use crate::state::AppState;
+
+/// One row of the `request_log` table.
+#[derive(Debug, Default, Clone)]
+pub struct AuditEntry {
+    pub key_hash: Option<String>,
+    /// End user under the key, from the request's `user` field.
+    pub user: Option<String>,
+    pub client_ip: Option<String>,
+    pub method: String,
+    pub route: String,
+    pub model: Option<String>,
+    pub session_id: Option<String>,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost: f64,
+    pub latency_ms: i64,
+    pub status: u16,
+    /// Lifecycle of the Claude process that served the request, if any.
+    pub process: Option<ProcessReport>,
+    /// Guardrail verdict as JSON, when guardrails are enabled.
+    pub guardrail: Option<String>,
+}
+
+/// Background writer for the request audit log.
+pub struct AuditLog {
+    tx: Option<mpsc::Sender<AuditEntry>>,
+}
+
+impl AuditLog {
+    /// Spawn the writer task. When `enabled` is false nothing is recorded.
+    pub fn spawn(pool: SqlitePool, enabled: bool) -> Self {
+        if !enabled {
+            return Self { tx: None };
+        }
+
+        let (tx, mut rx) = mpsc::channel::<AuditEntry>(1024);
+        tokio::spawn(async move {
+            while let Some(entry) = rx.recv().await {
+                if let Err(e) = db::insert_request_log(&pool, &entry).await {
+                    tracing::warn!(error = %e, "Failed to write audit log entry");
+                }
+            }
+        });
+        Self { tx: Some(tx) }
+    }
+
+    fn start(&self, entry: AuditEntry) -> Option<AuditContext> {
+        let sink = self.tx.clone()?;
+        Some(AuditContext(Arc::new(AuditRecord {
+            started: Instant::now(),
+            entry: Mutex::new(entry),
+            sink,
+        })))
+    }
+}
+
+struct AuditRecord {
+    started: Instant,
+    entry: Mutex<AuditEntry>,
+    sink: mpsc::Sender<AuditEntry>,
+}
+
+impl Drop for AuditRecord {
+    /// The entry is written once every handle is gone, so streaming responses
+    /// are logged with their full latency and final usage.
+    fn drop(&mut self) {
+        let mut entry = std::mem::take(self.entry.get_mut().unwrap_or_else(|e| e.into_inner()));
+        entry.latency_ms = self.started.elapsed().as_millis() as i64;
+        if self.sink.try_send(entry).is_err() {
+            tracing::warn!("Audit log queue full, dropping entry");
+        }
+    }
+}
+
+/// Per-request audit handle, available to handlers as a request extension.
+///
+/// Handlers that spawn background work (e.g. streaming) should move a clone
+/// into the task so the entry is completed when the work finishes.
+#[derive(Clone)]
+pub struct AuditContext(Arc<AuditRecord>);
+
+impl AuditContext {
+    fn update(&self, f: impl FnOnce(&mut AuditEntry)) {
+        if let Ok(mut entry) = self.0.entry.lock() {
+            f(&mut entry);
+        }
+    }
+
+    pub fn set_model(&self, model: &str) {
+        self.update(|e| e.model = Some(model.to_string()));
+    }
+
+    pub fn set_user(&self, user: &str) {
+        self.update(|e| e.user = Some(user.to_string()));
+    }
+
+    pub fn set_session(&self, session_id: &str) {
+        self.update(|e| e.session_id = Some(session_id.to_string()));
+    }
+
+    pub fn set_process(&self, report: &ProcessReport) {
+        self.update(|e| e.process = Some(report.clone()));
+    }
+
+    pub fn set_guardrail(&self, verdict: &Verdict) {
+        let json = serde_json::to_string(verdict).ok();
+        self.update(|e| e.guardrail = json);
+    }
+
+    pub fn add_usage(&self, prompt_tokens: u32, completion_tokens: u32, cost: f64) {
+        self.update(|e| {
+            e.prompt_tokens += prompt_tokens as i64;
+            e.completion_tokens += completion_tokens as i64;
+            e.cost += cost;
+        });
+    }
+}
+
+/// Record every API call in the audit log.
+pub async fn audit_middleware(
+    State(state): State<Arc<AppState>>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    let key_hash = extract_api_key(req.headers(), req.uri().query().unwrap_or(""))
+        .map(|k| hash_api_key(&k));
+    let entry = AuditEntry {
+        key_hash,
+        client_ip: req.extensions().get::<ClientIp>().map(|c| c.0.to_string()),
+        method: req.method().to_string(),
+        route: req.uri().path().to_string(),
+        ..Default::default()
+    };
+
+    let Some(ctx) = state.audit_log.start(entry) else {
+        return next.run(req).await;
+    };
+    req.extensions_mut().insert(ctx.clone());
+
+    let response = next.run(req).await;
+    let status = response.status().as_u16();
+    ctx.update(|e| e.status = status);
+    response
+}
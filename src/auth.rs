@@ -1,6 +1,4 @@
-use std::collections::HashMap;
 use std::sync::Arc;
-use std::time::Instant;
 
 use axum::body::Body;
 use axum::extract::State;
@@ -8,40 +6,15 @@ use axum::http::{HeaderMap, Request, StatusCode};
 use axum::middleware::Next;
 use axum::response::{IntoResponse, Response};
 use serde_json::json;
+use sha2::{Digest, Sha256};
 
+use crate::client_ip::ClientIp;
 use crate::state::AppState;
 
-/// Sliding-window rate limiter per API key.
-pub struct RateLimiter {
-    requests_per_minute: u32,
-    burst: u32,
-    windows: HashMap<String, Vec<Instant>>,
-}
-
-impl RateLimiter {
-    pub fn new(requests_per_minute: u32, burst: u32) -> Self {
-        Self {
-            requests_per_minute,
-            burst,
-            windows: HashMap::new(),
-        }
-    }
-
-    pub fn check(&mut self, key: &str) -> bool {
-        let now = Instant::now();
-        let window = self.windows.entry(key.to_string()).or_default();
-
-        // Remove entries older than 60 seconds
-        window.retain(|t| now.duration_since(*t).as_secs() < 60);
-
-        if window.len() as u32 >= self.requests_per_minute + self.burst {
-            return false;
-        }
-
-        window.push(now);
-        true
-    }
-}
+/// The caller's API key, attached to the request by [`auth_middleware`]
+/// (validated when `REQUIRE_AUTH` is on).
+#[derive(Debug, Clone)]
+pub struct ApiKey(pub String);
 
 /// Extract API key from request headers or query string.
 pub fn extract_api_key(headers: &HeaderMap, query: &str) -> Option<String> {
@@ -77,12 +50,19 @@ fn validate_api_key(key: &str, valid_keys: &[String]) -> bool {
     valid_keys.iter().any(|k| k == key)
 }
 
-const PUBLIC_PATHS: &[&str] = &["/", "/health", "/docs", "/redoc", "/openapi.json"];
+/// Hex SHA-256 of an API key, used wherever a key is persisted.
+pub fn hash_api_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+const PUBLIC_PATHS: &[&str] = &["/", "/health", "/readyz", "/docs", "/redoc", "/openapi.json"];
+/// Paths outside `/admin` that expose internals and get the same gate.
+pub const ADMIN_ONLY_PATHS: &[&str] = &["/v1/chat/completions/raw"];
 
 /// Authentication and rate-limiting middleware.
 pub async fn auth_middleware(
     State(state): State<Arc<AppState>>,
-    req: Request<Body>,
+    mut req: Request<Body>,
     next: Next,
 ) -> Response {
     let path = req.uri().path().to_string();
@@ -92,14 +72,28 @@ pub async fn auth_middleware(
         return next.run(req).await;
     }
 
-    // If auth not required, pass through
-    if !state.config.require_auth {
-        return next.run(req).await;
+    // Admin endpoints need an admin key on public listeners
+    if path == "/admin" || path.starts_with("/admin/") || ADMIN_ONLY_PATHS.contains(&path.as_str()) {
+        return admin_auth(&state, req, next).await;
     }
 
     let query = req.uri().query().unwrap_or("");
     let api_key = extract_api_key(req.headers(), query);
 
+    let client_ip = req.extensions().get::<ClientIp>().copied();
+
+    // If auth not required, pass through, limited per key or client IP
+    if !state.config().require_auth {
+        let valid = api_key.as_deref().filter(|key| validate_api_key(key, &state.config().api_keys));
+        if !within_rate_limit(&state, valid, client_ip) {
+            return rate_limited();
+        }
+        if let Some(key) = api_key {
+            req.extensions_mut().insert(ApiKey(key));
+        }
+        return next.run(req).await;
+    }
+
     let Some(key) = api_key else {
         return error_response(
             StatusCode::UNAUTHORIZED,
@@ -109,7 +103,7 @@ pub async fn auth_middleware(
         );
     };
 
-    if !validate_api_key(&key, &state.config.api_keys) {
+    if !validate_api_key(&key, &state.config().api_keys) {
         return error_response(
             StatusCode::UNAUTHORIZED,
             "authentication_error",
@@ -118,20 +112,65 @@ pub async fn auth_middleware(
         );
     }
 
-    // Rate limiting
-    {
-        let mut limiter = state.rate_limiter.write().await;
-        if !limiter.check(&key) {
+    if !within_rate_limit(&state, Some(&key), client_ip) {
+        return rate_limited();
+    }
+
+    req.extensions_mut().insert(ApiKey(key));
+    next.run(req).await
+}
+
+/// Count a request against its rate limit: the API key's, at its tier's
+/// rate if it sets one, else the client IP's (IPv6 clients by /64).
+/// Requests with neither (over a Unix socket) are not limited.
+fn within_rate_limit(state: &AppState, api_key: Option<&str>, client_ip: Option<ClientIp>) -> bool {
+    let config = state.config();
+    let (bucket, tier) = match (api_key, client_ip) {
+        (Some(key), _) => (key.to_string(), config.key_tier(Some(key))),
+        (None, Some(ip)) => (ip.rate_limit_bucket(), config.key_tier(None)),
+        (None, None) => return true,
+    };
+    match tier.and_then(|t| t.requests_per_minute) {
+        Some(rate) => state.rate_limiter.check_rate(&bucket, rate),
+        None => state.rate_limiter.check(&bucket),
+    }
+}
+
+fn rate_limited() -> Response {
+    error_response(
+        StatusCode::TOO_MANY_REQUESTS,
+        "rate_limit_error",
+        "rate_limit_exceeded",
+        "Rate limit exceeded",
+    )
+}
+
+/// Admin paths: require one of `ADMIN_API_KEYS` when configured. Without admin
+/// keys they are only reachable when auth is disabled altogether (or via an
+/// admin listener, which bypasses this middleware).
+async fn admin_auth(state: &AppState, req: Request<Body>, next: Next) -> Response {
+    if state.config().admin_api_keys.is_empty() {
+        if state.config().require_auth {
             return error_response(
-                StatusCode::TOO_MANY_REQUESTS,
-                "rate_limit_error",
-                "rate_limit_exceeded",
-                "Rate limit exceeded",
+                StatusCode::FORBIDDEN,
+                "permission_error",
+                "admin_disabled",
+                "Admin endpoints are only available on the admin listener",
             );
         }
+        return next.run(req).await;
     }
 
-    next.run(req).await
+    let query = req.uri().query().unwrap_or("");
+    match extract_api_key(req.headers(), query) {
+        Some(key) if validate_api_key(&key, &state.config().admin_api_keys) => next.run(req).await,
+        _ => error_response(
+            StatusCode::UNAUTHORIZED,
+            "authentication_error",
+            "invalid_admin_key",
+            "A valid admin API key is required",
+        ),
+    }
 }
 
 fn error_response(status: StatusCode, error_type: &str, code: &str, message: &str) -> Response {
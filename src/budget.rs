@@ -0,0 +1,272 @@
+//! Server-wide spend ceilings, against a runaway agent emptying the
+//! account overnight. The cost of every run is added to a ledger per UTC
+//! day; once today's spend reaches `DAILY_BUDGET_USD`, or this month's
+//! `MONTHLY_BUDGET_USD`, new completions, commands and tasks fail with
+//! `budget_exceeded` until the window resets. Runs already going finish.
+//!
+//! `POST /admin/budget/override` lifts the ceilings for this replica until
+//! the window resets (or a given time); restarting puts them back.
+//!
+//! When a run takes a window past one of `BUDGET_ALERT_PERCENTS` of its
+//! ceiling, a `budget.threshold_crossed` webhook is sent (see
+//! [`crate::webhooks`]). The ceilings act as a circuit breaker:
+//! `budget.breaker_opened` is sent when runs start being rejected, and
+//! `budget.breaker_closed` when they are let through again, because the
+//! window reset, the ceiling was raised or an admin lifted it.
+
+use std::sync::atomic::Ordering;
+
+use chrono::{DateTime, Datelike, Days, Months, NaiveTime, Utc};
+use serde_json::{json, Value};
+
+use crate::config::Config;
+use crate::db;
+use crate::error::AppError;
+use crate::state::AppState;
+
+/// A ceiling that is reached.
+#[derive(Debug, PartialEq)]
+struct Exceeded {
+    window: &'static str,
+    spent: f64,
+    limit: f64,
+    resets_at: i64,
+}
+
+/// Add a run's cost to today's spend, and announce the alert thresholds
+/// it crossed. Failures are logged.
+pub async fn record(state: &AppState, cost: f64) {
+    if cost <= 0.0 {
+        return;
+    }
+    if let Err(e) = db::add_spend(&state.db, cost).await {
+        tracing::warn!(error = %e, cost, "Failed to record spend");
+        return;
+    }
+    let config = state.config();
+    if config.daily_budget_usd <= 0.0 && config.monthly_budget_usd <= 0.0 {
+        return;
+    }
+    let (day, month) = match db::spend_totals(&state.db).await {
+        Ok(totals) => totals,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to read spend for budget alerts");
+            return;
+        }
+    };
+    let now = Utc::now();
+    let windows = [
+        ("daily", day, config.daily_budget_usd, next_day(now)),
+        ("monthly", month, config.monthly_budget_usd, next_month(now)),
+    ];
+    for (window, spent, limit, resets_at) in windows {
+        if let Some(percent) = crossed(&config.budget_alert_percents, limit, spent, cost) {
+            tracing::warn!(window, percent, spent, limit, "Spend passed a budget alert threshold");
+            state.webhooks.emit(
+                "budget.threshold_crossed",
+                json!({
+                    "window": window,
+                    "percent": percent,
+                    "spent_usd": spent,
+                    "limit_usd": limit,
+                    "resets_at": resets_at,
+                }),
+            );
+        }
+    }
+}
+
+/// The highest of `percents` of `limit` that adding `cost` took the spend
+/// to `spent` past, if any.
+fn crossed(percents: &[f64], limit: f64, spent: f64, cost: f64) -> Option<f64> {
+    if limit <= 0.0 {
+        return None;
+    }
+    let before = spent - cost;
+    percents
+        .iter()
+        .copied()
+        .filter(|p| before < limit * p / 100.0 && spent >= limit * p / 100.0)
+        .max_by(f64::total_cmp)
+}
+
+/// Fail with `budget_exceeded` if a ceiling is reached and not lifted.
+pub async fn check(state: &AppState) -> Result<(), AppError> {
+    let config = state.config();
+    if config.daily_budget_usd <= 0.0 && config.monthly_budget_usd <= 0.0 {
+        breaker(state, None, "disabled");
+        return Ok(());
+    }
+    let now = Utc::now();
+    if lifted_until(state).is_some_and(|until| until > now.timestamp()) {
+        return Ok(());
+    }
+    let (day, month) = db::spend_totals(&state.db).await?;
+    let exceeded = exceeded(&config, day, month, now);
+    breaker(state, exceeded.as_ref(), if exceeded.is_some() { "budget_exceeded" } else { "reset" });
+    match exceeded {
+        Some(e) => {
+            tracing::warn!(window = e.window, spent = e.spent, limit = e.limit, "Spend ceiling reached; rejecting run");
+            Err(AppError::BudgetExceeded {
+                message: format!(
+                    "The server's {} budget of ${:.2} is used up (${:.4} spent); it resets at {}",
+                    e.window,
+                    e.limit,
+                    e.spent,
+                    DateTime::from_timestamp(e.resets_at, 0).unwrap_or(now).to_rfc3339()
+                ),
+                resets_at: e.resets_at,
+            })
+        }
+        None => Ok(()),
+    }
+}
+
+/// Spend, ceilings and override, for `/admin/budget`.
+pub async fn status(state: &AppState) -> Result<Value, AppError> {
+    let config = state.config();
+    let (day, month) = db::spend_totals(&state.db).await?;
+    let now = Utc::now();
+    let window = |spent: f64, limit: f64, resets_at: i64| {
+        json!({
+            "spent_usd": spent,
+            "limit_usd": (limit > 0.0).then_some(limit),
+            "resets_at": resets_at,
+        })
+    };
+    let exceeded = exceeded(&config, day, month, now);
+    let lifted = lifted_until(state).filter(|&until| until > now.timestamp());
+    Ok(json!({
+        "daily": window(day, config.daily_budget_usd, next_day(now)),
+        "monthly": window(month, config.monthly_budget_usd, next_month(now)),
+        "exceeded": exceeded.as_ref().map(|e| e.window),
+        "override_until": lifted,
+        "blocking": exceeded.is_some() && lifted.is_none(),
+    }))
+}
+
+/// Lift the ceilings until `until` (unix time), by default until the
+/// reached window resets (the next UTC midnight if none is).
+pub async fn lift(state: &AppState, until: Option<i64>) -> Result<Value, AppError> {
+    let until = match until {
+        Some(until) => until,
+        None => {
+            let config = state.config();
+            let (day, month) = db::spend_totals(&state.db).await?;
+            let now = Utc::now();
+            let resets_at = exceeded(&config, day, month, now).map(|e| e.resets_at);
+            resets_at.unwrap_or_else(|| next_day(now))
+        }
+    };
+    *state.budget_override.write().unwrap_or_else(|e| e.into_inner()) = Some(until);
+    tracing::warn!(until, "Spend ceilings lifted by admin");
+    breaker(state, None, "override");
+    status(state).await
+}
+
+/// Put the ceilings back after [`lift`].
+pub async fn restore(state: &AppState) -> Result<Value, AppError> {
+    *state.budget_override.write().unwrap_or_else(|e| e.into_inner()) = None;
+    tracing::info!("Spend ceilings restored by admin");
+    let config = state.config();
+    let (day, month) = db::spend_totals(&state.db).await?;
+    breaker(state, exceeded(&config, day, month, Utc::now()).as_ref(), "restored");
+    status(state).await
+}
+
+/// Record whether runs are rejected (`exceeded` is the reached ceiling),
+/// sending `budget.breaker_opened` or `budget.breaker_closed` when that
+/// changes.
+fn breaker(state: &AppState, exceeded: Option<&Exceeded>, reason: &str) {
+    let open = exceeded.is_some();
+    if state.budget_breaker_open.swap(open, Ordering::Relaxed) == open {
+        return;
+    }
+    match exceeded {
+        Some(e) => state.webhooks.emit(
+            "budget.breaker_opened",
+            json!({
+                "reason": reason,
+                "window": e.window,
+                "spent_usd": e.spent,
+                "limit_usd": e.limit,
+                "resets_at": e.resets_at,
+            }),
+        ),
+        None => state.webhooks.emit(
+            "budget.breaker_closed",
+            json!({
+                "reason": reason,
+                "override_until": lifted_until(state),
+            }),
+        ),
+    }
+}
+
+fn lifted_until(state: &AppState) -> Option<i64> {
+    *state.budget_override.read().unwrap_or_else(|e| e.into_inner())
+}
+
+/// The reached ceiling that resets last, if any.
+fn exceeded(config: &Config, day: f64, month: f64, now: DateTime<Utc>) -> Option<Exceeded> {
+    let monthly = Exceeded {
+        window: "monthly",
+        spent: month,
+        limit: config.monthly_budget_usd,
+        resets_at: next_month(now),
+    };
+    let daily = Exceeded {
+        window: "daily",
+        spent: day,
+        limit: config.daily_budget_usd,
+        resets_at: next_day(now),
+    };
+    [monthly, daily].into_iter().find(|e| e.limit > 0.0 && e.spent >= e.limit)
+}
+
+fn next_day(now: DateTime<Utc>) -> i64 {
+    let day = now.date_naive() + Days::new(1);
+    day.and_time(NaiveTime::MIN).and_utc().timestamp()
+}
+
+pub(crate) fn next_month(now: DateTime<Utc>) -> i64 {
+    let first = now.date_naive().with_day(1).unwrap_or(now.date_naive()) + Months::new(1);
+    first.and_time(NaiveTime::MIN).and_utc().timestamp()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exceeded() {
+        let now = DateTime::parse_from_rfc3339("2025-01-31T22:15:00Z").unwrap().with_timezone(&Utc);
+        let jan_31_end = DateTime::parse_from_rfc3339("2025-02-01T00:00:00Z").unwrap().timestamp();
+        assert_eq!(next_day(now), jan_31_end);
+        assert_eq!(next_month(now), jan_31_end);
+
+        let mut config = Config::for_test();
+        config.daily_budget_usd = 10.0;
+        config.monthly_budget_usd = 0.0;
+        assert_eq!(exceeded(&config, 9.99, 500.0, now), None);
+        assert_eq!(exceeded(&config, 10.0, 500.0, now).map(|e| e.window), Some("daily"));
+
+        // The monthly ceiling wins: it resets later
+        config.monthly_budget_usd = 100.0;
+        let mid_month = DateTime::parse_from_rfc3339("2025-03-10T08:00:00Z").unwrap().with_timezone(&Utc);
+        let e = exceeded(&config, 20.0, 150.0, mid_month).unwrap();
+        assert_eq!((e.window, e.resets_at), ("monthly", DateTime::parse_from_rfc3339("2025-04-01T00:00:00Z").unwrap().timestamp()));
+    }
+
+    #[test]
+    fn test_crossed() {
+        let percents = [80.0, 100.0];
+        assert_eq!(crossed(&percents, 10.0, 7.9, 1.0), None);
+        assert_eq!(crossed(&percents, 10.0, 8.0, 1.0), Some(80.0));
+        // Already past 80%: only the next threshold counts
+        assert_eq!(crossed(&percents, 10.0, 9.5, 1.0), None);
+        // One run can jump both; the higher is reported
+        assert_eq!(crossed(&percents, 10.0, 12.0, 5.0), Some(100.0));
+        assert_eq!(crossed(&percents, 0.0, 12.0, 5.0), None);
+    }
+}
@@ -0,0 +1,162 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use sqlx::SqlitePool;
+
+use crate::db;
+use crate::models::openai::ChatCompletionRequest;
+
+struct CacheEntry {
+    response: Value,
+    expires: Instant,
+    last_used: u64,
+}
+
+/// Cache of non-streaming chat completions: an in-memory LRU in front of the
+/// `response_cache` table, so hits survive restarts.
+pub struct ResponseCache {
+    db: SqlitePool,
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<(HashMap<String, CacheEntry>, u64)>,
+}
+
+impl ResponseCache {
+    pub fn new(db: SqlitePool, capacity: usize, ttl: Duration) -> Self {
+        Self {
+            db,
+            capacity: capacity.max(1),
+            ttl,
+            entries: Mutex::new((HashMap::new(), 0)),
+        }
+    }
+
+    /// Look up a cached response, falling back to SQLite on a memory miss.
+    pub async fn get(&self, key: &str) -> Option<Value> {
+        if let Some(hit) = self.get_memory(key) {
+            return Some(hit);
+        }
+        let (response, age) = db::get_cached_response(&self.db, key, self.ttl.as_secs())
+            .await
+            .inspect_err(|e| tracing::warn!(error = %e, "Response cache lookup failed"))
+            .ok()??;
+        let response: Value = serde_json::from_str(&response).ok()?;
+        let remaining = self.ttl.saturating_sub(Duration::from_secs(age.max(0) as u64));
+        self.insert_memory(key, response.clone(), remaining);
+        Some(response)
+    }
+
+    /// Store a response in memory and persist it.
+    pub async fn put(&self, key: &str, response: &Value) {
+        self.insert_memory(key, response.clone(), self.ttl);
+        let session_id = response.get("session_id").and_then(Value::as_str);
+        if let Err(e) =
+            db::put_cached_response(&self.db, key, &response.to_string(), session_id, self.ttl.as_secs()).await
+        {
+            tracing::warn!(error = %e, "Failed to persist cached response");
+        }
+    }
+
+    /// Drop the responses to `session_id` held in memory; see
+    /// [`crate::erasure`].
+    pub fn forget_session(&self, session_id: &str) {
+        if let Ok(mut guard) = self.entries.lock() {
+            guard.0.retain(|_, e| e.response.get("session_id").and_then(Value::as_str) != Some(session_id));
+        }
+    }
+
+    fn get_memory(&self, key: &str) -> Option<Value> {
+        let mut guard = self.entries.lock().ok()?;
+        let (map, tick) = &mut *guard;
+        *tick += 1;
+        let entry = map.get_mut(key)?;
+        if entry.expires <= Instant::now() {
+            map.remove(key);
+            return None;
+        }
+        entry.last_used = *tick;
+        Some(entry.response.clone())
+    }
+
+    fn insert_memory(&self, key: &str, response: Value, ttl: Duration) {
+        let Ok(mut guard) = self.entries.lock() else { return };
+        let (map, tick) = &mut *guard;
+        *tick += 1;
+        if map.len() >= self.capacity && !map.contains_key(key) {
+            let now = Instant::now();
+            map.retain(|_, e| e.expires > now);
+            if map.len() >= self.capacity {
+                if let Some(lru) = map.iter().min_by_key(|(_, e)| e.last_used).map(|(k, _)| k.clone()) {
+                    map.remove(&lru);
+                }
+            }
+        }
+        map.insert(
+            key.to_string(),
+            CacheEntry {
+                response,
+                expires: Instant::now() + ttl,
+                last_used: *tick,
+            },
+        );
+    }
+}
+
+/// Hash of everything that determines a completion's content.
+///
+/// `scope` separates otherwise identical requests that must not share a
+/// result, such as ones routed to different profiles. Sampling parameters are
+/// not included because the CLI ignores them.
+pub fn request_key(request: &ChatCompletionRequest, model: &str, scope: &str) -> String {
+    // serde_json maps are sorted, so the serialization is canonical
+    let normalized = json!({
+        "model": model,
+        "scope": scope,
+        "messages": request.messages,
+        "tools": request.tools,
+        "tool_choice": request.tool_choice,
+        "system_prompt": request.system_prompt,
+        "agent": request.agent,
+        "mode": request.mode,
+    });
+    format!("{:x}", Sha256::digest(normalized.to_string().as_bytes()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cache(capacity: usize) -> ResponseCache {
+        let db = sqlx::sqlite::SqlitePoolOptions::new().connect_lazy("sqlite::memory:").unwrap();
+        ResponseCache::new(db, capacity, Duration::from_secs(60))
+    }
+
+    #[tokio::test]
+    async fn test_lru_eviction() {
+        let cache = cache(2);
+        cache.insert_memory("a", json!(1), cache.ttl);
+        cache.insert_memory("b", json!(2), cache.ttl);
+        assert_eq!(cache.get_memory("a"), Some(json!(1)));
+        cache.insert_memory("c", json!(3), cache.ttl);
+        assert_eq!(cache.get_memory("b"), None);
+        assert_eq!(cache.get_memory("a"), Some(json!(1)));
+        assert_eq!(cache.get_memory("c"), Some(json!(3)));
+
+        cache.insert_memory("d", json!(4), Duration::ZERO);
+        assert_eq!(cache.get_memory("d"), None);
+    }
+
+    #[test]
+    fn test_request_key_ignores_sampling_params() {
+        let parse = |v: Value| serde_json::from_value::<ChatCompletionRequest>(v).unwrap();
+        let a = parse(json!({"model": "x", "messages": [{"role": "user", "content": "hi"}]}));
+        let b = parse(json!({"model": "x", "temperature": 0.2, "messages": [{"role": "user", "content": "hi"}]}));
+        let c = parse(json!({"model": "x", "messages": [{"role": "user", "content": "hello"}]}));
+        assert_eq!(request_key(&a, "m", "default"), request_key(&b, "m", "default"));
+        assert_ne!(request_key(&a, "m", "default"), request_key(&c, "m", "default"));
+        assert_ne!(request_key(&a, "m", "default"), request_key(&a, "m", "beta"));
+    }
+}
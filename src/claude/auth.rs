@@ -0,0 +1,58 @@
+//! The Claude CLI's login. A CLI that is logged out, or whose API key or
+//! OAuth token is invalid or expired, fails every run the same way; such
+//! runs are recognised by the CLI's error text (see
+//! [`parse_auth_error`](crate::claude::parser::parse_auth_error)) and fail
+//! with `claude_auth_error`. `/health` and `/readyz` report the profile as
+//! logged out until one of its runs succeeds again; the `profile.logged_out`
+//! and `profile.logged_in` webhooks announce both changes.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::state::AppState;
+
+/// What to do about a login failure, appended to error messages.
+pub const GUIDANCE: &str = "Log the CLI in again (run `claude` and `/login` as the user the gateway runs as, \
+     or set a valid ANTHROPIC_API_KEY for the profile) and retry; no restart is needed";
+
+/// A profile's last login failure.
+#[derive(Debug, Clone, Serialize)]
+pub struct AuthFailure {
+    /// The CLI's message.
+    pub message: String,
+    /// When it was first seen (unix time).
+    pub since: i64,
+}
+
+/// Note that `profile`'s CLI is not logged in.
+pub fn failed(state: &AppState, profile: &str, message: &str) {
+    let mut failures = state.claude_auth.write().unwrap_or_else(|e| e.into_inner());
+    if !failures.contains_key(profile) {
+        tracing::error!(profile, error = message, "Claude CLI is not logged in");
+        state.webhooks.emit("profile.logged_out", json!({"profile": profile, "error": message}));
+    }
+    failures.entry(profile.to_string()).or_insert_with(|| AuthFailure {
+        message: message.to_string(),
+        since: chrono::Utc::now().timestamp(),
+    });
+}
+
+/// Note that a run of `profile` succeeded, so its CLI is logged in.
+pub fn succeeded(state: &AppState, profile: &str) {
+    let cleared = {
+        let failures = state.claude_auth.read().unwrap_or_else(|e| e.into_inner());
+        failures.contains_key(profile)
+    };
+    if cleared {
+        state.claude_auth.write().unwrap_or_else(|e| e.into_inner()).remove(profile);
+        tracing::info!(profile, "Claude CLI is logged in again");
+        state.webhooks.emit("profile.logged_in", json!({"profile": profile}));
+    }
+}
+
+/// Profiles whose CLI is not logged in.
+pub fn failures(state: &AppState) -> BTreeMap<String, AuthFailure> {
+    state.claude_auth.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
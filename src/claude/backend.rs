@@ -0,0 +1,366 @@
+use std::time::Duration;
+
+use futures::future::BoxFuture;
+use serde_json::{json, Value};
+
+use crate::claude::process::{ClaudeProcess, Invocation, MessageStream, ProcessReport, SpawnOptions};
+use crate::claude::version::CliCapabilities;
+use crate::config::ClaudeProfile;
+use crate::error::AppError;
+
+/// A started run: the tracked process, its message stream and session ID.
+pub type Spawned = (ClaudeProcess, MessageStream, Option<String>);
+
+/// An agent CLI the gateway can drive.
+///
+/// Every backend yields Claude stream-json messages (`system` init,
+/// `assistant`, `result`), which is what the routes consume; non-Claude
+/// backends translate their CLI's output with an [`OutputTranslator`].
+/// Profiles pick a backend with `CLAUDE_PROFILE_<NAME>_BACKEND`.
+pub trait CompletionBackend: Send + Sync {
+    /// Name used in configuration.
+    fn name(&self) -> &'static str;
+
+    /// Vendor reported as `owned_by` in `/v1/models`.
+    fn owned_by(&self) -> &'static str;
+
+    /// Variable the CLI reads its account/config directory from, set from
+    /// the profile's config dir.
+    fn config_dir_var(&self) -> Option<&'static str>;
+
+    /// Start a run and stream its output.
+    fn spawn<'a>(
+        &'a self,
+        profile: &'a ClaudeProfile,
+        caps: CliCapabilities,
+        opts: SpawnOptions<'a>,
+    ) -> BoxFuture<'a, Result<Spawned, AppError>>;
+
+    /// Stop a run started by this backend, giving it `grace` to exit on
+    /// its own before it is killed.
+    fn stop<'a>(&'a self, process: &'a mut ClaudeProcess, grace: Duration) -> BoxFuture<'a, ProcessReport> {
+        Box::pin(process.terminate(grace))
+    }
+}
+
+/// Converts one CLI's JSONL output into Claude stream-json messages.
+pub trait OutputTranslator: Send + Sync {
+    /// Translate one parsed output line into zero or more messages.
+    fn line(&mut self, msg: Value) -> Vec<Value>;
+
+    /// Messages still buffered when the output ends.
+    fn finish(&mut self) -> Vec<Value> {
+        Vec::new()
+    }
+}
+
+/// Look up a backend by configured name.
+pub fn by_name(name: &str) -> Option<&'static dyn CompletionBackend> {
+    match name {
+        "claude" => Some(&ClaudeCli),
+        "codex" => Some(&CodexCli),
+        "gemini" => Some(&GeminiCli),
+        _ => None,
+    }
+}
+
+/// The profile's backend; unknown names fall back to Claude (and are
+/// reported at startup).
+pub fn for_profile(profile: &ClaudeProfile) -> &'static dyn CompletionBackend {
+    by_name(&profile.backend).unwrap_or(&ClaudeCli)
+}
+
+/// The Claude Code CLI (`claude -p --output-format stream-json`).
+pub struct ClaudeCli;
+
+impl CompletionBackend for ClaudeCli {
+    fn name(&self) -> &'static str {
+        "claude"
+    }
+
+    fn owned_by(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn config_dir_var(&self) -> Option<&'static str> {
+        Some("CLAUDE_CONFIG_DIR")
+    }
+
+    fn spawn<'a>(
+        &'a self,
+        profile: &'a ClaudeProfile,
+        caps: CliCapabilities,
+        opts: SpawnOptions<'a>,
+    ) -> BoxFuture<'a, Result<Spawned, AppError>> {
+        Box::pin(ClaudeProcess::spawn(profile, caps, opts))
+    }
+}
+
+/// OpenAI's Codex CLI (`codex exec --json`).
+///
+/// Codex has no system prompt flag, so system prompts are prepended to the
+/// prompt. With client tools, built-in tools are limited to a read-only
+/// sandbox since they cannot be switched off.
+pub struct CodexCli;
+
+impl CompletionBackend for CodexCli {
+    fn name(&self) -> &'static str {
+        "codex"
+    }
+
+    fn owned_by(&self) -> &'static str {
+        "openai"
+    }
+
+    fn config_dir_var(&self) -> Option<&'static str> {
+        Some("CODEX_HOME")
+    }
+
+    fn spawn<'a>(
+        &'a self,
+        profile: &'a ClaudeProfile,
+        _caps: CliCapabilities,
+        opts: SpawnOptions<'a>,
+    ) -> BoxFuture<'a, Result<Spawned, AppError>> {
+        Box::pin(async move {
+            let mut args: Vec<String> = ["exec", "--json", "--skip-git-repo-check", "-m", opts.model]
+                .map(String::from)
+                .into();
+            if opts.disable_builtin_tools {
+                args.extend(["--sandbox".to_string(), "read-only".to_string()]);
+            } else {
+                args.push("--dangerously-bypass-approvals-and-sandbox".to_string());
+            }
+            // Read the prompt from stdin
+            args.push("-".to_string());
+            let input = fold_prompt(&opts);
+            let invocation = Invocation {
+                args,
+                translator: Some(Box::new(CodexOutput::default())),
+                ..Default::default()
+            };
+            ClaudeProcess::spawn_with(profile, opts, invocation, input.as_bytes()).await
+        })
+    }
+}
+
+/// Google's Gemini CLI (`gemini --output-format stream-json`, prompt on stdin).
+///
+/// System prompts are prepended to the prompt. The CLI has no config dir
+/// variable; set credentials such as `GEMINI_API_KEY` in the profile's env.
+pub struct GeminiCli;
+
+impl CompletionBackend for GeminiCli {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn owned_by(&self) -> &'static str {
+        "google"
+    }
+
+    fn config_dir_var(&self) -> Option<&'static str> {
+        None
+    }
+
+    fn spawn<'a>(
+        &'a self,
+        profile: &'a ClaudeProfile,
+        _caps: CliCapabilities,
+        opts: SpawnOptions<'a>,
+    ) -> BoxFuture<'a, Result<Spawned, AppError>> {
+        Box::pin(async move {
+            let mut args: Vec<String> = ["--output-format", "stream-json", "-m", opts.model]
+                .map(String::from)
+                .into();
+            // Without --yolo, tools needing approval are refused in headless mode
+            if !opts.disable_builtin_tools {
+                args.push("--yolo".to_string());
+            }
+            let input = fold_prompt(&opts);
+            let invocation = Invocation {
+                args,
+                translator: Some(Box::new(GeminiOutput::default())),
+                ..Default::default()
+            };
+            ClaudeProcess::spawn_with(profile, opts, invocation, input.as_bytes()).await
+        })
+    }
+}
+
+/// System prompt, tool instructions and prompt as one text, for CLIs
+/// without system prompt flags.
+fn fold_prompt(opts: &SpawnOptions<'_>) -> String {
+    [opts.system_prompt, opts.append_system_prompt, Some(opts.prompt)]
+        .into_iter()
+        .flatten()
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+fn init_message(session_id: &Value) -> Value {
+    json!({"type": "system", "subtype": "init", "session_id": session_id})
+}
+
+fn assistant_message(session_id: &Value, text: &str) -> Value {
+    json!({
+        "type": "assistant",
+        "session_id": session_id,
+        "message": {"role": "assistant", "content": [{"type": "text", "text": text}]},
+    })
+}
+
+fn result_message(session_id: &Value, error: Option<&str>, input_tokens: u64, output_tokens: u64) -> Value {
+    json!({
+        "type": "result",
+        "subtype": if error.is_some() { "error_during_execution" } else { "success" },
+        "is_error": error.is_some(),
+        "result": error,
+        "session_id": session_id,
+        "usage": {"input_tokens": input_tokens, "output_tokens": output_tokens},
+    })
+}
+
+/// `codex exec --json` events: `thread.started`, `item.completed` (agent
+/// messages), `turn.completed` with usage, `turn.failed` and `error`.
+#[derive(Default)]
+struct CodexOutput {
+    thread_id: Value,
+}
+
+impl OutputTranslator for CodexOutput {
+    fn line(&mut self, msg: Value) -> Vec<Value> {
+        match msg["type"].as_str().unwrap_or_default() {
+            "thread.started" => {
+                self.thread_id = msg["thread_id"].clone();
+                vec![init_message(&self.thread_id)]
+            }
+            "item.completed" if msg["item"]["type"] == "agent_message" => msg["item"]["text"]
+                .as_str()
+                .map(|text| vec![assistant_message(&self.thread_id, text)])
+                .unwrap_or_default(),
+            "turn.completed" => {
+                let usage = &msg["usage"];
+                let input = usage["input_tokens"].as_u64().unwrap_or(0);
+                let output = usage["output_tokens"].as_u64().unwrap_or(0);
+                vec![result_message(&self.thread_id, None, input, output)]
+            }
+            "turn.failed" => {
+                let error = msg["error"]["message"].as_str().unwrap_or("Codex turn failed");
+                vec![result_message(&self.thread_id, Some(error), 0, 0)]
+            }
+            "error" => {
+                let error = msg["message"].as_str().unwrap_or("Codex error");
+                vec![result_message(&self.thread_id, Some(error), 0, 0)]
+            }
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Gemini `stream-json` events: `init`, `message` deltas, `tool_use`,
+/// `tool_result` and `result` with stats. Deltas are buffered into one
+/// assistant message per stretch of text, like Claude's content blocks.
+#[derive(Default)]
+struct GeminiOutput {
+    session_id: Value,
+    text: String,
+}
+
+impl GeminiOutput {
+    fn flush(&mut self) -> Vec<Value> {
+        if self.text.trim().is_empty() {
+            self.text.clear();
+            return Vec::new();
+        }
+        let text = std::mem::take(&mut self.text);
+        vec![assistant_message(&self.session_id, &text)]
+    }
+}
+
+impl OutputTranslator for GeminiOutput {
+    fn line(&mut self, msg: Value) -> Vec<Value> {
+        match msg["type"].as_str().unwrap_or_default() {
+            "init" => {
+                self.session_id = msg["session_id"].clone();
+                vec![init_message(&self.session_id)]
+            }
+            "message" if msg["role"] == "assistant" => {
+                self.text.push_str(msg["content"].as_str().unwrap_or_default());
+                Vec::new()
+            }
+            "tool_use" | "tool_result" => self.flush(),
+            "result" => {
+                let mut out = self.flush();
+                let stats = &msg["stats"];
+                let error = (msg["status"] != "success")
+                    .then(|| msg["error"]["message"].as_str().unwrap_or("Gemini run failed"));
+                out.push(result_message(
+                    &self.session_id,
+                    error,
+                    stats["input_tokens"].as_u64().unwrap_or(0),
+                    stats["output_tokens"].as_u64().unwrap_or(0),
+                ));
+                out
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    fn finish(&mut self) -> Vec<Value> {
+        self.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::claude::parser::{extract_assistant_content, extract_usage};
+
+    fn run(translator: &mut dyn OutputTranslator, lines: &[Value]) -> Vec<Value> {
+        let mut out: Vec<Value> = lines.iter().flat_map(|l| translator.line(l.clone())).collect();
+        out.extend(translator.finish());
+        out
+    }
+
+    #[test]
+    fn test_codex_translation() {
+        let out = run(
+            &mut CodexOutput::default(),
+            &[
+                json!({"type": "thread.started", "thread_id": "t1"}),
+                json!({"type": "turn.started"}),
+                json!({"type": "item.completed", "item": {"type": "reasoning", "text": "hmm"}}),
+                json!({"type": "item.completed", "item": {"type": "agent_message", "text": "Hi"}}),
+                json!({"type": "turn.completed", "usage": {"input_tokens": 10, "output_tokens": 2}}),
+            ],
+        );
+        assert_eq!(out.len(), 3);
+        assert_eq!(out[0]["session_id"], "t1");
+        assert_eq!(extract_assistant_content(&out[1]).as_deref(), Some("Hi"));
+        assert_eq!(extract_usage(&out[2]).unwrap().input_tokens, 10);
+        assert_eq!(out[2]["session_id"], "t1");
+    }
+
+    #[test]
+    fn test_gemini_translation() {
+        let out = run(
+            &mut GeminiOutput::default(),
+            &[
+                json!({"type": "init", "session_id": "g1", "model": "gemini-2.5-pro"}),
+                json!({"type": "message", "role": "user", "content": "hi"}),
+                json!({"type": "message", "role": "assistant", "content": "Hel", "delta": true}),
+                json!({"type": "message", "role": "assistant", "content": "lo", "delta": true}),
+                json!({"type": "tool_use", "tool_name": "ls"}),
+                json!({"type": "message", "role": "assistant", "content": "Done", "delta": true}),
+                json!({"type": "result", "status": "success", "stats": {"input_tokens": 7, "output_tokens": 3}}),
+            ],
+        );
+        let texts: Vec<_> = out.iter().filter_map(extract_assistant_content).collect();
+        assert_eq!(texts, ["Hello", "Done"]);
+        let result = out.last().unwrap();
+        assert_eq!(result["is_error"], false);
+        assert_eq!(extract_usage(result).unwrap().output_tokens, 3);
+    }
+}
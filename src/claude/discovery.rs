@@ -0,0 +1,75 @@
+use std::path::{Path, PathBuf};
+
+/// Locate a Claude CLI binary when `CLAUDE_BINARY_PATH` is not set.
+///
+/// Searches `PATH` first, then common install locations that are often
+/// missing from a service user's `PATH`: the CLI's own local install,
+/// npm global prefixes, Volta, nvm (newest Node first), bun and Homebrew.
+pub fn find_claude_binary() -> Option<PathBuf> {
+    candidates().into_iter().find(|p| is_executable(p))
+}
+
+fn candidates() -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = std::env::var_os("PATH")
+        .map(|p| std::env::split_paths(&p).map(|dir| dir.join("claude")).collect())
+        .unwrap_or_default();
+
+    let home = std::env::var_os("HOME").map(PathBuf::from);
+    if let Some(ref home) = home {
+        paths.push(home.join(".claude/local/claude"));
+        paths.push(home.join(".npm-global/bin/claude"));
+        paths.push(home.join(".local/bin/claude"));
+    }
+    if let Some(prefix) = std::env::var_os("NPM_CONFIG_PREFIX") {
+        paths.push(PathBuf::from(prefix).join("bin/claude"));
+    }
+    if let Some(ref home) = home {
+        let volta = std::env::var_os("VOLTA_HOME")
+            .map(PathBuf::from)
+            .unwrap_or_else(|| home.join(".volta"));
+        paths.push(volta.join("bin/claude"));
+        paths.extend(nvm_bins(home));
+        paths.push(home.join(".bun/bin/claude"));
+    }
+    paths.push(PathBuf::from("/usr/local/bin/claude"));
+    paths.push(PathBuf::from("/opt/homebrew/bin/claude"));
+    paths
+}
+
+/// `~/.nvm/versions/node/<version>/bin/claude`, newest version first.
+fn nvm_bins(home: &Path) -> Vec<PathBuf> {
+    let nvm_dir = std::env::var_os("NVM_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| home.join(".nvm"));
+    let Ok(entries) = std::fs::read_dir(nvm_dir.join("versions/node")) else {
+        return Vec::new();
+    };
+
+    let mut versions: Vec<(Vec<u64>, PathBuf)> = entries
+        .filter_map(|e| e.ok())
+        .map(|e| {
+            let name = e.file_name().to_string_lossy().to_string();
+            let key = name
+                .trim_start_matches('v')
+                .split('.')
+                .map(|n| n.parse().unwrap_or(0))
+                .collect();
+            (key, e.path().join("bin/claude"))
+        })
+        .collect();
+    versions.sort_by(|a, b| b.0.cmp(&a.0));
+    versions.into_iter().map(|(_, p)| p).collect()
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+    std::fs::metadata(path)
+        .map(|m| m.is_file() && m.permissions().mode() & 0o111 != 0)
+        .unwrap_or(false)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
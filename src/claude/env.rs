@@ -0,0 +1,88 @@
+use crate::claude::backend;
+use crate::config::{ClaudeProfile, Config};
+
+/// Server variables passed to the CLI unless `CLAUDE_ENV_PASSTHROUGH` says
+/// otherwise. A trailing `*` matches a prefix.
+pub const DEFAULT_PASSTHROUGH: &[&str] = &[
+    "PATH", "HOME", "USER", "LOGNAME", "SHELL", "LANG", "LC_*", "TZ", "TMPDIR", "TERM",
+    "XDG_*", "HTTP_PROXY", "HTTPS_PROXY", "NO_PROXY", "ALL_PROXY", "http_proxy",
+    "https_proxy", "no_proxy", "all_proxy", "NODE_*", "SSL_CERT_FILE", "SSL_CERT_DIR",
+    "ANTHROPIC_*", "CLAUDE_CODE_*", "CLAUDE_CONFIG_DIR",
+];
+
+/// Build the complete environment for a spawned CLI process.
+///
+/// Starts from a scrubbed copy of the server's environment (only variables
+/// matching `CLAUDE_ENV_PASSTHROUGH`, or everything with `CLAUDE_ENV_INHERIT`)
+/// so secrets like `API_KEYS` never reach the agent, then layers `CLAUDE_ENV`,
+/// the profile's env, the project's env and the profile's config dir (as
+/// `CLAUDE_CONFIG_DIR` or the backend's equivalent).
+/// Later layers win.
+pub fn build_env(
+    config: &Config,
+    profile: &ClaudeProfile,
+    project_id: Option<&str>,
+) -> Vec<(String, String)> {
+    let mut env: Vec<(String, String)> = std::env::vars()
+        .filter(|(k, _)| config.claude_env_inherit || is_passed_through(k, &config.claude_env_passthrough))
+        .collect();
+
+    let project_env = project_id.and_then(|id| config.project_env(id)).unwrap_or_default();
+    let layers = config.claude_env.iter().chain(&profile.env).chain(project_env);
+    for (k, v) in layers {
+        set(&mut env, k, v);
+    }
+    let config_dir_var = backend::for_profile(profile).config_dir_var();
+    if let (Some(dir), Some(var)) = (&profile.config_dir, config_dir_var) {
+        set(&mut env, var, &dir.to_string_lossy());
+    }
+    env
+}
+
+fn set(env: &mut Vec<(String, String)>, key: &str, value: &str) {
+    match env.iter_mut().find(|(k, _)| k == key) {
+        Some(entry) => entry.1 = value.to_string(),
+        None => env.push((key.to_string(), value.to_string())),
+    }
+}
+
+fn is_passed_through(key: &str, patterns: &[String]) -> bool {
+    patterns.iter().any(|p| match p.strip_suffix('*') {
+        Some(prefix) => key.starts_with(prefix),
+        None => key == p,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_passthrough_patterns() {
+        let patterns: Vec<String> = DEFAULT_PASSTHROUGH.iter().map(|s| s.to_string()).collect();
+        assert!(is_passed_through("PATH", &patterns));
+        assert!(is_passed_through("LC_ALL", &patterns));
+        assert!(is_passed_through("ANTHROPIC_BASE_URL", &patterns));
+        assert!(!is_passed_through("API_KEYS", &patterns));
+        assert!(!is_passed_through("PATHX", &patterns));
+    }
+
+    #[test]
+    fn test_layers_override_in_order() {
+        let mut config = Config::for_test();
+        config.claude_env_inherit = false;
+        config.claude_env_passthrough = Vec::new();
+        config.claude_env = vec![("A".into(), "global".into()), ("B".into(), "global".into())];
+        config.project_env = vec![("PROJ_1".into(), vec![("B".into(), "project".into())])];
+        let mut profile = config.default_profile().clone();
+        profile.env = vec![("A".into(), "profile".into())];
+        profile.config_dir = Some("/acct".into());
+
+        let env = build_env(&config, &profile, Some("proj-1"));
+        let get = |k: &str| env.iter().find(|(key, _)| key == k).map(|(_, v)| v.as_str());
+        assert_eq!(get("A"), Some("profile"));
+        assert_eq!(get("B"), Some("project"));
+        assert_eq!(get("CLAUDE_CONFIG_DIR"), Some("/acct"));
+        assert_eq!(env.len(), 3);
+    }
+}
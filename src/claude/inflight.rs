@@ -0,0 +1,178 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use futures::StreamExt;
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+use crate::claude::process::MessageStream;
+
+/// Output of one run shared by every caller with the same request hash.
+#[derive(Default)]
+struct Run {
+    /// Messages so far, replayed to callers that join late.
+    buffer: Vec<serde_json::Value>,
+    subscribers: Vec<mpsc::UnboundedSender<serde_json::Value>>,
+}
+
+impl Run {
+    fn subscribe(&mut self) -> MessageStream {
+        let (tx, rx) = mpsc::unbounded_channel();
+        for msg in &self.buffer {
+            let _ = tx.send(msg.clone());
+        }
+        self.subscribers.push(tx);
+        Box::pin(UnboundedReceiverStream::new(rx))
+    }
+
+    fn publish(&mut self, msg: serde_json::Value) {
+        self.subscribers.retain(|tx| tx.send(msg.clone()).is_ok());
+        self.buffer.push(msg);
+    }
+}
+
+/// Registry of in-flight runs keyed by normalized request hash, so identical
+/// concurrent requests share one CLI process instead of spawning their own.
+#[derive(Default)]
+pub struct Inflight {
+    runs: Mutex<HashMap<String, Arc<Mutex<Run>>>>,
+}
+
+/// Result of [`Inflight::join_or_reserve`].
+pub enum Joined {
+    /// An identical request is running: its output, and its Claude session ID.
+    Follower(MessageStream, Option<String>),
+    /// This caller runs the request and must [`Reservation::publish`] it.
+    Leader(Reservation),
+}
+
+impl Inflight {
+    /// Attach to a running identical request, or reserve `key` for this one.
+    ///
+    /// If the run being joined fails to start, the caller retries on its own.
+    pub async fn join_or_reserve(self: &Arc<Self>, key: String) -> Joined {
+        loop {
+            let (run, leader) = {
+                let mut runs = self.runs.lock().unwrap();
+                match runs.get(&key) {
+                    Some(run) => (Arc::clone(run), false),
+                    None => {
+                        let run = Arc::new(Mutex::new(Run::default()));
+                        runs.insert(key.clone(), Arc::clone(&run));
+                        (run, true)
+                    }
+                }
+            };
+            if leader {
+                return Joined::Leader(Reservation {
+                    inflight: Arc::clone(self),
+                    key,
+                    run: Some(run),
+                });
+            }
+
+            // The first message carries the session ID; put it back afterwards
+            let mut stream = run.lock().unwrap().subscribe();
+            let Some(first) = stream.next().await else { continue };
+            let session_id = first
+                .get("session_id")
+                .and_then(|v| v.as_str())
+                .map(str::to_string);
+            tracing::info!(session_id = session_id.as_deref().unwrap_or("-"), "Joined identical in-flight request");
+            let stream = futures::stream::once(async { first }).chain(stream);
+            return Joined::Follower(Box::pin(stream), session_id);
+        }
+    }
+
+    /// Whether anyone is still reading the run for `key`.
+    pub fn has_listeners(&self, key: &str) -> bool {
+        let runs = self.runs.lock().unwrap();
+        runs.get(key)
+            .is_some_and(|run| run.lock().unwrap().subscribers.iter().any(|tx| !tx.is_closed()))
+    }
+
+    fn finish(&self, key: &str) {
+        self.runs.lock().unwrap().remove(key);
+    }
+}
+
+/// The leader's claim on a request hash. Dropping it unpublished (e.g. the
+/// spawn failed) releases the hash and sends waiting followers back to retry.
+pub struct Reservation {
+    inflight: Arc<Inflight>,
+    key: String,
+    run: Option<Arc<Mutex<Run>>>,
+}
+
+impl Reservation {
+    /// Fan `stream` out to the leader and all followers; returns the leader's copy.
+    pub fn publish(mut self, mut stream: MessageStream) -> MessageStream {
+        let run = self.run.take().expect("reservation already published");
+        let own = run.lock().unwrap().subscribe();
+        let inflight = Arc::clone(&self.inflight);
+        let key = std::mem::take(&mut self.key);
+        tokio::spawn(async move {
+            while let Some(msg) = stream.next().await {
+                run.lock().unwrap().publish(msg);
+            }
+            inflight.finish(&key);
+            run.lock().unwrap().subscribers.clear();
+        });
+        own
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        let Some(run) = self.run.take() else { return };
+        self.inflight.finish(&self.key);
+        run.lock().unwrap().subscribers.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_followers_share_leader_output() {
+        let inflight = Arc::new(Inflight::default());
+        let Joined::Leader(reservation) = inflight.join_or_reserve("k".into()).await else {
+            panic!("first caller must lead");
+        };
+
+        let (tx, rx) = mpsc::unbounded_channel();
+        tx.send(serde_json::json!({"type": "system", "session_id": "s1"})).unwrap();
+        let leader = reservation.publish(Box::pin(UnboundedReceiverStream::new(rx)));
+        tokio::task::yield_now().await;
+
+        let Joined::Follower(follower, sid) = inflight.join_or_reserve("k".into()).await else {
+            panic!("second caller must follow");
+        };
+        assert_eq!(sid.as_deref(), Some("s1"));
+        assert!(inflight.has_listeners("k"));
+
+        tx.send(serde_json::json!({"type": "result"})).unwrap();
+        drop(tx);
+        let leader: Vec<_> = leader.collect().await;
+        let follower: Vec<_> = follower.collect().await;
+        assert_eq!(leader.len(), 2);
+        assert_eq!(leader, follower);
+        assert!(!inflight.has_listeners("k"));
+
+        assert!(matches!(inflight.join_or_reserve("k".into()).await, Joined::Leader(_)));
+    }
+
+    #[tokio::test]
+    async fn test_dropped_reservation_promotes_follower() {
+        let inflight = Arc::new(Inflight::default());
+        let leader = inflight.join_or_reserve("k".into()).await;
+        let follower = {
+            let inflight = Arc::clone(&inflight);
+            tokio::spawn(async move { inflight.join_or_reserve("k".into()).await })
+        };
+        tokio::task::yield_now().await;
+        drop(leader);
+        assert!(matches!(follower.await.unwrap(), Joined::Leader(_)));
+    }
+}
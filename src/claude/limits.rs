@@ -0,0 +1,230 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use tokio::process::Command;
+
+use crate::config::Config;
+
+/// Memory and CPU caps for every spawned CLI, so a runaway process can't
+/// take the host down with it.
+///
+/// Limits are `setrlimit` values set between `fork` and `exec`: the data
+/// segment (`RLIMIT_DATA`, which unlike `RLIMIT_AS` leaves Node's address
+/// space reservations alone) and total CPU seconds (`RLIMIT_CPU`). With a
+/// delegated cgroup v2 directory (`PROCESS_CGROUP_ROOT`) each process gets
+/// its own child cgroup instead, with `memory.max` and `cpu.max`, which also
+/// covers the CLI's subprocesses. Docker sandboxes run the CLI outside this
+/// process tree; use `docker run` flags there.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResourceLimits {
+    memory_bytes: Option<u64>,
+    cpu_seconds: Option<u64>,
+    /// Share of one core, cgroup only.
+    cpu_percent: Option<u32>,
+    cgroup_root: Option<PathBuf>,
+}
+
+/// Which limit a process ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LimitHit {
+    Memory,
+    Cpu,
+}
+
+impl LimitHit {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Memory => "memory",
+            Self::Cpu => "cpu",
+        }
+    }
+}
+
+/// The limits of one process, from spawn until it is reaped.
+#[derive(Debug)]
+pub struct AppliedLimits {
+    cgroup: Option<PathBuf>,
+    rlimit_memory: Option<u64>,
+    rlimit_cpu: Option<u64>,
+}
+
+impl ResourceLimits {
+    /// Build the limits from `PROCESS_MEMORY_LIMIT_MB`,
+    /// `PROCESS_CPU_LIMIT_SECONDS`, `PROCESS_CPU_PERCENT` and
+    /// `PROCESS_CGROUP_ROOT`; `None` when no limit is set.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let nonzero = |v: u64| (v > 0).then_some(v);
+        let limits = Self {
+            memory_bytes: nonzero(config.process_memory_limit_mb).map(|mb| mb * 1024 * 1024),
+            cpu_seconds: nonzero(config.process_cpu_limit_seconds),
+            cpu_percent: (config.process_cpu_percent > 0).then_some(config.process_cpu_percent),
+            cgroup_root: config.process_cgroup_root.clone(),
+        };
+        if limits.cpu_percent.is_some() && limits.cgroup_root.is_none() {
+            tracing::warn!("PROCESS_CPU_PERCENT needs PROCESS_CGROUP_ROOT; ignoring it");
+        }
+        let any = limits.memory_bytes.is_some() || limits.cpu_seconds.is_some() || limits.cpu_percent.is_some();
+        any.then_some(limits)
+    }
+
+    /// Set up the limits for a process about to be spawned with `cmd`. A
+    /// cgroup that can't be created is logged and replaced by rlimits.
+    pub fn apply(&self, cmd: &mut Command) -> AppliedLimits {
+        let cgroup = self.cgroup_root.as_deref().and_then(|root| {
+            self.create_cgroup(root)
+                .inspect_err(|e| {
+                    tracing::warn!(root = %root.display(), error = %e, "Failed to create process cgroup; using rlimits")
+                })
+                .ok()
+        });
+        let applied = AppliedLimits {
+            rlimit_memory: self.memory_bytes.filter(|_| cgroup.is_none()),
+            rlimit_cpu: self.cpu_seconds,
+            cgroup,
+        };
+        applied.install(cmd);
+        applied
+    }
+
+    fn create_cgroup(&self, root: &Path) -> std::io::Result<PathBuf> {
+        let dir = root.join(format!("claude-{}", uuid::Uuid::new_v4().as_simple()));
+        std::fs::create_dir(&dir)?;
+        let limits = || -> std::io::Result<()> {
+            if let Some(bytes) = self.memory_bytes {
+                std::fs::write(dir.join("memory.max"), bytes.to_string())?;
+                // Without this the kernel swaps instead of enforcing the limit
+                let _ = std::fs::write(dir.join("memory.swap.max"), "0");
+            }
+            if let Some(percent) = self.cpu_percent {
+                std::fs::write(dir.join("cpu.max"), format!("{} 100000", u64::from(percent) * 1000))?;
+            }
+            Ok(())
+        };
+        limits().inspect_err(|_| {
+            let _ = std::fs::remove_dir(&dir);
+        })?;
+        Ok(dir)
+    }
+}
+
+impl AppliedLimits {
+    #[cfg(unix)]
+    fn install(&self, cmd: &mut Command) {
+        let (memory, cpu) = (self.rlimit_memory, self.rlimit_cpu);
+        if memory.is_none() && cpu.is_none() {
+            return;
+        }
+        // SAFETY: the closure only calls setrlimit, which is async-signal-safe
+        unsafe {
+            cmd.pre_exec(move || {
+                if let Some(bytes) = memory {
+                    set_rlimit(libc::RLIMIT_DATA, bytes, bytes)?;
+                }
+                if let Some(secs) = cpu {
+                    // SIGXCPU at the soft limit, SIGKILL shortly after
+                    set_rlimit(libc::RLIMIT_CPU, secs, secs + 5)?;
+                }
+                Ok(())
+            });
+        }
+    }
+
+    #[cfg(not(unix))]
+    fn install(&self, _cmd: &mut Command) {}
+
+    /// Move the spawned process into its cgroup.
+    pub fn attach(&self, pid: u32) {
+        if let Some(ref dir) = self.cgroup {
+            if let Err(e) = std::fs::write(dir.join("cgroup.procs"), pid.to_string()) {
+                tracing::warn!(pid, cgroup = %dir.display(), error = %e, "Failed to move process into its cgroup");
+            }
+        }
+    }
+
+    /// After the process exited: which limit, if any, ended it. Removes the
+    /// cgroup along with anything the CLI left running in it.
+    pub async fn finish(&self, exit_code: Option<i32>) -> Option<LimitHit> {
+        let mut hit = None;
+        if let Some(ref dir) = self.cgroup {
+            let events = std::fs::read_to_string(dir.join("memory.events")).unwrap_or_default();
+            let oom_kills = events
+                .lines()
+                .find_map(|l| l.strip_prefix("oom_kill "))
+                .and_then(|n| n.trim().parse::<u64>().ok())
+                .unwrap_or(0);
+            if oom_kills > 0 {
+                hit = Some(LimitHit::Memory);
+            }
+            let _ = std::fs::write(dir.join("cgroup.kill"), "1");
+            for _ in 0..20 {
+                if std::fs::remove_dir(dir).is_ok() {
+                    break;
+                }
+                tokio::time::sleep(Duration::from_millis(50)).await;
+            }
+        }
+        #[cfg(unix)]
+        if hit.is_none() {
+            hit = match exit_code.map(|c| -c) {
+                Some(libc::SIGXCPU) if self.rlimit_cpu.is_some() => Some(LimitHit::Cpu),
+                // V8 aborts when an allocation fails
+                Some(libc::SIGABRT) if self.rlimit_memory.is_some() => Some(LimitHit::Memory),
+                _ => None,
+            };
+        }
+        #[cfg(not(unix))]
+        let _ = exit_code;
+        hit
+    }
+}
+
+#[cfg(all(target_os = "linux", target_env = "gnu"))]
+type Resource = libc::__rlimit_resource_t;
+#[cfg(all(unix, not(all(target_os = "linux", target_env = "gnu"))))]
+type Resource = libc::c_int;
+
+#[cfg(unix)]
+fn set_rlimit(resource: Resource, soft: u64, hard: u64) -> std::io::Result<()> {
+    let limit = libc::rlimit { rlim_cur: soft as libc::rlim_t, rlim_max: hard as libc::rlim_t };
+    // SAFETY: `limit` is a valid rlimit for the duration of the call
+    if unsafe { libc::setrlimit(resource, &limit) } == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_config() {
+        let mut config = Config::for_test();
+        config.process_memory_limit_mb = 0;
+        config.process_cpu_limit_seconds = 0;
+        config.process_cpu_percent = 0;
+        assert_eq!(ResourceLimits::from_config(&config), None);
+
+        config.process_memory_limit_mb = 2048;
+        let limits = ResourceLimits::from_config(&config).unwrap();
+        assert_eq!(limits.memory_bytes, Some(2048 * 1024 * 1024));
+        assert_eq!(limits.cpu_seconds, None);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cpu_limit_hit() {
+        let limits = ResourceLimits { memory_bytes: None, cpu_seconds: Some(1), cpu_percent: None, cgroup_root: None };
+        let mut cmd = Command::new("sh");
+        cmd.args(["-c", "while :; do :; done"]);
+        let applied = limits.apply(&mut cmd);
+        let status = cmd.spawn().unwrap().wait().await.unwrap();
+
+        use std::os::unix::process::ExitStatusExt;
+        let code = status.signal().map(|s| -s);
+        assert_eq!(applied.finish(code).await, Some(LimitHit::Cpu));
+        assert_eq!(applied.finish(Some(0)).await, None);
+    }
+}
@@ -1,43 +1,134 @@
 use std::collections::HashMap;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, RwLock as StdRwLock};
+use std::time::Duration;
 
-use futures::Stream;
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use serde_json::json;
 use tokio::sync::RwLock;
 
-use crate::claude::process::ClaudeProcess;
-use crate::config::Config;
+use crate::claude::backend::{self, CompletionBackend};
+use crate::claude::limits::LimitHit;
+use crate::claude::mock::MockBackend;
+use crate::claude::plan_limits::PlanLimits;
+use crate::claude::pool::WarmPool;
+use crate::claude::process::{ClaudeProcess, ProcessReport, SpawnOptions};
+use crate::claude::slots::{SlotError, SlotPermit, Slots};
+use crate::claude::version::CliCapabilities;
+use crate::config::{ClaudeProfile, Config};
 use crate::error::AppError;
+use crate::metrics::Metrics;
+use crate::priority::Priority;
+use crate::registry::SessionRegistry;
+use crate::validation::check_project_id;
+use crate::webhooks::Webhooks;
 
-/// Manages concurrent Claude CLI processes.
+/// A tracked process, the backend that started it and the concurrency slot
+/// it occupies.
+struct ActiveSession {
+    process: ClaudeProcess,
+    backend: &'static dyn CompletionBackend,
+    profile: String,
+    model: String,
+    started_at: DateTime<Utc>,
+    /// The `chatcmpl-` ID the run answers, once known.
+    completion_id: Option<String>,
+    _permit: SlotPermit,
+}
+
+/// A running process as listed by `/admin/processes`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ProcessInfo {
+    pub session_id: String,
+    pub pid: Option<u32>,
+    pub profile: String,
+    pub backend: &'static str,
+    pub model: String,
+    pub started_at: DateTime<Utc>,
+    pub elapsed_ms: u64,
+    pub rss_kb: Option<u64>,
+    pub peak_rss_kb: Option<u64>,
+    pub cpu_ms: Option<u64>,
+    /// Average CPU use over the process's lifetime; 100 is one full core.
+    pub cpu_percent: Option<f64>,
+}
+
+/// Manages concurrent agent CLI processes, spawned through each profile's
+/// [`CompletionBackend`].
 pub struct ClaudeManager {
-    config: Config,
-    active: Arc<RwLock<HashMap<String, ClaudeProcess>>>,
+    /// CLI capabilities per profile name, probed at startup.
+    caps: HashMap<String, CliCapabilities>,
+    active: Arc<RwLock<HashMap<String, ActiveSession>>>,
+    /// One per allowed concurrent session; waiters are served by priority.
+    slots: Arc<Slots>,
     max_concurrent: usize,
+    /// How long a request may wait for a free slot (zero = fail immediately).
+    queue_timeout: Duration,
+    draining: AtomicBool,
+    /// Pre-spawned processes for the default profile, if enabled; rebuilt
+    /// on config reload.
+    pool: StdRwLock<Option<Arc<WarmPool>>>,
+    /// Synthetic responses instead of CLI processes (`CLAUDE_BACKEND=mock`).
+    mock: Option<MockBackend>,
+    metrics: Arc<Metrics>,
+    /// Where sessions are claimed when replicas share a registry.
+    registry: Option<Arc<SessionRegistry>>,
+    /// Told when sessions finish or fail.
+    webhooks: Webhooks,
+    /// Fed the subscription usage runs report.
+    plan_limits: Arc<PlanLimits>,
 }
 
 impl ClaudeManager {
-    pub fn new(config: Config) -> Self {
+    pub fn new(
+        config: &Config,
+        caps: HashMap<String, CliCapabilities>,
+        metrics: Arc<Metrics>,
+        registry: Option<Arc<SessionRegistry>>,
+        webhooks: Webhooks,
+        plan_limits: Arc<PlanLimits>,
+    ) -> Self {
         let max = config.max_concurrent_sessions;
+        let default_caps = caps.get(&config.default_profile().name).copied().unwrap_or_default();
+        let mock = MockBackend::from_config(config);
+        let slots = Slots::new(max);
+        let pool = WarmPool::from_config(config, default_caps, Arc::clone(&slots)).filter(|_| mock.is_none());
+        if let Some(ref pool) = pool {
+            pool.refill();
+        }
         Self {
-            config,
+            caps,
             active: Arc::new(RwLock::new(HashMap::new())),
+            slots,
             max_concurrent: max,
+            queue_timeout: Duration::from_secs(config.queue_timeout_seconds),
+            draining: AtomicBool::new(false),
+            pool: StdRwLock::new(pool),
+            mock,
+            metrics,
+            registry,
+            webhooks,
+            plan_limits,
         }
     }
 
-    /// Spawn a Claude CLI process and return the JSONL stream.
+    /// Spawn a process through the profile's backend and return its
+    /// (Claude-format) JSONL stream.
     ///
-    /// The process is tracked for concurrent-session limiting and can be
-    /// killed via [`stop_session`].
+    /// The process holds a concurrency slot until it is removed from tracking
+    /// and can be killed via [`stop_session`]. When all slots are taken the
+    /// request waits in line for up to `QUEUE_TIMEOUT_SECONDS`, behind those of
+    /// higher priority (`opts.priority`). With a shared registry the session
+    /// is also claimed there, which enforces `GLOBAL_MAX_CONCURRENT_SESSIONS`.
+    /// In mock mode nothing is spawned or tracked.
     pub async fn create_session(
         &self,
         session_id: &str,
-        prompt: &str,
-        model: &str,
-        system_prompt: Option<&str>,
-        append_system_prompt: Option<&str>,
-        disable_builtin_tools: bool,
+        profile: &ClaudeProfile,
+        opts: SpawnOptions<'_>,
     ) -> Result<
         (
             Pin<Box<dyn Stream<Item = serde_json::Value> + Send>>,
@@ -45,45 +136,210 @@ impl ClaudeManager {
         ),
         AppError,
     > {
-        let count = self.active.read().await.len();
-        if count >= self.max_concurrent {
-            return Err(AppError::ServiceUnavailable(format!(
-                "Maximum concurrent sessions ({}) reached",
-                self.max_concurrent
-            )));
+        if self.is_draining() {
+            return Err(AppError::ServiceUnavailable(
+                "Server is shutting down".to_string(),
+            ));
         }
 
-        let (process, stream, claude_sid) = ClaudeProcess::spawn(
-            &self.config,
-            prompt,
-            model,
-            system_prompt,
-            append_system_prompt,
-            disable_builtin_tools,
-        )
-        .await?;
+        if let Some(ref mock) = self.mock {
+            return Ok((mock.stream(session_id, &opts), Some(session_id.to_string())));
+        }
+        let profile_name = profile.name.clone();
+        let plan_limits = Arc::clone(&self.plan_limits);
+
+        // A warm process comes with the slot it holds
+        let (warm, permit) = match self.pool().and_then(|pool| pool.take(profile, &opts)) {
+            Some((process, permit)) => (Some(process), permit),
+            None => (None, self.acquire_slot(opts.priority).await?),
+        };
+        if let Some(ref registry) = self.registry {
+            if let Err(e) = registry.claim(session_id).await {
+                if let Some(mut process) = warm {
+                    process.kill().await;
+                }
+                return Err(e);
+            }
+        }
+
+        let caps = self.caps(profile);
+        let backend = backend::for_profile(profile);
+        let model = opts.model;
+        let started = match warm {
+            Some(process) => process.start_warm(opts.prompt).await.map_err(|e| {
+                tracing::warn!(error = %e, "Warm Claude process failed; spawning a new one");
+            }),
+            None => Err(()),
+        };
+        let spawned = match started {
+            Ok(started) => Ok(started),
+            Err(()) => backend.spawn(profile, caps, opts).await,
+        };
+        let (process, stream, claude_sid) = match spawned {
+            Ok(spawned) => spawned,
+            Err(e) => {
+                self.release(session_id).await;
+                drop(permit);
+                self.refill_pool();
+                return Err(e);
+            }
+        };
 
         let key = claude_sid
             .clone()
             .unwrap_or_else(|| session_id.to_string());
-        self.active.write().await.insert(key, process);
+        if let (Some(registry), true) = (self.registry.as_ref(), key != session_id) {
+            registry.rename(session_id, &key).await;
+        }
+        self.active.write().await.insert(
+            key,
+            ActiveSession {
+                process,
+                backend,
+                profile: profile.name.clone(),
+                model: model.to_string(),
+                started_at: Utc::now(),
+                completion_id: None,
+                _permit: permit,
+            },
+        );
+
+        let stream = stream.inspect(move |msg| plan_limits.observe(&profile_name, msg));
+        Ok((Box::pin(stream), claude_sid))
+    }
 
-        Ok((stream, claude_sid))
+    /// The probed capabilities of `profile`'s CLI.
+    pub fn caps(&self, profile: &ClaudeProfile) -> CliCapabilities {
+        self.caps.get(&profile.name).copied().unwrap_or_default()
+    }
+
+    async fn acquire_slot(&self, priority: Priority) -> Result<SlotPermit, AppError> {
+        let full = || {
+            AppError::ServiceUnavailable(format!(
+                "Maximum concurrent sessions ({}) reached",
+                self.max_concurrent
+            ))
+        };
+        let closed = || AppError::ServiceUnavailable("Server is shutting down".to_string());
+
+        // Idle warm processes give up their slots before anyone waits
+        let pool = self.pool();
+        loop {
+            match self.slots.try_acquire() {
+                Ok(permit) => return Ok(permit),
+                Err(SlotError::Closed) => return Err(closed()),
+                Err(SlotError::Full) => {}
+            }
+            match pool {
+                Some(ref pool) if pool.evict().await => {}
+                _ => break,
+            }
+        }
+        if self.queue_timeout.is_zero() {
+            return Err(full());
+        }
+
+        tracing::info!(timeout = ?self.queue_timeout, priority = priority.as_str(), "All session slots busy; queueing request");
+        match self.slots.acquire(priority, self.queue_timeout).await {
+            Ok(permit) => Ok(permit),
+            Err(SlotError::Closed) => Err(closed()),
+            Err(SlotError::Full) => Err(full()),
+        }
+    }
+
+    /// Requests waiting for a session slot, by priority.
+    pub fn queued(&self) -> serde_json::Value {
+        self.slots.waiting()
+    }
+
+    /// Mark a session as no longer running in the shared registry.
+    async fn release(&self, session_id: &str) {
+        if let Some(ref registry) = self.registry {
+            registry.release(session_id).await;
+        }
     }
 
     /// Kill a running session by its ID.
     pub async fn stop_session(&self, session_id: &str) {
-        if let Some(mut process) = self.active.write().await.remove(session_id) {
-            process.kill().await;
-            tracing::info!(session_id, "Claude session stopped");
+        self.terminate_session(session_id, Duration::ZERO).await;
+    }
+
+    /// Note the completion ID a running session answers, which is all a
+    /// client sees of it, so it can be cancelled by it.
+    pub async fn track_completion(&self, session_id: &str, completion_id: &str) {
+        if let Some(session) = self.active.write().await.get_mut(session_id) {
+            session.completion_id = Some(completion_id.to_string());
+        }
+        if let Some(ref registry) = self.registry {
+            registry.track_completion(completion_id, session_id).await;
         }
     }
 
+    /// Stop the session running `completion_id`. Returns its session ID, or
+    /// `None` if no such completion is running here.
+    pub async fn cancel_completion(&self, completion_id: &str) -> Option<String> {
+        let session_id = self
+            .active
+            .read()
+            .await
+            .iter()
+            .find(|(_, s)| s.completion_id.as_deref() == Some(completion_id))
+            .map(|(sid, _)| sid.clone())?;
+        self.terminate_session(&session_id, Duration::ZERO).await?;
+        Some(session_id)
+    }
+
+    /// Stop a running session, escalating from `SIGTERM` to `SIGKILL` after
+    /// `grace`. Returns the process report, or `None` if the session is not
+    /// running.
+    pub async fn terminate_session(&self, session_id: &str, grace: Duration) -> Option<ProcessReport> {
+        let mut session = self.active.write().await.remove(session_id)?;
+        let report = session.backend.stop(&mut session.process, grace).await;
+        self.metrics.record_process(&report);
+        self.release(session_id).await;
+        tracing::info!(session_id, "Claude session stopped");
+        self.report_end(session_id, &session, &report, Some("stopped"));
+        drop(session);
+        self.refill_pool();
+        Some(report)
+    }
+
     /// Remove a finished session from tracking and reap the child process.
-    pub async fn session_finished(&self, session_id: &str) {
-        if let Some(mut process) = self.active.write().await.remove(session_id) {
-            process.reap().await;
-        }
+    ///
+    /// Returns the process's lifecycle measurements, which are also added to
+    /// the aggregate metrics.
+    pub async fn session_finished(&self, session_id: &str) -> Option<ProcessReport> {
+        let mut session = self.active.write().await.remove(session_id)?;
+        let report = session.process.reap().await;
+        self.metrics.record_process(&report);
+        self.release(session_id).await;
+        tracing::debug!(session_id, report = ?report, "Claude process finished");
+        self.report_end(session_id, &session, &report, None);
+        drop(session);
+        self.refill_pool();
+        Some(report)
+    }
+
+    /// Send `session.finished`, or `session.failed` for a run that was
+    /// stopped, exited non-zero or hit a resource limit.
+    fn report_end(&self, session_id: &str, session: &ActiveSession, report: &ProcessReport, reason: Option<&str>) {
+        let reason = reason
+            .or(report.limit_hit.map(LimitHit::as_str))
+            .or(report.exit_code.filter(|&code| code < 0).map(|_| "signal"))
+            .or(report.exit_code.filter(|&code| code > 0).map(|_| "exit"));
+        let event = if reason.is_some() { "session.failed" } else { "session.finished" };
+        self.webhooks.emit(
+            event,
+            json!({
+                "session_id": session_id,
+                "profile": session.profile,
+                "model": session.model,
+                "started_at": session.started_at,
+                "duration_ms": report.duration_ms,
+                "exit_code": report.exit_code,
+                "reason": reason,
+            }),
+        );
     }
 
     /// Number of currently active sessions.
@@ -91,40 +347,168 @@ impl ClaudeManager {
         self.active.read().await.len()
     }
 
+    /// The running processes with their resource use, longest-running first.
+    pub async fn processes(&self) -> Vec<ProcessInfo> {
+        let map = self.active.read().await;
+        let mut list: Vec<ProcessInfo> = map
+            .iter()
+            .map(|(sid, session)| {
+                // A warm process was spawned before its session started
+                let age = session.process.age();
+                let usage = session.process.usage();
+                ProcessInfo {
+                    session_id: sid.clone(),
+                    pid: session.process.pid(),
+                    profile: session.profile.clone(),
+                    backend: session.backend.name(),
+                    model: session.model.clone(),
+                    started_at: session.started_at,
+                    elapsed_ms: (Utc::now() - session.started_at).num_milliseconds().max(0) as u64,
+                    rss_kb: usage.map(|u| u.rss_kb),
+                    peak_rss_kb: session.process.peak_rss_kb(),
+                    cpu_ms: usage.map(|u| u.cpu_ms),
+                    cpu_percent: usage
+                        .map(|u| (u.cpu_ms as f64 / age.as_millis().max(1) as f64 * 1000.0).round() / 10.0),
+                }
+            })
+            .collect();
+        list.sort_by_key(|p| std::cmp::Reverse(p.elapsed_ms));
+        list
+    }
+
     /// List active session IDs.
     pub async fn active_session_ids(&self) -> Vec<String> {
         self.active.read().await.keys().cloned().collect()
     }
 
-    /// Stop all sessions and reap all child processes.
-    pub async fn cleanup_all(&self) {
-        let mut map = self.active.write().await;
-        for (sid, mut process) in map.drain() {
-            process.kill().await;
-            tracing::info!(session_id = %sid, "Session cleaned up");
+    /// Kill sessions older than `max_age` and drop tracked processes that
+    /// already exited without being collected. Returns `(killed, reaped)`.
+    pub async fn reap_stale(&self, max_age: Duration) -> (usize, usize) {
+        // Take the stale sessions out under the lock, then stop them without
+        // it so other requests aren't held up by the kills
+        let stale: Vec<(String, ActiveSession, bool)> = {
+            let mut map = self.active.write().await;
+            let ids: Vec<(String, bool)> = map
+                .iter_mut()
+                .filter_map(|(sid, session)| {
+                    let exited = session.process.has_exited();
+                    (exited || session.process.age() > max_age).then(|| (sid.clone(), exited))
+                })
+                .collect();
+            ids.into_iter()
+                .filter_map(|(sid, exited)| map.remove(&sid).map(|session| (sid, session, exited)))
+                .collect()
+        };
+
+        let (mut killed, mut reaped) = (0, 0);
+        for (sid, mut session, exited) in stale {
+            if exited {
+                self.metrics.record_process(&session.process.reap().await);
+                reaped += 1;
+            } else {
+                let age_secs = session.process.age().as_secs();
+                self.metrics.record_process(&session.backend.stop(&mut session.process, Duration::ZERO).await);
+                killed += 1;
+                tracing::warn!(session_id = %sid, age_secs, "Killed Claude session exceeding timeout");
+            }
+            self.release(&sid).await;
         }
+        self.refill_pool();
+        (killed, reaped)
     }
 
-    /// Log how many active sessions remain (useful for debugging leaks).
-    pub async fn debug_sessions(&self) {
-        let map = self.active.read().await;
-        if !map.is_empty() {
-            let ids: Vec<_> = map.keys().collect();
-            tracing::warn!(
-                count = map.len(),
-                sessions = ?ids,
-                "Active sessions still tracked"
-            );
+    fn pool(&self) -> Option<Arc<WarmPool>> {
+        self.pool.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Top the warm pool up with slots that came free.
+    fn refill_pool(&self) {
+        if let Some(pool) = self.pool() {
+            pool.refill();
+        }
+    }
+
+    /// Replace the warm pool with one built from `config`, killing the old
+    /// one's idle processes first so their slots go to the new one.
+    pub async fn rebuild_pool(&self, config: &Config) {
+        let old = self.pool.write().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(old) = old {
+            old.shutdown().await;
         }
+        if self.mock.is_some() || self.is_draining() {
+            return;
+        }
+        let caps = self.caps.get(&config.default_profile().name).copied().unwrap_or_default();
+        let pool = WarmPool::from_config(config, caps, Arc::clone(&self.slots));
+        if let Some(ref pool) = pool {
+            pool.refill();
+            tracing::info!("Warm pool rebuilt");
+        }
+        *self.pool.write().unwrap_or_else(|e| e.into_inner()) = pool;
+    }
+
+    /// Warm pool statistics, or `None` when the pool is disabled.
+    pub fn pool_stats(&self) -> Option<serde_json::Value> {
+        self.pool().map(|pool| pool.stats())
+    }
+
+    /// Stop accepting new sessions; running ones are left to finish.
+    pub fn begin_drain(&self) {
+        self.draining.store(true, Ordering::SeqCst);
+        // Wake queued requests so they fail instead of waiting out the queue
+        self.slots.close();
+    }
+
+    /// Whether the manager is refusing new sessions due to shutdown.
+    pub fn is_draining(&self) -> bool {
+        self.draining.load(Ordering::SeqCst)
+    }
+
+    /// Wait up to `grace` for active sessions to finish on their own.
+    ///
+    /// Returns `true` if every session finished within the grace period.
+    pub async fn drain(&self, grace: Duration) -> bool {
+        self.begin_drain();
+        if let Some(pool) = self.pool() {
+            pool.shutdown().await;
+        }
+        let deadline = tokio::time::Instant::now() + grace;
+        loop {
+            let remaining = self.active_count().await;
+            if remaining == 0 {
+                return true;
+            }
+            if tokio::time::Instant::now() >= deadline {
+                tracing::warn!(remaining, "Shutdown grace period expired");
+                return false;
+            }
+            tracing::info!(remaining, "Waiting for active sessions to finish");
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+    }
+
+    /// Stop all sessions at once, each given `grace` before `SIGKILL`, and
+    /// reap their processes. Returns the IDs of the stopped sessions.
+    pub async fn cleanup_all(&self, grace: Duration) -> Vec<String> {
+        let sessions: Vec<_> = self.active.write().await.drain().collect();
+        let stops = sessions.into_iter().map(|(sid, mut session)| async move {
+            self.metrics.record_process(&session.backend.stop(&mut session.process, grace).await);
+            self.release(&sid).await;
+            tracing::info!(session_id = %sid, "Session cleaned up");
+            sid
+        });
+        futures::future::join_all(stops).await
     }
 }
 
-/// Create a project directory under `project_root`.
+/// Create a project directory under `project_root`. Fails for IDs that
+/// would point outside it.
 pub fn create_project_directory(
     project_root: &std::path::Path,
     project_id: &str,
-) -> std::path::PathBuf {
+) -> Result<std::path::PathBuf, AppError> {
+    check_project_id(project_id)?;
     let path = project_root.join(project_id);
     let _ = std::fs::create_dir_all(&path);
-    path
+    Ok(path)
 }
@@ -0,0 +1,177 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+use serde_json::{json, Value};
+
+use crate::claude::process::{MessageStream, SpawnOptions};
+use crate::config::Config;
+use crate::tokens::estimate_tokens;
+
+/// Synthetic stand-in for the Claude CLI (`CLAUDE_BACKEND=mock`).
+///
+/// Emits the same JSONL shapes as a real run (init, assistant, result) from
+/// canned settings, so clients can be tested without a logged-in CLI.
+/// Output depends only on the request, apart from the session ID.
+#[derive(Debug, Clone)]
+pub struct MockBackend {
+    response: String,
+    latency: Duration,
+    tool_call: Option<(String, Value)>,
+    error: Option<String>,
+}
+
+impl MockBackend {
+    /// `None` unless `CLAUDE_BACKEND=mock`.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        if config.claude_backend != "mock" {
+            return None;
+        }
+        let tool_call = config.mock_tool_call.as_deref().map(|spec| match spec.split_once(':') {
+            Some((name, args)) => (
+                name.trim().to_string(),
+                serde_json::from_str(args).unwrap_or_else(|_| json!({})),
+            ),
+            None => (spec.trim().to_string(), json!({})),
+        });
+        Some(Self {
+            response: config.mock_response.clone(),
+            latency: Duration::from_millis(config.mock_latency_ms),
+            tool_call,
+            error: config.mock_error.clone(),
+        })
+    }
+
+    /// Stream a synthetic run for the request, pausing `MOCK_LATENCY_MS`
+    /// after the init message.
+    pub fn stream(&self, session_id: &str, opts: &SpawnOptions<'_>) -> MessageStream {
+        let latency = self.latency;
+        let mut messages = self.messages(session_id, opts).into_iter();
+        let init = messages.next();
+        let rest = futures::stream::once(async move {
+            tokio::time::sleep(latency).await;
+            futures::stream::iter(messages)
+        })
+        .flatten();
+        Box::pin(futures::stream::iter(init).chain(rest))
+    }
+
+    fn messages(&self, session_id: &str, opts: &SpawnOptions<'_>) -> Vec<Value> {
+        let init = json!({
+            "type": "system",
+            "subtype": "init",
+            "session_id": session_id,
+            "model": opts.model,
+            "tools": [],
+        });
+        if let Some(ref error) = self.error {
+            return vec![
+                init,
+                json!({
+                    "type": "result",
+                    "subtype": "error_during_execution",
+                    "is_error": true,
+                    "session_id": session_id,
+                    "result": error,
+                    "usage": {"input_tokens": 0, "output_tokens": 0},
+                    "cost_usd": 0.0,
+                }),
+            ];
+        }
+
+        let mut text = self
+            .response
+            .replace("{prompt}", opts.prompt)
+            .replace("{model}", opts.model);
+        // Requests with client tools run with built-in tools disabled
+        if let (Some((name, args)), true) = (&self.tool_call, opts.disable_builtin_tools) {
+            let call = json!({"name": name, "arguments": args});
+            text = format!("```tool_call\n{call}\n```");
+        }
+
+        let input_tokens = estimate_tokens(opts.prompt)
+            + opts.system_prompt.map_or(0, estimate_tokens)
+            + opts.append_system_prompt.map_or(0, estimate_tokens);
+        let output_tokens = estimate_tokens(&text);
+        vec![
+            init,
+            json!({
+                "type": "assistant",
+                "session_id": session_id,
+                "message": {
+                    "role": "assistant",
+                    "model": opts.model,
+                    "content": [{"type": "text", "text": text}],
+                },
+            }),
+            json!({
+                "type": "result",
+                "subtype": "success",
+                "is_error": false,
+                "session_id": session_id,
+                "result": text,
+                "usage": {"input_tokens": input_tokens, "output_tokens": output_tokens},
+                "cost_usd": 0.0,
+            }),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::priority::Priority;
+    use crate::tools::parse_tool_calls;
+
+    fn opts<'a>(prompt: &'a str, tools: bool) -> SpawnOptions<'a> {
+        SpawnOptions {
+            prompt,
+            model: "claude-sonnet-4",
+            system_prompt: None,
+            append_system_prompt: None,
+            disable_builtin_tools: tools,
+            env: Vec::new(),
+            project_dir: std::path::Path::new("/tmp"),
+            sandbox: None,
+            limits: None,
+            resume: None,
+            plan: false,
+            priority: Priority::Normal,
+        }
+    }
+
+    fn mock(tool_call: Option<&str>, error: Option<&str>) -> MockBackend {
+        let mut config = Config::for_test();
+        config.claude_backend = "mock".into();
+        config.mock_response = "echo {prompt} via {model}".into();
+        config.mock_tool_call = tool_call.map(str::to_string);
+        config.mock_error = error.map(str::to_string);
+        MockBackend::from_config(&config).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_mock_reply_and_tool_call() {
+        let msgs: Vec<Value> = mock(None, None).stream("s1", &opts("hi", false)).collect().await;
+        assert_eq!(msgs.len(), 3);
+        assert_eq!(msgs[0]["session_id"], "s1");
+        assert_eq!(msgs[1]["message"]["content"][0]["text"], "echo hi via claude-sonnet-4");
+        assert_eq!(msgs[2]["usage"]["input_tokens"], 1);
+
+        let tool = mock(Some(r#"get_weather:{"city":"Paris"}"#), None);
+        let text = tool.messages("s1", &opts("hi", true))[1]["message"]["content"][0]["text"].clone();
+        let (calls, _) = parse_tool_calls(text.as_str().unwrap());
+        let calls = calls.unwrap();
+        assert_eq!(calls[0].function.name, "get_weather");
+        assert!(calls[0].function.arguments.contains("Paris"));
+        // Without client tools the canned text is used
+        let plain = tool.messages("s1", &opts("hi", false));
+        assert_eq!(plain[1]["message"]["content"][0]["text"], "echo hi via claude-sonnet-4");
+    }
+
+    #[test]
+    fn test_mock_error() {
+        let msgs = mock(None, Some("boom")).messages("s1", &opts("hi", false));
+        assert_eq!(msgs.len(), 2);
+        assert_eq!(msgs[1]["is_error"], true);
+        assert_eq!(msgs[1]["result"], "boom");
+    }
+}
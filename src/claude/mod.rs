@@ -1,3 +1,17 @@
+pub mod auth;
+pub mod backend;
+pub mod discovery;
+pub mod env;
+pub mod inflight;
+pub mod limits;
 pub mod manager;
+pub mod mock;
 pub mod parser;
+pub mod plan_limits;
+pub mod pool;
 pub mod process;
+pub mod sandbox;
+pub mod selftest;
+pub mod slots;
+pub mod startup;
+pub mod version;
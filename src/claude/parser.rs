@@ -1,5 +1,13 @@
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Serialize;
 use serde_json::Value;
 
+/// `Claude AI usage limit reached|<unix time>` and `retry-after: <seconds>`.
+static RESET_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"(?i)\|(\d{10})\b|retry[-_ ]after\D{0,4}(\d+)").unwrap());
+
 /// Extract text content from a Claude JSONL assistant message.
 ///
 /// Claude messages have `message.content` which can be:
@@ -54,7 +62,260 @@ pub fn is_result_message(msg: &Value) -> bool {
     msg.get("type").and_then(|v| v.as_str()) == Some("result")
 }
 
+/// Whether the model declined the request on policy grounds: a `refusal`
+/// stop reason on an assistant message or the result, or an error result
+/// citing the usage policy (how the CLI reports API-side refusals).
+pub fn is_refusal(msg: &Value) -> bool {
+    let stop_reason = msg.get("stop_reason").or_else(|| msg.pointer("/message/stop_reason"));
+    if stop_reason.and_then(|v| v.as_str()) == Some("refusal") {
+        return true;
+    }
+    is_result_message(msg)
+        && msg.get("is_error").and_then(|v| v.as_bool()) == Some(true)
+        && msg
+            .get("result")
+            .and_then(|v| v.as_str())
+            .is_some_and(|r| r.to_lowercase().contains("usage polic"))
+}
+
+/// Anthropic turning a run away for capacity or quota reasons.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LimitKind {
+    /// API rate limit (HTTP 429).
+    RateLimited,
+    /// API overloaded (HTTP 529).
+    Overloaded,
+    /// The subscription's usage limit (Pro/Max plans).
+    UsageLimit,
+}
+
+impl LimitKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::RateLimited => "rate_limited",
+            Self::Overloaded => "overloaded",
+            Self::UsageLimit => "usage_limit",
+        }
+    }
+}
+
+/// An upstream limit the CLI reported, see [`upstream_limit`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UpstreamLimit {
+    pub kind: LimitKind,
+    /// The CLI's message.
+    pub message: String,
+    /// When the limit lifts (unix seconds), if the CLI said.
+    pub resets_at: Option<i64>,
+}
+
+/// The upstream limit reported by `msg`: the CLI surfaces API errors as
+/// assistant messages from the `<synthetic>` model and as error results.
+pub fn upstream_limit(msg: &Value) -> Option<UpstreamLimit> {
+    let synthetic = is_assistant_message(msg) && msg.pointer("/message/model").and_then(|m| m.as_str()) == Some("<synthetic>");
+    let error_result = is_result_message(msg) && msg.get("is_error").and_then(|v| v.as_bool()) == Some(true);
+    let text = if synthetic {
+        extract_assistant_content(msg)?
+    } else if error_result {
+        msg.get("result")?.as_str()?.to_string()
+    } else {
+        return None;
+    };
+    parse_upstream_limit(&text)
+}
+
+/// The upstream limit described by CLI error text (a message or stderr).
+pub fn parse_upstream_limit(text: &str) -> Option<UpstreamLimit> {
+    let lower = text.to_lowercase();
+    let kind = if lower.contains("usage limit reached") || (lower.contains("limit reached") && lower.contains("reset")) {
+        LimitKind::UsageLimit
+    } else if lower.contains("overloaded") {
+        LimitKind::Overloaded
+    } else if lower.contains("rate_limit_error") || lower.contains("rate limit") || lower.contains("too many requests") {
+        LimitKind::RateLimited
+    } else {
+        return None;
+    };
+    let resets_at = RESET_PATTERN.captures(text).and_then(|caps| {
+        if let Some(at) = caps.get(1) {
+            at.as_str().parse().ok()
+        } else {
+            let secs: i64 = caps.get(2)?.as_str().parse().ok()?;
+            Some(chrono::Utc::now().timestamp() + secs)
+        }
+    });
+    // Drop the machine-readable `|<unix time>` suffix
+    let message = match text.trim().rsplit_once('|') {
+        Some((message, at)) if at.len() == 10 && at.bytes().all(|b| b.is_ascii_digit()) => message,
+        _ => text.trim(),
+    };
+    Some(UpstreamLimit { kind, message: message.to_string(), resets_at })
+}
+
+/// How close a subscription usage window is to its limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PlanStatus {
+    Allowed,
+    /// Close to the limit.
+    Warning,
+    /// Used up: runs fail until it resets.
+    Rejected,
+}
+
+/// The state of one of the subscription's (Pro/Max plans) usage windows,
+/// see [`plan_usage`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanUsage {
+    /// `five_hour`, `seven_day`, `seven_day_opus`, …; `usage` when the CLI
+    /// did not say.
+    pub window: String,
+    pub status: PlanStatus,
+    /// Share of the window used, 0 to 1, if known.
+    pub utilization: Option<f64>,
+    /// When the window resets (unix seconds), if known.
+    pub resets_at: Option<i64>,
+}
+
+/// `You've used 91% of your weekly limit`.
+static PLAN_PERCENT_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"(?i)(\d{1,3})% of your\b").unwrap());
+
+/// The subscription usage reported by `msg`: the CLI's `rate_limit_event`
+/// messages, its "approaching usage limit" warnings, and a usage limit
+/// reached (as [`upstream_limit`]).
+pub fn plan_usage(msg: &Value) -> Option<PlanUsage> {
+    if msg.get("type").and_then(|v| v.as_str()) == Some("rate_limit_event") {
+        let info = msg.get("rate_limit_info").unwrap_or(msg);
+        let status = match info.get("status")?.as_str()? {
+            "allowed" => PlanStatus::Allowed,
+            "allowed_warning" => PlanStatus::Warning,
+            "rejected" => PlanStatus::Rejected,
+            _ => return None,
+        };
+        let window = info.get("rateLimitType").or_else(|| info.get("rate_limit_type"));
+        let resets_at = info.get("resetsAt").or_else(|| info.get("resets_at")).and_then(|v| v.as_i64());
+        let utilization = info.get("utilization").and_then(|v| v.as_f64());
+        return Some(PlanUsage {
+            window: window.and_then(|v| v.as_str()).unwrap_or("usage").to_string(),
+            status,
+            // Percentages and milliseconds are taken too
+            utilization: utilization.map(|u| if u > 1.0 { u / 100.0 } else { u }),
+            resets_at: resets_at.map(|t| if t > 100_000_000_000 { t / 1000 } else { t }),
+        });
+    }
+    if let Some(limit) = upstream_limit(msg) {
+        return (limit.kind == LimitKind::UsageLimit).then(|| PlanUsage {
+            window: plan_window(&limit.message).to_string(),
+            status: PlanStatus::Rejected,
+            utilization: Some(1.0),
+            resets_at: limit.resets_at,
+        });
+    }
+    let synthetic = is_assistant_message(msg) && msg.pointer("/message/model").and_then(|m| m.as_str()) == Some("<synthetic>");
+    let text = extract_assistant_content(msg).filter(|_| synthetic)?;
+    let percent = PLAN_PERCENT_PATTERN.captures(&text).and_then(|caps| caps[1].parse::<f64>().ok());
+    let lower = text.to_lowercase();
+    if percent.is_none() && !(lower.contains("approaching") && lower.contains("limit")) {
+        return None;
+    }
+    Some(PlanUsage {
+        window: plan_window(&text).to_string(),
+        status: PlanStatus::Warning,
+        utilization: percent.map(|p| p / 100.0),
+        resets_at: RESET_PATTERN.captures(&text).and_then(|caps| caps.get(1)?.as_str().parse().ok()),
+    })
+}
+
+/// The usage window CLI text is about.
+fn plan_window(text: &str) -> &'static str {
+    let lower = text.to_lowercase();
+    if lower.contains("opus") {
+        "seven_day_opus"
+    } else if lower.contains("weekly") || lower.contains("7-day") {
+        "seven_day"
+    } else if lower.contains("5-hour") || lower.contains("session limit") {
+        "five_hour"
+    } else {
+        "usage"
+    }
+}
+
+/// The CLI's login failure reported by `msg` (as [`upstream_limit`]), see
+/// [`crate::claude::auth`].
+pub fn auth_error(msg: &Value) -> Option<String> {
+    let synthetic = is_assistant_message(msg) && msg.pointer("/message/model").and_then(|m| m.as_str()) == Some("<synthetic>");
+    let error_result = is_result_message(msg) && msg.get("is_error").and_then(|v| v.as_bool()) == Some(true);
+    let text = if synthetic {
+        extract_assistant_content(msg)?
+    } else if error_result {
+        msg.get("result")?.as_str()?.to_string()
+    } else {
+        return None;
+    };
+    parse_auth_error(&text)
+}
+
+/// The login failure described by CLI error text (a message or stderr):
+/// logged out, or an invalid, expired or revoked API key or OAuth token.
+pub fn parse_auth_error(text: &str) -> Option<String> {
+    const MARKERS: &[&str] = &[
+        "invalid api key",
+        "invalid x-api-key",
+        "not logged in",
+        "please run /login",
+        "authentication_error",
+        "oauth token has expired",
+        "oauth token revoked",
+        "api error: 401",
+    ];
+    let lower = text.to_lowercase();
+    if !MARKERS.iter().any(|m| lower.contains(m)) {
+        return None;
+    }
+    let line = text.lines().map(str::trim).find(|l| MARKERS.iter().any(|m| l.to_lowercase().contains(m)));
+    Some(line.unwrap_or(text.trim()).to_string())
+}
+
+/// `tool_use` content blocks of an assistant message, as
+/// `(id, name, input)`.
+pub fn extract_tool_uses(msg: &Value) -> Vec<(&str, &str, &Value)> {
+    content_blocks(msg, "assistant", "tool_use")
+        .filter_map(|b| Some((b.get("id")?.as_str()?, b.get("name")?.as_str()?, b.get("input")?)))
+        .collect()
+}
+
+/// The plan a plan-mode run proposed: the `plan` input of its
+/// `ExitPlanMode` call.
+pub fn extract_plan(msg: &Value) -> Option<String> {
+    let (_, _, input) = extract_tool_uses(msg).into_iter().find(|(_, name, _)| *name == "ExitPlanMode")?;
+    input.get("plan")?.as_str().map(str::to_string)
+}
+
+/// `tool_result` content blocks of a user message (the CLI reporting a
+/// built-in tool's outcome), as `(tool_use_id, is_error)`.
+pub fn extract_tool_results(msg: &Value) -> Vec<(&str, bool)> {
+    content_blocks(msg, "user", "tool_result")
+        .filter_map(|b| {
+            let is_error = b.get("is_error").and_then(|v| v.as_bool()).unwrap_or(false);
+            Some((b.get("tool_use_id")?.as_str()?, is_error))
+        })
+        .collect()
+}
+
+fn content_blocks<'a>(msg: &'a Value, kind: &str, block_type: &'a str) -> impl Iterator<Item = &'a Value> {
+    let blocks = if msg.get("type").and_then(|v| v.as_str()) == Some(kind) {
+        msg.pointer("/message/content").and_then(|c| c.as_array())
+    } else {
+        None
+    };
+    blocks
+        .into_iter()
+        .flatten()
+        .filter(move |b| b.get("type").and_then(|v| v.as_str()) == Some(block_type))
+}
+
 /// Usage information extracted from a Claude message.
+#[derive(Debug, Clone, Copy)]
 pub struct UsageInfo {
     pub input_tokens: u32,
     pub output_tokens: u32,
@@ -130,6 +391,123 @@ mod tests {
         assert!(!is_result_message(&msg));
     }
 
+    #[test]
+    fn test_is_refusal() {
+        let msg = json!({"type": "assistant", "message": {"content": [], "stop_reason": "refusal"}});
+        assert!(is_refusal(&msg));
+
+        let msg = json!({
+            "type": "result",
+            "subtype": "error_during_execution",
+            "is_error": true,
+            "result": "API Error: Claude Code is unable to respond to this request, which appears to violate our Usage Policy",
+        });
+        assert!(is_refusal(&msg));
+
+        let msg = json!({"type": "result", "subtype": "error_during_execution", "is_error": true, "result": "timeout"});
+        assert!(!is_refusal(&msg));
+        let msg = json!({"type": "assistant", "message": {"content": [], "stop_reason": "end_turn"}});
+        assert!(!is_refusal(&msg));
+    }
+
+    #[test]
+    fn test_upstream_limit() {
+        let msg = json!({
+            "type": "assistant",
+            "message": {"model": "<synthetic>", "content": [{"type": "text", "text": "Claude AI usage limit reached|1760000000"}]},
+        });
+        let limit = upstream_limit(&msg).unwrap();
+        assert_eq!(limit.kind, LimitKind::UsageLimit);
+        assert_eq!(limit.resets_at, Some(1_760_000_000));
+        assert_eq!(limit.message, "Claude AI usage limit reached");
+
+        let msg = json!({
+            "type": "result",
+            "is_error": true,
+            "result": "API Error: 529 {\"type\":\"error\",\"error\":{\"type\":\"overloaded_error\",\"message\":\"Overloaded\"}}",
+        });
+        assert_eq!(upstream_limit(&msg).unwrap().kind, LimitKind::Overloaded);
+        assert_eq!(upstream_limit(&msg).unwrap().resets_at, None);
+
+        let limit = parse_upstream_limit("API Error: 429 rate_limit_error (retry-after: 30)").unwrap();
+        assert_eq!(limit.kind, LimitKind::RateLimited);
+        assert!(limit.resets_at.unwrap() >= chrono::Utc::now().timestamp() + 29);
+
+        // The model talking about rate limits is not an error
+        let msg = json!({
+            "type": "assistant",
+            "message": {"model": "claude-sonnet-4", "content": "Add a rate limit to the API."},
+        });
+        assert_eq!(upstream_limit(&msg), None);
+    }
+
+    #[test]
+    fn test_plan_usage() {
+        let msg = json!({
+            "type": "rate_limit_event",
+            "rate_limit_info": {"status": "allowed_warning", "rateLimitType": "five_hour", "resetsAt": 1_760_000_000, "utilization": 0.9},
+        });
+        let usage = plan_usage(&msg).unwrap();
+        assert_eq!((usage.window.as_str(), usage.status), ("five_hour", PlanStatus::Warning));
+        assert_eq!((usage.utilization, usage.resets_at), (Some(0.9), Some(1_760_000_000)));
+
+        let msg = json!({
+            "type": "assistant",
+            "message": {"model": "<synthetic>", "content": [{"type": "text", "text": "Claude AI usage limit reached|1760000000"}]},
+        });
+        let usage = plan_usage(&msg).unwrap();
+        assert_eq!((usage.window.as_str(), usage.status, usage.resets_at), ("usage", PlanStatus::Rejected, Some(1_760_000_000)));
+
+        let msg = json!({
+            "type": "assistant",
+            "message": {"model": "<synthetic>", "content": "You've used 91% of your weekly limit · resets Oct 20"},
+        });
+        let usage = plan_usage(&msg).unwrap();
+        assert_eq!((usage.window.as_str(), usage.utilization), ("seven_day", Some(0.91)));
+
+        // Other limits and the model's own words are not plan usage
+        let msg = json!({"type": "result", "is_error": true, "result": "API Error: 429 rate_limit_error"});
+        assert_eq!(plan_usage(&msg), None);
+        let msg = json!({"type": "assistant", "message": {"model": "claude-sonnet-4", "content": "You've used 91% of your disk."}});
+        assert_eq!(plan_usage(&msg), None);
+    }
+
+    #[test]
+    fn test_auth_error() {
+        let msg = json!({
+            "type": "assistant",
+            "message": {"model": "<synthetic>", "content": [{"type": "text", "text": "Invalid API key · Please run /login"}]},
+        });
+        assert_eq!(auth_error(&msg).as_deref(), Some("Invalid API key · Please run /login"));
+        let stderr = "Loading config\nAPI Error: 401 {\"type\":\"error\",\"error\":{\"type\":\"authentication_error\"}}\n";
+        assert!(parse_auth_error(stderr).unwrap().starts_with("API Error: 401"));
+        assert_eq!(parse_auth_error("API Error: 429 rate_limit_error"), None);
+
+        // The model talking about logins is not an error
+        let msg = json!({
+            "type": "assistant",
+            "message": {"model": "claude-sonnet-4", "content": "Users who are not logged in see the landing page."},
+        });
+        assert_eq!(auth_error(&msg), None);
+    }
+
+    #[test]
+    fn test_extract_plan() {
+        let msg = json!({
+            "type": "assistant",
+            "message": {"content": [
+                {"type": "text", "text": "Here is my plan."},
+                {"type": "tool_use", "id": "t1", "name": "ExitPlanMode", "input": {"plan": "1. Add the route\n2. Test it"}},
+            ]},
+        });
+        assert_eq!(extract_plan(&msg).as_deref(), Some("1. Add the route\n2. Test it"));
+        let msg = json!({
+            "type": "assistant",
+            "message": {"content": [{"type": "tool_use", "id": "t2", "name": "Read", "input": {"plan": "x"}}]},
+        });
+        assert_eq!(extract_plan(&msg), None);
+    }
+
     #[test]
     fn test_extract_usage() {
         let msg = json!({
@@ -0,0 +1,164 @@
+//! The subscription's usage limits (Pro/Max plans). Runs on a subscription
+//! login share its usage windows (five hours, seven days, …); the CLI says
+//! how far along each is in `rate_limit_event` messages and warnings, and
+//! a window that is used up fails runs with `usage_limit_reached` until it
+//! resets. The latest word on each window of each profile is kept so
+//! schedulers can throttle before that: `GET /v1/limits` lists them,
+//! `/health` includes them, and `/v1` responses carry the tightest one in
+//! `x-plan-limit-*` headers. A window whose reset time has passed is
+//! forgotten.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::claude::parser::{plan_usage, PlanStatus, PlanUsage};
+use crate::state::AppState;
+
+/// What is known of one usage window of a profile.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlanWindow {
+    pub profile: String,
+    pub window: String,
+    pub status: PlanStatus,
+    /// Share of the window used, 0 to 1, if known.
+    pub utilization: Option<f64>,
+    /// When the window resets (unix time), if known.
+    pub resets_at: Option<i64>,
+    /// When the CLI last reported it (unix time).
+    pub updated_at: i64,
+}
+
+/// The usage windows seen, by profile and window.
+#[derive(Debug, Default)]
+pub struct PlanLimits {
+    windows: RwLock<BTreeMap<(String, String), PlanWindow>>,
+}
+
+impl PlanLimits {
+    /// Take note of what a CLI message of `profile` says about its usage.
+    pub fn observe(&self, profile: &str, msg: &Value) {
+        if let Some(usage) = plan_usage(msg) {
+            self.record(profile, usage, chrono::Utc::now().timestamp());
+        }
+    }
+
+    fn record(&self, profile: &str, usage: PlanUsage, now: i64) {
+        let mut windows = self.windows.write().unwrap_or_else(|e| e.into_inner());
+        let key = (profile.to_string(), usage.window.clone());
+        let previous = windows.get(&key).filter(|w| !expired(w, now));
+        if previous.is_none_or(|p| p.status != usage.status) && usage.status != PlanStatus::Allowed {
+            tracing::warn!(
+                profile,
+                window = %usage.window,
+                status = ?usage.status,
+                utilization = usage.utilization,
+                resets_at = usage.resets_at,
+                "Subscription usage limit approaching or reached"
+            );
+        }
+        // A warning without figures keeps the ones already known
+        let utilization = usage.utilization.or(previous.and_then(|p| p.utilization));
+        let resets_at = usage.resets_at.or(previous.and_then(|p| p.resets_at));
+        windows.insert(
+            key,
+            PlanWindow {
+                profile: profile.to_string(),
+                window: usage.window,
+                status: usage.status,
+                utilization,
+                resets_at,
+                updated_at: now,
+            },
+        );
+    }
+
+    /// The windows known and not reset yet, closest to their limit first:
+    /// the worst status, then the highest utilization.
+    pub fn windows(&self) -> Vec<PlanWindow> {
+        self.windows_at(chrono::Utc::now().timestamp())
+    }
+
+    fn windows_at(&self, now: i64) -> Vec<PlanWindow> {
+        let mut windows = self.windows.write().unwrap_or_else(|e| e.into_inner());
+        windows.retain(|_, w| !expired(w, now));
+        let mut windows: Vec<PlanWindow> = windows.values().cloned().collect();
+        windows.sort_by(|a, b| {
+            let used = |w: &PlanWindow| w.utilization.unwrap_or(0.0);
+            b.status.cmp(&a.status).then(used(b).total_cmp(&used(a)))
+        });
+        windows
+    }
+
+    /// The window closest to its limit.
+    pub fn tightest(&self) -> Option<PlanWindow> {
+        self.windows().into_iter().next()
+    }
+}
+
+fn expired(window: &PlanWindow, now: i64) -> bool {
+    window.resets_at.is_some_and(|at| at <= now)
+}
+
+/// Add the tightest usage window to the response as `x-plan-limit-status`,
+/// `x-plan-limit-window`, `x-plan-limit-utilization` and
+/// `x-plan-limit-reset` (unix time), once the CLI has reported one.
+pub async fn plan_limit_headers(State(state): State<Arc<AppState>>, req: Request<Body>, next: Next) -> Response {
+    let mut response = next.run(req).await;
+    let Some(window) = state.plan_limits.tightest() else {
+        return response;
+    };
+    let status = match window.status {
+        PlanStatus::Allowed => "allowed",
+        PlanStatus::Warning => "warning",
+        PlanStatus::Rejected => "rejected",
+    };
+    let headers = response.headers_mut();
+    headers.insert("x-plan-limit-status", HeaderValue::from_static(status));
+    if let Ok(value) = HeaderValue::from_str(&window.window) {
+        headers.insert("x-plan-limit-window", value);
+    }
+    if let Some(utilization) = window.utilization.and_then(|u| HeaderValue::from_str(&format!("{u:.2}")).ok()) {
+        headers.insert("x-plan-limit-utilization", utilization);
+    }
+    if let Some(resets_at) = window.resets_at {
+        headers.insert("x-plan-limit-reset", HeaderValue::from(resets_at));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn usage(window: &str, status: PlanStatus, utilization: Option<f64>, resets_at: Option<i64>) -> PlanUsage {
+        PlanUsage { window: window.to_string(), status, utilization, resets_at }
+    }
+
+    #[test]
+    fn test_plan_limits() {
+        let limits = PlanLimits::default();
+        limits.record("default", usage("five_hour", PlanStatus::Allowed, Some(0.4), Some(2_000)), 1_000);
+        limits.record("default", usage("seven_day", PlanStatus::Warning, Some(0.8), Some(9_000)), 1_000);
+        limits.record("work", usage("five_hour", PlanStatus::Allowed, Some(0.95), Some(2_000)), 1_000);
+        let windows = limits.windows_at(1_500);
+        let order: Vec<_> = windows.iter().map(|w| (w.profile.as_str(), w.window.as_str())).collect();
+        assert_eq!(order, [("default", "seven_day"), ("work", "five_hour"), ("default", "five_hour")]);
+
+        // A warning without figures keeps the known ones
+        limits.record("default", usage("seven_day", PlanStatus::Rejected, None, None), 1_200);
+        let tightest = limits.windows_at(1_500).remove(0);
+        assert_eq!((tightest.status, tightest.utilization, tightest.resets_at), (PlanStatus::Rejected, Some(0.8), Some(9_000)));
+
+        // Windows are forgotten once they reset
+        assert_eq!(limits.windows_at(2_000).len(), 1);
+        assert_eq!(limits.windows_at(9_000), []);
+    }
+}
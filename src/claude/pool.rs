@@ -0,0 +1,256 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use serde_json::json;
+
+use crate::claude::env::build_env;
+use crate::claude::manager::create_project_directory;
+use crate::claude::process::{ClaudeProcess, SpawnOptions};
+use crate::claude::limits::ResourceLimits;
+use crate::claude::sandbox::Sandbox;
+use crate::claude::slots::{SlotPermit, Slots};
+use crate::claude::version::CliCapabilities;
+use crate::config::{ClaudeProfile, Config};
+use crate::priority::Priority;
+
+/// Longest wait before retrying a warm process that failed to spawn.
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+#[derive(Default)]
+struct ModelSlot {
+    /// Idle processes, each holding the session slot it will run in.
+    idle: Vec<(ClaudeProcess, SlotPermit)>,
+    /// Warm processes currently being spawned or waiting to be retried.
+    pending: usize,
+    /// Spawns that failed in a row, which sets the retry delay.
+    failures: u32,
+}
+
+/// Idle Claude processes pre-spawned per model so requests skip the CLI's
+/// cold start.
+///
+/// Only plain requests can use a warm process: default profile and
+/// environment, no system prompt and built-in tools enabled, since those are
+/// fixed when the process is spawned. Everything else is spawned cold.
+///
+/// Warm processes count against `MAX_CONCURRENT_SESSIONS`: each holds a
+/// session slot, taken only while one is free, which the request using it
+/// inherits. A request that finds every slot held by the pool evicts an
+/// idle process instead of waiting.
+pub struct WarmPool {
+    size: usize,
+    models: Vec<String>,
+    profile: ClaudeProfile,
+    caps: CliCapabilities,
+    env: Vec<(String, String)>,
+    project_dir: PathBuf,
+    sandbox: Option<Sandbox>,
+    limits: Option<ResourceLimits>,
+    slots: Mutex<HashMap<String, ModelSlot>>,
+    /// The manager's session slots.
+    session_slots: Arc<Slots>,
+    /// Set by [`shutdown`](Self::shutdown); nothing is spawned after it.
+    closed: AtomicBool,
+    hits: AtomicU64,
+    misses: AtomicU64,
+    spawn_failures: AtomicU64,
+}
+
+impl WarmPool {
+    /// Build the pool from `WARM_POOL_SIZE`/`WARM_POOL_MODELS`; `None` when disabled.
+    pub fn from_config(config: &Config, caps: CliCapabilities, session_slots: Arc<Slots>) -> Option<Arc<Self>> {
+        if config.warm_pool_size == 0 || config.default_profile().backend != "claude" {
+            return None;
+        }
+        let mut models: Vec<String> = config
+            .warm_pool_models
+            .iter()
+            .map(|m| config.model_catalog.resolve(m))
+            .collect();
+        models.dedup();
+        let profile = config.default_profile().clone();
+        Some(Arc::new(Self {
+            size: config.warm_pool_size,
+            models,
+            env: build_env(config, &profile, Some("default")),
+            profile,
+            caps,
+            project_dir: create_project_directory(&config.project_root, "default").ok()?,
+            sandbox: Sandbox::from_config(config),
+            limits: ResourceLimits::from_config(config),
+            slots: Mutex::new(HashMap::new()),
+            session_slots,
+            closed: AtomicBool::new(false),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+            spawn_failures: AtomicU64::new(0),
+        }))
+    }
+
+    /// Whether a request with these parameters could run on a warm process.
+    /// Low-priority runs leave them to interactive requests.
+    fn accepts(&self, profile: &ClaudeProfile, opts: &SpawnOptions<'_>) -> bool {
+        opts.priority > Priority::Low
+            && *profile == self.profile
+            && self.models.iter().any(|m| m == opts.model)
+            && opts.system_prompt.is_none()
+            && opts.append_system_prompt.is_none()
+            && !opts.disable_builtin_tools
+            && opts.resume.is_none()
+            && !opts.plan
+            && opts.env == self.env
+            && opts.sandbox.is_some() == self.sandbox.is_some()
+            // Warm processes already run in the default project's directory
+            && opts.project_dir == self.project_dir
+    }
+
+    /// Take a live idle process for the request, with its session slot, if
+    /// the request is eligible and one is available, and start replacing it
+    /// in the background.
+    pub fn take(self: &Arc<Self>, profile: &ClaudeProfile, opts: &SpawnOptions<'_>) -> Option<(ClaudeProcess, SlotPermit)> {
+        if !self.accepts(profile, opts) {
+            return None;
+        }
+        let taken = {
+            let mut slots = self.slots.lock().unwrap();
+            let slot = slots.entry(opts.model.to_string()).or_default();
+            let mut taken = None;
+            while let Some((mut process, permit)) = slot.idle.pop() {
+                if !process.has_exited() {
+                    taken = Some((process, permit));
+                    break;
+                }
+            }
+            taken
+        };
+        let counter = if taken.is_some() { &self.hits } else { &self.misses };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.refill();
+        taken
+    }
+
+    /// Kill one idle process to give its session slot to a request that
+    /// can't use it. Returns whether there was one.
+    pub async fn evict(&self) -> bool {
+        let evicted = {
+            let mut slots = self.slots.lock().unwrap();
+            slots.values_mut().max_by_key(|s| s.idle.len()).and_then(|s| s.idle.pop())
+        };
+        let Some((mut process, permit)) = evicted else {
+            return false;
+        };
+        process.kill().await;
+        drop(permit);
+        true
+    }
+
+    /// Spawn processes in the background until every model has `size` idle
+    /// or pending ones, as far as session slots are free.
+    pub fn refill(self: &Arc<Self>) {
+        if self.closed.load(Ordering::Relaxed) {
+            return;
+        }
+        for model in &self.models {
+            let permits: Vec<SlotPermit> = {
+                let mut slots = self.slots.lock().unwrap();
+                let slot = slots.entry(model.clone()).or_default();
+                let deficit = self.size.saturating_sub(slot.idle.len() + slot.pending);
+                let permits: Vec<SlotPermit> =
+                    (0..deficit).map_while(|_| self.session_slots.try_acquire().ok()).collect();
+                slot.pending += permits.len();
+                permits
+            };
+            for permit in permits {
+                let pool = Arc::clone(self);
+                let model = model.clone();
+                tokio::spawn(async move { pool.spawn_one(model, permit).await });
+            }
+        }
+    }
+
+    /// Spawn a warm process into `permit`'s slot. A failure gives the slot
+    /// back and retries after a delay that doubles with every failure in a
+    /// row.
+    async fn spawn_one(self: Arc<Self>, model: String, permit: SlotPermit) {
+        let opts = SpawnOptions {
+            prompt: "",
+            model: &model,
+            system_prompt: None,
+            append_system_prompt: None,
+            disable_builtin_tools: false,
+            env: self.env.clone(),
+            project_dir: &self.project_dir,
+            sandbox: self.sandbox.as_ref(),
+            limits: self.limits.as_ref(),
+            resume: None,
+            plan: false,
+            priority: Priority::Normal,
+        };
+        let result = ClaudeProcess::spawn_warm(&self.profile, self.caps, opts).await;
+
+        let failures = match result {
+            Ok(mut process) => {
+                {
+                    let mut slots = self.slots.lock().unwrap();
+                    let slot = slots.entry(model).or_default();
+                    slot.pending -= 1;
+                    if !self.closed.load(Ordering::Relaxed) {
+                        slot.failures = 0;
+                        slot.idle.push((process, permit));
+                        return;
+                    }
+                }
+                process.kill().await;
+                return;
+            }
+            Err(e) => {
+                self.spawn_failures.fetch_add(1, Ordering::Relaxed);
+                let mut slots = self.slots.lock().unwrap();
+                let slot = slots.entry(model.clone()).or_default();
+                slot.failures += 1;
+                tracing::warn!(model, error = %e, failures = slot.failures, "Failed to pre-spawn warm Claude process");
+                slot.failures
+            }
+        };
+        drop(permit);
+        tokio::time::sleep(retry_delay(failures)).await;
+        self.slots.lock().unwrap().entry(model).or_default().pending -= 1;
+        self.refill();
+    }
+
+    /// Kill all idle processes and stop spawning new ones.
+    pub async fn shutdown(&self) {
+        let idle: Vec<(ClaudeProcess, SlotPermit)> = {
+            let mut slots = self.slots.lock().unwrap();
+            self.closed.store(true, Ordering::Relaxed);
+            slots.values_mut().flat_map(|s| s.idle.drain(..)).collect()
+        };
+        for (mut process, _permit) in idle {
+            process.kill().await;
+        }
+    }
+
+    pub fn stats(&self) -> serde_json::Value {
+        let slots = self.slots.lock().unwrap();
+        let idle: HashMap<&str, usize> = self
+            .models
+            .iter()
+            .map(|m| (m.as_str(), slots.get(m).map_or(0, |s| s.idle.len())))
+            .collect();
+        json!({
+            "size_per_model": self.size,
+            "idle": idle,
+            "hits": self.hits.load(Ordering::Relaxed),
+            "misses": self.misses.load(Ordering::Relaxed),
+            "spawn_failures": self.spawn_failures.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Wait before the next spawn after `failures` failures in a row.
+fn retry_delay(failures: u32) -> Duration {
+    Duration::from_secs(1u64 << failures.saturating_sub(1).min(8)).min(MAX_RETRY_DELAY)
+}
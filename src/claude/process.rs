@@ -1,95 +1,428 @@
+use std::path::{Path, PathBuf};
 use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
 use futures::Stream;
-use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use serde::Serialize;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
 use tokio::process::{Child, Command};
 
-use crate::config::Config;
+use crate::claude::backend::OutputTranslator;
+use crate::claude::limits::{AppliedLimits, LimitHit, ResourceLimits};
+use crate::claude::parser::{extract_assistant_content, is_assistant_message};
+use crate::claude::sandbox::{install_dir, resolve_binary, Sandbox, SandboxPaths};
+use crate::claude::version::CliCapabilities;
+use crate::config::ClaudeProfile;
 use crate::error::AppError;
+use crate::priority::Priority;
 
-/// A running Claude CLI process with streaming JSONL output.
+/// Per-request parameters for a Claude CLI invocation.
+pub struct SpawnOptions<'a> {
+    pub prompt: &'a str,
+    pub model: &'a str,
+    pub system_prompt: Option<&'a str>,
+    pub append_system_prompt: Option<&'a str>,
+    pub disable_builtin_tools: bool,
+    /// Complete process environment, see [`crate::claude::env::build_env`].
+    pub env: Vec<(String, String)>,
+    /// Project directory; the working directory when sandboxed.
+    pub project_dir: &'a Path,
+    pub sandbox: Option<&'a Sandbox>,
+    pub limits: Option<&'a ResourceLimits>,
+    /// CLI session to continue (`--resume`); Claude only.
+    pub resume: Option<&'a str>,
+    /// Plan mode (`--permission-mode plan`): Claude explores and proposes
+    /// a plan but changes nothing. Claude only.
+    pub plan: bool,
+    /// Place in the queue for a session slot; low-priority runs do not take
+    /// warm processes.
+    pub priority: Priority,
+}
+
+/// The Claude CLI arguments for `opts` (the prompt goes to stdin), and the
+/// system prompt to put in a `CLAUDE.md` instead when it is too large
+/// (>10KB) for the command line.
+pub fn claude_args<'a>(caps: CliCapabilities, opts: &SpawnOptions<'a>, warm: bool) -> (Vec<String>, Option<&'a str>) {
+    let mut args: Vec<String> = vec!["-p".to_string()];
+    let mut claude_md = None;
+    let mut append_system_prompt = opts.append_system_prompt.map(str::to_string);
+
+    if let Some(sp) = opts.system_prompt {
+        if sp.len() > 10_000 {
+            claude_md = Some(sp);
+        } else if caps.system_prompt_flag {
+            args.extend(["--system-prompt".to_string(), sp.to_string()]);
+        } else {
+            // Older CLIs: fold the system prompt into the appended one
+            append_system_prompt = Some(match append_system_prompt {
+                Some(asp) => format!("{sp}\n\n{asp}"),
+                None => sp.to_string(),
+            });
+        }
+    }
+
+    if let Some(asp) = append_system_prompt {
+        args.extend(["--append-system-prompt".to_string(), asp]);
+    }
+
+    if opts.disable_builtin_tools {
+        args.extend(caps.disable_tools_args());
+    }
+
+    if let Some(id) = opts.resume {
+        args.extend(["--resume".to_string(), id.to_string()]);
+    }
+    args.extend(["--model".to_string(), opts.model.to_string()]);
+    args.extend(["--output-format".to_string(), "stream-json".to_string()]);
+    args.push("--verbose".to_string());
+    if opts.plan {
+        args.extend(["--permission-mode".to_string(), "plan".to_string()]);
+    } else {
+        args.push("--dangerously-skip-permissions".to_string());
+    }
+    if warm {
+        args.extend(["--input-format".to_string(), "stream-json".to_string()]);
+    }
+    (args, claude_md)
+}
+
+/// A running agent CLI process with streaming JSONL output, normalized to
+/// Claude's stream-json format.
 pub struct ClaudeProcess {
     child: Child,
     _temp_dir: Option<tempfile::TempDir>,
+    started: Instant,
+    probe: Arc<Probe>,
+    translator: Option<Box<dyn OutputTranslator>>,
+    /// Taken when the process is reaped.
+    limits: Option<AppliedLimits>,
+}
+
+/// A command line for one CLI run, built by a
+/// [`CompletionBackend`](crate::claude::backend::CompletionBackend).
+#[derive(Default)]
+pub struct Invocation {
+    pub args: Vec<String>,
+    /// Kept alive for the duration of the run.
+    pub temp_dir: Option<tempfile::TempDir>,
+    /// Converts the CLI's output to Claude stream-json; `None` if it already is.
+    pub translator: Option<Box<dyn OutputTranslator>>,
+}
+
+/// Lifecycle measurements of one CLI run, taken when it is reaped or killed.
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ProcessReport {
+    /// Time from start until the CLI printed its first line.
+    pub spawn_ms: Option<u64>,
+    /// Time from start until the first assistant text.
+    pub ttft_ms: Option<u64>,
+    pub duration_ms: u64,
+    /// Exit code; negative for the signal that killed the process.
+    pub exit_code: Option<i32>,
+    /// Peak resident set size from `/proc` (Linux only).
+    pub peak_rss_kb: Option<u64>,
+    /// The resource limit that ended the process, if any.
+    pub limit_hit: Option<LimitHit>,
+    /// The last `STDERR_TAIL` bytes the process wrote to stderr.
+    #[serde(skip)]
+    pub stderr: String,
+}
+
+/// How much of a finished process's stderr is kept in its report.
+const STDERR_TAIL: usize = 4096;
+
+const UNSET: u64 = u64::MAX;
+
+/// Measurements filled in by the stdout reader and the RSS sampler.
+struct Probe {
+    first_line_ms: AtomicU64,
+    first_token_ms: AtomicU64,
+    peak_rss_kb: AtomicU64,
+    done: AtomicBool,
+}
+
+impl Probe {
+    fn new() -> Self {
+        Self {
+            first_line_ms: AtomicU64::new(UNSET),
+            first_token_ms: AtomicU64::new(UNSET),
+            peak_rss_kb: AtomicU64::new(UNSET),
+            done: AtomicBool::new(false),
+        }
+    }
+
+    /// Record the first line and first assistant text, once each.
+    fn observe(&self, started: Instant, msg: &serde_json::Value) {
+        let ms = started.elapsed().as_millis() as u64;
+        let _ = self.first_line_ms.compare_exchange(UNSET, ms, Ordering::Relaxed, Ordering::Relaxed);
+        if is_assistant_message(msg) && extract_assistant_content(msg).is_some() {
+            let _ = self.first_token_ms.compare_exchange(UNSET, ms, Ordering::Relaxed, Ordering::Relaxed);
+        }
+    }
+}
+
+fn get(v: &AtomicU64) -> Option<u64> {
+    Some(v.load(Ordering::Relaxed)).filter(|v| *v != UNSET)
 }
 
+/// Poll `VmHWM` (peak RSS) from `/proc/<pid>/status` until the process ends.
+#[cfg(target_os = "linux")]
+fn spawn_rss_sampler(pid: u32, probe: Arc<Probe>) {
+    tokio::spawn(async move {
+        let path = format!("/proc/{pid}/status");
+        while !probe.done.load(Ordering::Relaxed) {
+            let Ok(status) = tokio::fs::read_to_string(&path).await else { break };
+            let Some(kb) = status
+                .lines()
+                .find_map(|l| l.strip_prefix("VmHWM:"))
+                .and_then(|v| v.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+            else {
+                break;
+            };
+            let prev = probe.peak_rss_kb.load(Ordering::Relaxed);
+            if prev == UNSET || kb > prev {
+                probe.peak_rss_kb.store(kb, Ordering::Relaxed);
+            }
+            tokio::time::sleep(Duration::from_millis(200)).await;
+        }
+    });
+}
+
+#[cfg(not(target_os = "linux"))]
+fn spawn_rss_sampler(_pid: u32, _probe: Arc<Probe>) {}
+
+/// Current resource use of a running process.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ProcessUsage {
+    /// Resident set size (`VmRSS`).
+    pub rss_kb: u64,
+    /// User plus system CPU time so far.
+    pub cpu_ms: u64,
+}
+
+/// Read `VmRSS` from `/proc/<pid>/status` and `utime + stime` from
+/// `/proc/<pid>/stat`.
+#[cfg(target_os = "linux")]
+fn read_usage(pid: u32) -> Option<ProcessUsage> {
+    let status = std::fs::read_to_string(format!("/proc/{pid}/status")).ok()?;
+    let rss_kb = status
+        .lines()
+        .find_map(|l| l.strip_prefix("VmRSS:"))
+        .and_then(|v| v.trim().trim_end_matches("kB").trim().parse().ok())?;
+    let stat = std::fs::read_to_string(format!("/proc/{pid}/stat")).ok()?;
+    // Fields after the parenthesized command name start at field 3 (state)
+    let fields: Vec<&str> = stat.rsplit_once(')')?.1.split_whitespace().collect();
+    let ticks: u64 = fields.get(11)?.parse::<u64>().ok()? + fields.get(12)?.parse::<u64>().ok()?;
+    // SAFETY: sysconf has no preconditions
+    let per_sec = unsafe { libc::sysconf(libc::_SC_CLK_TCK) }.max(1) as u64;
+    Some(ProcessUsage { rss_kb, cpu_ms: ticks * 1000 / per_sec })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_usage(_pid: u32) -> Option<ProcessUsage> {
+    None
+}
+
+pub type MessageStream = Pin<Box<dyn Stream<Item = serde_json::Value> + Send>>;
+
 impl ClaudeProcess {
     /// Spawn a Claude CLI process and return a stream of parsed JSONL messages.
     ///
     /// The prompt is piped via stdin to avoid execve() argument size limits.
     /// Output is streamed line-by-line from stdout using BufReader (true streaming,
     /// unlike the Python version which buffers all output with communicate()).
+    /// The binary comes from `profile`; the environment is replaced by `opts.env`.
     pub async fn spawn(
-        config: &Config,
-        prompt: &str,
-        model: &str,
-        system_prompt: Option<&str>,
-        append_system_prompt: Option<&str>,
-        disable_builtin_tools: bool,
-    ) -> Result<
-        (
-            Self,
-            Pin<Box<dyn Stream<Item = serde_json::Value> + Send>>,
-            Option<String>,
-        ),
-        AppError,
-    > {
-        let mut cmd = Command::new(&config.claude_binary_path);
-        cmd.arg("-p");
+        profile: &ClaudeProfile,
+        caps: CliCapabilities,
+        opts: SpawnOptions<'_>,
+    ) -> Result<(Self, MessageStream, Option<String>), AppError> {
+        let prompt = opts.prompt;
+        let process = Self::launch(profile, caps, opts, false).await?;
+        process.start(prompt.as_bytes()).await
+    }
 
-        let mut temp_dir = None;
+    /// Pre-spawn a process that reads its prompt as a stream-json message
+    /// and waits idle until [`start_warm`](Self::start_warm) supplies it.
+    pub async fn spawn_warm(
+        profile: &ClaudeProfile,
+        caps: CliCapabilities,
+        opts: SpawnOptions<'_>,
+    ) -> Result<Self, AppError> {
+        Self::launch(profile, caps, opts, true).await
+    }
 
-        // Handle system prompt: write to CLAUDE.md if large (>10KB)
-        if let Some(sp) = system_prompt {
-            if sp.len() > 10_000 {
-                let dir = tempfile::tempdir().map_err(|e| {
-                    AppError::Internal(format!("Failed to create temp dir: {e}"))
-                })?;
-                let claude_md = dir.path().join("CLAUDE.md");
-                tokio::fs::write(&claude_md, sp).await.map_err(|e| {
-                    AppError::Internal(format!("Failed to write CLAUDE.md: {e}"))
-                })?;
-                tracing::info!(
-                    path = %claude_md.display(),
-                    size = sp.len(),
-                    "System prompt written to CLAUDE.md"
-                );
-                cmd.current_dir(dir.path());
-                temp_dir = Some(dir);
-            } else {
-                cmd.args(["--system-prompt", sp]);
-            }
-        }
+    /// Bind a warm process to a request by sending it the prompt.
+    pub async fn start_warm(
+        self,
+        prompt: &str,
+    ) -> Result<(Self, MessageStream, Option<String>), AppError> {
+        let message = serde_json::json!({
+            "type": "user",
+            "message": {"role": "user", "content": [{"type": "text", "text": prompt}]},
+        });
+        self.start(format!("{message}\n").as_bytes()).await
+    }
 
-        if let Some(asp) = append_system_prompt {
-            cmd.args(["--append-system-prompt", asp]);
-        }
+    async fn launch(
+        profile: &ClaudeProfile,
+        caps: CliCapabilities,
+        opts: SpawnOptions<'_>,
+        warm: bool,
+    ) -> Result<Self, AppError> {
+        let (mut args, claude_md) = claude_args(caps, &opts, warm);
+        let SpawnOptions {
+            prompt,
+            model,
+            system_prompt,
+            mut env,
+            project_dir,
+            sandbox,
+            limits,
+            ..
+        } = opts;
 
-        if disable_builtin_tools {
-            cmd.args(["--tools", ""]);
+        let mut temp_dir = None;
+        if let Some(sp) = claude_md {
+            // Sandboxed processes can only see the project directory
+            let dir = match sandbox {
+                Some(_) => tempfile::tempdir_in(project_dir),
+                None => tempfile::tempdir(),
+            }
+            .map_err(|e| {
+                AppError::Internal(format!("Failed to create temp dir: {e}"))
+            })?;
+            let claude_md = dir.path().join("CLAUDE.md");
+            tokio::fs::write(&claude_md, sp).await.map_err(|e| {
+                AppError::Internal(format!("Failed to write CLAUDE.md: {e}"))
+            })?;
+            tracing::info!(
+                path = %claude_md.display(),
+                size = sp.len(),
+                "System prompt written to CLAUDE.md"
+            );
+            // The CLI runs in the project directory and reads this one too
+            args.extend(["--add-dir".to_string(), dir.path().to_string_lossy().into_owned()]);
+            env.push(("CLAUDE_CODE_ADDITIONAL_DIRECTORIES_CLAUDE_MD".to_string(), "1".to_string()));
+            temp_dir = Some(dir);
         }
 
-        cmd.args(["--model", model]);
-        cmd.args(["--output-format", "stream-json"]);
-        cmd.args(["--verbose", "--dangerously-skip-permissions"]);
-        cmd.stdin(std::process::Stdio::piped());
-        cmd.stdout(std::process::Stdio::piped());
-        cmd.stderr(std::process::Stdio::piped());
-
         tracing::info!(
             model,
+            profile = %profile.name,
+            sandboxed = sandbox.is_some(),
+            warm,
             prompt_size = prompt.len(),
             system_prompt_size = system_prompt.map(|s| s.len()).unwrap_or(0),
             "Spawning Claude process"
         );
 
-        let mut child = cmd.spawn().map_err(|e| {
+        let invocation = Invocation {
+            args,
+            temp_dir,
+            translator: None,
+        };
+        Self::exec(profile, invocation, env, project_dir, sandbox, limits)
+    }
+
+    /// Spawn another agent CLI from a backend-built command line and start
+    /// streaming its (translated) output. `input` is written to stdin.
+    pub async fn spawn_with(
+        profile: &ClaudeProfile,
+        opts: SpawnOptions<'_>,
+        invocation: Invocation,
+        input: &[u8],
+    ) -> Result<(Self, MessageStream, Option<String>), AppError> {
+        tracing::info!(
+            model = opts.model,
+            profile = %profile.name,
+            binary = %profile.binary_path,
+            sandboxed = opts.sandbox.is_some(),
+            prompt_size = input.len(),
+            "Spawning agent CLI process"
+        );
+        let process = Self::exec(profile, invocation, opts.env, opts.project_dir, opts.sandbox, opts.limits)?;
+        process.start(input).await
+    }
+
+    /// Start `profile`'s binary in `project_dir` with the invocation's
+    /// arguments, replacing the environment with `env`, wrapping it in the
+    /// sandbox and applying the resource limits if any.
+    fn exec(
+        profile: &ClaudeProfile,
+        invocation: Invocation,
+        env: Vec<(String, String)>,
+        project_dir: &Path,
+        sandbox: Option<&Sandbox>,
+        limits: Option<&ResourceLimits>,
+    ) -> Result<Self, AppError> {
+        let Invocation {
+            args,
+            temp_dir,
+            translator,
+        } = invocation;
+
+        let mut cmd = match sandbox {
+            Some(sandbox) => {
+                let var = |key: &str| env.iter().find(|(k, _)| k == key).map(|(_, v)| PathBuf::from(v));
+                let home = var("HOME").unwrap_or_else(|| PathBuf::from("/"));
+                let config_dir = var("CLAUDE_CONFIG_DIR").unwrap_or_else(|| home.join(".claude"));
+                let path_var = env.iter().find(|(k, _)| k == "PATH").map(|(_, v)| v.as_str());
+                let binary = resolve_binary(&profile.binary_path, path_var);
+                let paths = SandboxPaths {
+                    project_dir,
+                    work_dir: project_dir,
+                    config_dir: &config_dir,
+                    home: &home,
+                    install_dir: &install_dir(&binary),
+                };
+                let argv = sandbox.wrap(&paths, &env, &binary.to_string_lossy());
+                let mut cmd = Command::new(&argv[0]);
+                cmd.args(&argv[1..]);
+                cmd
+            }
+            None => Command::new(&profile.binary_path),
+        };
+        cmd.args(&args);
+        cmd.current_dir(project_dir);
+        cmd.env_clear();
+        cmd.envs(env);
+        cmd.stdin(std::process::Stdio::piped());
+        cmd.stdout(std::process::Stdio::piped());
+        cmd.stderr(std::process::Stdio::piped());
+        let limits = limits.map(|l| l.apply(&mut cmd));
+
+        let child = cmd.spawn().map_err(|e| {
             AppError::ServiceUnavailable(format!("Failed to spawn Claude: {e}"))
         })?;
+        if let (Some(limits), Some(pid)) = (&limits, child.id()) {
+            limits.attach(pid);
+        }
+
+        Ok(Self {
+            child,
+            _temp_dir: temp_dir,
+            started: Instant::now(),
+            probe: Arc::new(Probe::new()),
+            translator,
+            limits,
+        })
+    }
+
+    /// Send the input, close stdin and start streaming stdout.
+    async fn start(mut self, input: &[u8]) -> Result<(Self, MessageStream, Option<String>), AppError> {
+        let child = &mut self.child;
+        self.started = Instant::now();
+        let (started, probe) = (self.started, Arc::clone(&self.probe));
+        if let Some(pid) = child.id() {
+            spawn_rss_sampler(pid, Arc::clone(&probe));
+        }
 
         // Pipe prompt through stdin
         if let Some(mut stdin) = child.stdin.take() {
-            stdin.write_all(prompt.as_bytes()).await.map_err(|e| {
+            stdin.write_all(input).await.map_err(|e| {
                 AppError::Internal(format!("Failed to write prompt to stdin: {e}"))
             })?;
             // Drop stdin to signal EOF — Claude will start processing
@@ -103,66 +436,218 @@ impl ClaudeProcess {
 
         let reader = BufReader::new(stdout);
         let mut lines = reader.lines();
+        let mut output = Output(self.translator.take());
 
-        // Extract session_id from first message, then yield all messages
+        // Extract session_id from the first message, then yield all messages
         let (tx, rx) = tokio::sync::mpsc::channel::<serde_json::Value>(64);
-        let mut session_id_holder: Option<String> = None;
-
-        // Read first line to extract session_id
-        if let Ok(Some(first_line)) = lines.next_line().await {
-            if let Ok(val) = serde_json::from_str::<serde_json::Value>(&first_line) {
-                if let Some(sid) = val.get("session_id").and_then(|v| v.as_str()) {
-                    session_id_holder = Some(sid.to_string());
-                }
-                let _ = tx.send(val).await;
+        let mut first = Vec::new();
+        while first.is_empty() {
+            match lines.next_line().await {
+                Ok(Some(line)) if line.trim().is_empty() => continue,
+                Ok(Some(line)) => first = output.line(&line),
+                _ => break,
             }
         }
-
-        let session_id = session_id_holder.clone();
+        let session_id = first
+            .first()
+            .and_then(|v| v.get("session_id"))
+            .and_then(|v| v.as_str())
+            .map(str::to_string);
+        for val in first {
+            probe.observe(started, &val);
+            let _ = tx.send(val).await;
+        }
 
         // Spawn task to read remaining lines and send them through the channel
         tokio::spawn(async move {
-            while let Ok(Some(line)) = lines.next_line().await {
+            'read: while let Ok(Some(line)) = lines.next_line().await {
                 if line.trim().is_empty() {
                     continue;
                 }
-                match serde_json::from_str::<serde_json::Value>(&line) {
-                    Ok(val) => {
-                        if tx.send(val).await.is_err() {
-                            break;
-                        }
-                    }
-                    Err(_) => {
-                        // Non-JSON output
-                        let val = serde_json::json!({"type": "text", "content": line});
-                        if tx.send(val).await.is_err() {
-                            break;
-                        }
+                for val in output.line(&line) {
+                    probe.observe(started, &val);
+                    if tx.send(val).await.is_err() {
+                        break 'read;
                     }
                 }
             }
+            for val in output.finish() {
+                let _ = tx.send(val).await;
+            }
         });
 
         let stream = tokio_stream::wrappers::ReceiverStream::new(rx);
 
-        Ok((
-            Self {
-                child,
-                _temp_dir: temp_dir,
-            },
-            Box::pin(stream),
-            session_id,
-        ))
+        Ok((self, Box::pin(stream), session_id))
     }
 
     /// Kill the subprocess and reap it to avoid zombies.
-    pub async fn kill(&mut self) {
+    pub async fn kill(&mut self) -> ProcessReport {
         let _ = self.child.kill().await;
-        let _ = self.child.wait().await;
+        self.reap().await
+    }
+
+    /// Ask the subprocess to exit with `SIGTERM`, escalating to `SIGKILL` if
+    /// it is still running after `grace`. A zero grace kills it outright.
+    pub async fn terminate(&mut self, grace: Duration) -> ProcessReport {
+        #[cfg(unix)]
+        if let Some(pid) = self.child.id().filter(|_| !grace.is_zero()) {
+            // SAFETY: signals our own child, which is not reaped yet
+            unsafe { libc::kill(pid as libc::pid_t, libc::SIGTERM) };
+            if tokio::time::timeout(grace, self.child.wait()).await.is_ok() {
+                return self.reap().await;
+            }
+            tracing::warn!(pid, grace_ms = grace.as_millis() as u64, "Process ignored SIGTERM; sending SIGKILL");
+        }
+        self.kill().await
     }
 
     /// Wait for the subprocess to finish and reap it.
-    pub async fn reap(&mut self) {
-        let _ = self.child.wait().await;
+    pub async fn reap(&mut self) -> ProcessReport {
+        let status = self.child.wait().await.ok();
+        self.probe.done.store(true, Ordering::Relaxed);
+        let exit_code = status.and_then(exit_code);
+        let limit_hit = match self.limits.take() {
+            Some(limits) => limits.finish(exit_code).await,
+            None => None,
+        };
+        if let Some(hit) = limit_hit {
+            tracing::warn!(limit = hit.as_str(), exit_code, "Process stopped by its resource limit");
+        }
+        // Subprocesses the CLI left behind may hold the pipe open
+        let mut stderr = tokio::time::timeout(Duration::from_millis(200), self.read_stderr())
+            .await
+            .unwrap_or_default();
+        if stderr.len() > STDERR_TAIL {
+            let mut start = stderr.len() - STDERR_TAIL;
+            while !stderr.is_char_boundary(start) {
+                start += 1;
+            }
+            stderr.drain(..start);
+        }
+        ProcessReport {
+            spawn_ms: get(&self.probe.first_line_ms),
+            ttft_ms: get(&self.probe.first_token_ms),
+            duration_ms: self.started.elapsed().as_millis() as u64,
+            exit_code,
+            peak_rss_kb: get(&self.probe.peak_rss_kb),
+            limit_hit,
+            stderr,
+        }
+    }
+
+    /// The OS process ID (of the sandbox wrapper when sandboxed), `None`
+    /// once the process has been reaped.
+    pub fn pid(&self) -> Option<u32> {
+        self.child.id()
+    }
+
+    /// Current RSS and CPU time (Linux only).
+    pub fn usage(&self) -> Option<ProcessUsage> {
+        self.pid().and_then(read_usage)
+    }
+
+    /// Peak RSS observed so far (Linux only).
+    pub fn peak_rss_kb(&self) -> Option<u64> {
+        get(&self.probe.peak_rss_kb)
+    }
+
+    /// How long ago the process was spawned.
+    pub fn age(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    /// Whether the process has exited (reaping it if so).
+    pub fn has_exited(&mut self) -> bool {
+        matches!(self.child.try_wait(), Ok(Some(_)) | Err(_))
+    }
+
+    /// Read whatever the process wrote to stderr (call after it has exited).
+    async fn read_stderr(&mut self) -> String {
+        let mut buf = String::new();
+        if let Some(mut stderr) = self.child.stderr.take() {
+            let _ = stderr.read_to_string(&mut buf).await;
+        }
+        buf
+    }
+}
+
+/// Parses output lines and applies the backend's translation, if any.
+struct Output(Option<Box<dyn OutputTranslator>>);
+
+impl Output {
+    fn line(&mut self, line: &str) -> Vec<serde_json::Value> {
+        // Non-JSON output is passed on as a text message
+        let val = serde_json::from_str(line)
+            .unwrap_or_else(|_| serde_json::json!({"type": "text", "content": line}));
+        match self.0 {
+            Some(ref mut translator) => translator.line(val),
+            None => vec![val],
+        }
+    }
+
+    fn finish(&mut self) -> Vec<serde_json::Value> {
+        self.0.as_mut().map(|t| t.finish()).unwrap_or_default()
+    }
+}
+
+#[cfg(unix)]
+fn exit_code(status: std::process::ExitStatus) -> Option<i32> {
+    use std::os::unix::process::ExitStatusExt;
+    status.code().or_else(|| status.signal().map(|s| -s))
+}
+
+#[cfg(not(unix))]
+fn exit_code(status: std::process::ExitStatus) -> Option<i32> {
+    status.code()
+}
+
+/// A stand-in CLI for tests that need a real child process.
+#[cfg(all(test, unix))]
+pub(crate) mod testing {
+    use std::os::unix::fs::PermissionsExt;
+
+    use futures::StreamExt;
+
+    use super::*;
+
+    /// Run `script` as the CLI, spawned like a request in `project_dir`, and
+    /// return what it prints, as the run's assistant text.
+    pub async fn run_cli(project_dir: &Path, script: &str) -> String {
+        let bin = tempfile::tempdir().unwrap();
+        let path = bin.path().join("claude");
+        let cli = format!(
+            "#!/bin/sh\ncat >/dev/null\ntext=$({script})\n\
+             printf '{{\"type\":\"assistant\",\"message\":{{\"content\":[{{\"type\":\"text\",\"text\":\"%s\"}}]}}}}\\n' \"$text\"\n\
+             echo '{{\"type\":\"result\",\"subtype\":\"success\",\"is_error\":false,\"result\":\"\"}}'\n"
+        );
+        std::fs::write(&path, cli).unwrap();
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let profile = ClaudeProfile {
+            name: "test".to_string(),
+            backend: "claude".to_string(),
+            binary_path: path.to_string_lossy().into_owned(),
+            config_dir: None,
+            env: Vec::new(),
+            model_prefixes: Vec::new(),
+        };
+        let opts = SpawnOptions {
+            prompt: "hi",
+            model: "claude-sonnet-4-5",
+            system_prompt: None,
+            append_system_prompt: None,
+            disable_builtin_tools: false,
+            env: vec![("PATH".to_string(), std::env::var("PATH").unwrap_or_default())],
+            project_dir,
+            sandbox: None,
+            limits: None,
+            resume: None,
+            plan: false,
+            priority: Priority::Normal,
+        };
+        let (mut process, stream, _) = ClaudeProcess::spawn(&profile, CliCapabilities::default(), opts).await.unwrap();
+        let text = stream.filter_map(|msg| async move { extract_assistant_content(&msg) }).collect::<Vec<_>>().await;
+        process.reap().await;
+        text.concat()
     }
 }
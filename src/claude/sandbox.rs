@@ -0,0 +1,292 @@
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+/// Command template that wraps every spawned CLI in a sandbox.
+///
+/// The `bwrap` preset starts from an empty root with only the system
+/// directories the CLI needs (`/usr`, `/lib*`, `/bin`, a few files of
+/// `/etc`) read-only, the CLI's install directory, its config dir and the
+/// project directory, and a private `/tmp`. `firejail` cannot start from an
+/// empty root: it hides everything else in each top-level directory that
+/// holds one of those paths (home, `/tmp`, `/srv`, ...), plus the gateway's
+/// `.env` and database, and keeps the rest read-only.
+///
+/// Templates are split on whitespace (no shell) and support the placeholders
+/// `{project_dir}`, `{work_dir}`, `{config_dir}`, `{home}` and
+/// `{install_dir}`; a `{env}` token
+/// expands to `-e KEY` for every variable passed to the CLI (for `docker run`),
+/// and `{binary}` marks where the CLI goes (appended at the end otherwise).
+#[derive(Debug, Clone)]
+pub struct Sandbox {
+    template: Vec<String>,
+    /// CLI path inside the sandbox when it differs from the host's (docker).
+    binary: Option<String>,
+}
+
+/// Host paths substituted into the template.
+pub struct SandboxPaths<'a> {
+    /// Writable project directory; the only writable location besides the config dir.
+    pub project_dir: &'a Path,
+    /// Working directory of the CLI; the project dir.
+    pub work_dir: &'a Path,
+    /// The account's CLI config dir (`CLAUDE_CONFIG_DIR` or `~/.claude`).
+    pub config_dir: &'a Path,
+    pub home: &'a Path,
+    /// Where the CLI is installed, see [`install_dir`].
+    pub install_dir: &'a Path,
+}
+
+/// System paths a CLI needs, bound read-only by the `bwrap` preset when
+/// they exist.
+const SYSTEM_PATHS: &[&str] = &[
+    "/usr",
+    "/bin",
+    "/sbin",
+    "/lib",
+    "/lib32",
+    "/lib64",
+    "/etc/alternatives",
+    "/etc/ssl",
+    "/etc/ca-certificates",
+    "/etc/pki",
+    "/etc/resolv.conf",
+    "/etc/hosts",
+    "/etc/nsswitch.conf",
+    "/etc/passwd",
+    "/etc/group",
+    "/etc/localtime",
+];
+
+impl Sandbox {
+    /// Build the sandbox from `SANDBOX` (`none`, `bwrap`, `firejail`, `docker`)
+    /// or a custom `SANDBOX_COMMAND`, which takes precedence.
+    pub fn from_config(config: &Config) -> Option<Self> {
+        let net = config.sandbox_network;
+        let mut binary = None;
+        let template: Vec<String> = match (&config.sandbox_command, config.sandbox.as_str()) {
+            (Some(cmd), _) => cmd.split_whitespace().map(str::to_string).collect(),
+            (None, "bwrap") => {
+                let mut t = vec!["bwrap".to_string()];
+                for path in SYSTEM_PATHS {
+                    t.extend(["--ro-bind-try", path, path].map(str::to_string));
+                }
+                t.extend(words(
+                    "--ro-bind {install_dir} {install_dir} --dev /dev --proc /proc --tmpfs /tmp \
+                     --bind {project_dir} {project_dir} --bind-try {config_dir} {config_dir} \
+                     --bind-try {home}/.claude.json {home}/.claude.json \
+                     --unshare-all --die-with-parent --new-session --chdir {work_dir}",
+                ));
+                if net {
+                    t.push("--share-net".to_string());
+                }
+                t.push("--".to_string());
+                t
+            }
+            (None, "firejail") => {
+                let etc: Vec<&str> = SYSTEM_PATHS.iter().filter_map(|p| p.strip_prefix("/etc/")).collect();
+                let mut t = words(
+                    "firejail --quiet --noprofile --private-dev --private-tmp \
+                     --whitelist={project_dir} --whitelist={config_dir} \
+                     --whitelist={home}/.claude.json --whitelist={install_dir}",
+                );
+                t.push(format!("--private-etc={}", etc.join(",")));
+                for path in gateway_files(config) {
+                    t.push(format!("--blacklist={}", path.display()));
+                }
+                t.extend(words(
+                    "--read-only=/ --read-write={project_dir} --read-write={config_dir} \
+                     --read-write={home}/.claude.json",
+                ));
+                if !net {
+                    t.push("--net=none".to_string());
+                }
+                t.push("--".to_string());
+                t
+            }
+            (None, "docker") => {
+                let mut t = words(
+                    "docker run --rm -i --init {env} \
+                     -v {project_dir}:{project_dir} -v {config_dir}:{config_dir} -w {work_dir}",
+                );
+                if !net {
+                    t.extend(words("--network none"));
+                }
+                t.push(config.sandbox_image.clone());
+                binary = Some("claude".to_string());
+                t
+            }
+            (None, "" | "none") => return None,
+            (None, other) => {
+                tracing::warn!(sandbox = other, "Unknown SANDBOX mode; running unsandboxed");
+                return None;
+            }
+        };
+        (!template.is_empty()).then_some(Self { template, binary })
+    }
+
+    /// Program and arguments that launch `binary` inside the sandbox; the
+    /// CLI's own arguments follow.
+    pub fn wrap(&self, paths: &SandboxPaths, env: &[(String, String)], binary: &str) -> Vec<String> {
+        let binary = self.binary.as_deref().unwrap_or(binary);
+        let mut out = Vec::new();
+        let mut has_binary = false;
+        for token in &self.template {
+            match token.as_str() {
+                "{env}" => {
+                    // The container keeps its own PATH to find its CLI
+                    for (k, _) in env.iter().filter(|(k, _)| k != "PATH") {
+                        out.push("-e".to_string());
+                        out.push(k.clone());
+                    }
+                }
+                "{binary}" => {
+                    has_binary = true;
+                    out.push(binary.to_string());
+                }
+                _ => out.push(
+                    token
+                        .replace("{project_dir}", &paths.project_dir.to_string_lossy())
+                        .replace("{work_dir}", &paths.work_dir.to_string_lossy())
+                        .replace("{config_dir}", &paths.config_dir.to_string_lossy())
+                        .replace("{home}", &paths.home.to_string_lossy())
+                        .replace("{install_dir}", &paths.install_dir.to_string_lossy()),
+                ),
+            }
+        }
+        if !has_binary {
+            out.push(binary.to_string());
+        }
+        out
+    }
+}
+
+/// The real path of `binary`, looked up in `path_var` (`PATH`) if it is a
+/// bare name. Sandboxes run it by this path, so symlinks to it need not be
+/// visible inside.
+pub fn resolve_binary(binary: &str, path_var: Option<&str>) -> PathBuf {
+    let found = if binary.contains('/') {
+        Some(PathBuf::from(binary))
+    } else {
+        path_var.and_then(|paths| {
+            std::env::split_paths(paths).map(|dir| dir.join(binary)).find(|p| p.is_file())
+        })
+    };
+    found
+        .and_then(|p| std::fs::canonicalize(p).ok())
+        .unwrap_or_else(|| PathBuf::from(binary))
+}
+
+/// The directory to make visible for the CLI at `binary` (a real path): its
+/// own directory, or for a CLI inside `node_modules` the prefix holding
+/// that tree, which also has the `node` it runs with.
+pub fn install_dir(binary: &Path) -> PathBuf {
+    let modules = binary.ancestors().filter(|dir| dir.ends_with("node_modules")).last();
+    let dir = match modules.and_then(Path::parent) {
+        Some(parent) if parent.ends_with("lib") => parent.parent(),
+        Some(parent) => Some(parent),
+        None => binary.parent(),
+    };
+    dir.unwrap_or(Path::new("/")).to_path_buf()
+}
+
+/// The gateway's own secrets next to it: `.env` and a SQLite database.
+fn gateway_files(config: &Config) -> Vec<PathBuf> {
+    let cwd = std::env::current_dir().unwrap_or_default();
+    let mut files = vec![cwd.join(".env")];
+    if let Some(db) = config.database_url.strip_prefix("sqlite:") {
+        let db = db.trim_start_matches("//");
+        let db = cwd.join(db.split('?').next().unwrap_or(db));
+        files.extend(["", "-wal", "-shm"].map(|suffix| PathBuf::from(format!("{}{suffix}", db.display()))));
+    }
+    files
+}
+
+fn words(s: &str) -> Vec<String> {
+    s.split_whitespace().map(str::to_string).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn paths() -> SandboxPaths<'static> {
+        SandboxPaths {
+            project_dir: Path::new("/srv/p/a"),
+            work_dir: Path::new("/srv/p/a/tmp1"),
+            config_dir: Path::new("/home/svc/.claude"),
+            home: Path::new("/home/svc"),
+            install_dir: Path::new("/home/svc/.local/share/claude"),
+        }
+    }
+
+    #[test]
+    fn test_custom_template() {
+        let sandbox = Sandbox {
+            template: words("nsjail --cwd {work_dir} -B {project_dir} --"),
+            binary: None,
+        };
+        assert_eq!(
+            sandbox.wrap(&paths(), &[], "/usr/bin/claude"),
+            words("nsjail --cwd /srv/p/a/tmp1 -B /srv/p/a -- /usr/bin/claude")
+        );
+
+        let sandbox = Sandbox {
+            template: words("run {binary} --flag"),
+            binary: None,
+        };
+        assert_eq!(sandbox.wrap(&paths(), &[], "claude-beta"), words("run claude-beta --flag"));
+    }
+
+    #[test]
+    fn test_bwrap_binds_only_what_the_cli_needs() {
+        let mut config = Config::for_test();
+        config.sandbox = "bwrap".to_string();
+        config.sandbox_command = None;
+        config.sandbox_network = true;
+        let sandbox = Sandbox::from_config(&config).unwrap();
+        let argv = sandbox.wrap(&paths(), &[], "/home/svc/.local/share/claude/claude");
+        let binds: Vec<&str> = argv
+            .windows(2)
+            .filter(|w| w[0].starts_with("--ro-bind") || w[0].starts_with("--bind"))
+            .map(|w| w[1].as_str())
+            .collect();
+        assert!(!binds.contains(&"/"));
+        assert!(!binds.contains(&"/home/svc"));
+        assert!(!binds.contains(&"/etc"));
+        for path in ["/usr", "/etc/ssl", "/srv/p/a", "/home/svc/.claude", "/home/svc/.local/share/claude"] {
+            assert!(binds.contains(&path), "{path}");
+        }
+        assert!(argv.windows(2).any(|w| w == ["--tmpfs", "/tmp"]));
+        assert_eq!(argv.last().unwrap(), "/home/svc/.local/share/claude/claude");
+    }
+
+    #[test]
+    fn test_install_dir() {
+        assert_eq!(
+            install_dir(Path::new("/home/svc/.nvm/versions/node/v22.1.0/lib/node_modules/@anthropic-ai/claude-code/cli.js")),
+            Path::new("/home/svc/.nvm/versions/node/v22.1.0")
+        );
+        assert_eq!(
+            install_dir(Path::new("/home/svc/.local/share/claude/versions/2.0.0")),
+            Path::new("/home/svc/.local/share/claude/versions")
+        );
+        assert_eq!(resolve_binary("claude", Some("/nonexistent")), Path::new("claude"));
+    }
+
+    #[test]
+    fn test_docker_env_flags() {
+        let mut config = Config::for_test();
+        config.sandbox = "docker".to_string();
+        config.sandbox_command = None;
+        config.sandbox_network = false;
+        config.sandbox_image = "img".to_string();
+        let sandbox = Sandbox::from_config(&config).unwrap();
+        let env = [("HOME".to_string(), "/home/svc".to_string())];
+        let argv = sandbox.wrap(&paths(), &env, "/usr/local/bin/claude");
+        assert_eq!(&argv[..6], &words("docker run --rm -i --init -e")[..]);
+        assert_eq!(argv[6], "HOME");
+        assert!(argv.windows(2).any(|w| w == ["--network", "none"]));
+        assert_eq!(&argv[argv.len() - 2..], &words("img claude")[..]);
+    }
+}
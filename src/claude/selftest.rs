@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+
+use crate::claude::parser::{extract_assistant_content, is_assistant_message, is_result_message};
+use crate::claude::manager::create_project_directory;
+use crate::claude::backend;
+use crate::claude::process::SpawnOptions;
+use crate::claude::limits::ResourceLimits;
+use crate::claude::sandbox::Sandbox;
+use crate::claude::version::CliCapabilities;
+use crate::config::Config;
+use crate::priority::Priority;
+use crate::routes::root::get_claude_version;
+
+const PING_PROMPT: &str = "Reply with the single word: pong";
+
+/// Why the startup self-test failed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SelfTestFailure {
+    BinaryMissing,
+    NotLoggedIn,
+    NetworkError,
+    Timeout,
+    Other,
+}
+
+impl SelfTestFailure {
+    /// Operator-facing hint for fixing the failure.
+    pub fn hint(self) -> &'static str {
+        match self {
+            Self::BinaryMissing => "Claude CLI binary not found or not executable; check CLAUDE_BINARY_PATH",
+            Self::NotLoggedIn => "Claude CLI is not authenticated; run `claude login` as the service user",
+            Self::NetworkError => "Claude CLI could not reach the Anthropic API; check network/proxy settings",
+            Self::Timeout => "Claude CLI did not answer the ping within the self-test timeout",
+            Self::Other => "Claude CLI ping failed",
+        }
+    }
+}
+
+impl std::fmt::Display for SelfTestFailure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::BinaryMissing => "binary_missing",
+            Self::NotLoggedIn => "not_logged_in",
+            Self::NetworkError => "network_error",
+            Self::Timeout => "timeout",
+            Self::Other => "other",
+        };
+        f.write_str(s)
+    }
+}
+
+/// Run `--version` and a tiny "ping" completion through [`ClaudeProcess`]
+/// using the default profile.
+///
+/// Returns the CLI version on success, or the failure kind plus details.
+pub async fn run(
+    config: &Config,
+    caps: CliCapabilities,
+) -> Result<String, (SelfTestFailure, String)> {
+    let version = get_claude_version(&config.default_profile().binary_path)
+        .await
+        .map_err(|e| {
+            let kind = match e.kind() {
+                std::io::ErrorKind::NotFound | std::io::ErrorKind::PermissionDenied => {
+                    SelfTestFailure::BinaryMissing
+                }
+                _ => SelfTestFailure::Other,
+            };
+            (kind, e.to_string())
+        })?;
+
+    let model = config.model_catalog.resolve(&config.self_test_model);
+    let project_dir = create_project_directory(&config.project_root, "default")
+        .map_err(|e| (SelfTestFailure::Other, e.to_string()))?;
+    let sandbox = Sandbox::from_config(config);
+    let limits = ResourceLimits::from_config(config);
+    let opts = SpawnOptions {
+        prompt: PING_PROMPT,
+        model: &model,
+        system_prompt: None,
+        append_system_prompt: None,
+        disable_builtin_tools: true,
+        env: crate::claude::env::build_env(config, config.default_profile(), None),
+        project_dir: &project_dir,
+        sandbox: sandbox.as_ref(),
+        limits: limits.as_ref(),
+        resume: None,
+        plan: false,
+        priority: Priority::Normal,
+    };
+    let profile = config.default_profile();
+    let (mut process, mut stream, _) = backend::for_profile(profile).spawn(profile, caps, opts)
+        .await
+        .map_err(|e| (SelfTestFailure::BinaryMissing, e.to_string()))?;
+
+    let timeout = Duration::from_secs(config.self_test_timeout_seconds);
+    let collected = tokio::time::timeout(timeout, async {
+        let mut reply = None;
+        let mut result = None;
+        while let Some(msg) = stream.next().await {
+            if is_assistant_message(&msg) {
+                reply = reply.or_else(|| extract_assistant_content(&msg));
+            }
+            if is_result_message(&msg) {
+                result = Some(msg);
+                break;
+            }
+        }
+        (reply, result)
+    })
+    .await;
+
+    let Ok((reply, result)) = collected else {
+        process.kill().await;
+        return Err((SelfTestFailure::Timeout, format!("no result after {timeout:?}")));
+    };
+    let stderr = process.reap().await.stderr;
+
+    let is_error = result
+        .as_ref()
+        .and_then(|r| r.get("is_error"))
+        .and_then(|v| v.as_bool())
+        .unwrap_or(false);
+    if reply.is_some() && !is_error {
+        return Ok(version);
+    }
+
+    let detail = result
+        .as_ref()
+        .and_then(|r| r.get("result"))
+        .and_then(|v| v.as_str())
+        .map(str::to_string)
+        .or(reply)
+        .unwrap_or_default();
+    let detail = format!("{detail} {}", stderr.trim()).trim().to_string();
+    Err((classify(&detail), detail))
+}
+
+/// Classify CLI error output into a failure kind.
+pub fn classify(output: &str) -> SelfTestFailure {
+    let lower = output.to_lowercase();
+    const AUTH: &[&str] = &["login", "log in", "invalid api key", "authenticat", "oauth", "unauthorized", "401"];
+    const NETWORK: &[&str] = &[
+        "network", "econnrefused", "enotfound", "etimedout", "econnreset",
+        "connection error", "fetch failed", "getaddrinfo", "socket hang up",
+    ];
+    if AUTH.iter().any(|p| lower.contains(p)) {
+        SelfTestFailure::NotLoggedIn
+    } else if NETWORK.iter().any(|p| lower.contains(p)) {
+        SelfTestFailure::NetworkError
+    } else {
+        SelfTestFailure::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(classify("Invalid API key · Please run /login"), SelfTestFailure::NotLoggedIn);
+        assert_eq!(classify("API Error: Connection error. (fetch failed)"), SelfTestFailure::NetworkError);
+        assert_eq!(classify("getaddrinfo ENOTFOUND api.anthropic.com"), SelfTestFailure::NetworkError);
+        assert_eq!(classify("something else"), SelfTestFailure::Other);
+    }
+}
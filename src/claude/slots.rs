@@ -0,0 +1,195 @@
+//! Concurrency slots for CLI runs. A semaphore whose waiters are served by
+//! [`Priority`], then in order of arrival, so queued background work does
+//! not hold up interactive requests.
+
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use serde_json::{json, Value};
+use tokio::sync::oneshot;
+
+use crate::priority::Priority;
+
+/// Why a slot could not be had.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SlotError {
+    /// None is free (and waiting was not allowed or timed out).
+    Full,
+    /// The slots were closed for shutdown.
+    Closed,
+}
+
+type WaiterKey = (Reverse<Priority>, u64);
+
+#[derive(Default)]
+struct Queue {
+    free: usize,
+    closed: bool,
+    arrivals: u64,
+    /// Served first to last.
+    waiters: BTreeMap<WaiterKey, oneshot::Sender<()>>,
+}
+
+pub struct Slots {
+    queue: Mutex<Queue>,
+}
+
+/// A taken slot, given back when dropped.
+pub struct SlotPermit {
+    slots: Arc<Slots>,
+}
+
+impl Drop for SlotPermit {
+    fn drop(&mut self) {
+        self.slots.release();
+    }
+}
+
+impl Slots {
+    pub fn new(size: usize) -> Arc<Self> {
+        Arc::new(Self { queue: Mutex::new(Queue { free: size, ..Queue::default() }) })
+    }
+
+    /// Take a free slot without waiting.
+    pub fn try_acquire(self: &Arc<Self>) -> Result<SlotPermit, SlotError> {
+        let mut queue = self.lock();
+        if queue.closed {
+            return Err(SlotError::Closed);
+        }
+        if queue.free == 0 {
+            return Err(SlotError::Full);
+        }
+        queue.free -= 1;
+        Ok(SlotPermit { slots: Arc::clone(self) })
+    }
+
+    /// Take a slot, waiting up to `timeout` behind the requests of the same
+    /// or higher priority that came first.
+    pub async fn acquire(self: &Arc<Self>, priority: Priority, timeout: std::time::Duration) -> Result<SlotPermit, SlotError> {
+        let (tx, rx) = oneshot::channel();
+        let key = {
+            let mut queue = self.lock();
+            if queue.closed {
+                return Err(SlotError::Closed);
+            }
+            if queue.free > 0 && queue.waiters.is_empty() {
+                queue.free -= 1;
+                return Ok(SlotPermit { slots: Arc::clone(self) });
+            }
+            queue.arrivals += 1;
+            let key = (Reverse(priority), queue.arrivals);
+            queue.waiters.insert(key, tx);
+            key
+        };
+        let mut waiter = Waiter { slots: self, key, rx: Some(rx) };
+        let received = tokio::time::timeout(timeout, waiter.rx.as_mut().expect("set above")).await;
+        match received {
+            Ok(Ok(())) => {
+                waiter.rx = None;
+                Ok(SlotPermit { slots: Arc::clone(self) })
+            }
+            // The sender was dropped by `close`
+            Ok(Err(_)) => {
+                waiter.rx = None;
+                Err(SlotError::Closed)
+            }
+            Err(_) => Err(SlotError::Full),
+        }
+    }
+
+    /// Hand a slot to the first waiter still waiting, else free it.
+    fn release(&self) {
+        let mut queue = self.lock();
+        while let Some((_, tx)) = queue.waiters.pop_first() {
+            if tx.send(()).is_ok() {
+                return;
+            }
+        }
+        queue.free += 1;
+    }
+
+    /// Refuse new requests and fail the waiting ones.
+    pub fn close(&self) {
+        let mut queue = self.lock();
+        queue.closed = true;
+        queue.waiters.clear();
+    }
+
+    /// Requests waiting, by priority.
+    pub fn waiting(&self) -> Value {
+        let queue = self.lock();
+        let count = |p: Priority| queue.waiters.keys().filter(|(Reverse(q), _)| *q == p).count();
+        json!({
+            "low": count(Priority::Low),
+            "normal": count(Priority::Normal),
+            "high": count(Priority::High),
+        })
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Queue> {
+        self.queue.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// A place in the queue. Leaving it, e.g. on timeout or when the request is
+/// dropped, passes on a slot that was handed over meanwhile.
+struct Waiter<'a> {
+    slots: &'a Arc<Slots>,
+    key: WaiterKey,
+    rx: Option<oneshot::Receiver<()>>,
+}
+
+impl Drop for Waiter<'_> {
+    fn drop(&mut self) {
+        let Some(mut rx) = self.rx.take() else {
+            return;
+        };
+        let mut queue = self.slots.lock();
+        if queue.waiters.remove(&self.key).is_none() && rx.try_recv().is_ok() {
+            drop(queue);
+            self.slots.release();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_slots_by_priority() {
+        let slots = Slots::new(1);
+        let held = slots.try_acquire().unwrap();
+        assert_eq!(slots.try_acquire().err(), Some(SlotError::Full));
+
+        let wait = Duration::from_secs(5);
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut waiters = Vec::new();
+        for (name, priority) in [("low", Priority::Low), ("normal", Priority::Normal), ("high", Priority::High)] {
+            let (slots, order) = (Arc::clone(&slots), Arc::clone(&order));
+            waiters.push(tokio::spawn(async move {
+                let _permit = slots.acquire(priority, wait).await.unwrap();
+                order.lock().unwrap().push(name);
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }));
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert_eq!(slots.waiting()["low"], 1);
+        drop(held);
+        for waiter in waiters {
+            waiter.await.unwrap();
+        }
+        assert_eq!(*order.lock().unwrap(), ["high", "normal", "low"]);
+
+        // A waiter that gives up does not keep the slot from the others
+        let held = slots.try_acquire().unwrap();
+        assert_eq!(slots.acquire(Priority::High, Duration::from_millis(10)).await.err(), Some(SlotError::Full));
+        drop(held);
+        let permit = slots.try_acquire().unwrap();
+        slots.close();
+        assert_eq!(slots.acquire(Priority::High, wait).await.err(), Some(SlotError::Closed));
+        drop(permit);
+    }
+}
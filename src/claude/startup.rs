@@ -0,0 +1,131 @@
+use std::collections::HashMap;
+
+use crate::claude::{backend, selftest};
+use crate::claude::version::{CliCapabilities, CliVersion};
+use crate::config::Config;
+use crate::routes::root::get_claude_version;
+use crate::systemd;
+
+/// What startup learned about the configured Claude CLIs.
+pub struct CliProbe {
+    /// Version of the default profile's CLI, if it could be parsed.
+    pub cli_version: Option<CliVersion>,
+    /// Capabilities of the non-default profiles.
+    pub profile_caps: HashMap<String, CliCapabilities>,
+    /// Whether the gateway should report itself ready to serve.
+    pub ready: bool,
+    /// The self-test's detail when the default profile's CLI is not
+    /// logged in.
+    pub auth_failure: Option<String>,
+}
+
+/// Probe every profile's CLI version and, if `STARTUP_SELF_TEST` is on, run
+/// a ping completion. Failures are logged and reported to systemd rather
+/// than aborting startup.
+pub async fn probe(config: &Config) -> CliProbe {
+    // Probe the CLI version once; spawned flags are adapted to it
+    let mock = config.claude_backend == "mock";
+    if mock {
+        tracing::warn!("CLAUDE_BACKEND=mock: serving synthetic responses, no Claude CLI is spawned");
+    }
+    let default_binary = config.default_profile().binary_path.clone();
+    let cli_version = if mock {
+        Err(None)
+    } else {
+        match get_claude_version(&default_binary).await {
+            Ok(raw) => {
+                tracing::info!(claude_version = %raw, "Claude CLI available");
+                let parsed = CliVersion::parse(&raw);
+                match parsed {
+                    Some(ref v) => v.warn_if_unsupported(),
+                    None => tracing::warn!(
+                        claude_version = %raw,
+                        "Could not parse Claude CLI version; assuming current flags"
+                    ),
+                }
+                parsed.map(Ok).unwrap_or(Err(None))
+            }
+            Err(e) => {
+                tracing::error!(error = %e, "Claude CLI check failed");
+                systemd::notify(&format!("STATUS=Claude CLI check failed: {e}"));
+                Err(Some(e))
+            }
+        }
+    };
+    let caps = CliCapabilities::for_version(cli_version.as_ref().ok());
+
+    // Probe the remaining profiles; a broken one only fails its own requests
+    let mut profile_caps = HashMap::new();
+    for profile in config.claude_profiles.iter().skip(1).filter(|_| !mock) {
+        if backend::by_name(&profile.backend).is_none() {
+            tracing::error!(
+                profile = %profile.name,
+                backend = %profile.backend,
+                "Unknown profile backend (expected claude, codex or gemini); using claude"
+            );
+        }
+        let version = match get_claude_version(&profile.binary_path).await {
+            Ok(raw) => {
+                tracing::info!(
+                    profile = %profile.name,
+                    backend = %profile.backend,
+                    version = %raw,
+                    models = ?profile.model_prefixes,
+                    "Agent CLI profile available"
+                );
+                // Claude flag capabilities only apply to the Claude CLI
+                CliVersion::parse(&raw).filter(|_| profile.backend == "claude")
+            }
+            Err(e) => {
+                tracing::error!(profile = %profile.name, error = %e, "Agent CLI profile check failed");
+                None
+            }
+        };
+        if let Some(ref v) = version {
+            v.warn_if_unsupported();
+        }
+        profile_caps.insert(profile.name.clone(), CliCapabilities::for_version(version.as_ref()));
+    }
+
+    for (model, name) in &config.model_routes {
+        if !config.claude_profiles.iter().any(|p| p.name == *name) {
+            tracing::error!(model, profile = %name, "MODEL_ROUTES names an unknown profile; ignoring");
+        }
+    }
+
+    // Verify the Claude CLI before reporting readiness: a full ping
+    // completion when STARTUP_SELF_TEST is on, otherwise the version probe.
+    let mut auth_failure = None;
+    let claude_ok = if mock {
+        true
+    } else if config.startup_self_test {
+        match selftest::run(config, caps).await {
+            Ok(version) => {
+                tracing::info!(claude_version = %version, "Claude CLI self-test passed");
+                true
+            }
+            Err((kind, detail)) => {
+                tracing::error!(
+                    failure = %kind,
+                    detail = %detail,
+                    "CLAUDE CLI SELF-TEST FAILED: {}",
+                    kind.hint()
+                );
+                systemd::notify(&format!("STATUS=Claude CLI self-test failed: {}", kind.hint()));
+                if kind == selftest::SelfTestFailure::NotLoggedIn {
+                    auth_failure = Some(detail);
+                }
+                false
+            }
+        }
+    } else {
+        !matches!(cli_version, Err(Some(_)))
+    };
+
+    CliProbe {
+        cli_version: cli_version.ok(),
+        profile_caps,
+        ready: claude_ok,
+        auth_failure,
+    }
+}
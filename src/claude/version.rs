@@ -0,0 +1,126 @@
+/// Oldest CLI release the gateway is known to work with.
+const MIN_SUPPORTED: (u64, u64, u64) = (1, 0, 0);
+/// Newest major version the flag mapping has been checked against.
+const MAX_TESTED_MAJOR: u64 = 2;
+/// First release with `--system-prompt` (older ones only have `--append-system-prompt`).
+const SYSTEM_PROMPT_FLAG_SINCE: (u64, u64, u64) = (2, 0, 0);
+/// First release with `--tools` (older ones need `--disallowedTools`).
+const TOOLS_FLAG_SINCE: (u64, u64, u64) = (2, 0, 0);
+
+/// Built-in tools disabled via `--disallowedTools` on CLIs without `--tools`.
+const BUILTIN_TOOLS: &[&str] = &[
+    "Bash", "Edit", "Glob", "Grep", "LS", "MultiEdit", "NotebookEdit", "Read",
+    "Task", "TodoWrite", "WebFetch", "WebSearch", "Write",
+];
+
+/// A parsed `claude --version` result, e.g. `2.0.14 (Claude Code)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CliVersion {
+    pub major: u64,
+    pub minor: u64,
+    pub patch: u64,
+    pub raw: String,
+}
+
+impl CliVersion {
+    /// Parse the first `X.Y.Z` found in the version output.
+    pub fn parse(output: &str) -> Option<Self> {
+        let token = output
+            .split_whitespace()
+            .map(|t| t.trim_start_matches('v'))
+            .find(|t| t.chars().next().is_some_and(|c| c.is_ascii_digit()))?;
+        let core = token.split(['-', '+']).next()?;
+        let mut parts = core.split('.').map(|p| p.parse::<u64>());
+        Some(Self {
+            major: parts.next()?.ok()?,
+            minor: parts.next().unwrap_or(Ok(0)).ok()?,
+            patch: parts.next().unwrap_or(Ok(0)).ok()?,
+            raw: output.trim().to_string(),
+        })
+    }
+
+    fn at_least(&self, v: (u64, u64, u64)) -> bool {
+        (self.major, self.minor, self.patch) >= v
+    }
+
+    /// Log a prominent warning when the version is outside the tested range.
+    pub fn warn_if_unsupported(&self) {
+        if !self.at_least(MIN_SUPPORTED) {
+            tracing::warn!(
+                version = %self.raw,
+                "UNSUPPORTED Claude CLI version (older than {}.{}.{}); requests may fail",
+                MIN_SUPPORTED.0, MIN_SUPPORTED.1, MIN_SUPPORTED.2
+            );
+        } else if self.major > MAX_TESTED_MAJOR {
+            tracing::warn!(
+                version = %self.raw,
+                "UNTESTED Claude CLI major version; flag compatibility is not guaranteed"
+            );
+        }
+    }
+}
+
+/// Flags the installed CLI understands, derived from its version.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CliCapabilities {
+    pub system_prompt_flag: bool,
+    pub tools_flag: bool,
+}
+
+impl Default for CliCapabilities {
+    /// Unknown versions are assumed to be current.
+    fn default() -> Self {
+        Self {
+            system_prompt_flag: true,
+            tools_flag: true,
+        }
+    }
+}
+
+impl CliCapabilities {
+    pub fn for_version(version: Option<&CliVersion>) -> Self {
+        match version {
+            Some(v) => Self {
+                system_prompt_flag: v.at_least(SYSTEM_PROMPT_FLAG_SINCE),
+                tools_flag: v.at_least(TOOLS_FLAG_SINCE),
+            },
+            None => Self::default(),
+        }
+    }
+
+    /// Arguments that disable all built-in tools.
+    pub fn disable_tools_args(&self) -> Vec<String> {
+        if self.tools_flag {
+            vec!["--tools".to_string(), String::new()]
+        } else {
+            vec!["--disallowedTools".to_string(), BUILTIN_TOOLS.join(",")]
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_version() {
+        let v = CliVersion::parse("2.0.14 (Claude Code)").unwrap();
+        assert_eq!((v.major, v.minor, v.patch), (2, 0, 14));
+        let v = CliVersion::parse("claude v1.0.3-beta.1").unwrap();
+        assert_eq!((v.major, v.minor, v.patch), (1, 0, 3));
+        assert!(CliVersion::parse("not a version").is_none());
+    }
+
+    #[test]
+    fn test_capabilities_by_version() {
+        let old = CliVersion::parse("1.0.80 (Claude Code)");
+        let caps = CliCapabilities::for_version(old.as_ref());
+        assert!(!caps.system_prompt_flag);
+        assert_eq!(caps.disable_tools_args()[0], "--disallowedTools");
+
+        let new = CliVersion::parse("2.1.0 (Claude Code)");
+        let caps = CliCapabilities::for_version(new.as_ref());
+        assert!(caps.system_prompt_flag && caps.tools_flag);
+        assert_eq!(CliCapabilities::for_version(None), CliCapabilities::default());
+    }
+}
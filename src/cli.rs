@@ -0,0 +1,182 @@
+//! The `claude-code-api` command line: `serve` runs the gateway, `mcp`
+//! serves its MCP tools over stdio, the other subcommands are one-off
+//! operational tasks against the configured database and CLIs.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use clap::{Parser, Subcommand};
+
+use crate::auth::hash_api_key;
+use crate::claude::startup;
+use crate::config::Config;
+use crate::crypto;
+use crate::db;
+use crate::mcp;
+use crate::state::AppState;
+
+#[derive(Debug, Parser)]
+#[command(name = "claude-code-api", version, about = "OpenAI-compatible API gateway for the Claude Code CLI")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the gateway (the default).
+    Serve,
+    /// Apply pending database migrations and exit.
+    Migrate,
+    /// Generate a random API key and print it with its SHA-256 hash.
+    Keygen {
+        /// Prepended to the random part.
+        #[arg(long, default_value = "sk-")]
+        prefix: String,
+    },
+    /// Database maintenance.
+    Db {
+        #[command(subcommand)]
+        command: DbCommand,
+    },
+    /// Stored conversations.
+    Sessions {
+        #[command(subcommand)]
+        command: SessionsCommand,
+    },
+    /// Validate the configuration and probe every profile's CLI. Exits
+    /// non-zero if the gateway would not report itself ready.
+    Check,
+    /// Serve the MCP tools over stdin/stdout instead of HTTP. Logs go to
+    /// stderr.
+    Mcp,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum DbCommand {
+    /// Row counts, schema version and on-disk size.
+    Stats,
+    /// Encrypt conversation content (messages, transcript events, job
+    /// responses, the response cache and recordings) stored before
+    /// `ENCRYPTION_KEY` was set.
+    Encrypt,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum SessionsCommand {
+    /// Deactivate idle sessions and/or delete old conversation data.
+    Prune {
+        /// Deactivate sessions idle for this many minutes, like the reaper.
+        #[arg(long)]
+        idle_minutes: Option<u64>,
+        /// Hard-delete messages and transcripts older than this, and the
+        /// sessions they leave empty, like `MESSAGE_RETENTION_DAYS`.
+        #[arg(long)]
+        older_than_days: Option<u64>,
+    },
+}
+
+/// Run a subcommand other than `serve`, printing its result to stdout.
+pub async fn run(command: Command, config: Config) -> Result<(), String> {
+    match command {
+        Command::Serve => unreachable!("serve is handled by the binary"),
+        Command::Migrate => {
+            let pool = db::init_db_with(&config.database_url, &db::DbOptions::from_config(&config)).await.map_err(|e| e.to_string())?;
+            let version = db::schema_version(&pool).await.map_err(|e| e.to_string())?;
+            println!("Database {} is at schema version {version}", config.database_url);
+        }
+        Command::Keygen { prefix } => {
+            let key = generate_key(&prefix);
+            println!("key:    {key}");
+            println!("sha256: {}", hash_api_key(&key));
+        }
+        Command::Db { command: DbCommand::Stats } => {
+            let pool = db::init_db_with(&config.database_url, &db::DbOptions::from_config(&config)).await.map_err(|e| e.to_string())?;
+            let version = db::schema_version(&pool).await.map_err(|e| e.to_string())?;
+            let size = db::db_size(&pool).await.map_err(|e| e.to_string())?;
+            println!("{:<20} {version}", "schema version");
+            println!("{:<20} {}", "size (bytes)", size.size_bytes);
+            println!("{:<20} {}", "free (bytes)", size.free_bytes);
+            println!("{:<20} {}", "wal (bytes)", size.wal_bytes);
+            for (table, count) in db::table_counts(&pool).await.map_err(|e| e.to_string())? {
+                println!("{table:<20} {count}");
+            }
+        }
+        Command::Db { command: DbCommand::Encrypt } => {
+            if !crypto::init(&config)? {
+                return Err("no encryption key is configured; set ENCRYPTION_KEY, ENCRYPTION_KEY_FILE or ENCRYPTION_KEY_COMMAND".to_string());
+            }
+            let pool = db::init_db_with(&config.database_url, &db::DbOptions::from_config(&config)).await.map_err(|e| e.to_string())?;
+            let counts = db::seal_plaintext(&pool).await.map_err(|e| e.to_string())?;
+            let counts: Vec<String> = counts.iter().map(|(label, count)| format!("{count} {label}")).collect();
+            println!("Encrypted {}", counts.join(", "));
+        }
+        Command::Sessions { command: SessionsCommand::Prune { idle_minutes, older_than_days } } => {
+            if idle_minutes.is_none() && older_than_days.is_none() {
+                return Err("sessions prune needs --idle-minutes and/or --older-than-days".to_string());
+            }
+            let pool = db::init_db_with(&config.database_url, &db::DbOptions::from_config(&config)).await.map_err(|e| e.to_string())?;
+            if let Some(minutes) = idle_minutes {
+                let count = db::deactivate_stale_sessions(&pool, minutes).await.map_err(|e| e.to_string())?;
+                println!("Deactivated {count} idle sessions");
+            }
+            if let Some(days) = older_than_days {
+                let report = db::purge_expired(&pool, days).await.map_err(|e| e.to_string())?;
+                println!(
+                    "Deleted {} messages, {} transcript events, {} recordings and {} empty sessions",
+                    report.messages, report.transcripts, report.recordings, report.sessions
+                );
+            }
+        }
+        Command::Mcp => {
+            crypto::init(&config)?;
+            let pool = db::init_db_with(&config.database_url, &db::DbOptions::from_config(&config)).await.map_err(|e| e.to_string())?;
+            let probe = startup::probe(&config).await;
+            let state = AppState::new(config, pool, probe.cli_version, probe.profile_caps, None);
+            let served = mcp::serve_stdio(Arc::clone(&state)).await;
+            state.claude_manager.cleanup_all(Duration::ZERO).await;
+            served.map_err(|e| e.to_string())?;
+        }
+        Command::Check => {
+            if crypto::init(&config)? {
+                println!("encryption at rest enabled");
+            }
+            let probe = startup::probe(&config).await;
+            for profile in &config.claude_profiles {
+                println!("profile {:<12} {} ({})", profile.name, profile.binary_path, profile.backend);
+            }
+            if !probe.ready {
+                return Err("the Claude CLI check failed; see the log above".to_string());
+            }
+            println!("Configuration OK");
+        }
+    }
+    Ok(())
+}
+
+/// `prefix` followed by 64 random hex digits.
+fn generate_key(prefix: &str) -> String {
+    format!("{prefix}{}{}", uuid::Uuid::new_v4().as_simple(), uuid::Uuid::new_v4().as_simple())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_commands() {
+        let parse = |args: &[&str]| Cli::try_parse_from(std::iter::once("claude-code-api").chain(args.iter().copied()));
+        assert!(parse(&[]).unwrap().command.is_none());
+        assert!(matches!(
+            parse(&["sessions", "prune", "--older-than-days", "30"]).unwrap().command,
+            Some(Command::Sessions { command: SessionsCommand::Prune { idle_minutes: None, older_than_days: Some(30) } })
+        ));
+        assert!(matches!(parse(&["db", "stats"]).unwrap().command, Some(Command::Db { command: DbCommand::Stats })));
+        assert!(parse(&["db"]).is_err());
+        assert!(matches!(parse(&["mcp"]).unwrap().command, Some(Command::Mcp)));
+
+        let key = generate_key("sk-");
+        assert_eq!(key.len(), 3 + 64);
+        assert_ne!(key, generate_key("sk-"));
+    }
+}
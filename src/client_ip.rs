@@ -0,0 +1,254 @@
+use std::net::IpAddr;
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::{ConnectInfo, State};
+use axum::http::{HeaderMap, Request};
+use axum::middleware::Next;
+use axum::response::Response;
+
+use crate::server::PeerAddr;
+use crate::state::AppState;
+
+/// The resolved client IP, stored in request extensions.
+///
+/// Absent when the peer is a Unix socket and no forwarding header was sent.
+#[derive(Debug, Clone, Copy)]
+pub struct ClientIp(pub IpAddr);
+
+impl ClientIp {
+    /// The client's rate limit bucket: its address, or for IPv6 its /64,
+    /// which a single host can pick addresses from at will.
+    pub fn rate_limit_bucket(&self) -> String {
+        match self.0.to_canonical() {
+            IpAddr::V4(ip) => format!("ip:{ip}"),
+            IpAddr::V6(ip) => {
+                let s = ip.segments();
+                format!("ip:{:x}:{:x}:{:x}:{:x}::/64", s[0], s[1], s[2], s[3])
+            }
+        }
+    }
+}
+
+/// An IP network in CIDR notation (a bare address is a /32 or /128).
+#[derive(Debug, Clone, Copy)]
+struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+    fn parse(s: &str) -> Option<Self> {
+        let (addr, prefix) = match s.split_once('/') {
+            Some((a, p)) => (a.parse::<IpAddr>().ok()?, Some(p.parse::<u8>().ok()?)),
+            None => (s.parse::<IpAddr>().ok()?, None),
+        };
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(Self { addr, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip.to_canonical()) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Networks whose forwarding headers are believed (`TRUSTED_PROXIES`).
+#[derive(Debug, Default)]
+pub struct TrustedProxies {
+    nets: Vec<IpNet>,
+}
+
+impl TrustedProxies {
+    pub fn new(entries: &[String]) -> Self {
+        let nets = entries
+            .iter()
+            .filter_map(|e| {
+                let net = IpNet::parse(e);
+                if net.is_none() {
+                    tracing::warn!(entry = %e, "Ignoring invalid TRUSTED_PROXIES entry");
+                }
+                net
+            })
+            .collect();
+        Self { nets }
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.nets.iter().any(|n| n.contains(ip))
+    }
+
+    /// Resolve the client IP for a request from `peer`.
+    ///
+    /// Forwarding headers are only consulted when the peer is trusted. Unix
+    /// socket peers (`None`) are local processes and are always trusted. The
+    /// hop chain is walked right-to-left and the first untrusted address is
+    /// the client.
+    pub fn resolve(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> Option<IpAddr> {
+        if let Some(ip) = peer {
+            if !self.is_trusted(ip) {
+                return Some(ip.to_canonical());
+            }
+        }
+
+        let chain = forwarded_chain(headers);
+        chain
+            .iter()
+            .rev()
+            .find(|ip| !self.is_trusted(**ip))
+            .or_else(|| chain.first())
+            .copied()
+            .or(peer)
+            .map(|ip| ip.to_canonical())
+    }
+}
+
+/// Addresses from `Forwarded` (preferred) or `X-Forwarded-For`, client first.
+fn forwarded_chain(headers: &HeaderMap) -> Vec<IpAddr> {
+    let forwarded: Vec<IpAddr> = headers
+        .get_all("forwarded")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(|element| {
+            element.split(';').find_map(|pair| {
+                let (k, v) = pair.trim().split_once('=')?;
+                k.eq_ignore_ascii_case("for").then(|| parse_node(v))?
+            })
+        })
+        .collect();
+    if !forwarded.is_empty() {
+        return forwarded;
+    }
+
+    headers
+        .get_all("x-forwarded-for")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .filter_map(parse_node)
+        .collect()
+}
+
+/// Parse a node identifier: `1.2.3.4`, `1.2.3.4:80`, `"[::1]:80"`, `::1`.
+fn parse_node(s: &str) -> Option<IpAddr> {
+    let s = s.trim().trim_matches('"');
+    if let Ok(ip) = s.parse() {
+        return Some(ip);
+    }
+    if let Some(rest) = s.strip_prefix('[') {
+        return rest.split_once(']')?.0.parse().ok();
+    }
+    s.rsplit_once(':')?.0.parse().ok()
+}
+
+/// Resolve the client IP and store it as a [`ClientIp`] request extension.
+pub async fn client_ip_middleware(
+    State(state): State<Arc<AppState>>,
+    mut req: Request<Body>,
+    next: Next,
+) -> Response {
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<PeerAddr>>()
+        .and_then(|ConnectInfo(PeerAddr(addr))| addr.map(|a| a.ip()));
+
+    if let Some(ip) = state.trusted_proxies.resolve(peer, req.headers()) {
+        req.extensions_mut().insert(ClientIp(ip));
+    }
+
+    next.run(req).await
+}
+
+/// Request span for `TraceLayer`, including the resolved client IP.
+///
+/// Uses the same target as tower-http's default span so existing log filters
+/// (`tower_http=debug`) keep applying.
+pub fn make_request_span(req: &Request<Body>) -> tracing::Span {
+    let client_ip = req.extensions().get::<ClientIp>().map(|c| c.0.to_string());
+    tracing::debug_span!(
+        target: "tower_http::trace::make_span",
+        "request",
+        method = %req.method(),
+        uri = %req.uri(),
+        version = ?req.version(),
+        client_ip,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn proxies(entries: &[&str]) -> TrustedProxies {
+        TrustedProxies::new(&entries.iter().map(|s| s.to_string()).collect::<Vec<_>>())
+    }
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut map = HeaderMap::new();
+        for (k, v) in pairs {
+            map.append(*k, v.parse().unwrap());
+        }
+        map
+    }
+
+    #[test]
+    fn test_untrusted_peer_ignores_headers() {
+        let p = proxies(&["10.0.0.0/8"]);
+        let h = headers(&[("x-forwarded-for", "1.2.3.4")]);
+        let peer = "203.0.113.9".parse().ok();
+        assert_eq!(p.resolve(peer, &h), peer);
+    }
+
+    #[test]
+    fn test_x_forwarded_for_skips_trusted_hops() {
+        let p = proxies(&["10.0.0.0/8"]);
+        let h = headers(&[("x-forwarded-for", "6.6.6.6, 1.2.3.4, 10.1.1.1")]);
+        let resolved = p.resolve("10.0.0.2".parse().ok(), &h);
+        assert_eq!(resolved, "1.2.3.4".parse().ok());
+    }
+
+    #[test]
+    fn test_forwarded_header() {
+        let p = proxies(&["127.0.0.1"]);
+        let h = headers(&[("forwarded", r#"for="[2001:db8::1]:4711";proto=https"#)]);
+        let resolved = p.resolve("127.0.0.1".parse().ok(), &h);
+        assert_eq!(resolved, "2001:db8::1".parse().ok());
+    }
+
+    #[test]
+    fn test_unix_peer_is_trusted() {
+        let p = proxies(&[]);
+        let h = headers(&[("x-forwarded-for", "1.2.3.4:5678")]);
+        assert_eq!(p.resolve(None, &h), "1.2.3.4".parse().ok());
+    }
+
+    #[test]
+    fn test_cidr_contains() {
+        let net = IpNet::parse("192.168.0.0/16").unwrap();
+        assert!(net.contains("192.168.44.1".parse().unwrap()));
+        assert!(!net.contains("192.169.0.1".parse().unwrap()));
+        assert!(IpNet::parse("::ffff:0:0/0").unwrap().contains("::1".parse().unwrap()));
+        assert!(IpNet::parse("10.0.0.0/33").is_none());
+    }
+
+    #[test]
+    fn test_rate_limit_bucket() {
+        let bucket = |ip: &str| ClientIp(ip.parse().unwrap()).rate_limit_bucket();
+        assert_eq!(bucket("203.0.113.9"), "ip:203.0.113.9");
+        assert_eq!(bucket("::ffff:203.0.113.9"), "ip:203.0.113.9");
+        assert_eq!(bucket("2001:db8:0:7::1"), "ip:2001:db8:0:7::/64");
+        assert_eq!(bucket("2001:db8:0:7:abcd::9"), bucket("2001:db8:0:7::1"));
+        assert_ne!(bucket("2001:db8:0:8::1"), bucket("2001:db8:0:7::1"));
+    }
+}
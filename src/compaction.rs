@@ -0,0 +1,140 @@
+//! Summarizing the oldest turns of long conversations so they fit the
+//! history token budget (`HISTORY_TOKEN_BUDGET`).
+
+use serde::Serialize;
+use serde_json::json;
+
+use crate::error::AppError;
+use crate::models::openai::ChatMessage;
+use crate::oneshot;
+use crate::priority::Priority;
+use crate::prompt::render_transcript;
+use crate::state::AppState;
+use crate::tokens;
+
+const SUMMARY_PROMPT: &str = "Summarize the following conversation so it can replace the \
+    original in a later prompt. Keep every fact, decision, open question, file name, \
+    identifier and tool result the conversation may still depend on. Reply with the \
+    summary only.";
+
+/// What a compaction replaced, recorded in message metadata.
+#[derive(Debug, Clone, Serialize)]
+pub struct Compaction {
+    /// Messages folded into the summary.
+    pub messages: usize,
+    pub tokens_before: u32,
+    pub tokens_after: u32,
+    pub model: String,
+    pub cost_usd: f64,
+}
+
+/// The range of `messages` to summarize, keeping the first system message
+/// (it is the system prompt) and the last `keep_recent` messages verbatim.
+/// The kept tail never starts with a tool result, so tool calls stay paired
+/// with their results. `None` if fewer than two messages would be folded.
+pub fn summary_range(messages: &[ChatMessage], keep_recent: usize) -> Option<std::ops::Range<usize>> {
+    let start = usize::from(messages.first().is_some_and(|m| m.role == "system"));
+    let mut end = messages.len().saturating_sub(keep_recent.max(1)).max(start);
+    while end > start && messages[end].role == "tool" {
+        end -= 1;
+    }
+    (end - start >= 2).then_some(start..end)
+}
+
+/// Replace the oldest turns of `messages` with a summary message (role
+/// `summary`) made by `SUMMARY_MODEL`. `None` when there is nothing to fold
+/// or the summary run failed; the caller then sends the history as is.
+pub async fn compact_history(
+    state: &AppState,
+    messages: &[ChatMessage],
+    api_key: Option<&str>,
+    project_id: &str,
+) -> Option<(Vec<ChatMessage>, Compaction)> {
+    let range = summary_range(messages, state.config().history_keep_recent)?;
+    let (summary, compaction) = summarize_range(state, messages, range.clone(), api_key, project_id)
+        .await
+        .inspect_err(|e| tracing::warn!(error = %e, "History summarization failed; sending full history"))
+        .ok()?;
+
+    Some((splice_summary(messages, range, summary), compaction))
+}
+
+/// `messages` with `messages[range]` replaced by one `summary` message.
+pub fn splice_summary(
+    messages: &[ChatMessage],
+    range: std::ops::Range<usize>,
+    summary: String,
+) -> Vec<ChatMessage> {
+    let mut compacted = messages[..range.start].to_vec();
+    compacted.push(ChatMessage {
+        role: "summary".to_string(),
+        content: Some(json!(summary)),
+        name: None,
+        tool_calls: None,
+        tool_call_id: None,
+    });
+    compacted.extend_from_slice(&messages[range.end..]);
+    compacted
+}
+
+/// Summarize `messages[range]`, returning the summary text and what it
+/// replaced.
+pub async fn summarize_range(
+    state: &AppState,
+    messages: &[ChatMessage],
+    range: std::ops::Range<usize>,
+    api_key: Option<&str>,
+    project_id: &str,
+) -> Result<(String, Compaction), AppError> {
+    let old = &messages[range];
+    let transcript = render_transcript(old).join("\n\n");
+    let tokens_before = tokens::estimate_tokens(&transcript);
+
+    let prompt = format!("{SUMMARY_PROMPT}\n\n{transcript}");
+    // Summaries are background work; interactive requests go first
+    let run = oneshot::run(state, &state.config().summary_model, &prompt, api_key, project_id, Priority::Low).await?;
+    let summary = run.text;
+    let compaction = Compaction {
+        messages: old.len(),
+        tokens_before,
+        tokens_after: tokens::estimate_tokens(&summary),
+        model: run.model,
+        cost_usd: run.usage.cost_usd,
+    };
+    tracing::info!(
+        messages = compaction.messages,
+        tokens_before,
+        tokens_after = compaction.tokens_after,
+        "Compacted conversation history"
+    );
+    Ok((summary, compaction))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn msg(role: &str) -> ChatMessage {
+        ChatMessage {
+            role: role.to_string(),
+            content: Some(json!("x")),
+            name: None,
+            tool_calls: None,
+            tool_call_id: None,
+        }
+    }
+
+    #[test]
+    fn test_summary_range() {
+        let roles = |rs: &[&str]| rs.iter().map(|r| msg(r)).collect::<Vec<_>>();
+
+        let history = roles(&["system", "user", "assistant", "user", "assistant", "user"]);
+        assert_eq!(summary_range(&history, 2), Some(1..4));
+        assert_eq!(summary_range(&history, 4), None);
+
+        let history = roles(&["user", "assistant", "user", "assistant", "tool", "user"]);
+        assert_eq!(summary_range(&history, 2), Some(0..3));
+
+        assert_eq!(summary_range(&roles(&["system"]), 1), None);
+    }
+}
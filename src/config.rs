@@ -1,38 +1,465 @@
+use std::collections::{BTreeMap, BTreeSet};
 use std::env;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::sync::{OnceLock, RwLock};
+
+use crate::models::catalog::ModelCatalog;
+use crate::priority::Priority;
+
+mod file;
+
+/// A named agent CLI: backend, binary, account config dir and extra env.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClaudeProfile {
+    pub name: String,
+    /// `claude`, `codex` or `gemini`, see [`crate::claude::backend`].
+    pub backend: String,
+    pub binary_path: String,
+    /// Passed to the CLI as `CLAUDE_CONFIG_DIR` or its backend's equivalent
+    /// (selects the logged-in account).
+    pub config_dir: Option<PathBuf>,
+    pub env: Vec<(String, String)>,
+    /// Requests whose model starts with one of these are routed here.
+    pub model_prefixes: Vec<String>,
+}
+
+/// An OpenAI-compatible embeddings API that `/v1/embeddings` requests for
+/// some models are forwarded to (OpenAI, Voyage, Ollama's `/v1`, ...).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddingUpstream {
+    pub name: String,
+    /// Base URL; requests go to `<url>/embeddings`.
+    pub url: String,
+    /// Sent as `Authorization: Bearer <key>`.
+    pub api_key: Option<String>,
+    /// Requests whose model starts with one of these are forwarded here.
+    pub model_prefixes: Vec<String>,
+}
+
+/// A named bundle of entitlements assigned to API keys, see
+/// [`crate::tiers`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Tier {
+    pub name: String,
+    /// Requests per minute for each key of the tier, instead of
+    /// `RATE_LIMIT_REQUESTS_PER_MINUTE`.
+    pub requests_per_minute: Option<u32>,
+    /// Prefixes of the Claude model IDs (after alias resolution) the tier
+    /// may use; empty allows all.
+    pub models: Vec<String>,
+    /// Highest `max_tokens` a request may ask for.
+    pub max_tokens: Option<u32>,
+    /// Features the tier may use: `tools`, `vision` and `batch`.
+    pub features: Vec<String>,
+    /// Highest `X-Priority` the tier may ask for.
+    pub max_priority: Priority,
+}
 
 #[derive(Debug, Clone)]
 pub struct Config {
     pub host: String,
     pub port: u16,
+    /// Public listeners (`host:port` or `unix:/path`), served with auth.
+    pub listen: Vec<String>,
+    /// Admin listeners served without authentication: loopback addresses
+    /// or Unix sockets only, unless `ADMIN_LISTEN_ALLOW_REMOTE` is set.
+    pub admin_listen: Vec<String>,
+    pub admin_listen_allow_remote: bool,
+    /// Permission bits applied to Unix socket files.
+    pub socket_mode: u32,
     pub claude_binary_path: String,
+    /// `cli` spawns the Claude CLI; `mock` serves synthetic responses.
+    pub claude_backend: String,
+    /// Mock reply text; `{prompt}` and `{model}` are substituted.
+    pub mock_response: String,
+    /// Delay before the mock's first assistant message.
+    pub mock_latency_ms: u64,
+    /// Tool call the mock makes when the request has tools (`name` or `name:{json}`).
+    pub mock_tool_call: Option<String>,
+    /// Make every mock run fail with this error message.
+    pub mock_error: Option<String>,
+    /// Named CLI backends; the first is always `default` (`CLAUDE_BINARY_PATH`).
+    pub claude_profiles: Vec<ClaudeProfile>,
+    /// Exact model ID to profile name (`MODEL_ROUTES=gpt-5=codex,...`); these
+    /// models are also listed under `/v1/models`.
+    pub model_routes: Vec<(String, String)>,
+    /// `CLAUDE_CONFIG_DIR` per API key (raw or hex SHA-256), overriding the profile's.
+    pub key_config_dirs: Vec<(String, PathBuf)>,
+    /// `CLAUDE_CONFIG_DIR` per project, used when the key has no mapping.
+    pub project_config_dirs: Vec<(String, PathBuf)>,
+    /// Projects each API key (raw or hex SHA-256) may use
+    /// (`KEY_PROJECTS=sk-a=alpha|beta`); keys not listed may use any.
+    pub key_projects: Vec<(String, Vec<String>)>,
+    /// Project selected by an `OpenAI-Organization` header, per organization.
+    pub organization_projects: Vec<(String, String)>,
+    /// Tiers named in `TIERS`, each configured by
+    /// `TIER_<NAME>_{RATE_LIMIT,MODELS,MAX_TOKENS,FEATURES,MAX_PRIORITY}`.
+    pub tiers: Vec<Tier>,
+    /// Tier of each API key (raw or hex SHA-256) (`KEY_TIERS=sk-a=power`).
+    pub key_tiers: Vec<(String, String)>,
+    /// Tier of keys not in `KEY_TIERS`; without one they are unrestricted.
+    pub default_tier: Option<String>,
+    /// Extra environment for every spawned CLI (`CLAUDE_ENV=K=V;K2=V2`).
+    pub claude_env: Vec<(String, String)>,
+    /// Pass the server's whole environment to the CLI instead of scrubbing it.
+    pub claude_env_inherit: bool,
+    /// Server variables kept when scrubbing (`*` suffix matches a prefix).
+    pub claude_env_passthrough: Vec<String>,
+    /// Extra environment per project, keyed by normalized project ID
+    /// (`CLAUDE_PROJECT_ENV_<ID>`).
+    pub project_env: Vec<(String, Vec<(String, String)>)>,
+    /// Sandbox preset for spawned CLIs: `none`, `bwrap`, `firejail` or `docker`.
+    pub sandbox: String,
+    /// Custom sandbox command template, overriding the preset.
+    pub sandbox_command: Option<String>,
+    /// Allow network access inside the sandbox (the CLI needs the API).
+    pub sandbox_network: bool,
+    /// Image for the `docker` preset.
+    pub sandbox_image: String,
+    /// Memory cap per spawned CLI, see [`crate::claude::limits`]; 0 means none.
+    pub process_memory_limit_mb: u64,
+    /// CPU time cap per spawned CLI; 0 means none.
+    pub process_cpu_limit_seconds: u64,
+    /// CPU share per spawned CLI (100 = one core, cgroup only); 0 means none.
+    pub process_cpu_percent: u32,
+    /// Delegated cgroup v2 directory to create per-process cgroups in.
+    pub process_cgroup_root: Option<PathBuf>,
     pub database_url: String,
+    /// SQLite pool size and connection settings, see [`crate::db::DbOptions`].
+    pub db_max_connections: u32,
+    /// How long a connection waits for another's write lock.
+    pub db_busy_timeout_ms: u64,
+    /// `PRAGMA synchronous`: `off`, `normal`, `full` or `extra`.
+    pub db_synchronous: String,
+    /// Page cache per connection; 0 keeps SQLite's default.
+    pub db_cache_size_kb: u32,
+    /// Key for encrypting conversation content at rest, see
+    /// [`crate::crypto`]; the first of these that is set is used.
+    pub encryption_key: Option<String>,
+    pub encryption_key_file: Option<PathBuf>,
+    pub encryption_key_command: Option<String>,
     pub api_keys: Vec<String>,
+    /// Keys allowed to call `/admin/*` on public listeners.
+    pub admin_api_keys: Vec<String>,
     pub require_auth: bool,
     pub default_model: String,
     pub max_concurrent_sessions: usize,
+    /// How long a request waits for a free session slot before failing.
+    pub queue_timeout_seconds: u64,
+    /// Shared session registry (`postgres://` or `sqlite:`) for running
+    /// several replicas, see [`crate::registry`]; `None` runs standalone.
+    pub session_registry_url: Option<String>,
+    /// This replica's name in the registry (defaults to a random one).
+    pub instance_id: String,
+    /// Base URL other replicas reach this one at, for forwarded requests.
+    pub instance_url: Option<String>,
+    /// Running sessions allowed across all replicas; 0 means no global cap.
+    pub global_max_concurrent_sessions: usize,
+    /// Idle pre-spawned processes kept per warm model (0 = no pool).
+    pub warm_pool_size: usize,
+    /// Models to keep warm processes for (defaults to `DEFAULT_MODEL`).
+    pub warm_pool_models: Vec<String>,
     pub session_timeout_minutes: u64,
     pub project_root: PathBuf,
+    /// Where `/v1/tasks` check out the git worktrees they run in.
+    pub task_worktree_root: PathBuf,
+    /// Token tasks open pull requests with unless the request has its own.
+    pub github_token: Option<String>,
+    /// Repositories `github_token` may be used for (`owner/name` or
+    /// `owner/*`); requests for others must bring their own token.
+    pub github_token_repos: Vec<String>,
+    /// GitHub (or GitHub Enterprise) web and API base URLs.
+    pub github_url: String,
+    pub github_api_url: String,
     pub allowed_origins: Vec<String>,
+    /// Proxy addresses/CIDRs whose `Forwarded`/`X-Forwarded-For` are trusted.
+    pub trusted_proxies: Vec<String>,
     pub rate_limit_requests_per_minute: u32,
     pub rate_limit_burst: u32,
+    /// Keys (and key/user pairs) the rate limiters track at most; the one
+    /// idle longest is dropped to make room.
+    pub rate_limit_max_keys: usize,
+    /// Requests per minute for each end user (the `user` request field)
+    /// under a key; 0 leaves users to share the key's limit.
+    pub user_rate_limit_requests_per_minute: u32,
+    /// Spend per end user under a key per UTC day, from the audit log;
+    /// 0 means no budget.
+    pub user_daily_budget_usd: f64,
+    /// Tokens per UTC month for keys without their own allowance
+    /// (`/admin/quotas`), see [`crate::quota`]; 0 means unlimited.
+    pub key_monthly_tokens: i64,
+    /// Server-wide spend per UTC day and month, see [`crate::budget`];
+    /// 0 means no ceiling.
+    pub daily_budget_usd: f64,
+    pub monthly_budget_usd: f64,
+    /// Percentages of those ceilings whose crossing sends a
+    /// `budget.threshold_crossed` webhook.
+    pub budget_alert_percents: Vec<f64>,
+    /// Endpoints receiving lifecycle events, see [`crate::webhooks`].
+    pub webhook_urls: Vec<String>,
+    /// Key for the `X-Webhook-Signature` HMAC; unsigned without.
+    pub webhook_secret: Option<String>,
+    /// Event types to send; empty sends all.
+    pub webhook_events: Vec<String>,
+    pub webhook_max_attempts: u32,
+    pub webhook_timeout_seconds: u64,
     pub streaming_timeout_seconds: u64,
+    /// Send `claude.tool_use` / `claude.tool_result` SSE events while
+    /// streaming, unless the request says otherwise.
+    pub stream_progress: bool,
+    /// How long a finished stream stays buffered for clients reconnecting
+    /// with `Last-Event-ID`; 0 disables resuming.
+    pub stream_resume_seconds: u64,
+    /// How streamed content is cut into deltas, see
+    /// [`crate::streaming::ChunkPolicy`]: at most this many bytes (0: no
+    /// limit), held back for up to `stream_flush_ms`, at sentence ends.
+    pub stream_chunk_bytes: usize,
+    pub stream_flush_ms: u64,
+    pub stream_split_sentences: bool,
     pub cleanup_interval_minutes: u64,
+    /// How long to wait for in-flight sessions to finish on shutdown.
+    pub shutdown_grace_seconds: u64,
+    /// Serve repeated identical non-streaming requests from a cache.
+    pub response_cache: bool,
+    /// Entries kept in memory (SQLite keeps everything within the TTL).
+    pub response_cache_size: usize,
+    pub response_cache_ttl_seconds: u64,
+    /// Attach identical concurrent stateless requests to one CLI run.
+    pub dedup_inflight: bool,
+    /// Keep every raw JSONL event from the CLI in the `transcripts` table.
+    pub store_transcripts: bool,
+    /// Hard-delete messages and transcripts older than this; 0 keeps them.
+    pub message_retention_days: u64,
+    /// Minutes between SQLite checkpoint/ANALYZE runs; 0 disables them.
+    pub db_maintenance_interval_minutes: u64,
+    /// Also `VACUUM` during maintenance (rewrites the whole file).
+    pub db_vacuum: bool,
+    /// Summarize the oldest turns once the rendered conversation exceeds
+    /// this many tokens; 0 disables compaction.
+    pub history_token_budget: u32,
+    /// Most recent messages always sent verbatim when compacting.
+    pub history_keep_recent: usize,
+    /// Jinja template replacing the built-in conversation rendering, see
+    /// [`crate::conversation`].
+    pub conversation_template_file: Option<PathBuf>,
+    /// Conversation templates of single projects, overriding
+    /// `CONVERSATION_TEMPLATE_FILE`.
+    pub project_conversation_templates: Vec<(String, PathBuf)>,
+    /// Model that writes history summaries.
+    pub summary_model: String,
+    /// Most messages accepted in one chat request; 0 means no limit.
+    pub max_messages: usize,
+    /// Largest accepted message content, in bytes; 0 means no limit.
+    pub max_message_bytes: usize,
+    /// Largest prompt sent to the CLI (messages, system prompt, tools,
+    /// retrieved context), in bytes; 0 means no limit.
+    pub max_prompt_bytes: usize,
+    /// Upstream embedding providers; models they don't claim use the local
+    /// hashing embedder.
+    pub embedding_upstreams: Vec<EmbeddingUpstream>,
+    pub embedding_timeout_seconds: u64,
+    /// Workspace chunks prepended to prompts of RAG-enabled projects.
+    pub rag_top_k: usize,
+    /// Lines per indexed chunk.
+    pub rag_chunk_lines: usize,
+    /// Workspace files larger than this are not indexed.
+    pub rag_max_file_bytes: u64,
+    /// Messages embedded per batch for semantic search; 0 turns the
+    /// background indexing off, see [`crate::message_index`].
+    pub message_index_batch: usize,
+    /// Pause between indexing rounds once caught up.
+    pub message_index_interval_ms: u64,
+    /// Largest accepted `/v1/files` upload.
+    pub max_upload_mb: u64,
+    /// Scrub credentials from assistant output, see [`crate::redact`].
+    pub redact_output: bool,
+    /// Also redact long high-entropy tokens that match no pattern.
+    pub redact_entropy: bool,
+    /// Extra redaction regexes by name (`REDACT_PATTERN_<NAME>`).
+    pub redact_patterns: Vec<(String, String)>,
+    /// Pre-flight policy checks: `off`, `annotate` or `reject`.
+    pub guardrail_mode: String,
+    /// Extra deny patterns by policy name (`GUARDRAIL_DENY_<NAME>`).
+    pub guardrail_deny: Vec<(String, String)>,
+    /// Also ask `GUARDRAIL_MODEL` to classify requests no pattern matched.
+    pub guardrail_classifier: bool,
+    pub guardrail_model: String,
+    /// Hook commands projects may set up (`/v1/projects/{id}/hooks`), see
+    /// [`crate::hooks`]. Empty disables project hooks.
+    pub hook_command_allowlist: Vec<String>,
+    /// Record every API call in the `request_log` table.
+    pub audit_log: bool,
+    /// Record completions with their rendered CLI invocation for replay
+    /// (`/admin/replay/{id}`), see [`crate::recording`].
+    pub record_requests: bool,
+    /// Advertised models and aliases: the built-ins merged with
+    /// `MODEL_CATALOG_FILE` (JSON or TOML) and `MODEL_ALIASES`.
+    pub model_catalog: ModelCatalog,
+    /// Reject unknown models with `model_not_found` instead of falling back
+    /// to the default model.
+    pub strict_model_validation: bool,
+    /// JSON file of per-model prices overriding the built-in table.
+    pub pricing_file: Option<PathBuf>,
+    /// Per-model prices in USD/Mtok (`MODEL_PRICING=claude-sonnet-4=3:15,...`),
+    /// applied after `PRICING_FILE`.
+    pub model_pricing: Vec<(String, String)>,
+    /// Run a ping completion at startup before reporting readiness.
+    pub startup_self_test: bool,
+    pub self_test_timeout_seconds: u64,
+    pub self_test_model: String,
+    /// Log output format: `json`, `pretty` or `compact`.
+    pub log_format: String,
+    /// Base log level, e.g. `info`.
+    pub log_level: String,
+    /// Per-module filter overrides, e.g. `tower_http=debug`.
+    pub log_filters: Vec<String>,
+    /// Also write logs to this file.
+    pub log_file: Option<PathBuf>,
+    /// File rotation: `minutely`, `hourly`, `daily`, `never` or `size`.
+    pub log_rotation: String,
+    pub log_max_size_mb: u64,
+    /// Rotated files to keep (0 = unlimited for time-based rotation).
+    pub log_max_files: usize,
 }
 
 impl Config {
+    /// Read `CONFIG_FILE`, if set, then build the configuration from the
+    /// environment layered over it. An unreadable or malformed file is an
+    /// error and leaves the previously loaded one in place.
+    pub fn load() -> Result<Self, String> {
+        let env_file = ENV_FILE.read().unwrap_or_else(|e| e.into_inner()).clone();
+        Self::load_with_env_file(env_file)
+    }
+
+    /// [`Config::load`] with `env_file`, the variables of a parsed `.env`,
+    /// in place of the previous ones. As at startup, variables set in the
+    /// process environment win over `.env`; ones [`load_dotenv`] copied
+    /// from `.env` don't, so edits to them apply. The process environment
+    /// is left untouched: other threads may be reading it.
+    pub fn load_with_env_file(env_file: BTreeMap<String, String>) -> Result<Self, String> {
+        let path = env_file.get("CONFIG_FILE").cloned().or_else(|| env::var("CONFIG_FILE").ok());
+        let layer = match path.filter(|s| !s.is_empty()) {
+            Some(path) => file::read(Path::new(&path)).map_err(|e| format!("{path}: {e}"))?,
+            None => file::Layer::default(),
+        };
+        *ENV_FILE.write().unwrap_or_else(|e| e.into_inner()) = env_file;
+        file::install(layer);
+        Ok(Self::from_env())
+    }
+
+    /// The defaults alone, ignoring the environment, `.env` and
+    /// `CONFIG_FILE`, and without looking for the CLI binary.
+    #[cfg(test)]
+    pub fn for_test() -> Self {
+        DEFAULTS_ONLY.set(true);
+        let config = Self::from_env();
+        DEFAULTS_ONLY.set(false);
+        config
+    }
+
+    /// Build the configuration from the environment, falling back to the
+    /// last loaded `CONFIG_FILE`.
     pub fn from_env() -> Self {
+        let host = env_or("HOST", "0.0.0.0");
+        let port = env_or("PORT", "8000").parse().unwrap_or(8000);
+
+        // LISTEN takes precedence; otherwise fall back to LISTEN_SOCKET or HOST/PORT.
+        let mut listen = env_csv("LISTEN");
+        if listen.is_empty() {
+            let socket = var("LISTEN_SOCKET")
+                .filter(|s| !s.is_empty())
+                .or_else(|| host.strip_prefix("unix:").map(str::to_string));
+            listen.push(match socket {
+                Some(path) => format!("unix:{path}"),
+                None if host.contains(':') => format!("[{host}]:{port}"),
+                None => format!("{host}:{port}"),
+            });
+        }
+
+        let claude_binary_path = var("CLAUDE_BINARY_PATH")
+            .filter(|s| !s.is_empty())
+            .or_else(|| {
+                if defaults_only() {
+                    return None;
+                }
+                crate::claude::discovery::find_claude_binary()
+                    .map(|p| p.to_string_lossy().to_string())
+            })
+            .unwrap_or_else(|| "claude".to_string());
+        let claude_profiles = profiles_from_env(&claude_binary_path);
+        let default_model = env_or("DEFAULT_MODEL", "claude-3-5-sonnet-20241022");
+
         Self {
-            host: env_or("HOST", "0.0.0.0"),
-            port: env_or("PORT", "8000").parse().unwrap_or(8000),
-            claude_binary_path: env_or("CLAUDE_BINARY_PATH", "claude"),
+            host,
+            port,
+            listen,
+            admin_listen: env_csv("ADMIN_LISTEN"),
+            admin_listen_allow_remote: env_bool("ADMIN_LISTEN_ALLOW_REMOTE", false),
+            socket_mode: u32::from_str_radix(&env_or("SOCKET_MODE", "660"), 8).unwrap_or(0o660),
+            claude_binary_path,
+            claude_backend: env_or("CLAUDE_BACKEND", "cli").to_ascii_lowercase(),
+            mock_response: env_or("MOCK_RESPONSE", "This is a mock response to: {prompt}"),
+            mock_latency_ms: env_or("MOCK_LATENCY_MS", "0").parse().unwrap_or(0),
+            mock_tool_call: var("MOCK_TOOL_CALL").filter(|s| !s.is_empty()),
+            mock_error: var("MOCK_ERROR").filter(|s| !s.is_empty()),
+            claude_profiles,
+            model_routes: env_map("MODEL_ROUTES"),
+            key_config_dirs: env_path_map("KEY_CONFIG_DIRS"),
+            project_config_dirs: env_path_map("PROJECT_CONFIG_DIRS"),
+            key_projects: env_map("KEY_PROJECTS")
+                .into_iter()
+                .map(|(key, projects)| (key, projects.split('|').map(|p| p.trim().to_string()).collect()))
+                .collect(),
+            organization_projects: env_map("ORGANIZATION_PROJECTS"),
+            tiers: tiers_from_env(),
+            key_tiers: env_map("KEY_TIERS"),
+            default_tier: var("DEFAULT_TIER").filter(|s| !s.is_empty()),
+            claude_env: parse_env_pairs(&env_or("CLAUDE_ENV", "")),
+            claude_env_inherit: env_bool("CLAUDE_ENV_INHERIT", false),
+            claude_env_passthrough: env_csv_or(
+                "CLAUDE_ENV_PASSTHROUGH",
+                crate::claude::env::DEFAULT_PASSTHROUGH.iter().map(|s| s.to_string()).collect(),
+            ),
+            project_env: vars()
+                .filter_map(|(k, v)| {
+                    let id = k.strip_prefix("CLAUDE_PROJECT_ENV_")?;
+                    Some((id.to_string(), parse_env_pairs(&v)))
+                })
+                .collect(),
+            sandbox: env_or("SANDBOX", "none").to_lowercase(),
+            sandbox_command: var("SANDBOX_COMMAND").filter(|s| !s.trim().is_empty()),
+            sandbox_network: env_bool("SANDBOX_NETWORK", true),
+            sandbox_image: env_or("SANDBOX_IMAGE", "claude-code-sandbox"),
+            process_memory_limit_mb: env_or("PROCESS_MEMORY_LIMIT_MB", "0").parse().unwrap_or(0),
+            process_cpu_limit_seconds: env_or("PROCESS_CPU_LIMIT_SECONDS", "0").parse().unwrap_or(0),
+            process_cpu_percent: env_or("PROCESS_CPU_PERCENT", "0").parse().unwrap_or(0),
+            process_cgroup_root: var("PROCESS_CGROUP_ROOT").filter(|s| !s.is_empty()).map(PathBuf::from),
             database_url: env_or("DATABASE_URL", "sqlite:./claude_api.db"),
+            db_max_connections: env_or("DB_MAX_CONNECTIONS", "10").parse().unwrap_or(10),
+            db_busy_timeout_ms: env_or("DB_BUSY_TIMEOUT_MS", "5000").parse().unwrap_or(5000),
+            db_synchronous: env_or("DB_SYNCHRONOUS", "normal").to_lowercase(),
+            db_cache_size_kb: env_or("DB_CACHE_SIZE_KB", "0").parse().unwrap_or(0),
+            encryption_key: var("ENCRYPTION_KEY").filter(|v| !v.is_empty()),
+            encryption_key_file: var("ENCRYPTION_KEY_FILE").filter(|v| !v.is_empty()).map(PathBuf::from),
+            encryption_key_command: var("ENCRYPTION_KEY_COMMAND").filter(|v| !v.is_empty()),
             api_keys: env_csv("API_KEYS"),
+            admin_api_keys: env_csv("ADMIN_API_KEYS"),
             require_auth: env_bool("REQUIRE_AUTH", false),
-            default_model: env_or("DEFAULT_MODEL", "claude-3-5-sonnet-20241022"),
+            warm_pool_models: env_csv_or("WARM_POOL_MODELS", vec![default_model.clone()]),
+            default_model,
             max_concurrent_sessions: env_or("MAX_CONCURRENT_SESSIONS", "10")
                 .parse()
                 .unwrap_or(10),
+            queue_timeout_seconds: env_or("QUEUE_TIMEOUT_SECONDS", "0").parse().unwrap_or(0),
+            session_registry_url: var("SESSION_REGISTRY_URL").filter(|s| !s.is_empty()),
+            instance_id: var("INSTANCE_ID")
+                .filter(|s| !s.is_empty())
+                .unwrap_or_else(|| uuid::Uuid::new_v4().as_simple().to_string()[..12].to_string()),
+            instance_url: var("INSTANCE_URL")
+                .filter(|s| !s.is_empty())
+                .map(|s| s.trim_end_matches('/').to_string()),
+            global_max_concurrent_sessions: env_or("GLOBAL_MAX_CONCURRENT_SESSIONS", "0").parse().unwrap_or(0),
+            warm_pool_size: env_or("WARM_POOL_SIZE", "0").parse().unwrap_or(0),
             session_timeout_minutes: env_or("SESSION_TIMEOUT_MINUTES", "30")
                 .parse()
                 .unwrap_or(30),
@@ -40,40 +467,527 @@ impl Config {
                 "PROJECT_ROOT",
                 &std::env::temp_dir().join("claude_projects").to_string_lossy(),
             )),
+            task_worktree_root: PathBuf::from(env_or(
+                "TASK_WORKTREE_ROOT",
+                &std::env::temp_dir().join("claude_tasks").to_string_lossy(),
+            )),
+            github_token: var("GITHUB_TOKEN").filter(|s| !s.is_empty()),
+            github_token_repos: env_csv("GITHUB_TOKEN_REPOS"),
+            github_url: env_or("GITHUB_URL", "https://github.com").trim_end_matches('/').to_string(),
+            github_api_url: env_or("GITHUB_API_URL", "https://api.github.com").trim_end_matches('/').to_string(),
             allowed_origins: env_csv_or("ALLOWED_ORIGINS", vec!["*".to_string()]),
+            trusted_proxies: env_csv("TRUSTED_PROXIES"),
             rate_limit_requests_per_minute: env_or("RATE_LIMIT_REQUESTS_PER_MINUTE", "100")
                 .parse()
                 .unwrap_or(100),
             rate_limit_burst: env_or("RATE_LIMIT_BURST", "10")
                 .parse()
                 .unwrap_or(10),
+            rate_limit_max_keys: env_or("RATE_LIMIT_MAX_KEYS", "100000").parse().unwrap_or(100_000),
+            user_rate_limit_requests_per_minute: env_or("USER_RATE_LIMIT_REQUESTS_PER_MINUTE", "0")
+                .parse()
+                .unwrap_or(0),
+            user_daily_budget_usd: env_or("USER_DAILY_BUDGET_USD", "0").parse().unwrap_or(0.0),
+            key_monthly_tokens: env_or("KEY_MONTHLY_TOKENS", "0").parse().unwrap_or(0),
+            daily_budget_usd: env_or("DAILY_BUDGET_USD", "0").parse().unwrap_or(0.0),
+            monthly_budget_usd: env_or("MONTHLY_BUDGET_USD", "0").parse().unwrap_or(0.0),
+            budget_alert_percents: env_csv_or("BUDGET_ALERT_PERCENTS", vec!["80".into(), "100".into()])
+                .iter()
+                .filter_map(|p| p.parse().ok())
+                .filter(|p: &f64| *p > 0.0)
+                .collect(),
+            webhook_urls: env_csv("WEBHOOK_URLS"),
+            webhook_secret: var("WEBHOOK_SECRET").filter(|s| !s.is_empty()),
+            webhook_events: env_csv("WEBHOOK_EVENTS"),
+            webhook_max_attempts: env_or("WEBHOOK_MAX_ATTEMPTS", "5").parse().unwrap_or(5),
+            webhook_timeout_seconds: env_or("WEBHOOK_TIMEOUT_SECONDS", "10").parse().unwrap_or(10),
             streaming_timeout_seconds: env_or("STREAMING_TIMEOUT_SECONDS", "300")
                 .parse()
                 .unwrap_or(300),
+            stream_progress: env_bool("STREAM_PROGRESS", false),
+            stream_resume_seconds: env_or("STREAM_RESUME_SECONDS", "60").parse().unwrap_or(60),
+            stream_chunk_bytes: env_or("STREAM_CHUNK_BYTES", "0").parse().unwrap_or(0),
+            stream_flush_ms: env_or("STREAM_FLUSH_MS", "0").parse().unwrap_or(0),
+            stream_split_sentences: env_bool("STREAM_SPLIT_SENTENCES", false),
             cleanup_interval_minutes: env_or("CLEANUP_INTERVAL_MINUTES", "60")
                 .parse()
                 .unwrap_or(60),
+            shutdown_grace_seconds: env_or("SHUTDOWN_GRACE_SECONDS", "30")
+                .parse()
+                .unwrap_or(30),
+            response_cache: env_bool("RESPONSE_CACHE", false),
+            response_cache_size: env_or("RESPONSE_CACHE_SIZE", "1000").parse().unwrap_or(1000),
+            response_cache_ttl_seconds: env_or("RESPONSE_CACHE_TTL_SECONDS", "3600")
+                .parse()
+                .unwrap_or(3600),
+            dedup_inflight: env_bool("DEDUP_INFLIGHT", true),
+            store_transcripts: env_bool("STORE_TRANSCRIPTS", false),
+            message_retention_days: env_or("MESSAGE_RETENTION_DAYS", "0").parse().unwrap_or(0),
+            db_maintenance_interval_minutes: env_or("DB_MAINTENANCE_INTERVAL_MINUTES", "360")
+                .parse()
+                .unwrap_or(360),
+            db_vacuum: env_bool("DB_VACUUM", false),
+            history_token_budget: env_or("HISTORY_TOKEN_BUDGET", "0").parse().unwrap_or(0),
+            history_keep_recent: env_or("HISTORY_KEEP_RECENT", "6").parse().unwrap_or(6),
+            conversation_template_file: var("CONVERSATION_TEMPLATE_FILE")
+                .filter(|s| !s.is_empty())
+                .map(PathBuf::from),
+            project_conversation_templates: env_path_map("PROJECT_CONVERSATION_TEMPLATES"),
+            summary_model: env_or("SUMMARY_MODEL", "cc-haiku-45"),
+            max_messages: env_or("MAX_MESSAGES", "0").parse().unwrap_or(0),
+            max_message_bytes: env_or("MAX_MESSAGE_BYTES", "0").parse().unwrap_or(0),
+            max_prompt_bytes: env_or("MAX_PROMPT_BYTES", "0").parse().unwrap_or(0),
+            embedding_upstreams: embedding_upstreams_from_env(),
+            embedding_timeout_seconds: env_or("EMBEDDING_TIMEOUT_SECONDS", "60").parse().unwrap_or(60),
+            rag_top_k: env_or("RAG_TOP_K", "5").parse().unwrap_or(5),
+            rag_chunk_lines: env_or("RAG_CHUNK_LINES", "40").parse().unwrap_or(40).max(1),
+            rag_max_file_bytes: env_or("RAG_MAX_FILE_BYTES", "262144").parse().unwrap_or(262144),
+            message_index_batch: env_or("MESSAGE_INDEX_BATCH", "64").parse().unwrap_or(64),
+            message_index_interval_ms: env_or("MESSAGE_INDEX_INTERVAL_MS", "2000").parse().unwrap_or(2000),
+            max_upload_mb: env_or("MAX_UPLOAD_MB", "512").parse().unwrap_or(512),
+            redact_output: env_bool("REDACT_OUTPUT", false),
+            redact_entropy: env_bool("REDACT_ENTROPY", true),
+            redact_patterns: vars()
+                .filter_map(|(k, v)| {
+                    let name = k.strip_prefix("REDACT_PATTERN_")?;
+                    Some((name.to_lowercase(), v))
+                })
+                .collect(),
+            guardrail_mode: env_or("GUARDRAIL_MODE", "off").to_lowercase(),
+            guardrail_deny: vars()
+                .filter_map(|(k, v)| {
+                    let name = k.strip_prefix("GUARDRAIL_DENY_")?;
+                    Some((name.to_lowercase(), v))
+                })
+                .collect(),
+            guardrail_classifier: env_bool("GUARDRAIL_CLASSIFIER", false),
+            guardrail_model: env_or("GUARDRAIL_MODEL", "cc-haiku-45"),
+            hook_command_allowlist: env_csv("HOOK_COMMAND_ALLOWLIST"),
+            audit_log: env_bool("AUDIT_LOG", true),
+            record_requests: env_bool("RECORD_REQUESTS", false),
+            model_catalog: ModelCatalog::load(
+                file::catalog(),
+                var("MODEL_CATALOG_FILE")
+                    .filter(|s| !s.is_empty())
+                    .map(PathBuf::from)
+                    .as_deref(),
+                &env_map("MODEL_ALIASES"),
+            ),
+            strict_model_validation: env_bool("STRICT_MODEL_VALIDATION", false),
+            pricing_file: var("PRICING_FILE").filter(|s| !s.is_empty()).map(PathBuf::from),
+            model_pricing: env_map("MODEL_PRICING"),
+            startup_self_test: env_bool("STARTUP_SELF_TEST", false),
+            self_test_timeout_seconds: env_or("SELF_TEST_TIMEOUT_SECONDS", "60")
+                .parse()
+                .unwrap_or(60),
+            self_test_model: env_or("SELF_TEST_MODEL", "cc-haiku-45"),
+            log_format: env_or("LOG_FORMAT", "json").to_lowercase(),
+            log_level: env_or("LOG_LEVEL", "info"),
+            log_filters: env_csv_or("LOG_FILTERS", vec!["tower_http=debug".to_string()]),
+            log_file: var("LOG_FILE").filter(|s| !s.is_empty()).map(PathBuf::from),
+            log_rotation: env_or("LOG_ROTATION", "daily").to_lowercase(),
+            log_max_size_mb: env_or("LOG_MAX_SIZE_MB", "100").parse().unwrap_or(100),
+            log_max_files: env_or("LOG_MAX_FILES", "7").parse().unwrap_or(7),
+        }
+    }
+
+    /// The profile used when no other one matches.
+    pub fn default_profile(&self) -> &ClaudeProfile {
+        &self.claude_profiles[0]
+    }
+
+    /// Pick the profile for a request.
+    ///
+    /// An explicitly requested profile must exist (`None` otherwise). Without
+    /// one, a `MODEL_ROUTES` entry for the requested model wins, then the
+    /// longest model prefix matching either the requested or the resolved
+    /// model, falling back to the default profile.
+    pub fn select_profile(
+        &self,
+        requested: Option<&str>,
+        models: &[&str],
+    ) -> Option<&ClaudeProfile> {
+        if let Some(name) = requested {
+            return self.claude_profiles.iter().find(|p| p.name == name);
+        }
+        let routed = models.first().and_then(|model| {
+            let (_, name) = self.model_routes.iter().find(|(m, _)| m == model)?;
+            self.claude_profiles.iter().find(|p| p.name == *name)
+        });
+        if routed.is_some() {
+            return routed;
+        }
+        self.claude_profiles
+            .iter()
+            .flat_map(|p| p.model_prefixes.iter().map(move |prefix| (p, prefix)))
+            .filter(|(_, prefix)| models.iter().any(|m| m.starts_with(prefix.as_str())))
+            .max_by_key(|(_, prefix)| prefix.len())
+            .map(|(p, _)| p)
+            .or_else(|| Some(self.default_profile()))
+    }
+
+    /// The upstream serving embeddings for `model`: the longest matching
+    /// model prefix, if any.
+    pub fn embedding_upstream(&self, model: &str) -> Option<&EmbeddingUpstream> {
+        self.embedding_upstreams
+            .iter()
+            .flat_map(|u| u.model_prefixes.iter().map(move |prefix| (u, prefix)))
+            .filter(|(_, prefix)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(_, prefix)| prefix.len())
+            .map(|(u, _)| u)
+    }
+
+    /// Extra environment configured for a project, if any.
+    pub fn project_env(&self, project_id: &str) -> Option<&[(String, String)]> {
+        let key: String = project_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_uppercase() } else { '_' })
+            .collect();
+        self.project_env
+            .iter()
+            .find(|(id, _)| *id == key)
+            .map(|(_, env)| env.as_slice())
+    }
+
+    /// The projects an API key is limited to, if it is.
+    pub fn key_projects(&self, api_key: Option<&str>) -> Option<&[String]> {
+        let key = api_key?;
+        let hash = crate::auth::hash_api_key(key);
+        self.key_projects
+            .iter()
+            .find(|(k, _)| *k == key || k.eq_ignore_ascii_case(&hash))
+            .map(|(_, projects)| projects.as_slice())
+    }
+
+    /// The tier of an API key: its own, else `DEFAULT_TIER`.
+    pub fn key_tier(&self, api_key: Option<&str>) -> Option<&Tier> {
+        let assigned = api_key.and_then(|key| {
+            let hash = crate::auth::hash_api_key(key);
+            self.key_tiers
+                .iter()
+                .find(|(k, _)| *k == key || k.eq_ignore_ascii_case(&hash))
+                .map(|(_, tier)| tier)
+        });
+        let name = assigned.or(self.default_tier.as_ref())?;
+        self.tiers.iter().find(|t| t.name == *name)
+    }
+
+    /// The account config dir assigned to an API key, else to a project.
+    pub fn account_config_dir(&self, api_key: Option<&str>, project_id: &str) -> Option<&Path> {
+        let by_key = api_key.and_then(|key| {
+            let hash = crate::auth::hash_api_key(key);
+            self.key_config_dirs
+                .iter()
+                .find(|(k, _)| *k == key || k.eq_ignore_ascii_case(&hash))
+        });
+        by_key
+            .or_else(|| self.project_config_dirs.iter().find(|(p, _)| p == project_id))
+            .map(|(_, dir)| dir.as_path())
+    }
+}
+
+/// Read `CLAUDE_PROFILES=name,...` and the per-profile
+/// `CLAUDE_PROFILE_<NAME>_{BINARY,CONFIG_DIR,ENV,MODELS}` variables.
+///
+/// `ENV` is a `;`-separated list of `KEY=VALUE` pairs; `MODELS` is a
+/// comma-separated list of model prefixes. A profile named `default`
+/// overrides the implicit one built from `CLAUDE_BINARY_PATH`.
+fn profiles_from_env(default_binary: &str) -> Vec<ClaudeProfile> {
+    let mut profiles = vec![ClaudeProfile {
+        name: "default".to_string(),
+        backend: "claude".to_string(),
+        binary_path: default_binary.to_string(),
+        config_dir: None,
+        env: Vec::new(),
+        model_prefixes: Vec::new(),
+    }];
+
+    for name in env_csv("CLAUDE_PROFILES") {
+        let var = |suffix: &str| {
+            let key = format!(
+                "CLAUDE_PROFILE_{}_{suffix}",
+                name.to_uppercase().replace('-', "_")
+            );
+            var(&key).filter(|s| !s.is_empty()).map(|v| (key, v))
+        };
+        let backend = var("BACKEND").map_or("claude".to_string(), |(_, v)| v.to_lowercase());
+        // Other CLIs default to their usual binary name on PATH
+        let binary_path = var("BINARY").map(|(_, v)| v).unwrap_or_else(|| match backend.as_str() {
+            "claude" => default_binary.to_string(),
+            other => other.to_string(),
+        });
+        let profile = ClaudeProfile {
+            backend,
+            binary_path,
+            config_dir: var("CONFIG_DIR").map(|(_, v)| PathBuf::from(v)),
+            env: var("ENV").map(|(_, v)| parse_env_pairs(&v)).unwrap_or_default(),
+            model_prefixes: var("MODELS").map(|(k, _)| env_csv(&k)).unwrap_or_default(),
+            name,
+        };
+        match profiles.iter_mut().find(|p| p.name == profile.name) {
+            Some(existing) => *existing = profile,
+            None => profiles.push(profile),
         }
     }
+    profiles
+}
+
+/// Tiers named in `TIERS`, each configured by
+/// `TIER_<NAME>_{RATE_LIMIT,MODELS,MAX_TOKENS,FEATURES,MAX_PRIORITY}`.
+/// `MODELS` and `FEATURES` are comma-separated; without `FEATURES` every
+/// feature is allowed, `FEATURES=none` allows none. `MAX_PRIORITY` is
+/// `normal` unless set.
+fn tiers_from_env() -> Vec<Tier> {
+    env_csv("TIERS")
+        .into_iter()
+        .map(|name| {
+            let key = |suffix: &str| format!("TIER_{}_{suffix}", name.to_uppercase().replace('-', "_"));
+            let features = match var(&key("FEATURES")).filter(|s| !s.is_empty()) {
+                Some(_) => env_csv(&key("FEATURES")).into_iter().filter(|f| f != "none").collect(),
+                None => crate::tiers::FEATURES.iter().map(|f| f.to_string()).collect(),
+            };
+            Tier {
+                requests_per_minute: var(&key("RATE_LIMIT")).and_then(|v| v.parse().ok()),
+                models: env_csv(&key("MODELS")),
+                max_tokens: var(&key("MAX_TOKENS")).and_then(|v| v.parse().ok()).filter(|&n| n > 0),
+                features,
+                max_priority: var(&key("MAX_PRIORITY")).and_then(|v| Priority::parse(&v)).unwrap_or_default(),
+                name,
+            }
+        })
+        .collect()
+}
+
+/// Upstreams named in `EMBEDDING_UPSTREAMS`, each configured by
+/// `EMBEDDING_UPSTREAM_<NAME>_{URL,API_KEY,MODELS}`. Upstreams without a URL
+/// or models are skipped.
+fn embedding_upstreams_from_env() -> Vec<EmbeddingUpstream> {
+    env_csv("EMBEDDING_UPSTREAMS")
+        .into_iter()
+        .filter_map(|name| {
+            let key = |suffix: &str| {
+                format!("EMBEDDING_UPSTREAM_{}_{suffix}", name.to_uppercase().replace('-', "_"))
+            };
+            let url = var(&key("URL")).filter(|s| !s.is_empty());
+            let model_prefixes = env_csv(&key("MODELS"));
+            let Some(url) = url.filter(|_| !model_prefixes.is_empty()) else {
+                tracing::warn!(upstream = %name, "Embedding upstream needs a URL and MODELS; skipping");
+                return None;
+            };
+            Some(EmbeddingUpstream {
+                url: url.trim_end_matches('/').to_string(),
+                api_key: var(&key("API_KEY")).filter(|s| !s.is_empty()),
+                model_prefixes,
+                name,
+            })
+        })
+        .collect()
+}
+
+/// Parse `KEY=VALUE;KEY2=VALUE2`, skipping malformed entries.
+fn parse_env_pairs(s: &str) -> Vec<(String, String)> {
+    s.split(';')
+        .filter_map(|pair| {
+            let (k, v) = pair.split_once('=')?;
+            let k = k.trim();
+            (!k.is_empty()).then(|| (k.to_string(), v.to_string()))
+        })
+        .collect()
+}
+
+/// `.env` variables read by the last reload, which win over the ones
+/// copied into the environment from `.env` at startup.
+static ENV_FILE: RwLock<BTreeMap<String, String>> = RwLock::new(BTreeMap::new());
+
+/// Variables set in the process environment before [`load_dotenv`].
+static PROCESS_ENV: OnceLock<BTreeSet<String>> = OnceLock::new();
+
+/// Load `.env`, if present, into the process environment without
+/// overriding variables already set, remembering which those were. Call at
+/// startup, before other threads read the environment.
+pub fn load_dotenv() {
+    PROCESS_ENV.get_or_init(|| env::vars_os().filter_map(|(key, _)| key.into_string().ok()).collect());
+    let _ = dotenvy::dotenv();
+}
+
+/// Whether `key` in the environment was set by the process's parent rather
+/// than copied from `.env` (everything is, without [`load_dotenv`]).
+fn from_process(key: &str) -> bool {
+    PROCESS_ENV.get().is_none_or(|keys| keys.contains(key))
+}
+
+#[cfg(test)]
+thread_local! {
+    /// Set while [`Config::for_test`] runs: every variable reads as unset.
+    static DEFAULTS_ONLY: std::cell::Cell<bool> = const { std::cell::Cell::new(false) };
+}
+
+fn defaults_only() -> bool {
+    #[cfg(test)]
+    return DEFAULTS_ONLY.get();
+    #[cfg(not(test))]
+    false
+}
+
+/// A variable from the process environment, else the reloaded `.env`,
+/// else what startup copied from `.env`, else the config file.
+fn var(key: &str) -> Option<String> {
+    if defaults_only() {
+        return None;
+    }
+    let process = env::var(key).ok();
+    if process.is_some() && from_process(key) {
+        return process;
+    }
+    let env_file = ENV_FILE.read().unwrap_or_else(|e| e.into_inner()).get(key).cloned();
+    env_file.or(process).or_else(|| file::var(key))
+}
+
+/// The config file's variables overlaid in the order [`var`] reads them.
+fn vars() -> impl Iterator<Item = (String, String)> {
+    let mut all = BTreeMap::new();
+    if defaults_only() {
+        return all.into_iter();
+    }
+    let (process, copied): (Vec<_>, Vec<_>) = env::vars().partition(|(key, _)| from_process(key));
+    all.extend(file::vars());
+    all.extend(copied);
+    all.extend(ENV_FILE.read().unwrap_or_else(|e| e.into_inner()).clone());
+    all.extend(process);
+    all.into_iter()
 }
 
 fn env_or(key: &str, default: &str) -> String {
-    env::var(key).unwrap_or_else(|_| default.to_string())
+    var(key).unwrap_or_else(|| default.to_string())
 }
 
 fn env_bool(key: &str, default: bool) -> bool {
-    env::var(key)
+    var(key)
         .map(|v| matches!(v.to_lowercase().as_str(), "1" | "true" | "yes"))
         .unwrap_or(default)
 }
 
 fn env_csv(key: &str) -> Vec<String> {
-    env::var(key)
+    var(key)
         .map(|v| v.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect())
         .unwrap_or_default()
 }
 
+/// Parse `key=value` csv entries, skipping malformed ones.
+fn env_map(key: &str) -> Vec<(String, String)> {
+    env_csv(key)
+        .into_iter()
+        .filter_map(|entry| {
+            let (k, v) = entry.split_once('=')?;
+            Some((k.trim().to_string(), v.trim().to_string()))
+        })
+        .collect()
+}
+
+fn env_path_map(key: &str) -> Vec<(String, PathBuf)> {
+    env_map(key).into_iter().map(|(k, v)| (k, PathBuf::from(v))).collect()
+}
+
 fn env_csv_or(key: &str, default: Vec<String>) -> Vec<String> {
     let result = env_csv(key);
     if result.is_empty() { default } else { result }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn profile(name: &str, prefixes: &[&str]) -> ClaudeProfile {
+        ClaudeProfile {
+            name: name.to_string(),
+            backend: "claude".to_string(),
+            binary_path: "claude".to_string(),
+            config_dir: None,
+            env: Vec::new(),
+            model_prefixes: prefixes.iter().map(|s| s.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_select_profile() {
+        let mut config = Config::for_test();
+        config.claude_profiles = vec![
+            profile("default", &[]),
+            profile("api", &["claude-"]),
+            profile("beta", &["claude-opus-4"]),
+        ];
+        config.model_routes = vec![
+            ("claude-opus-4-0".to_string(), "api".to_string()),
+            ("gpt-4".to_string(), "missing".to_string()),
+        ];
+        let pick = |req: Option<&str>, models: &[&str]| {
+            config.select_profile(req, models).map(|p| p.name.clone())
+        };
+
+        assert_eq!(pick(Some("api"), &["cc-opus"]).as_deref(), Some("api"));
+        assert_eq!(pick(Some("missing"), &["cc-opus"]), None);
+        assert_eq!(pick(None, &["claude-opus-4-1"]).as_deref(), Some("beta"));
+        assert_eq!(pick(None, &["cc-sonnet", "claude-sonnet-4-5"]).as_deref(), Some("api"));
+        assert_eq!(pick(None, &["gpt-4"]).as_deref(), Some("default"));
+        // Exact routes beat prefixes; routes to unknown profiles are ignored
+        assert_eq!(pick(None, &["claude-opus-4-0"]).as_deref(), Some("api"));
+    }
+
+    #[test]
+    fn test_account_config_dir() {
+        let mut config = Config::for_test();
+        config.key_config_dirs = vec![
+            ("sk-team-a".to_string(), PathBuf::from("/acct/a")),
+            (crate::auth::hash_api_key("sk-team-b"), PathBuf::from("/acct/b")),
+        ];
+        config.project_config_dirs = vec![("billing".to_string(), PathBuf::from("/acct/billing"))];
+
+        let dir = |key: Option<&str>, project: &str| config.account_config_dir(key, project);
+        assert_eq!(dir(Some("sk-team-a"), "billing"), Some(Path::new("/acct/a")));
+        assert_eq!(dir(Some("sk-team-b"), "default"), Some(Path::new("/acct/b")));
+        assert_eq!(dir(Some("sk-other"), "billing"), Some(Path::new("/acct/billing")));
+        assert_eq!(dir(None, "default"), None);
+    }
+
+    #[test]
+    fn test_embedding_upstream() {
+        let mut config = Config::for_test();
+        let upstream = |name: &str, prefixes: &[&str]| EmbeddingUpstream {
+            name: name.to_string(),
+            url: format!("https://{name}.example/v1"),
+            api_key: None,
+            model_prefixes: prefixes.iter().map(|s| s.to_string()).collect(),
+        };
+        config.embedding_upstreams =
+            vec![upstream("openai", &["text-embedding-"]), upstream("voyage", &["voyage-", "text-embedding-voyage"])];
+        let pick = |model: &str| config.embedding_upstream(model).map(|u| u.name.clone());
+
+        assert_eq!(pick("text-embedding-3-small").as_deref(), Some("openai"));
+        assert_eq!(pick("text-embedding-voyage-x").as_deref(), Some("voyage"));
+        assert_eq!(pick("voyage-3").as_deref(), Some("voyage"));
+        assert_eq!(pick("local"), None);
+    }
+
+    #[test]
+    fn test_parse_env_pairs() {
+        assert_eq!(
+            parse_env_pairs("A=1; B = x=y ;bad;=z"),
+            vec![("A".to_string(), "1".to_string()), ("B".to_string(), " x=y ".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_env_file_overrides_without_set_var() {
+        let env_file = BTreeMap::from([
+            ("LOG_MAX_FILES".to_string(), "42".to_string()),
+            ("PATH".to_string(), "/from/env-file".to_string()),
+        ]);
+        let config = Config::load_with_env_file(env_file).unwrap();
+        assert_eq!(config.log_max_files, 42);
+        assert!(env::var_os("LOG_MAX_FILES").is_none());
+        // The process environment still wins, as it does at startup
+        assert_eq!(var("PATH"), env::var("PATH").ok());
+        assert!(vars().any(|(key, value)| key == "PATH" && value != "/from/env-file"));
+        Config::load_with_env_file(BTreeMap::new()).unwrap();
+    }
+}
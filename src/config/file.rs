@@ -0,0 +1,233 @@
+//! `CONFIG_FILE`: the environment's settings as TOML, plus sections for
+//! structures that are awkward as variables. Top-level keys are variable
+//! names in lower case (`rate_limit_burst = 20`); arrays become
+//! comma-separated lists and tables `key=value` lists (`;`-separated for
+//! `claude_env`). The sections are:
+//!
+//! - `[profiles.<name>]`: `backend`, `binary`, `config_dir`, `models` and an
+//!   `env` table, as `CLAUDE_PROFILE_<NAME>_*`
+//! - `[embedding_upstreams.<name>]`: `url`, `api_key` and `models`
+//! - `[[keys]]`: `key`, `admin`, `config_dir` and `projects`, adding to
+//!   `API_KEYS`, `ADMIN_API_KEYS`, `KEY_CONFIG_DIRS` and `KEY_PROJECTS`
+//! - `[project_env.<id>]`, `[redact_patterns]` and `[guardrail_deny]`, the
+//!   prefixed variable families
+//! - `[catalog]`: a model catalog, laid out like `MODEL_CATALOG_FILE` and
+//!   applied before it
+//!
+//! Every value is a fallback: a variable set in the environment wins.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::RwLock;
+
+use toml::{Table, Value};
+
+use crate::models::catalog::CatalogFile;
+
+/// A parsed config file.
+#[derive(Debug, Default)]
+pub(super) struct Layer {
+    /// Values by environment variable name.
+    pub vars: BTreeMap<String, String>,
+    pub catalog: Option<CatalogFile>,
+}
+
+/// The layer `Config::from_env` falls back to, replaced on every load.
+static LAYER: RwLock<Layer> = RwLock::new(Layer { vars: BTreeMap::new(), catalog: None });
+
+pub(super) fn read(path: &Path) -> Result<Layer, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    parse(&text)
+}
+
+pub(super) fn install(layer: Layer) {
+    *LAYER.write().unwrap_or_else(|e| e.into_inner()) = layer;
+}
+
+pub(super) fn var(key: &str) -> Option<String> {
+    LAYER.read().unwrap_or_else(|e| e.into_inner()).vars.get(key).cloned()
+}
+
+pub(super) fn vars() -> Vec<(String, String)> {
+    let layer = LAYER.read().unwrap_or_else(|e| e.into_inner());
+    layer.vars.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+}
+
+pub(super) fn catalog() -> Option<CatalogFile> {
+    LAYER.read().unwrap_or_else(|e| e.into_inner()).catalog.clone()
+}
+
+fn parse(text: &str) -> Result<Layer, String> {
+    let table: Table = toml::from_str(text).map_err(|e| e.to_string())?;
+    let mut layer = Layer::default();
+    let vars = &mut layer.vars;
+    for (key, value) in table {
+        match key.as_str() {
+            "profiles" => named_sections(vars, "CLAUDE_PROFILES", "CLAUDE_PROFILE_", &key, value)?,
+            "embedding_upstreams" => {
+                named_sections(vars, "EMBEDDING_UPSTREAMS", "EMBEDDING_UPSTREAM_", &key, value)?
+            }
+            "keys" => keys(vars, value)?,
+            "project_env" => prefixed(vars, "CLAUDE_PROJECT_ENV_", &key, value, ';')?,
+            "redact_patterns" => prefixed(vars, "REDACT_PATTERN_", &key, value, ',')?,
+            "guardrail_deny" => prefixed(vars, "GUARDRAIL_DENY_", &key, value, ',')?,
+            "catalog" => layer.catalog = Some(value.try_into().map_err(|e| format!("catalog: {e}"))?),
+            _ => {
+                let separator = if key == "claude_env" { ';' } else { ',' };
+                vars.insert(key.to_uppercase(), render(&value, separator));
+            }
+        }
+    }
+    Ok(layer)
+}
+
+/// `value` as a variable: arrays comma-separated, tables as `k=v` pairs.
+fn render(value: &Value, separator: char) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Array(items) => items.iter().map(|v| render(v, separator)).collect::<Vec<_>>().join(","),
+        Value::Table(table) => table
+            .iter()
+            .map(|(k, v)| format!("{k}={}", render(v, separator)))
+            .collect::<Vec<_>>()
+            .join(&separator.to_string()),
+        other => other.to_string(),
+    }
+}
+
+fn sections(key: &str, value: Value) -> Result<Table, String> {
+    match value {
+        Value::Table(table) => Ok(table),
+        _ => Err(format!("{key}: expected a table")),
+    }
+}
+
+/// `[<key>.<name>]` sections as `<list>=name,...` and `<prefix><NAME>_<FIELD>`.
+fn named_sections(
+    vars: &mut BTreeMap<String, String>,
+    list: &str,
+    prefix: &str,
+    key: &str,
+    value: Value,
+) -> Result<(), String> {
+    let mut names = Vec::new();
+    for (name, section) in sections(key, value)? {
+        let upper = name.to_uppercase().replace('-', "_");
+        for (field, value) in sections(&format!("{key}.{name}"), section)? {
+            let separator = if field == "env" { ';' } else { ',' };
+            vars.insert(format!("{prefix}{upper}_{}", field.to_uppercase()), render(&value, separator));
+        }
+        names.push(name);
+    }
+    vars.insert(list.to_string(), names.join(","));
+    Ok(())
+}
+
+/// `[<key>]` entries as `<prefix><NAME>` variables.
+fn prefixed(
+    vars: &mut BTreeMap<String, String>,
+    prefix: &str,
+    key: &str,
+    value: Value,
+    separator: char,
+) -> Result<(), String> {
+    for (name, value) in sections(key, value)? {
+        let upper = name.to_uppercase().replace('-', "_");
+        vars.insert(format!("{prefix}{upper}"), render(&value, separator));
+    }
+    Ok(())
+}
+
+/// `[[keys]]` entries. Every key is an API key; `admin = true` also allows
+/// it on `/admin/*`, `config_dir` assigns it an account and `projects`
+/// limits it to those projects.
+fn keys(vars: &mut BTreeMap<String, String>, value: Value) -> Result<(), String> {
+    let Value::Array(entries) = value else {
+        return Err("keys: expected an array of tables".to_string());
+    };
+    let (mut api, mut admin, mut dirs, mut projects) = (Vec::new(), Vec::new(), Vec::new(), Vec::new());
+    for entry in entries {
+        let Some(key) = entry.get("key").and_then(Value::as_str).filter(|k| !k.is_empty()) else {
+            return Err("keys: every entry needs a key".to_string());
+        };
+        api.push(key.to_string());
+        if entry.get("admin").and_then(Value::as_bool).unwrap_or(false) {
+            admin.push(key.to_string());
+        }
+        if let Some(dir) = entry.get("config_dir").and_then(Value::as_str) {
+            dirs.push(format!("{key}={dir}"));
+        }
+        if let Some(Value::Array(list)) = entry.get("projects") {
+            let list: Vec<&str> = list.iter().filter_map(Value::as_str).collect();
+            projects.push(format!("{key}={}", list.join("|")));
+        }
+    }
+    for (name, list) in [
+        ("API_KEYS", api),
+        ("ADMIN_API_KEYS", admin),
+        ("KEY_CONFIG_DIRS", dirs),
+        ("KEY_PROJECTS", projects),
+    ] {
+        if !list.is_empty() {
+            vars.insert(name.to_string(), list.join(","));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let layer = parse(
+            r#"
+            rate_limit_burst = 20
+            allowed_origins = ["https://a.example", "https://b.example"]
+            model_routes = { "gpt-5" = "codex" }
+            claude_env = { A = "1", B = "2" }
+
+            [[keys]]
+            key = "sk-team"
+            config_dir = "/acct/team"
+            projects = ["alpha", "beta"]
+
+            [[keys]]
+            key = "sk-ops"
+            admin = true
+
+            [profiles.codex]
+            backend = "codex"
+            models = ["gpt-", "o3"]
+            env = { OPENAI_BASE_URL = "http://proxy" }
+
+            [guardrail_deny]
+            secrets = "(?i)password"
+
+            [catalog]
+            replace = true
+            [[catalog.models]]
+            id = "claude-sonnet-4-5-20250929"
+            "#,
+        )
+        .unwrap();
+        let var = |k: &str| layer.vars.get(k).map(String::as_str);
+        assert_eq!(var("RATE_LIMIT_BURST"), Some("20"));
+        assert_eq!(var("ALLOWED_ORIGINS"), Some("https://a.example,https://b.example"));
+        assert_eq!(var("MODEL_ROUTES"), Some("gpt-5=codex"));
+        assert_eq!(var("CLAUDE_ENV"), Some("A=1;B=2"));
+        assert_eq!(var("API_KEYS"), Some("sk-team,sk-ops"));
+        assert_eq!(var("ADMIN_API_KEYS"), Some("sk-ops"));
+        assert_eq!(var("KEY_CONFIG_DIRS"), Some("sk-team=/acct/team"));
+        assert_eq!(var("KEY_PROJECTS"), Some("sk-team=alpha|beta"));
+        assert_eq!(var("CLAUDE_PROFILES"), Some("codex"));
+        assert_eq!(var("CLAUDE_PROFILE_CODEX_MODELS"), Some("gpt-,o3"));
+        assert_eq!(var("CLAUDE_PROFILE_CODEX_ENV"), Some("OPENAI_BASE_URL=http://proxy"));
+        assert_eq!(var("GUARDRAIL_DENY_SECRETS"), Some("(?i)password"));
+        assert!(layer.catalog.is_some_and(|c| c.replace && c.models.len() == 1));
+
+        assert!(parse("[[keys]]\nadmin = true").is_err());
+        assert!(parse("profiles = 1").is_err());
+    }
+}
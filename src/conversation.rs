@@ -0,0 +1,117 @@
+//! Operator templates for the conversation prompt. `CONVERSATION_TEMPLATE_FILE`
+//! replaces the built-in `[User]: ...` transcript of
+//! [`crate::prompt::conversation_prompt`] for every project, and
+//! `PROJECT_CONVERSATION_TEMPLATES=<project>=<path>,...` for single projects.
+//!
+//! Templates are Jinja (minijinja) and render the whole prompt, the single
+//! message case included. They get:
+//!
+//! - `messages`: the conversation without the system prompt, each with
+//!   `role` (`user`, `assistant`, `system`, `tool` or `summary`), `content`,
+//!   `name` and `tool_calls` (`id`, `name`, `arguments` and `result`, the
+//!   content of the matching `tool` message, which is not listed separately)
+//! - `last_user`: the text of the last user message
+//!
+//! A template that can't be read, parsed or rendered is logged and the
+//! built-in rendering is used instead.
+
+use minijinja::{context, Environment};
+
+use crate::config::Config;
+use crate::models::openai::ChatMessage;
+use crate::prompt::{conversation_prompt, conversation_turns, history, last_user_text};
+
+/// Name of the template used by projects without their own.
+const DEFAULT_TEMPLATE: &str = "default";
+
+/// The configured conversation templates.
+pub struct ConversationTemplates {
+    env: Environment<'static>,
+}
+
+impl ConversationTemplates {
+    pub fn load(config: &Config) -> Self {
+        let mut env = Environment::new();
+        let files = config
+            .conversation_template_file
+            .iter()
+            .map(|path| (DEFAULT_TEMPLATE.to_string(), path))
+            .chain(config.project_conversation_templates.iter().map(|(id, path)| (project_template(id), path)));
+        for (name, path) in files {
+            let loaded = std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|source| env.add_template_owned(name, source).map_err(|e| e.to_string()));
+            if let Err(e) = loaded {
+                tracing::warn!(path = %path.display(), error = %e, "Ignoring conversation template");
+            }
+        }
+        Self { env }
+    }
+
+    /// The prompt for `messages` in `project_id`: its template's rendering,
+    /// else the deployment's, else [`conversation_prompt`].
+    pub fn prompt(&self, project_id: &str, messages: &[ChatMessage]) -> String {
+        let template = self
+            .env
+            .get_template(&project_template(project_id))
+            .or_else(|_| self.env.get_template(DEFAULT_TEMPLATE));
+        let Ok(template) = template else {
+            return conversation_prompt(messages);
+        };
+        let rendered = template.render(context! {
+            messages => conversation_turns(history(messages)),
+            last_user => last_user_text(messages),
+        });
+        rendered.unwrap_or_else(|e| {
+            tracing::warn!(template = template.name(), error = %e, "Conversation template failed; using the built-in rendering");
+            conversation_prompt(messages)
+        })
+    }
+}
+
+fn project_template(project_id: &str) -> String {
+    format!("project:{project_id}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prompt() {
+        let dir = tempfile::tempdir().unwrap();
+        let default = dir.path().join("default.j2");
+        std::fs::write(
+            &default,
+            "{% for m in messages %}{{ m.role | upper }}: {{ m.content }}\n\
+             {% for c in m.tool_calls %}-> {{ c.name }} = {{ c.result }}\n{% endfor %}{% endfor %}",
+        )
+        .unwrap();
+        let broken = dir.path().join("broken.j2");
+        std::fs::write(&broken, "{{ last_user + 1 }}").unwrap();
+
+        let mut config = Config::for_test();
+        config.conversation_template_file = Some(default);
+        config.project_conversation_templates = vec![
+            ("terse".to_string(), dir.path().join("missing.j2")),
+            ("broken".to_string(), broken),
+        ];
+        let templates = ConversationTemplates::load(&config);
+
+        let messages: Vec<ChatMessage> = serde_json::from_value(serde_json::json!([
+            {"role": "system", "content": "Be brief."},
+            {"role": "user", "content": "Time?"},
+            {"role": "assistant", "content": "", "tool_calls": [
+                {"id": "c1", "type": "function", "function": {"name": "clock", "arguments": "{}"}},
+            ]},
+            {"role": "tool", "tool_call_id": "c1", "content": "12:00"},
+        ]))
+        .unwrap();
+        let expected = "USER: Time?\nASSISTANT: \n-> clock = 12:00\n";
+        assert_eq!(templates.prompt("default", &messages), expected);
+        // An unreadable project template falls back to the deployment's
+        assert_eq!(templates.prompt("terse", &messages), expected);
+        // One that fails to render, to the built-in transcript
+        assert_eq!(templates.prompt("broken", &messages), conversation_prompt(&messages));
+    }
+}
@@ -0,0 +1,155 @@
+//! Encryption at rest for conversation content. With a key configured,
+//! message content, transcript events, background job responses, cached
+//! responses and recorded requests and responses are sealed with
+//! AES-256-GCM before they reach SQLite and opened again when read, so the
+//! database file alone doesn't give prompts and code away.
+//!
+//! The key is 32 bytes, base64-encoded, from `ENCRYPTION_KEY`,
+//! `ENCRYPTION_KEY_FILE` or the output of `ENCRYPTION_KEY_COMMAND` (e.g. a
+//! KMS or Vault CLI call). Sealed values are `enc:v1:<base64 nonce +
+//! ciphertext>`; rows stored before encryption was turned on stay readable,
+//! and `claude-code-api db encrypt` seals them.
+
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
+
+use crate::config::Config;
+
+/// Marks a sealed value.
+pub const PREFIX: &str = "enc:v1:";
+
+static CIPHER: OnceLock<Cipher> = OnceLock::new();
+
+/// An AES-256-GCM key.
+pub struct Cipher {
+    key: LessSafeKey,
+    rng: SystemRandom,
+}
+
+impl Cipher {
+    /// A cipher for a base64-encoded 32-byte key.
+    pub fn from_base64(key: &str) -> Result<Self, String> {
+        let bytes = STANDARD
+            .decode(key.trim())
+            .map_err(|e| format!("the encryption key is not valid base64: {e}"))?;
+        let key = UnboundKey::new(&AES_256_GCM, &bytes)
+            .map_err(|_| format!("the encryption key must be 32 bytes, not {}", bytes.len()))?;
+        Ok(Self { key: LessSafeKey::new(key), rng: SystemRandom::new() })
+    }
+
+    pub fn seal(&self, plaintext: &str) -> String {
+        let mut nonce = [0u8; NONCE_LEN];
+        self.rng.fill(&mut nonce).expect("system random number generator failed");
+        let mut sealed = plaintext.as_bytes().to_vec();
+        self.key
+            .seal_in_place_append_tag(Nonce::assume_unique_for_key(nonce), Aad::empty(), &mut sealed)
+            .expect("AES-GCM sealing failed");
+        let mut out = nonce.to_vec();
+        out.extend(sealed);
+        format!("{PREFIX}{}", STANDARD.encode(out))
+    }
+
+    /// The plaintext of a sealed value; `None` if it was sealed with another
+    /// key or is corrupt.
+    pub fn open(&self, sealed: &str) -> Option<String> {
+        let bytes = STANDARD.decode(sealed.strip_prefix(PREFIX)?).ok()?;
+        if bytes.len() < NONCE_LEN {
+            return None;
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).ok()?;
+        let mut buf = ciphertext.to_vec();
+        let plaintext = self.key.open_in_place(nonce, Aad::empty(), &mut buf).ok()?;
+        String::from_utf8(plaintext.to_vec()).ok()
+    }
+}
+
+/// Load the configured key, if any. Call once at startup; a key that is
+/// configured but can't be loaded is an error, not a reason to store
+/// plaintext.
+pub fn init(config: &Config) -> Result<bool, String> {
+    let key = if let Some(ref key) = config.encryption_key {
+        key.clone()
+    } else if let Some(ref path) = config.encryption_key_file {
+        std::fs::read_to_string(path).map_err(|e| format!("cannot read ENCRYPTION_KEY_FILE {}: {e}", path.display()))?
+    } else if let Some(ref command) = config.encryption_key_command {
+        let output = std::process::Command::new("sh")
+            .args(["-c", command])
+            .output()
+            .map_err(|e| format!("cannot run ENCRYPTION_KEY_COMMAND: {e}"))?;
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            return Err(format!("ENCRYPTION_KEY_COMMAND failed ({}): {}", output.status, stderr.trim()));
+        }
+        String::from_utf8_lossy(&output.stdout).into_owned()
+    } else {
+        return Ok(false);
+    };
+    let cipher = Cipher::from_base64(&key)?;
+    // A second call (tests, the CLI) keeps the first key
+    let _ = CIPHER.set(cipher);
+    Ok(true)
+}
+
+/// Whether content is being encrypted.
+pub fn enabled() -> bool {
+    CIPHER.get().is_some()
+}
+
+/// Whether `stored` is a sealed value.
+pub fn is_sealed(stored: &str) -> bool {
+    stored.starts_with(PREFIX)
+}
+
+/// `plaintext` as it is to be stored.
+pub fn seal(plaintext: &str) -> Cow<'_, str> {
+    match CIPHER.get() {
+        Some(cipher) => Cow::Owned(cipher.seal(plaintext)),
+        None => Cow::Borrowed(plaintext),
+    }
+}
+
+/// A stored value's plaintext. Values that can't be opened (no key, or
+/// another key) are returned as stored, and logged.
+pub fn open(stored: String) -> String {
+    if !is_sealed(&stored) {
+        return stored;
+    }
+    match CIPHER.get().and_then(|cipher| cipher.open(&stored)) {
+        Some(plaintext) => plaintext,
+        None => {
+            tracing::warn!("Cannot decrypt stored content; check the encryption key");
+            stored
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_seal_and_open() {
+        let key = STANDARD.encode([7u8; 32]);
+        let cipher = Cipher::from_base64(&key).unwrap();
+        let sealed = cipher.seal("fn main() { secret() }");
+        assert!(is_sealed(&sealed) && !sealed.contains("secret"));
+        assert_ne!(sealed, cipher.seal("fn main() { secret() }"), "nonces differ");
+        assert_eq!(cipher.open(&sealed).as_deref(), Some("fn main() { secret() }"));
+
+        let other = Cipher::from_base64(&STANDARD.encode([8u8; 32])).unwrap();
+        assert_eq!(other.open(&sealed), None);
+        let mut tampered = sealed.clone();
+        tampered.pop();
+        tampered.push(if sealed.ends_with('A') { 'B' } else { 'A' });
+        assert_eq!(cipher.open(&tampered), None);
+
+        assert!(Cipher::from_base64(&STANDARD.encode([1u8; 16])).is_err());
+        assert!(Cipher::from_base64("not base64!").is_err());
+    }
+}
@@ -1,16 +1,75 @@
+use futures::TryStreamExt;
 use serde::Serialize;
-use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions};
-use sqlx::{FromRow, SqlitePool};
+use sqlx::sqlite::{SqliteConnectOptions, SqlitePoolOptions, SqliteSynchronous};
+use sqlx::{FromRow, QueryBuilder, Sqlite, SqlitePool};
 use std::str::FromStr;
+use std::time::Duration;
 
-/// Initialize the SQLite connection pool and run migrations.
+use crate::audit::AuditEntry;
+use crate::config::Config;
+use crate::crypto;
+
+/// Connection pool settings (`DB_MAX_CONNECTIONS`, `DB_BUSY_TIMEOUT_MS`,
+/// `DB_SYNCHRONOUS`, `DB_CACHE_SIZE_KB`).
+#[derive(Debug, Clone)]
+pub struct DbOptions {
+    pub max_connections: u32,
+    /// How long a write waits for the lock before failing with "database is
+    /// locked"; SQLite retries within it.
+    pub busy_timeout: Duration,
+    pub synchronous: SqliteSynchronous,
+    /// Page cache per connection in KiB; 0 keeps SQLite's default.
+    pub cache_size_kb: u32,
+}
+
+impl Default for DbOptions {
+    fn default() -> Self {
+        Self {
+            max_connections: 10,
+            busy_timeout: Duration::from_secs(5),
+            // Durable with WAL except against power loss, and much faster
+            synchronous: SqliteSynchronous::Normal,
+            cache_size_kb: 0,
+        }
+    }
+}
+
+impl DbOptions {
+    /// Unknown `DB_SYNCHRONOUS` values are logged and fall back to `normal`.
+    pub fn from_config(config: &Config) -> Self {
+        let synchronous = SqliteSynchronous::from_str(&config.db_synchronous).unwrap_or_else(|_| {
+            tracing::warn!(value = %config.db_synchronous, "Unknown DB_SYNCHRONOUS; using normal");
+            SqliteSynchronous::Normal
+        });
+        Self {
+            max_connections: config.db_max_connections.max(1),
+            busy_timeout: Duration::from_millis(config.db_busy_timeout_ms),
+            synchronous,
+            cache_size_kb: config.db_cache_size_kb,
+        }
+    }
+}
+
+/// Initialize the SQLite connection pool with the default options and run
+/// migrations.
 pub async fn init_db(url: &str) -> Result<SqlitePool, sqlx::Error> {
-    let opts = SqliteConnectOptions::from_str(url)?
+    init_db_with(url, &DbOptions::default()).await
+}
+
+/// Initialize the SQLite connection pool and run migrations.
+pub async fn init_db_with(url: &str, options: &DbOptions) -> Result<SqlitePool, sqlx::Error> {
+    let mut opts = SqliteConnectOptions::from_str(url)?
         .create_if_missing(true)
-        .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal);
+        .journal_mode(sqlx::sqlite::SqliteJournalMode::Wal)
+        .busy_timeout(options.busy_timeout)
+        .synchronous(options.synchronous);
+    if options.cache_size_kb > 0 {
+        // Negative sizes are in KiB rather than pages
+        opts = opts.pragma("cache_size", format!("-{}", options.cache_size_kb));
+    }
 
     let pool = SqlitePoolOptions::new()
-        .max_connections(5)
+        .max_connections(options.max_connections)
         .connect_with(opts)
         .await?;
 
@@ -19,72 +78,64 @@ pub async fn init_db(url: &str) -> Result<SqlitePool, sqlx::Error> {
     Ok(pool)
 }
 
+/// Versioned migrations from `migrations/`, embedded at compile time.
+static MIGRATOR: sqlx::migrate::Migrator = sqlx::migrate!();
+
+/// Apply pending migrations; the applied version is recorded in
+/// `_sqlx_migrations` and readable from the `schema_version` view.
 async fn run_migrations(pool: &SqlitePool) -> Result<(), sqlx::Error> {
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS projects (
-            id TEXT PRIMARY KEY,
-            name TEXT NOT NULL,
-            description TEXT DEFAULT '',
-            path TEXT UNIQUE,
-            created_at TEXT NOT NULL DEFAULT (datetime('now')),
-            updated_at TEXT NOT NULL DEFAULT (datetime('now')),
-            is_active INTEGER NOT NULL DEFAULT 1
-        )",
-    )
-    .execute(pool)
-    .await?;
+    upgrade_unversioned(pool).await?;
+    MIGRATOR.run(pool).await?;
 
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS sessions (
-            id TEXT PRIMARY KEY,
-            project_id TEXT REFERENCES projects(id),
-            title TEXT DEFAULT '',
-            model TEXT NOT NULL,
-            system_prompt TEXT DEFAULT '',
-            created_at TEXT NOT NULL DEFAULT (datetime('now')),
-            updated_at TEXT NOT NULL DEFAULT (datetime('now')),
-            is_active INTEGER NOT NULL DEFAULT 1,
-            total_tokens INTEGER NOT NULL DEFAULT 0,
-            total_cost REAL NOT NULL DEFAULT 0.0,
-            message_count INTEGER NOT NULL DEFAULT 0
-        )",
-    )
-    .execute(pool)
-    .await?;
+    let version = schema_version(pool).await?;
+    tracing::info!(schema_version = version, "Database migrations completed");
+    Ok(())
+}
 
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS messages (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            session_id TEXT NOT NULL REFERENCES sessions(id),
-            role TEXT NOT NULL,
-            content TEXT NOT NULL DEFAULT '',
-            message_metadata TEXT DEFAULT '{}',
-            created_at TEXT NOT NULL DEFAULT (datetime('now')),
-            input_tokens INTEGER NOT NULL DEFAULT 0,
-            output_tokens INTEGER NOT NULL DEFAULT 0,
-            cost REAL NOT NULL DEFAULT 0.0
-        )",
-    )
-    .execute(pool)
-    .await?;
+/// The latest applied migration.
+pub async fn schema_version(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT version FROM schema_version").fetch_one(pool).await
+}
 
-    sqlx::query(
-        "CREATE TABLE IF NOT EXISTS api_keys (
-            id INTEGER PRIMARY KEY AUTOINCREMENT,
-            key_hash TEXT UNIQUE NOT NULL,
-            name TEXT DEFAULT '',
-            is_active INTEGER NOT NULL DEFAULT 1,
-            created_at TEXT NOT NULL DEFAULT (datetime('now')),
-            last_used_at TEXT,
-            total_requests INTEGER NOT NULL DEFAULT 0,
-            total_tokens INTEGER NOT NULL DEFAULT 0,
-            total_cost REAL NOT NULL DEFAULT 0.0
-        )",
-    )
-    .execute(pool)
-    .await?;
+/// Bring a database created before versioned migrations up to the baseline
+/// schema, so the idempotent baseline migration can adopt it.
+async fn upgrade_unversioned(pool: &SqlitePool) -> Result<(), sqlx::Error> {
+    let table_exists = |name: &'static str| async move {
+        sqlx::query_as::<_, (String,)>("SELECT name FROM sqlite_master WHERE type = 'table' AND name = ?")
+            .bind(name)
+            .fetch_optional(pool)
+            .await
+            .map(|row| row.is_some())
+    };
+    if table_exists("_sqlx_migrations").await? || !table_exists("request_log").await? {
+        return Ok(());
+    }
+
+    tracing::info!("Upgrading unversioned database to the baseline schema");
+    for column in ["spawn_ms", "ttft_ms", "process_ms", "exit_code", "peak_rss_kb"] {
+        add_column_if_missing(pool, "request_log", column, "INTEGER").await?;
+    }
+    Ok(())
+}
 
-    tracing::info!("Database migrations completed");
+/// `ALTER TABLE ... ADD COLUMN` for databases created before the column existed.
+async fn add_column_if_missing(
+    pool: &SqlitePool,
+    table: &str,
+    column: &str,
+    decl: &str,
+) -> Result<(), sqlx::Error> {
+    let exists: Option<(String,)> =
+        sqlx::query_as("SELECT name FROM pragma_table_info(?) WHERE name = ?")
+            .bind(table)
+            .bind(column)
+            .fetch_optional(pool)
+            .await?;
+    if exists.is_none() {
+        sqlx::query(&format!("ALTER TABLE {table} ADD COLUMN {column} {decl}"))
+            .execute(pool)
+            .await?;
+    }
     Ok(())
 }
 
@@ -99,6 +150,8 @@ pub struct ProjectRow {
     pub created_at: String,
     pub updated_at: String,
     pub is_active: i32,
+    /// Prepend retrieved workspace chunks to this project's prompts.
+    pub rag_enabled: bool,
 }
 
 #[derive(Debug, FromRow, Serialize)]
@@ -116,6 +169,241 @@ pub struct SessionRow {
     pub message_count: i64,
 }
 
+#[derive(Debug, FromRow, Serialize)]
+pub struct MessageRow {
+    pub id: i64,
+    pub session_id: String,
+    pub role: String,
+    pub content: String,
+    /// Model, finish reason, tool calls, attachments, latency, CLI session ID.
+    pub message_metadata: Option<sqlx::types::Json<serde_json::Value>>,
+    pub created_at: String,
+    pub input_tokens: i64,
+    pub output_tokens: i64,
+    pub cost: f64,
+}
+
+/// One version of a stored system prompt template.
+#[derive(Debug, FromRow, Serialize)]
+pub struct PromptRow {
+    pub id: String,
+    pub version: i64,
+    pub name: String,
+    pub description: String,
+    pub template: String,
+    pub created_at: String,
+}
+
+/// A headless coding task; see [`crate::tasks`].
+#[derive(Debug, FromRow, Serialize)]
+pub struct TaskRow {
+    pub id: String,
+    pub project_id: String,
+    pub instruction: String,
+    pub model: String,
+    pub base_commit: String,
+    pub session_id: String,
+    /// `queued`, `running`, `succeeded` or `failed`.
+    pub status: String,
+    pub result: Option<sqlx::types::Json<serde_json::Value>>,
+    pub diff: Option<String>,
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub completed_at: Option<String>,
+}
+
+/// A subagent of a project; see [`crate::agents`].
+#[derive(Debug, FromRow, Serialize)]
+pub struct AgentRow {
+    pub project_id: String,
+    pub name: String,
+    pub description: String,
+    pub prompt: String,
+    /// Tools the agent may use; all of the session's when `None`.
+    pub tools: Option<sqlx::types::Json<Vec<String>>>,
+    pub model: Option<String>,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+/// A project's hooks; see [`crate::hooks`].
+#[derive(Debug, FromRow, Serialize)]
+pub struct HooksRow {
+    pub project_id: String,
+    pub hooks: sqlx::types::Json<serde_json::Value>,
+    pub updated_at: String,
+}
+
+/// A key's monthly token allowance with this month's use; see
+/// [`crate::quota`].
+#[derive(Debug, FromRow, Serialize)]
+pub struct KeyQuotaRow {
+    pub key_hash: String,
+    pub monthly_tokens: i64,
+    pub used_tokens: i64,
+    pub updated_at: String,
+}
+
+/// A JSON column stored with [`crypto::seal`], opened when read.
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
+pub struct SealedJson(pub serde_json::Value);
+
+impl sqlx::Type<Sqlite> for SealedJson {
+    fn type_info() -> sqlx::sqlite::SqliteTypeInfo {
+        <String as sqlx::Type<Sqlite>>::type_info()
+    }
+}
+
+impl<'r> sqlx::Decode<'r, Sqlite> for SealedJson {
+    fn decode(value: sqlx::sqlite::SqliteValueRef<'r>) -> Result<Self, sqlx::error::BoxDynError> {
+        let stored = <String as sqlx::Decode<Sqlite>>::decode(value)?;
+        Ok(Self(serde_json::from_str(&crypto::open(stored))?))
+    }
+}
+
+/// A recorded completion; see [`crate::recording`].
+#[derive(Debug, FromRow, Serialize)]
+pub struct RecordingRow {
+    pub id: String,
+    pub session_id: Option<String>,
+    pub model: String,
+    pub request: SealedJson,
+    pub invocation: Option<sqlx::types::Json<serde_json::Value>>,
+    pub response: Option<SealedJson>,
+    pub replay_of: Option<String>,
+    pub created_at: String,
+}
+
+/// An uploaded file's record; the contents are at `path`.
+#[derive(Debug, FromRow, Serialize)]
+pub struct FileRow {
+    pub id: String,
+    pub filename: String,
+    pub purpose: String,
+    pub bytes: i64,
+    pub path: String,
+    pub created_at: String,
+}
+
+/// A background chat completion; see [`crate::jobs`].
+#[derive(Debug, FromRow, Serialize)]
+pub struct JobRow {
+    pub id: String,
+    pub model: String,
+    /// `queued`, `running`, `succeeded` or `failed`.
+    pub status: String,
+    pub response: Option<SealedJson>,
+    pub created_at: String,
+    pub started_at: Option<String>,
+    pub completed_at: Option<String>,
+}
+
+#[derive(Debug, FromRow, Serialize)]
+pub struct RequestLogRow {
+    pub id: i64,
+    pub created_at: String,
+    pub key_hash: Option<String>,
+    pub user_id: Option<String>,
+    pub client_ip: Option<String>,
+    pub method: String,
+    pub route: String,
+    pub model: Option<String>,
+    pub session_id: Option<String>,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost: f64,
+    pub latency_ms: i64,
+    pub status: i64,
+    pub spawn_ms: Option<i64>,
+    pub ttft_ms: Option<i64>,
+    pub process_ms: Option<i64>,
+    pub exit_code: Option<i64>,
+    pub peak_rss_kb: Option<i64>,
+    pub guardrail: Option<sqlx::types::Json<serde_json::Value>>,
+}
+
+// -- Maintenance --
+
+/// On-disk footprint of the database, for `/admin/metrics`.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct DbSize {
+    /// Main database file (`page_count * page_size`).
+    pub size_bytes: i64,
+    /// Pages on the freelist, reclaimable by `VACUUM`.
+    pub free_bytes: i64,
+    /// The `-wal` file next to the database, 0 if absent.
+    pub wal_bytes: u64,
+}
+
+pub async fn db_size(pool: &SqlitePool) -> Result<DbSize, sqlx::Error> {
+    let pragma = |name: &'static str| async move {
+        sqlx::query_scalar::<_, i64>(&format!("PRAGMA {name}")).fetch_one(pool).await
+    };
+    let page_size = pragma("page_size").await?;
+    let (_, _, file): (i64, String, String) = sqlx::query_as("PRAGMA database_list")
+        .fetch_one(pool)
+        .await?;
+    let wal_bytes = if file.is_empty() {
+        0
+    } else {
+        std::fs::metadata(format!("{file}-wal")).map_or(0, |m| m.len())
+    };
+    Ok(DbSize {
+        size_bytes: pragma("page_count").await? * page_size,
+        free_bytes: pragma("freelist_count").await? * page_size,
+        wal_bytes,
+    })
+}
+
+/// Tables reported by [`table_counts`].
+const COUNTED_TABLES: &[&str] = &[
+    "projects",
+    "project_chunks",
+    "project_agents",
+    "project_hooks",
+    "sessions",
+    "messages",
+    "spend",
+    "key_quotas",
+    "recordings",
+    "transcripts",
+    "files",
+    "tasks",
+    "jobs",
+    "prompts",
+    "request_log",
+    "response_cache",
+];
+
+/// Row count of each of the gateway's tables, plus active sessions.
+pub async fn table_counts(pool: &SqlitePool) -> Result<Vec<(&'static str, i64)>, sqlx::Error> {
+    let mut counts = Vec::with_capacity(COUNTED_TABLES.len() + 1);
+    for &table in COUNTED_TABLES {
+        let count = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}")).fetch_one(pool).await?;
+        counts.push((table, count));
+        if table == "sessions" {
+            let active = sqlx::query_scalar("SELECT COUNT(*) FROM sessions WHERE is_active = 1")
+                .fetch_one(pool)
+                .await?;
+            counts.push(("sessions (active)", active));
+        }
+    }
+    Ok(counts)
+}
+
+/// Truncate the WAL, refresh planner statistics and optionally `VACUUM`.
+pub async fn run_maintenance(pool: &SqlitePool, vacuum: bool) -> Result<(), sqlx::Error> {
+    sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(pool).await?;
+    sqlx::query("ANALYZE").execute(pool).await?;
+    if vacuum {
+        sqlx::query("VACUUM").execute(pool).await?;
+        // VACUUM rewrites the database through the WAL
+        sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(pool).await?;
+    }
+    Ok(())
+}
+
 // -- Project CRUD --
 
 pub async fn create_project(
@@ -142,7 +430,7 @@ pub async fn create_project(
 
 pub async fn list_projects(pool: &SqlitePool) -> Result<Vec<ProjectRow>, sqlx::Error> {
     sqlx::query_as::<_, ProjectRow>(
-        "SELECT id, name, description, path, created_at, updated_at, is_active
+        "SELECT id, name, description, path, created_at, updated_at, is_active, rag_enabled
          FROM projects WHERE is_active = 1 ORDER BY created_at DESC",
     )
     .fetch_all(pool)
@@ -154,7 +442,7 @@ pub async fn get_project(
     id: &str,
 ) -> Result<Option<ProjectRow>, sqlx::Error> {
     sqlx::query_as::<_, ProjectRow>(
-        "SELECT id, name, description, path, created_at, updated_at, is_active
+        "SELECT id, name, description, path, created_at, updated_at, is_active, rag_enabled
          FROM projects WHERE id = ? AND is_active = 1",
     )
     .bind(id)
@@ -170,6 +458,176 @@ pub async fn delete_project(pool: &SqlitePool, id: &str) -> Result<bool, sqlx::E
     Ok(result.rows_affected() > 0)
 }
 
+/// Turn retrieval on or off for an active project; false if there is none.
+pub async fn set_project_rag(pool: &SqlitePool, id: &str, enabled: bool) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE projects SET rag_enabled = ?, updated_at = datetime('now')
+         WHERE id = ? AND is_active = 1",
+    )
+    .bind(enabled)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// -- Project index --
+
+/// One indexed slice of a workspace file.
+#[derive(Debug, Clone, FromRow)]
+pub struct ChunkRow {
+    pub path: String,
+    pub start_line: i64,
+    pub end_line: i64,
+    pub content: String,
+    pub embedding: Vec<u8>,
+}
+
+/// Replace a project's whole index in one transaction.
+pub async fn replace_project_chunks(
+    pool: &SqlitePool,
+    project_id: &str,
+    chunks: &[ChunkRow],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    sqlx::query("DELETE FROM project_chunks WHERE project_id = ?")
+        .bind(project_id)
+        .execute(&mut *tx)
+        .await?;
+    for chunk in chunks {
+        sqlx::query(
+            "INSERT INTO project_chunks (project_id, path, start_line, end_line, content, embedding)
+             VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(project_id)
+        .bind(&chunk.path)
+        .bind(chunk.start_line)
+        .bind(chunk.end_line)
+        .bind(&chunk.content)
+        .bind(&chunk.embedding)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+pub async fn list_project_chunks(pool: &SqlitePool, project_id: &str) -> Result<Vec<ChunkRow>, sqlx::Error> {
+    sqlx::query_as::<_, ChunkRow>(
+        "SELECT path, start_line, end_line, content, embedding
+         FROM project_chunks WHERE project_id = ? ORDER BY id",
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn delete_project_chunks(pool: &SqlitePool, project_id: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM project_chunks WHERE project_id = ?")
+        .bind(project_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected())
+}
+
+// -- Project agents --
+
+/// Create or replace the agent `name` of a project.
+pub async fn put_agent(
+    pool: &SqlitePool,
+    project_id: &str,
+    name: &str,
+    description: &str,
+    prompt: &str,
+    tools: Option<&[String]>,
+    model: Option<&str>,
+) -> Result<AgentRow, sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO project_agents (project_id, name, description, prompt, tools, model)
+         VALUES (?, ?, ?, ?, ?, ?)
+         ON CONFLICT (project_id, name) DO UPDATE SET
+             description = excluded.description, prompt = excluded.prompt,
+             tools = excluded.tools, model = excluded.model, updated_at = datetime('now')",
+    )
+    .bind(project_id)
+    .bind(name)
+    .bind(description)
+    .bind(prompt)
+    .bind(tools.map(sqlx::types::Json))
+    .bind(model)
+    .execute(pool)
+    .await?;
+    get_agent(pool, project_id, name).await?.ok_or(sqlx::Error::RowNotFound)
+}
+
+pub async fn get_agent(
+    pool: &SqlitePool,
+    project_id: &str,
+    name: &str,
+) -> Result<Option<AgentRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT project_id, name, description, prompt, tools, model, created_at, updated_at
+         FROM project_agents WHERE project_id = ? AND name = ?",
+    )
+    .bind(project_id)
+    .bind(name)
+    .fetch_optional(pool)
+    .await
+}
+
+/// A project's agents, by name.
+pub async fn list_agents(pool: &SqlitePool, project_id: &str) -> Result<Vec<AgentRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT project_id, name, description, prompt, tools, model, created_at, updated_at
+         FROM project_agents WHERE project_id = ? ORDER BY name",
+    )
+    .bind(project_id)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn delete_agent(pool: &SqlitePool, project_id: &str, name: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM project_agents WHERE project_id = ? AND name = ?")
+        .bind(project_id)
+        .bind(name)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// -- Project hooks --
+
+/// Set a project's hooks, replacing any it had.
+pub async fn put_hooks(
+    pool: &SqlitePool,
+    project_id: &str,
+    hooks: &serde_json::Value,
+) -> Result<HooksRow, sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO project_hooks (project_id, hooks) VALUES (?, ?)
+         ON CONFLICT (project_id) DO UPDATE SET hooks = excluded.hooks, updated_at = datetime('now')",
+    )
+    .bind(project_id)
+    .bind(hooks.to_string())
+    .execute(pool)
+    .await?;
+    get_hooks(pool, project_id).await?.ok_or(sqlx::Error::RowNotFound)
+}
+
+pub async fn get_hooks(pool: &SqlitePool, project_id: &str) -> Result<Option<HooksRow>, sqlx::Error> {
+    sqlx::query_as("SELECT project_id, hooks, updated_at FROM project_hooks WHERE project_id = ?")
+        .bind(project_id)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn delete_hooks(pool: &SqlitePool, project_id: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM project_hooks WHERE project_id = ?")
+        .bind(project_id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
 // -- Session CRUD --
 
 pub async fn create_session(
@@ -195,6 +653,27 @@ pub async fn create_session(
     get_session(pool, id).await?.ok_or(sqlx::Error::RowNotFound)
 }
 
+/// Record a session started implicitly by a chat request, so its messages
+/// have a parent row. No-op if it already exists; the project link is kept
+/// only when that project is registered. Returns whether it was created.
+pub async fn ensure_session(
+    pool: &SqlitePool,
+    id: &str,
+    project_id: &str,
+    model: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT OR IGNORE INTO sessions (id, project_id, model)
+         VALUES (?, (SELECT id FROM projects WHERE id = ?), ?)",
+    )
+    .bind(id)
+    .bind(project_id)
+    .bind(model)
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
 pub async fn list_sessions(pool: &SqlitePool) -> Result<Vec<SessionRow>, sqlx::Error> {
     sqlx::query_as::<_, SessionRow>(
         "SELECT id, project_id, title, model, system_prompt, created_at, updated_at,
@@ -205,6 +684,18 @@ pub async fn list_sessions(pool: &SqlitePool) -> Result<Vec<SessionRow>, sqlx::E
     .await
 }
 
+/// The `limit` most recently updated active sessions.
+pub async fn recent_sessions(pool: &SqlitePool, limit: i64) -> Result<Vec<SessionRow>, sqlx::Error> {
+    sqlx::query_as::<_, SessionRow>(
+        "SELECT id, project_id, title, model, system_prompt, created_at, updated_at,
+                is_active, total_tokens, total_cost, message_count
+         FROM sessions WHERE is_active = 1 ORDER BY updated_at DESC LIMIT ?",
+    )
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
 pub async fn get_session(
     pool: &SqlitePool,
     id: &str,
@@ -219,6 +710,18 @@ pub async fn get_session(
     .await
 }
 
+/// A session whether or not it is active.
+pub async fn find_session(pool: &SqlitePool, id: &str) -> Result<Option<SessionRow>, sqlx::Error> {
+    sqlx::query_as::<_, SessionRow>(
+        "SELECT id, project_id, title, model, system_prompt, created_at, updated_at,
+                is_active, total_tokens, total_cost, message_count
+         FROM sessions WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+}
+
 pub async fn delete_session(pool: &SqlitePool, id: &str) -> Result<bool, sqlx::Error> {
     let result =
         sqlx::query("UPDATE sessions SET is_active = 0 WHERE id = ? AND is_active = 1")
@@ -250,8 +753,241 @@ pub async fn update_session_metrics(
     Ok(())
 }
 
+/// Mark sessions with no activity for `idle_minutes` inactive.
+pub async fn deactivate_stale_sessions(
+    pool: &SqlitePool,
+    idle_minutes: u64,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE sessions SET is_active = 0
+         WHERE is_active = 1 AND updated_at < datetime('now', ?)",
+    )
+    .bind(format!("-{idle_minutes} minutes"))
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Rows removed by [`erase_session`].
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct ErasureReport {
+    pub sessions: u64,
+    pub messages: u64,
+    pub transcripts: u64,
+    pub recordings: u64,
+    pub tasks: u64,
+    /// Background jobs and cached responses that answered the session.
+    pub jobs: u64,
+    pub cached_responses: u64,
+    /// Uploads (`/v1/files`) the session's messages referenced.
+    pub files: u64,
+}
+
+impl ErasureReport {
+    pub fn is_empty(&self) -> bool {
+        self.sessions
+            + self.messages
+            + self.transcripts
+            + self.recordings
+            + self.tasks
+            + self.jobs
+            + self.cached_responses
+            + self.files
+            == 0
+    }
+
+    pub fn add(&mut self, other: ErasureReport) {
+        self.sessions += other.sessions;
+        self.messages += other.messages;
+        self.transcripts += other.transcripts;
+        self.recordings += other.recordings;
+        self.tasks += other.tasks;
+        self.jobs += other.jobs;
+        self.cached_responses += other.cached_responses;
+        self.files += other.files;
+    }
+}
+
+/// Files kept outside the database for a session, found through the
+/// metadata of its messages, active or not.
+#[derive(Debug, Default, PartialEq)]
+pub struct SessionArtifacts {
+    /// Saved attachments (`images`, `files`).
+    pub attachments: Vec<String>,
+    /// Session IDs the CLI stored the conversation under.
+    pub cli_sessions: Vec<String>,
+    /// Where the contents of the uploads the messages referenced
+    /// (`file_ids`) are stored.
+    pub uploads: Vec<String>,
+}
+
+/// The uploads (`/v1/files`) referenced by a session's messages.
+const SESSION_UPLOADS: &str = "SELECT j.value FROM messages m, json_each(m.message_metadata, '$.file_ids') j
+     WHERE m.session_id = ?";
+
+pub async fn session_artifacts(pool: &SqlitePool, id: &str) -> Result<SessionArtifacts, sqlx::Error> {
+    let attachments = sqlx::query_scalar(
+        "SELECT j.value FROM messages m, json_each(m.message_metadata, '$.images') j WHERE m.session_id = ?
+         UNION
+         SELECT j.value FROM messages m, json_each(m.message_metadata, '$.files') j WHERE m.session_id = ?",
+    )
+    .bind(id)
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+    let cli_sessions = sqlx::query_scalar(
+        "SELECT DISTINCT json_extract(message_metadata, '$.claude_session_id') FROM messages
+         WHERE session_id = ? AND json_extract(message_metadata, '$.claude_session_id') IS NOT NULL",
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await?;
+    let uploads = sqlx::query_scalar(&format!("SELECT path FROM files WHERE id IN ({SESSION_UPLOADS})"))
+        .bind(id)
+        .fetch_all(pool)
+        .await?;
+    Ok(SessionArtifacts { attachments, cli_sessions, uploads })
+}
+
+/// Hard-delete a session, active or not, with its messages, transcript
+/// events, recordings (and their replays), tasks, jobs, cached responses
+/// and the records of the uploads its messages referenced. Deleted
+/// content is overwritten (`secure_delete`) and the WAL checkpointed, so
+/// it doesn't linger in free pages.
+pub async fn erase_session(pool: &SqlitePool, id: &str) -> Result<ErasureReport, sqlx::Error> {
+    let mut conn = pool.acquire().await?;
+    sqlx::query("PRAGMA secure_delete = ON").execute(&mut *conn).await?;
+    let erased = async {
+        let mut tx = sqlx::Connection::begin(&mut *conn).await?;
+        let mut delete = async |sql: &str| -> Result<u64, sqlx::Error> {
+            Ok(sqlx::query(sql).bind(id).execute(&mut *tx).await?.rows_affected())
+        };
+        let files = delete(&format!("DELETE FROM files WHERE id IN ({SESSION_UPLOADS})")).await?;
+        let messages = delete("DELETE FROM messages WHERE session_id = ?").await?;
+        let transcripts = delete("DELETE FROM transcripts WHERE session_id = ?").await?;
+        let replays = delete(
+            "DELETE FROM recordings WHERE replay_of IN (SELECT id FROM recordings WHERE session_id = ?)",
+        )
+        .await?;
+        let recordings = delete("DELETE FROM recordings WHERE session_id = ?").await? + replays;
+        let tasks = delete("DELETE FROM tasks WHERE session_id = ?").await?;
+        let jobs = delete("DELETE FROM jobs WHERE session_id = ?").await?;
+        let cached_responses = delete("DELETE FROM response_cache WHERE session_id = ?").await?;
+        let sessions = delete("DELETE FROM sessions WHERE id = ?").await?;
+        tx.commit().await?;
+        Ok::<_, sqlx::Error>(ErasureReport {
+            sessions,
+            messages,
+            transcripts,
+            recordings,
+            tasks,
+            jobs,
+            cached_responses,
+            files,
+        })
+    }
+    .await;
+    sqlx::query("PRAGMA secure_delete = OFF").execute(&mut *conn).await?;
+    let report = erased?;
+    if !report.is_empty() {
+        // Best effort: readers may hold the WAL; the next checkpoint gets it
+        let _ = sqlx::query("PRAGMA wal_checkpoint(TRUNCATE)").execute(&mut *conn).await;
+    }
+    Ok(report)
+}
+
+/// Sessions of `filter`'s key or user (per the request log) in its time
+/// range, or, with neither, those created in its time range. For
+/// `/admin/purge` and `/admin/export`.
+pub async fn sessions_matching(pool: &SqlitePool, filter: &RequestLogFilter) -> Result<Vec<String>, sqlx::Error> {
+    let by_requester = filter.key_hash.is_some() || filter.user.is_some();
+    let mut qb: QueryBuilder<Sqlite> = if by_requester {
+        QueryBuilder::new("SELECT DISTINCT session_id FROM request_log WHERE session_id IS NOT NULL")
+    } else {
+        QueryBuilder::new("SELECT id FROM sessions WHERE 1 = 1")
+    };
+    if let Some(ref v) = filter.key_hash {
+        qb.push(" AND key_hash = ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.user {
+        qb.push(" AND user_id = ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.since {
+        qb.push(" AND created_at >= ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.until {
+        qb.push(" AND created_at < ").push_bind(v.clone());
+    }
+    qb.build_query_scalar().fetch_all(pool).await
+}
+
+/// Clear the end user and client IP of request log entries matching
+/// `filter`'s key, user and time range; the entries themselves stay for
+/// accounting.
+pub async fn anonymize_request_log(pool: &SqlitePool, filter: &RequestLogFilter) -> Result<u64, sqlx::Error> {
+    let mut qb: QueryBuilder<Sqlite> =
+        QueryBuilder::new("UPDATE request_log SET user_id = NULL, client_ip = NULL WHERE 1 = 1");
+    if let Some(ref v) = filter.key_hash {
+        qb.push(" AND key_hash = ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.user {
+        qb.push(" AND user_id = ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.since {
+        qb.push(" AND created_at >= ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.until {
+        qb.push(" AND created_at < ").push_bind(v.clone());
+    }
+    Ok(qb.build().execute(pool).await?.rows_affected())
+}
+
+/// Rows removed by [`purge_expired`].
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct PurgeReport {
+    pub messages: u64,
+    pub transcripts: u64,
+    pub sessions: u64,
+    pub recordings: u64,
+}
+
+/// Hard-delete messages, transcript events and recordings older than
+/// `days`, then drop sessions left empty that have not been touched since
+/// the same cutoff.
+pub async fn purge_expired(pool: &SqlitePool, days: u64) -> Result<PurgeReport, sqlx::Error> {
+    let cutoff = format!("-{days} days");
+    let mut tx = pool.begin().await?;
+    let messages = sqlx::query("DELETE FROM messages WHERE created_at < datetime('now', ?)")
+        .bind(&cutoff)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    let transcripts = sqlx::query("DELETE FROM transcripts WHERE created_at < datetime('now', ?)")
+        .bind(&cutoff)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    let sessions = sqlx::query(
+        "DELETE FROM sessions
+         WHERE updated_at < datetime('now', ?)
+           AND NOT EXISTS (SELECT 1 FROM messages m WHERE m.session_id = sessions.id)",
+    )
+    .bind(&cutoff)
+    .execute(&mut *tx)
+    .await?
+    .rows_affected();
+    let recordings = sqlx::query("DELETE FROM recordings WHERE created_at < datetime('now', ?)")
+        .bind(&cutoff)
+        .execute(&mut *tx)
+        .await?
+        .rows_affected();
+    tx.commit().await?;
+    Ok(PurgeReport { messages, transcripts, sessions, recordings })
+}
+
 // -- Message CRUD --
 
+#[allow(clippy::too_many_arguments)]
 pub async fn add_message(
     pool: &SqlitePool,
     session_id: &str,
@@ -260,18 +996,1303 @@ pub async fn add_message(
     input_tokens: i64,
     output_tokens: i64,
     cost: f64,
+    metadata: &serde_json::Value,
 ) -> Result<(), sqlx::Error> {
     sqlx::query(
-        "INSERT INTO messages (session_id, role, content, input_tokens, output_tokens, cost)
-         VALUES (?, ?, ?, ?, ?, ?)",
+        "INSERT INTO messages (session_id, role, content, input_tokens, output_tokens, cost,
+                               message_metadata)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
     )
     .bind(session_id)
     .bind(role)
-    .bind(content)
+    .bind(crypto::seal(content))
     .bind(input_tokens)
     .bind(output_tokens)
     .bind(cost)
+    .bind(metadata.to_string())
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// A session's messages, oldest first.
+pub async fn list_messages(
+    pool: &SqlitePool,
+    session_id: &str,
+    limit: i64,
+    offset: i64,
+) -> Result<Vec<MessageRow>, sqlx::Error> {
+    let rows: Vec<MessageRow> = sqlx::query_as(
+        "SELECT id, session_id, role, content, message_metadata, created_at,
+                input_tokens, output_tokens, cost
+         FROM messages WHERE session_id = ? ORDER BY created_at, id LIMIT ? OFFSET ?",
+    )
+    .bind(session_id)
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|row| MessageRow { content: crypto::open(row.content), ..row }).collect())
+}
+
+/// The CLI session ID recorded with the session's latest message, the one
+/// to `--resume` to continue the conversation in the CLI.
+pub async fn last_claude_session_id(
+    pool: &SqlitePool,
+    session_id: &str,
+) -> Result<Option<String>, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT json_extract(message_metadata, '$.claude_session_id') FROM messages
+         WHERE session_id = ? AND json_extract(message_metadata, '$.claude_session_id') IS NOT NULL
+         ORDER BY created_at DESC, id DESC LIMIT 1",
+    )
+    .bind(session_id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// A message matching a [`search_messages`] query.
+#[derive(Debug, FromRow, Serialize)]
+pub struct MessageMatch {
+    pub session_id: String,
+    pub project_id: Option<String>,
+    pub title: String,
+    pub message_id: i64,
+    pub role: String,
+    pub content: String,
+    pub created_at: String,
+}
+
+/// Messages of active sessions containing `query` (case-insensitive for
+/// ASCII), newest first, optionally in one project.
+pub async fn search_messages(
+    pool: &SqlitePool,
+    query: &str,
+    project_id: Option<&str>,
+    limit: i64,
+) -> Result<Vec<MessageMatch>, sqlx::Error> {
+    if crypto::enabled() {
+        return search_sealed_messages(pool, query, project_id, limit).await;
+    }
+    let pattern = format!("%{}%", query.replace('\\', "\\\\").replace('%', "\\%").replace('_', "\\_"));
+    sqlx::query_as(
+        "SELECT m.session_id, s.project_id, s.title, m.id AS message_id, m.role, m.content, m.created_at
+         FROM messages m JOIN sessions s ON s.id = m.session_id
+         WHERE s.is_active = 1 AND m.content LIKE ? ESCAPE '\\' AND (? IS NULL OR s.project_id = ?)
+         ORDER BY m.created_at DESC, m.id DESC LIMIT ?",
+    )
+    .bind(pattern)
+    .bind(project_id)
+    .bind(project_id)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+/// [`search_messages`] over encrypted content, which SQLite can't match:
+/// messages are decrypted and matched here, newest first, until `limit`
+/// are found.
+async fn search_sealed_messages(
+    pool: &SqlitePool,
+    query: &str,
+    project_id: Option<&str>,
+    limit: i64,
+) -> Result<Vec<MessageMatch>, sqlx::Error> {
+    let needle = query.to_ascii_lowercase();
+    let mut rows = sqlx::query_as::<_, MessageMatch>(
+        "SELECT m.session_id, s.project_id, s.title, m.id AS message_id, m.role, m.content, m.created_at
+         FROM messages m JOIN sessions s ON s.id = m.session_id
+         WHERE s.is_active = 1 AND (? IS NULL OR s.project_id = ?)
+         ORDER BY m.created_at DESC, m.id DESC",
+    )
+    .bind(project_id)
+    .bind(project_id)
+    .fetch(pool);
+    let mut matches = Vec::new();
+    while let Some(row) = rows.try_next().await? {
+        let content = crypto::open(row.content);
+        if content.to_ascii_lowercase().contains(&needle) {
+            matches.push(MessageMatch { content, ..row });
+            if matches.len() as i64 >= limit {
+                break;
+            }
+        }
+    }
+    Ok(matches)
+}
+
+// -- Message index --
+
+/// A message with its embedding, for semantic search.
+#[derive(Debug, FromRow)]
+pub struct EmbeddedMessage {
+    #[sqlx(flatten)]
+    pub message: MessageMatch,
+    pub embedding: Vec<u8>,
+}
+
+/// The highest message ID, 0 with no messages.
+pub async fn max_message_id(pool: &SqlitePool) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM messages").fetch_one(pool).await
+}
+
+/// Up to `limit` messages with IDs in `(after, upto]` and no embedding,
+/// oldest first, as ID and content.
+pub async fn unindexed_messages(
+    pool: &SqlitePool,
+    after: i64,
+    upto: i64,
+    limit: i64,
+) -> Result<Vec<(i64, String)>, sqlx::Error> {
+    let rows: Vec<(i64, String)> = sqlx::query_as(
+        "SELECT m.id, m.content FROM messages m
+         WHERE m.id > ? AND m.id <= ?
+           AND NOT EXISTS (SELECT 1 FROM message_embeddings e WHERE e.message_id = m.id)
+         ORDER BY m.id LIMIT ?",
+    )
+    .bind(after)
+    .bind(upto)
+    .bind(limit)
+    .fetch_all(pool)
+    .await?;
+    Ok(rows.into_iter().map(|(id, content)| (id, crypto::open(content))).collect())
+}
+
+/// Store message embeddings in one transaction. Messages deleted meanwhile
+/// are skipped.
+pub async fn store_message_embeddings(pool: &SqlitePool, embeddings: &[(i64, Vec<u8>)]) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for (id, embedding) in embeddings {
+        sqlx::query(
+            "INSERT OR REPLACE INTO message_embeddings (message_id, embedding)
+             SELECT id, ? FROM messages WHERE id = ?",
+        )
+        .bind(embedding)
+        .bind(id)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// How many messages after `after` there are, and when the oldest was stored.
+pub async fn messages_after(pool: &SqlitePool, after: i64) -> Result<(i64, Option<String>), sqlx::Error> {
+    sqlx::query_as("SELECT COUNT(*), MIN(created_at) FROM messages WHERE id > ?")
+        .bind(after)
+        .fetch_one(pool)
+        .await
+}
+
+/// The embedded messages of active sessions, optionally in one project.
+pub async fn list_embedded_messages(
+    pool: &SqlitePool,
+    project_id: Option<&str>,
+) -> Result<Vec<EmbeddedMessage>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT m.session_id, s.project_id, s.title, m.id AS message_id, m.role, m.content, m.created_at, e.embedding
+         FROM message_embeddings e
+         JOIN messages m ON m.id = e.message_id
+         JOIN sessions s ON s.id = m.session_id
+         WHERE s.is_active = 1 AND (? IS NULL OR s.project_id = ?)",
+    )
+    .bind(project_id)
+    .bind(project_id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Fold messages `ids` (oldest first) into one `summary` message: the
+/// oldest row is rewritten in place, so it keeps its position, and the rest
+/// are deleted. The summary run's cost is added to the session.
+pub async fn replace_with_summary(
+    pool: &SqlitePool,
+    session_id: &str,
+    ids: &[i64],
+    summary: &str,
+    cost: f64,
+    metadata: &serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    let Some((&first, rest)) = ids.split_first() else {
+        return Ok(());
+    };
+    let mut tx = pool.begin().await?;
+    sqlx::query(
+        "UPDATE messages
+         SET role = 'summary', content = ?, message_metadata = ?,
+             input_tokens = 0, output_tokens = 0, cost = ?
+         WHERE id = ? AND session_id = ?",
+    )
+    .bind(crypto::seal(summary))
+    .bind(metadata.to_string())
+    .bind(cost)
+    .bind(first)
+    .bind(session_id)
+    .execute(&mut *tx)
+    .await?;
+    for id in rest {
+        sqlx::query("DELETE FROM messages WHERE id = ? AND session_id = ?")
+            .bind(id)
+            .bind(session_id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    sqlx::query(
+        "UPDATE sessions
+         SET total_cost = total_cost + ?,
+             message_count = MAX(message_count - ?, 0),
+             updated_at = datetime('now')
+         WHERE id = ?",
+    )
+    .bind(cost)
+    .bind(rest.len() as i64)
+    .bind(session_id)
+    .execute(&mut *tx)
+    .await?;
+    tx.commit().await
+}
+
+// -- Files --
+
+pub async fn create_file(
+    pool: &SqlitePool,
+    id: &str,
+    filename: &str,
+    purpose: &str,
+    bytes: i64,
+    path: &str,
+    key_hash: Option<&str>,
+) -> Result<FileRow, sqlx::Error> {
+    sqlx::query("INSERT INTO files (id, filename, purpose, bytes, path, key_hash) VALUES (?, ?, ?, ?, ?, ?)")
+        .bind(id)
+        .bind(filename)
+        .bind(purpose)
+        .bind(bytes)
+        .bind(path)
+        .bind(key_hash)
+        .execute(pool)
+        .await?;
+    get_file(pool, id, key_hash).await?.ok_or(sqlx::Error::RowNotFound)
+}
+
+/// A file uploaded by the key with `key_hash` (`None`: without a key).
+pub async fn get_file(pool: &SqlitePool, id: &str, key_hash: Option<&str>) -> Result<Option<FileRow>, sqlx::Error> {
+    sqlx::query_as("SELECT id, filename, purpose, bytes, path, created_at FROM files WHERE id = ? AND key_hash IS ?")
+        .bind(id)
+        .bind(key_hash)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Files uploaded by the key with `key_hash`, newest first, optionally
+/// only those with `purpose`.
+pub async fn list_files(
+    pool: &SqlitePool,
+    key_hash: Option<&str>,
+    purpose: Option<&str>,
+    limit: i64,
+) -> Result<Vec<FileRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, filename, purpose, bytes, path, created_at FROM files
+         WHERE key_hash IS ? AND (? IS NULL OR purpose = ?)
+         ORDER BY created_at DESC, rowid DESC LIMIT ?",
+    )
+    .bind(key_hash)
+    .bind(purpose)
+    .bind(purpose)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn delete_file(pool: &SqlitePool, id: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM files WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// -- Prompts --
+
+/// Insert version 1 of a new prompt.
+pub async fn create_prompt(
+    pool: &SqlitePool,
+    id: &str,
+    name: &str,
+    description: &str,
+    template: &str,
+) -> Result<PromptRow, sqlx::Error> {
+    sqlx::query("INSERT INTO prompts (id, version, name, description, template) VALUES (?, 1, ?, ?, ?)")
+        .bind(id)
+        .bind(name)
+        .bind(description)
+        .bind(template)
+        .execute(pool)
+        .await?;
+    get_prompt(pool, id, Some(1)).await?.ok_or(sqlx::Error::RowNotFound)
+}
+
+/// Insert the next version of prompt `id`, keeping its name. `None` if the
+/// prompt doesn't exist; the description carries over unless given.
+pub async fn add_prompt_version(
+    pool: &SqlitePool,
+    id: &str,
+    description: Option<&str>,
+    template: &str,
+) -> Result<Option<PromptRow>, sqlx::Error> {
+    let result = sqlx::query(
+        "INSERT INTO prompts (id, version, name, description, template)
+         SELECT id, version + 1, name, COALESCE(?, description), ? FROM prompts
+         WHERE id = ? ORDER BY version DESC LIMIT 1",
+    )
+    .bind(description)
+    .bind(template)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    if result.rows_affected() == 0 {
+        return Ok(None);
+    }
+    get_prompt(pool, id, None).await
+}
+
+/// Version `version` of a prompt, or its latest. `id_or_name` matches the
+/// ID or the name.
+pub async fn get_prompt(
+    pool: &SqlitePool,
+    id_or_name: &str,
+    version: Option<i64>,
+) -> Result<Option<PromptRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, version, name, description, template, created_at FROM prompts
+         WHERE (id = ? OR name = ?) AND (? IS NULL OR version = ?)
+         ORDER BY version DESC LIMIT 1",
+    )
+    .bind(id_or_name)
+    .bind(id_or_name)
+    .bind(version)
+    .bind(version)
+    .fetch_optional(pool)
+    .await
+}
+
+/// The latest version of every prompt, by name.
+pub async fn list_prompts(pool: &SqlitePool) -> Result<Vec<PromptRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, version, name, description, template, created_at FROM prompts p
+         WHERE version = (SELECT MAX(version) FROM prompts WHERE id = p.id)
+         ORDER BY name",
+    )
+    .fetch_all(pool)
+    .await
+}
+
+/// Every version of prompt `id`, newest first.
+pub async fn list_prompt_versions(pool: &SqlitePool, id: &str) -> Result<Vec<PromptRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, version, name, description, template, created_at FROM prompts
+         WHERE id = ? ORDER BY version DESC",
+    )
+    .bind(id)
+    .fetch_all(pool)
+    .await
+}
+
+/// Delete a prompt with all its versions.
+pub async fn delete_prompt(pool: &SqlitePool, id: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM prompts WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+// -- Jobs --
+
+pub async fn create_job(pool: &SqlitePool, id: &str, model: &str) -> Result<JobRow, sqlx::Error> {
+    sqlx::query("INSERT INTO jobs (id, model) VALUES (?, ?)")
+        .bind(id)
+        .bind(model)
+        .execute(pool)
+        .await?;
+    get_job(pool, id).await?.ok_or(sqlx::Error::RowNotFound)
+}
+
+pub async fn get_job(pool: &SqlitePool, id: &str) -> Result<Option<JobRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, model, status, response, created_at, started_at, completed_at
+         FROM jobs WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Jobs, newest first, optionally only those in `status`.
+pub async fn list_jobs(
+    pool: &SqlitePool,
+    status: Option<&str>,
+    limit: i64,
+) -> Result<Vec<JobRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, model, status, response, created_at, started_at, completed_at
+         FROM jobs WHERE ? IS NULL OR status = ?
+         ORDER BY created_at DESC, rowid DESC LIMIT ?",
+    )
+    .bind(status)
+    .bind(status)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn start_job(pool: &SqlitePool, id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE jobs SET status = 'running', started_at = datetime('now') WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn finish_job(
+    pool: &SqlitePool,
+    id: &str,
+    succeeded: bool,
+    response: &serde_json::Value,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE jobs SET status = ?, response = ?, session_id = ?, completed_at = datetime('now') WHERE id = ?",
+    )
+    .bind(if succeeded { "succeeded" } else { "failed" })
+    .bind(crypto::seal(&response.to_string()))
+    .bind(response.get("session_id").and_then(|s| s.as_str()))
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Fail jobs left queued or running by a previous process; their tasks
+/// died with it. Returns how many were marked.
+pub async fn fail_interrupted_jobs(pool: &SqlitePool) -> Result<u64, sqlx::Error> {
+    let error = serde_json::json!({
+        "error": {
+            "message": "Job was interrupted by a server restart",
+            "type": "service_error",
+            "code": "job_interrupted",
+        }
+    });
+    let result = sqlx::query(
+        "UPDATE jobs SET status = 'failed', response = ?, completed_at = datetime('now')
+         WHERE status IN ('queued', 'running')",
+    )
+    .bind(crypto::seal(&error.to_string()))
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+// -- Tasks --
+
+const TASK_COLUMNS: &str = "id, project_id, instruction, model, base_commit, session_id, status, result, diff,
+     created_at, started_at, completed_at";
+
+pub async fn create_task(
+    pool: &SqlitePool,
+    id: &str,
+    project_id: &str,
+    instruction: &str,
+    model: &str,
+    base_commit: &str,
+    session_id: &str,
+) -> Result<TaskRow, sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO tasks (id, project_id, instruction, model, base_commit, session_id)
+         VALUES (?, ?, ?, ?, ?, ?)",
+    )
+    .bind(id)
+    .bind(project_id)
+    .bind(instruction)
+    .bind(model)
+    .bind(base_commit)
+    .bind(session_id)
+    .execute(pool)
+    .await?;
+    get_task(pool, id).await?.ok_or(sqlx::Error::RowNotFound)
+}
+
+pub async fn get_task(pool: &SqlitePool, id: &str) -> Result<Option<TaskRow>, sqlx::Error> {
+    sqlx::query_as(&format!("SELECT {TASK_COLUMNS} FROM tasks WHERE id = ?"))
+        .bind(id)
+        .fetch_optional(pool)
+        .await
+}
+
+/// Tasks, newest first, optionally only those in `status`.
+pub async fn list_tasks(
+    pool: &SqlitePool,
+    status: Option<&str>,
+    limit: i64,
+) -> Result<Vec<TaskRow>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {TASK_COLUMNS} FROM tasks WHERE ? IS NULL OR status = ?
+         ORDER BY created_at DESC, rowid DESC LIMIT ?"
+    ))
+    .bind(status)
+    .bind(status)
+    .bind(limit)
+    .fetch_all(pool)
+    .await
+}
+
+pub async fn start_task(pool: &SqlitePool, id: &str) -> Result<(), sqlx::Error> {
+    sqlx::query("UPDATE tasks SET status = 'running', started_at = datetime('now') WHERE id = ?")
+        .bind(id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+pub async fn finish_task(
+    pool: &SqlitePool,
+    id: &str,
+    succeeded: bool,
+    result: &serde_json::Value,
+    diff: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE tasks SET status = ?, result = ?, diff = ?, completed_at = datetime('now') WHERE id = ?",
+    )
+    .bind(if succeeded { "succeeded" } else { "failed" })
+    .bind(result.to_string())
+    .bind(diff)
+    .bind(id)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+/// Fail tasks left queued or running by a previous process, returning
+/// them; their worktrees may still be on disk.
+pub async fn fail_interrupted_tasks(pool: &SqlitePool) -> Result<Vec<TaskRow>, sqlx::Error> {
+    let interrupted: Vec<TaskRow> =
+        sqlx::query_as(&format!("SELECT {TASK_COLUMNS} FROM tasks WHERE status IN ('queued', 'running')"))
+            .fetch_all(pool)
+            .await?;
+    let error = serde_json::json!({
+        "error": {
+            "message": "Task was interrupted by a server restart",
+            "type": "service_error",
+            "code": "task_interrupted",
+        }
+    });
+    for task in &interrupted {
+        finish_task(pool, &task.id, false, &error, None).await?;
+    }
+    Ok(interrupted)
+}
+
+// -- Transcripts --
+
+/// Append raw CLI events to a session's transcript in one transaction.
+pub async fn add_transcript_events(
+    pool: &SqlitePool,
+    session_id: &str,
+    events: &[serde_json::Value],
+) -> Result<(), sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    for event in events {
+        sqlx::query("INSERT INTO transcripts (session_id, event) VALUES (?, ?)")
+            .bind(session_id)
+            .bind(crypto::seal(&event.to_string()))
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await
+}
+
+/// A session's raw events as JSONL lines, in arrival order.
+pub async fn get_transcript(pool: &SqlitePool, session_id: &str) -> Result<Vec<String>, sqlx::Error> {
+    let events: Vec<String> = sqlx::query_scalar("SELECT event FROM transcripts WHERE session_id = ? ORDER BY id")
+        .bind(session_id)
+        .fetch_all(pool)
+        .await?;
+    Ok(events.into_iter().map(crypto::open).collect())
+}
+
+/// Columns sealed when encryption is on, with how `db encrypt` reports them.
+const SEALED_COLUMNS: &[(&str, &str, &str)] = &[
+    ("messages", "content", "messages"),
+    ("transcripts", "event", "transcript events"),
+    ("jobs", "response", "job responses"),
+    ("response_cache", "response", "cached responses"),
+    ("recordings", "request", "recorded requests"),
+    ("recordings", "response", "recorded responses"),
+];
+
+/// Encrypt conversation content stored in plaintext, in batches. Returns
+/// how many values of each kind were encrypted. Needs [`crypto::init`] to
+/// have loaded a key.
+pub async fn seal_plaintext(pool: &SqlitePool) -> Result<Vec<(&'static str, u64)>, sqlx::Error> {
+    const BATCH: i64 = 500;
+    if !crypto::enabled() {
+        return Ok(Vec::new());
+    }
+    let mut counts = Vec::new();
+    for &(table, column, label) in SEALED_COLUMNS {
+        let mut count = 0u64;
+        let mut after = 0i64;
+        loop {
+            let rows: Vec<(i64, String)> = sqlx::query_as(&format!(
+                "SELECT rowid, {column} FROM {table} WHERE rowid > ? AND {column} NOT LIKE '{}%' ORDER BY rowid LIMIT ?",
+                crypto::PREFIX
+            ))
+            .bind(after)
+            .bind(BATCH)
+            .fetch_all(pool)
+            .await?;
+            let Some(&(last, _)) = rows.last() else { break };
+            let mut tx = pool.begin().await?;
+            for (id, value) in &rows {
+                sqlx::query(&format!("UPDATE {table} SET {column} = ? WHERE rowid = ?"))
+                    .bind(crypto::seal(value))
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+            tx.commit().await?;
+            count += rows.len() as u64;
+            after = last;
+        }
+        counts.push((label, count));
+    }
+    Ok(counts)
+}
+
+// -- Request log --
+
+pub async fn insert_request_log(pool: &SqlitePool, entry: &AuditEntry) -> Result<(), sqlx::Error> {
+    let process = entry.process.as_ref();
+    sqlx::query(
+        "INSERT INTO request_log (key_hash, user_id, client_ip, method, route, model, session_id,
+                                  prompt_tokens, completion_tokens, cost, latency_ms, status,
+                                  spawn_ms, ttft_ms, process_ms, exit_code, peak_rss_kb, guardrail)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(&entry.key_hash)
+    .bind(&entry.user)
+    .bind(&entry.client_ip)
+    .bind(&entry.method)
+    .bind(&entry.route)
+    .bind(&entry.model)
+    .bind(&entry.session_id)
+    .bind(entry.prompt_tokens)
+    .bind(entry.completion_tokens)
+    .bind(entry.cost)
+    .bind(entry.latency_ms)
+    .bind(entry.status as i64)
+    .bind(process.and_then(|p| p.spawn_ms).map(|v| v as i64))
+    .bind(process.and_then(|p| p.ttft_ms).map(|v| v as i64))
+    .bind(process.map(|p| p.duration_ms as i64))
+    .bind(process.and_then(|p| p.exit_code))
+    .bind(process.and_then(|p| p.peak_rss_kb).map(|v| v as i64))
+    .bind(&entry.guardrail)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// -- Response cache --
+
+/// A cached response younger than `ttl_secs`, with its age in seconds.
+pub async fn get_cached_response(
+    pool: &SqlitePool,
+    key: &str,
+    ttl_secs: u64,
+) -> Result<Option<(String, i64)>, sqlx::Error> {
+    let row: Option<(String, i64)> = sqlx::query_as(
+        "SELECT response, CAST(strftime('%s', 'now') - strftime('%s', created_at) AS INTEGER)
+         FROM response_cache WHERE key = ? AND created_at > datetime('now', ?)",
+    )
+    .bind(key)
+    .bind(format!("-{ttl_secs} seconds"))
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|(response, age)| (crypto::open(response), age)))
+}
+
+/// Store a response to `session_id`, dropping entries older than
+/// `ttl_secs`.
+pub async fn put_cached_response(
+    pool: &SqlitePool,
+    key: &str,
+    response: &str,
+    session_id: Option<&str>,
+    ttl_secs: u64,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM response_cache WHERE created_at <= datetime('now', ?)")
+        .bind(format!("-{ttl_secs} seconds"))
+        .execute(pool)
+        .await?;
+    sqlx::query("INSERT OR REPLACE INTO response_cache (key, response, session_id) VALUES (?, ?, ?)")
+        .bind(key)
+        .bind(crypto::seal(response))
+        .bind(session_id)
+        .execute(pool)
+        .await?;
+    Ok(())
+}
+
+/// Filters for [`list_request_log`]; `None` fields are not applied.
+#[derive(Debug, Default, Clone)]
+pub struct RequestLogFilter {
+    pub key_hash: Option<String>,
+    pub user: Option<String>,
+    pub route: Option<String>,
+    pub model: Option<String>,
+    pub session_id: Option<String>,
+    pub status: Option<i64>,
+    pub since: Option<String>,
+    pub until: Option<String>,
+    pub limit: i64,
+    pub offset: i64,
+}
+
+pub async fn list_request_log(
+    pool: &SqlitePool,
+    filter: &RequestLogFilter,
+) -> Result<Vec<RequestLogRow>, sqlx::Error> {
+    let mut qb: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "SELECT id, created_at, key_hash, user_id, client_ip, method, route, model, session_id,
+                prompt_tokens, completion_tokens, cost, latency_ms, status,
+                spawn_ms, ttft_ms, process_ms, exit_code, peak_rss_kb, guardrail
+         FROM request_log WHERE 1 = 1",
+    );
+    if let Some(ref v) = filter.key_hash {
+        qb.push(" AND key_hash = ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.user {
+        qb.push(" AND user_id = ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.route {
+        qb.push(" AND route = ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.model {
+        qb.push(" AND model = ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.session_id {
+        qb.push(" AND session_id = ").push_bind(v.clone());
+    }
+    if let Some(v) = filter.status {
+        qb.push(" AND status = ").push_bind(v);
+    }
+    if let Some(ref v) = filter.since {
+        qb.push(" AND created_at >= ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.until {
+        qb.push(" AND created_at < ").push_bind(v.clone());
+    }
+    qb.push(" ORDER BY id DESC LIMIT ")
+        .push_bind(filter.limit)
+        .push(" OFFSET ")
+        .push_bind(filter.offset);
+
+    qb.build_query_as::<RequestLogRow>().fetch_all(pool).await
+}
+
+/// Requests, tokens and cost of one end user under one key.
+#[derive(Debug, FromRow, Serialize)]
+pub struct UsageRow {
+    pub key_hash: Option<String>,
+    /// `None` for requests without a `user`.
+    pub user: Option<String>,
+    pub requests: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost: f64,
+}
+
+/// Usage per key and user from the request log, most expensive first.
+/// Only `key_hash`, `user`, `since` and `until` of the filter apply.
+pub async fn usage_by_user(
+    pool: &SqlitePool,
+    filter: &RequestLogFilter,
+) -> Result<Vec<UsageRow>, sqlx::Error> {
+    let mut qb: QueryBuilder<Sqlite> = QueryBuilder::new(
+        "SELECT key_hash, user_id AS user, COUNT(*) AS requests,
+                COALESCE(SUM(prompt_tokens), 0) AS prompt_tokens,
+                COALESCE(SUM(completion_tokens), 0) AS completion_tokens,
+                COALESCE(SUM(cost), 0.0) AS cost
+         FROM request_log WHERE 1 = 1",
+    );
+    if let Some(ref v) = filter.key_hash {
+        qb.push(" AND key_hash = ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.user {
+        qb.push(" AND user_id = ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.since {
+        qb.push(" AND created_at >= ").push_bind(v.clone());
+    }
+    if let Some(ref v) = filter.until {
+        qb.push(" AND created_at < ").push_bind(v.clone());
+    }
+    qb.push(" GROUP BY key_hash, user_id ORDER BY cost DESC LIMIT ")
+        .push_bind(filter.limit)
+        .push(" OFFSET ")
+        .push_bind(filter.offset);
+    qb.build_query_as::<UsageRow>().fetch_all(pool).await
+}
+
+/// Requests, failures, tokens and cost of one UTC day.
+#[derive(Debug, FromRow, Serialize)]
+pub struct DailyUsageRow {
+    pub day: String,
+    pub requests: i64,
+    /// Requests answered with a 4xx or 5xx status.
+    pub errors: i64,
+    pub prompt_tokens: i64,
+    pub completion_tokens: i64,
+    pub cost: f64,
+}
+
+/// Usage per UTC day from the request log, for the days since `since`
+/// (`YYYY-MM-DD`) that had requests, oldest first.
+pub async fn daily_usage(pool: &SqlitePool, since: &str) -> Result<Vec<DailyUsageRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT date(created_at) AS day, COUNT(*) AS requests,
+                COALESCE(SUM(status >= 400), 0) AS errors,
+                COALESCE(SUM(prompt_tokens), 0) AS prompt_tokens,
+                COALESCE(SUM(completion_tokens), 0) AS completion_tokens,
+                COALESCE(SUM(cost), 0.0) AS cost
+         FROM request_log WHERE created_at >= ?
+         GROUP BY day ORDER BY day",
+    )
+    .bind(since)
+    .fetch_all(pool)
+    .await
+}
+
+/// What an end user under a key has spent since the start of the UTC day.
+pub async fn user_spend_today(pool: &SqlitePool, key_hash: Option<&str>, user: &str) -> Result<f64, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT COALESCE(SUM(cost), 0.0) FROM request_log
+         WHERE user_id = ? AND key_hash IS ? AND created_at >= date('now')",
+    )
+    .bind(user)
+    .bind(key_hash)
+    .fetch_one(pool)
+    .await
+}
+
+/// Add `cost` to today's spend.
+pub async fn add_spend(pool: &SqlitePool, cost: f64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO spend (day, cost) VALUES (date('now'), ?)
+         ON CONFLICT (day) DO UPDATE SET cost = cost + excluded.cost",
+    )
+    .bind(cost)
     .execute(pool)
     .await?;
     Ok(())
 }
+
+/// Spend of today and of this month (UTC).
+pub async fn spend_totals(pool: &SqlitePool) -> Result<(f64, f64), sqlx::Error> {
+    sqlx::query_as(
+        "SELECT COALESCE(SUM(CASE WHEN day = date('now') THEN cost END), 0.0),
+                COALESCE(SUM(cost), 0.0)
+         FROM spend WHERE day >= date('now', 'start of month')",
+    )
+    .fetch_one(pool)
+    .await
+}
+
+#[allow(clippy::too_many_arguments)]
+pub async fn save_recording(
+    pool: &SqlitePool,
+    id: &str,
+    session_id: Option<&str>,
+    model: &str,
+    request: &serde_json::Value,
+    invocation: Option<&serde_json::Value>,
+    response: &serde_json::Value,
+    replay_of: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO recordings (id, session_id, model, request, invocation, response, replay_of)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(id)
+    .bind(session_id)
+    .bind(model)
+    .bind(crypto::seal(&request.to_string()))
+    .bind(invocation.map(|v| v.to_string()))
+    .bind(crypto::seal(&response.to_string()))
+    .bind(replay_of)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_recording(pool: &SqlitePool, id: &str) -> Result<Option<RecordingRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, session_id, model, request, invocation, response, replay_of, created_at
+         FROM recordings WHERE id = ?",
+    )
+    .bind(id)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Recordings, newest first.
+pub async fn list_recordings(pool: &SqlitePool, limit: i64, offset: i64) -> Result<Vec<RecordingRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT id, session_id, model, request, invocation, response, replay_of, created_at
+         FROM recordings ORDER BY created_at DESC, rowid DESC LIMIT ? OFFSET ?",
+    )
+    .bind(limit)
+    .bind(offset)
+    .fetch_all(pool)
+    .await
+}
+
+const KEY_QUOTA_COLUMNS: &str = "q.key_hash, q.monthly_tokens, q.updated_at,
+     COALESCE((SELECT tokens FROM key_token_usage u
+               WHERE u.key_hash = q.key_hash AND u.month = strftime('%Y-%m', 'now')), 0) AS used_tokens";
+
+pub async fn put_key_quota(pool: &SqlitePool, key_hash: &str, monthly_tokens: i64) -> Result<KeyQuotaRow, sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO key_quotas (key_hash, monthly_tokens) VALUES (?, ?)
+         ON CONFLICT (key_hash) DO UPDATE SET monthly_tokens = excluded.monthly_tokens, updated_at = datetime('now')",
+    )
+    .bind(key_hash)
+    .bind(monthly_tokens)
+    .execute(pool)
+    .await?;
+    get_key_quota(pool, key_hash).await?.ok_or(sqlx::Error::RowNotFound)
+}
+
+pub async fn get_key_quota(pool: &SqlitePool, key_hash: &str) -> Result<Option<KeyQuotaRow>, sqlx::Error> {
+    sqlx::query_as(&format!("SELECT {KEY_QUOTA_COLUMNS} FROM key_quotas q WHERE q.key_hash = ?"))
+        .bind(key_hash)
+        .fetch_optional(pool)
+        .await
+}
+
+pub async fn list_key_quotas(pool: &SqlitePool) -> Result<Vec<KeyQuotaRow>, sqlx::Error> {
+    sqlx::query_as(&format!("SELECT {KEY_QUOTA_COLUMNS} FROM key_quotas q ORDER BY q.key_hash"))
+        .fetch_all(pool)
+        .await
+}
+
+pub async fn delete_key_quota(pool: &SqlitePool, key_hash: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM key_quotas WHERE key_hash = ?")
+        .bind(key_hash)
+        .execute(pool)
+        .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Tokens a key used this month.
+pub async fn key_tokens_this_month(pool: &SqlitePool, key_hash: &str) -> Result<i64, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT COALESCE(SUM(tokens), 0) FROM key_token_usage
+         WHERE key_hash = ? AND month = strftime('%Y-%m', 'now')",
+    )
+    .bind(key_hash)
+    .fetch_one(pool)
+    .await
+}
+
+/// Add `tokens` to a key's use this month.
+pub async fn add_key_tokens(pool: &SqlitePool, key_hash: &str, tokens: i64) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO key_token_usage (key_hash, month, tokens) VALUES (?, strftime('%Y-%m', 'now'), ?)
+         ON CONFLICT (key_hash, month) DO UPDATE SET tokens = tokens + excluded.tokens",
+    )
+    .bind(key_hash)
+    .bind(tokens)
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_migrations_adopt_unversioned_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite:{}", dir.path().join("t.db").display());
+
+        // A request_log from before the process metric columns existed
+        let pool = SqlitePool::connect_with(SqliteConnectOptions::from_str(&url).unwrap().create_if_missing(true))
+            .await
+            .unwrap();
+        sqlx::query("CREATE TABLE request_log (id INTEGER PRIMARY KEY, method TEXT NOT NULL, route TEXT NOT NULL)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        pool.close().await;
+
+        for _ in 0..2 {
+            let pool = init_db(&url).await.unwrap();
+            let (version,): (i64,) = sqlx::query_as("SELECT version FROM schema_version")
+                .fetch_one(&pool)
+                .await
+                .unwrap();
+            assert_eq!(version, MIGRATOR.iter().map(|m| m.version).max().unwrap());
+            sqlx::query("SELECT peak_rss_kb FROM request_log").fetch_all(&pool).await.unwrap();
+            pool.close().await;
+        }
+    }
+
+    #[tokio::test]
+    async fn test_purge_expired() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = init_db(&format!("sqlite:{}", dir.path().join("t.db").display())).await.unwrap();
+        for (id, age) in [("old", "-40 days"), ("new", "-1 days")] {
+            sqlx::query("INSERT INTO sessions (id, model, updated_at) VALUES (?, 'm', datetime('now', ?))")
+                .bind(id)
+                .bind(age)
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO messages (session_id, role, created_at) VALUES (?, 'user', datetime('now', ?))")
+                .bind(id)
+                .bind(age)
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO transcripts (session_id, event, created_at) VALUES (?, '{}', datetime('now', ?))")
+                .bind(id)
+                .bind(age)
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO recordings (id, model, request, created_at) VALUES (?, 'm', '{}', datetime('now', ?))")
+                .bind(id)
+                .bind(age)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        let report = purge_expired(&pool, 30).await.unwrap();
+        assert_eq!((report.messages, report.transcripts, report.sessions, report.recordings), (1, 1, 1, 1));
+        assert!(get_session(&pool, "old").await.unwrap().is_none());
+        assert_eq!(list_messages(&pool, "new", 10, 0).await.unwrap().len(), 1);
+        assert_eq!(get_transcript(&pool, "new").await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_files_belong_to_their_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = init_db(&format!("sqlite:{}", dir.path().join("t.db").display())).await.unwrap();
+        create_file(&pool, "file-a", "a.txt", "user_data", 1, "/f/a", Some("key-a")).await.unwrap();
+        create_file(&pool, "file-n", "n.txt", "user_data", 1, "/f/n", None).await.unwrap();
+
+        assert!(get_file(&pool, "file-a", Some("key-a")).await.unwrap().is_some());
+        assert!(get_file(&pool, "file-a", Some("key-b")).await.unwrap().is_none());
+        assert!(get_file(&pool, "file-a", None).await.unwrap().is_none());
+        let ids = |files: Vec<FileRow>| files.into_iter().map(|f| f.id).collect::<Vec<_>>();
+        assert_eq!(ids(list_files(&pool, Some("key-a"), None, 10).await.unwrap()), ["file-a"]);
+        assert_eq!(ids(list_files(&pool, None, None, 10).await.unwrap()), ["file-n"]);
+    }
+
+    #[tokio::test]
+    async fn test_search_messages() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = init_db(&format!("sqlite:{}", dir.path().join("t.db").display())).await.unwrap();
+        for (session, project, content) in [("s1", "a", "Deploy with 100% rollout"), ("s2", "b", "deploy_script failed")] {
+            sqlx::query("INSERT INTO projects (id, name) VALUES (?1, ?1)")
+                .bind(project)
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO sessions (id, project_id, model) VALUES (?, ?, 'm')")
+                .bind(session)
+                .bind(project)
+                .execute(&pool)
+                .await
+                .unwrap();
+            sqlx::query("INSERT INTO messages (session_id, role, content) VALUES (?, 'user', ?)")
+                .bind(session)
+                .bind(content)
+                .execute(&pool)
+                .await
+                .unwrap();
+        }
+
+        assert_eq!(search_messages(&pool, "DEPLOY", None, 10).await.unwrap().len(), 2);
+        let hits = search_messages(&pool, "deploy", Some("b"), 10).await.unwrap();
+        assert_eq!(hits[0].session_id, "s2");
+        // LIKE wildcards in the query are literal
+        assert_eq!(search_messages(&pool, "100%", None, 10).await.unwrap().len(), 1);
+        assert_eq!(search_messages(&pool, "y_s", None, 10).await.unwrap().len(), 1);
+        assert!(search_messages(&pool, "0%r", None, 10).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_maintenance_truncates_wal() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = init_db(&format!("sqlite:{}", dir.path().join("t.db").display())).await.unwrap();
+        sqlx::query("INSERT INTO sessions (id, model) VALUES ('s1', 'm')")
+            .execute(&pool)
+            .await
+            .unwrap();
+
+        let before = db_size(&pool).await.unwrap();
+        assert!(before.size_bytes > 0);
+        assert!(before.wal_bytes > 0);
+        run_maintenance(&pool, true).await.unwrap();
+        let after = db_size(&pool).await.unwrap();
+        assert_eq!(after.wal_bytes, 0);
+        assert_eq!(after.free_bytes, 0);
+    }
+
+    #[tokio::test]
+    async fn test_hot_queries_use_indexes() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = init_db(&format!("sqlite:{}", dir.path().join("t.db").display())).await.unwrap();
+        let plan = |sql: &'static str| {
+            let pool = pool.clone();
+            async move {
+                let rows: Vec<(i64, i64, i64, String)> =
+                    sqlx::query_as(&format!("EXPLAIN QUERY PLAN {sql}")).fetch_all(&pool).await.unwrap();
+                rows.into_iter().map(|r| r.3).collect::<Vec<_>>().join("; ")
+            }
+        };
+
+        let messages = plan("SELECT id FROM messages WHERE session_id = 's' ORDER BY created_at, id").await;
+        assert!(messages.contains("idx_messages_session_created"), "{messages}");
+        assert!(!messages.contains("TEMP B-TREE"), "{messages}");
+        let stale = plan("SELECT id FROM sessions WHERE updated_at < datetime('now', '-5 minutes')").await;
+        assert!(stale.contains("idx_sessions_updated"), "{stale}");
+        let project = plan("SELECT id FROM sessions WHERE project_id = 'p'").await;
+        assert!(project.contains("idx_sessions_project"), "{project}");
+    }
+
+    #[tokio::test]
+    async fn test_replace_with_summary() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = init_db(&format!("sqlite:{}", dir.path().join("t.db").display())).await.unwrap();
+        ensure_session(&pool, "s1", "default", "m").await.unwrap();
+        for role in ["user", "assistant", "user", "assistant"] {
+            add_message(&pool, "s1", role, role, 1, 1, 0.0, &serde_json::json!({})).await.unwrap();
+            update_session_metrics(&pool, "s1", 2, 0.0).await.unwrap();
+        }
+        let ids: Vec<i64> = list_messages(&pool, "s1", 10, 0).await.unwrap().iter().map(|m| m.id).collect();
+
+        replace_with_summary(&pool, "s1", &ids[..3], "gist", 0.25, &serde_json::json!({})).await.unwrap();
+        let messages = list_messages(&pool, "s1", 10, 0).await.unwrap();
+        let roles: Vec<_> = messages.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, ["summary", "assistant"]);
+        assert_eq!(messages[0].content, "gist");
+        let session = get_session(&pool, "s1").await.unwrap().unwrap();
+        assert_eq!((session.message_count, session.total_cost), (2, 0.25));
+    }
+
+    #[tokio::test]
+    async fn test_erase_session() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = init_db(&format!("sqlite:{}", dir.path().join("t.db").display())).await.unwrap();
+        for sid in ["s1", "s2"] {
+            ensure_session(&pool, sid, "default", "m").await.unwrap();
+            let metadata = serde_json::json!({
+                "images": ["/tmp/a.png"],
+                "files": [],
+                "file_ids": [format!("file-{sid}")],
+                "claude_session_id": "cli-1",
+            });
+            add_message(&pool, sid, "user", "secret", 0, 0, 0.0, &metadata).await.unwrap();
+            let path = format!("/data/.files/file-{sid}");
+            create_file(&pool, &format!("file-{sid}"), "a.txt", "user_data", 1, &path, None).await.unwrap();
+            create_job(&pool, &format!("job-{sid}"), "m").await.unwrap();
+            finish_job(&pool, &format!("job-{sid}"), true, &serde_json::json!({"session_id": sid})).await.unwrap();
+            put_cached_response(&pool, sid, "{}", Some(sid), 60).await.unwrap();
+            add_transcript_events(&pool, sid, &[serde_json::json!({"type": "system"})]).await.unwrap();
+        }
+        delete_session(&pool, "s1").await.unwrap();
+        let request = serde_json::json!({});
+        save_recording(&pool, "rec-1", Some("s1"), "m", &request, None, &request, None).await.unwrap();
+        save_recording(&pool, "rec-2", None, "m", &request, None, &request, Some("rec-1")).await.unwrap();
+
+        let artifacts = session_artifacts(&pool, "s1").await.unwrap();
+        assert_eq!(artifacts.attachments, vec!["/tmp/a.png".to_string()]);
+        assert_eq!(artifacts.cli_sessions, vec!["cli-1".to_string()]);
+        assert_eq!(artifacts.uploads, vec!["/data/.files/file-s1".to_string()]);
+
+        // Soft-deleted sessions are erased too
+        let report = erase_session(&pool, "s1").await.unwrap();
+        assert_eq!((report.sessions, report.messages, report.transcripts, report.recordings), (1, 1, 1, 2));
+        assert_eq!((report.jobs, report.cached_responses, report.files), (1, 1, 1));
+        assert!(erase_session(&pool, "s1").await.unwrap().is_empty());
+        assert_eq!(list_messages(&pool, "s2", 10, 0).await.unwrap().len(), 1);
+        assert!(get_job(&pool, "job-s2").await.unwrap().is_some());
+        assert!(get_cached_response(&pool, "s2", 60).await.unwrap().is_some());
+        assert!(get_file(&pool, "file-s2", None).await.unwrap().is_some());
+    }
+
+    #[tokio::test]
+    async fn test_job_lifecycle() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = init_db(&format!("sqlite:{}", dir.path().join("t.db").display())).await.unwrap();
+        for id in ["done", "stuck"] {
+            create_job(&pool, id, "m").await.unwrap();
+            start_job(&pool, id).await.unwrap();
+        }
+        finish_job(&pool, "done", true, &serde_json::json!({"id": "chatcmpl-1"})).await.unwrap();
+
+        assert_eq!(fail_interrupted_jobs(&pool).await.unwrap(), 1);
+        let done = get_job(&pool, "done").await.unwrap().unwrap();
+        assert_eq!(done.status, "succeeded");
+        assert_eq!(done.response.unwrap().0["id"], "chatcmpl-1");
+        let failed = list_jobs(&pool, Some("failed"), 10).await.unwrap();
+        assert_eq!(failed.len(), 1);
+        assert_eq!(failed[0].id, "stuck");
+        assert!(failed[0].completed_at.is_some());
+    }
+
+    #[tokio::test]
+    async fn test_key_quota_usage() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = init_db(&format!("sqlite:{}", dir.path().join("t.db").display())).await.unwrap();
+        add_key_tokens(&pool, "k1", 300).await.unwrap();
+        add_key_tokens(&pool, "k1", 200).await.unwrap();
+        // Last month's use doesn't count
+        sqlx::query("INSERT INTO key_token_usage (key_hash, month, tokens) VALUES ('k1', '2000-01', 9999)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        assert_eq!(key_tokens_this_month(&pool, "k1").await.unwrap(), 500);
+
+        let quota = put_key_quota(&pool, "k1", 1000).await.unwrap();
+        assert_eq!((quota.monthly_tokens, quota.used_tokens), (1000, 500));
+        put_key_quota(&pool, "k2", 10).await.unwrap();
+        let quotas = list_key_quotas(&pool).await.unwrap();
+        assert_eq!(quotas.iter().map(|q| q.used_tokens).collect::<Vec<_>>(), [500, 0]);
+        assert!(delete_key_quota(&pool, "k2").await.unwrap());
+        assert!(get_key_quota(&pool, "k2").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_busy_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite:{}", dir.path().join("t.db").display());
+        let options = DbOptions { busy_timeout: Duration::from_millis(50), ..Default::default() };
+        let pool = init_db_with(&url, &options).await.unwrap();
+
+        // Another connection holds the write lock past the busy timeout
+        let mut holder = pool.acquire().await.unwrap();
+        sqlx::query("BEGIN IMMEDIATE").execute(&mut *holder).await.unwrap();
+        let err = add_spend(&pool, 1.0).await.unwrap_err();
+        let (status, body) = crate::error::AppError::from(err).parts();
+        assert_eq!((status.as_u16(), body.error.code.as_str()), (503, "service_unavailable"));
+        sqlx::query("ROLLBACK").execute(&mut *holder).await.unwrap();
+        add_spend(&pool, 1.0).await.unwrap();
+    }
+
+}
@@ -0,0 +1,190 @@
+//! Erasure of conversations, for data-subject deletion requests.
+//! `DELETE /v1/sessions/{id}` only hides a session; erasing it
+//! (`?hard=true`, or `POST /admin/purge` by key, user or time range)
+//! physically removes its messages (with their search embeddings),
+//! transcript events, recordings, tasks, background jobs and cached
+//! responses, the attachments saved for its requests and the uploads
+//! (`/v1/files`) they referenced, and the CLI's own copy of the
+//! conversation under `<config dir>/projects/`. Every erasure returns a receipt, which is also
+//! logged.
+//!
+//! Project indexes (`/v1/projects/{id}/index`) hold embeddings of the
+//! project's files, not of conversations, and are left alone; the request
+//! log keeps its entries for accounting, with end user and client IP
+//! cleared on purges by key or user.
+
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::db::{self, ErasureReport};
+use crate::error::AppError;
+use crate::models::openai::IMAGE_FILE_PREFIX;
+use crate::state::AppState;
+
+/// What an erasure removed.
+#[derive(Debug, Serialize)]
+pub struct Receipt {
+    pub id: String,
+    pub erased_at: String,
+    pub sessions: Vec<String>,
+    pub deleted: ErasureReport,
+    /// Saved images and documents.
+    pub attachments: u64,
+    /// Conversation files of the CLI.
+    pub cli_transcripts: u64,
+    /// Request log entries whose end user and client IP were cleared.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub anonymized_requests: Option<u64>,
+}
+
+/// Erase `sessions`, stopping any of them that are running.
+pub async fn erase(state: &AppState, sessions: Vec<String>) -> Result<Receipt, AppError> {
+    let config = state.config();
+    let mut deleted = ErasureReport::default();
+    let mut attachments = 0;
+    let mut cli_transcripts = 0;
+    for id in &sessions {
+        state.claude_manager.stop_session(id).await;
+        let mut artifacts = db::session_artifacts(&state.db, id).await?;
+        deleted.add(db::erase_session(&state.db, id).await?);
+        if let Some(ref cache) = state.response_cache {
+            cache.forget_session(id);
+        }
+        attachments += artifacts
+            .attachments
+            .iter()
+            .filter(|path| remove_attachment(&config, Path::new(path), &std::env::temp_dir()))
+            .count() as u64;
+        for path in &artifacts.uploads {
+            if let Err(e) = std::fs::remove_file(path) {
+                tracing::warn!(path = %path, error = %e, "Failed to remove erased upload");
+            }
+        }
+        artifacts.cli_sessions.push(id.clone());
+        cli_transcripts += remove_cli_transcripts(&config, &artifacts.cli_sessions);
+    }
+    let receipt = Receipt {
+        id: format!("erasure-{}", uuid::Uuid::new_v4().as_simple()),
+        erased_at: chrono::Utc::now().to_rfc3339(),
+        sessions,
+        deleted,
+        attachments,
+        cli_transcripts,
+        anonymized_requests: None,
+    };
+    tracing::info!(
+        receipt_id = %receipt.id,
+        sessions = receipt.sessions.len(),
+        messages = deleted.messages,
+        transcripts = deleted.transcripts,
+        recordings = deleted.recordings,
+        tasks = deleted.tasks,
+        jobs = deleted.jobs,
+        cached_responses = deleted.cached_responses,
+        files = deleted.files,
+        attachments,
+        cli_transcripts,
+        "Erased conversation data"
+    );
+    Ok(receipt)
+}
+
+/// Delete an attachment if it is one the gateway saved: an image it wrote
+/// to the temp directory or a document in a project's `uploads/`. Returns
+/// whether it was deleted.
+fn remove_attachment(config: &Config, path: &Path, temp_dir: &Path) -> bool {
+    let Ok(path) = path.canonicalize() else {
+        return false;
+    };
+    let under = |root: &Path| root.canonicalize().is_ok_and(|root| path.starts_with(root));
+    let upload = path.parent().is_some_and(|dir| dir.ends_with("uploads")) && under(&config.project_root);
+    let image = path.file_name().is_some_and(|name| name.to_string_lossy().starts_with(IMAGE_FILE_PREFIX))
+        && path.parent() == temp_dir.canonicalize().ok().as_deref();
+    (upload || image) && path.is_file() && std::fs::remove_file(&path).is_ok()
+}
+
+/// Delete the CLI's conversation files for `ids` in every config directory
+/// the gateway runs it with. Returns how many were deleted.
+fn remove_cli_transcripts(config: &Config, ids: &[String]) -> u64 {
+    let ids: Vec<&String> = ids
+        .iter()
+        .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_')))
+        .collect();
+    let mut removed = 0;
+    for dir in cli_config_dirs(config) {
+        let Ok(projects) = std::fs::read_dir(dir.join("projects")) else {
+            continue;
+        };
+        for project in projects.flatten().map(|entry| entry.path()) {
+            for id in &ids {
+                let file = project.join(format!("{id}.jsonl"));
+                if std::fs::remove_file(&file).is_ok() {
+                    removed += 1;
+                }
+                // Subagent conversations of the session
+                let _ = std::fs::remove_dir_all(project.join(id.as_str()));
+            }
+        }
+    }
+    removed
+}
+
+/// `CLAUDE_CONFIG_DIR` (or `~/.claude`) and the per-profile, per-key and
+/// per-project config directories.
+fn cli_config_dirs(config: &Config) -> BTreeSet<PathBuf> {
+    let default = std::env::var_os("CLAUDE_CONFIG_DIR").map(PathBuf::from).or_else(|| {
+        std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".claude"))
+    });
+    default
+        .into_iter()
+        .chain(config.claude_profiles.iter().filter_map(|p| p.config_dir.clone()))
+        .chain(config.key_config_dirs.iter().map(|(_, dir)| dir.clone()))
+        .chain(config.project_config_dirs.iter().map(|(_, dir)| dir.clone()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_remove_cli_transcripts() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = dir.path().join("projects").join("-srv-app");
+        std::fs::create_dir_all(project.join("abc-123")).unwrap();
+        for name in ["abc-123.jsonl", "other.jsonl"] {
+            std::fs::write(project.join(name), "{}").unwrap();
+        }
+        let mut config = Config::for_test();
+        config.claude_profiles[0].config_dir = Some(dir.path().to_path_buf());
+
+        let ids = ["abc-123".to_string(), "../other".to_string()];
+        assert_eq!(remove_cli_transcripts(&config, &ids), 1);
+        assert!(!project.join("abc-123.jsonl").exists() && !project.join("abc-123").exists());
+        assert!(project.join("other.jsonl").exists());
+    }
+
+    #[test]
+    fn test_remove_attachment() {
+        let tmp = tempfile::tempdir().unwrap();
+        let projects = tempfile::tempdir().unwrap();
+        let mut config = Config::for_test();
+        config.project_root = projects.path().to_path_buf();
+        std::fs::create_dir_all(projects.path().join("app/uploads")).unwrap();
+
+        let image = tmp.path().join(format!("{IMAGE_FILE_PREFIX}1.png"));
+        let upload = projects.path().join("app/uploads/notes.txt");
+        let other = tmp.path().join("unrelated.txt");
+        for path in [&image, &upload, &other] {
+            std::fs::write(path, "x").unwrap();
+        }
+        assert!(remove_attachment(&config, &image, tmp.path()));
+        assert!(remove_attachment(&config, &upload, tmp.path()));
+        // Other files in the temp dir aren't the gateway's to delete
+        assert!(!remove_attachment(&config, &other, tmp.path()));
+        assert!(other.exists());
+    }
+}
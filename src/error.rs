@@ -1,56 +1,176 @@
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
-use serde_json::json;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::claude::auth::GUIDANCE;
+use crate::claude::parser::{LimitKind, UpstreamLimit};
 
 #[derive(Debug)]
 pub enum AppError {
     BadRequest(String),
+    /// A request parameter failed validation; `param` points at it, e.g.
+    /// `messages[2].role`.
+    InvalidParam { param: String, message: String },
     Unauthorized(String),
+    /// Authenticated, but the key may not access this.
+    Forbidden(String),
     NotFound(String),
+    /// The requested model is unknown (holds the model name).
+    ModelNotFound(String),
+    /// The prompt (plus `max_tokens`) doesn't fit the model's context window.
+    ContextLengthExceeded(String),
+    /// Blocked by a guardrail policy, see [`crate::guardrails`].
+    PolicyViolation(String),
     RateLimited,
+    /// A spending budget or quota is used up.
+    QuotaExceeded(String),
+    /// The server-wide spend ceiling is reached until `resets_at` (unix
+    /// time), see [`crate::budget`].
+    BudgetExceeded { message: String, resets_at: i64 },
+    /// Anthropic rate limited or overloaded, or the account's usage limit
+    /// is reached; sent with `Retry-After` when the reset time is known.
+    UpstreamLimited(UpstreamLimit),
+    /// The Claude CLI is not logged in (holds its message), see
+    /// [`crate::claude::auth`].
+    ClaudeAuth(String),
+    /// The CLI process ended before its result (holds what is known about
+    /// why), e.g. it crashed or was killed.
+    ProcessExited(String),
     ServiceUnavailable(String),
     Internal(String),
 }
 
+/// The OpenAI error envelope every failure is returned in.
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorResponse {
+    pub error: ErrorDetail,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ErrorDetail {
+    pub message: String,
+    #[serde(rename = "type")]
+    pub error_type: String,
+    /// The offending request parameter, e.g. `messages[2].role`.
+    pub param: Option<String>,
+    pub code: String,
+}
+
 impl std::fmt::Display for AppError {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Self::BadRequest(msg) => write!(f, "Bad request: {msg}"),
+            Self::InvalidParam { param, message } => write!(f, "Invalid {param}: {message}"),
             Self::Unauthorized(msg) => write!(f, "Unauthorized: {msg}"),
+            Self::Forbidden(msg) => write!(f, "Forbidden: {msg}"),
             Self::NotFound(msg) => write!(f, "Not found: {msg}"),
+            Self::ModelNotFound(model) => write!(f, "Model not found: {model}"),
+            Self::ContextLengthExceeded(msg) => write!(f, "Context length exceeded: {msg}"),
+            Self::PolicyViolation(msg) => write!(f, "Policy violation: {msg}"),
             Self::RateLimited => write!(f, "Rate limit exceeded"),
+            Self::QuotaExceeded(msg) => write!(f, "Quota exceeded: {msg}"),
+            Self::BudgetExceeded { message, .. } => write!(f, "Budget exceeded: {message}"),
+            Self::UpstreamLimited(limit) => write!(f, "Upstream limit ({}): {}", limit.kind.as_str(), limit.message),
+            Self::ClaudeAuth(msg) => write!(f, "Claude CLI not logged in: {msg}"),
+            Self::ProcessExited(msg) => write!(f, "CLI process exited early: {msg}"),
             Self::ServiceUnavailable(msg) => write!(f, "Service unavailable: {msg}"),
             Self::Internal(msg) => write!(f, "Internal error: {msg}"),
         }
     }
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
+impl AppError {
+    /// The status and OpenAI error body this error is returned as.
+    pub fn parts(&self) -> (StatusCode, ErrorResponse) {
+        let param = match &self {
+            Self::InvalidParam { param, .. } => Some(param.clone()),
+            Self::ModelNotFound(_) => Some("model".to_string()),
+            _ => None,
+        };
         let (status, error_type, code, message) = match &self {
             Self::BadRequest(msg) => (StatusCode::BAD_REQUEST, "invalid_request_error", "bad_request", msg.clone()),
+            Self::InvalidParam { message, .. } => (StatusCode::BAD_REQUEST, "invalid_request_error", "invalid_value", message.clone()),
             Self::Unauthorized(msg) => (StatusCode::UNAUTHORIZED, "authentication_error", "invalid_api_key", msg.clone()),
+            Self::Forbidden(msg) => (StatusCode::FORBIDDEN, "permission_error", "forbidden", msg.clone()),
             Self::NotFound(msg) => (StatusCode::NOT_FOUND, "not_found", "not_found", msg.clone()),
+            Self::ModelNotFound(model) => (
+                StatusCode::NOT_FOUND,
+                "invalid_request_error",
+                "model_not_found",
+                format!("The model `{model}` does not exist or you do not have access to it."),
+            ),
+            Self::ContextLengthExceeded(msg) => (StatusCode::BAD_REQUEST, "invalid_request_error", "context_length_exceeded", msg.clone()),
+            Self::PolicyViolation(msg) => (StatusCode::BAD_REQUEST, "invalid_request_error", "content_policy_violation", msg.clone()),
             Self::RateLimited => (StatusCode::TOO_MANY_REQUESTS, "rate_limit_error", "rate_limit_exceeded", "Rate limit exceeded".to_string()),
+            Self::QuotaExceeded(msg) => (StatusCode::TOO_MANY_REQUESTS, "insufficient_quota", "insufficient_quota", msg.clone()),
+            Self::BudgetExceeded { message, .. } => (StatusCode::TOO_MANY_REQUESTS, "insufficient_quota", "budget_exceeded", message.clone()),
+            Self::UpstreamLimited(limit) => {
+                let code = match limit.kind {
+                    LimitKind::RateLimited => "rate_limit_exceeded",
+                    LimitKind::Overloaded => "overloaded",
+                    LimitKind::UsageLimit => "usage_limit_reached",
+                };
+                (StatusCode::TOO_MANY_REQUESTS, "rate_limit_error", code, limit.message.clone())
+            }
+            Self::ClaudeAuth(msg) => (
+                StatusCode::SERVICE_UNAVAILABLE,
+                "service_error",
+                "claude_auth_error",
+                format!("The Claude CLI is not logged in ({msg}). {GUIDANCE}."),
+            ),
+            Self::ProcessExited(msg) => (
+                StatusCode::BAD_GATEWAY,
+                "server_error",
+                "process_exited",
+                format!("The CLI exited before finishing the response ({msg}); the output is incomplete"),
+            ),
             Self::ServiceUnavailable(msg) => (StatusCode::SERVICE_UNAVAILABLE, "service_error", "service_unavailable", msg.clone()),
             Self::Internal(msg) => (StatusCode::INTERNAL_SERVER_ERROR, "internal_error", "internal_error", msg.clone()),
         };
 
-        let body = json!({
-            "error": {
-                "message": message,
-                "type": error_type,
-                "code": code,
-            }
-        });
+        let body = ErrorResponse {
+            error: ErrorDetail {
+                message,
+                error_type: error_type.to_string(),
+                param,
+                code: code.to_string(),
+            },
+        };
+        (status, body)
+    }
+}
 
-        (status, Json(body)).into_response()
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        let (status, body) = self.parts();
+        let mut response = (status, Json(body)).into_response();
+        let resets_at = match self {
+            Self::UpstreamLimited(UpstreamLimit { resets_at, .. }) => resets_at,
+            Self::BudgetExceeded { resets_at, .. } => Some(resets_at),
+            _ => None,
+        };
+        if let Some(at) = resets_at {
+            let secs = (at - chrono::Utc::now().timestamp()).max(1);
+            response.headers_mut().insert(axum::http::header::RETRY_AFTER, secs.into());
+        }
+        response
     }
 }
 
 impl From<sqlx::Error> for AppError {
     fn from(e: sqlx::Error) -> Self {
+        // Lock contention outlasting the busy timeout is transient: retry
+        let busy = match &e {
+            sqlx::Error::Database(db) => matches!(db.code().as_deref(), Some("5" | "6" | "261" | "262" | "517" | "773")),
+            sqlx::Error::PoolTimedOut => true,
+            _ => false,
+        };
+        if busy {
+            tracing::warn!(error = %e, "Database busy");
+            return Self::ServiceUnavailable("The database is busy; retry the request".to_string());
+        }
         tracing::error!(error = %e, "Database error");
         Self::Internal("Database error".to_string())
     }
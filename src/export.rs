@@ -0,0 +1,160 @@
+//! Data export for `GET /admin/export`, for data-portability requests and
+//! offline analysis: everything stored for an API key or end user, i.e.
+//! the sessions the request log ties to them (soft-deleted ones included),
+//! their messages, and the request log entries with a usage summary.
+//!
+//! As JSONL, every line has a `type`: one `export` line describing the
+//! export, then `session`, `message` and `request` lines. As a ZIP, the
+//! same records are in `manifest.json`, `sessions.jsonl`, `messages.jsonl`
+//! and `requests.jsonl`.
+
+use std::io::{Cursor, Write};
+
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use crate::db::{self, RequestLogFilter};
+use crate::error::AppError;
+use crate::state::AppState;
+
+/// Messages and request log entries are read in pages of this size.
+const PAGE: i64 = 1000;
+
+/// Everything exported for one key or user.
+pub struct Export {
+    manifest: Value,
+    sessions: Vec<Value>,
+    messages: Vec<Value>,
+    requests: Vec<Value>,
+}
+
+/// Collect the data of `filter`'s key or user.
+pub async fn collect(state: &AppState, filter: RequestLogFilter) -> Result<Export, AppError> {
+    let mut sessions = Vec::new();
+    let mut messages = Vec::new();
+    for id in db::sessions_matching(&state.db, &filter).await? {
+        let Some(session) = db::find_session(&state.db, &id).await? else {
+            continue;
+        };
+        sessions.push(record(&session));
+        let mut offset = 0;
+        loop {
+            let page = db::list_messages(&state.db, &id, PAGE, offset).await?;
+            offset += PAGE;
+            let done = (page.len() as i64) < PAGE;
+            messages.extend(page.iter().map(record));
+            if done {
+                break;
+            }
+        }
+    }
+
+    let mut requests = Vec::new();
+    let mut page_filter = RequestLogFilter { limit: PAGE, offset: 0, ..filter.clone() };
+    loop {
+        let page = db::list_request_log(&state.db, &page_filter).await?;
+        page_filter.offset += PAGE;
+        let done = (page.len() as i64) < PAGE;
+        requests.extend(page.iter().map(record));
+        if done {
+            break;
+        }
+    }
+    let usage = db::usage_by_user(&state.db, &RequestLogFilter { limit: PAGE, offset: 0, ..filter.clone() }).await?;
+
+    let manifest = json!({
+        "generated_at": chrono::Utc::now().to_rfc3339(),
+        "key_hash": filter.key_hash,
+        "user": filter.user,
+        "since": filter.since,
+        "until": filter.until,
+        "counts": {
+            "sessions": sessions.len(),
+            "messages": messages.len(),
+            "requests": requests.len(),
+        },
+        "usage": usage,
+    });
+    Ok(Export { manifest, sessions, messages, requests })
+}
+
+impl Export {
+    /// All records as one JSONL document.
+    pub fn to_jsonl(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        let tagged = [("session", &self.sessions), ("message", &self.messages), ("request", &self.requests)];
+        write_line(&mut out, &self.manifest, "export");
+        for (kind, records) in tagged {
+            for record in records {
+                write_line(&mut out, record, kind);
+            }
+        }
+        out
+    }
+
+    /// A ZIP archive of the manifest and one JSONL file per record type.
+    pub fn to_zip(&self) -> Result<Vec<u8>, AppError> {
+        let failed = |e: &dyn std::fmt::Display| AppError::Internal(format!("Failed to build the export archive: {e}"));
+        let options = zip::write::SimpleFileOptions::default().compression_method(zip::CompressionMethod::Deflated);
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let manifest = serde_json::to_vec_pretty(&self.manifest).unwrap_or_default();
+        let files = [
+            ("sessions.jsonl", &self.sessions),
+            ("messages.jsonl", &self.messages),
+            ("requests.jsonl", &self.requests),
+        ];
+        zip.start_file("manifest.json", options).map_err(|e| failed(&e))?;
+        zip.write_all(&manifest).map_err(|e| failed(&e))?;
+        for (name, records) in files {
+            zip.start_file(name, options).map_err(|e| failed(&e))?;
+            for record in records {
+                let mut line = serde_json::to_vec(record).unwrap_or_default();
+                line.push(b'\n');
+                zip.write_all(&line).map_err(|e| failed(&e))?;
+            }
+        }
+        Ok(zip.finish().map_err(|e| failed(&e))?.into_inner())
+    }
+}
+
+fn record(row: &impl Serialize) -> Value {
+    serde_json::to_value(row).unwrap_or_default()
+}
+
+/// Append `record` with `"type": kind` as a line.
+fn write_line(out: &mut Vec<u8>, record: &Value, kind: &str) {
+    let mut line = json!({ "type": kind });
+    if let (Some(line), Some(fields)) = (line.as_object_mut(), record.as_object()) {
+        line.extend(fields.iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+    let _ = serde_json::to_writer(&mut *out, &line);
+    out.push(b'\n');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Read;
+
+    #[test]
+    fn test_export_formats() {
+        let export = Export {
+            manifest: json!({"user": "alice"}),
+            sessions: vec![json!({"id": "s1"})],
+            messages: vec![json!({"session_id": "s1", "content": "hi"}), json!({"session_id": "s1", "content": "hello"})],
+            requests: vec![],
+        };
+        let jsonl = String::from_utf8(export.to_jsonl()).unwrap();
+        let types: Vec<String> = jsonl
+            .lines()
+            .map(|l| serde_json::from_str::<Value>(l).unwrap()["type"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(types, ["export", "session", "message", "message"]);
+
+        let mut zip = zip::ZipArchive::new(Cursor::new(export.to_zip().unwrap())).unwrap();
+        let mut messages = String::new();
+        zip.by_name("messages.jsonl").unwrap().read_to_string(&mut messages).unwrap();
+        assert_eq!(messages.lines().count(), 2);
+        assert!(zip.by_name("manifest.json").is_ok() && zip.by_name("requests.jsonl").is_ok());
+    }
+}
@@ -0,0 +1,75 @@
+//! Request extractors whose rejections use the OpenAI error envelope.
+
+use axum::extract::rejection::JsonRejection;
+use axum::extract::FromRequest;
+
+use crate::error::AppError;
+
+/// `axum::Json` for request bodies: malformed JSON, a missing
+/// `Content-Type` or a body that doesn't match the schema is reported as an
+/// `invalid_request_error` rather than axum's plain-text 400/415/422.
+#[derive(Debug, FromRequest)]
+#[from_request(via(axum::Json), rejection(AppError))]
+pub struct AppJson<T>(pub T);
+
+impl From<JsonRejection> for AppError {
+    fn from(rejection: JsonRejection) -> Self {
+        let message = match rejection {
+            JsonRejection::MissingJsonContentType(_) => {
+                "Expected a JSON body with `Content-Type: application/json`".to_string()
+            }
+            // "Failed to deserialize the JSON body into the target type: <field>: <reason>"
+            other => other.body_text(),
+        };
+        AppError::BadRequest(message)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use axum::response::IntoResponse;
+
+    #[derive(Debug, serde::Deserialize)]
+    struct Payload {
+        model: String,
+    }
+
+    async fn extract(content_type: Option<&str>, body: &str) -> serde_json::Value {
+        let mut req = Request::post("/");
+        if let Some(ct) = content_type {
+            req = req.header("content-type", ct);
+        }
+        let req = req.body(Body::from(body.to_string())).unwrap();
+        let err = AppJson::<Payload>::from_request(req, &()).await.unwrap_err();
+        let response = err.into_response();
+        assert_eq!(response.status(), 400);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_accepts_valid_body() {
+        let req = Request::post("/")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"model": "cc-sonnet-4"}"#))
+            .unwrap();
+        let AppJson(payload) = AppJson::<Payload>::from_request(req, &()).await.unwrap();
+        assert_eq!(payload.model, "cc-sonnet-4");
+    }
+
+    #[tokio::test]
+    async fn test_rejections_use_error_envelope() {
+        let missing = extract(Some("application/json"), r#"{"messages": []}"#).await;
+        assert_eq!(missing["error"]["type"], "invalid_request_error");
+        assert!(missing["error"]["message"].as_str().unwrap().contains("missing field `model`"));
+
+        let syntax = extract(Some("application/json"), "{").await;
+        assert_eq!(syntax["error"]["code"], "bad_request");
+
+        let no_type = extract(None, "{}").await;
+        assert!(no_type["error"]["message"].as_str().unwrap().contains("Content-Type"));
+    }
+}
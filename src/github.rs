@@ -0,0 +1,171 @@
+//! Pull requests for `/v1/tasks`: a task with `pull_request` set commits
+//! its changes to a branch of its own, pushes it to the GitHub repository
+//! and opens a pull request described by the run's summary. The token is
+//! the request's, or `GITHUB_TOKEN` for the repositories listed in
+//! `GITHUB_TOKEN_REPOS` (`owner/name` or `owner/*`), and is never stored or
+//! put on a command line. `GITHUB_URL` and `GITHUB_API_URL` point at GitHub
+//! Enterprise.
+
+use std::path::Path;
+use std::time::Duration;
+
+use base64::Engine;
+use serde_json::{json, Value};
+
+use crate::db::TaskRow;
+use crate::error::AppError;
+use crate::models::openai::PullRequestOptions;
+use crate::state::AppState;
+use crate::tasks::{git, git_env};
+
+/// Task branches are `<prefix><task id>`.
+const BRANCH_PREFIX: &str = "claude/";
+
+/// Whether `repo` looks like `owner/name`.
+pub fn valid_repo(repo: &str) -> bool {
+    let valid = |part: &str| {
+        !part.is_empty()
+            && !part.starts_with('.')
+            && part.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+    };
+    matches!(repo.split_once('/'), Some((owner, name)) if valid(owner) && valid(name))
+}
+
+/// Whether the server's `GITHUB_TOKEN` may be used for `repo`, going by
+/// `allowlist` (GitHub names are case-insensitive).
+pub fn server_token_allowed(allowlist: &[String], repo: &str) -> bool {
+    allowlist.iter().any(|entry| match entry.strip_suffix("/*") {
+        Some(owner) => repo.split_once('/').is_some_and(|(o, _)| o.eq_ignore_ascii_case(owner)),
+        None => entry.eq_ignore_ascii_case(repo),
+    })
+}
+
+/// Commit what is staged in `worktree`, push it to a new branch of
+/// `options.repo` and open a pull request for it. Returns the pull
+/// request's `url`, `number`, `branch` and `base`.
+pub async fn open_pull_request(
+    state: &AppState,
+    options: &PullRequestOptions,
+    token: &str,
+    task: &TaskRow,
+    worktree: &Path,
+    summary: &str,
+) -> Result<Value, AppError> {
+    let config = state.config();
+    let api = |path: String| format!("{}/repos/{}{path}", config.github_api_url, options.repo);
+    let base = match options.base.as_deref().filter(|b| !b.is_empty()) {
+        Some(base) => base.to_string(),
+        None => {
+            let repo = send(state.http.get(api(String::new())), token, "repository lookup").await?;
+            repo["default_branch"].as_str().unwrap_or("main").to_string()
+        }
+    };
+    let title = options.title.clone().unwrap_or_else(|| title(&task.instruction));
+    let branch = format!("{BRANCH_PREFIX}{}", task.id);
+
+    // Changes the run committed itself are already on HEAD
+    if git(worktree, ["diff", "--cached", "--quiet"]).await.is_err() {
+        let mut identity = Vec::new();
+        if git(worktree, ["config", "user.email"]).await.is_err() {
+            for key in ["GIT_AUTHOR_NAME", "GIT_COMMITTER_NAME"] {
+                identity.push((key, "claude-code-api"));
+            }
+            for key in ["GIT_AUTHOR_EMAIL", "GIT_COMMITTER_EMAIL"] {
+                identity.push((key, "claude-code-api@localhost"));
+            }
+        }
+        let message = format!("{title}\n\n{}", task.instruction);
+        git_env(worktree, ["commit", "-q", "--no-verify", "-m", &message], &identity).await?;
+    }
+
+    // The token goes in through the environment, not the remote URL or argv
+    let credentials = base64::engine::general_purpose::STANDARD.encode(format!("x-access-token:{token}"));
+    let header = format!("AUTHORIZATION: basic {credentials}");
+    let auth = [
+        ("GIT_TERMINAL_PROMPT", "0"),
+        ("GIT_CONFIG_COUNT", "1"),
+        ("GIT_CONFIG_KEY_0", &format!("http.{}/.extraheader", config.github_url)),
+        ("GIT_CONFIG_VALUE_0", &header),
+    ];
+    let remote = format!("{}/{}.git", config.github_url, options.repo);
+    git_env(worktree, ["push", "-q", &remote, &format!("HEAD:refs/heads/{branch}")], &auth)
+        .await
+        .map_err(|e| {
+            let detail = e.parts().1.error.message;
+            AppError::ServiceUnavailable(format!("Pushing {branch} to {} failed: {detail}", options.repo))
+        })?;
+
+    let body = json!({
+        "title": title,
+        "head": branch,
+        "base": base,
+        "body": summary,
+        "draft": options.draft,
+    });
+    let pr = send(state.http.post(api("/pulls".to_string())).json(&body), token, "pull request").await?;
+    tracing::info!(task_id = %task.id, repo = %options.repo, number = %pr["number"], "Opened pull request");
+    Ok(json!({
+        "url": pr["html_url"],
+        "number": pr["number"],
+        "repo": options.repo,
+        "branch": branch,
+        "base": base,
+    }))
+}
+
+/// Send a GitHub API request, returning the JSON response.
+async fn send(request: reqwest::RequestBuilder, token: &str, what: &str) -> Result<Value, AppError> {
+    let failed = |detail: String| {
+        tracing::warn!(error = %detail, "GitHub {what} failed");
+        AppError::ServiceUnavailable(format!("GitHub {what} failed: {detail}"))
+    };
+    let resp = request
+        .bearer_auth(token)
+        .header("Accept", "application/vnd.github+json")
+        .header("User-Agent", concat!("claude-code-api/", env!("CARGO_PKG_VERSION")))
+        .timeout(Duration::from_secs(30))
+        .send()
+        .await
+        .map_err(|e| failed(e.to_string()))?;
+    let status = resp.status();
+    let body: Value = resp.json().await.unwrap_or_default();
+    if !status.is_success() {
+        let message = body["message"].as_str().unwrap_or("no details");
+        return Err(failed(format!("{status}: {message}")));
+    }
+    Ok(body)
+}
+
+/// The first line of `instruction`, shortened to fit a title.
+fn title(instruction: &str) -> String {
+    const MAX: usize = 72;
+    let line = instruction.lines().find(|l| !l.trim().is_empty()).unwrap_or("").trim();
+    match line.char_indices().nth(MAX) {
+        Some((end, _)) => format!("{}...", line[..end].trim_end()),
+        None => line.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_title_and_repo() {
+        assert_eq!(title("\nFix the flaky test\nIt fails on CI."), "Fix the flaky test");
+        let long = "word ".repeat(30);
+        assert!(title(&long).len() <= 75 && title(&long).ends_with("..."));
+
+        assert!(valid_repo("octo-org/my_repo.rs"));
+        for repo in ["octo", "octo/", "/repo", "a/b/c", "octo/../x", "octo/re po"] {
+            assert!(!valid_repo(repo), "{repo}");
+        }
+
+        let allowlist = vec!["octo-org/*".to_string(), "me/site".to_string()];
+        assert!(server_token_allowed(&allowlist, "Octo-Org/api"));
+        assert!(server_token_allowed(&allowlist, "me/site"));
+        assert!(!server_token_allowed(&allowlist, "me/other"));
+        assert!(!server_token_allowed(&allowlist, "octo-org-evil/api"));
+        assert!(!server_token_allowed(&[], "me/site"));
+    }
+}
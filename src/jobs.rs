@@ -0,0 +1,99 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::{HeaderMap, HeaderValue};
+use axum::response::IntoResponse;
+use axum::{Extension, Json};
+
+use crate::auth::ApiKey;
+use crate::db::{self, JobRow};
+use crate::error::AppError;
+use crate::models::openai::ChatCompletionRequest;
+use crate::priority;
+use crate::routes::chat;
+use crate::state::AppState;
+
+/// Record a job for `request` and run the completion in the background.
+/// Streaming is turned off; the result is polled from `/v1/jobs/{id}`.
+pub async fn submit(
+    state: Arc<AppState>,
+    mut request: ChatCompletionRequest,
+    api_key: Option<String>,
+    mut headers: HeaderMap,
+) -> Result<JobRow, AppError> {
+    let id = format!("job-{}", uuid::Uuid::new_v4().as_simple());
+    let job = db::create_job(&state.db, &id, &request.model).await?;
+    request.stream = Some(false);
+    // Jobs are background work unless they ask otherwise
+    headers.entry(priority::HEADER).or_insert(HeaderValue::from_static("low"));
+    tracing::info!(job_id = %id, model = %request.model, "Chat completion job queued");
+    tokio::spawn(run(state, id, request, api_key, headers));
+    Ok(job)
+}
+
+async fn run(
+    state: Arc<AppState>,
+    id: String,
+    request: ChatCompletionRequest,
+    api_key: Option<String>,
+    headers: HeaderMap,
+) {
+    let _ = db::start_job(&state.db, &id).await;
+    let response = chat::complete(
+        State(Arc::clone(&state)),
+        None,
+        api_key.map(|key| Extension(ApiKey(key))),
+        None,
+        headers,
+        Json(request),
+    )
+    .await
+    .unwrap_or_else(IntoResponse::into_response);
+
+    let succeeded = response.status().is_success();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .ok()
+        .and_then(|bytes| serde_json::from_slice(&bytes).ok())
+        .unwrap_or(serde_json::Value::Null);
+    if let Err(e) = db::finish_job(&state.db, &id, succeeded, &body).await {
+        tracing::error!(job_id = %id, error = %e, "Failed to record job result");
+    }
+    tracing::info!(job_id = %id, succeeded, "Chat completion job finished");
+}
+
+/// Mark jobs orphaned by a restart as failed.
+pub async fn recover(state: &AppState) {
+    match db::fail_interrupted_jobs(&state.db).await {
+        Ok(0) => {}
+        Ok(n) => tracing::warn!(jobs = n, "Marked jobs interrupted by restart as failed"),
+        Err(e) => tracing::warn!(error = %e, "Failed to recover interrupted jobs"),
+    }
+}
+
+/// An API `chat.completion.job` object; `result` or `error` is set once the
+/// job has finished.
+pub fn job_object(job: &JobRow) -> serde_json::Value {
+    let response = job.response.as_ref().map(|r| &r.0);
+    let (result, error) = match job.status.as_str() {
+        "succeeded" => (response, None),
+        _ => (None, response.and_then(|r| r.get("error"))),
+    };
+    serde_json::json!({
+        "id": job.id,
+        "object": "chat.completion.job",
+        "model": job.model,
+        "status": job.status,
+        "created_at": unix_time(Some(&job.created_at)),
+        "started_at": unix_time(job.started_at.as_deref()),
+        "completed_at": unix_time(job.completed_at.as_deref()),
+        "result": result,
+        "error": error,
+    })
+}
+
+/// A SQLite `datetime('now')` value as a Unix timestamp.
+pub fn unix_time(t: Option<&str>) -> Option<i64> {
+    t.and_then(|t| chrono::NaiveDateTime::parse_from_str(t, "%Y-%m-%d %H:%M:%S").ok())
+        .map(|t| t.and_utc().timestamp())
+}
@@ -0,0 +1,109 @@
+//! OpenAI-compatible API gateway for the Claude Code CLI.
+//!
+//! The `claude-code-api` binary is a thin wrapper around this crate. To embed
+//! the gateway in another axum app, build an [`AppState`] and either mount
+//! [`build_router`] under your own middleware or use [`build_apps`] for the
+//! stock auth/audit/CORS stack.
+
+pub mod agents;
+pub mod audit;
+pub mod auth;
+pub mod budget;
+pub mod cache;
+pub mod claude;
+pub mod cli;
+pub mod client_ip;
+pub mod compaction;
+pub mod config;
+pub mod conversation;
+pub mod crypto;
+pub mod db;
+pub mod erasure;
+pub mod error;
+pub mod export;
+pub mod extract;
+pub mod github;
+pub mod guardrails;
+pub mod hooks;
+pub mod jobs;
+pub mod logging;
+pub mod maintenance;
+pub mod mcp;
+pub mod message_index;
+pub mod metrics;
+pub mod models;
+pub mod oneshot;
+pub mod pricing;
+pub mod priority;
+pub mod prompt;
+pub mod quota;
+pub mod rag;
+pub mod ratelimit;
+pub mod reaper;
+pub mod recording;
+pub mod redact;
+pub mod registry;
+pub mod reload;
+pub mod replay;
+pub mod resumable;
+pub mod retention;
+pub mod routes;
+pub mod server;
+pub mod state;
+pub mod streaming;
+pub mod systemd;
+pub mod tasks;
+pub mod tiers;
+pub mod timing;
+pub mod tokens;
+pub mod tools;
+pub mod transcript;
+pub mod validation;
+pub mod webhooks;
+
+use std::sync::Arc;
+
+use axum::Router;
+use tower_http::cors::{Any, CorsLayer};
+use tower_http::trace::TraceLayer;
+
+pub use config::Config;
+pub use routes::build_router;
+pub use state::AppState;
+
+/// The gateway's routes with its standard middleware, as `(public, admin)`:
+/// the public router requires auth, the admin router (for trusted
+/// listeners) does not. Both record the audit log.
+pub fn build_apps(state: Arc<AppState>) -> (Router, Router) {
+    // Build CORS layer
+    let cors = CorsLayer::new()
+        .allow_origin(Any)
+        .allow_methods(Any)
+        .allow_headers(Any);
+
+    let router = routes::build_router(state.clone());
+    let trace = TraceLayer::new_for_http().make_span_with(client_ip::make_request_span);
+    let client_ip = axum::middleware::from_fn_with_state(
+        state.clone(),
+        client_ip::client_ip_middleware,
+    );
+    let audit = axum::middleware::from_fn_with_state(state.clone(), audit::audit_middleware);
+    let app = router
+        .clone()
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            auth::auth_middleware,
+        ))
+        .layer(audit.clone())
+        .layer(cors.clone())
+        .layer(trace.clone())
+        .layer(client_ip.clone());
+    let admin_app = router
+        .layer(audit)
+        .layer(cors)
+        .layer(trace)
+        .layer(client_ip);
+
+
+    (app, admin_app)
+}
@@ -0,0 +1,196 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, IsTerminal, Write};
+use std::path::{Path, PathBuf};
+
+use tracing_appender::non_blocking::WorkerGuard;
+use tracing_appender::rolling::{RollingFileAppender, Rotation};
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::{EnvFilter, Layer, Registry};
+
+use crate::config::Config;
+
+type BoxedLayer = Box<dyn Layer<Registry> + Send + Sync>;
+
+/// Initialize the global tracing subscriber from configuration.
+///
+/// Filter precedence: `RUST_LOG` if set, otherwise `LOG_LEVEL` plus the
+/// per-module overrides in `LOG_FILTERS`. Logs always go to stdout; when
+/// `LOG_FILE` is set they are also written to a rotating file. The returned
+/// guard must be kept alive to flush buffered file output on exit.
+pub fn init(config: &Config) -> Option<WorkerGuard> {
+    init_with(config, false)
+}
+
+/// [`init`] with console output on stderr, for when stdout carries a
+/// protocol (`claude-code-api mcp`).
+pub fn init_stderr(config: &Config) -> Option<WorkerGuard> {
+    init_with(config, true)
+}
+
+fn init_with(config: &Config, stderr: bool) -> Option<WorkerGuard> {
+    let filter = EnvFilter::try_from_default_env().unwrap_or_else(|_| {
+        let mut directives = config.log_level.clone();
+        for f in &config.log_filters {
+            directives.push(',');
+            directives.push_str(f);
+        }
+        EnvFilter::new(directives)
+    });
+
+    let mut layers: Vec<BoxedLayer> = vec![if stderr {
+        fmt_layer(&config.log_format, io::stderr, io::stderr().is_terminal())
+    } else {
+        fmt_layer(&config.log_format, io::stdout, io::stdout().is_terminal())
+    }];
+
+    let mut guard = None;
+    if let Some(ref path) = config.log_file {
+        match file_writer(path, &config.log_rotation, config.log_max_size_mb, config.log_max_files) {
+            Ok(writer) => {
+                let (writer, g) = tracing_appender::non_blocking(writer);
+                layers.push(fmt_layer(&config.log_format, writer, false));
+                guard = Some(g);
+            }
+            Err(e) => eprintln!("Failed to open log file {}: {e}", path.display()),
+        }
+    }
+
+    tracing_subscriber::registry()
+        .with(layers.with_filter(filter))
+        .init();
+    guard
+}
+
+fn fmt_layer<W>(format: &str, writer: W, ansi: bool) -> BoxedLayer
+where
+    W: for<'w> tracing_subscriber::fmt::MakeWriter<'w> + Send + Sync + 'static,
+{
+    let layer = tracing_subscriber::fmt::layer()
+        .with_writer(writer)
+        .with_ansi(ansi);
+    match format {
+        "pretty" => layer.pretty().boxed(),
+        "compact" => layer.compact().boxed(),
+        _ => layer.json().boxed(),
+    }
+}
+
+/// Build the file writer for `LOG_ROTATION`: `minutely`, `hourly`, `daily`,
+/// `never`, or `size` (rotate when the file exceeds `LOG_MAX_SIZE_MB`).
+fn file_writer(
+    path: &Path,
+    rotation: &str,
+    max_size_mb: u64,
+    max_files: usize,
+) -> io::Result<Box<dyn Write + Send>> {
+    if rotation == "size" {
+        return Ok(Box::new(SizeRotatingWriter::open(
+            path.to_path_buf(),
+            max_size_mb * 1024 * 1024,
+            max_files,
+        )?));
+    }
+
+    let rotation = match rotation {
+        "minutely" => Rotation::MINUTELY,
+        "hourly" => Rotation::HOURLY,
+        "never" => Rotation::NEVER,
+        _ => Rotation::DAILY,
+    };
+    let dir = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(Path::new("."));
+    let prefix = path
+        .file_name()
+        .map(|n| n.to_string_lossy().to_string())
+        .unwrap_or_else(|| "claude-code-api.log".to_string());
+    fs::create_dir_all(dir)?;
+
+    let mut builder = RollingFileAppender::builder()
+        .rotation(rotation)
+        .filename_prefix(prefix);
+    if max_files > 0 {
+        builder = builder.max_log_files(max_files);
+    }
+    builder.build(dir).map(|w| Box::new(w) as Box<dyn Write + Send>).map_err(io::Error::other)
+}
+
+/// Appends to `path`, renaming it to `path.1` (shifting older files up to
+/// `path.<max_files>`) once it grows past `max_bytes`.
+struct SizeRotatingWriter {
+    path: PathBuf,
+    file: File,
+    written: u64,
+    max_bytes: u64,
+    max_files: usize,
+}
+
+impl SizeRotatingWriter {
+    fn open(path: PathBuf, max_bytes: u64, max_files: usize) -> io::Result<Self> {
+        if let Some(parent) = path.parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)?;
+        }
+        let file = OpenOptions::new().create(true).append(true).open(&path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path,
+            file,
+            written,
+            max_bytes: max_bytes.max(1),
+            max_files: max_files.max(1),
+        })
+    }
+
+    fn rotated(&self, n: usize) -> PathBuf {
+        let mut name = self.path.as_os_str().to_owned();
+        name.push(format!(".{n}"));
+        PathBuf::from(name)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let _ = fs::remove_file(self.rotated(self.max_files));
+        for n in (1..self.max_files).rev() {
+            let _ = fs::rename(self.rotated(n), self.rotated(n + 1));
+        }
+        fs::rename(&self.path, self.rotated(1))?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for SizeRotatingWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let n = self.file.write(buf)?;
+        self.written += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_size_rotation_shifts_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api.log");
+        let mut w = SizeRotatingWriter::open(path.clone(), 10, 2).unwrap();
+
+        for line in ["aaaaaaaa\n", "bbbbbbbb\n", "cccccccc\n", "dddddddd\n"] {
+            w.write_all(line.as_bytes()).unwrap();
+        }
+        w.flush().unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "dddddddd\n");
+        assert_eq!(fs::read_to_string(w.rotated(1)).unwrap(), "cccccccc\n");
+        assert_eq!(fs::read_to_string(w.rotated(2)).unwrap(), "bbbbbbbb\n");
+        assert!(!w.rotated(3).exists());
+    }
+}
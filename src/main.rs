@@ -1,93 +1,211 @@
-mod auth;
-mod claude;
-mod config;
-mod db;
-mod error;
-mod models;
-mod routes;
-mod state;
-mod streaming;
-mod tools;
-
-use std::net::SocketAddr;
-
-use tower_http::cors::{Any, CorsLayer};
-use tower_http::trace::TraceLayer;
-use tracing_subscriber::prelude::*;
-
-use crate::config::Config;
-use crate::state::AppState;
+use std::time::Duration;
+
+use clap::Parser;
+use claude_code_api::cli::{self, Cli, Command};
+use claude_code_api::config::{self, Config};
+use claude_code_api::registry::SessionRegistry;
+use claude_code_api::server::{self, BindAddr, BoundListener};
+use claude_code_api::state::AppState;
+use claude_code_api::{claude, crypto, db, jobs, logging, maintenance, message_index, ratelimit, reaper, reload, retention, systemd, tasks};
 
 #[tokio::main]
 async fn main() {
+    let cli = Cli::parse();
+
     // Load .env file if present
-    let _ = dotenvy::dotenv();
-
-    // Initialize structured logging (JSON)
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "info,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer().json())
-        .init();
-
-    // Load configuration
-    let config = Config::from_env();
-    let addr = SocketAddr::new(
-        config.host.parse().expect("Invalid HOST"),
-        config.port,
-    );
+    config::load_dotenv();
+
+    // Load configuration (CONFIG_FILE, overridden by the environment)
+    let config = Config::load().unwrap_or_else(|e| {
+        eprintln!("Failed to load CONFIG_FILE {e}");
+        std::process::exit(1);
+    });
+
+    match cli.command {
+        None | Some(Command::Serve) => serve(config).await,
+        Some(command) => {
+            // Only `check` and `mcp` log; the other commands print their results
+            let _log_guard = match command {
+                Command::Check => logging::init(&config),
+                Command::Mcp => logging::init_stderr(&config),
+                _ => None,
+            };
+            if let Err(e) = cli::run(command, config).await {
+                eprintln!("Error: {e}");
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+async fn serve(config: Config) {
+    // Initialize logging (JSON by default; keep the guard to flush file output)
+    let _log_guard = logging::init(&config);
+    let listen = config.listen.clone();
+    let admin_listen = config.admin_listen.clone();
+    let socket_mode = config.socket_mode;
+
+    // Admin listeners skip auth, so other hosts must not reach them
+    for addr in &admin_listen {
+        if BindAddr::parse(addr).is_local() {
+            continue;
+        }
+        if config.admin_listen_allow_remote {
+            tracing::warn!(
+                addr = %addr,
+                "ADMIN_LISTEN address is reachable from other hosts and serves every endpoint WITHOUT AUTHENTICATION (ADMIN_LISTEN_ALLOW_REMOTE=true)"
+            );
+        } else {
+            tracing::error!(
+                addr = %addr,
+                "ADMIN_LISTEN must be a loopback address or a Unix socket; set ADMIN_LISTEN_ALLOW_REMOTE=true to serve it unauthenticated anyway"
+            );
+            std::process::exit(1);
+        }
+    }
 
     tracing::info!(
-        host = %config.host,
-        port = config.port,
+        listen = ?listen,
+        admin_listen = ?admin_listen,
         claude_binary = %config.claude_binary_path,
         "Starting Claude Code API Gateway (Rust)"
     );
 
+    if std::env::var_os("CLAUDE_BINARY_PATH").is_none() {
+        if config.claude_binary_path == "claude" {
+            tracing::warn!("CLAUDE_BINARY_PATH not set and no Claude binary found; relying on PATH");
+        } else {
+            tracing::info!(path = %config.claude_binary_path, "Auto-discovered Claude binary");
+        }
+    }
+
+    // Load the encryption key before anything is stored
+    match crypto::init(&config) {
+        Ok(true) => tracing::info!("Encryption at rest enabled"),
+        Ok(false) => {}
+        Err(e) => panic!("Failed to load the encryption key: {e}"),
+    }
+
     // Initialize database
-    let db = db::init_db(&config.database_url)
+    let db = db::init_db_with(&config.database_url, &db::DbOptions::from_config(&config))
         .await
         .expect("Failed to initialize database");
     tracing::info!("Database initialized");
 
-    // Build shared state
-    let state = AppState::new(config, db);
-
-    // Build CORS layer
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods(Any)
-        .allow_headers(Any);
-
-    // Build router
-    let app = routes::build_router(state.clone())
-        .layer(axum::middleware::from_fn_with_state(
-            state,
-            auth::auth_middleware,
-        ))
-        .layer(cors)
-        .layer(TraceLayer::new_for_http());
-
-    // Start server
-    let listener = tokio::net::TcpListener::bind(addr)
-        .await
-        .expect("Failed to bind");
-    tracing::info!(%addr, "Server listening");
+    // Probe the CLIs (and self-test) before reporting readiness
+    let probe = claude::startup::probe(&config).await;
 
-    // Graceful shutdown on ctrl-c
-    axum::serve(listener, app)
-        .with_graceful_shutdown(shutdown_signal())
+    // Join the other replicas, if any
+    let registry = SessionRegistry::connect(&config)
         .await
-        .expect("Server error");
+        .expect("Failed to connect to the session registry");
+    if let Some(ref registry) = registry {
+        registry.spawn();
+    }
+
+    // Build shared state
+    let state = AppState::new(config, db, probe.cli_version, probe.profile_caps, registry);
+    if let Some(ref detail) = probe.auth_failure {
+        claude::auth::failed(&state, &state.config().default_profile().name, detail);
+    }
+    if let Some(ref sandbox) = state.sandbox {
+        tracing::info!(sandbox = ?sandbox, "Claude processes run sandboxed");
+    }
+    jobs::recover(&state).await;
+    tasks::recover(&state).await;
+    reaper::spawn(state.clone());
+    retention::spawn(state.clone());
+    maintenance::spawn(state.clone());
+    ratelimit::spawn_sweeper(state.clone());
+    message_index::spawn(state.clone());
+    reload::spawn(state.clone());
+
+    // Public listeners go through auth, admin listeners do not
+    let (app, admin_app) = claude_code_api::build_apps(state.clone());
+
+    // Collect listeners: systemd-activated sockets replace configured ones.
+    // Activated sockets named "admin" (FileDescriptorName=) skip auth.
+    let activated = systemd::listen_fds().expect("Failed to adopt systemd sockets");
+    let mut bound: Vec<(BoundListener, axum::Router)> = Vec::new();
+    if activated.is_empty() {
+        let listeners = listen
+            .iter()
+            .map(|a| (a, &app))
+            .chain(admin_listen.iter().map(|a| (a, &admin_app)));
+        for (addr, router) in listeners {
+            let addr = BindAddr::parse(addr);
+            let listener = BoundListener::bind(&addr, socket_mode)
+                .await
+                .unwrap_or_else(|e| panic!("Failed to bind {addr}: {e}"));
+            bound.push((listener, router.clone()));
+        }
+    } else {
+        for (name, listener) in activated {
+            let router = if name.as_deref() == Some("admin") { &admin_app } else { &app };
+            bound.push((listener, router.clone()));
+        }
+    }
+
+    // Start one server per listener, all sharing a shutdown signal
+    let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
+    let mut servers = tokio::task::JoinSet::new();
+    for (listener, router) in bound {
+        let rx = shutdown_rx.clone();
+        servers.spawn(async move {
+            if let Err(e) = server::serve(listener, router, rx).await {
+                panic!("Server error: {e}");
+            }
+        });
+    }
+
+    if probe.ready {
+        systemd::notify("READY=1\nSTATUS=Serving requests");
+    }
+    systemd::spawn_watchdog();
+
+    // Graceful shutdown on ctrl-c or SIGTERM
+    tokio::select! {
+        _ = shutdown_signal() => {
+            systemd::notify("STOPPING=1");
+            let _ = shutdown_tx.send(true);
+        }
+        Some(Err(e)) = servers.join_next() => {
+            std::panic::resume_unwind(e.into_panic());
+        }
+    }
+
+    // Stop taking new completions, let running ones finish streaming, then
+    // kill whatever is left so no Claude process outlives the gateway.
+    let grace = Duration::from_secs(state.config().shutdown_grace_seconds);
+    if !state.claude_manager.drain(grace).await {
+        state.claude_manager.cleanup_all(Duration::ZERO).await;
+    }
+    while servers.join_next().await.is_some() {}
 
     tracing::info!("Server shut down");
 }
 
+/// Wait for ctrl-c (SIGINT) or, on unix, SIGTERM from Docker/Kubernetes/systemd.
 async fn shutdown_signal() {
-    tokio::signal::ctrl_c()
-        .await
-        .expect("Failed to listen for ctrl-c");
-    tracing::info!("Shutdown signal received");
+    let ctrl_c = async {
+        tokio::signal::ctrl_c()
+            .await
+            .expect("Failed to listen for ctrl-c");
+    };
+
+    #[cfg(unix)]
+    let terminate = async {
+        tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())
+            .expect("Failed to listen for SIGTERM")
+            .recv()
+            .await;
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+
+    let signal = tokio::select! {
+        _ = ctrl_c => "SIGINT",
+        _ = terminate => "SIGTERM",
+    };
+    tracing::info!(signal, "Shutdown signal received");
 }
@@ -0,0 +1,46 @@
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::db;
+use crate::state::AppState;
+
+/// Start the background task that keeps the SQLite files in shape every
+/// `DB_MAINTENANCE_INTERVAL_MINUTES`: truncates the WAL, runs `ANALYZE`
+/// and, with `DB_VACUUM=true`, `VACUUM`s the database.
+pub fn spawn(state: Arc<AppState>) {
+    let minutes = state.config().db_maintenance_interval_minutes;
+    if minutes == 0 {
+        return;
+    }
+    let interval = Duration::from_secs(minutes * 60);
+    let vacuum = state.config().db_vacuum;
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            run(&state, vacuum).await;
+        }
+    });
+}
+
+async fn run(state: &AppState, vacuum: bool) {
+    let started = Instant::now();
+    let before = db::db_size(&state.db).await.unwrap_or_default();
+    if let Err(e) = db::run_maintenance(&state.db, vacuum).await {
+        tracing::warn!(error = %e, "Database maintenance failed");
+        return;
+    }
+    let after = db::db_size(&state.db).await.unwrap_or_default();
+    let duration_ms = started.elapsed().as_millis() as u64;
+    state.metrics.record_maintenance(duration_ms, vacuum, before, after);
+    tracing::info!(
+        duration_ms,
+        vacuum,
+        size_bytes = after.size_bytes,
+        wal_bytes_before = before.wal_bytes,
+        wal_bytes = after.wal_bytes,
+        "Database maintenance completed"
+    );
+}
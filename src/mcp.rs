@@ -0,0 +1,352 @@
+//! The gateway as an MCP server, so other agents can use it as a tool:
+//! `POST /mcp` (streamable HTTP, behind the usual API key auth) and
+//! `claude-code-api mcp` (stdio, trusted like the admin listener). Tools:
+//!
+//! - `run_completion`: a non-streaming chat completion, optionally
+//!   continuing a session
+//! - `list_sessions`: recent stored sessions
+//! - `search_sessions`: stored messages containing a text
+//!
+//! The server is stateless: no `Mcp-Session-Id`, no server-initiated
+//! messages, every request answered with a single JSON response.
+
+use std::sync::Arc;
+
+use axum::extract::{Query, State};
+use axum::http::HeaderMap;
+use axum::Extension;
+use serde_json::{json, Value};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+
+use crate::auth::ApiKey;
+use crate::db::{self, MessageMatch};
+use crate::error::AppError;
+use crate::extract::AppJson;
+use crate::message_index;
+use crate::routes::chat::{self, CompletionQuery};
+use crate::routes::projects::resolve_project;
+use crate::state::AppState;
+
+/// Protocol revisions this server speaks, newest first.
+const PROTOCOL_VERSIONS: &[&str] = &["2025-06-18", "2025-03-26", "2024-11-05"];
+
+/// Most sessions or matches a listing tool returns.
+const MAX_RESULTS: i64 = 100;
+
+/// Characters of message content shown around a search match.
+const SNIPPET_CHARS: usize = 200;
+
+// JSON-RPC error codes
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INVALID_REQUEST: i64 = -32600;
+
+/// Answer one JSON-RPC message; `None` for notifications and responses,
+/// which get no reply.
+pub async fn handle(state: &Arc<AppState>, api_key: Option<&str>, message: Value) -> Option<Value> {
+    let Some(method) = message.get("method").and_then(Value::as_str) else {
+        // A response to a request we never send, or garbage
+        return message.get("id").is_none().then(|| error(Value::Null, INVALID_REQUEST, "Not a JSON-RPC request"));
+    };
+    let id = message.get("id").cloned()?;
+    let params = message.get("params").cloned().unwrap_or(Value::Null);
+    let result = match method {
+        "initialize" => Ok(initialize(&params)),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": tool_definitions() })),
+        "tools/call" => call_tool(state, api_key, &params).await,
+        _ => Err((METHOD_NOT_FOUND, format!("Unknown method {method}"))),
+    };
+    Some(match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => error(id, code, &message),
+    })
+}
+
+/// Serve MCP over stdin/stdout, one JSON-RPC message per line, until
+/// stdin closes.
+pub async fn serve_stdio(state: Arc<AppState>) -> std::io::Result<()> {
+    let mut lines = BufReader::new(tokio::io::stdin()).lines();
+    let mut stdout = tokio::io::stdout();
+    while let Some(line) = lines.next_line().await? {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let reply = match serde_json::from_str(&line) {
+            Ok(message) => handle(&state, None, message).await,
+            Err(e) => Some(error(Value::Null, -32700, &format!("Parse error: {e}"))),
+        };
+        if let Some(reply) = reply {
+            stdout.write_all(format!("{reply}\n").as_bytes()).await?;
+            stdout.flush().await?;
+        }
+    }
+    Ok(())
+}
+
+fn error(id: Value, code: i64, message: &str) -> Value {
+    json!({ "jsonrpc": "2.0", "id": id, "error": { "code": code, "message": message } })
+}
+
+fn initialize(params: &Value) -> Value {
+    let requested = params.get("protocolVersion").and_then(Value::as_str);
+    json!({
+        "protocolVersion": protocol_version(requested),
+        "capabilities": { "tools": {} },
+        "serverInfo": { "name": "claude-code-api", "version": env!("CARGO_PKG_VERSION") },
+    })
+}
+
+/// The client's revision if we speak it, else our newest.
+fn protocol_version(requested: Option<&str>) -> &'static str {
+    PROTOCOL_VERSIONS
+        .iter()
+        .find(|v| Some(**v) == requested)
+        .unwrap_or(&PROTOCOL_VERSIONS[0])
+}
+
+fn tool_definitions() -> Value {
+    json!([
+        {
+            "name": "run_completion",
+            "description": "Run a prompt through Claude Code and return the reply. Pass the returned session_id to continue the conversation.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "prompt": { "type": "string", "description": "The user message" },
+                    "model": { "type": "string", "description": "Model; the session's or the default one when omitted" },
+                    "session_id": { "type": "string", "description": "Stored session to continue" },
+                    "project_id": { "type": "string", "description": "Project (working directory) to run in" },
+                    "system_prompt": { "type": "string" },
+                },
+                "required": ["prompt"],
+            },
+        },
+        {
+            "name": "list_sessions",
+            "description": "List stored sessions, most recently active first.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "project_id": { "type": "string" },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": MAX_RESULTS, "default": 20 },
+                },
+            },
+        },
+        {
+            "name": "search_sessions",
+            "description": "Find stored messages containing a text, newest first, or with `semantic` the ones closest in meaning, best first.",
+            "inputSchema": {
+                "type": "object",
+                "properties": {
+                    "query": { "type": "string" },
+                    "project_id": { "type": "string" },
+                    "semantic": { "type": "boolean", "default": false },
+                    "limit": { "type": "integer", "minimum": 1, "maximum": MAX_RESULTS, "default": 20 },
+                },
+                "required": ["query"],
+            },
+        },
+    ])
+}
+
+/// `tools/call`. Failures of the tool itself are results with `isError`,
+/// so the calling model sees them; bad calls are JSON-RPC errors.
+async fn call_tool(state: &Arc<AppState>, api_key: Option<&str>, params: &Value) -> Result<Value, (i64, String)> {
+    let name = params.get("name").and_then(Value::as_str).unwrap_or_default();
+    let args = params.get("arguments").cloned().unwrap_or_else(|| json!({}));
+    let arg = |key: &str| args.get(key).and_then(Value::as_str).filter(|s| !s.is_empty());
+    let limit = args.get("limit").and_then(Value::as_i64).unwrap_or(20).clamp(1, MAX_RESULTS);
+    let outcome = match name {
+        "run_completion" => {
+            let prompt = arg("prompt").ok_or((INVALID_PARAMS, "prompt is required".to_string()))?;
+            run_completion(state, api_key, prompt, &args).await
+        }
+        "list_sessions" => list_sessions(state, api_key, arg("project_id"), limit).await,
+        "search_sessions" => {
+            let query = arg("query").ok_or((INVALID_PARAMS, "query is required".to_string()))?;
+            let semantic = args.get("semantic").and_then(Value::as_bool).unwrap_or(false);
+            search_sessions(state, api_key, query, arg("project_id"), semantic, limit).await
+        }
+        _ => return Err((INVALID_PARAMS, format!("Unknown tool {name}"))),
+    };
+    Ok(match outcome {
+        Ok((text, structured)) => json!({
+            "content": [{ "type": "text", "text": text }],
+            "structuredContent": structured,
+            "isError": false,
+        }),
+        Err(e) => json!({ "content": [{ "type": "text", "text": e.to_string() }], "isError": true }),
+    })
+}
+
+/// A tool's reply text and its structured form.
+type ToolOutput = Result<(String, Value), AppError>;
+
+async fn run_completion(state: &Arc<AppState>, api_key: Option<&str>, prompt: &str, args: &Value) -> ToolOutput {
+    let mut messages = Vec::new();
+    if let Some(system) = args.get("system_prompt").and_then(Value::as_str) {
+        messages.push(json!({ "role": "system", "content": system }));
+    }
+    messages.push(json!({ "role": "user", "content": prompt }));
+    let session_id = args.get("session_id").and_then(Value::as_str);
+    // A continued session brings its own model
+    let model = match (args.get("model").and_then(Value::as_str), session_id) {
+        (Some(model), _) => model.to_string(),
+        (None, Some(_)) => String::new(),
+        (None, None) => state.config().default_model.clone(),
+    };
+    let request = serde_json::from_value(json!({
+        "model": model,
+        "messages": messages,
+        "stream": false,
+        "session_id": session_id,
+        "project_id": args.get("project_id"),
+    }))?;
+
+    let response = chat::create_chat_completion(
+        State(Arc::clone(state)),
+        Query(CompletionQuery { run_async: false }),
+        None,
+        api_key.map(|key| Extension(ApiKey(key.to_string()))),
+        HeaderMap::new(),
+        AppJson(request),
+    )
+    .await?;
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to read the completion: {e}")))?;
+    let completion: Value = serde_json::from_slice(&bytes)?;
+    let message = &completion["choices"][0]["message"];
+    let text = match message.get("tool_calls").filter(|t| !t.is_null()) {
+        Some(calls) => calls.to_string(),
+        None => message["content"].as_str().unwrap_or_default().to_string(),
+    };
+    let structured = json!({
+        "session_id": completion["session_id"],
+        "project_id": completion["project_id"],
+        "model": completion["model"],
+        "finish_reason": completion["choices"][0]["finish_reason"],
+        "usage": completion["usage"],
+    });
+    Ok((text, structured))
+}
+
+async fn list_sessions(state: &AppState, api_key: Option<&str>, project_id: Option<&str>, limit: i64) -> ToolOutput {
+    let project = scope(state, api_key, project_id)?;
+    let sessions: Vec<Value> = db::list_sessions(&state.db)
+        .await?
+        .into_iter()
+        .filter(|s| project.is_none() || s.project_id == project)
+        .take(limit as usize)
+        .map(|s| {
+            json!({
+                "session_id": s.id,
+                "project_id": s.project_id,
+                "title": s.title,
+                "model": s.model,
+                "updated_at": s.updated_at,
+                "message_count": s.message_count,
+            })
+        })
+        .collect();
+    let text = serde_json::to_string_pretty(&sessions)?;
+    Ok((text, json!({ "sessions": sessions })))
+}
+
+async fn search_sessions(
+    state: &AppState,
+    api_key: Option<&str>,
+    query: &str,
+    project_id: Option<&str>,
+    semantic: bool,
+    limit: i64,
+) -> ToolOutput {
+    let project = scope(state, api_key, project_id)?;
+    let found: Vec<(Option<f32>, MessageMatch)> = if semantic {
+        message_index::search(&state.db, query, project.as_deref(), limit as usize)
+            .await?
+            .into_iter()
+            .map(|(score, m)| (Some(score), m))
+            .collect()
+    } else {
+        db::search_messages(&state.db, query, project.as_deref(), limit)
+            .await?
+            .into_iter()
+            .map(|m| (None, m))
+            .collect()
+    };
+    let matches: Vec<Value> = found
+        .into_iter()
+        .map(|(score, m)| {
+            let mut found = json!({
+                "session_id": m.session_id,
+                "project_id": m.project_id,
+                "title": m.title,
+                "role": m.role,
+                "created_at": m.created_at,
+                "snippet": snippet(&m.content, query),
+            });
+            if let Some(score) = score {
+                found["score"] = json!(score);
+            }
+            found
+        })
+        .collect();
+    let text = serde_json::to_string_pretty(&matches)?;
+    Ok((text, json!({ "matches": matches })))
+}
+
+/// The project a listing is limited to: the one asked for, or all of them,
+/// except that keys limited by `KEY_PROJECTS` only see their own.
+fn scope(state: &AppState, api_key: Option<&str>, project_id: Option<&str>) -> Result<Option<String>, AppError> {
+    resolve_project(&state.config(), &HeaderMap::new(), api_key, project_id.map(str::to_string))
+}
+
+/// About `SNIPPET_CHARS` characters of `content` around the first match of
+/// `query`.
+fn snippet(content: &str, query: &str) -> String {
+    let chars: Vec<char> = content.chars().collect();
+    if chars.len() <= SNIPPET_CHARS {
+        return content.to_string();
+    }
+    let at = content
+        .to_lowercase()
+        .find(&query.to_lowercase())
+        .map_or(0, |byte| content.to_lowercase()[..byte].chars().count());
+    let start = at.saturating_sub(SNIPPET_CHARS / 2).min(chars.len() - SNIPPET_CHARS);
+    let end = start + SNIPPET_CHARS;
+    let mut text: String = chars[start..end].iter().collect();
+    if start > 0 {
+        text.insert(0, '…');
+    }
+    if end < chars.len() {
+        text.push('…');
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_initialize() {
+        let result = initialize(&json!({ "protocolVersion": "2025-03-26" }));
+        assert_eq!(result["protocolVersion"], "2025-03-26");
+        assert_eq!(protocol_version(Some("1999-01-01")), PROTOCOL_VERSIONS[0]);
+        let tools = tool_definitions();
+        let names: Vec<&str> = tools.as_array().unwrap().iter().map(|t| t["name"].as_str().unwrap()).collect();
+        assert_eq!(names, ["run_completion", "list_sessions", "search_sessions"]);
+    }
+
+    #[test]
+    fn test_snippet() {
+        assert_eq!(snippet("short", "x"), "short");
+        let content = format!("{}needle{}", "a".repeat(300), "b".repeat(300));
+        let s = snippet(&content, "NEEDLE");
+        assert!(s.starts_with('…') && s.ends_with('…'));
+        assert!(s.contains("needle"));
+        assert_eq!(s.chars().count(), SNIPPET_CHARS + 2);
+    }
+}
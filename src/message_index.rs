@@ -0,0 +1,211 @@
+//! Background embedding of stored messages, for semantic search (the
+//! `semantic` option of the MCP `search_sessions` tool). A worker embeds
+//! new messages in batches of `MESSAGE_INDEX_BATCH` with the local hashing
+//! embedder, off the request path: it only takes a database connection
+//! when the pool has one to spare, so under load it falls behind instead of
+//! making requests wait, and catches up later. How far behind it is shows
+//! under `message_index` in `/admin/metrics`.
+//!
+//! With encryption at rest on, messages are not embedded: the vectors would
+//! give the words of the content away.
+
+use std::sync::atomic::{AtomicBool, AtomicI64, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+use sqlx::SqlitePool;
+
+use crate::crypto;
+use crate::db::{self, MessageMatch};
+use crate::error::AppError;
+use crate::rag;
+use crate::routes::embeddings::embed_text;
+use crate::state::AppState;
+
+/// Dimension of message embeddings.
+const DIM: usize = 512;
+/// Messages scoring below this share too little vocabulary with the query.
+const MIN_SCORE: f32 = 0.05;
+
+/// Progress of the worker.
+#[derive(Debug, Default)]
+pub struct MessageIndex {
+    /// Messages up to this ID have been embedded.
+    cursor: AtomicI64,
+    running: AtomicBool,
+    indexed: AtomicU64,
+    batches: AtomicU64,
+    /// Rounds put off because the pool had no connection to spare.
+    deferred: AtomicU64,
+    failures: AtomicU64,
+    /// Messages stored after the cursor, as of the last round.
+    pending: AtomicI64,
+    /// When the oldest of them was stored (unix time), 0 with none.
+    oldest_pending: AtomicI64,
+    last_batch_ms: AtomicU64,
+}
+
+impl MessageIndex {
+    /// Have the messages from `id` on looked at again, e.g. after one was
+    /// rewritten and lost its embedding.
+    pub fn rewind(&self, id: i64) {
+        self.cursor.fetch_min(id - 1, Ordering::Relaxed);
+    }
+
+    /// Counters and indexing lag, for `/admin/metrics`.
+    pub fn stats(&self) -> Value {
+        let oldest = self.oldest_pending.load(Ordering::Relaxed);
+        let lag_seconds = if oldest > 0 { (chrono::Utc::now().timestamp() - oldest).max(0) } else { 0 };
+        json!({
+            "enabled": self.running.load(Ordering::Relaxed),
+            "cursor": self.cursor.load(Ordering::Relaxed),
+            "pending": self.pending.load(Ordering::Relaxed),
+            "lag_seconds": lag_seconds,
+            "indexed_total": self.indexed.load(Ordering::Relaxed),
+            "batches_total": self.batches.load(Ordering::Relaxed),
+            "deferred_total": self.deferred.load(Ordering::Relaxed),
+            "failures_total": self.failures.load(Ordering::Relaxed),
+            "last_batch_ms": self.last_batch_ms.load(Ordering::Relaxed),
+        })
+    }
+}
+
+/// Start the worker, unless `MESSAGE_INDEX_BATCH=0` or encryption is on.
+/// Messages stored before it ran are embedded first.
+pub fn spawn(state: Arc<AppState>) {
+    if state.config().message_index_batch == 0 {
+        return;
+    }
+    if crypto::enabled() {
+        tracing::info!("Encryption at rest is on; messages are not embedded for semantic search");
+        return;
+    }
+    state.message_index.running.store(true, Ordering::Relaxed);
+    tokio::spawn(async move {
+        loop {
+            let config = state.config();
+            let (batch, interval) = (config.message_index_batch, config.message_index_interval_ms);
+            drop(config);
+            if batch > 0 && has_spare_connection(&state.db) {
+                match run_batch(&state.db, &state.message_index, batch).await {
+                    // More are waiting: go on, letting other tasks run first
+                    Ok(true) => {
+                        tokio::task::yield_now().await;
+                        continue;
+                    }
+                    Ok(false) => {}
+                    Err(e) => {
+                        state.message_index.failures.fetch_add(1, Ordering::Relaxed);
+                        tracing::warn!(error = %e, "Message indexing failed");
+                    }
+                }
+            } else if batch > 0 {
+                state.message_index.deferred.fetch_add(1, Ordering::Relaxed);
+            }
+            tokio::time::sleep(Duration::from_millis(interval.max(100))).await;
+        }
+    });
+}
+
+fn has_spare_connection(pool: &SqlitePool) -> bool {
+    pool.num_idle() > 0 || pool.size() < pool.options().get_max_connections()
+}
+
+/// Embed up to `batch` messages past the cursor. Returns whether more are
+/// waiting.
+async fn run_batch(pool: &SqlitePool, index: &MessageIndex, batch: usize) -> Result<bool, AppError> {
+    let started = Instant::now();
+    let cursor = index.cursor.load(Ordering::Relaxed);
+    let upto = db::max_message_id(pool).await?;
+    let messages = db::unindexed_messages(pool, cursor, upto, batch as i64).await?;
+    let full = messages.len() >= batch;
+    let mut reached = upto;
+    if let Some(&(last, _)) = messages.last() {
+        if full {
+            reached = last;
+        }
+        let count = messages.len() as u64;
+        let embeddings = tokio::task::spawn_blocking(move || {
+            messages.into_iter().map(|(id, content)| (id, embed(&content))).collect::<Vec<_>>()
+        })
+        .await
+        .map_err(|e| AppError::Internal(format!("Embedding task failed: {e}")))?;
+        db::store_message_embeddings(pool, &embeddings).await?;
+        index.indexed.fetch_add(count, Ordering::Relaxed);
+        index.batches.fetch_add(1, Ordering::Relaxed);
+        index.last_batch_ms.store(started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+    // A rewind in the meantime wins
+    let _ = index.cursor.compare_exchange(cursor, reached, Ordering::Relaxed, Ordering::Relaxed);
+
+    let (pending, oldest) = db::messages_after(pool, index.cursor.load(Ordering::Relaxed)).await?;
+    let oldest = oldest
+        .and_then(|t| chrono::NaiveDateTime::parse_from_str(&t, "%Y-%m-%d %H:%M:%S").ok())
+        .map_or(0, |t| t.and_utc().timestamp());
+    index.pending.store(pending, Ordering::Relaxed);
+    index.oldest_pending.store(oldest, Ordering::Relaxed);
+    Ok(full)
+}
+
+fn embed(content: &str) -> Vec<u8> {
+    embed_text(content, DIM).into_iter().flat_map(f32::to_le_bytes).collect()
+}
+
+/// Up to `limit` messages of active sessions closest in meaning to `query`,
+/// best first, with their scores. Messages not embedded yet are missed.
+pub async fn search(
+    pool: &SqlitePool,
+    query: &str,
+    project_id: Option<&str>,
+    limit: usize,
+) -> Result<Vec<(f32, MessageMatch)>, AppError> {
+    let query = embed_text(query, DIM);
+    let mut scored: Vec<(f32, MessageMatch)> = db::list_embedded_messages(pool, project_id)
+        .await?
+        .into_iter()
+        .map(|row| (rag::dot(&query, &row.embedding), row.message))
+        .filter(|(score, _)| *score >= MIN_SCORE)
+        .collect();
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(limit);
+    for (_, message) in &mut scored {
+        message.content = crypto::open(std::mem::take(&mut message.content));
+    }
+    Ok(scored)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_index_and_search() {
+        let dir = tempfile::tempdir().unwrap();
+        let pool = db::init_db(&format!("sqlite:{}", dir.path().join("t.db").display())).await.unwrap();
+        sqlx::query("INSERT INTO sessions (id, model) VALUES ('s1', 'm')").execute(&pool).await.unwrap();
+        let texts = ["How do I rotate the database credentials?", "Lunch menu for Friday", "Rotate credentials weekly"];
+        for text in texts {
+            db::add_message(&pool, "s1", "user", text, 0, 0, 0.0, &json!({})).await.unwrap();
+        }
+
+        let index = MessageIndex::default();
+        assert!(run_batch(&pool, &index, 2).await.unwrap(), "a full batch leaves more");
+        assert!(!run_batch(&pool, &index, 2).await.unwrap());
+        assert_eq!(index.stats()["indexed_total"], 3);
+        assert_eq!(index.stats()["pending"], 0);
+
+        let top = async |query: &str| -> Vec<String> {
+            search(&pool, query, None, 2).await.unwrap().into_iter().map(|(_, m)| m.content).collect()
+        };
+        assert_eq!(top("rotate credentials").await, [texts[2], texts[0]]);
+
+        // A rewritten message loses its embedding and is embedded again
+        sqlx::query("UPDATE messages SET content = 'Lunch at noon' WHERE id = 1").execute(&pool).await.unwrap();
+        assert_eq!(top("lunch").await, [texts[1]]);
+        index.rewind(1);
+        run_batch(&pool, &index, 10).await.unwrap();
+        assert_eq!(index.stats()["indexed_total"], 4);
+        assert_eq!(top("lunch").await, ["Lunch at noon", texts[1]]);
+    }
+}
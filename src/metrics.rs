@@ -0,0 +1,162 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::Mutex;
+
+use serde_json::{json, Value};
+
+use crate::claude::parser::LimitKind;
+use crate::claude::process::ProcessReport;
+use crate::db::DbSize;
+
+/// Samples kept per series for percentile estimates.
+const WINDOW: usize = 1024;
+
+/// Running count/sum/min/max plus a window of recent samples.
+#[derive(Default)]
+struct Series {
+    count: u64,
+    sum: u64,
+    min: Option<u64>,
+    max: Option<u64>,
+    recent: VecDeque<u64>,
+}
+
+impl Series {
+    fn record(&mut self, v: u64) {
+        self.count += 1;
+        self.sum += v;
+        self.min = Some(self.min.map_or(v, |m| m.min(v)));
+        self.max = Some(self.max.map_or(v, |m| m.max(v)));
+        if self.recent.len() == WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(v);
+    }
+
+    fn percentile(&self, p: f64) -> Option<u64> {
+        let mut sorted: Vec<u64> = self.recent.iter().copied().collect();
+        sorted.sort_unstable();
+        let idx = ((sorted.len() as f64 - 1.0) * p).round() as usize;
+        sorted.get(idx).copied()
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "count": self.count,
+            "avg": (self.count > 0).then(|| self.sum as f64 / self.count as f64),
+            "min": self.min,
+            "max": self.max,
+            "p50": self.percentile(0.5),
+            "p95": self.percentile(0.95),
+        })
+    }
+}
+
+#[derive(Default)]
+struct ProcessMetrics {
+    spawn_ms: Series,
+    ttft_ms: Series,
+    duration_ms: Series,
+    peak_rss_kb: Series,
+    exit_codes: BTreeMap<String, u64>,
+    limit_hits: BTreeMap<&'static str, u64>,
+    /// Runs Anthropic turned away, by [`LimitKind`].
+    upstream_limits: BTreeMap<&'static str, u64>,
+}
+
+/// Outcome of the last database maintenance run.
+struct Maintenance {
+    at: chrono::DateTime<chrono::Utc>,
+    duration_ms: u64,
+    vacuum: bool,
+    before: DbSize,
+    after: DbSize,
+}
+
+/// In-process aggregates of Claude CLI process lifecycles.
+#[derive(Default)]
+pub struct Metrics {
+    processes: Mutex<ProcessMetrics>,
+    maintenance: Mutex<Option<Maintenance>>,
+}
+
+impl Metrics {
+    pub fn record_process(&self, report: &ProcessReport) {
+        let Ok(mut m) = self.processes.lock() else { return };
+        if let Some(v) = report.spawn_ms {
+            m.spawn_ms.record(v);
+        }
+        if let Some(v) = report.ttft_ms {
+            m.ttft_ms.record(v);
+        }
+        m.duration_ms.record(report.duration_ms);
+        if let Some(v) = report.peak_rss_kb {
+            m.peak_rss_kb.record(v);
+        }
+        let code = report.exit_code.map_or("unknown".to_string(), |c| c.to_string());
+        *m.exit_codes.entry(code).or_default() += 1;
+        if let Some(hit) = report.limit_hit {
+            *m.limit_hits.entry(hit.as_str()).or_default() += 1;
+        }
+    }
+
+    pub fn record_upstream_limit(&self, kind: LimitKind) {
+        if let Ok(mut m) = self.processes.lock() {
+            *m.upstream_limits.entry(kind.as_str()).or_default() += 1;
+        }
+    }
+
+    pub fn record_maintenance(&self, duration_ms: u64, vacuum: bool, before: DbSize, after: DbSize) {
+        if let Ok(mut m) = self.maintenance.lock() {
+            *m = Some(Maintenance { at: chrono::Utc::now(), duration_ms, vacuum, before, after });
+        }
+    }
+
+    /// The last maintenance run, `null` if none has happened yet.
+    pub fn maintenance_snapshot(&self) -> Value {
+        let Ok(m) = self.maintenance.lock() else { return Value::Null };
+        m.as_ref().map_or(Value::Null, |m| {
+            json!({
+                "at": m.at.to_rfc3339(),
+                "duration_ms": m.duration_ms,
+                "vacuum": m.vacuum,
+                "before": m.before,
+                "after": m.after,
+            })
+        })
+    }
+
+    pub fn snapshot(&self) -> Value {
+        let Ok(m) = self.processes.lock() else { return Value::Null };
+        json!({
+            "processes": {
+                "spawn_ms": m.spawn_ms.to_json(),
+                "ttft_ms": m.ttft_ms.to_json(),
+                "duration_ms": m.duration_ms.to_json(),
+                "peak_rss_kb": m.peak_rss_kb.to_json(),
+                "exit_codes": m.exit_codes,
+                "limit_hits": m.limit_hits,
+                "upstream_limits": m.upstream_limits,
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_series_summary() {
+        let mut s = Series::default();
+        for v in 1..=100 {
+            s.record(v);
+        }
+        let j = s.to_json();
+        assert_eq!(j["count"], 100);
+        assert_eq!(j["min"], 1);
+        assert_eq!(j["max"], 100);
+        assert_eq!(j["p50"], 51);
+        assert_eq!(j["p95"], 95);
+        assert_eq!(Series::default().to_json()["p50"], Value::Null);
+    }
+}
@@ -0,0 +1,349 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::pricing::ModelPrice;
+
+/// What an unknown, non-`claude-` model name resolves to.
+const FALLBACK_MODEL: &str = "claude-sonnet-4-5-20250929";
+
+const DEFAULT_CAPABILITIES: &[&str] = &["chat", "streaming", "tools", "vision", "files"];
+
+/// Models served by the Claude CLI out of the box: `(id, aliases)`.
+const BUILTIN_MODELS: &[(&str, &[&str])] = &[
+    ("claude-opus-4-6", &[]),
+    ("claude-sonnet-4-5-20250929", &["cc-sonnet-45"]),
+    ("claude-haiku-4-5-20251001", &["cc-haiku-45"]),
+    ("claude-3-7-sonnet-20250219", &[]),
+];
+
+/// Context window in tokens per model prefix, for models without a catalog
+/// entry; the longest match wins.
+const CONTEXT_WINDOWS: &[(&str, u32)] = &[
+    ("claude-opus-4", 200_000),
+    ("claude-sonnet-4", 200_000),
+    ("claude-haiku-4", 200_000),
+    ("claude-3", 200_000),
+    ("claude-2.1", 200_000),
+    ("claude-2", 100_000),
+    ("claude-instant", 100_000),
+];
+
+/// Most output tokens per model prefix, for entries that don't set
+/// `max_output_tokens`; the longest match wins.
+const MAX_OUTPUT_TOKENS: &[(&str, u32)] = &[
+    ("claude-opus-4-6", 128_000),
+    ("claude-opus-4-5", 64_000),
+    ("claude-opus-4", 32_000),
+    ("claude-sonnet-4", 64_000),
+    ("claude-haiku-4", 64_000),
+    ("claude-3-7-sonnet", 64_000),
+    ("claude-3-5", 8_192),
+    ("claude-3", 4_096),
+];
+
+fn longest_prefix(table: &[(&str, u32)], model: &str) -> Option<u32> {
+    table
+        .iter()
+        .filter(|(prefix, _)| model.starts_with(prefix))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|&(_, value)| value)
+}
+
+/// One `/v1/models` entry. Requests may name it by `id` or any alias;
+/// aliases resolve to `id`, which is what the CLI is run with.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelEntry {
+    pub id: String,
+    #[serde(default)]
+    pub aliases: Vec<String>,
+    #[serde(default)]
+    pub context_window: Option<u32>,
+    #[serde(default)]
+    pub max_output_tokens: Option<u32>,
+    /// Overrides the built-in price table for this model.
+    #[serde(default)]
+    pub pricing: Option<ModelPrice>,
+    #[serde(default = "default_capabilities")]
+    pub capabilities: Vec<String>,
+    /// Unix timestamp reported as `created`.
+    #[serde(default)]
+    pub created: Option<i64>,
+}
+
+fn default_capabilities() -> Vec<String> {
+    DEFAULT_CAPABILITIES.iter().map(|c| c.to_string()).collect()
+}
+
+/// The layout of `MODEL_CATALOG_FILE` and the config file's `[catalog]`.
+#[derive(Debug, Clone, Deserialize)]
+pub(crate) struct CatalogFile {
+    /// Drop the built-in models instead of merging into them.
+    #[serde(default)]
+    pub(crate) replace: bool,
+    #[serde(default)]
+    pub(crate) models: Vec<ModelEntry>,
+    /// Extra `alias = "model"` mappings, e.g. `"gpt-4o" = "cc-sonnet-45"`.
+    #[serde(default)]
+    pub(crate) aliases: std::collections::BTreeMap<String, String>,
+}
+
+/// The models the gateway advertises and how request model names resolve.
+#[derive(Debug, Clone)]
+pub struct ModelCatalog {
+    models: Vec<ModelEntry>,
+    /// Aliases for models outside the catalog, e.g. `gpt-4o` to a dated
+    /// `claude-` name; aliases of catalog models live on their entries.
+    aliases: Vec<(String, String)>,
+}
+
+impl Default for ModelCatalog {
+    fn default() -> Self {
+        let models = BUILTIN_MODELS
+            .iter()
+            .map(|&(id, aliases)| ModelEntry {
+                id: id.to_string(),
+                aliases: aliases.iter().map(|a| a.to_string()).collect(),
+                context_window: None,
+                max_output_tokens: None,
+                pricing: None,
+                capabilities: default_capabilities(),
+                created: None,
+            })
+            .collect();
+        Self { models, aliases: Vec::new() }
+    }
+}
+
+impl ModelCatalog {
+    /// The built-in models, merged with (or, with `replace = true`, replaced
+    /// by) the entries of `inline` (the config file's `[catalog]`) and then
+    /// of `file` (JSON, or TOML by `.toml` extension), then their `aliases`
+    /// and `MODEL_ALIASES` (`alias` to model). Entries replace earlier ones
+    /// with the same `id`. An unreadable file is logged and ignored.
+    pub(crate) fn load(inline: Option<CatalogFile>, file: Option<&Path>, aliases: &[(String, String)]) -> Self {
+        let mut catalog = Self::default();
+        let mut file_aliases = Vec::new();
+        let from_file = file.and_then(|path| {
+            read_file(path)
+                .map_err(|e| tracing::warn!(path = %path.display(), error = %e, "Ignoring model catalog file"))
+                .ok()
+        });
+        for parsed in inline.into_iter().chain(from_file) {
+            if parsed.replace {
+                catalog.models.clear();
+            }
+            parsed.models.into_iter().for_each(|m| catalog.insert(m));
+            file_aliases.extend(parsed.aliases);
+        }
+        for (alias, target) in file_aliases.iter().chain(aliases) {
+            catalog.add_alias(alias, target);
+        }
+        catalog
+    }
+
+    /// Point `alias` at `target`: a catalog id or alias, or any `claude-`
+    /// model. Later definitions of the same alias win.
+    fn add_alias(&mut self, alias: &str, target: &str) {
+        let id = match self.get(target) {
+            Some(entry) => Some(entry.id.clone()),
+            None => self.alias_target(target).map(str::to_string),
+        };
+        self.models.iter_mut().for_each(|m| m.aliases.retain(|a| a != alias));
+        self.aliases.retain(|(a, _)| a != alias);
+        match id {
+            Some(id) => {
+                if let Some(entry) = self.models.iter_mut().find(|m| m.id == id) {
+                    entry.aliases.push(alias.to_string());
+                } else {
+                    self.aliases.push((alias.to_string(), id));
+                }
+            }
+            None if target.starts_with("claude-") => {
+                self.aliases.push((alias.to_string(), target.to_string()))
+            }
+            None => tracing::warn!(alias, model = target, "Ignoring alias for unknown model"),
+        }
+    }
+
+    fn alias_target(&self, alias: &str) -> Option<&str> {
+        self.aliases.iter().find(|(a, _)| a == alias).map(|(_, target)| target.as_str())
+    }
+
+    /// Aliases of models outside the catalog, as `(alias, model)`.
+    pub fn extra_aliases(&self) -> &[(String, String)] {
+        &self.aliases
+    }
+
+    fn insert(&mut self, entry: ModelEntry) {
+        match self.models.iter_mut().find(|m| m.id == entry.id) {
+            Some(existing) => *existing = entry,
+            None => self.models.push(entry),
+        }
+    }
+
+    pub fn entries(&self) -> &[ModelEntry] {
+        &self.models
+    }
+
+    /// The entry `model` names by id or alias.
+    pub fn get(&self, model: &str) -> Option<&ModelEntry> {
+        self.models
+            .iter()
+            .find(|m| m.id == model)
+            .or_else(|| self.models.iter().find(|m| m.aliases.iter().any(|a| a == model)))
+    }
+
+    /// The Claude CLI model a request's model name stands for: catalog ids
+    /// and aliases, user-defined aliases, then any `claude-` name as-is.
+    /// `None` for unknown models.
+    pub fn lookup(&self, model: &str) -> Option<String> {
+        if let Some(entry) = self.get(model) {
+            return Some(entry.id.clone());
+        }
+        if let Some(target) = self.alias_target(model) {
+            return Some(target.to_string());
+        }
+        model.starts_with("claude-").then(|| model.to_string())
+    }
+
+    /// [`lookup`](Self::lookup), falling back to the default model for
+    /// unknown names.
+    pub fn resolve(&self, model: &str) -> String {
+        self.lookup(model).unwrap_or_else(|| {
+            tracing::warn!(model, "Unknown model, falling back to {FALLBACK_MODEL}");
+            FALLBACK_MODEL.to_string()
+        })
+    }
+
+    /// The context window of a resolved model: its catalog entry's, else
+    /// the built-in per-prefix value.
+    pub fn context_window(&self, model: &str) -> Option<u32> {
+        self.get(model)
+            .and_then(|m| m.context_window)
+            .or_else(|| longest_prefix(CONTEXT_WINDOWS, model))
+    }
+
+    /// The most tokens a resolved model can generate in one completion.
+    pub fn max_output_tokens(&self, model: &str) -> Option<u32> {
+        self.get(model)
+            .and_then(|m| m.max_output_tokens)
+            .or_else(|| longest_prefix(MAX_OUTPUT_TOKENS, model))
+    }
+
+    /// The capabilities of a resolved model; models outside the catalog
+    /// get the defaults.
+    pub fn capabilities(&self, model: &str) -> Vec<String> {
+        self.get(model).map_or_else(default_capabilities, |m| m.capabilities.clone())
+    }
+
+    /// Every alias that resolves to `model`.
+    pub fn aliases_of(&self, model: &str) -> Vec<String> {
+        let entry = self.models.iter().find(|m| m.id == model);
+        entry
+            .into_iter()
+            .flat_map(|m| m.aliases.iter().cloned())
+            .chain(self.aliases.iter().filter(|(_, t)| t == model).map(|(a, _)| a.clone()))
+            .collect()
+    }
+
+    /// Prices configured on catalog entries, by model id.
+    pub fn prices(&self) -> impl Iterator<Item = (&str, ModelPrice)> {
+        self.models.iter().filter_map(|m| Some((m.id.as_str(), m.pricing?)))
+    }
+}
+
+fn read_file(path: &Path) -> Result<CatalogFile, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    if path.extension().is_some_and(|ext| ext == "toml") {
+        toml::from_str(&text).map_err(|e| e.to_string())
+    } else {
+        serde_json::from_str(&text).map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve() {
+        let catalog = ModelCatalog::default();
+        assert_eq!(catalog.resolve("cc-sonnet-45"), "claude-sonnet-4-5-20250929");
+        assert_eq!(catalog.resolve("cc-haiku-45"), "claude-haiku-4-5-20251001");
+        assert_eq!(catalog.resolve("claude-opus-4-6"), "claude-opus-4-6");
+        assert_eq!(catalog.resolve("claude-3-7-sonnet-20250219"), "claude-3-7-sonnet-20250219");
+        assert_eq!(catalog.resolve("claude-opus-5-0"), "claude-opus-5-0");
+        assert_eq!(catalog.resolve("gpt-4"), FALLBACK_MODEL);
+        assert_eq!(catalog.lookup("gpt-4"), None);
+    }
+
+    #[test]
+    fn test_context_window() {
+        let catalog = ModelCatalog::default();
+        assert_eq!(catalog.context_window("claude-sonnet-4-5-20250929"), Some(200_000));
+        assert_eq!(catalog.context_window("claude-2.1"), Some(200_000));
+        assert_eq!(catalog.context_window("claude-2.0"), Some(100_000));
+        assert_eq!(catalog.context_window("gpt-5"), None);
+        assert_eq!(catalog.max_output_tokens("claude-opus-4-1-20250805"), Some(32_000));
+        assert_eq!(catalog.max_output_tokens("claude-3-5-haiku-20241022"), Some(8_192));
+    }
+
+    #[test]
+    fn test_load_toml() {
+        let path = std::env::temp_dir().join(format!("catalog-{}.toml", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"
+            [[models]]
+            id = "claude-opus-4-7"
+            aliases = ["cc-opus"]
+            context_window = 1000000
+            pricing = { input = 5.0, output = 25.0 }
+            capabilities = ["chat", "tools"]
+
+            [[models]]
+            id = "claude-haiku-4-5-20251001"
+            "#,
+        )
+        .unwrap();
+        let aliases = vec![
+            ("fast".to_string(), "claude-haiku-4-5-20251001".to_string()),
+            ("nope".to_string(), "claude-missing".to_string()),
+        ];
+        let catalog = ModelCatalog::load(None, Some(&path), &aliases);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(catalog.entries().len(), 5);
+        assert_eq!(catalog.resolve("cc-opus"), "claude-opus-4-7");
+        assert_eq!(catalog.context_window("claude-opus-4-7"), Some(1_000_000));
+        // The file entry replaced the built-in one, dropping its alias
+        assert_eq!(catalog.get("claude-haiku-4-5-20251001").unwrap().aliases, ["fast"]);
+        assert_eq!(catalog.resolve("cc-haiku-45"), FALLBACK_MODEL);
+        assert_eq!(catalog.prices().collect::<Vec<_>>().len(), 1);
+    }
+
+    #[test]
+    fn test_user_aliases() {
+        let aliases = [
+            ("gpt-4o", "cc-sonnet-45"),
+            ("gpt-4o-mini", "claude-haiku-4-5"),
+            ("o1", "gpt-4o"),
+            ("o3", "gpt-5"),
+            ("gpt-4o", "claude-opus-4-6"),
+        ]
+        .map(|(a, m)| (a.to_string(), m.to_string()));
+        let catalog = ModelCatalog::load(None, None, &aliases);
+
+        // Redefinitions win; aliases of aliases resolve to the final model
+        assert_eq!(catalog.resolve("gpt-4o"), "claude-opus-4-6");
+        assert_eq!(catalog.resolve("o1"), "claude-sonnet-4-5-20250929");
+        assert_eq!(catalog.resolve("gpt-4o-mini"), "claude-haiku-4-5");
+        assert_eq!(catalog.aliases_of("claude-haiku-4-5"), ["gpt-4o-mini"]);
+        assert_eq!(catalog.aliases_of("claude-opus-4-6"), ["gpt-4o"]);
+        assert_eq!(catalog.extra_aliases(), [("gpt-4o-mini".to_string(), "claude-haiku-4-5".to_string())]);
+        assert!(!catalog.get("claude-sonnet-4-5-20250929").unwrap().aliases.contains(&"gpt-4o".to_string()));
+        // Targets that are neither known nor `claude-` models are dropped
+        assert_eq!(catalog.resolve("o3"), FALLBACK_MODEL);
+    }
+}
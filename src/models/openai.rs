@@ -1,9 +1,19 @@
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+/// File name prefix of the images [`ChatMessage::extract_images`] saves in
+/// the temp dir.
+pub const IMAGE_FILE_PREFIX: &str = "claude_image_";
 
 // -- Request types --
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, ToSchema)]
 pub struct ChatCompletionRequest {
+    /// May be omitted when `session_id` names a session, whose stored
+    /// model is then used.
+    #[serde(default)]
     pub model: String,
     pub messages: Vec<ChatMessage>,
     #[serde(default)]
@@ -27,15 +37,55 @@ pub struct ChatCompletionRequest {
     #[serde(default)]
     pub tool_choice: Option<serde_json::Value>,
     // Extension fields
+    /// Project (working directory) to run in; `default` when omitted.
     #[serde(default)]
     pub project_id: Option<String>,
+    /// Continue a stored session, replaying its history before `messages`.
+    /// The session's model and system prompt are the defaults.
     #[serde(default)]
     pub session_id: Option<String>,
+    /// System prompt used when `messages` has no system message.
     #[serde(default)]
     pub system_prompt: Option<String>,
+    /// Stored prompt (ID or name, see `/v1/prompts`) rendered with
+    /// `variables` into `system_prompt`.
+    #[serde(default)]
+    pub prompt_id: Option<String>,
+    /// Version of `prompt_id` to use; the latest when omitted.
+    #[serde(default)]
+    pub prompt_version: Option<i64>,
+    /// Values for the prompt's `{{variable}}` placeholders.
+    #[serde(default)]
+    pub variables: Option<serde_json::Map<String, serde_json::Value>>,
+    /// Named Claude profile to run on, overriding model-prefix routing.
+    #[serde(default)]
+    pub profile: Option<String>,
+    /// Interleave named `claude.tool_use` / `claude.tool_result` events
+    /// with the stream's chunks; defaults to `STREAM_PROGRESS`.
+    #[serde(default)]
+    pub stream_progress: Option<bool>,
+    /// Subagent of the project (see `/v1/projects/{id}/agents`) to hand
+    /// the request to.
+    #[serde(default)]
+    pub agent: Option<String>,
+    /// `plan` runs the CLI in plan mode: the reply is a proposed plan,
+    /// also returned as `plan`, and nothing is changed. `default` when
+    /// omitted.
+    #[serde(default)]
+    pub mode: Option<String>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// Body of `POST /v1/chat/completions/compare`: a chat completion request
+/// whose `model` is replaced by `models`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CompareRequest {
+    /// Models (or aliases) to run the request against, at least two.
+    pub models: Vec<String>,
+    #[serde(flatten)]
+    pub request: ChatCompletionRequest,
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct ChatMessage {
     pub role: String,
     #[serde(default)]
@@ -118,7 +168,7 @@ impl ChatMessage {
                 Ok(bytes) => {
                     let path =
                         std::env::temp_dir()
-                            .join(format!("claude_image_{}.{}", uuid::Uuid::new_v4(), ext))
+                            .join(format!("{IMAGE_FILE_PREFIX}{}.{}", uuid::Uuid::new_v4(), ext))
                             .to_string_lossy()
                             .to_string();
                     if std::fs::write(&path, &bytes).is_ok() {
@@ -132,16 +182,106 @@ impl ChatMessage {
         }
         paths
     }
+
+    /// Save base64 document blocks (`file` / `input_file`, e.g. PDFs or
+    /// text files) into `dir` and return their paths.
+    pub fn extract_files(&self, dir: &std::path::Path) -> Vec<String> {
+        use base64::Engine;
+
+        let arr = match &self.content {
+            Some(serde_json::Value::Array(a)) => a,
+            _ => return vec![],
+        };
+
+        let mut paths = Vec::new();
+        for item in arr {
+            // Chat Completions nest the file under `file`; the Responses API doesn't
+            let file = match item.get("type").and_then(|v| v.as_str()) {
+                Some("file") => item.get("file").unwrap_or(item),
+                Some("input_file") => item,
+                _ => continue,
+            };
+            // Uploaded files are referenced by `file_id` instead, see `file_ids`
+            let Some(data) = file.get("file_data").and_then(|v| v.as_str()) else {
+                continue;
+            };
+            let (mime, encoded) = match data.strip_prefix("data:").and_then(|d| d.split_once(',')) {
+                Some((header, encoded)) => (header.trim_end_matches(";base64"), encoded),
+                None => ("", data),
+            };
+            let bytes = match base64::engine::general_purpose::STANDARD.decode(encoded.trim()) {
+                Ok(bytes) => bytes,
+                Err(e) => {
+                    tracing::warn!(error = %e, "Failed to decode base64 file");
+                    continue;
+                }
+            };
+            let name = file
+                .get("filename")
+                .and_then(|v| v.as_str())
+                .map(sanitize_filename)
+                .filter(|n| !n.is_empty())
+                .unwrap_or_else(|| format!("document.{}", extension_for_mime(mime)));
+            let path = dir.join(format!("{}-{name}", uuid::Uuid::new_v4().as_simple()));
+            match std::fs::create_dir_all(dir).and_then(|_| std::fs::write(&path, &bytes)) {
+                Ok(()) => paths.push(path.to_string_lossy().to_string()),
+                Err(e) => tracing::warn!(error = %e, "Failed to save uploaded file"),
+            }
+        }
+        paths
+    }
+
+    /// IDs of `/v1/files` uploads referenced by document blocks.
+    pub fn file_ids(&self) -> Vec<String> {
+        let Some(serde_json::Value::Array(arr)) = &self.content else {
+            return vec![];
+        };
+        arr.iter()
+            .filter_map(|item| {
+                let file = match item.get("type")?.as_str()? {
+                    "file" => item.get("file").unwrap_or(item),
+                    "input_file" => item,
+                    _ => return None,
+                };
+                if file.get("file_data").is_some() {
+                    return None;
+                }
+                Some(file.get("file_id")?.as_str()?.to_string())
+            })
+            .collect()
+    }
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+/// The last path component of `name`, limited to `[A-Za-z0-9._-]`.
+pub fn sanitize_filename(name: &str) -> String {
+    let base = name.rsplit(['/', '\\']).next().unwrap_or_default();
+    base.chars()
+        .map(|c| if c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | '_') { c } else { '_' })
+        .collect::<String>()
+        .trim_start_matches('.')
+        .to_string()
+}
+
+fn extension_for_mime(mime: &str) -> &'static str {
+    match mime {
+        "application/pdf" => "pdf",
+        "text/plain" => "txt",
+        "text/markdown" => "md",
+        "text/csv" => "csv",
+        "text/html" => "html",
+        "application/json" => "json",
+        _ => "bin",
+    }
+}
+
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct Tool {
     #[serde(rename = "type")]
     pub tool_type: String,
     pub function: ToolFunction,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct ToolFunction {
     pub name: String,
     #[serde(default)]
@@ -150,7 +290,7 @@ pub struct ToolFunction {
     pub parameters: Option<serde_json::Value>,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct ToolCall {
     pub id: String,
     #[serde(rename = "type")]
@@ -158,7 +298,7 @@ pub struct ToolCall {
     pub function: FunctionCall,
 }
 
-#[derive(Debug, Deserialize, Serialize, Clone)]
+#[derive(Debug, Deserialize, Serialize, Clone, ToSchema)]
 pub struct FunctionCall {
     pub name: String,
     pub arguments: String,
@@ -166,7 +306,7 @@ pub struct FunctionCall {
 
 // -- Response types --
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ChatCompletionResponse {
     pub id: String,
     pub object: String,
@@ -178,16 +318,74 @@ pub struct ChatCompletionResponse {
     pub session_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub project_id: Option<String>,
+    /// The proposed plan of a `mode: "plan"` completion.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub plan: Option<Plan>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metadata: Option<CompletionMetadata>,
+}
+
+/// Gateway extensions to a completion.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CompletionMetadata {
+    pub timing: Timing,
+}
+
+/// Where a completion's time went, in milliseconds since the request
+/// arrived at the gateway.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Timing {
+    /// Until the CLI was started: validation, history, retrieval, hooks.
+    pub gateway_ms: u64,
+    /// From starting the CLI until it printed its first line.
+    pub spawn_ms: Option<u64>,
+    /// Until the first completion text (time to first token).
+    pub ttft_ms: Option<u64>,
+    /// From the first token until the completion was done.
+    pub generation_ms: Option<u64>,
+    pub total_ms: u64,
+}
+
+/// A plan proposed in plan mode.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+pub struct Plan {
+    /// The plan as Claude wrote it (Markdown).
+    pub text: String,
+    /// Its top-level list items: the numbered ones, else the bullets.
+    pub steps: Vec<String>,
+}
+
+impl Plan {
+    pub fn parse(text: &str) -> Self {
+        let numbered: Vec<String> = text
+            .lines()
+            .filter_map(|line| {
+                let rest = line.trim_start_matches(|c: char| c.is_ascii_digit());
+                let digits = line.len() - rest.len();
+                let item = rest.strip_prefix(". ").or_else(|| rest.strip_prefix(") "));
+                item.filter(|_| digits > 0).map(|item| item.trim().to_string())
+            })
+            .collect();
+        let steps = if numbered.is_empty() {
+            text.lines()
+                .filter_map(|line| line.strip_prefix("- ").or_else(|| line.strip_prefix("* ")))
+                .map(|item| item.trim().to_string())
+                .collect()
+        } else {
+            numbered
+        };
+        Self { text: text.to_string(), steps }
+    }
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ChatCompletionChoice {
     pub index: u32,
     pub message: ChatMessageResponse,
     pub finish_reason: String,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ChatMessageResponse {
     pub role: String,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -196,7 +394,7 @@ pub struct ChatMessageResponse {
     pub tool_calls: Option<Vec<ToolCall>>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ChatCompletionUsage {
     pub prompt_tokens: u32,
     pub completion_tokens: u32,
@@ -205,7 +403,7 @@ pub struct ChatCompletionUsage {
 
 // -- Streaming chunk types --
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ChatCompletionChunk {
     pub id: String,
     pub object: String,
@@ -214,14 +412,14 @@ pub struct ChatCompletionChunk {
     pub choices: Vec<ChunkChoice>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ChunkChoice {
     pub index: u32,
     pub delta: ChunkDelta,
     pub finish_reason: Option<String>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ChunkDelta {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub role: Option<String>,
@@ -233,16 +431,16 @@ pub struct ChunkDelta {
 
 // -- Embedding types --
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 pub struct EmbeddingRequest {
     pub input: EmbeddingInput,
     #[serde(default = "default_embedding_model")]
     pub model: String,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub encoding_format: Option<String>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub dimensions: Option<usize>,
-    #[serde(default)]
+    #[serde(default, skip_serializing_if = "Option::is_none")]
     pub user: Option<String>,
 }
 
@@ -250,14 +448,14 @@ fn default_embedding_model() -> String {
     "text-embedding-local".to_string()
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, Serialize, ToSchema)]
 #[serde(untagged)]
 pub enum EmbeddingInput {
     Single(String),
     Multiple(Vec<String>),
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct EmbeddingResponse {
     pub object: String,
     pub data: Vec<EmbeddingData>,
@@ -265,14 +463,14 @@ pub struct EmbeddingResponse {
     pub usage: EmbeddingUsage,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct EmbeddingData {
     pub object: String,
     pub index: u32,
     pub embedding: Vec<f32>,
 }
 
-#[derive(Debug, Serialize)]
+#[derive(Debug, Serialize, ToSchema)]
 pub struct EmbeddingUsage {
     pub prompt_tokens: u32,
     pub total_tokens: u32,
@@ -280,16 +478,122 @@ pub struct EmbeddingUsage {
 
 // -- Other types --
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateProjectRequest {
     pub name: String,
     #[serde(default)]
     pub description: Option<String>,
     #[serde(default)]
     pub path: Option<String>,
+    /// Prepend relevant chunks of the project's workspace files to its
+    /// prompts; the workspace is indexed on first use.
+    #[serde(default)]
+    pub rag: bool,
+}
+
+/// Body of `PUT /v1/projects/{project_id}/agents/{name}`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PutAgentRequest {
+    /// When to use the agent; Claude delegates to it based on this.
+    pub description: String,
+    /// The agent's system prompt.
+    pub prompt: String,
+    /// Tools the agent may use (`Read`, `Grep`, ...); all of the
+    /// session's when omitted.
+    #[serde(default)]
+    pub tools: Option<Vec<String>>,
+    /// Model alias (`sonnet`, `opus`, `haiku`) or `inherit`.
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// Body of `POST /v1/tasks`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreateTaskRequest {
+    /// What to change, as you would tell Claude Code.
+    pub instruction: String,
+    /// Project whose workspace, a git repository, to work on; `default`
+    /// when omitted.
+    #[serde(default)]
+    pub project_id: Option<String>,
+    /// Commit, branch or tag to start from; `HEAD` when omitted.
+    #[serde(default, rename = "ref")]
+    pub git_ref: Option<String>,
+    /// Defaults to `DEFAULT_MODEL`.
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Push the changes to GitHub and open a pull request with them.
+    #[serde(default)]
+    pub pull_request: Option<PullRequestOptions>,
+}
+
+/// Where a task opens its pull request, see [`crate::github`].
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct PullRequestOptions {
+    /// `owner/name` of the GitHub repository.
+    pub repo: String,
+    /// Branch to merge into; the repository's default branch when omitted.
+    #[serde(default)]
+    pub base: Option<String>,
+    /// Defaults to the first line of the instruction.
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default)]
+    pub draft: bool,
+    /// Token allowed to push to and open pull requests on `repo`; when
+    /// omitted, `GITHUB_TOKEN` if `GITHUB_TOKEN_REPOS` lists `repo`. Not
+    /// stored.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+/// Body of `PUT /v1/projects/{project_id}/hooks`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PutHooksRequest {
+    /// Matchers by event (`PreToolUse`, `PostToolUse`, ...), as in Claude
+    /// Code's `settings.json`.
+    pub hooks: BTreeMap<String, Vec<HookMatcher>>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct HookMatcher {
+    /// Tool name pattern (`Bash`, `Edit|Write`); every tool when omitted.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub matcher: Option<String>,
+    pub hooks: Vec<HookCommand>,
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize, ToSchema)]
+pub struct HookCommand {
+    /// Always `command`.
+    #[serde(rename = "type")]
+    pub kind: String,
+    /// Must be allowed by `HOOK_COMMAND_ALLOWLIST`.
+    pub command: String,
+    /// Seconds before the CLI gives up on the command.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreatePromptRequest {
+    /// Unique name; chat requests may use it in place of the ID.
+    pub name: String,
+    /// System prompt text with `{{variable}}` placeholders.
+    pub template: String,
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CreatePromptVersionRequest {
+    pub template: String,
+    /// Replaces the description; kept from the previous version when omitted.
+    #[serde(default)]
+    pub description: Option<String>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
 pub struct CreateSessionRequest {
     pub project_id: String,
     #[serde(default)]
@@ -299,3 +603,71 @@ pub struct CreateSessionRequest {
     #[serde(default)]
     pub system_prompt: Option<String>,
 }
+
+/// Body of `POST /v1/sessions/{session_id}/commands`.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SessionCommandRequest {
+    /// Slash command, built-in (`/review`) or from the project's
+    /// `.claude/commands` (`/deploy`, `/frontend:lint`).
+    pub command: String,
+    /// Text passed to the command after its name.
+    #[serde(default)]
+    pub arguments: Option<String>,
+    /// Model to run the command on; defaults to the session's.
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub stream: Option<bool>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plan_steps() {
+        let plan = Plan::parse("## Plan\n\n1. Add the route\n   - nested detail\n2) Write tests\n10. Ship\n");
+        assert_eq!(plan.steps, ["Add the route", "Write tests", "Ship"]);
+        let plan = Plan::parse("- Read config\n* Patch it\n  - not top level\n");
+        assert_eq!(plan.steps, ["Read config", "Patch it"]);
+        assert!(Plan::parse("Just prose, v1.2 of it.").steps.is_empty());
+    }
+
+    #[test]
+    fn test_compare_request() {
+        let body: CompareRequest = serde_json::from_value(serde_json::json!({
+            "models": ["sonnet", "haiku"],
+            "messages": [{"role": "user", "content": "hi"}],
+            "temperature": 0.2
+        }))
+        .unwrap();
+        assert_eq!(body.models, ["sonnet", "haiku"]);
+        assert_eq!((body.request.model.as_str(), body.request.temperature), ("", Some(0.2)));
+        assert_eq!(body.request.messages.len(), 1);
+    }
+
+    #[test]
+    fn test_extract_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let msg: ChatMessage = serde_json::from_value(serde_json::json!({
+            "role": "user",
+            "content": [
+                {"type": "text", "text": "Summarize these"},
+                {"type": "file", "file": {"filename": "../../etc/report q1.pdf", "file_data": "data:application/pdf;base64,JVBERi0="}},
+                {"type": "input_file", "file_data": "aGVsbG8="},
+                {"type": "file", "file": {"file_data": "not base64!"}},
+                {"type": "file", "file": {"file_id": "file-abc"}}
+            ]
+        }))
+        .unwrap();
+
+        let paths = msg.extract_files(dir.path());
+        assert_eq!(paths.len(), 2);
+        assert!(paths[0].ends_with("-report_q1.pdf"), "{}", paths[0]);
+        assert!(paths[1].ends_with("-document.bin"), "{}", paths[1]);
+        assert_eq!(std::fs::read(&paths[0]).unwrap(), b"%PDF-");
+        assert_eq!(std::fs::read(&paths[1]).unwrap(), b"hello");
+        assert!(paths.iter().all(|p| std::path::Path::new(p).starts_with(dir.path())));
+        assert_eq!(msg.file_ids(), ["file-abc"]);
+    }
+}
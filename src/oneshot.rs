@@ -0,0 +1,103 @@
+//! Single-prompt CLI runs the gateway makes on its own behalf (history
+//! summaries, guardrail classification), outside any client session.
+
+use futures::StreamExt;
+
+use crate::budget;
+use crate::claude::env::build_env;
+use crate::claude::manager::create_project_directory;
+use crate::claude::parser::{
+    extract_assistant_content, extract_usage, is_assistant_message, is_result_message, UsageInfo,
+};
+use crate::claude::process::SpawnOptions;
+use crate::error::AppError;
+use crate::priority::Priority;
+use crate::quota;
+use crate::state::AppState;
+use crate::tokens;
+
+/// The outcome of a [`run`].
+#[derive(Debug, Clone)]
+pub struct Oneshot {
+    pub text: String,
+    /// Usage as reported, or estimated and costed from the pricing table.
+    pub usage: UsageInfo,
+    /// The resolved model the run used.
+    pub model: String,
+}
+
+/// Run `prompt` once on the profile that serves `model`, billed to the
+/// caller's account (by API key or project) and counted against the
+/// session limit like any other run, queued at `priority`. Fails if the
+/// reply is empty.
+pub async fn run(
+    state: &AppState,
+    model: &str,
+    prompt: &str,
+    api_key: Option<&str>,
+    project_id: &str,
+    priority: Priority,
+) -> Result<Oneshot, AppError> {
+    let config = state.config();
+    let resolved = config.model_catalog.resolve(model);
+    let mut profile = config
+        .select_profile(None, &[model, &resolved])
+        .cloned()
+        .unwrap_or_else(|| config.default_profile().clone());
+    let model = if profile.backend == "claude" { resolved } else { model.to_string() };
+    if let Some(dir) = config.account_config_dir(api_key, project_id) {
+        profile.config_dir = Some(dir.to_path_buf());
+    }
+
+    let project_dir = create_project_directory(&config.project_root, project_id)?;
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let (mut stream, claude_sid) = state
+        .claude_manager
+        .create_session(
+            &session_id,
+            &profile,
+            SpawnOptions {
+                prompt,
+                model: &model,
+                system_prompt: None,
+                append_system_prompt: None,
+                disable_builtin_tools: true,
+                env: build_env(&config, &profile, Some(project_id)),
+                project_dir: &project_dir,
+                sandbox: state.sandbox.as_ref(),
+                limits: state.limits.as_ref(),
+                resume: None,
+                plan: false,
+                priority,
+            },
+        )
+        .await?;
+
+    let mut parts = Vec::new();
+    let mut reported = None;
+    while let Some(msg) = stream.next().await {
+        if is_assistant_message(&msg) {
+            if let Some(text) = extract_assistant_content(&msg) {
+                parts.push(text);
+            }
+        }
+        if is_result_message(&msg) {
+            reported = extract_usage(&msg);
+            break;
+        }
+    }
+    state
+        .claude_manager
+        .session_finished(claude_sid.as_deref().unwrap_or(&session_id))
+        .await;
+
+    let text = parts.join("\n");
+    if text.trim().is_empty() {
+        return Err(AppError::ServiceUnavailable(format!("{model} returned no text")));
+    }
+    let (mut usage, _) = tokens::fill_usage(reported, prompt, &text);
+    state.pricing().fill_cost(&model, &mut usage);
+    budget::record(state, usage.cost_usd).await;
+    quota::record_tokens(state, api_key, usage.input_tokens + usage.output_tokens).await;
+    Ok(Oneshot { text, usage, model })
+}
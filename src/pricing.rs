@@ -0,0 +1,163 @@
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+
+use crate::claude::parser::UsageInfo;
+use crate::models::catalog::ModelCatalog;
+
+/// USD per million tokens.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ModelPrice {
+    pub input: f64,
+    pub output: f64,
+}
+
+/// Anthropic list prices, keyed by model prefix.
+const DEFAULT_PRICES: &[(&str, f64, f64)] = &[
+    ("claude-opus-4-6", 5.0, 25.0),
+    ("claude-opus-4-5", 5.0, 25.0),
+    ("claude-opus-4", 15.0, 75.0),
+    ("claude-sonnet-4", 3.0, 15.0),
+    ("claude-haiku-4-5", 1.0, 5.0),
+    ("claude-3-7-sonnet", 3.0, 15.0),
+    ("claude-3-5-sonnet", 3.0, 15.0),
+    ("claude-3-5-haiku", 0.8, 4.0),
+    ("claude-3-opus", 15.0, 75.0),
+    ("claude-3-haiku", 0.25, 1.25),
+];
+
+/// Per-model price table used to cost runs the CLI reports as free
+/// (subscription accounts always report `cost_usd: 0`).
+#[derive(Debug, Clone)]
+pub struct Pricing {
+    prices: Vec<(String, ModelPrice)>,
+}
+
+impl Default for Pricing {
+    fn default() -> Self {
+        Self {
+            prices: DEFAULT_PRICES
+                .iter()
+                .map(|&(model, input, output)| (model.to_string(), ModelPrice { input, output }))
+                .collect(),
+        }
+    }
+}
+
+impl Pricing {
+    /// The built-in prices, overridden by the `catalog` entries' prices,
+    /// then by `file` (a JSON object of
+    /// `{"model-prefix": {"input": 3.0, "output": 15.0}}`) and then by
+    /// `overrides` (`model-prefix` to `input:output`). Unreadable or
+    /// malformed entries are logged and skipped.
+    pub fn load(catalog: &ModelCatalog, file: Option<&Path>, overrides: &[(String, String)]) -> Self {
+        let mut pricing = Self::default();
+        for (model, price) in catalog.prices() {
+            pricing.set(model.to_string(), price);
+        }
+        if let Some(path) = file {
+            let parsed = std::fs::read_to_string(path)
+                .map_err(|e| e.to_string())
+                .and_then(|s| {
+                    serde_json::from_str::<std::collections::BTreeMap<String, ModelPrice>>(&s)
+                        .map_err(|e| e.to_string())
+                });
+            match parsed {
+                Ok(prices) => prices.into_iter().for_each(|(m, p)| pricing.set(m, p)),
+                Err(e) => {
+                    tracing::warn!(path = %path.display(), error = %e, "Ignoring pricing file")
+                }
+            }
+        }
+        for (model, price) in overrides {
+            let parsed = price.split_once(':').and_then(|(i, o)| {
+                Some(ModelPrice { input: i.trim().parse().ok()?, output: o.trim().parse().ok()? })
+            });
+            match parsed {
+                Some(p) => pricing.set(model.clone(), p),
+                None => tracing::warn!(model = %model, price = %price, "Ignoring malformed model price"),
+            }
+        }
+        pricing
+    }
+
+    fn set(&mut self, model: String, price: ModelPrice) {
+        match self.prices.iter_mut().find(|(m, _)| *m == model) {
+            Some(entry) => entry.1 = price,
+            None => self.prices.push((model, price)),
+        }
+    }
+
+    /// The price of the longest configured prefix of `model`.
+    pub fn price(&self, model: &str) -> Option<ModelPrice> {
+        self.prices
+            .iter()
+            .filter(|(prefix, _)| model.starts_with(prefix.as_str()))
+            .max_by_key(|(prefix, _)| prefix.len())
+            .map(|(_, price)| *price)
+    }
+
+    /// Cost in USD of a run, or `None` for unpriced models.
+    pub fn cost(&self, model: &str, input_tokens: u32, output_tokens: u32) -> Option<f64> {
+        let price = self.price(model)?;
+        Some((input_tokens as f64 * price.input + output_tokens as f64 * price.output) / 1e6)
+    }
+
+    /// Fill in `usage.cost_usd` when the CLI reported none. Returns `true`
+    /// when the cost was computed here.
+    pub fn fill_cost(&self, model: &str, usage: &mut UsageInfo) -> bool {
+        if usage.cost_usd > 0.0 {
+            return false;
+        }
+        match self.cost(model, usage.input_tokens, usage.output_tokens) {
+            Some(cost) if cost > 0.0 => {
+                usage.cost_usd = cost;
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_lookup() {
+        let pricing = Pricing::default();
+        assert_eq!(pricing.price("claude-opus-4-1-20250805").unwrap().input, 15.0);
+        assert_eq!(pricing.price("claude-opus-4-6").unwrap().input, 5.0);
+        assert_eq!(pricing.price("gpt-5"), None);
+        let cost = pricing.cost("claude-sonnet-4-5-20250929", 1_000_000, 100_000).unwrap();
+        assert!((cost - 4.5).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_load_overrides() {
+        let path = std::env::temp_dir().join(format!("pricing-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(&path, r#"{"gpt-5": {"input": 1.25, "output": 10.0}}"#).unwrap();
+        let overrides = vec![
+            ("claude-sonnet-4".to_string(), "2:8".to_string()),
+            ("bad".to_string(), "free".to_string()),
+        ];
+        let pricing = Pricing::load(&ModelCatalog::default(), Some(&path), &overrides);
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(pricing.price("gpt-5-codex"), Some(ModelPrice { input: 1.25, output: 10.0 }));
+        assert_eq!(pricing.price("claude-sonnet-4-5"), Some(ModelPrice { input: 2.0, output: 8.0 }));
+        assert_eq!(pricing.price("bad"), None);
+    }
+
+    #[test]
+    fn test_fill_cost() {
+        let pricing = Pricing::default();
+        let mut usage = UsageInfo { input_tokens: 1000, output_tokens: 1000, cost_usd: 0.0 };
+        assert!(pricing.fill_cost("claude-haiku-4-5-20251001", &mut usage));
+        assert!((usage.cost_usd - 0.006).abs() < 1e-9);
+
+        usage.cost_usd = 0.5;
+        assert!(!pricing.fill_cost("claude-haiku-4-5-20251001", &mut usage));
+        assert_eq!(usage.cost_usd, 0.5);
+    }
+}
@@ -0,0 +1,95 @@
+//! Request priority, from the `X-Priority: low|normal|high` header. When
+//! every session slot is taken, queued requests get the next free one by
+//! priority, then in order of arrival; low-priority runs also leave the
+//! warm pool's processes to others and start cold. A key's tier caps the
+//! priority it may ask for (`TIER_<NAME>_MAX_PRIORITY`, `normal` unless
+//! set); keys without a tier may use any.
+
+use axum::http::HeaderMap;
+use serde::Serialize;
+
+use crate::config::Config;
+use crate::error::AppError;
+
+pub const HEADER: &str = "x-priority";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Priority {
+    /// Background work: summaries, batch jobs.
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl Priority {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "low" => Some(Self::Low),
+            "normal" => Some(Self::Normal),
+            "high" => Some(Self::High),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Low => "low",
+            Self::Normal => "normal",
+            Self::High => "high",
+        }
+    }
+}
+
+/// The priority a request asks for in `X-Priority`, else `default`. Fails
+/// if the header is malformed or above what `api_key`'s tier allows.
+pub fn from_headers(
+    config: &Config,
+    api_key: Option<&str>,
+    headers: &HeaderMap,
+    default: Priority,
+) -> Result<Priority, AppError> {
+    let Some(value) = headers.get(HEADER) else {
+        return Ok(default);
+    };
+    let priority = value.to_str().ok().and_then(Priority::parse).ok_or_else(|| AppError::InvalidParam {
+        param: HEADER.to_string(),
+        message: "must be low, normal or high".to_string(),
+    })?;
+    match config.key_tier(api_key) {
+        Some(tier) if priority > tier.max_priority => Err(AppError::Forbidden(format!(
+            "The {} tier allows priority up to {}",
+            tier.name,
+            tier.max_priority.as_str()
+        ))),
+        _ => Ok(priority),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Tier;
+
+    #[test]
+    fn test_from_headers() {
+        let mut config = Config::for_test();
+        config.tiers = vec![Tier {
+            name: "free".to_string(),
+            requests_per_minute: None,
+            models: Vec::new(),
+            max_tokens: None,
+            features: Vec::new(),
+            max_priority: Priority::Normal,
+        }];
+        config.key_tiers = vec![("sk-free".to_string(), "free".to_string())];
+        let headers = |value: &str| HeaderMap::from_iter([(HEADER.parse().unwrap(), value.parse().unwrap())]);
+
+        assert_eq!(from_headers(&config, None, &HeaderMap::new(), Priority::Low).unwrap(), Priority::Low);
+        assert_eq!(from_headers(&config, None, &headers("HIGH"), Priority::Normal).unwrap(), Priority::High);
+        assert_eq!(from_headers(&config, Some("sk-free"), &headers("low"), Priority::Normal).unwrap(), Priority::Low);
+        assert!(matches!(from_headers(&config, Some("sk-free"), &headers("high"), Priority::Normal), Err(AppError::Forbidden(_))));
+        assert!(matches!(from_headers(&config, None, &headers("urgent"), Priority::Normal), Err(AppError::InvalidParam { .. })));
+    }
+}
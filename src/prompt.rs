@@ -0,0 +1,276 @@
+//! Rendering OpenAI chat requests into the single prompt the CLI takes.
+
+use std::collections::{HashMap, HashSet};
+use std::sync::LazyLock;
+
+use regex::Regex;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::models::openai::{ChatCompletionRequest, ChatMessage};
+use crate::tools::format_tools_prompt;
+
+/// A `{{variable}}` placeholder in a stored prompt template.
+static VARIABLE_PATTERN: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r"\{\{\s*([A-Za-z_][A-Za-z0-9_.-]*)\s*\}\}").unwrap());
+
+/// The prompt sent to the CLI for `messages`: the last user message alone,
+/// or the whole conversation rendered as a transcript. The first system
+/// message is left out (it becomes the system prompt); later ones are kept
+/// as `[System Event]` entries. Deployments can replace this with a
+/// template, see [`crate::conversation`].
+pub fn conversation_prompt(messages: &[ChatMessage]) -> String {
+    let conversation_messages = history(messages);
+    if conversation_messages.len() > 1 {
+        let parts = render_transcript(conversation_messages);
+
+        format!(
+            "Below is the conversation history. Continue naturally from where it left off. \
+             Reply ONLY as the Assistant to the last User message.\n\n{}",
+            parts.join("\n\n")
+        )
+    } else {
+        last_user_text(messages)
+    }
+}
+
+/// `messages` without the first system message, which is sent as the
+/// system prompt.
+pub fn history(messages: &[ChatMessage]) -> Vec<&ChatMessage> {
+    let mut first_system_seen = false;
+    messages
+        .iter()
+        .filter(|msg| {
+            if msg.role == "system" {
+                if first_system_seen {
+                    true
+                } else {
+                    first_system_seen = true;
+                    false
+                }
+            } else {
+                true
+            }
+        })
+        .collect()
+}
+
+/// The text of the last user message, empty if there is none.
+pub fn last_user_text(messages: &[ChatMessage]) -> String {
+    messages
+        .iter()
+        .rfind(|m| m.role == "user")
+        .map(|m| m.get_text_content())
+        .unwrap_or_default()
+}
+
+/// The first system message, else the `system_prompt` extension field.
+pub fn system_prompt(request: &ChatCompletionRequest) -> Option<String> {
+    request
+        .messages
+        .iter()
+        .find(|m| m.role == "system")
+        .map(|m| m.get_text_content())
+        .or_else(|| request.system_prompt.clone())
+}
+
+/// The tool-calling instructions appended to the system prompt, if the
+/// request has tools.
+pub fn tools_prompt(request: &ChatCompletionRequest) -> Option<String> {
+    request
+        .tools
+        .as_deref()
+        .filter(|t| !t.is_empty())
+        .map(format_tools_prompt)
+}
+
+/// One entry of a conversation, with the results of its tool calls
+/// attached to the calls.
+#[derive(Debug, Serialize)]
+pub struct Turn {
+    pub role: String,
+    pub content: String,
+    /// The tool name of a `tool` message that answers no call.
+    pub name: Option<String>,
+    pub tool_calls: Vec<TurnToolCall>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TurnToolCall {
+    pub id: String,
+    pub name: String,
+    pub arguments: String,
+    /// The content of the `tool` message with this call's `tool_call_id`.
+    pub result: Option<String>,
+}
+
+/// `messages` as turns: each tool result is moved to the assistant call it
+/// answers (matched by `tool_call_id`). Results that match no earlier call
+/// stay where they are.
+pub fn conversation_turns<'a>(messages: impl IntoIterator<Item = &'a ChatMessage>) -> Vec<Turn> {
+    let messages: Vec<&ChatMessage> = messages.into_iter().collect();
+    let mut results: HashMap<&str, usize> = HashMap::new();
+    for (i, msg) in messages.iter().enumerate() {
+        if let (true, Some(id)) = (msg.role == "tool", msg.tool_call_id.as_deref()) {
+            results.entry(id).or_insert(i);
+        }
+    }
+
+    let mut placed = HashSet::new();
+    let mut turns = Vec::new();
+    for (i, msg) in messages.iter().enumerate() {
+        if placed.contains(&i) {
+            continue;
+        }
+        let calls = msg.tool_calls.as_deref().filter(|_| msg.role == "assistant").unwrap_or_default();
+        let tool_calls = calls
+            .iter()
+            .map(|tc| {
+                let result = results.get(tc.id.as_str()).filter(|&&j| j > i && !placed.contains(&j)).map(|&j| {
+                    placed.insert(j);
+                    messages[j].get_text_content()
+                });
+                TurnToolCall {
+                    id: tc.id.clone(),
+                    name: tc.function.name.clone(),
+                    arguments: tc.function.arguments.clone(),
+                    result,
+                }
+            })
+            .collect();
+        turns.push(Turn {
+            role: msg.role.clone(),
+            content: msg.get_text_content(),
+            name: msg.name.clone(),
+            tool_calls,
+        });
+    }
+    turns
+}
+
+/// The `tool` messages after the last assistant message, i.e. the results
+/// sent for its calls, each with the name of the tool it answers.
+pub fn latest_tool_results(messages: &[ChatMessage]) -> Vec<(&ChatMessage, Option<&str>)> {
+    let last = messages.iter().rposition(|m| m.role == "assistant");
+    let calls = last.and_then(|i| messages[i].tool_calls.as_deref()).unwrap_or_default();
+    messages[last.map_or(0, |i| i + 1)..]
+        .iter()
+        .filter(|m| m.role == "tool")
+        .map(|m| {
+            let call = calls.iter().find(|tc| m.tool_call_id.as_deref() == Some(tc.id.as_str()));
+            (m, m.name.as_deref().or(call.map(|tc| tc.function.name.as_str())))
+        })
+        .collect()
+}
+
+/// `messages` as `[Role]: text` transcript entries, with each tool result
+/// right after its call and labeled with the call's name and arguments.
+pub fn render_transcript<'a>(messages: impl IntoIterator<Item = &'a ChatMessage>) -> Vec<String> {
+    conversation_turns(messages).iter().map(render_turn).collect()
+}
+
+fn render_turn(turn: &Turn) -> String {
+    let content = &turn.content;
+    match turn.role.as_str() {
+        "user" => format!("[User]: {content}"),
+        "assistant" => {
+            let mut text = format!("[Assistant]: {content}");
+            for tc in &turn.tool_calls {
+                let call = format!("{}({})", tc.name, tc.arguments);
+                text.push_str(&format!("\n[Called tool: {call}]"));
+                if let Some(ref result) = tc.result {
+                    text.push_str(&format!("\n[Tool Result ({call})]: {result}"));
+                }
+            }
+            text
+        }
+        "system" => format!("[System Event]: {content}"),
+        // Inserted by history compaction, see `crate::compaction`
+        "summary" => format!("[Summary of Earlier Conversation]: {content}"),
+        "tool" => {
+            let name = turn.name.as_deref().unwrap_or("unknown");
+            format!("[Tool Result ({name})]: {content}")
+        }
+        role => format!("[{role}]: {content}"),
+    }
+}
+
+/// The distinct `{{variable}}` names in `template`, in order of appearance.
+pub fn template_variables(template: &str) -> Vec<String> {
+    let mut names: Vec<String> = Vec::new();
+    for caps in VARIABLE_PATTERN.captures_iter(template) {
+        if !names.iter().any(|n| n == &caps[1]) {
+            names.push(caps[1].to_string());
+        }
+    }
+    names
+}
+
+/// `template` with each `{{variable}}` replaced by its value; strings are
+/// inserted as is, other JSON values in their JSON form. Errors with the
+/// names that have no value.
+pub fn render_template(template: &str, variables: &Map<String, Value>) -> Result<String, Vec<String>> {
+    let missing: Vec<String> = template_variables(template)
+        .into_iter()
+        .filter(|name| !variables.contains_key(name))
+        .collect();
+    if !missing.is_empty() {
+        return Err(missing);
+    }
+    let rendered = VARIABLE_PATTERN.replace_all(template, |caps: &regex::Captures| match &variables[&caps[1]] {
+        Value::String(s) => s.clone(),
+        other => other.to_string(),
+    });
+    Ok(rendered.into_owned())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_transcript_pairs_tool_results() {
+        let messages: Vec<ChatMessage> = serde_json::from_value(serde_json::json!([
+            {"role": "user", "content": "Weather in Paris and Oslo?"},
+            {"role": "assistant", "content": null, "tool_calls": [
+                {"id": "call_1", "type": "function", "function": {"name": "weather", "arguments": "{\"city\":\"Paris\"}"}},
+                {"id": "call_2", "type": "function", "function": {"name": "weather", "arguments": "{\"city\":\"Oslo\"}"}},
+            ]},
+            // Parallel results, out of order
+            {"role": "tool", "tool_call_id": "call_2", "content": "3C"},
+            {"role": "tool", "tool_call_id": "call_1", "content": "18C"},
+            {"role": "tool", "tool_call_id": "call_9", "name": "stale", "content": "?"},
+        ]))
+        .unwrap();
+        let entries = render_transcript(&messages);
+        assert_eq!(entries.len(), 3);
+        assert_eq!(
+            entries[1],
+            "[Assistant]: \n\
+             [Called tool: weather({\"city\":\"Paris\"})]\n\
+             [Tool Result (weather({\"city\":\"Paris\"}))]: 18C\n\
+             [Called tool: weather({\"city\":\"Oslo\"})]\n\
+             [Tool Result (weather({\"city\":\"Oslo\"}))]: 3C"
+        );
+        assert_eq!(entries[2], "[Tool Result (stale)]: ?");
+
+        let results = latest_tool_results(&messages);
+        let names: Vec<_> = results.iter().map(|(m, name)| (m.get_text_content(), *name)).collect();
+        assert_eq!(names, [("3C".into(), Some("weather")), ("18C".into(), Some("weather")), ("?".into(), Some("stale"))]);
+        assert!(latest_tool_results(&messages[..2]).is_empty());
+    }
+
+    #[test]
+    fn test_render_template() {
+        let template = "You review {{ language }} code for {{team}}. Max {{limit}} comments. {{team}} style.";
+        assert_eq!(template_variables(template), ["language", "team", "limit"]);
+
+        let vars = serde_json::json!({"language": "Rust", "team": "infra", "limit": 5, "unused": true});
+        assert_eq!(
+            render_template(template, vars.as_object().unwrap()).unwrap(),
+            "You review Rust code for infra. Max 5 comments. infra style."
+        );
+        let vars = serde_json::json!({"team": "infra"});
+        assert_eq!(render_template(template, vars.as_object().unwrap()).unwrap_err(), ["language", "limit"]);
+    }
+}
@@ -0,0 +1,140 @@
+//! Limits per end user and per key. Apps that proxy many users through one
+//! API key name them in the OpenAI `user` field; each user under a key gets
+//! its own rate limit bucket (`USER_RATE_LIMIT_REQUESTS_PER_MINUTE`) and
+//! daily budget (`USER_DAILY_BUDGET_USD`), and is recorded in the audit log.
+//!
+//! Keys also get a token allowance per UTC month: their own, set through
+//! `/admin/quotas`, else `KEY_MONTHLY_TOKENS`. Every run's tokens count
+//! against it; once it is used up, new runs fail with `insufficient_quota`
+//! until the month turns. `/v1` responses carry the key's quota in
+//! `x-quota-*` headers, and `GET /v1/quota` returns it.
+
+use std::sync::Arc;
+
+use axum::body::Body;
+use axum::extract::{Request, State};
+use axum::http::HeaderValue;
+use axum::middleware::Next;
+use axum::response::Response;
+use chrono::Utc;
+use serde_json::{json, Value};
+
+use crate::auth::{hash_api_key, ApiKey};
+use crate::budget::next_month;
+use crate::db;
+use crate::error::AppError;
+use crate::state::AppState;
+
+/// A key's allowance this month.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct KeyQuota {
+    pub limit: i64,
+    pub used: i64,
+    /// Unix time the month turns.
+    pub resets_at: i64,
+}
+
+impl KeyQuota {
+    pub fn remaining(&self) -> i64 {
+        (self.limit - self.used).max(0)
+    }
+
+    pub fn to_json(self) -> Value {
+        json!({
+            "object": "quota",
+            "period": "month",
+            "limit_tokens": self.limit,
+            "used_tokens": self.used,
+            "remaining_tokens": self.remaining(),
+            "resets_at": self.resets_at,
+        })
+    }
+}
+
+/// The allowance of `api_key`, if it has one.
+pub async fn key_quota(state: &AppState, api_key: &str) -> Result<Option<KeyQuota>, AppError> {
+    let key_hash = hash_api_key(api_key);
+    let resets_at = next_month(Utc::now());
+    if let Some(row) = db::get_key_quota(&state.db, &key_hash).await? {
+        return Ok(Some(KeyQuota { limit: row.monthly_tokens, used: row.used_tokens, resets_at }));
+    }
+    let limit = state.config().key_monthly_tokens;
+    if limit <= 0 {
+        return Ok(None);
+    }
+    let used = db::key_tokens_this_month(&state.db, &key_hash).await?;
+    Ok(Some(KeyQuota { limit, used, resets_at }))
+}
+
+/// Fail with `insufficient_quota` if `api_key` has used up its allowance.
+pub async fn check_key(state: &AppState, api_key: Option<&str>) -> Result<(), AppError> {
+    let Some(api_key) = api_key else {
+        return Ok(());
+    };
+    match key_quota(state, api_key).await? {
+        Some(quota) if quota.remaining() == 0 => Err(AppError::QuotaExceeded(format!(
+            "This key has used its {} tokens for the month; the allowance resets at {}",
+            quota.limit,
+            chrono::DateTime::from_timestamp(quota.resets_at, 0).unwrap_or_default().to_rfc3339()
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Count a run's tokens against `api_key`. Failures are logged.
+pub async fn record_tokens(state: &AppState, api_key: Option<&str>, tokens: u32) {
+    let Some(api_key) = api_key.filter(|_| tokens > 0) else {
+        return;
+    };
+    if let Err(e) = db::add_key_tokens(&state.db, &hash_api_key(api_key), tokens.into()).await {
+        tracing::warn!(error = %e, tokens, "Failed to record key token use");
+    }
+}
+
+/// Add the key's quota to the response as `x-quota-limit-tokens`,
+/// `x-quota-remaining-tokens` and `x-quota-reset` (unix time).
+pub async fn quota_headers(State(state): State<Arc<AppState>>, req: Request<Body>, next: Next) -> Response {
+    let api_key = req.extensions().get::<ApiKey>().map(|ApiKey(key)| key.clone());
+    let mut response = next.run(req).await;
+    let Some(api_key) = api_key else {
+        return response;
+    };
+    // Taken after the handler, so a non-streaming run's own tokens count
+    if let Ok(Some(quota)) = key_quota(&state, &api_key).await {
+        let headers = response.headers_mut();
+        for (name, value) in [
+            ("x-quota-limit-tokens", quota.limit),
+            ("x-quota-remaining-tokens", quota.remaining()),
+            ("x-quota-reset", quota.resets_at),
+        ] {
+            headers.insert(name, HeaderValue::from(value));
+        }
+    }
+    response
+}
+
+/// Check the request of `user` under `api_key` against the per-user limits.
+pub async fn check_user(state: &AppState, api_key: Option<&str>, user: &str) -> Result<(), AppError> {
+    let config = state.config();
+    if config.user_rate_limit_requests_per_minute > 0 {
+        let bucket = format!("{}\u{0}{user}", api_key.unwrap_or_default());
+        if !state.user_rate_limiter.check(&bucket) {
+            return Err(AppError::RateLimited);
+        }
+    }
+    if config.user_daily_budget_usd > 0.0 {
+        if !config.audit_log {
+            tracing::warn!("USER_DAILY_BUDGET_USD needs AUDIT_LOG; not enforced");
+            return Ok(());
+        }
+        let key_hash = api_key.map(hash_api_key);
+        let spent = db::user_spend_today(&state.db, key_hash.as_deref(), user).await?;
+        if spent >= config.user_daily_budget_usd {
+            return Err(AppError::QuotaExceeded(format!(
+                "User {user} has spent ${spent:.4} today, the daily budget is ${:.4}",
+                config.user_daily_budget_usd
+            )));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,245 @@
+//! Retrieval-augmented project context. A project's workspace files are
+//! split into line chunks, embedded with the local hashing embedder and
+//! stored in `project_chunks`; prompts of projects with `rag_enabled` get
+//! the best-matching chunks prepended, and the chunks used are recorded as
+//! citations in the user message's metadata.
+
+use std::path::Path;
+
+use serde::Serialize;
+
+use crate::claude::manager::create_project_directory;
+use crate::db::{self, ChunkRow};
+use crate::error::AppError;
+use crate::routes::embeddings::embed_text;
+use crate::state::AppState;
+
+/// Dimension of chunk embeddings.
+const DIM: usize = 512;
+/// Directories never indexed, besides hidden ones. `uploads` holds chat
+/// attachments, which are passed to the CLI separately.
+const SKIP_DIRS: &[&str] = &["uploads", "node_modules", "target", "dist", "build", "vendor", "__pycache__"];
+/// Chunks scoring below this share too little vocabulary with the query.
+const MIN_SCORE: f32 = 0.05;
+
+#[derive(Debug, Default, Serialize)]
+pub struct IndexStats {
+    pub files: usize,
+    pub chunks: usize,
+    /// Files left out as binary, unreadable or larger than `RAG_MAX_FILE_BYTES`.
+    pub skipped: usize,
+}
+
+/// A chunk included in a prompt.
+#[derive(Debug, Clone, Serialize)]
+pub struct Citation {
+    /// The chunk's `[n]` marker in the prompt.
+    pub index: usize,
+    pub path: String,
+    pub start_line: i64,
+    pub end_line: i64,
+    pub score: f32,
+}
+
+#[derive(Debug)]
+pub struct Retrieved {
+    /// The chunks rendered for the prompt.
+    pub context: String,
+    pub citations: Vec<Citation>,
+}
+
+/// Rebuild the index of `project_id`'s workspace.
+pub async fn index_project(state: &AppState, project_id: &str) -> Result<IndexStats, AppError> {
+    let config = state.config();
+    let root = create_project_directory(&config.project_root, project_id)?;
+    let chunk_lines = config.rag_chunk_lines;
+    let max_bytes = config.rag_max_file_bytes;
+    let (chunks, stats) = tokio::task::spawn_blocking(move || collect_chunks(&root, chunk_lines, max_bytes))
+        .await
+        .map_err(|e| AppError::Internal(format!("Indexing task failed: {e}")))?;
+    db::replace_project_chunks(&state.db, project_id, &chunks).await?;
+    tracing::info!(project_id, files = stats.files, chunks = stats.chunks, skipped = stats.skipped, "Indexed project");
+    Ok(stats)
+}
+
+/// The top `RAG_TOP_K` chunks for `query`, or `None` when the project is
+/// not registered, has retrieval off, or nothing matches. A project that
+/// was never indexed is indexed first. Failures are logged, never fatal.
+pub async fn retrieve(state: &AppState, project_id: &str, query: &str) -> Option<Retrieved> {
+    if state.config().rag_top_k == 0 || query.trim().is_empty() {
+        return None;
+    }
+    let result = async {
+        let enabled = db::get_project(&state.db, project_id).await?.is_some_and(|p| p.rag_enabled);
+        if !enabled {
+            return Ok(None);
+        }
+        let mut chunks = db::list_project_chunks(&state.db, project_id).await?;
+        if chunks.is_empty() {
+            index_project(state, project_id).await?;
+            chunks = db::list_project_chunks(&state.db, project_id).await?;
+        }
+        Ok::<_, AppError>(rank(&chunks, query, state.config().rag_top_k))
+    }
+    .await;
+    match result {
+        Ok(retrieved) => retrieved,
+        Err(e) => {
+            tracing::warn!(project_id, error = %e, "Project retrieval failed; sending the prompt as is");
+            None
+        }
+    }
+}
+
+/// `prompt` with the retrieved context in front of it.
+pub fn augment_prompt(retrieved: &Retrieved, prompt: &str) -> String {
+    format!(
+        "Relevant excerpts from the project's files (cite them as [n] where you use them):\n\n\
+         {}\n\n{prompt}",
+        retrieved.context
+    )
+}
+
+fn rank(chunks: &[ChunkRow], query: &str, top_k: usize) -> Option<Retrieved> {
+    let query = embed_text(query, DIM);
+    let mut scored: Vec<(f32, &ChunkRow)> = chunks
+        .iter()
+        .map(|c| (dot(&query, &c.embedding), c))
+        .filter(|(score, _)| *score >= MIN_SCORE)
+        .collect();
+    if scored.is_empty() {
+        return None;
+    }
+    scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+    scored.truncate(top_k);
+
+    let mut context = Vec::with_capacity(scored.len());
+    let mut citations = Vec::with_capacity(scored.len());
+    for (i, (score, chunk)) in scored.into_iter().enumerate() {
+        let index = i + 1;
+        context.push(format!(
+            "[{index}] {}:{}-{}\n```\n{}\n```",
+            chunk.path, chunk.start_line, chunk.end_line, chunk.content
+        ));
+        citations.push(Citation {
+            index,
+            path: chunk.path.clone(),
+            start_line: chunk.start_line,
+            end_line: chunk.end_line,
+            score,
+        });
+    }
+    Some(Retrieved { context: context.join("\n\n"), citations })
+}
+
+/// Cosine similarity of a unit query vector and a stored unit vector.
+pub(crate) fn dot(query: &[f32], embedding: &[u8]) -> f32 {
+    embedding
+        .chunks_exact(4)
+        .zip(query)
+        .map(|(b, q)| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) * q)
+        .sum()
+}
+
+/// Split every indexable text file under `root` into chunks of
+/// `chunk_lines` lines, in path order.
+fn collect_chunks(root: &Path, chunk_lines: usize, max_file_bytes: u64) -> (Vec<ChunkRow>, IndexStats) {
+    let mut chunks = Vec::new();
+    let mut stats = IndexStats::default();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        let mut entries: Vec<_> = entries.flatten().collect();
+        entries.sort_by_key(|e| e.file_name());
+        let mut subdirs = Vec::new();
+        for entry in entries {
+            let name = entry.file_name();
+            let name = name.to_string_lossy();
+            let Ok(file_type) = entry.file_type() else { continue };
+            if name.starts_with('.') || file_type.is_symlink() {
+                continue;
+            }
+            if file_type.is_dir() {
+                if !SKIP_DIRS.contains(&name.as_ref()) {
+                    subdirs.push(entry.path());
+                }
+                continue;
+            }
+            let text = entry
+                .metadata()
+                .ok()
+                .filter(|m| m.len() <= max_file_bytes)
+                .and_then(|_| std::fs::read(entry.path()).ok())
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .filter(|text| !text.contains('\0'));
+            let Some(text) = text else {
+                stats.skipped += 1;
+                continue;
+            };
+            let path = entry.path();
+            let rel = path.strip_prefix(root).unwrap_or(&path);
+            let rel = rel.components().map(|c| c.as_os_str().to_string_lossy()).collect::<Vec<_>>().join("/");
+            stats.files += 1;
+            chunks.extend(chunk_file(&rel, &text, chunk_lines));
+        }
+        // Depth-first, in name order
+        dirs.extend(subdirs.into_iter().rev());
+    }
+    stats.chunks = chunks.len();
+    (chunks, stats)
+}
+
+fn chunk_file(path: &str, text: &str, chunk_lines: usize) -> Vec<ChunkRow> {
+    let lines: Vec<&str> = text.lines().collect();
+    lines
+        .chunks(chunk_lines)
+        .enumerate()
+        .filter(|(_, lines)| lines.iter().any(|l| !l.trim().is_empty()))
+        .map(|(i, lines)| {
+            let content = lines.join("\n");
+            // The path is embedded too, so queries naming a file find it
+            let embedding = embed_text(&format!("{path}\n{content}"), DIM)
+                .into_iter()
+                .flat_map(f32::to_le_bytes)
+                .collect();
+            let start_line = (i * chunk_lines + 1) as i64;
+            ChunkRow {
+                path: path.to_string(),
+                start_line,
+                end_line: start_line + lines.len() as i64 - 1,
+                content,
+                embedding,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_index_and_rank() {
+        let root = std::env::temp_dir().join(format!("rag-test-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(root.join("src")).unwrap();
+        std::fs::create_dir_all(root.join("uploads")).unwrap();
+        std::fs::create_dir_all(root.join(".git")).unwrap();
+        let billing = (1..=6).map(|i| format!("invoice total line {i}")).collect::<Vec<_>>().join("\n");
+        std::fs::write(root.join("src/billing.rs"), billing).unwrap();
+        std::fs::write(root.join("src/auth.rs"), "verify the bearer token\ncheck the api key").unwrap();
+        std::fs::write(root.join("logo.png"), [0x89, b'P', b'N', b'G', 0, 0xff]).unwrap();
+        std::fs::write(root.join("uploads/report.txt"), "invoice total").unwrap();
+        std::fs::write(root.join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+
+        let (chunks, stats) = collect_chunks(&root, 4, 1024);
+        std::fs::remove_dir_all(&root).unwrap();
+        assert_eq!((stats.files, stats.chunks, stats.skipped), (2, 3, 1));
+        let spans: Vec<_> = chunks.iter().map(|c| (c.path.as_str(), c.start_line, c.end_line)).collect();
+        assert_eq!(spans, [("src/auth.rs", 1, 2), ("src/billing.rs", 1, 4), ("src/billing.rs", 5, 6)]);
+
+        let retrieved = rank(&chunks, "how is the api key checked?", 1).unwrap();
+        assert_eq!(retrieved.citations[0].path, "src/auth.rs");
+        assert!(retrieved.context.starts_with("[1] src/auth.rs:1-2\n```\nverify the bearer token"));
+        assert!(rank(&chunks, "zzz qqq", 3).is_none());
+    }
+}
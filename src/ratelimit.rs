@@ -0,0 +1,235 @@
+//! Sliding-window rate limiting per API key (per client IP for requests
+//! without a valid one), and per end user under a key (see
+//! [`crate::quota`]). Windows are spread over shards with a lock each,
+//! so requests for different keys rarely wait on one another. A window is
+//! dropped once its requests have aged out, and at most
+//! `RATE_LIMIT_MAX_KEYS` keys are tracked: a new key in a full shard evicts
+//! the one idle longest among those under their limit, so throttled keys
+//! can't be freed by flooding the limiter with new ones; when all of them
+//! are at their limit the new key is refused. [`spawn_sweeper`] drops the
+//! windows of keys that went quiet.
+
+use std::collections::hash_map::RandomState;
+use std::collections::{HashMap, VecDeque};
+use std::hash::BuildHasher;
+use std::sync::atomic::{AtomicU32, AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::state::AppState;
+
+const SHARDS: usize = 16;
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// A key's requests within [`WINDOW`], and the limit it was last checked
+/// against.
+struct Window {
+    requests: VecDeque<Instant>,
+    limit: u32,
+}
+
+type Windows = HashMap<String, Window>;
+
+pub struct RateLimiter {
+    requests_per_minute: AtomicU32,
+    burst: AtomicU32,
+    max_keys: AtomicUsize,
+    hasher: RandomState,
+    shards: Vec<Mutex<Windows>>,
+    /// Requests refused since startup.
+    limited: AtomicU64,
+    /// Keys dropped to stay under `max_keys` since startup.
+    evicted: AtomicU64,
+}
+
+impl RateLimiter {
+    pub fn new(requests_per_minute: u32, burst: u32, max_keys: usize) -> Self {
+        Self {
+            requests_per_minute: AtomicU32::new(requests_per_minute),
+            burst: AtomicU32::new(burst),
+            max_keys: AtomicUsize::new(max_keys),
+            hasher: RandomState::new(),
+            shards: (0..SHARDS).map(|_| Mutex::new(HashMap::new())).collect(),
+            limited: AtomicU64::new(0),
+            evicted: AtomicU64::new(0),
+        }
+    }
+
+    /// Change the limits, keeping the request history.
+    pub fn set_limits(&self, requests_per_minute: u32, burst: u32, max_keys: usize) {
+        self.requests_per_minute.store(requests_per_minute, Ordering::Relaxed);
+        self.burst.store(burst, Ordering::Relaxed);
+        self.max_keys.store(max_keys, Ordering::Relaxed);
+    }
+
+    /// Count a request for `key`; `false` if it is over the limit.
+    pub fn check(&self, key: &str) -> bool {
+        self.check_at(key, None, Instant::now())
+    }
+
+    /// As [`check`](Self::check), with `requests_per_minute` instead of the
+    /// configured rate (e.g. the key's tier's).
+    pub fn check_rate(&self, key: &str, requests_per_minute: u32) -> bool {
+        self.check_at(key, Some(requests_per_minute), Instant::now())
+    }
+
+    fn check_at(&self, key: &str, requests_per_minute: Option<u32>, now: Instant) -> bool {
+        let rate = requests_per_minute.unwrap_or_else(|| self.requests_per_minute.load(Ordering::Relaxed));
+        let limit = rate + self.burst.load(Ordering::Relaxed);
+        let mut shard = self.shard(key);
+        if let Some(window) = shard.get_mut(key) {
+            window.limit = limit;
+            expire(&mut window.requests, now);
+            if window.requests.len() as u32 >= limit {
+                self.limited.fetch_add(1, Ordering::Relaxed);
+                return false;
+            }
+            window.requests.push_back(now);
+            return true;
+        }
+        if limit == 0 {
+            self.limited.fetch_add(1, Ordering::Relaxed);
+            return false;
+        }
+
+        let capacity = (self.max_keys.load(Ordering::Relaxed) / SHARDS).max(1);
+        if shard.len() >= capacity {
+            prune(&mut shard, now);
+        }
+        while shard.len() >= capacity {
+            let idle = shard
+                .iter()
+                .filter(|(_, window)| (window.requests.len() as u32) < window.limit)
+                .min_by_key(|(_, window)| window.requests.back().copied())
+                .map(|(k, _)| k.clone());
+            let Some(idle) = idle else {
+                self.limited.fetch_add(1, Ordering::Relaxed);
+                return false;
+            };
+            shard.remove(&idle);
+            self.evicted.fetch_add(1, Ordering::Relaxed);
+        }
+        shard.insert(key.to_string(), Window { requests: VecDeque::from([now]), limit });
+        true
+    }
+
+    /// Drop the windows whose requests have all aged out. Returns how many
+    /// were dropped.
+    pub fn sweep(&self) -> usize {
+        let now = Instant::now();
+        (0..SHARDS).map(|i| prune(&mut self.lock(i), now)).sum()
+    }
+
+    /// Tracked keys and requests, limits, and refusal and eviction counts,
+    /// for `/admin/metrics`.
+    pub fn stats(&self) -> Value {
+        let (keys, requests) = (0..SHARDS).fold((0, 0), |(keys, requests), i| {
+            let shard = self.lock(i);
+            (keys + shard.len(), requests + shard.values().map(|w| w.requests.len()).sum::<usize>())
+        });
+        json!({
+            "keys": keys,
+            "tracked_requests": requests,
+            "max_keys": self.max_keys.load(Ordering::Relaxed),
+            "requests_per_minute": self.requests_per_minute.load(Ordering::Relaxed),
+            "burst": self.burst.load(Ordering::Relaxed),
+            "limited_total": self.limited.load(Ordering::Relaxed),
+            "evicted_total": self.evicted.load(Ordering::Relaxed),
+        })
+    }
+
+    fn shard(&self, key: &str) -> MutexGuard<'_, Windows> {
+        self.lock(self.hasher.hash_one(key) as usize % SHARDS)
+    }
+
+    fn lock(&self, i: usize) -> MutexGuard<'_, Windows> {
+        self.shards[i].lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Drop the requests of `window` older than [`WINDOW`].
+fn expire(window: &mut VecDeque<Instant>, now: Instant) {
+    while window.front().is_some_and(|t| now.duration_since(*t) >= WINDOW) {
+        window.pop_front();
+    }
+}
+
+/// Drop the windows of `shard` left empty. Returns how many were dropped.
+fn prune(shard: &mut Windows, now: Instant) -> usize {
+    let before = shard.len();
+    shard.retain(|_, window| {
+        expire(&mut window.requests, now);
+        !window.requests.is_empty()
+    });
+    before - shard.len()
+}
+
+/// Start the background task sweeping both limiters every minute.
+pub fn spawn_sweeper(state: Arc<AppState>) {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(WINDOW);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let dropped = state.rate_limiter.sweep() + state.user_rate_limiter.sweep();
+            if dropped > 0 {
+                tracing::debug!(dropped, "Swept idle rate limit windows");
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rate_limiter() {
+        let limiter = RateLimiter::new(2, 1, 100);
+        let start = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.check_at("a", None, start));
+        }
+        assert!(!limiter.check_at("a", None, start));
+        assert!(limiter.check_at("b", None, start));
+        // The window slides
+        assert!(limiter.check_at("a", None, start + WINDOW));
+        assert_eq!(limiter.stats()["limited_total"], 1);
+
+        // Windows are dropped once empty
+        let later = start + WINDOW * 3;
+        let dropped: usize = (0..SHARDS).map(|i| prune(&mut limiter.lock(i), later)).sum();
+        assert_eq!(dropped, 2);
+        assert_eq!(limiter.stats()["keys"], 0);
+    }
+
+    #[test]
+    fn test_rate_limiter_is_bounded() {
+        // One key per shard: every new key in a used shard evicts the idle one
+        let limiter = RateLimiter::new(10, 0, SHARDS);
+        let start = Instant::now();
+        for i in 0..1000u64 {
+            assert!(limiter.check_at(&format!("key-{i}"), None, start + Duration::from_millis(i)));
+        }
+        let stats = limiter.stats();
+        assert!(stats["keys"].as_u64().unwrap() <= SHARDS as u64);
+        assert_eq!(stats["keys"].as_u64().unwrap() + stats["evicted_total"].as_u64().unwrap(), 1000);
+    }
+
+    #[test]
+    fn test_throttled_keys_are_not_evicted() {
+        let limiter = RateLimiter::new(1, 0, SHARDS);
+        let start = Instant::now();
+        assert!(limiter.check_at("a", None, start));
+        assert!(!limiter.check_at("a", None, start));
+        // New keys landing in a's shard are refused rather than evicting it
+        let refused = (0..1000u64)
+            .filter(|i| !limiter.check_at(&format!("key-{i}"), None, start + Duration::from_millis(*i)))
+            .count();
+        assert!(refused > 0);
+        assert!(!limiter.check_at("a", None, start + Duration::from_secs(1)));
+        assert!(limiter.check_at("a", None, start + WINDOW));
+    }
+}
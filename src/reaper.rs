@@ -0,0 +1,122 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::db;
+use crate::models::openai::IMAGE_FILE_PREFIX;
+use crate::state::AppState;
+
+/// Start the background task that cleans up after stale sessions every
+/// `CLEANUP_INTERVAL_MINUTES`:
+///
+/// - kills CLI processes running longer than `SESSION_TIMEOUT_MINUTES`
+///   and reaps ones that exited without being collected,
+/// - removes orphaned image files and CLAUDE.md temp dirs,
+/// - marks DB sessions idle for longer than the timeout inactive.
+pub fn spawn(state: Arc<AppState>) {
+    let interval = Duration::from_secs(state.config().cleanup_interval_minutes.max(1) * 60);
+    let timeout_minutes = state.config().session_timeout_minutes;
+    let timeout = Duration::from_secs(timeout_minutes * 60);
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+
+            let (killed, reaped) = state.claude_manager.reap_stale(timeout).await;
+
+            let project_root = state.config().project_root.clone();
+            let removed = tokio::task::spawn_blocking(move || {
+                clean_orphans(&std::env::temp_dir(), &project_root, timeout)
+            })
+            .await
+            .unwrap_or(0);
+
+            let deactivated = db::deactivate_stale_sessions(&state.db, timeout_minutes)
+                .await
+                .unwrap_or_else(|e| {
+                    tracing::warn!(error = %e, "Failed to deactivate stale sessions");
+                    0
+                });
+
+            if killed + reaped + removed > 0 || deactivated > 0 {
+                tracing::info!(killed, reaped, removed, deactivated, "Stale session cleanup");
+            }
+        }
+    });
+}
+
+/// Remove image files and CLAUDE.md temp dirs older than `max_age` from the
+/// temp dir and from each project directory. Returns how many were removed.
+fn clean_orphans(temp_dir: &Path, project_root: &Path, max_age: Duration) -> usize {
+    let mut dirs = vec![temp_dir.to_path_buf()];
+    if let Ok(entries) = std::fs::read_dir(project_root) {
+        dirs.extend(entries.filter_map(|e| e.ok()).map(|e| e.path()).filter(|p| p.is_dir()));
+    }
+
+    let mut removed = 0;
+    for dir in dirs {
+        let Ok(entries) = std::fs::read_dir(&dir) else { continue };
+        for path in entries.filter_map(|e| e.ok()).map(|e| e.path()) {
+            if !is_orphan_candidate(&path) || !older_than(&path, max_age) {
+                continue;
+            }
+            let result = if path.is_dir() {
+                std::fs::remove_dir_all(&path)
+            } else {
+                std::fs::remove_file(&path)
+            };
+            match result {
+                Ok(()) => removed += 1,
+                Err(e) => tracing::debug!(path = %path.display(), error = %e, "Failed to remove orphan"),
+            }
+        }
+    }
+    removed
+}
+
+/// Files written by `extract_images` and temp dirs holding a large system prompt.
+fn is_orphan_candidate(path: &Path) -> bool {
+    let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+    if path.is_dir() {
+        name.starts_with(".tmp") && path.join("CLAUDE.md").is_file()
+    } else {
+        name.starts_with(IMAGE_FILE_PREFIX)
+    }
+}
+
+fn older_than(path: &Path, max_age: Duration) -> bool {
+    std::fs::metadata(path)
+        .and_then(|m| m.modified())
+        .ok()
+        .and_then(|t| SystemTime::now().duration_since(t).ok())
+        .is_some_and(|age| age > max_age)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_clean_orphans() {
+        let tmp = tempfile::tempdir().unwrap();
+        let projects = tempfile::tempdir().unwrap();
+        let project = projects.path().join("p1");
+        std::fs::create_dir(&project).unwrap();
+
+        std::fs::write(tmp.path().join("claude_image_1.png"), b"x").unwrap();
+        std::fs::write(tmp.path().join("unrelated.png"), b"x").unwrap();
+        let prompt_dir = project.join(".tmpAbc");
+        std::fs::create_dir(&prompt_dir).unwrap();
+        std::fs::write(prompt_dir.join("CLAUDE.md"), b"x").unwrap();
+        std::fs::create_dir(project.join(".tmpOther")).unwrap();
+
+        assert_eq!(clean_orphans(tmp.path(), projects.path(), Duration::from_secs(3600)), 0);
+        std::thread::sleep(Duration::from_millis(20));
+        assert_eq!(clean_orphans(tmp.path(), projects.path(), Duration::from_millis(1)), 2);
+        assert!(tmp.path().join("unrelated.png").exists());
+        assert!(project.join(".tmpOther").exists());
+        assert!(!prompt_dir.exists());
+    }
+}
@@ -0,0 +1,95 @@
+//! Request recording for debugging (`RECORD_REQUESTS`). Each chat
+//! completion is stored with the request as received, the CLI invocation
+//! it was rendered to (profile, prompt, flags) and the response, and
+//! answered with `X-Recording-ID`. `POST /admin/replay/{id}` runs the
+//! request again on the current build and configuration, so "it answered
+//! differently yesterday" can be compared side by side.
+//!
+//! Recordings hold whole conversations; they expire with
+//! `MESSAGE_RETENTION_DAYS` like messages do.
+
+use axum::http::HeaderValue;
+use axum::response::Response;
+use serde_json::{json, Value};
+
+use crate::claude::process::{claude_args, SpawnOptions};
+use crate::config::ClaudeProfile;
+use crate::db;
+use crate::models::openai::ChatCompletionRequest;
+use crate::state::AppState;
+
+/// Header carrying the recording's ID.
+pub const HEADER: &str = "x-recording-id";
+
+/// Request extension marking a completion as the replay of a recording;
+/// it is recorded whether or not `RECORD_REQUESTS` is on.
+#[derive(Clone)]
+pub struct ReplayOf(pub String);
+
+/// A completion being recorded.
+pub struct Recording {
+    pub id: String,
+    request: Value,
+    invocation: Option<Value>,
+    /// Set when this run replays another recording.
+    pub replay_of: Option<String>,
+}
+
+impl Recording {
+    /// Start recording `request` if `RECORD_REQUESTS` is on or it replays
+    /// the recording `replay_of`.
+    pub fn start(state: &AppState, request: &ChatCompletionRequest, replay_of: Option<String>) -> Option<Self> {
+        if !state.config().record_requests && replay_of.is_none() {
+            return None;
+        }
+        Some(Self {
+            id: format!("rec-{}", uuid::Uuid::new_v4().as_simple()),
+            request: serde_json::to_value(request).unwrap_or_default(),
+            invocation: None,
+            replay_of,
+        })
+    }
+
+    /// Note the CLI invocation the request was rendered to. The flags are
+    /// the Claude CLI's; other backends build their own command line.
+    pub fn set_invocation(&mut self, state: &AppState, profile: &ClaudeProfile, opts: &SpawnOptions<'_>) {
+        let flags = (profile.backend == "claude").then(|| {
+            let (args, claude_md) = claude_args(state.claude_manager.caps(profile), opts, false);
+            json!({ "args": args, "claude_md": claude_md })
+        });
+        self.invocation = Some(json!({
+            "profile": profile.name,
+            "backend": profile.backend,
+            "binary": profile.binary_path,
+            "project_dir": opts.project_dir,
+            "prompt": opts.prompt,
+            "cli": flags,
+        }));
+    }
+
+    /// Store the recording with `response`. Failures are logged.
+    pub async fn save(self, state: &AppState, session_id: Option<&str>, model: &str, response: &Value) {
+        let saved = db::save_recording(
+            &state.db,
+            &self.id,
+            session_id,
+            model,
+            &self.request,
+            self.invocation.as_ref(),
+            response,
+            self.replay_of.as_deref(),
+        )
+        .await;
+        if let Err(e) = saved {
+            tracing::warn!(recording_id = %self.id, error = %e, "Failed to store recording");
+        }
+    }
+}
+
+/// Add [`HEADER`] to `response` when the completion was recorded.
+pub fn tag(mut response: Response, id: Option<String>) -> Response {
+    if let Some(value) = id.and_then(|id| HeaderValue::from_str(&id).ok()) {
+        response.headers_mut().insert(HEADER, value);
+    }
+    response
+}
@@ -0,0 +1,482 @@
+//! Shared session registry for running several gateway replicas behind one
+//! load balancer (`SESSION_REGISTRY_URL`).
+//!
+//! Every replica records which sessions it owns in a shared database
+//! (Postgres, or a SQLite file on a shared volume) and heartbeats its row
+//! in `gateway_instances`. A session belongs to the replica that last ran
+//! it, since the CLI keeps the conversation on that host's disk. Requests
+//! naming a session owned by another live replica (continuations, status,
+//! stop, kill, and cancelling a completion it is answering) are forwarded
+//! to that replica's `INSTANCE_URL`, and `GLOBAL_MAX_CONCURRENT_SESSIONS`
+//! caps running sessions across all of them. Without a registry each
+//! replica only knows its own sessions.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::body::{Body, Bytes};
+use axum::extract::{OriginalUri, RawPathParams, Request, State};
+use axum::http::{header, HeaderName};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use serde::Deserialize;
+use sqlx::any::AnyPoolOptions;
+use sqlx::AnyPool;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::state::AppState;
+
+/// How often a replica refreshes its heartbeat.
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(10);
+/// Replicas not heard from for this long are treated as gone: their
+/// sessions no longer count or get requests forwarded.
+const INSTANCE_TTL: Duration = Duration::from_secs(30);
+/// Replicas gone this long are deleted along with their sessions.
+const INSTANCE_EXPIRY: Duration = Duration::from_secs(24 * 3600);
+/// Marks a forwarded request (with the sender's instance ID) so it is
+/// never forwarded again.
+const FORWARDED_HEADER: &str = "x-gateway-forwarded-by";
+/// Largest chat request body buffered to find its `session_id` (axum's
+/// default body limit, which the handler would apply anyway).
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+const SCHEMA: [&str; 3] = [
+    "CREATE TABLE IF NOT EXISTS gateway_instances (
+        instance_id TEXT PRIMARY KEY,
+        url TEXT,
+        heartbeat_at BIGINT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS gateway_sessions (
+        session_id TEXT PRIMARY KEY,
+        instance_id TEXT NOT NULL,
+        state TEXT NOT NULL,
+        updated_at BIGINT NOT NULL
+    )",
+    "CREATE TABLE IF NOT EXISTS gateway_completions (
+        completion_id TEXT PRIMARY KEY,
+        session_id TEXT NOT NULL,
+        updated_at BIGINT NOT NULL
+    )",
+];
+
+/// This replica's view of the shared registry.
+pub struct SessionRegistry {
+    pool: AnyPool,
+    instance_id: String,
+    instance_url: Option<String>,
+    global_max: usize,
+}
+
+/// Another live replica that owns a session.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Owner {
+    pub instance_id: String,
+    pub url: String,
+    /// Whether the session has a process running there.
+    pub running: bool,
+}
+
+fn now_ms() -> i64 {
+    chrono::Utc::now().timestamp_millis()
+}
+
+impl SessionRegistry {
+    /// Connect to `SESSION_REGISTRY_URL` and register this replica; `None`
+    /// when no registry is configured.
+    pub async fn connect(config: &Config) -> Result<Option<Arc<Self>>, sqlx::Error> {
+        let Some(ref url) = config.session_registry_url else {
+            return Ok(None);
+        };
+        sqlx::any::install_default_drivers();
+        // Let the first replica create a SQLite registry file
+        let url = if url.starts_with("sqlite:") && !url.contains('?') {
+            format!("{url}?mode=rwc")
+        } else {
+            url.clone()
+        };
+        let pool = AnyPoolOptions::new().max_connections(5).connect(&url).await?;
+        for statement in SCHEMA {
+            sqlx::query(statement).execute(&pool).await?;
+        }
+        if config.instance_url.is_none() {
+            tracing::warn!("SESSION_REGISTRY_URL is set without INSTANCE_URL; other replicas can't forward requests here");
+        }
+        let registry = Self {
+            pool,
+            instance_id: config.instance_id.clone(),
+            instance_url: config.instance_url.clone(),
+            global_max: config.global_max_concurrent_sessions,
+        };
+        // Processes of a previous run with this ID are gone, their sessions aren't
+        sqlx::query("UPDATE gateway_sessions SET state = 'idle' WHERE instance_id = $1")
+            .bind(&registry.instance_id)
+            .execute(&registry.pool)
+            .await?;
+        registry.heartbeat().await?;
+        tracing::info!(instance_id = %registry.instance_id, "Joined the session registry");
+        Ok(Some(Arc::new(registry)))
+    }
+
+    pub fn instance_id(&self) -> &str {
+        &self.instance_id
+    }
+
+    /// Refresh this replica's heartbeat and drop replicas long gone.
+    async fn heartbeat(&self) -> Result<(), sqlx::Error> {
+        let now = now_ms();
+        sqlx::query(
+            "INSERT INTO gateway_instances (instance_id, url, heartbeat_at) VALUES ($1, $2, $3)
+             ON CONFLICT (instance_id) DO UPDATE SET url = excluded.url, heartbeat_at = excluded.heartbeat_at",
+        )
+        .bind(&self.instance_id)
+        .bind(self.instance_url.clone())
+        .bind(now)
+        .execute(&self.pool)
+        .await?;
+        let expired = now - INSTANCE_EXPIRY.as_millis() as i64;
+        sqlx::query(
+            "DELETE FROM gateway_sessions WHERE instance_id IN
+             (SELECT instance_id FROM gateway_instances WHERE heartbeat_at < $1)",
+        )
+        .bind(expired)
+        .execute(&self.pool)
+        .await?;
+        sqlx::query("DELETE FROM gateway_completions WHERE updated_at < $1")
+            .bind(expired)
+            .execute(&self.pool)
+            .await?;
+        sqlx::query("DELETE FROM gateway_instances WHERE heartbeat_at < $1")
+            .bind(expired)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Heartbeat every [`HEARTBEAT_INTERVAL`] until the process exits.
+    pub fn spawn(self: &Arc<Self>) {
+        let registry = Arc::clone(self);
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(HEARTBEAT_INTERVAL);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                if let Err(e) = registry.heartbeat().await {
+                    tracing::warn!(error = %e, "Session registry heartbeat failed");
+                }
+            }
+        });
+    }
+
+    /// Record a session as running here, first checking the global cap.
+    ///
+    /// The check and the insert are separate statements, so replicas
+    /// claiming at the same moment can overshoot the cap by a few. A
+    /// registry that can't be reached doesn't block the session.
+    pub async fn claim(&self, session_id: &str) -> Result<(), AppError> {
+        if self.global_max > 0 {
+            match self.running_besides(session_id).await {
+                Ok(running) if running >= self.global_max as i64 => {
+                    return Err(AppError::ServiceUnavailable(format!(
+                        "Maximum concurrent sessions across instances ({}) reached",
+                        self.global_max
+                    )));
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!(error = %e, "Session registry unavailable; skipping the global limit"),
+            }
+        }
+        self.record(session_id, "running").await;
+        Ok(())
+    }
+
+    /// Running sessions on live replicas, other than `session_id`.
+    async fn running_besides(&self, session_id: &str) -> Result<i64, sqlx::Error> {
+        let (count,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM gateway_sessions s
+             JOIN gateway_instances i ON i.instance_id = s.instance_id
+             WHERE s.state = 'running' AND i.heartbeat_at >= $1 AND s.session_id <> $2",
+        )
+        .bind(now_ms() - INSTANCE_TTL.as_millis() as i64)
+        .bind(session_id)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok(count)
+    }
+
+    /// The session's process ended; this replica still owns its history.
+    pub async fn release(&self, session_id: &str) {
+        self.record(session_id, "idle").await;
+        let result = sqlx::query("DELETE FROM gateway_completions WHERE session_id = $1")
+            .bind(session_id)
+            .execute(&self.pool)
+            .await;
+        if let Err(e) = result {
+            tracing::warn!(session_id, error = %e, "Failed to update the session registry");
+        }
+    }
+
+    /// Record that `session_id` is answering `completion_id`, so a cancel
+    /// sent to any replica reaches the one running it.
+    pub async fn track_completion(&self, completion_id: &str, session_id: &str) {
+        let result = sqlx::query(
+            "INSERT INTO gateway_completions (completion_id, session_id, updated_at) VALUES ($1, $2, $3)
+             ON CONFLICT (completion_id) DO UPDATE SET
+                session_id = excluded.session_id, updated_at = excluded.updated_at",
+        )
+        .bind(completion_id)
+        .bind(session_id)
+        .bind(now_ms())
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = result {
+            tracing::warn!(completion_id, error = %e, "Failed to update the session registry");
+        }
+    }
+
+    /// The session answering a running completion.
+    pub async fn completion_session(&self, completion_id: &str) -> Result<Option<String>, sqlx::Error> {
+        let row: Option<(String,)> = sqlx::query_as("SELECT session_id FROM gateway_completions WHERE completion_id = $1")
+            .bind(completion_id)
+            .fetch_optional(&self.pool)
+            .await?;
+        Ok(row.map(|(session_id,)| session_id))
+    }
+
+    /// Move a claim to the ID the CLI reported for the session.
+    pub async fn rename(&self, from: &str, to: &str) {
+        self.record(to, "running").await;
+        let result = sqlx::query("DELETE FROM gateway_sessions WHERE session_id = $1 AND instance_id = $2")
+            .bind(from)
+            .bind(&self.instance_id)
+            .execute(&self.pool)
+            .await;
+        if let Err(e) = result {
+            tracing::warn!(session_id = from, error = %e, "Failed to update the session registry");
+        }
+    }
+
+    async fn record(&self, session_id: &str, state: &str) {
+        let result = sqlx::query(
+            "INSERT INTO gateway_sessions (session_id, instance_id, state, updated_at) VALUES ($1, $2, $3, $4)
+             ON CONFLICT (session_id) DO UPDATE SET
+                instance_id = excluded.instance_id, state = excluded.state, updated_at = excluded.updated_at",
+        )
+        .bind(session_id)
+        .bind(&self.instance_id)
+        .bind(state)
+        .bind(now_ms())
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = result {
+            tracing::warn!(session_id, error = %e, "Failed to update the session registry");
+        }
+    }
+
+    /// The live replica that owns `session_id`, unless it is this one or
+    /// nobody does.
+    pub async fn owner(&self, session_id: &str) -> Result<Option<Owner>, sqlx::Error> {
+        let row: Option<(String, Option<String>, String)> = sqlx::query_as(
+            "SELECT i.instance_id, i.url, s.state FROM gateway_sessions s
+             JOIN gateway_instances i ON i.instance_id = s.instance_id
+             WHERE s.session_id = $1 AND i.heartbeat_at >= $2",
+        )
+        .bind(session_id)
+        .bind(now_ms() - INSTANCE_TTL.as_millis() as i64)
+        .fetch_optional(&self.pool)
+        .await?;
+        Ok(row.and_then(|(instance_id, url, state)| {
+            if instance_id == self.instance_id {
+                return None;
+            }
+            let Some(url) = url else {
+                tracing::warn!(session_id, owner = %instance_id, "Session owner has no INSTANCE_URL; handling it here");
+                return None;
+            };
+            Some(Owner { instance_id, url, running: state == "running" })
+        }))
+    }
+
+    /// Live replicas and how many sessions each is running.
+    pub async fn instances(&self) -> Result<Vec<(String, i64)>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT i.instance_id, COUNT(s.session_id) FROM gateway_instances i
+             LEFT JOIN gateway_sessions s ON s.instance_id = i.instance_id AND s.state = 'running'
+             WHERE i.heartbeat_at >= $1 GROUP BY i.instance_id ORDER BY i.instance_id",
+        )
+        .bind(now_ms() - INSTANCE_TTL.as_millis() as i64)
+        .fetch_all(&self.pool)
+        .await
+    }
+}
+
+/// The `session_id` of a chat completion request body.
+#[derive(Deserialize)]
+struct SessionRef {
+    session_id: Option<String>,
+}
+
+/// Route layer for endpoints about one session: when another live replica
+/// owns it, proxy the request there instead of handling it here. The
+/// session is the `{session_id}` path parameter, the one answering the
+/// `{completion_id}` path parameter, or the body's `session_id`.
+pub async fn forward_to_owner(
+    State(state): State<Arc<AppState>>,
+    params: RawPathParams,
+    request: Request,
+    next: Next,
+) -> Result<Response, AppError> {
+    let Some(ref registry) = state.registry else {
+        return Ok(next.run(request).await);
+    };
+    if request.headers().contains_key(FORWARDED_HEADER) {
+        return Ok(next.run(request).await);
+    }
+    let mut from_path = params.iter().find(|(k, _)| *k == "session_id").map(|(_, v)| v.to_string());
+    if let Some((_, completion_id)) = params.iter().find(|(k, _)| *k == "completion_id") {
+        from_path = registry.completion_session(completion_id).await.unwrap_or_else(|e| {
+            tracing::warn!(completion_id, error = %e, "Session registry lookup failed; handling it here");
+            None
+        });
+    }
+    let (parts, body) = request.into_parts();
+    let body = match from_path {
+        Some(_) => None,
+        None => Some(
+            axum::body::to_bytes(body, MAX_BODY_BYTES)
+                .await
+                .map_err(|e| AppError::BadRequest(format!("Failed to read request body: {e}")))?,
+        ),
+    };
+    let session_id = from_path.or_else(|| {
+        body.as_deref()
+            .and_then(|b| serde_json::from_slice::<SessionRef>(b).ok())
+            .and_then(|r| r.session_id)
+    });
+    let owner = match session_id {
+        Some(ref sid) => registry.owner(sid).await.unwrap_or_else(|e| {
+            tracing::warn!(session_id = %sid, error = %e, "Session registry lookup failed; handling it here");
+            None
+        }),
+        None => None,
+    };
+    let body = body.unwrap_or_default();
+    match owner {
+        Some(owner) => forward(&state.http, registry, &owner, parts, body).await,
+        None => Ok(next.run(Request::from_parts(parts, Body::from(body))).await),
+    }
+}
+
+/// Headers that only apply to one hop.
+const HOP_HEADERS: [HeaderName; 4] =
+    [header::HOST, header::CONNECTION, header::CONTENT_LENGTH, header::TRANSFER_ENCODING];
+
+async fn forward(
+    client: &reqwest::Client,
+    registry: &SessionRegistry,
+    owner: &Owner,
+    parts: axum::http::request::Parts,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    let path = parts
+        .extensions
+        .get::<OriginalUri>()
+        .map(|u| &u.0)
+        .unwrap_or(&parts.uri)
+        .path_and_query()
+        .map_or("/", |p| p.as_str())
+        .to_string();
+    let mut headers = parts.headers;
+    for name in &HOP_HEADERS {
+        headers.remove(name);
+    }
+    let resp = client
+        .request(parts.method.clone(), format!("{}{path}", owner.url))
+        .headers(headers)
+        .header(FORWARDED_HEADER, registry.instance_id())
+        .body(body)
+        .send()
+        .await
+        .map_err(|e| {
+            tracing::warn!(owner = %owner.instance_id, error = %e, "Forwarding to the session's instance failed");
+            AppError::ServiceUnavailable(format!("Instance {} owning this session is unreachable: {e}", owner.instance_id))
+        })?;
+    tracing::info!(
+        owner = %owner.instance_id,
+        method = %parts.method,
+        path = %path,
+        status = resp.status().as_u16(),
+        "Forwarded request to the session's instance"
+    );
+    let mut response = Response::builder().status(resp.status());
+    for (name, value) in resp.headers() {
+        if !HOP_HEADERS.contains(name) {
+            response = response.header(name, value);
+        }
+    }
+    Ok(response
+        .body(Body::from_stream(resp.bytes_stream()))
+        .unwrap_or_else(|e| AppError::Internal(e.to_string()).into_response()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn replica(path: &std::path::Path, id: &str, global_max: usize) -> Arc<SessionRegistry> {
+        let mut config = Config::for_test();
+        config.session_registry_url = Some(format!("sqlite:{}", path.display()));
+        config.instance_id = id.to_string();
+        config.instance_url = Some(format!("http://{id}:8000"));
+        config.global_max_concurrent_sessions = global_max;
+        SessionRegistry::connect(&config).await.unwrap().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_ownership_and_global_limit() {
+        let path = std::env::temp_dir().join(format!("registry-{}.db", uuid::Uuid::new_v4()));
+        let a = replica(&path, "a", 1).await;
+        let b = replica(&path, "b", 1).await;
+
+        a.claim("s1").await.unwrap();
+        assert_eq!(a.owner("s1").await.unwrap(), None);
+        let owner = b.owner("s1").await.unwrap().unwrap();
+        assert_eq!((owner.instance_id.as_str(), owner.url.as_str(), owner.running), ("a", "http://a:8000", true));
+
+        // One running session across both replicas
+        assert!(matches!(b.claim("s2").await, Err(AppError::ServiceUnavailable(_))));
+        a.release("s1").await;
+        b.claim("s2").await.unwrap();
+        assert!(!b.owner("s1").await.unwrap().unwrap().running);
+
+        b.rename("s2", "s3").await;
+        assert_eq!(a.owner("s2").await.unwrap(), None);
+        assert_eq!(a.owner("s3").await.unwrap().unwrap().instance_id, "b");
+        assert_eq!(a.instances().await.unwrap(), [("a".to_string(), 0), ("b".to_string(), 1)]);
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_completion_lookup() {
+        let path = std::env::temp_dir().join(format!("registry-{}.db", uuid::Uuid::new_v4()));
+        let a = replica(&path, "a", 0).await;
+        let b = replica(&path, "b", 0).await;
+
+        a.claim("s1").await.unwrap();
+        a.track_completion("chatcmpl-1", "s1").await;
+        // A cancel arriving at b is routed to the session a runs
+        let session_id = b.completion_session("chatcmpl-1").await.unwrap().unwrap();
+        assert_eq!(session_id, "s1");
+        assert_eq!(b.owner(&session_id).await.unwrap().unwrap().instance_id, "a");
+
+        a.release("s1").await;
+        assert_eq!(b.completion_session("chatcmpl-1").await.unwrap(), None);
+
+        for suffix in ["", "-wal", "-shm"] {
+            let _ = std::fs::remove_file(format!("{}{suffix}", path.display()));
+        }
+    }
+}
@@ -0,0 +1,150 @@
+//! Hot configuration reload on `SIGHUP` or `POST /admin/reload`: `.env` and
+//! `CONFIG_FILE` are read again and the settings that are safe to change
+//! under running sessions are applied. As at startup, variables set in the
+//! process environment win over `.env` (which is applied without touching
+//! the environment). Anything else still needs a restart.
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::state::AppState;
+
+/// Copy the reloadable fields from `fresh` into `next`, returning the names
+/// of those whose value changed.
+macro_rules! take_fields {
+    ($next:ident, $fresh:ident, $($field:ident),+ $(,)?) => {{
+        let mut changed = Vec::new();
+        $(
+            if format!("{:?}", $next.$field) != format!("{:?}", $fresh.$field) {
+                changed.push(stringify!($field));
+            }
+            $next.$field = $fresh.$field;
+        )+
+        changed
+    }};
+}
+
+/// Merge the reloadable settings of `fresh` into `current`: API keys and
+/// their accounts, rate limits, the model catalog and pricing, request and
+/// history budgets, conversation templates and the hook allowlist.
+fn merge(current: &Config, fresh: Config) -> (Config, Vec<&'static str>) {
+    let mut next = current.clone();
+    let changed = take_fields!(
+        next,
+        fresh,
+        api_keys,
+        admin_api_keys,
+        key_config_dirs,
+        key_projects,
+        organization_projects,
+        tiers,
+        key_tiers,
+        default_tier,
+        rate_limit_requests_per_minute,
+        rate_limit_burst,
+        rate_limit_max_keys,
+        user_rate_limit_requests_per_minute,
+        user_daily_budget_usd,
+        key_monthly_tokens,
+        daily_budget_usd,
+        monthly_budget_usd,
+        budget_alert_percents,
+        webhook_urls,
+        webhook_secret,
+        webhook_events,
+        webhook_max_attempts,
+        webhook_timeout_seconds,
+        model_catalog,
+        strict_model_validation,
+        pricing_file,
+        model_pricing,
+        history_token_budget,
+        history_keep_recent,
+        conversation_template_file,
+        project_conversation_templates,
+        hook_command_allowlist,
+        record_requests,
+        stream_chunk_bytes,
+        stream_flush_ms,
+        stream_split_sentences,
+        github_token,
+        github_token_repos,
+        max_messages,
+        max_message_bytes,
+        max_prompt_bytes,
+        message_index_batch,
+        message_index_interval_ms,
+    );
+    (next, changed)
+}
+
+/// Re-read the configuration and apply it. Returns the settings that
+/// changed; a malformed `.env` or config file fails the reload and changes
+/// nothing.
+pub async fn reload(state: &AppState) -> Result<Vec<&'static str>, AppError> {
+    let env_file = read_env_file().map_err(|e| {
+        tracing::warn!(error = %e, "Configuration reload failed");
+        AppError::Internal(format!("Failed to read .env: {e}"))
+    })?;
+    let fresh = Config::load_with_env_file(env_file).map_err(|e| {
+        tracing::warn!(error = %e, "Configuration reload failed");
+        AppError::Internal(format!("Failed to read CONFIG_FILE {e}"))
+    })?;
+    let (next, changed) = merge(&state.config(), fresh);
+    state.replace_config(next).await;
+    tracing::info!(changed = ?changed, "Configuration reloaded");
+    Ok(changed)
+}
+
+/// Parse `.env`, if there is one, without applying it to the process
+/// environment (`set_var` is unsound while other threads read it).
+fn read_env_file() -> Result<BTreeMap<String, String>, dotenvy::Error> {
+    match dotenvy::dotenv_iter() {
+        Ok(iter) => iter.collect(),
+        Err(e) if e.not_found() => Ok(BTreeMap::new()),
+        Err(e) => Err(e),
+    }
+}
+
+/// Reload on every `SIGHUP` (unix only).
+pub fn spawn(state: Arc<AppState>) {
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut hangup = match signal(SignalKind::hangup()) {
+            Ok(s) => s,
+            Err(e) => {
+                tracing::warn!(error = %e, "Failed to listen for SIGHUP; reload via /admin/reload only");
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            tracing::info!("SIGHUP received, reloading configuration");
+            let _ = reload(&state).await;
+        }
+    });
+    #[cfg(not(unix))]
+    let _ = state;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_merge() {
+        let current = Config::for_test();
+        let mut fresh = current.clone();
+        fresh.api_keys = vec!["rotated".to_string()];
+        fresh.rate_limit_burst = current.rate_limit_burst + 5;
+        fresh.port = current.port + 1;
+
+        let (next, changed) = merge(&current, fresh);
+        assert_eq!(changed, ["api_keys", "rate_limit_burst"]);
+        assert_eq!(next.api_keys, ["rotated"]);
+        // Listeners can't change without a restart
+        assert_eq!(next.port, current.port);
+    }
+}
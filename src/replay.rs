@@ -0,0 +1,109 @@
+use serde_json::{json, Value};
+
+use crate::db::MessageRow;
+use crate::streaming;
+
+/// Rebuild a stored conversation as `chat.completion.chunk` objects, in the
+/// order a live client would have seen them.
+///
+/// Assistant turns are framed exactly like a live completion (role chunk,
+/// content, tool calls, finish chunk); user turns become a single chunk
+/// with `delta.role = "user"` so front-ends can render both sides. With
+/// `split_words`, content is cut into word-sized deltas for paced replay.
+pub fn replay_chunks(messages: &[MessageRow], split_words: bool) -> Vec<Value> {
+    let mut chunks = Vec::new();
+    for msg in messages {
+        let meta = msg
+            .message_metadata
+            .as_ref()
+            .map(|m| m.0.clone())
+            .unwrap_or(Value::Null);
+        let id = meta
+            .get("completion_id")
+            .and_then(|v| v.as_str())
+            .map_or_else(|| format!("chatcmpl-replay-{}", msg.id), str::to_string);
+        let model = meta.get("model").and_then(|v| v.as_str()).unwrap_or("unknown");
+        let created = chrono::NaiveDateTime::parse_from_str(&msg.created_at, "%Y-%m-%d %H:%M:%S")
+            .map_or(0, |t| t.and_utc().timestamp());
+
+        if msg.role != "assistant" {
+            chunks.push(json!({
+                "id": id,
+                "object": "chat.completion.chunk",
+                "created": created,
+                "model": model,
+                "choices": [{
+                    "index": 0,
+                    "delta": {"role": msg.role, "content": msg.content},
+                    "finish_reason": null
+                }]
+            }));
+            continue;
+        }
+
+        chunks.push(streaming::initial_chunk(&id, model, created));
+        if split_words {
+            for word in msg.content.split_inclusive(char::is_whitespace) {
+                chunks.push(streaming::content_chunk(&id, model, created, word));
+            }
+        } else if !msg.content.is_empty() {
+            chunks.push(streaming::content_chunk(&id, model, created, &msg.content));
+        }
+        if let Some(tool_calls) = meta.get("tool_calls").and_then(|v| v.as_array()) {
+            for (i, tc) in tool_calls.iter().enumerate() {
+                chunks.push(streaming::tool_call_chunk(&id, model, created, i, tc));
+            }
+        }
+        let finish_reason = meta
+            .get("finish_reason")
+            .and_then(|v| v.as_str())
+            .unwrap_or("stop");
+        chunks.push(streaming::final_chunk(&id, model, created, finish_reason));
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn row(id: i64, role: &str, content: &str, meta: Value) -> MessageRow {
+        MessageRow {
+            id,
+            session_id: "s1".into(),
+            role: role.into(),
+            content: content.into(),
+            message_metadata: Some(sqlx::types::Json(meta)),
+            created_at: "2026-01-02 03:04:05".into(),
+            input_tokens: 0,
+            output_tokens: 0,
+            cost: 0.0,
+        }
+    }
+
+    #[test]
+    fn test_replay_chunks() {
+        let messages = vec![
+            row(1, "user", "hi", json!({"model": "m"})),
+            row(2, "assistant", "hello there", json!({
+                "model": "m",
+                "completion_id": "chatcmpl-abc",
+                "finish_reason": "tool_calls",
+                "tool_calls": [{"id": "call_1", "type": "function", "function": {"name": "f", "arguments": "{}"}}],
+            })),
+        ];
+
+        let chunks = replay_chunks(&messages, false);
+        assert_eq!(chunks.len(), 5);
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "user");
+        assert_eq!(chunks[1]["id"], "chatcmpl-abc");
+        assert_eq!(chunks[1]["created"], 1767323045);
+        assert_eq!(chunks[2]["choices"][0]["delta"]["content"], "hello there");
+        assert_eq!(chunks[3]["choices"][0]["delta"]["tool_calls"][0]["id"], "call_1");
+        assert_eq!(chunks[4]["choices"][0]["finish_reason"], "tool_calls");
+
+        let paced = replay_chunks(&messages, true);
+        assert_eq!(paced[2]["choices"][0]["delta"]["content"], "hello ");
+        assert_eq!(paced[3]["choices"][0]["delta"]["content"], "there");
+    }
+}
@@ -0,0 +1,132 @@
+//! Resumable SSE streams. Every event of a streamed completion gets an
+//! `id: <completion id>:<n>` line and is kept in memory until the stream
+//! has been finished for `STREAM_RESUME_SECONDS`. A client that lost the
+//! connection sends the same request again with `Last-Event-ID` and gets
+//! the events it missed, then the rest of the live stream, from the run
+//! already in progress.
+
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures::{Stream, StreamExt};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::UnboundedReceiverStream;
+
+/// Formatted SSE events.
+pub type EventStream = Pin<Box<dyn Stream<Item = String> + Send>>;
+
+/// The events of one completion so far.
+#[derive(Default)]
+struct Buffer {
+    /// The API key that started the stream; only it may resume.
+    api_key: Option<String>,
+    events: Vec<String>,
+    subscribers: Vec<mpsc::UnboundedSender<String>>,
+    finished: Option<Instant>,
+}
+
+impl Buffer {
+    /// Events after index `after` (all of them for `None`), then live ones.
+    fn subscribe(&mut self, after: Option<usize>) -> EventStream {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let start = after.map_or(0, |n| n + 1);
+        for event in self.events.iter().skip(start) {
+            let _ = tx.send(event.clone());
+        }
+        if self.finished.is_none() {
+            self.subscribers.push(tx);
+        }
+        Box::pin(UnboundedReceiverStream::new(rx))
+    }
+}
+
+/// Buffered streams by completion ID.
+pub struct StreamBuffers {
+    /// How long finished streams are kept.
+    window: Duration,
+    streams: Mutex<HashMap<String, Arc<Mutex<Buffer>>>>,
+}
+
+impl StreamBuffers {
+    pub fn new(window: Duration) -> Self {
+        Self { window, streams: Mutex::default() }
+    }
+
+    /// Buffer `events` under `completion_id`, numbering them as they pass,
+    /// and return the stream for the client that started the completion.
+    /// The events keep being buffered if that client disconnects.
+    pub fn record(
+        self: &Arc<Self>,
+        completion_id: &str,
+        api_key: Option<&str>,
+        mut events: impl Stream<Item = String> + Send + Unpin + 'static,
+    ) -> EventStream {
+        let buffer = Arc::new(Mutex::new(Buffer { api_key: api_key.map(str::to_string), ..Default::default() }));
+        {
+            let mut streams = self.streams.lock().unwrap();
+            // Finished streams are dropped lazily, when a new one starts
+            streams.retain(|_, b| b.lock().unwrap().finished.is_none_or(|at| at.elapsed() < self.window));
+            streams.insert(completion_id.to_string(), Arc::clone(&buffer));
+        }
+        let stream = buffer.lock().unwrap().subscribe(None);
+
+        let completion_id = completion_id.to_string();
+        tokio::spawn(async move {
+            while let Some(event) = events.next().await {
+                let mut buffer = buffer.lock().unwrap();
+                let event = format!("id: {completion_id}:{}\n{event}", buffer.events.len());
+                buffer.subscribers.retain(|tx| tx.send(event.clone()).is_ok());
+                buffer.events.push(event);
+            }
+            let mut buffer = buffer.lock().unwrap();
+            buffer.finished = Some(Instant::now());
+            buffer.subscribers.clear();
+        });
+        stream
+    }
+
+    /// The events after `last_event_id`, followed by the live stream if it
+    /// is still running. `None` if the ID is malformed, no longer buffered,
+    /// or belongs to another API key.
+    pub fn resume(&self, last_event_id: &str, api_key: Option<&str>) -> Option<EventStream> {
+        let (completion_id, n) = last_event_id.trim().rsplit_once(':')?;
+        let n: usize = n.parse().ok()?;
+        let buffer = Arc::clone(self.streams.lock().unwrap().get(completion_id)?);
+        let mut buffer = buffer.lock().unwrap();
+        if buffer.api_key.as_deref() != api_key || buffer.finished.is_some_and(|at| at.elapsed() >= self.window) {
+            return None;
+        }
+        Some(buffer.subscribe(Some(n)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_resume() {
+        let buffers = Arc::new(StreamBuffers::new(Duration::from_secs(60)));
+        let (tx, rx) = mpsc::unbounded_channel();
+        let primary = buffers.record("chatcmpl-1", Some("sk-a"), UnboundedReceiverStream::new(rx));
+        for event in ["data: a\n\n", "data: b\n\n", "data: c\n\n"] {
+            tx.send(event.to_string()).unwrap();
+        }
+        // The first client got everything, numbered
+        let first: Vec<String> = primary.take(3).collect().await;
+        assert_eq!(first[0], "id: chatcmpl-1:0\ndata: a\n\n");
+
+        // A reconnect after event 0 gets 1 and 2, then what comes next
+        let resumed = buffers.resume("chatcmpl-1:0", Some("sk-a")).unwrap();
+        tx.send("data: [DONE]\n\n".to_string()).unwrap();
+        drop(tx);
+        let rest: Vec<String> = resumed.collect().await;
+        assert_eq!(rest, ["id: chatcmpl-1:1\ndata: b\n\n", "id: chatcmpl-1:2\ndata: c\n\n", "id: chatcmpl-1:3\ndata: [DONE]\n\n"]);
+
+        assert!(buffers.resume("chatcmpl-1:0", Some("sk-b")).is_none());
+        assert!(buffers.resume("chatcmpl-2:0", Some("sk-a")).is_none());
+        assert!(buffers.resume("garbage", Some("sk-a")).is_none());
+    }
+}
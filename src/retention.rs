@@ -0,0 +1,45 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use sqlx::SqlitePool;
+
+use crate::db::{self, PurgeReport};
+use crate::state::AppState;
+
+/// Start the background task enforcing `MESSAGE_RETENTION_DAYS`, once at
+/// startup and then every `CLEANUP_INTERVAL_MINUTES`. No-op when retention
+/// is disabled.
+pub fn spawn(state: Arc<AppState>) {
+    let days = state.config().message_retention_days;
+    if days == 0 {
+        return;
+    }
+    let interval = Duration::from_secs(state.config().cleanup_interval_minutes.max(1) * 60);
+    tracing::info!(retention_days = days, "Message retention enabled");
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            let _ = run(&state.db, days).await;
+        }
+    });
+}
+
+/// Purge everything older than `days` and log what was removed.
+pub async fn run(db: &SqlitePool, days: u64) -> Result<PurgeReport, sqlx::Error> {
+    let report = db::purge_expired(db, days)
+        .await
+        .inspect_err(|e| tracing::warn!(error = %e, "Retention purge failed"))?;
+    if report.messages + report.transcripts + report.sessions + report.recordings > 0 {
+        tracing::info!(
+            retention_days = days,
+            messages = report.messages,
+            transcripts = report.transcripts,
+            sessions = report.sessions,
+            recordings = report.recordings,
+            "Purged expired conversation data"
+        );
+    }
+    Ok(report)
+}
@@ -0,0 +1,813 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap};
+use axum::response::{Html, IntoResponse};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use serde_json::{json, Value};
+
+use crate::auth::hash_api_key;
+use crate::budget;
+use crate::db::{self, RequestLogFilter};
+use crate::erasure;
+use crate::quota;
+use crate::export;
+use crate::error::{AppError, ErrorResponse};
+use crate::extract::AppJson;
+use crate::models::openai::ChatCompletionRequest;
+use crate::recording::{self, ReplayOf};
+use crate::routes::chat;
+use crate::reload;
+use crate::retention;
+use crate::state::AppState;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct AuditQuery {
+    /// Raw API key; hashed before matching.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub key_hash: Option<String>,
+    /// End user, the `user` field of the request.
+    #[serde(default)]
+    pub user: Option<String>,
+    #[serde(default)]
+    pub route: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default)]
+    pub session_id: Option<String>,
+    #[serde(default)]
+    pub status: Option<i64>,
+    /// Inclusive lower bound, RFC 3339 or `YYYY-MM-DD HH:MM:SS` (UTC).
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Exclusive upper bound, same formats as `since`.
+    #[serde(default)]
+    pub until: Option<String>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub offset: Option<i64>,
+}
+
+/// GET /admin/audit
+#[utoipa::path(
+    get, path = "/admin/audit", tag = "admin",
+    params(AuditQuery),
+    responses((status = 200, description = "Request log entries, newest first", body = Object), (status = 400, description = "Invalid request", body = ErrorResponse))
+)]
+pub async fn list_audit_log(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<AuditQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let limit = q.limit.unwrap_or(100).clamp(1, 1000);
+    let offset = q.offset.unwrap_or(0).max(0);
+    let filter = RequestLogFilter {
+        key_hash: q.api_key.as_deref().map(hash_api_key).or(q.key_hash),
+        user: q.user,
+        route: q.route,
+        model: q.model,
+        session_id: q.session_id,
+        status: q.status,
+        since: q.since.as_deref().map(normalize_timestamp).transpose()?,
+        until: q.until.as_deref().map(normalize_timestamp).transpose()?,
+        limit,
+        offset,
+    };
+
+    let rows = db::list_request_log(&state.db, &filter).await?;
+    Ok(Json(json!({
+        "data": rows,
+        "pagination": { "count": rows.len(), "limit": limit, "offset": offset },
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct UsageQuery {
+    /// Raw API key; hashed before matching.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub key_hash: Option<String>,
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Inclusive lower bound, RFC 3339 or `YYYY-MM-DD HH:MM:SS` (UTC).
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Exclusive upper bound, same formats as `since`.
+    #[serde(default)]
+    pub until: Option<String>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub offset: Option<i64>,
+}
+
+/// GET /admin/usage
+///
+/// Requests, tokens and cost per API key and end user (the `user` request
+/// field), from the audit log.
+#[utoipa::path(
+    get, path = "/admin/usage", tag = "admin",
+    params(UsageQuery),
+    responses((status = 200, description = "Usage per key and user, most expensive first", body = Object), (status = 400, description = "Invalid request", body = ErrorResponse))
+)]
+pub async fn list_usage(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<UsageQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let limit = q.limit.unwrap_or(100).clamp(1, 1000);
+    let offset = q.offset.unwrap_or(0).max(0);
+    let filter = RequestLogFilter {
+        key_hash: q.api_key.as_deref().map(hash_api_key).or(q.key_hash),
+        user: q.user,
+        since: q.since.as_deref().map(normalize_timestamp).transpose()?,
+        until: q.until.as_deref().map(normalize_timestamp).transpose()?,
+        limit,
+        offset,
+        ..Default::default()
+    };
+
+    let rows = db::usage_by_user(&state.db, &filter).await?;
+    Ok(Json(json!({
+        "data": rows,
+        "pagination": { "count": rows.len(), "limit": limit, "offset": offset },
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DailyUsageQuery {
+    /// Days to cover, today included (default 14, at most 366).
+    #[serde(default)]
+    pub days: Option<i64>,
+}
+
+/// GET /admin/usage/daily
+///
+/// Requests, errors, tokens and cost per UTC day, oldest first; days
+/// without requests are included with zeros.
+#[utoipa::path(
+    get, path = "/admin/usage/daily", tag = "admin",
+    params(DailyUsageQuery),
+    responses((status = 200, description = "Usage per day", body = Object))
+)]
+pub async fn daily_usage(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<DailyUsageQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let days = q.days.unwrap_or(14).clamp(1, 366);
+    let today = chrono::Utc::now().date_naive();
+    let first = today - chrono::Days::new(days as u64 - 1);
+    let rows = db::daily_usage(&state.db, &first.format("%Y-%m-%d").to_string()).await?;
+    let data: Vec<Value> = first
+        .iter_days()
+        .take(days as usize)
+        .map(|day| {
+            let day = day.format("%Y-%m-%d").to_string();
+            match rows.iter().find(|row| row.day == day) {
+                Some(row) => json!(row),
+                None => json!({
+                    "day": day,
+                    "requests": 0,
+                    "errors": 0,
+                    "prompt_tokens": 0,
+                    "completion_tokens": 0,
+                    "cost": 0.0,
+                }),
+            }
+        })
+        .collect();
+    Ok(Json(json!({ "object": "list", "data": data })))
+}
+
+/// Convert a timestamp to SQLite's `datetime('now')` format for comparison.
+fn normalize_timestamp(ts: &str) -> Result<String, AppError> {
+    if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(ts) {
+        return Ok(dt
+            .with_timezone(&chrono::Utc)
+            .format("%Y-%m-%d %H:%M:%S")
+            .to_string());
+    }
+    if chrono::NaiveDateTime::parse_from_str(ts, "%Y-%m-%d %H:%M:%S").is_ok() {
+        return Ok(ts.to_string());
+    }
+    if chrono::NaiveDate::parse_from_str(ts, "%Y-%m-%d").is_ok() {
+        return Ok(format!("{ts} 00:00:00"));
+    }
+    Err(AppError::BadRequest(format!("Invalid timestamp: {ts}")))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RetentionQuery {
+    /// Overrides `MESSAGE_RETENTION_DAYS` for this run.
+    #[serde(default)]
+    pub days: Option<u64>,
+}
+
+/// POST /admin/retention/run
+///
+/// Purge expired messages, transcripts and empty sessions now instead of
+/// waiting for the background job.
+#[utoipa::path(
+    post, path = "/admin/retention/run", tag = "admin",
+    params(RetentionQuery),
+    responses((status = 200, description = "Rows deleted per table", body = Object), (status = 400, description = "Invalid request", body = ErrorResponse))
+)]
+pub async fn run_retention(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<RetentionQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let days = q.days.unwrap_or(state.config().message_retention_days);
+    if days == 0 {
+        return Err(AppError::BadRequest(
+            "Retention is disabled; set MESSAGE_RETENTION_DAYS or pass ?days=".to_string(),
+        ));
+    }
+    let report = retention::run(&state.db, days).await?;
+    Ok(Json(json!({
+        "retention_days": days,
+        "deleted": report,
+    })))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PurgeRequest {
+    /// Raw API key; hashed before matching.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub key_hash: Option<String>,
+    /// End user, the `user` field of the requests.
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Inclusive lower bound, RFC 3339 or `YYYY-MM-DD HH:MM:SS` (UTC).
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Exclusive upper bound, same formats as `since`.
+    #[serde(default)]
+    pub until: Option<String>,
+}
+
+/// POST /admin/purge
+///
+/// Erase every conversation of a key or end user (those the request log
+/// ties to them), optionally within a time range, or every conversation
+/// started in a time range. Returns the erasure receipt.
+#[utoipa::path(
+    post, path = "/admin/purge", tag = "admin",
+    request_body = PurgeRequest,
+    responses((status = 200, description = "Erasure receipt", body = Object), (status = 400, description = "Invalid request", body = ErrorResponse))
+)]
+pub async fn purge(
+    State(state): State<Arc<AppState>>,
+    AppJson(body): AppJson<PurgeRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let filter = RequestLogFilter {
+        key_hash: body.api_key.as_deref().map(hash_api_key).or(body.key_hash),
+        user: body.user,
+        since: body.since.as_deref().map(normalize_timestamp).transpose()?,
+        until: body.until.as_deref().map(normalize_timestamp).transpose()?,
+        ..Default::default()
+    };
+    let by_requester = filter.key_hash.is_some() || filter.user.is_some();
+    if !by_requester && filter.since.is_none() && filter.until.is_none() {
+        return Err(AppError::BadRequest(
+            "Purge needs api_key, key_hash, user, since or until".to_string(),
+        ));
+    }
+    let sessions = db::sessions_matching(&state.db, &filter).await?;
+    let mut receipt = erasure::erase(&state, sessions).await?;
+    if by_requester {
+        receipt.anonymized_requests = Some(db::anonymize_request_log(&state.db, &filter).await?);
+    }
+    Ok(Json(json!(receipt)))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    /// Raw API key; hashed before matching.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub key_hash: Option<String>,
+    /// End user, the `user` field of the requests.
+    #[serde(default)]
+    pub user: Option<String>,
+    /// Inclusive lower bound, RFC 3339 or `YYYY-MM-DD HH:MM:SS` (UTC).
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Exclusive upper bound, same formats as `since`.
+    #[serde(default)]
+    pub until: Option<String>,
+    /// `jsonl` (the default) or `zip`.
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+/// GET /admin/export
+///
+/// Everything stored for a key or end user: their sessions, messages and
+/// request log entries, as JSONL or a ZIP archive, see [`crate::export`].
+#[utoipa::path(
+    get, path = "/admin/export", tag = "admin",
+    params(ExportQuery),
+    responses(
+        (status = 200, description = "The export, as JSONL (or `application/zip` with `format=zip`)", content_type = "application/x-ndjson"),
+        (status = 400, description = "Invalid request", body = ErrorResponse)
+    )
+)]
+pub async fn export_data(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<ExportQuery>,
+) -> Result<impl IntoResponse, AppError> {
+    let zip = match q.format.as_deref() {
+        None | Some("jsonl") => false,
+        Some("zip") => true,
+        Some(other) => return Err(AppError::BadRequest(format!("Unknown export format: {other}; use jsonl or zip"))),
+    };
+    let filter = RequestLogFilter {
+        key_hash: q.api_key.as_deref().map(hash_api_key).or(q.key_hash),
+        user: q.user,
+        since: q.since.as_deref().map(normalize_timestamp).transpose()?,
+        until: q.until.as_deref().map(normalize_timestamp).transpose()?,
+        ..Default::default()
+    };
+    if filter.key_hash.is_none() && filter.user.is_none() {
+        return Err(AppError::BadRequest("Export needs api_key, key_hash or user".to_string()));
+    }
+    let export = export::collect(&state, filter).await?;
+    let stamp = chrono::Utc::now().format("%Y%m%dT%H%M%SZ");
+    let (content_type, filename, body) = if zip {
+        let body = tokio::task::spawn_blocking(move || export.to_zip())
+            .await
+            .map_err(|e| AppError::Internal(e.to_string()))??;
+        ("application/zip", format!("export-{stamp}.zip"), body)
+    } else {
+        ("application/x-ndjson", format!("export-{stamp}.jsonl"), export.to_jsonl())
+    };
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (header::CONTENT_DISPOSITION, format!("attachment; filename=\"{filename}\"")),
+        ],
+        body,
+    ))
+}
+
+/// GET /admin/metrics
+///
+/// Aggregated Claude process lifecycle metrics plus live session counts,
+/// for tuning `MAX_CONCURRENT_SESSIONS`, and the database's on-disk size.
+#[utoipa::path(
+    get, path = "/admin/metrics", tag = "admin",
+    responses((status = 200, description = "Process, session and database metrics", body = Object))
+)]
+pub async fn get_metrics(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let mut body = state.metrics.snapshot();
+    body["active_sessions"] = json!(state.claude_manager.active_count().await);
+    body["max_concurrent_sessions"] = json!(state.config().max_concurrent_sessions);
+    body["queued_sessions"] = state.claude_manager.queued();
+    body["rate_limiter"] = json!({
+        "keys": state.rate_limiter.stats(),
+        "users": state.user_rate_limiter.stats(),
+    });
+    body["message_index"] = state.message_index.stats();
+    body["webhooks"] = state.webhooks.stats();
+    body["database"] = match db::db_size(&state.db).await {
+        Ok(size) => json!({
+            "size_bytes": size.size_bytes,
+            "free_bytes": size.free_bytes,
+            "wal_bytes": size.wal_bytes,
+            "last_maintenance": state.metrics.maintenance_snapshot(),
+        }),
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to read database size");
+            Value::Null
+        }
+    };
+    Json(body)
+}
+
+/// GET /admin/budget
+///
+/// Today's and this month's spend against `DAILY_BUDGET_USD` and
+/// `MONTHLY_BUDGET_USD`, and whether new runs are being refused.
+#[utoipa::path(
+    get, path = "/admin/budget", tag = "admin",
+    responses((status = 200, description = "Spend, ceilings and override", body = Object))
+)]
+pub async fn get_budget(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, AppError> {
+    Ok(Json(budget::status(&state).await?))
+}
+
+#[derive(Debug, Default, Deserialize, ToSchema)]
+pub struct BudgetOverrideRequest {
+    /// Unix time to lift the ceilings until; when the reached window
+    /// resets if omitted.
+    #[serde(default)]
+    pub until: Option<i64>,
+}
+
+/// POST /admin/budget/override
+///
+/// Let runs through although a spend ceiling is reached, on this replica.
+#[utoipa::path(
+    post, path = "/admin/budget/override", tag = "admin",
+    request_body(content = Option<BudgetOverrideRequest>),
+    responses((status = 200, description = "Spend, ceilings and override", body = Object))
+)]
+pub async fn lift_budget(
+    State(state): State<Arc<AppState>>,
+    body: Option<Json<BudgetOverrideRequest>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let Json(body) = body.unwrap_or_default();
+    Ok(Json(budget::lift(&state, body.until).await?))
+}
+
+/// DELETE /admin/budget/override
+#[utoipa::path(
+    delete, path = "/admin/budget/override", tag = "admin",
+    responses((status = 200, description = "Spend, ceilings and override", body = Object))
+)]
+pub async fn restore_budget(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, AppError> {
+    Ok(Json(budget::restore(&state).await?))
+}
+
+/// GET /admin/keys
+///
+/// The keys in `API_KEYS`, by hash and last characters, with their tier
+/// and token allowance this month. Keys change with the configuration (and reload);
+/// allowances through `/admin/quotas`.
+#[utoipa::path(
+    get, path = "/admin/keys", tag = "admin",
+    responses((status = 200, description = "Configured keys", body = Object))
+)]
+pub async fn list_keys(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, AppError> {
+    let config = state.config();
+    let own: Vec<String> = db::list_key_quotas(&state.db).await?.into_iter().map(|q| q.key_hash).collect();
+    let mut data = Vec::with_capacity(config.api_keys.len());
+    for key in &config.api_keys {
+        let key_hash = hash_api_key(key);
+        let tail: String = key.chars().rev().take(4).collect::<Vec<_>>().into_iter().rev().collect();
+        data.push(json!({
+            "key_hash": key_hash,
+            "hint": format!("…{tail}"),
+            "own_quota": own.contains(&key_hash),
+            "tier": config.key_tier(Some(key)).map(|t| t.name.as_str()),
+            "quota": quota::key_quota(&state, key).await?.map(quota::KeyQuota::to_json),
+        }));
+    }
+    Ok(Json(json!({
+        "object": "list",
+        "data": data,
+        "require_auth": config.require_auth,
+        "default_monthly_tokens": config.key_monthly_tokens,
+    })))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct PutKeyQuotaRequest {
+    /// Raw API key; hashed before storing. Give this or `key_hash`.
+    #[serde(default)]
+    pub api_key: Option<String>,
+    #[serde(default)]
+    pub key_hash: Option<String>,
+    /// Tokens (prompt and completion) the key may use per UTC month.
+    pub monthly_tokens: i64,
+}
+
+/// GET /admin/quotas
+///
+/// Keys with their own monthly token allowance, and their use this month.
+#[utoipa::path(
+    get, path = "/admin/quotas", tag = "admin",
+    responses((status = 200, description = "Allowances by key hash", body = Object))
+)]
+pub async fn list_key_quotas(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, AppError> {
+    let quotas = db::list_key_quotas(&state.db).await?;
+    Ok(Json(json!({
+        "object": "list",
+        "data": quotas,
+        "default_monthly_tokens": state.config().key_monthly_tokens,
+    })))
+}
+
+/// PUT /admin/quotas
+///
+/// Set a key's monthly token allowance, replacing `KEY_MONTHLY_TOKENS` for
+/// it. Tokens it already used this month count.
+#[utoipa::path(
+    put, path = "/admin/quotas", tag = "admin",
+    request_body = PutKeyQuotaRequest,
+    responses((status = 200, description = "The allowance", body = Object), (status = 400, description = "Invalid request", body = ErrorResponse))
+)]
+pub async fn put_key_quota(
+    State(state): State<Arc<AppState>>,
+    AppJson(body): AppJson<PutKeyQuotaRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let key_hash = match (body.api_key.as_deref(), body.key_hash) {
+        (Some(key), _) => hash_api_key(key),
+        (None, Some(hash)) if !hash.is_empty() => hash,
+        _ => return Err(AppError::BadRequest("Give api_key or key_hash".to_string())),
+    };
+    if body.monthly_tokens < 0 {
+        return Err(AppError::InvalidParam {
+            param: "monthly_tokens".to_string(),
+            message: "must not be negative".to_string(),
+        });
+    }
+    let quota = db::put_key_quota(&state.db, &key_hash, body.monthly_tokens).await?;
+    Ok(Json(serde_json::to_value(quota).unwrap_or(json!({}))))
+}
+
+/// DELETE /admin/quotas/{key_hash}
+///
+/// Remove a key's own allowance; `KEY_MONTHLY_TOKENS` applies again.
+#[utoipa::path(
+    delete, path = "/admin/quotas/{key_hash}", tag = "admin",
+    params(("key_hash" = String, Path, description = "Hash of the API key")),
+    responses((status = 200, description = "Deletion status", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn delete_key_quota(
+    State(state): State<Arc<AppState>>,
+    Path(key_hash): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !db::delete_key_quota(&state.db, &key_hash).await? {
+        return Err(AppError::NotFound(format!("No quota for key {key_hash}")));
+    }
+    Ok(Json(json!({ "key_hash": key_hash, "deleted": true })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RecordingsQuery {
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub offset: Option<i64>,
+}
+
+/// GET /admin/recordings
+///
+/// Completions recorded with `RECORD_REQUESTS`, and replays of them.
+#[utoipa::path(
+    get, path = "/admin/recordings", tag = "admin",
+    params(RecordingsQuery),
+    responses((status = 200, description = "Recordings, newest first", body = Object))
+)]
+pub async fn list_recordings(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<RecordingsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let limit = q.limit.unwrap_or(20).clamp(1, 100);
+    let offset = q.offset.unwrap_or(0).max(0);
+    let rows = db::list_recordings(&state.db, limit, offset).await?;
+    Ok(Json(json!({
+        "data": rows,
+        "pagination": { "count": rows.len(), "limit": limit, "offset": offset },
+    })))
+}
+
+/// GET /admin/recordings/{record_id}
+///
+/// A recording: the request, the CLI invocation it was rendered to and the
+/// response.
+#[utoipa::path(
+    get, path = "/admin/recordings/{record_id}", tag = "admin",
+    params(("record_id" = String, Path, description = "Recording ID, from `X-Recording-ID`")),
+    responses((status = 200, description = "The recording", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn get_recording(
+    State(state): State<Arc<AppState>>,
+    Path(record_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let row = db::get_recording(&state.db, &record_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Recording {record_id} not found")))?;
+    Ok(Json(serde_json::to_value(row)?))
+}
+
+/// POST /admin/replay/{record_id}
+///
+/// Run a recorded request again on the current build and configuration, in
+/// a new session and without streaming. The replay is recorded too, with
+/// `replay_of` pointing at the original; both are returned.
+#[utoipa::path(
+    post, path = "/admin/replay/{record_id}", tag = "admin",
+    params(("record_id" = String, Path, description = "Recording ID, from `X-Recording-ID`")),
+    responses(
+        (status = 200, description = "The original recording and the replay", body = Object),
+        (status = 404, description = "Not found", body = ErrorResponse),
+    )
+)]
+pub async fn replay_recording(
+    State(state): State<Arc<AppState>>,
+    Path(record_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let original = db::get_recording(&state.db, &record_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Recording {record_id} not found")))?;
+    let mut request: ChatCompletionRequest = serde_json::from_value(original.request.0.clone())
+        .map_err(|e| AppError::BadRequest(format!("Recording {record_id} no longer parses as a request: {e}")))?;
+    request.session_id = None;
+    request.stream = Some(false);
+
+    tracing::info!(recording_id = %record_id, "Replaying recorded completion");
+    let response = chat::complete(
+        State(Arc::clone(&state)),
+        None,
+        None,
+        Some(Extension(ReplayOf(record_id.clone()))),
+        HeaderMap::new(),
+        Json(request),
+    )
+    .await
+    .unwrap_or_else(IntoResponse::into_response);
+
+    let status = response.status().as_u16();
+    let replay_id = response
+        .headers()
+        .get(recording::HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let replay = match replay_id {
+        Some(id) => db::get_recording(&state.db, &id).await?.map(serde_json::to_value).transpose()?,
+        None => None,
+    };
+    // A run that failed before reaching the CLI has no recording; its error is the result
+    let replay = match replay {
+        Some(replay) => replay,
+        None => {
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap_or_default();
+            json!({ "status": status, "response": serde_json::from_slice::<Value>(&body).unwrap_or(Value::Null) })
+        }
+    };
+    Ok(Json(json!({
+        "record_id": record_id,
+        "original": original,
+        "replay": replay,
+    })))
+}
+
+/// POST /admin/reload
+///
+/// Re-read `.env` and apply the hot-reloadable settings (API keys, rate
+/// limits, model catalog and pricing, budgets), like `SIGHUP`. Running
+/// sessions are not interrupted.
+#[utoipa::path(
+    post, path = "/admin/reload", tag = "admin",
+    responses((status = 200, description = "The settings that changed", body = Object), (status = 500, description = "The configuration could not be read", body = ErrorResponse))
+)]
+pub async fn reload_config(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, AppError> {
+    let changed = reload::reload(&state).await?;
+    Ok(Json(json!({
+        "status": "reloaded",
+        "changed": changed,
+    })))
+}
+
+/// GET /admin/processes
+///
+/// Running CLI processes: session, model, PID, age and, on Linux, current
+/// RSS and CPU time.
+#[utoipa::path(
+    get, path = "/admin/processes", tag = "admin",
+    responses((status = 200, description = "Running processes, longest-running first", body = Object))
+)]
+pub async fn list_processes(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let processes = state.claude_manager.processes().await;
+    Json(json!({
+        "object": "list",
+        "data": processes,
+        "max_concurrent_sessions": state.config().max_concurrent_sessions,
+    }))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct RecentSessionsQuery {
+    /// Default 20, at most 200.
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+/// GET /admin/sessions
+///
+/// The most recently updated sessions, across keys and projects.
+#[utoipa::path(
+    get, path = "/admin/sessions", tag = "admin",
+    params(RecentSessionsQuery),
+    responses((status = 200, description = "Recent sessions, newest first", body = Object))
+)]
+pub async fn recent_sessions(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<RecentSessionsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let sessions = db::recent_sessions(&state.db, q.limit.unwrap_or(20).clamp(1, 200)).await?;
+    Ok(Json(json!({ "object": "list", "data": sessions })))
+}
+
+/// GET /admin/ui
+///
+/// A single-page dashboard over the admin endpoints. With `ADMIN_API_KEYS`
+/// set, open it as `/admin/ui?api_key=<admin key>`; the page keeps the key
+/// for its own requests and drops it from the address bar.
+pub async fn dashboard() -> impl IntoResponse {
+    ([(header::CACHE_CONTROL, "no-store")], Html(include_str!("dashboard.html")))
+}
+
+/// GET /admin/instances
+///
+/// The replicas sharing the session registry and their running sessions;
+/// only this one without `SESSION_REGISTRY_URL`.
+#[utoipa::path(
+    get, path = "/admin/instances", tag = "admin",
+    responses((status = 200, description = "Live replicas", body = Object))
+)]
+pub async fn list_instances(State(state): State<Arc<AppState>>) -> Result<Json<serde_json::Value>, AppError> {
+    let config = state.config();
+    let instances = match state.registry {
+        Some(ref registry) => registry.instances().await?,
+        None => vec![(config.instance_id.clone(), state.claude_manager.active_count().await as i64)],
+    };
+    let data: Vec<Value> = instances
+        .into_iter()
+        .map(|(id, running)| json!({ "instance_id": id, "running_sessions": running }))
+        .collect();
+    Ok(Json(json!({
+        "object": "list",
+        "data": data,
+        "instance_id": config.instance_id,
+        "global_max_concurrent_sessions": config.global_max_concurrent_sessions,
+    })))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct StopQuery {
+    /// Seconds between `SIGTERM` and `SIGKILL`; 0 kills immediately.
+    #[serde(default = "default_stop_grace")]
+    pub grace_seconds: u64,
+}
+
+fn default_stop_grace() -> u64 {
+    5
+}
+
+/// POST /admin/sessions/stop_all
+///
+/// Kill switch: stop every running CLI process. The server keeps accepting
+/// new requests.
+#[utoipa::path(
+    post, path = "/admin/sessions/stop_all", tag = "admin",
+    params(StopQuery),
+    responses((status = 200, description = "The sessions that were stopped", body = Object))
+)]
+pub async fn stop_all_sessions(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<StopQuery>,
+) -> Json<serde_json::Value> {
+    let stopped = state.claude_manager.cleanup_all(Duration::from_secs(q.grace_seconds)).await;
+    tracing::warn!(count = stopped.len(), sessions = ?stopped, "Stopped all sessions by admin request");
+    Json(json!({
+        "object": "list",
+        "stopped": stopped,
+        "count": stopped.len(),
+    }))
+}
+
+/// POST /admin/sessions/{session_id}/kill
+///
+/// Stop one running session, escalating to `SIGKILL` after the grace period.
+#[utoipa::path(
+    post, path = "/admin/sessions/{session_id}/kill", tag = "admin",
+    params(("session_id" = String, Path, description = "Session ID"), StopQuery),
+    responses((status = 200, description = "The session was stopped", body = Object), (status = 404, description = "No running session with this ID", body = ErrorResponse))
+)]
+pub async fn kill_session(
+    State(state): State<Arc<AppState>>,
+    Path(session_id): Path<String>,
+    Query(q): Query<StopQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let report = state
+        .claude_manager
+        .terminate_session(&session_id, Duration::from_secs(q.grace_seconds))
+        .await
+        .ok_or_else(|| AppError::NotFound(format!("No running session {session_id}")))?;
+    tracing::warn!(session_id = %session_id, exit_code = ?report.exit_code, "Session killed by admin request");
+    Ok(Json(json!({
+        "session_id": session_id,
+        "status": "stopped",
+        "exit_code": report.exit_code,
+        "duration_ms": report.duration_ms,
+    })))
+}
@@ -1,155 +1,412 @@
+use std::borrow::Cow;
 use std::sync::Arc;
 
 use axum::body::Body;
-use axum::extract::{Path, State};
+use axum::extract::{Path, Query, State};
+use axum::http::{HeaderMap, StatusCode};
+use axum::Extension;
 use axum::response::{IntoResponse, Response};
 use axum::Json;
 use futures::StreamExt;
+use serde::Deserialize;
+use utoipa::IntoParams;
 use serde_json::json;
 
+use crate::agents;
+use crate::audit::AuditContext;
+use crate::auth::ApiKey;
+use crate::budget;
+use crate::cache;
+use crate::compaction;
+use crate::claude::auth;
+use crate::claude::env::build_env;
+use crate::claude::inflight::{Inflight, Joined};
 use crate::claude::manager::create_project_directory;
+use crate::claude::process::{ProcessReport, SpawnOptions};
 use crate::claude::parser::{
-    extract_assistant_content, extract_usage, is_assistant_message, is_result_message,
+    auth_error, extract_assistant_content, extract_plan, extract_usage, is_assistant_message, is_refusal,
+    is_result_message, parse_auth_error, parse_upstream_limit, upstream_limit,
 };
+use crate::config::{ClaudeProfile, Config};
 use crate::db;
-use crate::error::AppError;
-use crate::models::claude::validate_claude_model;
+use crate::hooks;
+use crate::jobs;
+use crate::recording::{self, Recording, ReplayOf};
+use crate::routes::{files, projects, prompts, sessions};
+use crate::error::{AppError, ErrorResponse};
+use crate::extract::AppJson;
 use crate::models::openai::{
-    ChatCompletionChoice, ChatCompletionRequest, ChatCompletionResponse, ChatCompletionUsage,
-    ChatMessageResponse,
+    ChatCompletionChoice, ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse,
+    ChatCompletionUsage, ChatMessageResponse, CompareRequest, CompletionMetadata, Plan,
 };
 use crate::state::AppState;
 use crate::streaming;
-use crate::tools::{format_tools_prompt, parse_tool_calls};
+use crate::priority::{self, Priority};
+use crate::tiers;
+use crate::timing;
+use crate::tokens;
+use crate::quota;
+use crate::prompt::{latest_tool_results, system_prompt, tools_prompt};
+use crate::rag;
+use crate::tools::parse_tool_calls;
+use crate::transcript;
+use crate::validation::{check_prompt_size, validate_chat_request};
 
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CompletionQuery {
+    /// Run as a background job and return its ID immediately.
+    #[serde(default, rename = "async")]
+    pub run_async: bool,
+}
+
+/// POST /v1/chat/completions
+///
+/// With `?async=true` the completion runs as a job: the response is `202`
+/// with the job object, polled at `/v1/jobs/{id}`. Streamed events carry
+/// IDs; sending the request again with `Last-Event-ID` resumes the stream.
+#[utoipa::path(
+    post, path = "/v1/chat/completions", tag = "chat",
+    params(
+        CompletionQuery,
+        ("Last-Event-ID" = Option<String>, Header, description = "Resume a recent stream after this event"),
+        ("OpenAI-Project" = Option<String>, Header, description = "Project to run in, unless the body sets `project_id`"),
+        ("OpenAI-Organization" = Option<String>, Header, description = "Selects the project mapped by `ORGANIZATION_PROJECTS`"),
+    ),
+    request_body = ChatCompletionRequest,
+    responses(
+        (status = 200, description = "The completion, or `chat.completion.chunk` SSE events when `stream` is set",
+            content((ChatCompletionResponse = "application/json"), (ChatCompletionChunk = "text/event-stream"))),
+        (status = 202, description = "The queued job (with `?async=true`)", body = Object),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 404, description = "Unknown model with `STRICT_MODEL_VALIDATION`", body = ErrorResponse),
+    )
+)]
 pub async fn create_chat_completion(
     State(state): State<Arc<AppState>>,
-    Json(request): Json<ChatCompletionRequest>,
+    Query(q): Query<CompletionQuery>,
+    audit: Option<Extension<AuditContext>>,
+    api_key: Option<Extension<ApiKey>>,
+    headers: HeaderMap,
+    AppJson(mut request): AppJson<ChatCompletionRequest>,
 ) -> Result<Response, AppError> {
+    let key = api_key.as_ref().map(|Extension(ApiKey(key))| key.as_str());
+    request.project_id = projects::resolve_project(&state.config(), &headers, key, request.project_id.take())?;
+
+    // Attribute the request to the end user under the key, and apply their limits
+    if let Some(ref user) = request.user {
+        if let Some(Extension(ref audit)) = audit {
+            audit.set_user(user);
+        }
+        quota::check_user(&state, key, user).await?;
+    }
+    budget::check(&state).await?;
+    quota::check_key(&state, key).await?;
+    if !q.run_async {
+        return complete(State(state), audit, api_key, None, headers, Json(request)).await;
+    }
+    tiers::check_feature(state.config().key_tier(key), "batch")?;
+    priority::from_headers(&state.config(), key, &headers, Priority::Low)?;
+    sessions::apply_session_defaults(&state, &mut request).await?;
+    validate_chat_request(&request, &state.config())?;
+    prompts::apply_prompt(&state, &mut request).await?;
+    let api_key = api_key.map(|Extension(ApiKey(key))| key);
+    let job = jobs::submit(state, request, api_key, headers).await?;
+    if let Some(Extension(audit)) = audit {
+        audit.set_model(&job.model);
+    }
+    Ok((StatusCode::ACCEPTED, Json(jobs::job_object(&job))).into_response())
+}
+
+/// Most models one comparison may run.
+const MAX_COMPARE_MODELS: usize = 5;
+
+/// POST /v1/chat/completions/compare
+///
+/// Run one request against several models at once and return every
+/// completion with its usage, cost and latency side by side. Each model
+/// runs in a session of its own; nothing is streamed.
+#[utoipa::path(
+    post, path = "/v1/chat/completions/compare", tag = "chat",
+    params(("OpenAI-Project" = Option<String>, Header, description = "Project to run in, unless the body sets `project_id`")),
+    request_body = CompareRequest,
+    responses(
+        (status = 200, description = "A result per model, in the order given", body = Object),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    )
+)]
+pub async fn compare_chat_completions(
+    State(state): State<Arc<AppState>>,
+    audit: Option<Extension<AuditContext>>,
+    api_key: Option<Extension<ApiKey>>,
+    headers: HeaderMap,
+    AppJson(body): AppJson<CompareRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let CompareRequest { models, mut request } = body;
+    if !(2..=MAX_COMPARE_MODELS).contains(&models.len()) {
+        return Err(AppError::InvalidParam {
+            param: "models".to_string(),
+            message: format!("give between 2 and {MAX_COMPARE_MODELS} models"),
+        });
+    }
+    if request.session_id.is_some() {
+        return Err(AppError::InvalidParam {
+            param: "session_id".to_string(),
+            message: "comparisons run without a session".to_string(),
+        });
+    }
+    let key = api_key.as_ref().map(|Extension(ApiKey(key))| key.as_str());
+    request.project_id = projects::resolve_project(&state.config(), &headers, key, request.project_id.take())?;
+    if let Some(ref user) = request.user {
+        if let Some(Extension(ref audit)) = audit {
+            audit.set_user(user);
+        }
+        quota::check_user(&state, key, user).await?;
+    }
+    budget::check(&state).await?;
+    quota::check_key(&state, key).await?;
+    request.stream = Some(false);
+
+    let runs = models.iter().map(|model| {
+        let mut request = request.clone();
+        request.model = model.clone();
+        let (state, audit, api_key, headers) = (Arc::clone(&state), audit.clone(), api_key.clone(), headers.clone());
+        async move {
+            let started = std::time::Instant::now();
+            let response = complete(State(state), audit, api_key, None, headers, Json(request))
+                .await
+                .unwrap_or_else(IntoResponse::into_response);
+            let status = response.status();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap_or_default();
+            let body: serde_json::Value = serde_json::from_slice(&body).unwrap_or_default();
+            (status, started.elapsed().as_millis() as u64, body)
+        }
+    });
+    let outcomes = futures::future::join_all(runs).await;
+
+    let pricing = state.pricing();
+    let results: Vec<_> = models
+        .iter()
+        .zip(outcomes)
+        .map(|(model, (status, latency_ms, body))| {
+            if !status.is_success() {
+                return json!({
+                    "model": model,
+                    "status": status.as_u16(),
+                    "latency_ms": latency_ms,
+                    "error": body["error"],
+                });
+            }
+            let usage = &body["usage"];
+            let tokens = |field: &str| usage[field].as_u64().unwrap_or(0) as u32;
+            let resolved = body["model"].as_str().unwrap_or(model);
+            json!({
+                "model": model,
+                "status": status.as_u16(),
+                "latency_ms": latency_ms,
+                "usage": usage,
+                "cost_usd": pricing.cost(resolved, tokens("prompt_tokens"), tokens("completion_tokens")),
+                "completion": body,
+            })
+        })
+        .collect();
+    if let Some(Extension(audit)) = audit {
+        audit.set_model(&models.join(","));
+    }
+    Ok(Json(json!({
+        "object": "chat.completion.comparison",
+        "created": chrono::Utc::now().timestamp(),
+        "results": results,
+    })))
+}
+
+/// Run a chat completion and respond with it (or stream it).
+pub async fn complete(
+    State(state): State<Arc<AppState>>,
+    audit: Option<Extension<AuditContext>>,
+    api_key: Option<Extension<ApiKey>>,
+    replay: Option<Extension<ReplayOf>>,
+    headers: HeaderMap,
+    Json(mut request): Json<ChatCompletionRequest>,
+) -> Result<Response, AppError> {
+    let config = state.config();
+    let audit = audit.map(|Extension(ctx)| ctx);
+    let started = std::time::Instant::now();
+
+    // A client reconnecting to a stream still buffered gets what it missed
+    let last_event_id = headers.get("last-event-id").and_then(|v| v.to_str().ok());
+    if let (Some(buffers), Some(last_event_id)) = (state.stream_buffers.as_ref(), last_event_id) {
+        let key = api_key.as_ref().map(|Extension(ApiKey(key))| key.as_str());
+        match buffers.resume(last_event_id, key) {
+            Some(events) => {
+                tracing::info!(last_event_id, "Resuming chat completion stream");
+                return Ok(Response::builder()
+                    .status(200)
+                    .header("Content-Type", "text/event-stream")
+                    .header("Cache-Control", "no-cache")
+                    .header("Connection", "keep-alive")
+                    .body(Body::from_stream(events.map(Ok::<_, std::io::Error>)))
+                    .unwrap()
+                    .into_response());
+            }
+            None => tracing::info!(last_event_id, "Stream to resume is gone; running the request again"),
+        }
+    }
+    let mut recording = Recording::start(&state, &request, replay.map(|Extension(ReplayOf(id))| id));
+    sessions::apply_session_defaults(&state, &mut request).await?;
+    validate_chat_request(&request, &config)?;
+    prompts::apply_prompt(&state, &mut request).await?;
+
     // When tools are present, collect full response for tool_call parsing
-    let has_tools = request.tools.as_ref().map_or(false, |t| !t.is_empty());
+    let has_tools = request.tools.as_ref().is_some_and(|t| !t.is_empty());
     let wants_stream = request.stream.unwrap_or(false);
     let do_stream = wants_stream && !has_tools;
 
-    // Validate / resolve model alias
-    let claude_model = validate_claude_model(&request.model);
-
-    // Must have at least one user message
-    if request.messages.is_empty() {
-        return Err(AppError::BadRequest(
-            "At least one message is required".to_string(),
-        ));
+    // Resolve model aliases and route to a CLI profile
+    let (profile, claude_model) = route_model(&config, &request)?;
+    let key = api_key.as_ref().map(|Extension(ApiKey(key))| key.as_str());
+    tiers::enforce(config.key_tier(key), &mut request, &claude_model)?;
+    let priority = priority::from_headers(&config, key, &headers, Priority::Normal)?;
+    let plan_mode = plan_mode(&request, profile)?;
+    if let Some(ref audit) = audit {
+        audit.set_model(&claude_model);
     }
+
     let user_messages: Vec<_> = request
         .messages
         .iter()
         .filter(|m| m.role == "user")
         .collect();
-    if user_messages.is_empty() {
-        return Err(AppError::BadRequest(
-            "At least one user message is required".to_string(),
-        ));
+
+    let project_id = request
+        .project_id
+        .clone()
+        .unwrap_or_else(|| "default".to_string());
+    let api_key = api_key.map(|Extension(ApiKey(key))| key);
+
+    // Pre-flight policy checks, before anything is served or spawned
+    let mut guardrail = None;
+    if let Some(ref guardrails) = state.guardrails {
+        let verdict = guardrails
+            .check(&state, &request.messages, api_key.as_deref(), &project_id)
+            .await;
+        if let Some(ref audit) = audit {
+            audit.set_guardrail(&verdict);
+        }
+        if verdict.rejected() {
+            return Err(AppError::PolicyViolation(verdict.message()));
+        }
+        guardrail = Some(verdict);
     }
 
-    // Build conversation prompt from messages.
-    // Skip the first system message (extracted as system_prompt),
-    // but keep subsequent system messages as [System Event] in history.
-    let conversation_messages: Vec<_> = {
-        let mut first_system_seen = false;
-        request
-            .messages
-            .iter()
-            .filter(|msg| {
-                if msg.role == "system" {
-                    if first_system_seen {
-                        true
-                    } else {
-                        first_system_seen = true;
-                        false
-                    }
-                } else {
-                    true
-                }
-            })
-            .collect()
-    };
+    // Response cache: stateless, non-streaming requests only.
+    // `Cache-Control: no-cache` skips the lookup, `no-store` skips storing.
+    let cache_control = headers
+        .get("cache-control")
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    let cache_key = state
+        .response_cache
+        .as_ref()
+        .filter(|_| !wants_stream && request.session_id.is_none())
+        .map(|_| cache::request_key(&request, &claude_model, &profile.name));
+    if let (Some(cache), Some(key)) = (state.response_cache.as_ref(), cache_key.as_ref()) {
+        if !cache_control.contains("no-cache") {
+            if let Some(mut cached) = cache.get(key).await {
+                cached["id"] = json!(format!(
+                    "chatcmpl-{}",
+                    &uuid::Uuid::new_v4().as_simple().to_string()[..29]
+                ));
+                cached["created"] = json!(chrono::Utc::now().timestamp());
+                tracing::info!(model = %claude_model, "Serving chat completion from cache");
+                return Ok(([("X-Cache", "HIT")], Json(cached)).into_response());
+            }
+        }
+    }
+
+    // Project context, with its subagents and hooks in place
+    let project_path = create_project_directory(&config.project_root, &project_id)?;
+    hooks::materialize(&state, &project_id, &project_path).await?;
+    let delegation =
+        agents::prepare(&state, &project_id, &project_path, request.agent.as_deref(), has_tools).await?;
+
+    // Per-key / per-project Anthropic account
+    let mut profile = profile.clone();
+    if let Some(dir) = config.account_config_dir(api_key.as_deref(), &project_id) {
+        profile.config_dir = Some(dir.to_path_buf());
+    }
+
+    // Fold the oldest turns into a summary once the history outgrows its budget
+    let budget = config.history_token_budget;
+    let mut messages = Cow::Borrowed(request.messages.as_slice());
+    let mut compaction = None;
+    let templates = state.conversation_templates();
+    if budget > 0 && tokens::estimate_tokens(&templates.prompt(&project_id, &messages)) > budget {
+        if let Some((compacted, info)) =
+            compaction::compact_history(&state, &messages, api_key.as_deref(), &project_id).await
+        {
+            messages = Cow::Owned(compacted);
+            compaction = Some(info);
+        }
+    }
 
     let last_user = user_messages.last().unwrap();
-    let user_prompt = if conversation_messages.len() > 1 {
-        let parts: Vec<String> = conversation_messages
-            .iter()
-            .map(|msg| match msg.role.as_str() {
-                "user" => format!("[User]: {}", msg.get_text_content()),
-                "assistant" => {
-                    let mut text = msg.get_text_content();
-                    if let Some(ref tcs) = msg.tool_calls {
-                        for tc in tcs {
-                            text.push_str(&format!(
-                                "\n[Called tool: {}({})]",
-                                tc.function.name, tc.function.arguments
-                            ));
-                        }
-                    }
-                    format!("[Assistant]: {text}")
-                }
-                "system" => format!("[System Event]: {}", msg.get_text_content()),
-                "tool" => {
-                    let name = msg.name.as_deref().unwrap_or("unknown");
-                    format!("[Tool Result ({name})]: {}", msg.get_text_content())
-                }
-                _ => format!("[{}]: {}", msg.role, msg.get_text_content()),
-            })
-            .collect();
+    let mut user_prompt = templates.prompt(&project_id, &messages);
 
-        format!(
-            "Below is the conversation history. Continue naturally from where it left off. \
-             Reply ONLY as the Assistant to the last User message.\n\n{}",
-            parts.join("\n\n")
-        )
-    } else {
-        last_user.get_text_content()
-    };
+    // Ground the prompt in the project's indexed workspace (opt-in per project)
+    let mut citations = None;
+    if let Some(retrieved) = rag::retrieve(&state, &project_id, &last_user.get_text_content()).await {
+        user_prompt = rag::augment_prompt(&retrieved, &user_prompt);
+        citations = Some(retrieved.citations);
+    }
 
-    // Handle vision: extract images and prepend Read instructions
+    // Attachments: save images and documents, and have Claude Read them
     let image_paths = last_user.extract_images();
-    let user_prompt = if !image_paths.is_empty() {
+    let uploads = project_path.join("uploads");
+    let mut file_paths = last_user.extract_files(&uploads);
+    let file_ids = last_user.file_ids();
+    file_paths.extend(files::materialize_files(&state, api_key.as_deref(), &file_ids, &uploads).await?);
+    let user_prompt = if image_paths.is_empty() && file_paths.is_empty() {
+        user_prompt
+    } else {
         let refs: Vec<String> = image_paths
             .iter()
             .enumerate()
             .map(|(i, p)| format!("- Image {}: {p}", i + 1))
+            .chain(
+                file_paths
+                    .iter()
+                    .enumerate()
+                    .map(|(i, p)| format!("- Document {}: {p}", i + 1)),
+            )
             .collect();
         format!(
-            "Read the following image file(s) using the Read tool, \
+            "Read the following file(s) using the Read tool, \
              then answer the question below.\n\n\
-             Image files:\n{}\n\n\
+             Files:\n{}\n\n\
              Question: {user_prompt}",
             refs.join("\n")
         )
-    } else {
-        user_prompt
     };
 
-    // Extract system prompt (first system message)
-    let system_prompt = request
-        .messages
-        .iter()
-        .find(|m| m.role == "system")
-        .map(|m| m.get_text_content())
-        .or_else(|| request.system_prompt.clone());
+    let system_prompt = system_prompt(&request);
+    let append_system_prompt = tools_prompt(&request).or(delegation);
 
-    // Build tool prompt appendix
-    let append_system_prompt = if has_tools {
-        Some(format_tools_prompt(request.tools.as_deref().unwrap_or(&[])))
-    } else {
-        None
-    };
+    // Everything sent to the CLI, for estimating usage it doesn't report
+    let prompt_text = [system_prompt.as_deref(), append_system_prompt.as_deref(), Some(&user_prompt)]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join("\n");
 
-    // Project context
-    let project_id = request
-        .project_id
-        .clone()
-        .unwrap_or_else(|| "default".to_string());
-    let _project_path = create_project_directory(&state.config.project_root, &project_id);
+    // Reject prompts that can't fit before spending a CLI run on them
+    check_prompt_size(&prompt_text, &config)?;
+    check_context_window(
+        config.model_catalog.context_window(&claude_model),
+        &prompt_text,
+        request.max_tokens,
+    )?;
 
     // Session management
     let session_id = request
@@ -159,6 +416,7 @@ pub async fn create_chat_completion(
 
     tracing::info!(
         model = %claude_model,
+        profile = %profile.name,
         prompt_size = user_prompt.len(),
         stream = do_stream,
         has_tools,
@@ -166,47 +424,153 @@ pub async fn create_chat_completion(
         "Chat completion request"
     );
 
+    // Identical concurrent stateless requests share one run
+    let mut reservation = None;
+    let mut followed = None;
+    let mut shared = None;
+    if let (Some(inflight), None) = (state.inflight.as_ref(), request.session_id.as_ref()) {
+        let scope = format!(
+            "{}|{}|{}",
+            profile.name,
+            project_id,
+            profile.config_dir.as_deref().unwrap_or(std::path::Path::new("")).display()
+        );
+        let key = cache::request_key(&request, &claude_model, &scope);
+        match inflight.join_or_reserve(key.clone()).await {
+            Joined::Follower(stream, sid) => followed = Some((stream, sid)),
+            Joined::Leader(r) => {
+                reservation = Some(r);
+                shared = Some((Arc::clone(inflight), key));
+            }
+        }
+    }
+    // Followers leave persistence, reaping and usage accounting to the leader
+    let is_follower = followed.is_some();
+
+    // The ID is known before the run starts, so the run can be cancelled by it
+    let completion_id = format!(
+        "chatcmpl-{}",
+        &uuid::Uuid::new_v4().as_simple().to_string()[..29]
+    );
+
     // Spawn Claude process
-    let (claude_stream, claude_session_id) = state
-        .claude_manager
-        .create_session(
-            &session_id,
-            &user_prompt,
-            &claude_model,
-            system_prompt.as_deref(),
-            append_system_prompt.as_deref(),
-            has_tools,
-        )
-        .await
-        .map_err(|e| {
-            tracing::error!(error = %e, "Failed to create Claude session");
-            AppError::ServiceUnavailable(format!("Failed to start Claude Code: {e}"))
-        })?;
+    let (claude_stream, claude_session_id) = match followed {
+        Some(followed) => followed,
+        None => {
+            let opts = SpawnOptions {
+                prompt: &user_prompt,
+                model: &claude_model,
+                system_prompt: system_prompt.as_deref(),
+                append_system_prompt: append_system_prompt.as_deref(),
+                disable_builtin_tools: has_tools,
+                env: build_env(&config, &profile, Some(&project_id)),
+                project_dir: &project_path,
+                sandbox: state.sandbox.as_ref(),
+                limits: state.limits.as_ref(),
+                resume: None,
+                plan: plan_mode,
+                priority,
+            };
+            if let Some(ref mut recording) = recording {
+                recording.set_invocation(&state, &profile, &opts);
+            }
+            let (stream, sid) = state
+                .claude_manager
+                .create_session(&session_id, &profile, opts)
+                .await
+                .map_err(|e| {
+                    tracing::error!(error = %e, "Failed to create Claude session");
+                    AppError::ServiceUnavailable(format!("Failed to start Claude Code: {e}"))
+                })?;
+            match reservation {
+                Some(reservation) => (reservation.publish(stream), sid),
+                None => (stream, sid),
+            }
+        }
+    };
+    let mut stopwatch = timing::Stopwatch::new(started);
+    stopwatch.spawned();
 
     let effective_session_id = claude_session_id
         .clone()
         .unwrap_or_else(|| session_id.clone());
+    if let Some(ref audit) = audit {
+        audit.set_session(&effective_session_id);
+    }
+    // Cancelling a shared run is left to its leader
+    if !is_follower {
+        state.claude_manager.track_completion(&effective_session_id, &completion_id).await;
+    }
+    let claude_stream = if config.store_transcripts && !is_follower {
+        transcript::record(state.db.clone(), effective_session_id.clone(), claude_stream)
+    } else {
+        claude_stream
+    };
 
-    // Save user message to DB (fire-and-forget)
-    let db = state.db.clone();
-    let sid = effective_session_id.clone();
-    let prompt_clone = user_prompt.clone();
-    tokio::spawn(async move {
-        let _ = db::add_message(&db, &sid, "user", &prompt_clone, 0, 0, 0.0).await;
-    });
+    // Save the tool results and user message to DB (fire-and-forget)
+    if !is_follower {
+        let tool_results: Vec<(String, serde_json::Value)> = latest_tool_results(&request.messages)
+            .into_iter()
+            .map(|(msg, name)| {
+                let metadata = json!({
+                    "tool_call_id": msg.tool_call_id,
+                    "name": name,
+                    "claude_session_id": claude_session_id,
+                });
+                (msg.get_text_content(), metadata)
+            })
+            .collect();
+        let db = state.db.clone();
+        let sid = effective_session_id.clone();
+        let prompt_clone = user_prompt.clone();
+        let project = project_id.clone();
+        let model = claude_model.to_string();
+        let webhooks = state.webhooks.clone();
+        let metadata = json!({
+            "model": claude_model,
+            "profile": profile.name,
+            "stream": wants_stream,
+            "images": image_paths,
+            "files": file_paths,
+            "file_ids": file_ids,
+            "compaction": compaction,
+            "guardrail": guardrail,
+            "rag": citations,
+            "claude_session_id": claude_session_id,
+        });
+        tokio::spawn(async move {
+            if let Ok(true) = db::ensure_session(&db, &sid, &project, &model).await {
+                webhooks.emit(
+                    "session.created",
+                    json!({"session_id": sid, "project_id": project, "model": model, "source": "chat"}),
+                );
+            }
+            for (content, metadata) in tool_results {
+                let _ = db::add_message(&db, &sid, "tool", &content, 0, 0, 0.0, &metadata).await;
+            }
+            let _ = db::add_message(&db, &sid, "user", &prompt_clone, 0, 0, 0.0, &metadata).await;
+        });
+    }
 
     // ── Streaming path ──
     if do_stream {
-        let completion_id = format!(
-            "chatcmpl-{}",
-            &uuid::Uuid::new_v4().as_simple().to_string()[..29]
-        );
         let created = chrono::Utc::now().timestamp();
         let model = claude_model.to_string();
         let state_clone = Arc::clone(&state);
+        let quota_key = api_key.clone();
         let sid = effective_session_id.clone();
+        let stream_id = completion_id.clone();
+        let mut progress = request
+            .stream_progress
+            .unwrap_or(config.stream_progress)
+            .then(streaming::ToolProgress::default);
 
+        let profile_name = profile.name.clone();
+        let mut chunker = streaming::Chunker::new(streaming::ChunkPolicy::from_config(&config));
         let (tx, rx) = tokio::sync::mpsc::channel::<String>(64);
+        let recording_id = recording.as_ref().map(|r| r.id.clone());
+        // Only the gateway's share is known before the body starts
+        let gateway_timing = format!("gateway;dur={}", stopwatch.finish().gateway_ms);
 
         tokio::spawn(async move {
             let _ = tx
@@ -218,117 +582,352 @@ pub async fn create_chat_completion(
                 .await;
 
             let mut claude_stream = claude_stream;
-            while let Some(msg) = claude_stream.next().await {
-                if is_assistant_message(&msg) {
+            let redactor = state_clone.redactor();
+            let mut streamed = String::new();
+            let mut reported = None;
+            let mut refused = false;
+            let mut limited = None;
+            let mut auth_failed = None;
+            let mut plan = None;
+            let mut finished = false;
+            let delta = |text: &str| streaming::sse_event(&streaming::content_chunk(&completion_id, &model, created, text));
+            loop {
+                // Content held back by the chunk policy goes out when due
+                let next = match chunker.due_in() {
+                    Some(wait) => tokio::select! {
+                        msg = claude_stream.next() => msg,
+                        _ = tokio::time::sleep(wait) => {
+                            for text in chunker.tick() {
+                                let _ = tx.send(delta(&text)).await;
+                            }
+                            continue;
+                        }
+                    },
+                    None => claude_stream.next().await,
+                };
+                let Some(msg) = next else { break };
+                stopwatch.event();
+                refused |= is_refusal(&msg);
+                // The plan is a tool call's input; it goes out as content
+                if let Some(text) = extract_plan(&msg) {
+                    stopwatch.token();
+                    let text = match redactor.as_ref() {
+                        Some(redactor) => redactor.scrub(&text, &sid).0,
+                        None => text,
+                    };
+                    for text in chunker.push(&text) {
+                        let _ = tx.send(delta(&text)).await;
+                    }
+                    streamed.push_str(&text);
+                    plan = Some(Plan::parse(&text));
+                }
+                // The CLI's error text goes out as an error event instead
+                let limit = upstream_limit(&msg);
+                let auth = auth_error(&msg);
+                let is_error_text = (limit.is_some() || auth.is_some()) && is_assistant_message(&msg);
+                limited = limited.or(limit);
+                auth_failed = auth_failed.or(auth);
+                if is_assistant_message(&msg) && !is_error_text {
                     if let Some(content) = extract_assistant_content(&msg) {
-                        let _ = tx
-                            .send(streaming::sse_event(&streaming::content_chunk(
-                                &completion_id,
-                                &model,
-                                created,
-                                &content,
-                            )))
-                            .await;
+                        stopwatch.token();
+                        let content = match redactor.as_ref() {
+                            Some(redactor) => redactor.scrub(&content, &sid).0,
+                            None => content,
+                        };
+                        for text in chunker.push(&content) {
+                            let _ = tx.send(delta(&text)).await;
+                        }
+                        streamed.push_str(&content);
+                    }
+                }
+                if let Some(ref mut progress) = progress {
+                    let events = progress.events(&completion_id, &msg);
+                    if !events.is_empty() {
+                        for text in chunker.flush() {
+                            let _ = tx.send(delta(&text)).await;
+                        }
+                    }
+                    for event in events {
+                        // Tool inputs (commands, paths) may carry secrets too
+                        let event = match redactor.as_ref() {
+                            Some(redactor) => redactor.scrub(&event, &sid).0,
+                            None => event,
+                        };
+                        let _ = tx.send(event).await;
                     }
                 }
                 if is_result_message(&msg) {
-                    if let Some(usage) = extract_usage(&msg) {
-                        let _ = db::update_session_metrics(
-                            &state_clone.db,
-                            &sid,
-                            (usage.input_tokens + usage.output_tokens) as i64,
-                            usage.cost_usd,
-                        )
-                        .await;
+                    reported = extract_usage(&msg);
+                    // A refusal without text still tells the client why
+                    let explanation = msg.get("result").and_then(|r| r.as_str()).unwrap_or_default();
+                    if refused && streamed.is_empty() && !explanation.is_empty() {
+                        let _ = tx.send(delta(explanation)).await;
                     }
+                    finished = true;
                     break;
                 }
             }
+            for text in chunker.flush() {
+                let _ = tx.send(delta(&text)).await;
+            }
 
-            let _ = tx
-                .send(streaming::sse_event(&streaming::final_chunk(
-                    &completion_id,
-                    &model,
-                    created,
-                    "stop",
-                )))
+            // Without a result the process died: reap it now to say how
+            let mut report = None;
+            if !finished && !is_follower {
+                report = state_clone.claude_manager.session_finished(&sid).await;
+                if let (Some(audit), Some(report)) = (audit.as_ref(), report.as_ref()) {
+                    audit.set_process(report);
+                }
+                let stderr = report.as_ref().filter(|_| streamed.is_empty()).map(|r| r.stderr.as_str());
+                auth_failed = auth_failed.or_else(|| parse_auth_error(stderr?));
+                limited = limited.or_else(|| parse_upstream_limit(stderr?));
+            }
+
+            let mut accounted = None;
+            if !is_follower {
+                let (mut usage, estimated) = tokens::fill_usage(reported, &prompt_text, &streamed);
+                if estimated {
+                    tracing::debug!(session_id = %sid, "CLI reported no usage; estimated locally");
+                }
+                let cost_estimated = state_clone.pricing().fill_cost(&model, &mut usage);
+                budget::record(&state_clone, usage.cost_usd).await;
+                quota::record_tokens(&state_clone, quota_key.as_deref(), usage.input_tokens + usage.output_tokens).await;
+                if let Some(ref audit) = audit {
+                    audit.add_usage(usage.input_tokens, usage.output_tokens, usage.cost_usd);
+                }
+                let _ = db::update_session_metrics(
+                    &state_clone.db,
+                    &sid,
+                    (usage.input_tokens + usage.output_tokens) as i64,
+                    usage.cost_usd,
+                )
                 .await;
+                accounted = Some((usage, estimated, cost_estimated));
+            }
+
+            let plan = plan.map(|plan| json!(plan));
+            let (last_event, finish_reason) = match (auth_failed, limited) {
+                (Some(message), _) => {
+                    auth::failed(&state_clone, &profile_name, &message);
+                    (json!(AppError::ClaudeAuth(message).parts().1), None)
+                }
+                (None, Some(limit)) => {
+                    tracing::warn!(session_id = %sid, kind = limit.kind.as_str(), resets_at = limit.resets_at, "Anthropic limit reached");
+                    if !is_follower {
+                        state_clone.metrics.record_upstream_limit(limit.kind);
+                    }
+                    (json!(AppError::UpstreamLimited(limit).parts().1), None)
+                }
+                (None, None) if !finished => {
+                    let error = json!(exited_early(report.as_ref()).parts().1);
+                    tracing::warn!(session_id = %sid, error = %error["error"]["message"], "Stream ended without a result");
+                    let mut chunk = streaming::final_chunk(&completion_id, &model, created, "error");
+                    chunk["metadata"] = json!(CompletionMetadata { timing: stopwatch.finish() });
+                    let _ = tx.send(streaming::sse_event(&chunk)).await;
+                    (error, (!streamed.is_empty()).then_some("error"))
+                }
+                (None, None) => {
+                    if !streamed.is_empty() {
+                        auth::succeeded(&state_clone, &profile_name);
+                    }
+                    let finish_reason = if refused { "content_filter" } else { "stop" };
+                    let mut chunk = streaming::final_chunk(&completion_id, &model, created, finish_reason);
+                    if let Some(ref plan) = plan {
+                        chunk["plan"] = plan.clone();
+                    }
+                    chunk["metadata"] = json!(CompletionMetadata { timing: stopwatch.finish() });
+                    (chunk, Some(finish_reason))
+                }
+            };
+            let _ = tx.send(streaming::sse_event(&last_event)).await;
             let _ = tx.send(streaming::sse_done()).await;
 
-            state_clone.claude_manager.session_finished(&sid).await;
+            // Save the reply like the non-streaming path does; a partial one
+            // if the run died, noting whether the client was still there
+            if let (Some((usage, usage_estimated, cost_estimated)), Some(finish_reason)) = (accounted, finish_reason) {
+                let timing = stopwatch.finish();
+                let metadata = json!({
+                    "model": model,
+                    "profile": profile_name,
+                    "stream": true,
+                    "finish_reason": finish_reason,
+                    "client_disconnected": tx.is_closed(),
+                    "latency_ms": timing.total_ms,
+                    "ttft_ms": timing.ttft_ms,
+                    "usage_estimated": usage_estimated,
+                    "cost_estimated": cost_estimated,
+                    "claude_session_id": claude_session_id,
+                    "completion_id": completion_id,
+                    "plan": plan,
+                });
+                let saved = db::add_message(
+                    &state_clone.db,
+                    &sid,
+                    "assistant",
+                    &streamed,
+                    usage.input_tokens as i64,
+                    usage.output_tokens as i64,
+                    usage.cost_usd,
+                    &metadata,
+                )
+                .await;
+                if let Err(e) = saved {
+                    tracing::warn!(session_id = %sid, error = %e, "Failed to store streamed reply");
+                }
+            }
+
+            if let Some(recording) = recording {
+                let response = json!({ "content": streamed, "last_event": last_event });
+                recording.save(&state_clone, Some(&sid), &model, &response).await;
+            }
+            if !is_follower {
+                let report = state_clone.claude_manager.session_finished(&sid).await;
+                if let (Some(audit), Some(report)) = (audit.as_ref(), report) {
+                    audit.set_process(&report);
+                }
+            }
         });
 
-        let body_stream =
-            tokio_stream::wrappers::ReceiverStream::new(rx).map(Ok::<_, std::io::Error>);
-        let body = Body::from_stream(body_stream);
+        let events = tokio_stream::wrappers::ReceiverStream::new(rx);
+        let events = match state.stream_buffers {
+            Some(ref buffers) => buffers.record(&stream_id, api_key.as_deref(), events),
+            None => Box::pin(events),
+        };
+        let body = Body::from_stream(events.map(Ok::<_, std::io::Error>));
 
-        return Ok(Response::builder()
+        let response = Response::builder()
             .status(200)
             .header("Content-Type", "text/event-stream")
             .header("Cache-Control", "no-cache")
             .header("Connection", "keep-alive")
             .header("X-Session-ID", &effective_session_id)
             .header("X-Project-ID", &project_id)
+            .header("Server-Timing", gateway_timing)
             .body(body)
             .unwrap()
-            .into_response());
+            .into_response();
+        return Ok(recording::tag(response, recording_id));
     }
 
     // ── Non-streaming path ──
     {
         let mut claude_stream = claude_stream;
         let mut content_parts = Vec::new();
-        let mut usage_input: u32 = 0;
-        let mut usage_output: u32 = 0;
-        let mut cost: f64 = 0.0;
+        let mut reported = None;
+        let mut refused = false;
+        let mut result_text = None;
+        let mut limited = None;
+        let mut auth_failed = None;
+        let mut proposed_plan = None;
 
+        let guard = (!is_follower).then(|| AbortOnDisconnect {
+            state: Arc::clone(&state),
+            session_id: Some(effective_session_id.clone()),
+            shared,
+        });
         while let Some(msg) = claude_stream.next().await {
+            stopwatch.event();
+            refused |= is_refusal(&msg);
+            limited = limited.or_else(|| upstream_limit(&msg));
+            auth_failed = auth_failed.or_else(|| auth_error(&msg));
+            proposed_plan = proposed_plan.or_else(|| extract_plan(&msg));
             if is_assistant_message(&msg) {
                 if let Some(text) = extract_assistant_content(&msg) {
+                    stopwatch.token();
                     content_parts.push(text);
                 }
             }
             if is_result_message(&msg) {
-                if let Some(u) = extract_usage(&msg) {
-                    usage_input = u.input_tokens;
-                    usage_output = u.output_tokens;
-                    cost = u.cost_usd;
-                }
+                reported = extract_usage(&msg);
+                result_text = msg.get("result").and_then(|r| r.as_str()).map(str::to_string);
                 break;
             }
         }
+        if let Some(guard) = guard {
+            guard.disarm();
+        }
 
-        state
-            .claude_manager
-            .session_finished(&effective_session_id)
-            .await;
+        let (mut usage, usage_estimated) =
+            tokens::fill_usage(reported, &prompt_text, &content_parts.join("\n"));
+        if usage_estimated {
+            tracing::debug!(session_id = %effective_session_id, "CLI reported no usage; estimated locally");
+        }
+        let cost_estimated = state.pricing().fill_cost(&claude_model, &mut usage);
+        let (usage_input, usage_output, cost) = (usage.input_tokens, usage.output_tokens, usage.cost_usd);
 
-        let complete_content = if content_parts.is_empty() {
+        // The leader reaps the shared process and accounts for its usage
+        let report = if is_follower {
+            None
+        } else {
+            budget::record(&state, cost).await;
+            quota::record_tokens(&state, api_key.as_deref(), usage_input + usage_output).await;
+            state.claude_manager.session_finished(&effective_session_id).await
+        };
+        if let (Some(audit), false) = (audit.as_ref(), is_follower) {
+            audit.add_usage(usage_input, usage_output, cost);
+            if let Some(ref report) = report {
+                audit.set_process(report);
+            }
+        }
+
+        // A run that produced nothing may have said why on stderr only
+        let stderr = report.as_ref().filter(|_| content_parts.is_empty()).map(|r| r.stderr.as_str());
+        let auth_failed = auth_failed.or_else(|| parse_auth_error(stderr?));
+        if let Some(message) = auth_failed {
+            auth::failed(&state, &profile.name, &message);
+            return Err(AppError::ClaudeAuth(message));
+        }
+        let limited = limited.or_else(|| parse_upstream_limit(stderr?));
+        if let Some(limit) = limited {
+            tracing::warn!(session_id = %effective_session_id, kind = limit.kind.as_str(), resets_at = limit.resets_at, "Anthropic limit reached");
+            if !is_follower {
+                state.metrics.record_upstream_limit(limit.kind);
+            }
+            return Err(AppError::UpstreamLimited(limit));
+        }
+        if !content_parts.is_empty() {
+            auth::succeeded(&state, &profile.name);
+        }
+
+        let complete_content = if let Some(plan) = proposed_plan.clone() {
+            // Plan mode: the proposal is the reply, not the narration around it
+            plan
+        } else if content_parts.is_empty() && refused {
+            // The CLI's explanation, if it gave one
+            result_text.unwrap_or_default()
+        } else if content_parts.is_empty() {
             "Hello! I'm Claude, ready to help.".to_string()
         } else {
             content_parts.join("\n")
         };
+        // Scrub secrets before the content is parsed, returned, cached or stored
+        let (complete_content, redactions) = match state.redactor().as_ref() {
+            Some(redactor) => redactor.scrub(&complete_content, &effective_session_id),
+            None => (complete_content, Default::default()),
+        };
+        let plan = proposed_plan.map(|_| Plan::parse(&complete_content));
 
         // Parse tool calls from response text
-        let (tool_calls, cleaned_text) = if has_tools {
+        let (tool_calls, cleaned_text) = if has_tools && !refused {
             parse_tool_calls(&complete_content)
         } else {
             (None, complete_content.clone())
         };
+        // Calls are stored in the metadata, not as markup in the text
+        let stored_content = if tool_calls.is_some() { cleaned_text.clone() } else { complete_content.clone() };
 
-        let (response_content, response_tool_calls, finish_reason) = if tool_calls.is_some() {
+        let (response_content, response_tool_calls, finish_reason) = if refused {
+            (Some(cleaned_text), None, "content_filter".to_string())
+        } else if tool_calls.is_some() {
             // Drop text content when tool_calls are present to avoid duplicate messages
             (None, tool_calls, "tool_calls".to_string())
         } else {
             (Some(cleaned_text), None, "stop".to_string())
         };
 
-        let completion_id = format!(
-            "chatcmpl-{}",
-            &uuid::Uuid::new_v4().as_simple().to_string()[..29]
-        );
         let created = chrono::Utc::now().timestamp();
 
+        let timing = stopwatch.finish();
         let response = ChatCompletionResponse {
             id: completion_id,
             object: "chat.completion".to_string(),
@@ -350,35 +949,65 @@ pub async fn create_chat_completion(
             },
             session_id: Some(effective_session_id.clone()),
             project_id: Some(project_id.clone()),
+            plan,
+            metadata: Some(CompletionMetadata { timing: timing.clone() }),
         };
 
         // Save assistant message to DB
-        let _ = db::add_message(
-            &state.db,
-            &effective_session_id,
-            "assistant",
-            &complete_content,
-            usage_input as i64,
-            usage_output as i64,
-            cost,
-        )
-        .await;
-        let _ = db::update_session_metrics(
-            &state.db,
-            &effective_session_id,
-            (usage_input + usage_output) as i64,
-            cost,
-        )
-        .await;
+        if !is_follower {
+            let choice = &response.choices[0];
+            let metadata = json!({
+                "model": claude_model,
+                "profile": profile.name,
+                "finish_reason": choice.finish_reason,
+                "tool_calls": choice.message.tool_calls,
+                "latency_ms": started.elapsed().as_millis() as u64,
+                "ttft_ms": report.as_ref().and_then(|r| r.ttft_ms),
+                "usage_estimated": usage_estimated,
+                "cost_estimated": cost_estimated,
+                "redactions": redactions,
+                "claude_session_id": claude_session_id,
+                "completion_id": response.id,
+                "plan": response.plan,
+            });
+            let _ = db::add_message(
+                &state.db,
+                &effective_session_id,
+                "assistant",
+                &stored_content,
+                usage_input as i64,
+                usage_output as i64,
+                cost,
+                &metadata,
+            )
+            .await;
+            let _ = db::update_session_metrics(
+                &state.db,
+                &effective_session_id,
+                (usage_input + usage_output) as i64,
+                cost,
+            )
+            .await;
+        }
+
+        let recording_id = match recording {
+            Some(recording) => {
+                let id = recording.id.clone();
+                let value = serde_json::to_value(&response)?;
+                recording.save(&state, Some(&effective_session_id), &claude_model, &value).await;
+                Some(id)
+            }
+            None => None,
+        };
 
         // If the client originally requested streaming, wrap as SSE
-        if wants_stream {
+        let response = if wants_stream {
             let response_value = serde_json::to_value(&response)?;
             let events = streaming::wrap_response_as_sse(&response_value);
             let all_events = events.join("");
 
             let body = Body::from(all_events);
-            return Ok(Response::builder()
+            Response::builder()
                 .status(200)
                 .header("Content-Type", "text/event-stream")
                 .header("Cache-Control", "no-cache")
@@ -386,15 +1015,191 @@ pub async fn create_chat_completion(
                 .header("X-Session-ID", &effective_session_id)
                 .body(body)
                 .unwrap()
-                .into_response());
-        }
+                .into_response()
+        } else if let (Some(cache), Some(key)) = (state.response_cache.as_ref(), cache_key.as_ref()) {
+            if !content_parts.is_empty() && !refused && !cache_control.contains("no-store") {
+                // Timings are this run's, not a cache hit's
+                let mut cached = serde_json::to_value(&response)?;
+                if let Some(fields) = cached.as_object_mut() {
+                    fields.remove("metadata");
+                }
+                cache.put(key, &cached).await;
+            }
+            ([("X-Cache", "MISS")], Json(response)).into_response()
+        } else {
+            Json(response).into_response()
+        };
+        Ok(recording::tag(timing::tag(response, &timing), recording_id))
+    }
+}
+
+/// Stops the CLI run of a non-streaming request whose client hung up: axum
+/// drops the handler future when the connection closes, and this with it.
+/// A run that identical requests are still reading is left alone.
+struct AbortOnDisconnect {
+    state: Arc<AppState>,
+    /// `None` once the run completed.
+    session_id: Option<String>,
+    shared: Option<(Arc<Inflight>, String)>,
+}
 
-        Ok(Json(response).into_response())
+impl AbortOnDisconnect {
+    fn disarm(mut self) {
+        self.session_id = None;
     }
 }
 
+impl Drop for AbortOnDisconnect {
+    fn drop(&mut self) {
+        let Some(session_id) = self.session_id.take() else { return };
+        let state = Arc::clone(&self.state);
+        let shared = self.shared.take();
+        tokio::spawn(async move {
+            if shared.is_some_and(|(inflight, key)| inflight.has_listeners(&key)) {
+                return;
+            }
+            tracing::info!(session_id = %session_id, "Client disconnected; stopping its Claude session");
+            state.claude_manager.stop_session(&session_id).await;
+        });
+    }
+}
+
+/// The profile that serves the request (by explicit name or model prefix)
+/// and the model to run it with. Catalog aliases only apply on Claude
+/// profiles; other backends get the model as requested. With
+/// `STRICT_MODEL_VALIDATION`, unknown models fail with `model_not_found`
+/// instead of falling back to the default model.
+fn route_model<'a>(
+    config: &'a Config,
+    request: &ChatCompletionRequest,
+) -> Result<(&'a ClaudeProfile, String), AppError> {
+    let known = config.model_catalog.lookup(&request.model);
+    let claude_model = known.clone().unwrap_or_else(|| config.model_catalog.resolve(&request.model));
+    let profile = config
+        .select_profile(request.profile.as_deref(), &[&request.model, &claude_model])
+        .ok_or_else(|| {
+            AppError::BadRequest(format!(
+                "Unknown profile: {}",
+                request.profile.as_deref().unwrap_or_default()
+            ))
+        })?;
+    if profile.backend != "claude" {
+        return Ok((profile, request.model.clone()));
+    }
+    if known.is_none() && config.strict_model_validation {
+        return Err(AppError::ModelNotFound(request.model.clone()));
+    }
+    Ok((profile, claude_model))
+}
+
+/// The error for a run whose process ended before its result, with what
+/// its report says about why.
+pub(crate) fn exited_early(report: Option<&ProcessReport>) -> AppError {
+    let Some(report) = report else {
+        return AppError::ProcessExited("the process was stopped".to_string());
+    };
+    let mut detail = match report.exit_code {
+        Some(code) if code < 0 => format!("killed by signal {}", -code),
+        Some(code) => format!("exit code {code}"),
+        None => "exit status unknown".to_string(),
+    };
+    if let Some(limit) = report.limit_hit {
+        detail.push_str(&format!(", {} limit reached", limit.as_str()));
+    }
+    if let Some(line) = report.stderr.lines().rev().map(str::trim).find(|l| !l.is_empty()) {
+        detail.push_str(&format!(": {line}"));
+    }
+    AppError::ProcessExited(detail)
+}
+
+/// Whether the request asks for plan mode, which only the Claude CLI has.
+fn plan_mode(request: &ChatCompletionRequest, profile: &ClaudeProfile) -> Result<bool, AppError> {
+    let plan = request.mode.as_deref() == Some("plan");
+    if plan && profile.backend != "claude" {
+        return Err(AppError::InvalidParam {
+            param: "mode".to_string(),
+            message: format!("plan mode needs the Claude CLI; profile '{}' runs {}", profile.name, profile.backend),
+        });
+    }
+    Ok(plan)
+}
+
+/// Fail with `context_length_exceeded` when the prompt plus the requested
+/// completion budget exceeds the model's context window, if known.
+fn check_context_window(window: Option<u32>, prompt: &str, max_tokens: Option<u32>) -> Result<(), AppError> {
+    let Some(window) = window else {
+        return Ok(());
+    };
+    let prompt_tokens = tokens::estimate_tokens(prompt);
+    let requested = prompt_tokens.saturating_add(max_tokens.unwrap_or(0));
+    if requested <= window {
+        return Ok(());
+    }
+    let detail = match max_tokens {
+        Some(n) => format!("{requested} tokens ({prompt_tokens} in the messages, {n} in the completion)"),
+        None => format!("{prompt_tokens} tokens"),
+    };
+    Err(AppError::ContextLengthExceeded(format!(
+        "This model's maximum context length is {window} tokens. However, you requested \
+         {detail}. Please reduce the length of the messages or completion."
+    )))
+}
+
+/// POST /v1/estimate
+///
+/// Projected token usage and cost of a chat completion request without
+/// running it. The completion side is bounded by `max_tokens`; without it
+/// only the prompt is costed.
+#[utoipa::path(
+    post, path = "/v1/estimate", tag = "chat",
+    request_body = ChatCompletionRequest,
+    responses((status = 200, description = "Projected usage and cost", body = Object), (status = 400, description = "Invalid request", body = ErrorResponse))
+)]
+pub async fn estimate_chat_completion(
+    State(state): State<Arc<AppState>>,
+    AppJson(mut request): AppJson<ChatCompletionRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let config = state.config();
+    sessions::apply_session_defaults(&state, &mut request).await?;
+    validate_chat_request(&request, &config)?;
+    prompts::apply_prompt(&state, &mut request).await?;
+    let (_, model) = route_model(&config, &request)?;
+    let price = state
+        .pricing()
+        .price(&model)
+        .ok_or_else(|| AppError::BadRequest(format!("No pricing configured for model {model}")))?;
+
+    let prompt_text = [system_prompt(&request), tools_prompt(&request)]
+        .into_iter()
+        .flatten()
+        .chain([state
+            .conversation_templates()
+            .prompt(request.project_id.as_deref().unwrap_or("default"), &request.messages)])
+        .collect::<Vec<_>>()
+        .join("\n");
+    let prompt_tokens = tokens::estimate_tokens(&prompt_text);
+    let prompt_cost = prompt_tokens as f64 * price.input / 1e6;
+    let completion_cost = request.max_tokens.map(|n| n as f64 * price.output / 1e6);
+
+    Ok(Json(json!({
+        "object": "chat.completion.estimate",
+        "model": model,
+        "prompt_tokens": prompt_tokens,
+        "max_completion_tokens": request.max_tokens,
+        "pricing": price,
+        "prompt_cost_usd": prompt_cost,
+        "max_completion_cost_usd": completion_cost,
+        "cost_usd": prompt_cost + completion_cost.unwrap_or(0.0),
+    })))
+}
+
+#[utoipa::path(
+    post, path = "/v1/chat/completions/debug", tag = "chat",
+    request_body = Object,
+    responses((status = 200, description = "The request body, echoed back", body = Object))
+)]
 pub async fn debug_chat_completion(
-    Json(body): Json<serde_json::Value>,
+    AppJson(body): AppJson<serde_json::Value>,
 ) -> Json<serde_json::Value> {
     Json(json!({
         "debug": true,
@@ -402,6 +1207,122 @@ pub async fn debug_chat_completion(
     }))
 }
 
+/// POST /v1/chat/completions/raw
+///
+/// Run the request and stream the CLI's JSONL events as they arrive,
+/// without translating them, so a suspicious translated response can be
+/// compared with what the CLI produced. Requires an admin key (see
+/// `auth::ADMIN_ONLY_PATHS`). No cache, guardrails, compaction or
+/// attachments; nothing is stored.
+#[utoipa::path(
+    post, path = "/v1/chat/completions/raw", tag = "chat",
+    request_body = ChatCompletionRequest,
+    responses(
+        (status = 200, description = "The CLI's events, one JSON object per line", content_type = "application/x-ndjson"),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+    )
+)]
+pub async fn raw_chat_completion(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<ApiKey>>,
+    headers: HeaderMap,
+    AppJson(mut request): AppJson<ChatCompletionRequest>,
+) -> Result<Response, AppError> {
+    let config = state.config();
+    budget::check(&state).await?;
+    sessions::apply_session_defaults(&state, &mut request).await?;
+    validate_chat_request(&request, &config)?;
+    prompts::apply_prompt(&state, &mut request).await?;
+    let (profile, claude_model) = route_model(&config, &request)?;
+    let plan_mode = plan_mode(&request, profile)?;
+    let project_id = request.project_id.clone().unwrap_or_else(|| "default".to_string());
+    let api_key = api_key.map(|Extension(ApiKey(key))| key);
+    quota::check_key(&state, api_key.as_deref()).await?;
+    let priority = priority::from_headers(&config, api_key.as_deref(), &headers, Priority::Normal)?;
+    let project_path = create_project_directory(&config.project_root, &project_id)?;
+    hooks::materialize(&state, &project_id, &project_path).await?;
+    let has_tools = request.tools.as_ref().is_some_and(|t| !t.is_empty());
+    let delegation =
+        agents::prepare(&state, &project_id, &project_path, request.agent.as_deref(), has_tools).await?;
+    let mut profile = profile.clone();
+    if let Some(dir) = config.account_config_dir(api_key.as_deref(), &project_id) {
+        profile.config_dir = Some(dir.to_path_buf());
+    }
+
+    let prompt = state.conversation_templates().prompt(&project_id, &request.messages);
+    let system_prompt = system_prompt(&request);
+    let append_system_prompt = tools_prompt(&request).or(delegation);
+    let prompt_text = [system_prompt.as_deref(), append_system_prompt.as_deref(), Some(&prompt)]
+        .into_iter()
+        .flatten()
+        .collect::<Vec<_>>()
+        .join("\n");
+    check_prompt_size(&prompt_text, &config)?;
+    let session_id = request
+        .session_id
+        .clone()
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    tracing::info!(model = %claude_model, profile = %profile.name, session_id = %session_id, "Raw chat completion request");
+    let (mut claude_stream, claude_session_id) = state
+        .claude_manager
+        .create_session(
+            &session_id,
+            &profile,
+            SpawnOptions {
+                prompt: &prompt,
+                model: &claude_model,
+                system_prompt: system_prompt.as_deref(),
+                append_system_prompt: append_system_prompt.as_deref(),
+                disable_builtin_tools: has_tools,
+                env: build_env(&config, &profile, Some(&project_id)),
+                project_dir: &project_path,
+                sandbox: state.sandbox.as_ref(),
+                limits: state.limits.as_ref(),
+                resume: None,
+                plan: plan_mode,
+                priority,
+            },
+        )
+        .await
+        .map_err(|e| AppError::ServiceUnavailable(format!("Failed to start Claude Code: {e}")))?;
+    let effective_session_id = claude_session_id.unwrap_or(session_id);
+
+    let (tx, rx) = tokio::sync::mpsc::channel::<String>(64);
+    let sid = effective_session_id.clone();
+    let model = claude_model.clone();
+    tokio::spawn(async move {
+        while let Some(msg) = claude_stream.next().await {
+            if tx.send(format!("{msg}\n")).await.is_err() {
+                break;
+            }
+            if is_result_message(&msg) {
+                if let Some(mut usage) = extract_usage(&msg) {
+                    state.pricing().fill_cost(&model, &mut usage);
+                    budget::record(&state, usage.cost_usd).await;
+                    quota::record_tokens(&state, api_key.as_deref(), usage.input_tokens + usage.output_tokens).await;
+                }
+                break;
+            }
+        }
+        state.claude_manager.session_finished(&sid).await;
+    });
+
+    let body = Body::from_stream(tokio_stream::wrappers::ReceiverStream::new(rx).map(Ok::<_, std::io::Error>));
+    Ok(Response::builder()
+        .status(200)
+        .header("Content-Type", "application/x-ndjson")
+        .header("Cache-Control", "no-cache")
+        .header("X-Session-ID", &effective_session_id)
+        .body(body)
+        .unwrap()
+        .into_response())
+}
+
+#[utoipa::path(
+    get, path = "/v1/chat/completions/{session_id}/status", tag = "chat",
+    params(("session_id" = String, Path, description = "Session ID")),
+    responses((status = 200, description = "Whether the session has a running completion", body = Object))
+)]
 pub async fn get_completion_status(
     State(state): State<Arc<AppState>>,
     Path(session_id): Path<String>,
@@ -423,6 +1344,11 @@ pub async fn get_completion_status(
     }
 }
 
+#[utoipa::path(
+    delete, path = "/v1/chat/completions/{session_id}", tag = "chat",
+    params(("session_id" = String, Path, description = "Session ID")),
+    responses((status = 200, description = "The completion was stopped", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
 pub async fn stop_completion(
     State(state): State<Arc<AppState>>,
     Path(session_id): Path<String>,
@@ -434,3 +1360,32 @@ pub async fn stop_completion(
         "status": "stopped",
     }))
 }
+
+/// POST /v1/chat/completions/{completion_id}/cancel
+///
+/// Stop a running completion by the `chatcmpl-` ID its chunks carry, for
+/// clients that never see the session ID. With a session registry, cancels
+/// of completions another replica is answering are forwarded to it (see
+/// [`crate::registry`]); without one, only this replica's are found.
+#[utoipa::path(
+    post, path = "/v1/chat/completions/{completion_id}/cancel", tag = "chat",
+    params(("completion_id" = String, Path, description = "Completion ID (`chatcmpl-...`)")),
+    responses((status = 200, description = "The completion was cancelled", body = Object), (status = 404, description = "No such completion is running", body = ErrorResponse))
+)]
+pub async fn cancel_completion(
+    State(state): State<Arc<AppState>>,
+    Path(completion_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let session_id = state
+        .claude_manager
+        .cancel_completion(&completion_id)
+        .await
+        .ok_or_else(|| AppError::NotFound(format!("Completion {completion_id} is not running")))?;
+    tracing::info!(completion_id = %completion_id, session_id = %session_id, "Chat completion cancelled");
+    Ok(Json(json!({
+        "id": completion_id,
+        "object": "chat.completion",
+        "session_id": session_id,
+        "status": "cancelled",
+    })))
+}
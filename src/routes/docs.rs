@@ -0,0 +1,249 @@
+use axum::response::Html;
+use axum::Json;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::{Modify, OpenApi};
+
+use crate::error::{ErrorDetail, ErrorResponse};
+use crate::models::openai::{
+    ChatCompletionChoice, ChatCompletionChunk, ChatCompletionRequest, ChatCompletionResponse,
+    ChatCompletionUsage, ChatMessage, ChatMessageResponse, ChunkChoice, ChunkDelta, CompareRequest,
+    CreateProjectRequest, PutAgentRequest, PutHooksRequest, HookMatcher, HookCommand, CreatePromptRequest, CreatePromptVersionRequest, CreateSessionRequest, SessionCommandRequest, CreateTaskRequest, PullRequestOptions, EmbeddingData, EmbeddingInput, EmbeddingRequest,
+    EmbeddingResponse, EmbeddingUsage, FunctionCall, Tool, ToolCall, ToolFunction,
+};
+use crate::routes::{admin, chat, embeddings, files, jobs, limits, mcp, models, projects, prompts, quota, root, sessions, tasks};
+
+/// The gateway's OpenAPI document, generated from the handlers'
+/// `#[utoipa::path]` annotations.
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Claude Code API Gateway",
+        description = "OpenAI-compatible API for the Claude Code CLI",
+    ),
+    paths(
+        root::root,
+        root::health,
+        root::readyz,
+        chat::create_chat_completion,
+        chat::compare_chat_completions,
+        chat::estimate_chat_completion,
+        chat::debug_chat_completion,
+        chat::raw_chat_completion,
+        chat::get_completion_status,
+        chat::stop_completion,
+        chat::cancel_completion,
+        jobs::list_jobs,
+        jobs::get_job,
+        quota::get_quota,
+        limits::get_limits,
+        tasks::list_tasks,
+        tasks::create_task,
+        tasks::get_task,
+        tasks::get_task_diff,
+        embeddings::create_embeddings,
+        files::upload_file,
+        files::list_files,
+        files::get_file,
+        files::get_file_content,
+        files::delete_file,
+        models::list_models,
+        models::get_model_capabilities,
+        models::get_model,
+        projects::list_projects,
+        projects::create_project,
+        projects::get_project,
+        projects::delete_project,
+        projects::index_project,
+        projects::delete_project_index,
+        projects::get_hooks,
+        projects::put_hooks,
+        projects::delete_hooks,
+        projects::list_agents,
+        projects::put_agent,
+        projects::get_agent,
+        projects::delete_agent,
+        prompts::list_prompts,
+        prompts::create_prompt,
+        prompts::get_prompt,
+        prompts::delete_prompt,
+        prompts::list_prompt_versions,
+        prompts::create_prompt_version,
+        sessions::list_sessions,
+        sessions::create_session,
+        sessions::get_session_stats,
+        sessions::get_session,
+        sessions::delete_session,
+        sessions::list_messages,
+        sessions::get_transcript,
+        sessions::replay_session,
+        sessions::compact_session,
+        sessions::run_command,
+        admin::list_audit_log,
+        admin::list_usage,
+        admin::daily_usage,
+        admin::get_metrics,
+        admin::run_retention,
+        admin::purge,
+        admin::export_data,
+        admin::reload_config,
+        admin::get_budget,
+        admin::lift_budget,
+        admin::restore_budget,
+        admin::list_keys,
+        admin::list_key_quotas,
+        admin::put_key_quota,
+        admin::delete_key_quota,
+        admin::list_recordings,
+        admin::get_recording,
+        admin::replay_recording,
+        admin::list_processes,
+        admin::recent_sessions,
+        admin::list_instances,
+        admin::stop_all_sessions,
+        admin::kill_session,
+        mcp::post_mcp,
+    ),
+    components(schemas(
+        ChatCompletionRequest,
+        CompareRequest,
+        ChatMessage,
+        Tool,
+        ToolFunction,
+        ToolCall,
+        FunctionCall,
+        ChatCompletionResponse,
+        ChatCompletionChoice,
+        ChatMessageResponse,
+        ChatCompletionUsage,
+        ChatCompletionChunk,
+        ChunkChoice,
+        ChunkDelta,
+        EmbeddingRequest,
+        EmbeddingInput,
+        EmbeddingResponse,
+        EmbeddingData,
+        EmbeddingUsage,
+        CreateProjectRequest,
+        PutAgentRequest,
+        PutHooksRequest,
+        HookMatcher,
+        HookCommand,
+        CreatePromptRequest,
+        CreatePromptVersionRequest,
+        CreateSessionRequest,
+        SessionCommandRequest,
+        CreateTaskRequest,
+        PullRequestOptions,
+        admin::PurgeRequest,
+        admin::BudgetOverrideRequest,
+        admin::PutKeyQuotaRequest,
+        ErrorResponse,
+        ErrorDetail,
+    )),
+    modifiers(&SecurityAddon),
+    security(("bearer" = []), ("api_key" = [])),
+    tags(
+        (name = "chat", description = "Chat completions and estimates"),
+        (name = "jobs", description = "Background chat completion jobs"),
+        (name = "quota", description = "The calling key's monthly token allowance and the subscription's usage windows"),
+        (name = "tasks", description = "Headless coding tasks returning a diff or a pull request"),
+        (name = "embeddings", description = "Local feature-hashing embeddings"),
+        (name = "files", description = "Uploaded files for chat attachments"),
+        (name = "models", description = "Model catalog"),
+        (name = "projects", description = "Project working directories, their retrieval index, subagents and hooks"),
+        (name = "prompts", description = "Versioned system prompt templates"),
+        (name = "sessions", description = "Stored conversations"),
+        (name = "mcp", description = "The gateway as an MCP server"),
+        (name = "admin", description = "Operator endpoints (admin listener or admin key)"),
+        (name = "meta", description = "Service info and health"),
+    ),
+)]
+pub struct ApiDoc;
+
+/// The ways `auth::auth_middleware` accepts an API key.
+struct SecurityAddon;
+
+impl Modify for SecurityAddon {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme(
+            "bearer",
+            SecurityScheme::Http(HttpBuilder::new().scheme(HttpAuthScheme::Bearer).build()),
+        );
+        components.add_security_scheme(
+            "api_key",
+            SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new("x-api-key"))),
+        );
+    }
+}
+
+/// GET /openapi.json
+pub async fn openapi_json() -> Json<utoipa::openapi::OpenApi> {
+    Json(ApiDoc::openapi())
+}
+
+/// GET /docs — Swagger UI for `/openapi.json`.
+pub async fn swagger_ui() -> Html<&'static str> {
+    Html(
+        r##"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>Claude Code API Gateway</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "/openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>"##,
+    )
+}
+
+/// GET /redoc — Redoc for `/openapi.json`.
+pub async fn redoc() -> Html<&'static str> {
+    Html(
+        r##"<!DOCTYPE html>
+<html>
+<head>
+  <meta charset="utf-8">
+  <title>Claude Code API Gateway</title>
+</head>
+<body>
+  <redoc spec-url="/openapi.json"></redoc>
+  <script src="https://cdn.redoc.ly/redoc/latest/bundles/redoc.standalone.js"></script>
+</body>
+</html>"##,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_openapi_document() {
+        let doc = serde_json::to_value(ApiDoc::openapi()).unwrap();
+        let paths = doc["paths"].as_object().unwrap();
+        for path in [
+            "/v1/chat/completions",
+            "/v1/models/capabilities",
+            "/v1/sessions/{session_id}/compact",
+            "/admin/audit",
+        ] {
+            assert!(paths.contains_key(path), "missing {path}");
+        }
+        assert!(paths["/v1/files/{file_id}"].get("delete").is_some());
+
+        // Gateway extension fields are part of the request schema
+        let request = &doc["components"]["schemas"]["ChatCompletionRequest"]["properties"];
+        for field in ["session_id", "project_id", "system_prompt", "profile"] {
+            assert!(request.get(field).is_some(), "missing {field}");
+        }
+        let params = paths["/v1/chat/completions"]["post"]["parameters"].as_array().unwrap();
+        assert!(params.iter().any(|p| p["name"] == "async"));
+    }
+}
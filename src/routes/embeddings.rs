@@ -1,9 +1,15 @@
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::extract::State;
-use axum::Json;
+use axum::http::{header, HeaderValue};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
 
-use crate::error::AppError;
+use crate::audit::AuditContext;
+use crate::config::EmbeddingUpstream;
+use crate::error::{AppError, ErrorResponse};
+use crate::extract::AppJson;
 use crate::models::openai::{
     EmbeddingData, EmbeddingInput, EmbeddingRequest, EmbeddingResponse, EmbeddingUsage,
 };
@@ -14,12 +20,32 @@ const DEFAULT_DIM: usize = 1536;
 
 /// POST /v1/embeddings
 ///
-/// Pure-Rust embeddings using feature hashing with word unigrams,
-/// bigrams, and character trigrams. No external model required.
+/// Models claimed by an `EMBEDDING_UPSTREAMS` provider are forwarded to it.
+/// Everything else gets pure-Rust embeddings using feature hashing with
+/// word unigrams, bigrams, and character trigrams. No external model
+/// required.
+#[utoipa::path(
+    post, path = "/v1/embeddings", tag = "embeddings",
+    request_body = EmbeddingRequest,
+    responses(
+        (status = 200, description = "The embeddings", body = EmbeddingResponse),
+        (status = 400, description = "Invalid request", body = ErrorResponse),
+        (status = 503, description = "The upstream provider is unreachable", body = ErrorResponse),
+    )
+)]
 pub async fn create_embeddings(
-    State(_state): State<Arc<AppState>>,
-    Json(request): Json<EmbeddingRequest>,
-) -> Result<Json<EmbeddingResponse>, AppError> {
+    State(state): State<Arc<AppState>>,
+    audit: Option<Extension<AuditContext>>,
+    AppJson(request): AppJson<EmbeddingRequest>,
+) -> Result<Response, AppError> {
+    if let (Some(Extension(audit)), Some(user)) = (audit, request.user.as_deref()) {
+        audit.set_user(user);
+    }
+    if let Some(upstream) = state.config().embedding_upstream(&request.model) {
+        let timeout = Duration::from_secs(state.config().embedding_timeout_seconds);
+        return forward(&state.http, upstream, timeout, &request).await;
+    }
+
     let dim = request.dimensions.unwrap_or(DEFAULT_DIM);
     if dim == 0 || dim > 4096 {
         return Err(AppError::BadRequest(format!(
@@ -55,7 +81,44 @@ pub async fn create_embeddings(
             prompt_tokens: total_tokens,
             total_tokens,
         },
-    }))
+    })
+    .into_response())
+}
+
+/// Forward `request` to `upstream`, passing its status and body through
+/// (provider errors already use the OpenAI error envelope).
+async fn forward(
+    client: &reqwest::Client,
+    upstream: &EmbeddingUpstream,
+    timeout: Duration,
+    request: &EmbeddingRequest,
+) -> Result<Response, AppError> {
+    let unreachable = |e: reqwest::Error| {
+        tracing::warn!(upstream = %upstream.name, error = %e, "Embedding upstream request failed");
+        AppError::ServiceUnavailable(format!("Embedding upstream '{}' failed: {e}", upstream.name))
+    };
+    let mut req = client
+        .post(format!("{}/embeddings", upstream.url))
+        .timeout(timeout)
+        .json(request);
+    if let Some(ref key) = upstream.api_key {
+        req = req.bearer_auth(key);
+    }
+    let resp = req.send().await.map_err(unreachable)?;
+    let status = resp.status();
+    let content_type = resp
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .cloned()
+        .unwrap_or_else(|| HeaderValue::from_static("application/json"));
+    let body = resp.bytes().await.map_err(unreachable)?;
+    tracing::info!(
+        upstream = %upstream.name,
+        model = %request.model,
+        status = status.as_u16(),
+        "Forwarded embeddings request"
+    );
+    Ok((status, [(header::CONTENT_TYPE, content_type)], body).into_response())
 }
 
 /// Generate an embedding vector for the given text using feature hashing.
@@ -63,7 +126,7 @@ pub async fn create_embeddings(
 /// Combines word unigrams, word bigrams, and character trigrams
 /// to capture both exact-word and sub-word similarity.
 /// The result is L2-normalized to unit length.
-fn embed_text(text: &str, dim: usize) -> Vec<f32> {
+pub(crate) fn embed_text(text: &str, dim: usize) -> Vec<f32> {
     let mut vec = vec![0.0f32; dim];
     let text = text.to_lowercase();
 
@@ -154,6 +217,50 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn test_forward_to_upstream() {
+        use axum::http::HeaderMap;
+
+        // A fake provider that echoes what it received
+        let app = axum::Router::new().route(
+            "/v1/embeddings",
+            axum::routing::post(|headers: HeaderMap, Json(body): Json<serde_json::Value>| async move {
+                let auth = headers.get("authorization").and_then(|v| v.to_str().ok()).map(str::to_string);
+                let status = if body["model"] == "missing" { 404 } else { 200 };
+                let status = axum::http::StatusCode::from_u16(status).unwrap();
+                (status, Json(serde_json::json!({"auth": auth, "received": body})))
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let upstream = EmbeddingUpstream {
+            name: "fake".to_string(),
+            url: format!("http://{addr}/v1"),
+            api_key: Some("sk-upstream".to_string()),
+            model_prefixes: vec!["text-embedding-3".to_string()],
+        };
+        let call = |model: &str| {
+            let request: EmbeddingRequest =
+                serde_json::from_value(serde_json::json!({"input": ["a", "b"], "model": model})).unwrap();
+            let upstream = upstream.clone();
+            async move {
+                let client = reqwest::Client::new();
+                let resp = forward(&client, &upstream, Duration::from_secs(5), &request).await.unwrap();
+                let status = resp.status();
+                let bytes = axum::body::to_bytes(resp.into_body(), usize::MAX).await.unwrap();
+                (status, serde_json::from_slice::<serde_json::Value>(&bytes).unwrap())
+            }
+        };
+
+        let (status, body) = call("text-embedding-3-small").await;
+        assert_eq!(status, 200);
+        assert_eq!(body["auth"], "Bearer sk-upstream");
+        assert_eq!(body["received"], serde_json::json!({"input": ["a", "b"], "model": "text-embedding-3-small"}));
+        assert_eq!(call("missing").await.0, 404);
+    }
+
     #[test]
     fn test_empty_text() {
         let v = embed_text("", 384);
@@ -0,0 +1,240 @@
+use std::path::{Path as FsPath, PathBuf};
+use std::sync::Arc;
+
+use axum::extract::{Multipart, Path, Query, State};
+use axum::Extension;
+use axum::http::header;
+use axum::response::{IntoResponse, Response};
+use axum::Json;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use serde_json::json;
+use tokio::io::AsyncWriteExt;
+
+use crate::auth::{hash_api_key, ApiKey};
+use crate::db::{self, FileRow};
+use crate::error::{AppError, ErrorResponse};
+use crate::models::openai::sanitize_filename;
+use crate::state::AppState;
+
+/// Where uploaded file contents are kept.
+pub fn files_dir(project_root: &FsPath) -> PathBuf {
+    project_root.join(".files")
+}
+
+/// POST /v1/files — multipart upload with `file` and `purpose` fields.
+#[utoipa::path(
+    post, path = "/v1/files", tag = "files",
+    request_body(content_type = "multipart/form-data", description = "`file` and optional `purpose` fields"),
+    responses((status = 200, description = "The file object", body = Object), (status = 400, description = "Invalid request", body = ErrorResponse))
+)]
+pub async fn upload_file(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<ApiKey>>,
+    mut multipart: Multipart,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let dir = files_dir(&state.config().project_root);
+    tokio::fs::create_dir_all(&dir).await?;
+    let id = format!("file-{}", uuid::Uuid::new_v4().as_simple());
+    let path = dir.join(&id);
+
+    let mut purpose = None;
+    let mut upload = None;
+    let result = async {
+        while let Some(mut field) = multipart.next_field().await.map_err(bad_multipart)? {
+            match field.name() {
+                Some("purpose") => purpose = Some(field.text().await.map_err(bad_multipart)?),
+                Some("file") => {
+                    let filename = field
+                        .file_name()
+                        .map(sanitize_filename)
+                        .filter(|n| !n.is_empty())
+                        .unwrap_or_else(|| "upload".to_string());
+                    // Stream to disk rather than buffering the whole file
+                    let mut file = tokio::fs::File::create(&path).await?;
+                    let mut bytes = 0usize;
+                    while let Some(chunk) = field.chunk().await.map_err(bad_multipart)? {
+                        bytes += chunk.len();
+                        file.write_all(&chunk).await?;
+                    }
+                    file.flush().await?;
+                    upload = Some((filename, bytes));
+                }
+                _ => {}
+            }
+        }
+        let (filename, bytes) =
+            upload.ok_or_else(|| AppError::BadRequest("Missing 'file' field".to_string()))?;
+        let purpose = purpose.unwrap_or_else(|| "user_data".to_string());
+        let row = db::create_file(
+            &state.db,
+            &id,
+            &filename,
+            &purpose,
+            bytes as i64,
+            &path.to_string_lossy(),
+            owner(&api_key).as_deref(),
+        )
+        .await?;
+        Ok(row)
+    }
+    .await;
+
+    match result {
+        Ok(row) => {
+            tracing::info!(file_id = %row.id, bytes = row.bytes, "File uploaded");
+            Ok(Json(file_object(&row)))
+        }
+        Err(e) => {
+            let _ = tokio::fs::remove_file(&path).await;
+            Err(e)
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct FilesQuery {
+    #[serde(default)]
+    pub purpose: Option<String>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+/// GET /v1/files
+#[utoipa::path(
+    get, path = "/v1/files", tag = "files",
+    params(FilesQuery),
+    responses((status = 200, description = "File objects", body = Object))
+)]
+pub async fn list_files(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<ApiKey>>,
+    Query(q): Query<FilesQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let limit = q.limit.unwrap_or(10_000).clamp(1, 10_000);
+    let files = db::list_files(&state.db, owner(&api_key).as_deref(), q.purpose.as_deref(), limit + 1).await?;
+    let has_more = files.len() as i64 > limit;
+    let data: Vec<_> = files.iter().take(limit as usize).map(file_object).collect();
+    Ok(Json(json!({
+        "object": "list",
+        "data": data,
+        "has_more": has_more,
+    })))
+}
+
+/// GET /v1/files/{file_id}
+#[utoipa::path(
+    get, path = "/v1/files/{file_id}", tag = "files",
+    params(("file_id" = String, Path, description = "File ID")),
+    responses((status = 200, description = "The file object", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn get_file(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<ApiKey>>,
+    Path(file_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    Ok(Json(file_object(&find_file(&state, &api_key, &file_id).await?)))
+}
+
+/// GET /v1/files/{file_id}/content
+#[utoipa::path(
+    get, path = "/v1/files/{file_id}/content", tag = "files",
+    params(("file_id" = String, Path, description = "File ID")),
+    responses((status = 200, description = "The file's contents", content_type = "application/octet-stream"), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn get_file_content(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<ApiKey>>,
+    Path(file_id): Path<String>,
+) -> Result<Response, AppError> {
+    let file = find_file(&state, &api_key, &file_id).await?;
+    let bytes = tokio::fs::read(&file.path).await?;
+    let disposition = format!("attachment; filename=\"{}\"", file.filename);
+    Ok((
+        [
+            (header::CONTENT_TYPE, "application/octet-stream".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        bytes,
+    )
+        .into_response())
+}
+
+/// DELETE /v1/files/{file_id}
+#[utoipa::path(
+    delete, path = "/v1/files/{file_id}", tag = "files",
+    params(("file_id" = String, Path, description = "File ID")),
+    responses((status = 200, description = "Deletion status", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn delete_file(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<ApiKey>>,
+    Path(file_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let file = find_file(&state, &api_key, &file_id).await?;
+    db::delete_file(&state.db, &file.id).await?;
+    if let Err(e) = tokio::fs::remove_file(&file.path).await {
+        tracing::warn!(file_id = %file.id, error = %e, "Failed to remove file contents");
+    }
+    Ok(Json(json!({
+        "id": file.id,
+        "object": "file",
+        "deleted": true,
+    })))
+}
+
+/// Copy files `api_key` uploaded into `dir` (a project's uploads
+/// directory) so the CLI can Read them, returning the copies' paths. Copies
+/// are reused across requests.
+pub async fn materialize_files(
+    state: &AppState,
+    api_key: Option<&str>,
+    file_ids: &[String],
+    dir: &FsPath,
+) -> Result<Vec<String>, AppError> {
+    let owner = api_key.map(hash_api_key);
+    let mut paths = Vec::with_capacity(file_ids.len());
+    for id in file_ids {
+        let file = db::get_file(&state.db, id, owner.as_deref())
+            .await?
+            .ok_or_else(|| AppError::BadRequest(format!("No such file: {id}")))?;
+        let target = dir.join(format!("{}-{}", file.id, file.filename));
+        if !tokio::fs::try_exists(&target).await.unwrap_or(false) {
+            tokio::fs::create_dir_all(dir).await?;
+            tokio::fs::copy(&file.path, &target).await?;
+        }
+        paths.push(target.to_string_lossy().to_string());
+    }
+    Ok(paths)
+}
+
+/// Files belong to the key that uploaded them: its hash, or `None` without
+/// a key.
+fn owner(api_key: &Option<Extension<ApiKey>>) -> Option<String> {
+    api_key.as_ref().map(|Extension(ApiKey(key))| hash_api_key(key))
+}
+
+async fn find_file(state: &AppState, api_key: &Option<Extension<ApiKey>>, file_id: &str) -> Result<FileRow, AppError> {
+    db::get_file(&state.db, file_id, owner(api_key).as_deref())
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("File {file_id} not found")))
+}
+
+/// An OpenAI `file` object.
+fn file_object(file: &FileRow) -> serde_json::Value {
+    let created_at = chrono::NaiveDateTime::parse_from_str(&file.created_at, "%Y-%m-%d %H:%M:%S")
+        .map_or(0, |t| t.and_utc().timestamp());
+    json!({
+        "id": file.id,
+        "object": "file",
+        "bytes": file.bytes,
+        "created_at": created_at,
+        "filename": file.filename,
+        "purpose": file.purpose,
+    })
+}
+
+fn bad_multipart(e: axum::extract::multipart::MultipartError) -> AppError {
+    AppError::BadRequest(format!("Invalid multipart body: {e}"))
+}
@@ -0,0 +1,56 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::Json;
+use serde::Deserialize;
+use utoipa::IntoParams;
+use serde_json::json;
+
+use crate::db;
+use crate::error::{AppError, ErrorResponse};
+use crate::jobs::job_object;
+use crate::state::AppState;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct JobsQuery {
+    /// Only jobs in this status (`queued`, `running`, `succeeded`, `failed`).
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+/// GET /v1/jobs
+#[utoipa::path(
+    get, path = "/v1/jobs", tag = "jobs",
+    params(JobsQuery),
+    responses((status = 200, description = "Jobs, newest first", body = Object))
+)]
+pub async fn list_jobs(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<JobsQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let limit = q.limit.unwrap_or(20).clamp(1, 100);
+    let jobs = db::list_jobs(&state.db, q.status.as_deref(), limit).await?;
+    Ok(Json(json!({
+        "object": "list",
+        "data": jobs.iter().map(job_object).collect::<Vec<_>>(),
+    })))
+}
+
+/// GET /v1/jobs/{job_id} — the job's status, with its completion once done.
+#[utoipa::path(
+    get, path = "/v1/jobs/{job_id}", tag = "jobs",
+    params(("job_id" = String, Path, description = "Job ID")),
+    responses((status = 200, description = "The job", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn get_job(
+    State(state): State<Arc<AppState>>,
+    Path(job_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    match db::get_job(&state.db, &job_id).await? {
+        Some(job) => Ok(Json(job_object(&job))),
+        None => Err(AppError::NotFound(format!("Job {job_id} not found"))),
+    }
+}
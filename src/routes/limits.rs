@@ -0,0 +1,28 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::Json;
+use serde_json::json;
+
+use crate::state::AppState;
+
+/// GET /v1/limits — the subscription usage windows the CLI last reported.
+#[utoipa::path(
+    get, path = "/v1/limits", tag = "quota",
+    responses((status = 200, description = "Usage windows by profile, tightest first; empty until a run reports one", body = Object))
+)]
+pub async fn get_limits(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let data: Vec<_> = state
+        .plan_limits
+        .windows()
+        .into_iter()
+        .map(|w| {
+            let remaining = w.utilization.map(|u| (1.0 - u).max(0.0));
+            let mut window = json!(w);
+            window["object"] = json!("plan_limit");
+            window["remaining"] = json!(remaining);
+            window
+        })
+        .collect();
+    Json(json!({ "object": "list", "data": data }))
+}
@@ -0,0 +1,43 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::http::StatusCode;
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde_json::Value;
+
+use crate::auth::ApiKey;
+use crate::extract::AppJson;
+use crate::mcp;
+use crate::state::AppState;
+
+/// POST /mcp
+///
+/// The MCP streamable HTTP transport: one JSON-RPC message in, its
+/// response out (`202` for notifications). Tools run with the caller's API
+/// key, see [`crate::mcp`].
+#[utoipa::path(
+    post, path = "/mcp", tag = "mcp",
+    request_body(content = Object, description = "A JSON-RPC 2.0 message"),
+    responses(
+        (status = 200, description = "The JSON-RPC response", body = Object),
+        (status = 202, description = "Notification accepted"),
+    )
+)]
+pub async fn post_mcp(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<ApiKey>>,
+    AppJson(message): AppJson<Value>,
+) -> Response {
+    let key = api_key.as_ref().map(|Extension(ApiKey(key))| key.as_str());
+    match mcp::handle(&state, key, message).await {
+        Some(reply) => Json(reply).into_response(),
+        None => StatusCode::ACCEPTED.into_response(),
+    }
+}
+
+/// GET /mcp — the server never initiates messages, so there is no event
+/// stream to open.
+pub async fn get_mcp() -> StatusCode {
+    StatusCode::METHOD_NOT_ALLOWED
+}
@@ -1,32 +1,75 @@
+pub mod admin;
 pub mod root;
 pub mod chat;
+pub mod docs;
 pub mod embeddings;
+pub mod files;
+pub mod jobs;
+pub mod limits;
+pub mod mcp;
 pub mod models;
 pub mod projects;
+pub mod prompts;
+pub mod quota;
 pub mod sessions;
+pub mod tasks;
 
 use std::sync::Arc;
 
+use axum::extract::DefaultBodyLimit;
 use axum::routing::{delete, get, post};
 use axum::Router;
 
+use crate::registry;
 use crate::state::AppState;
 
 pub fn build_router(state: Arc<AppState>) -> Router {
+    // Requests about a session another replica owns are proxied there
+    let owned = axum::middleware::from_fn_with_state(state.clone(), registry::forward_to_owner);
     let v1 = Router::new()
         // Chat completions
-        .route("/chat/completions", post(chat::create_chat_completion))
+        .route(
+            "/chat/completions",
+            post(chat::create_chat_completion).route_layer(owned.clone()),
+        )
+        .route("/chat/completions/compare", post(chat::compare_chat_completions))
         .route("/chat/completions/debug", post(chat::debug_chat_completion))
+        .route("/chat/completions/raw", post(chat::raw_chat_completion))
         .route(
             "/chat/completions/{session_id}/status",
-            get(chat::get_completion_status),
+            get(chat::get_completion_status).route_layer(owned.clone()),
         )
         .route(
             "/chat/completions/{session_id}",
-            delete(chat::stop_completion),
+            delete(chat::stop_completion).route_layer(owned.clone()),
         )
+        .route(
+            "/chat/completions/{completion_id}/cancel",
+            post(chat::cancel_completion).route_layer(owned.clone()),
+        )
+        .route("/estimate", post(chat::estimate_chat_completion))
+        // The calling key's monthly token allowance
+        .route("/quota", get(quota::get_quota))
+        // The subscription's usage windows
+        .route("/limits", get(limits::get_limits))
+        // Background completion jobs
+        .route("/jobs", get(jobs::list_jobs))
+        .route("/jobs/{job_id}", get(jobs::get_job))
+        // Headless tasks in git worktrees
+        .route("/tasks", get(tasks::list_tasks).post(tasks::create_task))
+        .route("/tasks/{task_id}", get(tasks::get_task))
+        .route("/tasks/{task_id}/diff", get(tasks::get_task_diff))
         // Embeddings
         .route("/embeddings", post(embeddings::create_embeddings))
+        // Files
+        .route(
+            "/files",
+            get(files::list_files).post(files::upload_file).layer(DefaultBodyLimit::max(
+                (state.config().max_upload_mb as usize).saturating_mul(1024 * 1024),
+            )),
+        )
+        .route("/files/{file_id}", get(files::get_file).delete(files::delete_file))
+        .route("/files/{file_id}/content", get(files::get_file_content))
         // Models
         .route("/models", get(models::list_models))
         .route("/models/capabilities", get(models::get_model_capabilities))
@@ -37,17 +80,80 @@ pub fn build_router(state: Arc<AppState>) -> Router {
             "/projects/{project_id}",
             get(projects::get_project).delete(projects::delete_project),
         )
+        .route(
+            "/projects/{project_id}/index",
+            post(projects::index_project).delete(projects::delete_project_index),
+        )
+        .route(
+            "/projects/{project_id}/hooks",
+            get(projects::get_hooks).put(projects::put_hooks).delete(projects::delete_hooks),
+        )
+        .route("/projects/{project_id}/agents", get(projects::list_agents))
+        .route(
+            "/projects/{project_id}/agents/{name}",
+            get(projects::get_agent).put(projects::put_agent).delete(projects::delete_agent),
+        )
+        // Prompt library
+        .route("/prompts", get(prompts::list_prompts).post(prompts::create_prompt))
+        .route(
+            "/prompts/{prompt_id}",
+            get(prompts::get_prompt).delete(prompts::delete_prompt),
+        )
+        .route(
+            "/prompts/{prompt_id}/versions",
+            get(prompts::list_prompt_versions).post(prompts::create_prompt_version),
+        )
         // Sessions
         .route("/sessions", get(sessions::list_sessions).post(sessions::create_session))
         .route("/sessions/stats", get(sessions::get_session_stats))
         .route(
             "/sessions/{session_id}",
             get(sessions::get_session).delete(sessions::delete_session),
-        );
+        )
+        .route("/sessions/{session_id}/messages", get(sessions::list_messages))
+        .route("/sessions/{session_id}/transcript", get(sessions::get_transcript))
+        .route("/sessions/{session_id}/replay", get(sessions::replay_session))
+        .route("/sessions/{session_id}/compact", post(sessions::compact_session))
+        .route("/sessions/{session_id}/commands", post(sessions::run_command))
+        .layer(axum::middleware::from_fn_with_state(state.clone(), crate::quota::quota_headers))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            crate::claude::plan_limits::plan_limit_headers,
+        ));
+
+    let admin = Router::new()
+        .route("/audit", get(admin::list_audit_log))
+        .route("/ui", get(admin::dashboard))
+        .route("/usage", get(admin::list_usage))
+        .route("/usage/daily", get(admin::daily_usage))
+        .route("/metrics", get(admin::get_metrics))
+        .route("/retention/run", post(admin::run_retention))
+        .route("/purge", post(admin::purge))
+        .route("/export", get(admin::export_data))
+        .route("/reload", post(admin::reload_config))
+        .route("/budget", get(admin::get_budget))
+        .route("/budget/override", post(admin::lift_budget).delete(admin::restore_budget))
+        .route("/keys", get(admin::list_keys))
+        .route("/quotas", get(admin::list_key_quotas).put(admin::put_key_quota))
+        .route("/quotas/{key_hash}", delete(admin::delete_key_quota))
+        .route("/recordings", get(admin::list_recordings))
+        .route("/recordings/{record_id}", get(admin::get_recording))
+        .route("/replay/{record_id}", post(admin::replay_recording))
+        .route("/processes", get(admin::list_processes))
+        .route("/instances", get(admin::list_instances))
+        .route("/sessions", get(admin::recent_sessions))
+        .route("/sessions/stop_all", post(admin::stop_all_sessions))
+        .route("/sessions/{session_id}/kill", post(admin::kill_session).route_layer(owned));
 
     Router::new()
         .route("/", get(root::root))
         .route("/health", get(root::health))
+        .route("/readyz", get(root::readyz))
+        .route("/openapi.json", get(docs::openapi_json))
+        .route("/docs", get(docs::swagger_ui))
+        .route("/redoc", get(docs::redoc))
+        .route("/mcp", post(mcp::post_mcp).get(mcp::get_mcp))
         .nest("/v1", v1)
+        .nest("/admin", admin)
         .with_state(state)
 }
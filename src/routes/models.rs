@@ -1,62 +1,138 @@
-use axum::extract::Path;
+use std::sync::Arc;
+
+use axum::extract::{Path, State};
 use axum::Json;
 use serde_json::json;
 
-use crate::error::AppError;
+use crate::claude::backend;
+use crate::error::{AppError, ErrorResponse};
+use crate::state::AppState;
 
-pub async fn list_models() -> Json<serde_json::Value> {
+#[utoipa::path(
+    get, path = "/v1/models", tag = "models",
+    responses((status = 200, description = "Every model and alias", body = Object))
+)]
+pub async fn list_models(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
     Json(json!({
         "object": "list",
-        "data": get_model_objects(),
+        "data": get_model_objects(&state),
     }))
 }
 
+#[utoipa::path(
+    get, path = "/v1/models/{model_id}", tag = "models",
+    params(("model_id" = String, Path, description = "Model ID or alias")),
+    responses((status = 200, description = "The model object", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
 pub async fn get_model(
+    State(state): State<Arc<AppState>>,
     Path(model_id): Path<String>,
 ) -> Result<Json<serde_json::Value>, AppError> {
-    let models = get_model_objects();
+    let models = get_model_objects(&state);
     let model = models
         .iter()
         .find(|m| m["id"].as_str() == Some(model_id.as_str()));
 
     match model {
         Some(m) => Ok(Json(m.clone())),
-        None => Err(AppError::NotFound(format!("Model '{model_id}' not found"))),
+        None => Err(AppError::ModelNotFound(model_id)),
     }
 }
 
-pub async fn get_model_capabilities() -> Json<serde_json::Value> {
-    // TODO: detailed capabilities in Step 2
+/// GET /v1/models/capabilities
+///
+/// One entry per servable model (catalog models, alias targets outside the
+/// catalog and `MODEL_ROUTES` models) with its limits, features, price and
+/// aliases. Limits and features are `null` when unknown, e.g. for models
+/// routed to other backends.
+#[utoipa::path(
+    get, path = "/v1/models/capabilities", tag = "models",
+    responses((status = 200, description = "Limits, features, pricing and aliases per model", body = Object))
+)]
+pub async fn get_model_capabilities(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    let config = &state.config();
+    let catalog = &config.model_catalog;
+    let claude_models = catalog
+        .entries()
+        .iter()
+        .map(|m| m.id.as_str())
+        .chain(catalog.extra_aliases().iter().map(|(_, target)| target.as_str()));
+    let routed = config.model_routes.iter().map(|(model, _)| model.as_str());
+    let mut ids: Vec<&str> = claude_models.chain(routed).collect();
+    let mut seen = std::collections::HashSet::new();
+    ids.retain(|id| seen.insert(*id));
+
+    let data: Vec<_> = ids
+        .into_iter()
+        .filter_map(|id| {
+            let profile = config.select_profile(None, &[id])?;
+            let backend = backend::for_profile(profile);
+            let known = profile.backend == "claude";
+            let capabilities = known.then(|| catalog.capabilities(id));
+            let supports = capabilities.as_ref().map(|caps| {
+                let has = |c: &str| caps.iter().any(|cap| cap == c);
+                json!({
+                    "streaming": has("streaming"),
+                    "tools": has("tools"),
+                    "vision": has("vision"),
+                    "files": has("files"),
+                })
+            });
+            Some(json!({
+                "id": id,
+                "object": "model.capabilities",
+                "backend": backend.name(),
+                "profile": profile.name,
+                "aliases": catalog.aliases_of(id),
+                "context_window": catalog.context_window(id),
+                "max_output_tokens": catalog.max_output_tokens(id),
+                "supports": supports,
+                "capabilities": capabilities,
+                "pricing": state.pricing().price(id),
+            }))
+        })
+        .collect();
     Json(json!({
-        "models": get_model_objects(),
+        "object": "list",
+        "data": data,
     }))
 }
 
-fn get_model_objects() -> Vec<serde_json::Value> {
-    vec![
-        json!({
-            "id": "claude-opus-4-6",
-            "object": "model",
-            "created": 1700000000,
-            "owned_by": "anthropic",
-        }),
-        json!({
-            "id": "cc-sonnet-45",
-            "object": "model",
-            "created": 1700000000,
-            "owned_by": "anthropic",
-        }),
-        json!({
-            "id": "cc-haiku-45",
-            "object": "model",
-            "created": 1700000000,
-            "owned_by": "anthropic",
-        }),
-        json!({
-            "id": "claude-3-7-sonnet-20250219",
-            "object": "model",
-            "created": 1700000000,
-            "owned_by": "anthropic",
-        }),
-    ]
+/// Every catalog model and alias (including `MODEL_ALIASES`) plus every
+/// `MODEL_ROUTES` model, each annotated with the profile and backend that
+/// would serve it.
+fn get_model_objects(state: &AppState) -> Vec<serde_json::Value> {
+    let config = &state.config();
+    let catalog = &config.model_catalog;
+    let listed = catalog
+        .entries()
+        .iter()
+        .flat_map(|m| std::iter::once(m.id.as_str()).chain(m.aliases.iter().map(String::as_str)))
+        .chain(catalog.extra_aliases().iter().map(|(alias, _)| alias.as_str()));
+    let routed = config.model_routes.iter().map(|(model, _)| model.as_str());
+    let mut ids: Vec<&str> = listed.chain(routed).collect();
+    let mut seen = std::collections::HashSet::new();
+    ids.retain(|id| seen.insert(*id));
+
+    ids.into_iter()
+        .filter_map(|id| {
+            let is_routed = config.model_routes.iter().any(|(m, _)| m == id);
+            let resolved = if is_routed { id.to_string() } else { catalog.resolve(id) };
+            let profile = config.select_profile(None, &[id, &resolved])?;
+            let backend = backend::for_profile(profile);
+            let entry = catalog.get(&resolved);
+            Some(json!({
+                "id": id,
+                "object": "model",
+                "created": entry.and_then(|m| m.created).unwrap_or(1700000000),
+                "owned_by": backend.owned_by(),
+                "backend": backend.name(),
+                "profile": profile.name,
+                "alias_for": (resolved != id && !is_routed).then_some(&resolved),
+                "context_window": catalog.context_window(&resolved),
+                "capabilities": entry.map(|m| &m.capabilities),
+                "pricing": state.pricing().price(&resolved),
+            }))
+        })
+        .collect()
 }
@@ -1,14 +1,58 @@
 use std::sync::Arc;
 
 use axum::extract::{Path, State};
+use axum::http::HeaderMap;
 use axum::Json;
 use serde_json::json;
 
+use crate::agents;
+use crate::claude::manager::create_project_directory;
+use crate::config::Config;
 use crate::db;
-use crate::error::AppError;
-use crate::models::openai::CreateProjectRequest;
+use crate::error::{AppError, ErrorResponse};
+use crate::extract::AppJson;
+use crate::hooks;
+use crate::models::openai::{CreateProjectRequest, PutAgentRequest, PutHooksRequest};
+use crate::rag;
 use crate::state::AppState;
 
+/// The project a chat request runs in: its `project_id`, else the
+/// `OpenAI-Project` header, else the project `ORGANIZATION_PROJECTS` maps
+/// the `OpenAI-Organization` header to. A key limited by `KEY_PROJECTS`
+/// defaults to its first project and may not use others.
+pub fn resolve_project(
+    config: &Config,
+    headers: &HeaderMap,
+    api_key: Option<&str>,
+    requested: Option<String>,
+) -> Result<Option<String>, AppError> {
+    let header = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(str::trim)
+            .filter(|v| !v.is_empty())
+    };
+    let project = requested
+        .or_else(|| header("openai-project").map(str::to_string))
+        .or_else(|| {
+            let org = header("openai-organization")?;
+            config.organization_projects.iter().find(|(o, _)| o == org).map(|(_, p)| p.clone())
+        });
+    let Some(allowed) = config.key_projects(api_key) else {
+        return Ok(project);
+    };
+    match project {
+        None => Ok(allowed.first().cloned()),
+        Some(p) if allowed.iter().any(|a| *a == p || a == "*") => Ok(Some(p)),
+        Some(p) => Err(AppError::Forbidden(format!("This API key may not use project {p}"))),
+    }
+}
+
+#[utoipa::path(
+    get, path = "/v1/projects", tag = "projects",
+    responses((status = 200, description = "Projects", body = Object))
+)]
 pub async fn list_projects(
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<serde_json::Value>, AppError> {
@@ -19,17 +63,31 @@ pub async fn list_projects(
     })))
 }
 
+#[utoipa::path(
+    post, path = "/v1/projects", tag = "projects",
+    request_body = CreateProjectRequest,
+    responses((status = 200, description = "The project", body = Object), (status = 400, description = "Invalid request", body = ErrorResponse))
+)]
 pub async fn create_project(
     State(state): State<Arc<AppState>>,
-    Json(body): Json<CreateProjectRequest>,
+    AppJson(body): AppJson<CreateProjectRequest>,
 ) -> Result<Json<serde_json::Value>, AppError> {
     let id = uuid::Uuid::new_v4().to_string();
     let desc = body.description.as_deref().unwrap_or("");
-    let project = db::create_project(&state.db, &id, &body.name, desc, body.path.as_deref())
+    let mut project = db::create_project(&state.db, &id, &body.name, desc, body.path.as_deref())
         .await?;
+    if body.rag {
+        db::set_project_rag(&state.db, &id, true).await?;
+        project.rag_enabled = true;
+    }
     Ok(Json(serde_json::to_value(project).unwrap_or(json!({}))))
 }
 
+#[utoipa::path(
+    get, path = "/v1/projects/{project_id}", tag = "projects",
+    params(("project_id" = String, Path, description = "Project ID")),
+    responses((status = 200, description = "The project", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
 pub async fn get_project(
     State(state): State<Arc<AppState>>,
     Path(project_id): Path<String>,
@@ -42,6 +100,11 @@ pub async fn get_project(
     }
 }
 
+#[utoipa::path(
+    delete, path = "/v1/projects/{project_id}", tag = "projects",
+    params(("project_id" = String, Path, description = "Project ID")),
+    responses((status = 200, description = "Deletion status", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
 pub async fn delete_project(
     State(state): State<Arc<AppState>>,
     Path(project_id): Path<String>,
@@ -58,3 +121,244 @@ pub async fn delete_project(
         )))
     }
 }
+
+/// POST /v1/projects/{project_id}/index
+///
+/// (Re)index the project's workspace and turn retrieval on for it.
+#[utoipa::path(
+    post, path = "/v1/projects/{project_id}/index", tag = "projects",
+    params(("project_id" = String, Path, description = "Project ID")),
+    responses(
+        (status = 200, description = "Index statistics", body = Object),
+        (status = 404, description = "Not found", body = ErrorResponse),
+    )
+)]
+pub async fn index_project(
+    State(state): State<Arc<AppState>>,
+    Path(project_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !db::set_project_rag(&state.db, &project_id, true).await? {
+        return Err(AppError::NotFound(format!("Project {project_id} not found")));
+    }
+    let stats = rag::index_project(&state, &project_id).await?;
+    Ok(Json(json!({
+        "project_id": project_id,
+        "rag_enabled": true,
+        "files": stats.files,
+        "chunks": stats.chunks,
+        "skipped": stats.skipped,
+    })))
+}
+
+/// DELETE /v1/projects/{project_id}/index
+///
+/// Turn retrieval off for the project and drop its index.
+#[utoipa::path(
+    delete, path = "/v1/projects/{project_id}/index", tag = "projects",
+    params(("project_id" = String, Path, description = "Project ID")),
+    responses(
+        (status = 200, description = "Deletion status", body = Object),
+        (status = 404, description = "Not found", body = ErrorResponse),
+    )
+)]
+pub async fn delete_project_index(
+    State(state): State<Arc<AppState>>,
+    Path(project_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !db::set_project_rag(&state.db, &project_id, false).await? {
+        return Err(AppError::NotFound(format!("Project {project_id} not found")));
+    }
+    let deleted = db::delete_project_chunks(&state.db, &project_id).await?;
+    Ok(Json(json!({
+        "project_id": project_id,
+        "rag_enabled": false,
+        "chunks_deleted": deleted,
+    })))
+}
+
+/// GET /v1/projects/{project_id}/agents
+#[utoipa::path(
+    get, path = "/v1/projects/{project_id}/agents", tag = "projects",
+    params(("project_id" = String, Path, description = "Project ID")),
+    responses((status = 200, description = "The project's subagents", body = Object))
+)]
+pub async fn list_agents(
+    State(state): State<Arc<AppState>>,
+    Path(project_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let agents = db::list_agents(&state.db, &project_id).await?;
+    Ok(Json(json!({ "object": "list", "data": agents })))
+}
+
+/// PUT /v1/projects/{project_id}/agents/{name}
+///
+/// Create or replace a subagent. It is written to the workspace's
+/// `.claude/agents/` before the project's next run.
+#[utoipa::path(
+    put, path = "/v1/projects/{project_id}/agents/{name}", tag = "projects",
+    params(("project_id" = String, Path, description = "Project ID"), ("name" = String, Path, description = "Agent name")),
+    request_body = PutAgentRequest,
+    responses((status = 200, description = "The agent", body = Object), (status = 400, description = "Invalid request", body = ErrorResponse))
+)]
+pub async fn put_agent(
+    State(state): State<Arc<AppState>>,
+    Path((project_id, name)): Path<(String, String)>,
+    AppJson(body): AppJson<PutAgentRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let invalid = |param: &str, message: &str| AppError::InvalidParam {
+        param: param.to_string(),
+        message: message.to_string(),
+    };
+    if !agents::valid_name(&name) {
+        return Err(invalid("name", "use lowercase letters, digits and hyphens"));
+    }
+    let description = body.description.trim();
+    if description.is_empty() {
+        return Err(invalid("description", "must not be empty"));
+    }
+    if body.prompt.trim().is_empty() {
+        return Err(invalid("prompt", "must not be empty"));
+    }
+    let model = body.model.as_deref().map(str::trim).filter(|m| !m.is_empty());
+    if model.is_some_and(|m| !agents::valid_identifier(m)) {
+        return Err(invalid("model", "use letters, digits, '_', '-', '.' and ':'"));
+    }
+    if body.tools.iter().flatten().any(|t| !agents::valid_identifier(t)) {
+        return Err(invalid("tools", "tool names may only contain letters, digits, '_', '-', '.' and ':'"));
+    }
+    let agent = db::put_agent(&state.db, &project_id, &name, description, &body.prompt, body.tools.as_deref(), model)
+        .await?;
+    tracing::info!(project_id = %project_id, agent = %name, "Agent saved");
+    Ok(Json(serde_json::to_value(agent).unwrap_or(json!({}))))
+}
+
+/// GET /v1/projects/{project_id}/agents/{name}
+#[utoipa::path(
+    get, path = "/v1/projects/{project_id}/agents/{name}", tag = "projects",
+    params(("project_id" = String, Path, description = "Project ID"), ("name" = String, Path, description = "Agent name")),
+    responses((status = 200, description = "The agent", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn get_agent(
+    State(state): State<Arc<AppState>>,
+    Path((project_id, name)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    match db::get_agent(&state.db, &project_id, &name).await? {
+        Some(agent) => Ok(Json(serde_json::to_value(agent).unwrap_or(json!({})))),
+        None => Err(AppError::NotFound(format!("Agent {name} not found in project {project_id}"))),
+    }
+}
+
+/// DELETE /v1/projects/{project_id}/agents/{name}
+///
+/// Delete a subagent and its file in the workspace.
+#[utoipa::path(
+    delete, path = "/v1/projects/{project_id}/agents/{name}", tag = "projects",
+    params(("project_id" = String, Path, description = "Project ID"), ("name" = String, Path, description = "Agent name")),
+    responses((status = 200, description = "Deletion status", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn delete_agent(
+    State(state): State<Arc<AppState>>,
+    Path((project_id, name)): Path<(String, String)>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !db::delete_agent(&state.db, &project_id, &name).await? {
+        return Err(AppError::NotFound(format!("Agent {name} not found in project {project_id}")));
+    }
+    let project_dir = create_project_directory(&state.config().project_root, &project_id)?;
+    agents::remove(&project_dir, &name).await;
+    Ok(Json(json!({
+        "project_id": project_id,
+        "name": name,
+        "status": "deleted",
+    })))
+}
+
+/// GET /v1/projects/{project_id}/hooks
+#[utoipa::path(
+    get, path = "/v1/projects/{project_id}/hooks", tag = "projects",
+    params(("project_id" = String, Path, description = "Project ID")),
+    responses((status = 200, description = "The project's hooks", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn get_hooks(
+    State(state): State<Arc<AppState>>,
+    Path(project_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    match db::get_hooks(&state.db, &project_id).await? {
+        Some(row) => Ok(Json(serde_json::to_value(row).unwrap_or(json!({})))),
+        None => Err(AppError::NotFound(format!("Project {project_id} has no hooks"))),
+    }
+}
+
+/// PUT /v1/projects/{project_id}/hooks
+///
+/// Replace the project's Claude Code hooks. Every command must be allowed
+/// by `HOOK_COMMAND_ALLOWLIST`; they are written to the workspace's
+/// `.claude/settings.json` before the project's next run.
+#[utoipa::path(
+    put, path = "/v1/projects/{project_id}/hooks", tag = "projects",
+    params(("project_id" = String, Path, description = "Project ID")),
+    request_body = PutHooksRequest,
+    responses((status = 200, description = "The project's hooks", body = Object), (status = 400, description = "Invalid or disallowed hooks", body = ErrorResponse))
+)]
+pub async fn put_hooks(
+    State(state): State<Arc<AppState>>,
+    Path(project_id): Path<String>,
+    AppJson(body): AppJson<PutHooksRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    hooks::validate(&body.hooks, &state.config().hook_command_allowlist)?;
+    let row = db::put_hooks(&state.db, &project_id, &json!(body.hooks)).await?;
+    tracing::info!(project_id = %project_id, events = body.hooks.len(), "Project hooks saved");
+    Ok(Json(serde_json::to_value(row).unwrap_or(json!({}))))
+}
+
+/// DELETE /v1/projects/{project_id}/hooks
+#[utoipa::path(
+    delete, path = "/v1/projects/{project_id}/hooks", tag = "projects",
+    params(("project_id" = String, Path, description = "Project ID")),
+    responses((status = 200, description = "Deletion status", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn delete_hooks(
+    State(state): State<Arc<AppState>>,
+    Path(project_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    if !db::delete_hooks(&state.db, &project_id).await? {
+        return Err(AppError::NotFound(format!("Project {project_id} has no hooks")));
+    }
+    Ok(Json(json!({
+        "project_id": project_id,
+        "status": "deleted",
+    })))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_project() {
+        let mut config = Config::for_test();
+        config.key_projects = vec![("sk-team".to_string(), vec!["alpha".to_string(), "beta".to_string()])];
+        config.organization_projects = vec![("org-1".to_string(), "beta".to_string())];
+        let headers = |pairs: &[(&'static str, &'static str)]| {
+            let mut map = HeaderMap::new();
+            for (k, v) in pairs {
+                map.insert(*k, v.parse().unwrap());
+            }
+            map
+        };
+        let resolve = |h: &HeaderMap, key: Option<&str>, requested: Option<&str>| {
+            resolve_project(&config, h, key, requested.map(str::to_string))
+        };
+
+        let none = headers(&[]);
+        assert_eq!(resolve(&none, None, None).unwrap(), None);
+        assert_eq!(resolve(&none, Some("sk-team"), None).unwrap().as_deref(), Some("alpha"));
+        let project = headers(&[("openai-project", "beta")]);
+        assert_eq!(resolve(&project, Some("sk-team"), None).unwrap().as_deref(), Some("beta"));
+        // The body field wins over the header
+        assert_eq!(resolve(&project, None, Some("gamma")).unwrap().as_deref(), Some("gamma"));
+        assert!(matches!(resolve(&project, Some("sk-team"), Some("gamma")), Err(AppError::Forbidden(_))));
+        // Organizations only select mapped projects
+        assert_eq!(resolve(&headers(&[("openai-organization", "org-1")]), None, None).unwrap().as_deref(), Some("beta"));
+        assert_eq!(resolve(&headers(&[("openai-organization", "org-2")]), None, None).unwrap(), None);
+    }
+}
@@ -0,0 +1,183 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::Json;
+use serde::Deserialize;
+use serde_json::json;
+use utoipa::IntoParams;
+
+use crate::db::{self, PromptRow};
+use crate::error::{AppError, ErrorResponse};
+use crate::extract::AppJson;
+use crate::models::openai::{ChatCompletionRequest, CreatePromptRequest, CreatePromptVersionRequest};
+use crate::prompt::{render_template, template_variables};
+use crate::state::AppState;
+
+/// The API representation of a stored prompt version.
+fn prompt_object(row: &PromptRow) -> serde_json::Value {
+    json!({
+        "id": row.id,
+        "object": "prompt",
+        "version": row.version,
+        "name": row.name,
+        "description": row.description,
+        "template": row.template,
+        "variables": template_variables(&row.template),
+        "created_at": row.created_at,
+    })
+}
+
+async fn find_prompt(state: &AppState, prompt_id: &str, version: Option<i64>) -> Result<PromptRow, AppError> {
+    db::get_prompt(&state.db, prompt_id, version).await?.ok_or_else(|| {
+        AppError::NotFound(match version {
+            Some(v) => format!("Prompt {prompt_id} version {v} not found"),
+            None => format!("Prompt {prompt_id} not found"),
+        })
+    })
+}
+
+/// Render the request's `prompt_id` into its `system_prompt`. The prompt
+/// reference is consumed, so calling this again is a no-op.
+pub async fn apply_prompt(state: &AppState, request: &mut ChatCompletionRequest) -> Result<(), AppError> {
+    let Some(prompt_id) = request.prompt_id.take() else {
+        return Ok(());
+    };
+    let row = db::get_prompt(&state.db, &prompt_id, request.prompt_version)
+        .await?
+        .ok_or_else(|| AppError::InvalidParam {
+            param: "prompt_id".to_string(),
+            message: match request.prompt_version {
+                Some(v) => format!("prompt '{prompt_id}' has no version {v}"),
+                None => format!("prompt '{prompt_id}' does not exist"),
+            },
+        })?;
+    let variables = request.variables.take().unwrap_or_default();
+    let rendered = render_template(&row.template, &variables).map_err(|missing| AppError::InvalidParam {
+        param: "variables".to_string(),
+        message: format!("missing values for {}", missing.join(", ")),
+    })?;
+    tracing::debug!(prompt_id = %row.id, version = row.version, "Applied stored prompt");
+    request.system_prompt = Some(rendered);
+    Ok(())
+}
+
+/// GET /v1/prompts — the latest version of each prompt.
+#[utoipa::path(
+    get, path = "/v1/prompts", tag = "prompts",
+    responses((status = 200, description = "Prompt objects", body = Object))
+)]
+pub async fn list_prompts(
+    State(state): State<Arc<AppState>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let prompts = db::list_prompts(&state.db).await?;
+    Ok(Json(json!({
+        "object": "list",
+        "data": prompts.iter().map(prompt_object).collect::<Vec<_>>(),
+    })))
+}
+
+/// POST /v1/prompts
+#[utoipa::path(
+    post, path = "/v1/prompts", tag = "prompts",
+    request_body = CreatePromptRequest,
+    responses((status = 200, description = "The prompt object", body = Object), (status = 400, description = "Invalid request", body = ErrorResponse))
+)]
+pub async fn create_prompt(
+    State(state): State<Arc<AppState>>,
+    AppJson(body): AppJson<CreatePromptRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let name = body.name.trim();
+    if name.is_empty() {
+        return Err(AppError::InvalidParam { param: "name".to_string(), message: "must not be empty".to_string() });
+    }
+    if db::get_prompt(&state.db, name, None).await?.is_some() {
+        return Err(AppError::InvalidParam {
+            param: "name".to_string(),
+            message: format!("a prompt named '{name}' already exists"),
+        });
+    }
+    let id = format!("prompt-{}", uuid::Uuid::new_v4().as_simple());
+    let desc = body.description.as_deref().unwrap_or("");
+    let row = db::create_prompt(&state.db, &id, name, desc, &body.template).await?;
+    tracing::info!(prompt_id = %row.id, name = %row.name, "Prompt created");
+    Ok(Json(prompt_object(&row)))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PromptQuery {
+    /// Version to return; the latest when omitted.
+    #[serde(default)]
+    pub version: Option<i64>,
+}
+
+/// GET /v1/prompts/{prompt_id} — by ID or name.
+#[utoipa::path(
+    get, path = "/v1/prompts/{prompt_id}", tag = "prompts",
+    params(("prompt_id" = String, Path, description = "Prompt ID or name"), PromptQuery),
+    responses((status = 200, description = "The prompt object", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn get_prompt(
+    State(state): State<Arc<AppState>>,
+    Path(prompt_id): Path<String>,
+    Query(q): Query<PromptQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    Ok(Json(prompt_object(&find_prompt(&state, &prompt_id, q.version).await?)))
+}
+
+/// DELETE /v1/prompts/{prompt_id} — removes every version.
+#[utoipa::path(
+    delete, path = "/v1/prompts/{prompt_id}", tag = "prompts",
+    params(("prompt_id" = String, Path, description = "Prompt ID or name")),
+    responses((status = 200, description = "Deletion status", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn delete_prompt(
+    State(state): State<Arc<AppState>>,
+    Path(prompt_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let row = find_prompt(&state, &prompt_id, None).await?;
+    db::delete_prompt(&state.db, &row.id).await?;
+    Ok(Json(json!({
+        "id": row.id,
+        "object": "prompt",
+        "deleted": true,
+    })))
+}
+
+/// GET /v1/prompts/{prompt_id}/versions — newest first.
+#[utoipa::path(
+    get, path = "/v1/prompts/{prompt_id}/versions", tag = "prompts",
+    params(("prompt_id" = String, Path, description = "Prompt ID or name")),
+    responses((status = 200, description = "Prompt objects", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn list_prompt_versions(
+    State(state): State<Arc<AppState>>,
+    Path(prompt_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let row = find_prompt(&state, &prompt_id, None).await?;
+    let versions = db::list_prompt_versions(&state.db, &row.id).await?;
+    Ok(Json(json!({
+        "object": "list",
+        "data": versions.iter().map(prompt_object).collect::<Vec<_>>(),
+    })))
+}
+
+/// POST /v1/prompts/{prompt_id}/versions — publish a new version.
+#[utoipa::path(
+    post, path = "/v1/prompts/{prompt_id}/versions", tag = "prompts",
+    params(("prompt_id" = String, Path, description = "Prompt ID or name")),
+    request_body = CreatePromptVersionRequest,
+    responses((status = 200, description = "The new prompt version", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn create_prompt_version(
+    State(state): State<Arc<AppState>>,
+    Path(prompt_id): Path<String>,
+    AppJson(body): AppJson<CreatePromptVersionRequest>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let current = find_prompt(&state, &prompt_id, None).await?;
+    let row = db::add_prompt_version(&state.db, &current.id, body.description.as_deref(), &body.template)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Prompt {prompt_id} not found")))?;
+    tracing::info!(prompt_id = %row.id, version = row.version, "Prompt version created");
+    Ok(Json(prompt_object(&row)))
+}
@@ -0,0 +1,37 @@
+use std::sync::Arc;
+
+use axum::extract::State;
+use axum::{Extension, Json};
+
+use crate::auth::ApiKey;
+use crate::error::{AppError, ErrorResponse};
+use crate::quota;
+use crate::state::AppState;
+
+/// GET /v1/quota — the calling key's token allowance for this month.
+#[utoipa::path(
+    get, path = "/v1/quota", tag = "quota",
+    responses(
+        (status = 200, description = "The key's quota; `limit_tokens` is null when it has none", body = Object),
+        (status = 401, description = "No API key", body = ErrorResponse),
+    )
+)]
+pub async fn get_quota(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<ApiKey>>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let Some(Extension(ApiKey(key))) = api_key else {
+        return Err(AppError::Unauthorized("Quotas are per API key; send one".to_string()));
+    };
+    Ok(Json(match quota::key_quota(&state, &key).await? {
+        Some(quota) => quota.to_json(),
+        None => serde_json::json!({
+            "object": "quota",
+            "period": "month",
+            "limit_tokens": null,
+            "used_tokens": null,
+            "remaining_tokens": null,
+            "resets_at": null,
+        }),
+    }))
+}
@@ -1,3 +1,4 @@
+use std::collections::BTreeMap;
 use std::sync::Arc;
 
 use axum::extract::State;
@@ -6,8 +7,14 @@ use axum::response::IntoResponse;
 use axum::Json;
 use serde_json::json;
 
+use crate::claude::auth::{self, AuthFailure};
+use crate::claude::version::CliVersion;
 use crate::state::AppState;
 
+#[utoipa::path(
+    get, path = "/", tag = "meta",
+    responses((status = 200, description = "Gateway name, version and endpoints", body = Object))
+)]
 pub async fn root() -> Json<serde_json::Value> {
     Json(json!({
         "name": "Claude Code API Gateway",
@@ -21,20 +28,52 @@ pub async fn root() -> Json<serde_json::Value> {
             "sessions": "/v1/sessions",
         },
         "docs": "/docs",
+        "openapi": "/openapi.json",
         "health": "/health",
     }))
 }
 
+/// GET /health
+///
+/// `degraded`, with `claude_auth`, while a profile's CLI is not logged in.
+/// `plan_limits` lists the subscription usage windows the CLI reported.
+#[utoipa::path(
+    get, path = "/health", tag = "meta",
+    responses(
+        (status = 200, description = "The Claude CLI is reachable", body = Object),
+        (status = 503, description = "The Claude CLI is missing or broken", body = Object),
+    )
+)]
 pub async fn health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
-    match get_claude_version(&state.config.claude_binary_path).await {
-        Ok(version) => Json(json!({
-            "status": "healthy",
-            "version": "1.0.0",
-            "backend": "rust-axum",
-            "claude_version": version,
-            "active_sessions": 0,
-        }))
-        .into_response(),
+    match get_claude_version(&state.config().default_profile().binary_path).await {
+        Ok(version) => {
+            let mut body = json!({
+                "status": "healthy",
+                "version": "1.0.0",
+                "backend": "rust-axum",
+                "claude_version": version,
+                "active_sessions": 0,
+            });
+            let logged_out = auth::failures(&state);
+            if !logged_out.is_empty() {
+                body["status"] = json!("degraded");
+                body["claude_auth"] = auth_error(&logged_out);
+            }
+            let plan_limits = state.plan_limits.windows();
+            if !plan_limits.is_empty() {
+                body["plan_limits"] = json!(plan_limits);
+            }
+            // Spawn flags were chosen for the version seen at startup
+            if let Some(ref detected) = state.cli_version {
+                if CliVersion::parse(&version).as_ref() != Some(detected) {
+                    body["warning"] = json!(format!(
+                        "Claude CLI changed since startup (was {}); restart to re-detect flags",
+                        detected.raw
+                    ));
+                }
+            }
+            Json(body).into_response()
+        }
         Err(e) => {
             tracing::error!(error = %e, "Health check failed");
             (
@@ -49,7 +88,37 @@ pub async fn health(State(state): State<Arc<AppState>>) -> impl IntoResponse {
     }
 }
 
-async fn get_claude_version(binary: &str) -> Result<String, std::io::Error> {
+/// GET /readyz
+///
+/// Whether to route requests here: not while a profile's CLI is logged
+/// out, as every run of it would fail.
+#[utoipa::path(
+    get, path = "/readyz", tag = "meta",
+    responses(
+        (status = 200, description = "Ready to serve", body = Object),
+        (status = 503, description = "A profile's CLI is not logged in", body = Object),
+    )
+)]
+pub async fn readyz(State(state): State<Arc<AppState>>) -> impl IntoResponse {
+    let logged_out = auth::failures(&state);
+    if logged_out.is_empty() {
+        return Json(json!({ "status": "ready" })).into_response();
+    }
+    let body = json!({ "status": "not_ready", "error": auth_error(&logged_out) });
+    (StatusCode::SERVICE_UNAVAILABLE, Json(body)).into_response()
+}
+
+/// The `claude_auth_error` detail for the profiles that are logged out.
+fn auth_error(logged_out: &BTreeMap<String, AuthFailure>) -> serde_json::Value {
+    json!({
+        "code": "claude_auth_error",
+        "message": format!("The Claude CLI is not logged in. {}.", auth::GUIDANCE),
+        "profiles": logged_out,
+    })
+}
+
+/// Run `<binary> --version` and return its trimmed output.
+pub async fn get_claude_version(binary: &str) -> Result<String, std::io::Error> {
     let output = tokio::process::Command::new(binary)
         .arg("--version")
         .output()
@@ -58,12 +127,9 @@ async fn get_claude_version(binary: &str) -> Result<String, std::io::Error> {
     if output.status.success() {
         Ok(String::from_utf8_lossy(&output.stdout).trim().to_string())
     } else {
-        Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            format!(
-                "Claude version check failed: {}",
-                String::from_utf8_lossy(&output.stderr).trim()
-            ),
-        ))
+        Err(std::io::Error::other(format!(
+            "Claude version check failed: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
     }
 }
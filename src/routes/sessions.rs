@@ -1,14 +1,77 @@
 use std::sync::Arc;
+use std::time::Duration;
 
-use axum::extract::{Path, State};
-use axum::Json;
+use axum::body::Body;
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use futures::StreamExt;
 use serde_json::json;
 
+use crate::agents;
+use crate::budget;
+use crate::auth::ApiKey;
+use crate::claude::auth;
+use crate::claude::env::build_env;
+use crate::claude::manager::create_project_directory;
+use crate::claude::parser::{
+    auth_error, extract_assistant_content, extract_usage, is_assistant_message, is_result_message,
+    upstream_limit, UsageInfo,
+};
+use crate::claude::process::SpawnOptions;
+use crate::compaction;
 use crate::db;
-use crate::error::AppError;
-use crate::models::openai::CreateSessionRequest;
+use crate::erasure;
+use crate::hooks;
+use crate::quota;
+use crate::error::{AppError, ErrorResponse};
+use crate::extract::AppJson;
+use crate::models::openai::{
+    ChatCompletionRequest, ChatMessage, CreateSessionRequest, SessionCommandRequest,
+};
+use crate::priority::{self, Priority};
+use crate::replay;
+use crate::routes::chat;
+use crate::routes::projects::resolve_project;
 use crate::state::AppState;
+use crate::streaming;
+use crate::tiers;
+use crate::tokens;
 
+/// Fill in the model and system prompt of a request continuing a stored
+/// session from the session row, where the request doesn't set them. The
+/// system prompt applies only if the request has no system message.
+pub async fn apply_session_defaults(
+    state: &AppState,
+    request: &mut ChatCompletionRequest,
+) -> Result<(), AppError> {
+    let Some(ref session_id) = request.session_id else {
+        return Ok(());
+    };
+    let Some(session) = db::get_session(&state.db, session_id).await? else {
+        return Ok(());
+    };
+    if request.model.trim().is_empty() {
+        request.model = session.model;
+    }
+    let has_system = request.messages.iter().any(|m| m.role == "system");
+    if !has_system
+        && request.system_prompt.is_none()
+        && request.prompt_id.is_none()
+        && !session.system_prompt.is_empty()
+    {
+        request.system_prompt = Some(session.system_prompt);
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    get, path = "/v1/sessions", tag = "sessions",
+    responses((status = 200, description = "Sessions", body = Object))
+)]
 pub async fn list_sessions(
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<serde_json::Value>, AppError> {
@@ -19,15 +82,21 @@ pub async fn list_sessions(
     })))
 }
 
+#[utoipa::path(
+    post, path = "/v1/sessions", tag = "sessions",
+    request_body = CreateSessionRequest,
+    responses((status = 200, description = "The session", body = Object), (status = 400, description = "Invalid request", body = ErrorResponse))
+)]
 pub async fn create_session(
     State(state): State<Arc<AppState>>,
-    Json(body): Json<CreateSessionRequest>,
+    AppJson(body): AppJson<CreateSessionRequest>,
 ) -> Result<Json<serde_json::Value>, AppError> {
+    let config = state.config();
     let id = uuid::Uuid::new_v4().to_string();
     let model = body
         .model
         .as_deref()
-        .unwrap_or(&state.config.default_model);
+        .unwrap_or(&config.default_model);
     let session = db::create_session(
         &state.db,
         &id,
@@ -37,9 +106,18 @@ pub async fn create_session(
         body.title.as_deref(),
     )
     .await?;
+    state.webhooks.emit(
+        "session.created",
+        json!({"session_id": session.id, "project_id": body.project_id, "model": model, "source": "api"}),
+    );
     Ok(Json(serde_json::to_value(session).unwrap_or(json!({}))))
 }
 
+#[utoipa::path(
+    get, path = "/v1/sessions/{session_id}", tag = "sessions",
+    params(("session_id" = String, Path, description = "Session ID")),
+    responses((status = 200, description = "The session", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
 pub async fn get_session(
     State(state): State<Arc<AppState>>,
     Path(session_id): Path<String>,
@@ -52,10 +130,543 @@ pub async fn get_session(
     }
 }
 
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MessagesQuery {
+    #[serde(default)]
+    pub limit: Option<i64>,
+    #[serde(default)]
+    pub offset: Option<i64>,
+}
+
+/// GET /v1/sessions/{session_id}/messages
+#[utoipa::path(
+    get, path = "/v1/sessions/{session_id}/messages", tag = "sessions",
+    params(("session_id" = String, Path, description = "Session ID"), MessagesQuery),
+    responses((status = 200, description = "Stored messages, oldest first", body = Object))
+)]
+pub async fn list_messages(
+    State(state): State<Arc<AppState>>,
+    Path(session_id): Path<String>,
+    Query(q): Query<MessagesQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let limit = q.limit.unwrap_or(100).clamp(1, 1000);
+    let offset = q.offset.unwrap_or(0).max(0);
+    let messages = db::list_messages(&state.db, &session_id, limit, offset).await?;
+    Ok(Json(json!({
+        "session_id": session_id,
+        "data": messages,
+        "pagination": { "count": messages.len(), "limit": limit, "offset": offset },
+    })))
+}
+
+/// GET /v1/sessions/{session_id}/transcript — raw CLI events as JSONL.
+#[utoipa::path(
+    get, path = "/v1/sessions/{session_id}/transcript", tag = "sessions",
+    params(("session_id" = String, Path, description = "Session ID")),
+    responses((status = 200, description = "CLI events, one JSON object per line", content_type = "application/x-ndjson"), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn get_transcript(
+    State(state): State<Arc<AppState>>,
+    Path(session_id): Path<String>,
+) -> Result<Response, AppError> {
+    let lines = db::get_transcript(&state.db, &session_id).await?;
+    if lines.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "No transcript for session {session_id}"
+        )));
+    }
+    let mut body = lines.join("\n");
+    body.push('\n');
+    Ok(([(header::CONTENT_TYPE, "application/x-ndjson")], body).into_response())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReplayQuery {
+    /// Delay between chunks; content is streamed word by word when set.
+    #[serde(default)]
+    pub pace_ms: Option<u64>,
+}
+
+/// GET /v1/sessions/{session_id}/replay
+///
+/// Stream the stored conversation back as SSE `chat.completion.chunk`
+/// events, ending with `[DONE]` like a live completion.
+#[utoipa::path(
+    get, path = "/v1/sessions/{session_id}/replay", tag = "sessions",
+    params(("session_id" = String, Path, description = "Session ID"), ReplayQuery),
+    responses((status = 200, description = "`chat.completion.chunk` SSE events", content_type = "text/event-stream"), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn replay_session(
+    State(state): State<Arc<AppState>>,
+    Path(session_id): Path<String>,
+    Query(q): Query<ReplayQuery>,
+) -> Result<Response, AppError> {
+    let messages = all_messages(&state, &session_id).await?;
+    if messages.is_empty() {
+        return Err(AppError::NotFound(format!(
+            "No messages for session {session_id}"
+        )));
+    }
+
+    let pace = q.pace_ms.filter(|&ms| ms > 0).map(Duration::from_millis);
+    let mut events: Vec<String> = replay::replay_chunks(&messages, pace.is_some())
+        .iter()
+        .map(streaming::sse_event)
+        .collect();
+    events.push(streaming::sse_done());
+
+    let body_stream = futures::stream::iter(events.into_iter().enumerate()).then(
+        move |(i, event)| async move {
+            if let (Some(pace), true) = (pace, i > 0) {
+                tokio::time::sleep(pace).await;
+            }
+            Ok::<_, std::io::Error>(event)
+        },
+    );
+
+    Ok(Response::builder()
+        .status(200)
+        .header("Content-Type", "text/event-stream")
+        .header("Cache-Control", "no-cache")
+        .header("Connection", "keep-alive")
+        .body(Body::from_stream(body_stream))
+        .unwrap())
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CompactQuery {
+    /// Most recent messages kept verbatim (default `HISTORY_KEEP_RECENT`).
+    #[serde(default)]
+    pub keep_recent: Option<usize>,
+}
+
+/// POST /v1/sessions/{session_id}/compact
+///
+/// Fold the session's stored history, except its most recent messages,
+/// into one `summary` message written by `SUMMARY_MODEL`. Completions
+/// replay this stored history, so they see the summary from the next turn.
+/// Slash commands (`/commands`) resume the CLI's own copy of the
+/// conversation with `--resume`, which this leaves as it is: compacting
+/// that copy (the CLI's `/compact`, run as a command) is out of scope here.
+#[utoipa::path(
+    post, path = "/v1/sessions/{session_id}/compact", tag = "sessions",
+    params(("session_id" = String, Path, description = "Session ID"), CompactQuery),
+    responses((status = 200, description = "Token counts before and after", body = Object), (status = 400, description = "Invalid request", body = ErrorResponse), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn compact_session(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<ApiKey>>,
+    Path(session_id): Path<String>,
+    Query(q): Query<CompactQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let session = db::get_session(&state.db, &session_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Session {session_id} not found")))?;
+    let rows = all_messages(&state, &session_id).await?;
+    let messages: Vec<ChatMessage> = rows.iter().map(stored_message).collect();
+    let project_id = session.project_id.as_deref().unwrap_or("default");
+    let templates = state.conversation_templates();
+    let tokens_before = tokens::estimate_tokens(&templates.prompt(project_id, &messages));
+
+    let keep_recent = q.keep_recent.unwrap_or(state.config().history_keep_recent);
+    let Some(range) = compaction::summary_range(&messages, keep_recent) else {
+        return Ok(Json(json!({
+            "session_id": session_id,
+            "compacted": false,
+            "messages_compacted": 0,
+            "tokens_before": tokens_before,
+            "tokens_after": tokens_before,
+        })));
+    };
+
+    let api_key = api_key.map(|Extension(ApiKey(key))| key);
+    let (summary, info) =
+        compaction::summarize_range(&state, &messages, range.clone(), api_key.as_deref(), project_id)
+            .await?;
+    let ids: Vec<i64> = rows[range.clone()].iter().map(|row| row.id).collect();
+    db::replace_with_summary(
+        &state.db,
+        &session_id,
+        &ids,
+        &summary,
+        info.cost_usd,
+        &json!({ "model": info.model, "compaction": info }),
+    )
+    .await?;
+    // The summary replaced the first message's content and embedding
+    state.message_index.rewind(ids[0]);
+
+    let compacted = compaction::splice_summary(&messages, range, summary);
+    Ok(Json(json!({
+        "session_id": session_id,
+        "compacted": true,
+        "messages_compacted": info.messages,
+        "tokens_before": tokens_before,
+        "tokens_after": tokens::estimate_tokens(&templates.prompt(project_id, &compacted)),
+        "summary_model": info.model,
+        "cost_usd": info.cost_usd,
+    })))
+}
+
+/// POST /v1/sessions/{session_id}/commands
+///
+/// Run a slash command in the session's CLI conversation, resumed with
+/// `--resume`: built-in ones like `/review` and the project's own from
+/// `.claude/commands`. With `stream` the output comes as
+/// `chat.completion.chunk` SSE events. The command and its output are
+/// stored as messages of the session.
+#[utoipa::path(
+    post, path = "/v1/sessions/{session_id}/commands", tag = "sessions",
+    params(("session_id" = String, Path, description = "Session ID")),
+    request_body = SessionCommandRequest,
+    responses(
+        (status = 200, description = "Command output, or `chat.completion.chunk` SSE events with `stream`", body = Object),
+        (status = 400, description = "Invalid command", body = ErrorResponse),
+        (status = 404, description = "Not found", body = ErrorResponse),
+        (status = 429, description = "Anthropic rate or usage limit", body = ErrorResponse),
+    )
+)]
+pub async fn run_command(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<ApiKey>>,
+    headers: HeaderMap,
+    Path(session_id): Path<String>,
+    AppJson(body): AppJson<SessionCommandRequest>,
+) -> Result<Response, AppError> {
+    let session = db::get_session(&state.db, &session_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Session {session_id} not found")))?;
+    let prompt = command_prompt(&body.command, body.arguments.as_deref())?;
+    budget::check(&state).await?;
+
+    let config = state.config();
+    let api_key = api_key.map(|Extension(ApiKey(key))| key);
+    quota::check_key(&state, api_key.as_deref()).await?;
+    let priority = priority::from_headers(&config, api_key.as_deref(), &headers, Priority::Normal)?;
+    let project_id = session.project_id.clone().unwrap_or_else(|| "default".to_string());
+    let project_id = resolve_project(&config, &headers, api_key.as_deref(), Some(project_id))?
+        .unwrap_or_default();
+    let requested = body.model.filter(|m| !m.trim().is_empty()).unwrap_or(session.model);
+    let model = config.model_catalog.resolve(&requested);
+    tiers::enforce_run(config.key_tier(api_key.as_deref()), "Slash commands", &requested, &model)?;
+    let mut profile = config
+        .select_profile(None, &[&requested, &model])
+        .cloned()
+        .unwrap_or_else(|| config.default_profile().clone());
+    if profile.backend != "claude" {
+        return Err(AppError::BadRequest(format!(
+            "Slash commands need the Claude CLI; {requested} is served by {}",
+            profile.backend
+        )));
+    }
+    if let Some(dir) = config.account_config_dir(api_key.as_deref(), &project_id) {
+        profile.config_dir = Some(dir.to_path_buf());
+    }
+
+    // Sessions started by a completion are stored under the CLI's own ID
+    let resume = db::last_claude_session_id(&state.db, &session_id)
+        .await?
+        .unwrap_or_else(|| session_id.clone());
+    let project_dir = create_project_directory(&config.project_root, &project_id)?;
+    agents::materialize(&state, &project_id, &project_dir).await?;
+    hooks::materialize(&state, &project_id, &project_dir).await?;
+    let run_id = uuid::Uuid::new_v4().to_string();
+    let (mut stream, claude_sid) = state
+        .claude_manager
+        .create_session(
+            &run_id,
+            &profile,
+            SpawnOptions {
+                prompt: &prompt,
+                model: &model,
+                system_prompt: None,
+                append_system_prompt: None,
+                disable_builtin_tools: false,
+                env: build_env(&config, &profile, Some(&project_id)),
+                project_dir: &project_dir,
+                sandbox: state.sandbox.as_ref(),
+                limits: state.limits.as_ref(),
+                resume: Some(&resume),
+                plan: false,
+                priority,
+            },
+        )
+        .await?;
+    let run = CommandRun {
+        state: Arc::clone(&state),
+        session_id,
+        claude_session_id: claude_sid.unwrap_or(run_id),
+        prompt,
+        model,
+        profile: profile.name.clone(),
+        api_key,
+    };
+
+    if body.stream.unwrap_or(false) {
+        let (tx, rx) = tokio::sync::mpsc::channel::<String>(64);
+        let completion_id = format!("chatcmpl-{}", &uuid::Uuid::new_v4().as_simple().to_string()[..29]);
+        state.claude_manager.track_completion(&run.claude_session_id, &completion_id).await;
+        let created = chrono::Utc::now().timestamp();
+        let mut chunker = streaming::Chunker::new(streaming::ChunkPolicy::from_config(&state.config()));
+        tokio::spawn(async move {
+            let chunk = |content: &str| {
+                streaming::sse_event(&streaming::content_chunk(&completion_id, &run.model, created, content))
+            };
+            let _ = tx
+                .send(streaming::sse_event(&streaming::initial_chunk(&completion_id, &run.model, created)))
+                .await;
+            let mut output = String::new();
+            let mut reported = None;
+            let mut limited = None;
+            let mut auth_failed = None;
+            let mut finished = false;
+            loop {
+                let next = match chunker.due_in() {
+                    Some(wait) => tokio::select! {
+                        msg = stream.next() => msg,
+                        _ = tokio::time::sleep(wait) => {
+                            for text in chunker.tick() {
+                                let _ = tx.send(chunk(&text)).await;
+                            }
+                            continue;
+                        }
+                    },
+                    None => stream.next().await,
+                };
+                let Some(msg) = next else { break };
+                let limit = upstream_limit(&msg);
+                let auth = auth_error(&msg);
+                let is_error_text = (limit.is_some() || auth.is_some()) && is_assistant_message(&msg);
+                limited = limited.or(limit);
+                auth_failed = auth_failed.or(auth);
+                if is_assistant_message(&msg) && !is_error_text {
+                    if let Some(text) = extract_assistant_content(&msg) {
+                        for delta in chunker.push(&text) {
+                            let _ = tx.send(chunk(&delta)).await;
+                        }
+                        output.push_str(&text);
+                    }
+                }
+                if is_result_message(&msg) {
+                    reported = extract_usage(&msg);
+                    finished = true;
+                    break;
+                }
+            }
+            for delta in chunker.flush() {
+                let _ = tx.send(chunk(&delta)).await;
+            }
+            let report = match finished {
+                true => None,
+                false => run.state.claude_manager.session_finished(&run.claude_session_id).await,
+            };
+            let last_event = match (auth_failed, limited) {
+                (Some(message), _) => {
+                    auth::failed(&run.state, &run.profile, &message);
+                    json!(AppError::ClaudeAuth(message).parts().1)
+                }
+                (None, Some(limit)) => {
+                    run.state.metrics.record_upstream_limit(limit.kind);
+                    json!(AppError::UpstreamLimited(limit).parts().1)
+                }
+                (None, None) if !finished => {
+                    let final_chunk = streaming::final_chunk(&completion_id, &run.model, created, "error");
+                    let _ = tx.send(streaming::sse_event(&final_chunk)).await;
+                    json!(chat::exited_early(report.as_ref()).parts().1)
+                }
+                (None, None) => streaming::final_chunk(&completion_id, &run.model, created, "stop"),
+            };
+            let _ = tx.send(streaming::sse_event(&last_event)).await;
+            let _ = tx.send(streaming::sse_done()).await;
+            run.finish(&output, reported).await;
+        });
+
+        let body_stream = tokio_stream::wrappers::ReceiverStream::new(rx).map(Ok::<_, std::io::Error>);
+        return Ok(Response::builder()
+            .status(200)
+            .header("Content-Type", "text/event-stream")
+            .header("Cache-Control", "no-cache")
+            .header("Connection", "keep-alive")
+            .body(Body::from_stream(body_stream))
+            .unwrap());
+    }
+
+    let mut parts = Vec::new();
+    let mut reported = None;
+    let mut limited = None;
+    let mut auth_failed = None;
+    while let Some(msg) = stream.next().await {
+        limited = limited.or_else(|| upstream_limit(&msg));
+        auth_failed = auth_failed.or_else(|| auth_error(&msg));
+        if is_assistant_message(&msg) {
+            if let Some(text) = extract_assistant_content(&msg) {
+                parts.push(text);
+            }
+        }
+        if is_result_message(&msg) {
+            reported = extract_usage(&msg);
+            break;
+        }
+    }
+    if let Some(message) = auth_failed {
+        state.claude_manager.session_finished(&run.claude_session_id).await;
+        auth::failed(&state, &run.profile, &message);
+        return Err(AppError::ClaudeAuth(message));
+    }
+    if let Some(limit) = limited {
+        state.claude_manager.session_finished(&run.claude_session_id).await;
+        state.metrics.record_upstream_limit(limit.kind);
+        return Err(AppError::UpstreamLimited(limit));
+    }
+    let output = parts.join("\n");
+    let usage = run.finish(&output, reported).await;
+    Ok(Json(json!({
+        "session_id": run.session_id,
+        "command": body.command,
+        "content": output,
+        "model": run.model,
+        "usage": {
+            "prompt_tokens": usage.input_tokens,
+            "completion_tokens": usage.output_tokens,
+            "total_tokens": usage.input_tokens + usage.output_tokens,
+        },
+        "cost_usd": usage.cost_usd,
+    }))
+    .into_response())
+}
+
+/// The prompt that runs `command`: the CLI expands a prompt starting with
+/// `/name` into the command. Names are limited to what command files and
+/// built-ins use, so a request can't smuggle a second line of instructions
+/// in as the command.
+fn command_prompt(command: &str, arguments: Option<&str>) -> Result<String, AppError> {
+    let command = command.trim();
+    let valid = command.strip_prefix('/').is_some_and(|name| {
+        name.starts_with(|c: char| c.is_ascii_alphanumeric())
+            && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '_' | '-' | ':' | '.'))
+    });
+    if !valid {
+        return Err(AppError::InvalidParam {
+            param: "command".to_string(),
+            message: format!("{command:?} is not a slash command like /review"),
+        });
+    }
+    Ok(match arguments.map(str::trim).filter(|a| !a.is_empty()) {
+        Some(arguments) => format!("{command} {arguments}"),
+        None => command.to_string(),
+    })
+}
+
+/// A slash command running in a resumed CLI session.
+struct CommandRun {
+    state: Arc<AppState>,
+    session_id: String,
+    /// The CLI session of this run, resumed by the next command.
+    claude_session_id: String,
+    prompt: String,
+    model: String,
+    profile: String,
+    api_key: Option<String>,
+}
+
+impl CommandRun {
+    /// Reap the process, cost the run and store the command and its output.
+    async fn finish(&self, output: &str, reported: Option<UsageInfo>) -> UsageInfo {
+        let state = &self.state;
+        state.claude_manager.session_finished(&self.claude_session_id).await;
+        let (mut usage, _) = tokens::fill_usage(reported, &self.prompt, output);
+        state.pricing().fill_cost(&self.model, &mut usage);
+        budget::record(state, usage.cost_usd).await;
+        quota::record_tokens(state, self.api_key.as_deref(), usage.input_tokens + usage.output_tokens).await;
+
+        let metadata = json!({
+            "model": self.model,
+            "command": true,
+            "claude_session_id": self.claude_session_id,
+        });
+        let _ = db::add_message(&state.db, &self.session_id, "user", &self.prompt, 0, 0, 0.0, &metadata).await;
+        let _ = db::add_message(
+            &state.db,
+            &self.session_id,
+            "assistant",
+            output,
+            usage.input_tokens as i64,
+            usage.output_tokens as i64,
+            usage.cost_usd,
+            &metadata,
+        )
+        .await;
+        let _ = db::update_session_metrics(
+            &state.db,
+            &self.session_id,
+            (usage.input_tokens + usage.output_tokens) as i64,
+            usage.cost_usd,
+        )
+        .await;
+        usage
+    }
+}
+
+/// A stored message as a chat message, with the tool calls and tool result
+/// details kept in its metadata.
+fn stored_message(row: &db::MessageRow) -> ChatMessage {
+    let field = |key: &str| row.message_metadata.as_ref().and_then(|m| m.get(key)).filter(|v| !v.is_null()).cloned();
+    let text = |key: &str| field(key).and_then(|v| v.as_str().map(str::to_string));
+    ChatMessage {
+        role: row.role.clone(),
+        content: Some(json!(row.content)),
+        name: text("name").filter(|_| row.role == "tool"),
+        tool_calls: field("tool_calls").and_then(|v| serde_json::from_value(v).ok()),
+        tool_call_id: text("tool_call_id"),
+    }
+}
+
+/// Every stored message of a session, oldest first.
+async fn all_messages(state: &AppState, session_id: &str) -> Result<Vec<db::MessageRow>, AppError> {
+    const PAGE: i64 = 1000;
+    let mut messages = Vec::new();
+    loop {
+        let page = db::list_messages(&state.db, session_id, PAGE, messages.len() as i64).await?;
+        let done = (page.len() as i64) < PAGE;
+        messages.extend(page);
+        if done {
+            return Ok(messages);
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DeleteSessionQuery {
+    /// Erase the conversation instead of hiding it, see [`crate::erasure`].
+    #[serde(default)]
+    pub hard: bool,
+}
+
+#[utoipa::path(
+    delete, path = "/v1/sessions/{session_id}", tag = "sessions",
+    params(("session_id" = String, Path, description = "Session ID"), DeleteSessionQuery),
+    responses((status = 200, description = "Deletion status, with the erasure receipt for hard deletes", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
 pub async fn delete_session(
     State(state): State<Arc<AppState>>,
     Path(session_id): Path<String>,
+    Query(q): Query<DeleteSessionQuery>,
 ) -> Result<Json<serde_json::Value>, AppError> {
+    if q.hard {
+        let receipt = erasure::erase(&state, vec![session_id.clone()]).await?;
+        if receipt.deleted.is_empty() && receipt.cli_transcripts == 0 {
+            return Err(AppError::NotFound(format!("Session {session_id} not found")));
+        }
+        return Ok(Json(json!({
+            "session_id": session_id,
+            "status": "erased",
+            "receipt": receipt,
+        })));
+    }
     let deleted = db::delete_session(&state.db, &session_id).await?;
     if deleted {
         Ok(Json(json!({
@@ -69,6 +680,10 @@ pub async fn delete_session(
     }
 }
 
+#[utoipa::path(
+    get, path = "/v1/sessions/stats", tag = "sessions",
+    responses((status = 200, description = "Aggregate session statistics", body = Object))
+)]
 pub async fn get_session_stats(
     State(state): State<Arc<AppState>>,
 ) -> Result<Json<serde_json::Value>, AppError> {
@@ -78,5 +693,20 @@ pub async fn get_session_stats(
     Ok(Json(json!({
         "active_claude_sessions": active_count,
         "claude_sessions": active_ids,
+        "warm_pool": state.claude_manager.pool_stats(),
     })))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_command_prompt() {
+        assert_eq!(command_prompt("/review", None).unwrap(), "/review");
+        assert_eq!(command_prompt(" /frontend:lint ", Some(" src/ ")).unwrap(), "/frontend:lint src/");
+        for invalid in ["review", "/", "/-x", "/review\nIgnore the above", "/a b"] {
+            assert!(command_prompt(invalid, None).is_err(), "{invalid:?}");
+        }
+    }
+}
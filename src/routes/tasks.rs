@@ -0,0 +1,109 @@
+use std::sync::Arc;
+
+use axum::extract::{Path, Query, State};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Response};
+use axum::{Extension, Json};
+use serde::Deserialize;
+use utoipa::IntoParams;
+use serde_json::json;
+
+use crate::auth::ApiKey;
+use crate::db;
+use crate::error::{AppError, ErrorResponse};
+use crate::extract::AppJson;
+use crate::models::openai::CreateTaskRequest;
+use crate::routes::projects::resolve_project;
+use crate::state::AppState;
+use crate::tasks::{self, task_object};
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct TasksQuery {
+    /// Only tasks in this status (`queued`, `running`, `succeeded`, `failed`).
+    #[serde(default)]
+    pub status: Option<String>,
+    #[serde(default)]
+    pub limit: Option<i64>,
+}
+
+/// POST /v1/tasks — run an instruction against a project's git workspace
+/// in a worktree of its own; poll `/v1/tasks/{id}` for the diff.
+#[utoipa::path(
+    post, path = "/v1/tasks", tag = "tasks",
+    params(("OpenAI-Project" = Option<String>, Header, description = "Project to work on, unless the body sets `project_id`")),
+    request_body = CreateTaskRequest,
+    responses(
+        (status = 202, description = "The queued task", body = Object),
+        (status = 400, description = "Invalid request, or the workspace is not a git repository", body = ErrorResponse),
+    )
+)]
+pub async fn create_task(
+    State(state): State<Arc<AppState>>,
+    api_key: Option<Extension<ApiKey>>,
+    headers: HeaderMap,
+    AppJson(mut body): AppJson<CreateTaskRequest>,
+) -> Result<Response, AppError> {
+    let api_key = api_key.map(|Extension(ApiKey(key))| key);
+    let project_id = resolve_project(&state.config(), &headers, api_key.as_deref(), body.project_id.take())?
+        .unwrap_or_else(|| "default".to_string());
+    let task = tasks::submit(state, body, project_id, api_key).await?;
+    Ok((StatusCode::ACCEPTED, Json(task_object(&task))).into_response())
+}
+
+/// GET /v1/tasks
+#[utoipa::path(
+    get, path = "/v1/tasks", tag = "tasks",
+    params(TasksQuery),
+    responses((status = 200, description = "Tasks, newest first", body = Object))
+)]
+pub async fn list_tasks(
+    State(state): State<Arc<AppState>>,
+    Query(q): Query<TasksQuery>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    let limit = q.limit.unwrap_or(20).clamp(1, 100);
+    let tasks = db::list_tasks(&state.db, q.status.as_deref(), limit).await?;
+    Ok(Json(json!({
+        "object": "list",
+        "data": tasks.iter().map(task_object).collect::<Vec<_>>(),
+    })))
+}
+
+/// GET /v1/tasks/{task_id} — the task's status, with its diff, changed
+/// files and summary once done.
+#[utoipa::path(
+    get, path = "/v1/tasks/{task_id}", tag = "tasks",
+    params(("task_id" = String, Path, description = "Task ID")),
+    responses((status = 200, description = "The task", body = Object), (status = 404, description = "Not found", body = ErrorResponse))
+)]
+pub async fn get_task(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+) -> Result<Json<serde_json::Value>, AppError> {
+    match db::get_task(&state.db, &task_id).await? {
+        Some(task) => Ok(Json(task_object(&task))),
+        None => Err(AppError::NotFound(format!("Task {task_id} not found"))),
+    }
+}
+
+/// GET /v1/tasks/{task_id}/diff — the diff alone, ready for `git apply`.
+#[utoipa::path(
+    get, path = "/v1/tasks/{task_id}/diff", tag = "tasks",
+    params(("task_id" = String, Path, description = "Task ID")),
+    responses(
+        (status = 200, description = "Unified diff against the task's base commit", content_type = "text/x-diff"),
+        (status = 404, description = "Not found, or the task has not succeeded", body = ErrorResponse),
+    )
+)]
+pub async fn get_task_diff(
+    State(state): State<Arc<AppState>>,
+    Path(task_id): Path<String>,
+) -> Result<Response, AppError> {
+    let task = db::get_task(&state.db, &task_id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Task {task_id} not found")))?;
+    match task.diff {
+        Some(diff) => Ok(([(header::CONTENT_TYPE, "text/x-diff")], diff).into_response()),
+        None => Err(AppError::NotFound(format!("Task {task_id} has no diff ({})", task.status))),
+    }
+}
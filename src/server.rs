@@ -0,0 +1,224 @@
+use std::fmt;
+use std::net::{SocketAddr, ToSocketAddrs};
+use std::path::PathBuf;
+
+use axum::extract::connect_info::Connected;
+use axum::serve::IncomingStream;
+use axum::Router;
+use tokio::sync::watch;
+
+/// A single bind address: TCP `host:port` or a Unix domain socket path.
+#[derive(Debug, Clone)]
+pub enum BindAddr {
+    Tcp(String),
+    Unix(PathBuf),
+}
+
+impl BindAddr {
+    /// Parse `host:port` or `unix:/path/to/socket`.
+    pub fn parse(s: &str) -> Self {
+        match s.strip_prefix("unix:") {
+            Some(path) => Self::Unix(PathBuf::from(path)),
+            None => Self::Tcp(s.to_string()),
+        }
+    }
+
+    /// Whether only this host can connect: a Unix socket, or a TCP address
+    /// whose every resolved IP is loopback.
+    pub fn is_local(&self) -> bool {
+        match self {
+            Self::Unix(_) => true,
+            Self::Tcp(addr) => addr
+                .to_socket_addrs()
+                .map(|addrs| {
+                    let ips: Vec<_> = addrs.map(|a| a.ip()).collect();
+                    !ips.is_empty() && ips.iter().all(|ip| ip.is_loopback())
+                })
+                .unwrap_or(false),
+        }
+    }
+}
+
+impl fmt::Display for BindAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Tcp(addr) => write!(f, "{addr}"),
+            Self::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// A listening socket ready to be served.
+pub enum BoundListener {
+    Tcp(tokio::net::TcpListener),
+    /// Unix listener plus the socket path to remove on shutdown, if we
+    /// created it ourselves (inherited sockets are left alone).
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, Option<PathBuf>),
+}
+
+impl BoundListener {
+    /// Bind a fresh listener for `addr`.
+    pub async fn bind(addr: &BindAddr, socket_mode: u32) -> std::io::Result<Self> {
+        match addr {
+            BindAddr::Tcp(a) => Ok(Self::Tcp(tokio::net::TcpListener::bind(a.as_str()).await?)),
+            #[cfg(unix)]
+            BindAddr::Unix(path) => Ok(Self::Unix(
+                bind_unix_socket(path, socket_mode)?,
+                Some(path.clone()),
+            )),
+            #[cfg(not(unix))]
+            BindAddr::Unix(_) => Err(std::io::Error::new(
+                std::io::ErrorKind::Unsupported,
+                "Unix domain sockets are not supported on this platform",
+            )),
+        }
+    }
+}
+
+/// Connection info for both listener kinds: the TCP peer address, or `None`
+/// for Unix domain socket peers.
+#[derive(Debug, Clone, Copy)]
+pub struct PeerAddr(pub Option<SocketAddr>);
+
+impl Connected<IncomingStream<'_, tokio::net::TcpListener>> for PeerAddr {
+    fn connect_info(stream: IncomingStream<'_, tokio::net::TcpListener>) -> Self {
+        Self(Some(*stream.remote_addr()))
+    }
+}
+
+#[cfg(unix)]
+impl Connected<IncomingStream<'_, tokio::net::UnixListener>> for PeerAddr {
+    fn connect_info(_stream: IncomingStream<'_, tokio::net::UnixListener>) -> Self {
+        Self(None)
+    }
+}
+
+/// Serve `app` on `listener` until the shutdown channel fires.
+///
+/// HTTP/1.1 and HTTP/2 (h2c with prior knowledge) are both accepted on every
+/// listener; hyper detects the protocol from the connection preface.
+pub async fn serve(
+    listener: BoundListener,
+    app: Router,
+    shutdown: watch::Receiver<bool>,
+) -> std::io::Result<()> {
+    match listener {
+        BoundListener::Tcp(listener) => {
+            tracing::info!(addr = %listener.local_addr()?, "Server listening");
+            axum::serve(listener, app.into_make_service_with_connect_info::<PeerAddr>())
+                .with_graceful_shutdown(wait_for_shutdown(shutdown))
+                .await
+        }
+        #[cfg(unix)]
+        BoundListener::Unix(listener, cleanup) => {
+            // Sockets we bound were moved into place after binding
+            let socket = match cleanup {
+                Some(ref path) => Some(path.clone()),
+                None => listener.local_addr()?.as_pathname().map(std::path::Path::to_path_buf),
+            };
+            tracing::info!(socket = ?socket, "Server listening");
+            let result = axum::serve(listener, app.into_make_service_with_connect_info::<PeerAddr>())
+                .with_graceful_shutdown(wait_for_shutdown(shutdown))
+                .await;
+            if let Some(path) = cleanup {
+                let _ = std::fs::remove_file(path);
+            }
+            result
+        }
+    }
+}
+
+async fn wait_for_shutdown(mut rx: watch::Receiver<bool>) {
+    let _ = rx.wait_for(|stop| *stop).await;
+}
+
+/// Bind a Unix domain socket, replacing a stale socket file left behind by
+/// a previous run (a socket still being listened on, or any other file at
+/// the path, is an error), and apply the configured permission bits. The
+/// socket is bound in a private staging directory and moved into place
+/// once its permissions are set, so it is never reachable with the
+/// default ones.
+#[cfg(unix)]
+fn bind_unix_socket(path: &std::path::Path, mode: u32) -> std::io::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{DirBuilderExt, FileTypeExt, PermissionsExt};
+
+    match std::fs::symlink_metadata(path) {
+        Ok(meta) if meta.file_type().is_socket() => {
+            if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::AddrInUse,
+                    format!("{} is in use by another process", path.display()),
+                ));
+            }
+        }
+        Ok(_) => {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket", path.display()),
+            ))
+        }
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e),
+    }
+    let Some(name) = path.file_name() else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidInput,
+            format!("{} is not a socket path", path.display()),
+        ));
+    };
+    let parent = path.parent().filter(|p| !p.as_os_str().is_empty()).unwrap_or(std::path::Path::new("."));
+    std::fs::create_dir_all(parent)?;
+
+    let staging = parent.join(format!(".{}.{}", name.to_string_lossy(), std::process::id()));
+    let _ = std::fs::remove_dir_all(&staging);
+    std::fs::DirBuilder::new().mode(0o700).create(&staging)?;
+    let staged = staging.join("socket");
+    let bound = tokio::net::UnixListener::bind(&staged).and_then(|listener| {
+        std::fs::set_permissions(&staged, std::fs::Permissions::from_mode(mode))?;
+        // Replaces a stale socket atomically
+        std::fs::rename(&staged, path)?;
+        Ok(listener)
+    });
+    let _ = std::fs::remove_file(&staged);
+    let _ = std::fs::remove_dir(&staging);
+    bound
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_local() {
+        for addr in ["127.0.0.1:9000", "[::1]:9000", "localhost:9000", "unix:/run/gw/admin.sock"] {
+            assert!(BindAddr::parse(addr).is_local(), "{addr}");
+        }
+        for addr in ["0.0.0.0:9000", "[::]:9000", "10.0.0.5:9000", "not an address"] {
+            assert!(!BindAddr::parse(addr).is_local(), "{addr}");
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_bind_unix_socket_keeps_other_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api.sock");
+        std::fs::write(&path, "data").unwrap();
+        assert!(bind_unix_socket(&path, 0o660).is_err());
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "data");
+
+        std::fs::remove_file(&path).unwrap();
+        drop(bind_unix_socket(&path, 0o660).unwrap());
+        // A leftover socket from a previous run is replaced
+        let live = bind_unix_socket(&path, 0o660).unwrap();
+        let mode = std::os::unix::fs::PermissionsExt::mode(&std::fs::metadata(&path).unwrap().permissions());
+        assert_eq!(mode & 0o777, 0o660);
+        // One still being listened on is not
+        let err = bind_unix_socket(&path, 0o660).unwrap_err();
+        assert_eq!(err.kind(), std::io::ErrorKind::AddrInUse);
+        assert!(std::os::unix::net::UnixStream::connect(&path).is_ok());
+        drop(live);
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 1);
+    }
+}
@@ -1,31 +1,200 @@
-use std::sync::Arc;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, RwLock as StdRwLock};
+use std::time::Duration;
 
 use sqlx::SqlitePool;
-use tokio::sync::RwLock;
 
-use crate::auth::RateLimiter;
+use crate::audit::AuditLog;
+use crate::cache::ResponseCache;
+use crate::claude::auth::AuthFailure;
+use crate::claude::inflight::Inflight;
 use crate::claude::manager::ClaudeManager;
+use crate::claude::plan_limits::PlanLimits;
+use crate::claude::limits::ResourceLimits;
+use crate::claude::sandbox::Sandbox;
+use crate::claude::version::{CliCapabilities, CliVersion};
+use crate::client_ip::TrustedProxies;
 use crate::config::Config;
+use crate::conversation::ConversationTemplates;
+use crate::guardrails::Guardrails;
+use crate::metrics::Metrics;
+use crate::message_index::MessageIndex;
+use crate::pricing::Pricing;
+use crate::ratelimit::RateLimiter;
+use crate::redact::Redactor;
+use crate::registry::SessionRegistry;
+use crate::resumable::StreamBuffers;
+use crate::webhooks::Webhooks;
 
 pub struct AppState {
-    pub config: Config,
+    /// Swapped by [`crate::reload`]; read it through [`AppState::config`].
+    config: StdRwLock<Arc<Config>>,
     pub db: SqlitePool,
-    pub rate_limiter: RwLock<RateLimiter>,
+    pub rate_limiter: RateLimiter,
+    /// Buckets per key and end user, see [`crate::quota`].
+    pub user_rate_limiter: RateLimiter,
     pub claude_manager: ClaudeManager,
+    /// Claude CLI version detected at startup, if it could be parsed.
+    pub cli_version: Option<CliVersion>,
+    pub trusted_proxies: TrustedProxies,
+    pub audit_log: AuditLog,
+    pub metrics: Arc<Metrics>,
+    /// Cache of non-streaming completions, if `RESPONSE_CACHE` is on.
+    pub response_cache: Option<ResponseCache>,
+    /// Runs shared by identical concurrent requests, if `DEDUP_INFLIGHT` is on.
+    pub inflight: Option<Arc<Inflight>>,
+    /// Streamed completions kept for `Last-Event-ID`, unless
+    /// `STREAM_RESUME_SECONDS=0`.
+    pub stream_buffers: Option<Arc<StreamBuffers>>,
+    /// Wrapper for spawned CLIs when `SANDBOX`/`SANDBOX_COMMAND` is set.
+    pub sandbox: Option<Sandbox>,
+    pub limits: Option<ResourceLimits>,
+    /// Shared with the other replicas when `SESSION_REGISTRY_URL` is set.
+    pub registry: Option<Arc<SessionRegistry>>,
+    /// Prices used to cost runs the CLI reports as free; rebuilt on reload.
+    pricing: StdRwLock<Arc<Pricing>>,
+    /// Conversation rendering templates; rebuilt on reload.
+    conversation_templates: StdRwLock<Arc<ConversationTemplates>>,
+    /// Output scrubber, if `REDACT_OUTPUT` is on; rebuilt on reload.
+    redactor: StdRwLock<Option<Arc<Redactor>>>,
+    /// Pre-flight policy checks, unless `GUARDRAIL_MODE=off`.
+    pub guardrails: Option<Guardrails>,
+    /// Client for upstream APIs (embedding providers).
+    pub http: reqwest::Client,
+    /// Progress of the background message embedding.
+    pub message_index: MessageIndex,
+    /// Lifecycle event delivery, see [`crate::webhooks`].
+    pub webhooks: Webhooks,
+    /// Until when (unix time) an admin lifted the spend ceilings, see
+    /// [`crate::budget`].
+    pub budget_override: StdRwLock<Option<i64>>,
+    /// Whether runs were last being rejected for the spend ceilings.
+    pub budget_breaker_open: AtomicBool,
+    /// Profiles whose CLI was last seen logged out, see
+    /// [`crate::claude::auth`].
+    pub claude_auth: StdRwLock<BTreeMap<String, AuthFailure>>,
+    /// The subscription usage windows runs reported, see
+    /// [`crate::claude::plan_limits`].
+    pub plan_limits: Arc<PlanLimits>,
 }
 
 impl AppState {
-    pub fn new(config: Config, db: SqlitePool) -> Arc<Self> {
-        let rate_limiter = RwLock::new(RateLimiter::new(
+    /// `profile_caps` holds the probed capabilities of non-default profiles;
+    /// the default profile's are derived from `cli_version`.
+    pub fn new(
+        config: Config,
+        db: SqlitePool,
+        cli_version: Option<CliVersion>,
+        mut profile_caps: HashMap<String, CliCapabilities>,
+        registry: Option<Arc<SessionRegistry>>,
+    ) -> Arc<Self> {
+        let rate_limiter = RateLimiter::new(
             config.rate_limit_requests_per_minute,
             config.rate_limit_burst,
-        ));
-        let claude_manager = ClaudeManager::new(config.clone());
+            config.rate_limit_max_keys,
+        );
+        let user_rate_limiter =
+            RateLimiter::new(config.user_rate_limit_requests_per_minute, 0, config.rate_limit_max_keys);
+        profile_caps.insert(
+            config.default_profile().name.clone(),
+            CliCapabilities::for_version(cli_version.as_ref()),
+        );
+        let metrics = Arc::new(Metrics::default());
+        let webhooks = Webhooks::new(&config);
+        let plan_limits = Arc::new(PlanLimits::default());
+        let claude_manager = ClaudeManager::new(
+            &config,
+            profile_caps,
+            Arc::clone(&metrics),
+            registry.clone(),
+            webhooks.clone(),
+            Arc::clone(&plan_limits),
+        );
+        let trusted_proxies = TrustedProxies::new(&config.trusted_proxies);
+        let audit_log = AuditLog::spawn(db.clone(), config.audit_log);
+        let sandbox = Sandbox::from_config(&config);
+        let limits = ResourceLimits::from_config(&config);
+        let response_cache = config.response_cache.then(|| {
+            ResponseCache::new(
+                db.clone(),
+                config.response_cache_size,
+                Duration::from_secs(config.response_cache_ttl_seconds),
+            )
+        });
+        let pricing = Pricing::load(&config.model_catalog, config.pricing_file.as_deref(), &config.model_pricing);
+        let conversation_templates = ConversationTemplates::load(&config);
+        let redactor = Redactor::from_config(&config).map(Arc::new);
+        let guardrails = Guardrails::from_config(&config);
+        let inflight = config.dedup_inflight.then(|| Arc::new(Inflight::default()));
+        let stream_buffers = (config.stream_resume_seconds > 0)
+            .then(|| Arc::new(StreamBuffers::new(Duration::from_secs(config.stream_resume_seconds))));
         Arc::new(Self {
-            config,
+            config: StdRwLock::new(Arc::new(config)),
             db,
             rate_limiter,
+            user_rate_limiter,
             claude_manager,
+            cli_version,
+            trusted_proxies,
+            audit_log,
+            metrics,
+            response_cache,
+            inflight,
+            stream_buffers,
+            sandbox,
+            limits,
+            registry,
+            pricing: StdRwLock::new(Arc::new(pricing)),
+            conversation_templates: StdRwLock::new(Arc::new(conversation_templates)),
+            redactor: StdRwLock::new(redactor),
+            guardrails,
+            http: reqwest::Client::new(),
+            message_index: MessageIndex::default(),
+            webhooks,
+            budget_override: StdRwLock::new(None),
+            budget_breaker_open: AtomicBool::new(false),
+            claude_auth: StdRwLock::new(BTreeMap::new()),
+            plan_limits,
         })
     }
+
+    /// The current configuration. Hold the returned snapshot for the whole
+    /// of a request so a concurrent reload can't mix old and new values.
+    pub fn config(&self) -> Arc<Config> {
+        Arc::clone(&self.config.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    pub fn pricing(&self) -> Arc<Pricing> {
+        Arc::clone(&self.pricing.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    pub fn conversation_templates(&self) -> Arc<ConversationTemplates> {
+        Arc::clone(&self.conversation_templates.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    pub fn redactor(&self) -> Option<Arc<Redactor>> {
+        self.redactor.read().unwrap_or_else(|e| e.into_inner()).clone()
+    }
+
+    /// Install a reloaded configuration, rebuilding what is derived from
+    /// it. Sessions already running keep the settings they started with.
+    pub async fn replace_config(&self, config: Config) {
+        let pricing = Pricing::load(&config.model_catalog, config.pricing_file.as_deref(), &config.model_pricing);
+        let conversation_templates = ConversationTemplates::load(&config);
+        let redactor = Redactor::from_config(&config).map(Arc::new);
+        self.rate_limiter.set_limits(
+            config.rate_limit_requests_per_minute,
+            config.rate_limit_burst,
+            config.rate_limit_max_keys,
+        );
+        self.user_rate_limiter
+            .set_limits(config.user_rate_limit_requests_per_minute, 0, config.rate_limit_max_keys);
+        *self.pricing.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(pricing);
+        *self.conversation_templates.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(conversation_templates);
+        *self.redactor.write().unwrap_or_else(|e| e.into_inner()) = redactor;
+        self.webhooks.configure(&config);
+        self.claude_manager.rebuild_pool(&config).await;
+        *self.config.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(config);
+    }
 }
@@ -1,4 +1,13 @@
-use serde_json::json;
+use std::collections::HashMap;
+use std::time::{Duration, Instant};
+
+use serde_json::{json, Value};
+
+use crate::claude::parser::{extract_tool_results, extract_tool_uses};
+use crate::config::Config;
+
+/// Longest tool input summary in a progress event.
+const SUMMARY_MAX_CHARS: usize = 120;
 
 /// Format a JSON value as an SSE `data:` event.
 pub fn sse_event(data: &serde_json::Value) -> String {
@@ -8,6 +17,15 @@ pub fn sse_event(data: &serde_json::Value) -> String {
     )
 }
 
+/// Format a JSON value as a named SSE event. Clients reading only
+/// unnamed `data:` events (the OpenAI SDKs) should not be sent these.
+pub fn sse_named_event(event: &str, data: &serde_json::Value) -> String {
+    format!(
+        "event: {event}\ndata: {}\n\n",
+        serde_json::to_string(data).unwrap_or_default()
+    )
+}
+
 /// The SSE completion signal.
 pub fn sse_done() -> String {
     "data: [DONE]\n\n".to_string()
@@ -68,6 +86,32 @@ pub fn final_chunk(
     })
 }
 
+/// Tool call delta chunk for an OpenAI-format `tool_calls` entry.
+pub fn tool_call_chunk(
+    id: &str,
+    model: &str,
+    created: i64,
+    index: usize,
+    tool_call: &serde_json::Value,
+) -> serde_json::Value {
+    json!({
+        "id": id,
+        "object": "chat.completion.chunk",
+        "created": created,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "delta": {"tool_calls": [{
+                "index": index,
+                "id": tool_call.get("id"),
+                "type": "function",
+                "function": tool_call.get("function"),
+            }]},
+            "finish_reason": null
+        }]
+    })
+}
+
 /// Wrap a complete `chat.completion` response as SSE events.
 ///
 /// Used when tool_calls force non-streaming collection but the client
@@ -118,22 +162,7 @@ pub fn wrap_response_as_sse(response: &serde_json::Value) -> Vec<String> {
 
         if let Some(tcs) = tool_calls {
             for (i, tc) in tcs.iter().enumerate() {
-                events.push(sse_event(&json!({
-                    "id": id,
-                    "object": "chat.completion.chunk",
-                    "created": created,
-                    "model": model,
-                    "choices": [{
-                        "index": 0,
-                        "delta": {"tool_calls": [{
-                            "index": i,
-                            "id": tc.get("id"),
-                            "type": "function",
-                            "function": tc.get("function"),
-                        }]},
-                        "finish_reason": null
-                    }]
-                })));
+                events.push(sse_event(&tool_call_chunk(id, model, created, i, tc)));
             }
         }
 
@@ -143,3 +172,285 @@ pub fn wrap_response_as_sse(response: &serde_json::Value) -> Vec<String> {
     events.push(sse_done());
     events
 }
+
+/// How streamed content is cut into deltas (`STREAM_CHUNK_BYTES`,
+/// `STREAM_FLUSH_MS`, `STREAM_SPLIT_SENTENCES`). The default sends each
+/// CLI message as one delta, as soon as it arrives.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ChunkPolicy {
+    /// Largest delta in bytes; 0 for no limit.
+    pub max_bytes: usize,
+    /// Hold text back and send it at most this often, so short messages
+    /// share a delta; zero sends text as it arrives.
+    pub flush_interval: Duration,
+    /// Cut at sentence ends, holding a partial sentence back until it is
+    /// complete, the flush interval passes or the stream ends.
+    pub sentences: bool,
+}
+
+impl ChunkPolicy {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            max_bytes: config.stream_chunk_bytes,
+            flush_interval: Duration::from_millis(config.stream_flush_ms),
+            sentences: config.stream_split_sentences,
+        }
+    }
+}
+
+/// Cuts streamed content into deltas by a [`ChunkPolicy`].
+pub struct Chunker {
+    policy: ChunkPolicy,
+    held: String,
+    last_flush: Instant,
+}
+
+impl Chunker {
+    pub fn new(policy: ChunkPolicy) -> Self {
+        Self { policy, held: String::new(), last_flush: Instant::now() }
+    }
+
+    /// Add content; returns the deltas to send now.
+    pub fn push(&mut self, text: &str) -> Vec<String> {
+        self.held.push_str(text);
+        let mut deltas = self.take_full();
+        if self.last_flush.elapsed() >= self.policy.flush_interval {
+            let end = match self.policy.sentences {
+                true => sentence_end(&self.held, self.held.len()).unwrap_or(0),
+                false => self.held.len(),
+            };
+            deltas.extend(self.take(end));
+        }
+        deltas
+    }
+
+    /// How long until held-back content is due, if any is held and a flush
+    /// interval is set; then call [`tick`](Self::tick).
+    pub fn due_in(&self) -> Option<Duration> {
+        let interval = self.policy.flush_interval;
+        (!self.held.is_empty() && !interval.is_zero()).then(|| interval.saturating_sub(self.last_flush.elapsed()))
+    }
+
+    /// The held-back content, once the flush interval has passed.
+    pub fn tick(&mut self) -> Vec<String> {
+        if self.last_flush.elapsed() < self.policy.flush_interval {
+            return Vec::new();
+        }
+        self.flush()
+    }
+
+    /// All held-back content, before other events or the end of the stream.
+    pub fn flush(&mut self) -> Vec<String> {
+        let mut deltas = self.take_full();
+        deltas.extend(self.take(self.held.len()));
+        deltas
+    }
+
+    /// Deltas of `max_bytes` while more than that is held.
+    fn take_full(&mut self) -> Vec<String> {
+        let max = self.policy.max_bytes;
+        let mut deltas = Vec::new();
+        while max > 0 && self.held.len() > max {
+            let end = match self.policy.sentences {
+                true => sentence_end(&self.held, max),
+                false => None,
+            };
+            let end = end.or_else(|| word_end(&self.held, max)).unwrap_or_else(|| char_end(&self.held, max));
+            deltas.extend(self.take(end));
+        }
+        deltas
+    }
+
+    fn take(&mut self, end: usize) -> Option<String> {
+        if end == 0 {
+            return None;
+        }
+        self.last_flush = Instant::now();
+        Some(self.held.drain(..end).collect())
+    }
+}
+
+/// The end of the last sentence (its punctuation and following space, or
+/// a line break) within the first `limit` bytes of `text`.
+fn sentence_end(text: &str, limit: usize) -> Option<usize> {
+    let mut end = None;
+    let mut chars = text.char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        let next = chars.peek().copied();
+        let cut = match (c, next) {
+            ('\n', _) => i + 1,
+            ('.' | '!' | '?', Some((j, n))) if n.is_whitespace() => j + n.len_utf8(),
+            _ => continue,
+        };
+        if cut > limit {
+            break;
+        }
+        end = Some(cut);
+    }
+    end
+}
+
+/// The end of the last whitespace within the first `limit` bytes.
+fn word_end(text: &str, limit: usize) -> Option<usize> {
+    text.char_indices()
+        .rev()
+        .map(|(i, c)| (i + c.len_utf8(), c))
+        .find(|&(end, c)| c.is_whitespace() && end <= limit)
+        .map(|(end, _)| end)
+}
+
+/// `limit` rounded down to a char boundary, but at least one char.
+fn char_end(text: &str, limit: usize) -> usize {
+    match (1..=limit).rev().find(|&i| text.is_char_boundary(i)) {
+        Some(end) => end,
+        None => text.chars().next().map_or(0, char::len_utf8),
+    }
+}
+
+/// Turns the CLI's built-in tool activity into `claude.tool_use` and
+/// `claude.tool_result` SSE events, so UIs can show what a long agentic
+/// turn is doing.
+#[derive(Debug, Default)]
+pub struct ToolProgress {
+    /// Running tools by `tool_use` ID: name and start time.
+    running: HashMap<String, (String, Instant)>,
+}
+
+impl ToolProgress {
+    /// The progress events for one CLI message, if any.
+    pub fn events(&mut self, completion_id: &str, msg: &Value) -> Vec<String> {
+        let mut events = Vec::new();
+        for (id, name, input) in extract_tool_uses(msg) {
+            self.running.insert(id.to_string(), (name.to_string(), Instant::now()));
+            events.push(sse_named_event(
+                "claude.tool_use",
+                &json!({
+                    "id": completion_id,
+                    "object": "chat.completion.tool_use",
+                    "tool_use_id": id,
+                    "name": name,
+                    "summary": summarize_tool_input(name, input),
+                }),
+            ));
+        }
+        for (id, is_error) in extract_tool_results(msg) {
+            let (name, duration_ms) = match self.running.remove(id) {
+                Some((name, started)) => (Some(name), Some(started.elapsed().as_millis() as u64)),
+                None => (None, None),
+            };
+            events.push(sse_named_event(
+                "claude.tool_result",
+                &json!({
+                    "id": completion_id,
+                    "object": "chat.completion.tool_result",
+                    "tool_use_id": id,
+                    "name": name,
+                    "is_error": is_error,
+                    "duration_ms": duration_ms,
+                }),
+            ));
+        }
+        events
+    }
+}
+
+/// A short human-readable description of a built-in tool call, e.g.
+/// `Editing src/main.rs`.
+pub fn summarize_tool_input(name: &str, input: &Value) -> String {
+    let field = |key: &str| input.get(key).and_then(|v| v.as_str()).unwrap_or_default();
+    let summary = match name {
+        "Bash" if !field("description").is_empty() => field("description").to_string(),
+        "Bash" => format!("Running `{}`", field("command")),
+        "Read" => format!("Reading {}", field("file_path")),
+        "Write" => format!("Writing {}", field("file_path")),
+        "Edit" | "MultiEdit" => format!("Editing {}", field("file_path")),
+        "NotebookEdit" => format!("Editing {}", field("notebook_path")),
+        "Grep" | "Glob" => format!("Searching for {}", field("pattern")),
+        "WebFetch" => format!("Fetching {}", field("url")),
+        "WebSearch" => format!("Searching the web for {}", field("query")),
+        "Task" => format!("Running subagent: {}", field("description")),
+        "TodoWrite" => "Updating the todo list".to_string(),
+        _ => format!("Using {name}"),
+    };
+    let summary = summary.lines().next().unwrap_or_default().trim_end();
+    if summary.chars().count() > SUMMARY_MAX_CHARS {
+        let cut: String = summary.chars().take(SUMMARY_MAX_CHARS - 1).collect();
+        format!("{cut}…")
+    } else {
+        summary.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_chunker() {
+        // The default passes content through
+        let mut chunker = Chunker::new(ChunkPolicy::default());
+        assert_eq!(chunker.push("Hello there. How"), ["Hello there. How"]);
+        assert!(chunker.flush().is_empty());
+
+        let sized = ChunkPolicy { max_bytes: 12, ..Default::default() };
+        let mut chunker = Chunker::new(sized);
+        assert_eq!(chunker.push("The quick brown fox jumps"), ["The quick ", "brown fox ", "jumps"]);
+
+        let sentences = ChunkPolicy { sentences: true, ..Default::default() };
+        let mut chunker = Chunker::new(sentences);
+        assert_eq!(chunker.push("One. Two and"), ["One. "]);
+        assert_eq!(chunker.push(" three! Four"), ["Two and three! "]);
+        assert_eq!(chunker.flush(), ["Four"]);
+
+        // Held until the interval passes, then sent together
+        let paced = ChunkPolicy { flush_interval: Duration::from_secs(60), ..Default::default() };
+        let mut chunker = Chunker::new(paced);
+        assert!(chunker.push("a").is_empty() && chunker.push("b").is_empty());
+        assert!(chunker.due_in().is_some() && chunker.tick().is_empty());
+        assert_eq!(chunker.flush(), ["ab"]);
+        assert_eq!(chunker.due_in(), None);
+
+        assert_eq!(char_end("héllo", 2), 1);
+        assert_eq!(char_end("é", 1), 2);
+    }
+
+    #[test]
+    fn test_tool_progress() {
+        let mut progress = ToolProgress::default();
+        let tool_use = json!({
+            "type": "assistant",
+            "message": {"content": [
+                {"type": "text", "text": "Let me fix it."},
+                {"type": "tool_use", "id": "toolu_1", "name": "Edit",
+                 "input": {"file_path": "src/main.rs", "old_string": "a", "new_string": "b"}},
+            ]},
+        });
+        let events = progress.events("chatcmpl-1", &tool_use);
+        assert_eq!(events.len(), 1);
+        assert!(events[0].starts_with("event: claude.tool_use\ndata: "));
+        assert!(events[0].contains(r#""summary":"Editing src/main.rs""#));
+
+        let tool_result = json!({
+            "type": "user",
+            "message": {"content": [{"type": "tool_result", "tool_use_id": "toolu_1", "content": "ok"}]},
+        });
+        let events = progress.events("chatcmpl-1", &tool_result);
+        let data: Value = serde_json::from_str(events[0].split_once("data: ").unwrap().1.trim()).unwrap();
+        assert_eq!(data["name"], "Edit");
+        assert_eq!(data["is_error"], false);
+        assert!(data["duration_ms"].is_u64());
+        assert!(progress.events("chatcmpl-1", &json!({"type": "result"})).is_empty());
+    }
+
+    #[test]
+    fn test_summarize_tool_input() {
+        assert_eq!(
+            summarize_tool_input("Bash", &json!({"command": "cargo test", "description": "Running tests"})),
+            "Running tests"
+        );
+        assert_eq!(summarize_tool_input("Bash", &json!({"command": "ls\npwd"})), "Running `ls");
+        assert_eq!(summarize_tool_input("mcp__db__query", &json!({})), "Using mcp__db__query");
+        let long = summarize_tool_input("Grep", &json!({"pattern": "x".repeat(200)}));
+        assert_eq!(long.chars().count(), SUMMARY_MAX_CHARS);
+    }
+}
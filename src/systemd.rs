@@ -0,0 +1,135 @@
+//! Minimal systemd integration: socket activation (`LISTEN_FDS`),
+//! readiness/status notification (`NOTIFY_SOCKET`) and watchdog pings.
+//!
+//! Everything here is a no-op when not running under systemd.
+
+use std::time::Duration;
+
+use crate::server::BoundListener;
+
+/// First file descriptor passed by systemd socket activation.
+#[cfg(unix)]
+const SD_LISTEN_FDS_START: i32 = 3;
+
+/// Send a state string (e.g. `READY=1`, `STATUS=...`) to the service manager.
+#[cfg(unix)]
+pub fn notify(state: &str) {
+    use std::os::unix::net::UnixDatagram;
+
+    let Ok(socket_path) = std::env::var("NOTIFY_SOCKET") else {
+        return;
+    };
+
+    let result = UnixDatagram::unbound().and_then(|sock| {
+        if let Some(name) = socket_path.strip_prefix('@') {
+            // Abstract namespace socket
+            #[cfg(target_os = "linux")]
+            {
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name)?;
+                return sock.send_to_addr(state.as_bytes(), &addr).map(|_| ());
+            }
+            #[cfg(not(target_os = "linux"))]
+            {
+                let _ = name;
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::Unsupported,
+                    "abstract notify sockets require Linux",
+                ));
+            }
+        }
+        sock.send_to(state.as_bytes(), &socket_path).map(|_| ())
+    });
+
+    if let Err(e) = result {
+        tracing::warn!(error = %e, state, "Failed to notify systemd");
+    }
+}
+
+#[cfg(not(unix))]
+pub fn notify(_state: &str) {}
+
+/// Start sending `WATCHDOG=1` at half the interval requested by systemd.
+pub fn spawn_watchdog() {
+    let Some(usec) = std::env::var("WATCHDOG_USEC")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|usec| *usec > 0)
+    else {
+        return;
+    };
+    if !env_pid_matches("WATCHDOG_PID").unwrap_or(true) {
+        return;
+    }
+
+    let interval = Duration::from_micros(usec / 2);
+    tracing::info!(interval_ms = interval.as_millis() as u64, "systemd watchdog enabled");
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            notify("WATCHDOG=1");
+        }
+    });
+}
+
+/// Take ownership of sockets passed via `LISTEN_FDS`.
+///
+/// Returns each listener with its `LISTEN_FDNAMES` entry, if any. Inherited
+/// descriptors are marked close-on-exec so spawned Claude processes do not
+/// keep them open.
+#[cfg(unix)]
+pub fn listen_fds() -> std::io::Result<Vec<(Option<String>, BoundListener)>> {
+    use std::os::fd::FromRawFd;
+
+    if !env_pid_matches("LISTEN_PID").unwrap_or(false) {
+        return Ok(Vec::new());
+    }
+    let count: i32 = match std::env::var("LISTEN_FDS").ok().and_then(|v| v.parse().ok()) {
+        Some(n) if n > 0 => n,
+        _ => return Ok(Vec::new()),
+    };
+    let names: Vec<String> = std::env::var("LISTEN_FDNAMES")
+        .map(|v| v.split(':').map(str::to_string).collect())
+        .unwrap_or_default();
+
+    let mut listeners = Vec::with_capacity(count as usize);
+    for (i, fd) in (SD_LISTEN_FDS_START..SD_LISTEN_FDS_START + count).enumerate() {
+        // SAFETY: fcntl on a descriptor number only inspects/updates its flags.
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } == -1 {
+            return Err(std::io::Error::last_os_error());
+        }
+
+        // SAFETY: systemd hands these descriptors to us exclusively; each is
+        // wrapped exactly once and owned by the returned listener.
+        let tcp = unsafe { std::net::TcpListener::from_raw_fd(fd) };
+        let listener = if tcp.local_addr().is_ok() {
+            tcp.set_nonblocking(true)?;
+            BoundListener::Tcp(tokio::net::TcpListener::from_std(tcp)?)
+        } else {
+            // Not an inet socket: reinterpret the same descriptor as Unix.
+            let fd = std::os::fd::IntoRawFd::into_raw_fd(tcp);
+            // SAFETY: ownership was released by `into_raw_fd` just above.
+            let unix = unsafe { std::os::unix::net::UnixListener::from_raw_fd(fd) };
+            unix.set_nonblocking(true)?;
+            BoundListener::Unix(tokio::net::UnixListener::from_std(unix)?, None)
+        };
+
+        let name = names.get(i).filter(|n| !n.is_empty()).cloned();
+        listeners.push((name, listener));
+    }
+
+    tracing::info!(count, "Using systemd socket activation");
+    Ok(listeners)
+}
+
+#[cfg(not(unix))]
+pub fn listen_fds() -> std::io::Result<Vec<(Option<String>, BoundListener)>> {
+    Ok(Vec::new())
+}
+
+/// Whether a `*_PID` variable targets this process; `None` when unset.
+fn env_pid_matches(var: &str) -> Option<bool> {
+    let pid = std::env::var(var).ok()?;
+    Some(pid.parse::<u32>().ok() == Some(std::process::id()))
+}
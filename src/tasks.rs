@@ -0,0 +1,495 @@
+//! Headless coding tasks (`/v1/tasks`): an instruction run as a full agent
+//! session in a throwaway git worktree of the project's workspace, so the
+//! workspace itself is never touched. What the run changed is kept as a
+//! unified diff with the list of changed files; the session, its messages
+//! and its transcript are stored like any other. Tasks run in the
+//! background and are polled like jobs. With `pull_request` set, the
+//! changes are also pushed to GitHub as a pull request, see
+//! [`crate::github`].
+
+use std::ffi::OsStr;
+use std::path::Path;
+use std::sync::Arc;
+
+use futures::StreamExt;
+use serde_json::{json, Value};
+
+use crate::agents;
+use crate::budget;
+use crate::claude::auth;
+use crate::claude::env::build_env;
+use crate::claude::manager::create_project_directory;
+use crate::claude::parser::{
+    auth_error, extract_assistant_content, extract_usage, is_assistant_message, is_result_message,
+    upstream_limit,
+};
+use crate::claude::process::SpawnOptions;
+use crate::config::ClaudeProfile;
+use crate::db::{self, TaskRow};
+use crate::error::AppError;
+use crate::github;
+use crate::hooks;
+use crate::jobs::unix_time;
+use crate::models::openai::{CreateTaskRequest, PullRequestOptions};
+use crate::priority::Priority;
+use crate::quota;
+use crate::state::AppState;
+use crate::tiers;
+use crate::tokens;
+use crate::transcript;
+
+/// Files the gateway writes into the worktree itself, left out of the diff.
+const GATEWAY_PATHS: &[&str] = &[
+    ":(exclude).claude/agents",
+    ":(exclude).claude/settings.json",
+    ":(exclude).claude/settings.local.json",
+];
+
+/// Check the request, record the task and run it in the background.
+pub async fn submit(
+    state: Arc<AppState>,
+    request: CreateTaskRequest,
+    project_id: String,
+    api_key: Option<String>,
+) -> Result<TaskRow, AppError> {
+    let invalid = |param: &str, message: String| AppError::InvalidParam { param: param.to_string(), message };
+    let instruction = request.instruction.trim();
+    if instruction.is_empty() {
+        return Err(invalid("instruction", "must not be empty".to_string()));
+    }
+    budget::check(&state).await?;
+    quota::check_key(&state, api_key.as_deref()).await?;
+    let config = state.config();
+    let workspace = create_project_directory(&config.project_root, &project_id)?;
+    if git(&workspace, ["rev-parse", "--is-inside-work-tree"]).await.is_err() {
+        return Err(invalid("project_id", format!("the workspace of '{project_id}' is not a git repository")));
+    }
+    let git_ref = request.git_ref.as_deref().map(str::trim).filter(|r| !r.is_empty()).unwrap_or("HEAD");
+    let base_commit = match git_ref.starts_with('-') {
+        true => None,
+        false => git(&workspace, ["rev-parse", "--verify", "--quiet", &format!("{git_ref}^{{commit}}")]).await.ok(),
+    };
+    let Some(base_commit) = base_commit else {
+        return Err(invalid("ref", format!("'{git_ref}' is not a commit of '{project_id}'")));
+    };
+
+    let pull_request = match request.pull_request {
+        Some(mut options) => {
+            options.repo = options.repo.trim().to_string();
+            if !github::valid_repo(&options.repo) {
+                return Err(invalid("pull_request.repo", format!("'{}' is not an owner/name repository", options.repo)));
+            }
+            options.token = options.token.filter(|t| !t.is_empty());
+            if options.token.is_none() {
+                if !github::server_token_allowed(&config.github_token_repos, &options.repo) {
+                    return Err(invalid(
+                        "pull_request.token",
+                        format!("no token given and '{}' is not in GITHUB_TOKEN_REPOS", options.repo),
+                    ));
+                }
+                options.token = config.github_token.clone();
+            }
+            if options.token.is_none() {
+                return Err(invalid("pull_request.token", "no token given and GITHUB_TOKEN is not set".to_string()));
+            }
+            Some(options)
+        }
+        None => None,
+    };
+
+    let requested = request.model.unwrap_or_else(|| config.default_model.clone());
+    let resolved = config.model_catalog.resolve(&requested);
+    tiers::enforce_run(config.key_tier(api_key.as_deref()), "Tasks", &requested, &resolved)?;
+    let mut profile = config
+        .select_profile(None, &[&requested, &resolved])
+        .cloned()
+        .unwrap_or_else(|| config.default_profile().clone());
+    if profile.backend != "claude" {
+        return Err(AppError::BadRequest(format!(
+            "Tasks need the Claude CLI; {requested} is served by {}",
+            profile.backend
+        )));
+    }
+    if let Some(dir) = config.account_config_dir(api_key.as_deref(), &project_id) {
+        profile.config_dir = Some(dir.to_path_buf());
+    }
+
+    let id = format!("task-{}", uuid::Uuid::new_v4().as_simple());
+    let session_id = uuid::Uuid::new_v4().to_string();
+    let task = db::create_task(&state.db, &id, &project_id, instruction, &resolved, base_commit.trim(), &session_id)
+        .await?;
+    tracing::info!(task_id = %id, project_id = %project_id, base_commit = %task.base_commit, "Task queued");
+    tokio::spawn(run(state, task.id.clone(), profile, pull_request, api_key));
+    Ok(task)
+}
+
+async fn run(
+    state: Arc<AppState>,
+    id: String,
+    profile: ClaudeProfile,
+    pull_request: Option<PullRequestOptions>,
+    api_key: Option<String>,
+) {
+    let Ok(Some(task)) = db::get_task(&state.db, &id).await else {
+        return;
+    };
+    let _ = db::start_task(&state.db, &id).await;
+    let config = state.config();
+    let workspace = match create_project_directory(&config.project_root, &task.project_id) {
+        Ok(workspace) => workspace,
+        Err(e) => {
+            let _ = db::finish_task(&state.db, &id, false, &json!(e.parts().1), None).await;
+            return;
+        }
+    };
+    let worktree = config.task_worktree_root.join(&task.id);
+
+    let outcome = match execute(&state, &task, &profile, api_key.as_deref(), &workspace, &worktree).await {
+        Ok((mut result, diff)) => match pull_request {
+            Some(options) if !diff.is_empty() => {
+                let token = options.token.as_deref().unwrap_or_default();
+                let summary = result["summary"].as_str().unwrap_or_default();
+                match github::open_pull_request(&state, &options, token, &task, &worktree, summary).await {
+                    Ok(pr) => {
+                        result["pull_request"] = pr;
+                        Ok((result, diff))
+                    }
+                    Err(e) => Err((e, Some(diff))),
+                }
+            }
+            _ => Ok((result, diff)),
+        },
+        Err(e) => Err((e, None)),
+    };
+    if git(&workspace, [OsStr::new("worktree"), "remove".as_ref(), "--force".as_ref(), worktree.as_os_str()])
+        .await
+        .is_err()
+    {
+        let _ = tokio::fs::remove_dir_all(&worktree).await;
+        let _ = git(&workspace, ["worktree", "prune"]).await;
+    }
+
+    let (succeeded, result, diff) = match outcome {
+        Ok((result, diff)) => (true, result, Some(diff)),
+        // A failed pull request keeps the diff it was for
+        Err((e, diff)) => {
+            tracing::warn!(task_id = %id, error = %e, "Task failed");
+            (false, json!(e.parts().1), diff)
+        }
+    };
+    if let Err(e) = db::finish_task(&state.db, &id, succeeded, &result, diff.as_deref()).await {
+        tracing::error!(task_id = %id, error = %e, "Failed to record task result");
+    }
+    tracing::info!(task_id = %id, succeeded, "Task finished");
+}
+
+/// Run the task in a new worktree at `worktree`, returning its result and
+/// diff.
+async fn execute(
+    state: &AppState,
+    task: &TaskRow,
+    profile: &ClaudeProfile,
+    api_key: Option<&str>,
+    workspace: &Path,
+    worktree: &Path,
+) -> Result<(Value, String), AppError> {
+    let config = state.config();
+    if let Some(parent) = worktree.parent() {
+        tokio::fs::create_dir_all(parent)
+            .await
+            .map_err(|e| AppError::Internal(format!("Failed to create {}: {e}", parent.display())))?;
+    }
+    git(
+        workspace,
+        [OsStr::new("worktree"), "add".as_ref(), "--detach".as_ref(), worktree.as_os_str(), task.base_commit.as_ref()],
+    )
+    .await?;
+    agents::materialize(state, &task.project_id, worktree).await?;
+    hooks::materialize(state, &task.project_id, worktree).await?;
+
+    if db::ensure_session(&state.db, &task.session_id, &task.project_id, &task.model).await? {
+        state.webhooks.emit(
+            "session.created",
+            json!({
+                "session_id": task.session_id,
+                "project_id": task.project_id,
+                "model": task.model,
+                "source": "task",
+                "task_id": task.id,
+            }),
+        );
+    }
+    let (stream, claude_sid) = state
+        .claude_manager
+        .create_session(
+            &task.session_id,
+            profile,
+            SpawnOptions {
+                prompt: &task.instruction,
+                model: &task.model,
+                system_prompt: None,
+                append_system_prompt: None,
+                disable_builtin_tools: false,
+                env: build_env(&config, profile, Some(&task.project_id)),
+                project_dir: worktree,
+                sandbox: state.sandbox.as_ref(),
+                limits: state.limits.as_ref(),
+                resume: None,
+                plan: false,
+                priority: Priority::Normal,
+            },
+        )
+        .await?;
+    let mut stream = transcript::record(state.db.clone(), task.session_id.clone(), stream);
+
+    let mut parts = Vec::new();
+    let mut reported = None;
+    let mut limited = None;
+    let mut auth_failed = None;
+    while let Some(msg) = stream.next().await {
+        limited = limited.or_else(|| upstream_limit(&msg));
+        auth_failed = auth_failed.or_else(|| auth_error(&msg));
+        if is_assistant_message(&msg) {
+            if let Some(text) = extract_assistant_content(&msg) {
+                parts.push(text);
+            }
+        }
+        if is_result_message(&msg) {
+            reported = extract_usage(&msg);
+            break;
+        }
+    }
+    let claude_session_id = claude_sid.unwrap_or_else(|| task.session_id.clone());
+    state.claude_manager.session_finished(&claude_session_id).await;
+    if let Some(message) = auth_failed {
+        auth::failed(state, &profile.name, &message);
+        return Err(AppError::ClaudeAuth(message));
+    }
+    if let Some(limit) = limited {
+        state.metrics.record_upstream_limit(limit.kind);
+        return Err(AppError::UpstreamLimited(limit));
+    }
+
+    let summary = parts.join("\n");
+    let (mut usage, _) = tokens::fill_usage(reported, &task.instruction, &summary);
+    state.pricing().fill_cost(&task.model, &mut usage);
+    budget::record(state, usage.cost_usd).await;
+    quota::record_tokens(state, api_key, usage.input_tokens + usage.output_tokens).await;
+    let metadata = json!({
+        "model": task.model,
+        "task_id": task.id,
+        "claude_session_id": claude_session_id,
+    });
+    let _ = db::add_message(&state.db, &task.session_id, "user", &task.instruction, 0, 0, 0.0, &metadata).await;
+    let _ = db::add_message(
+        &state.db,
+        &task.session_id,
+        "assistant",
+        &summary,
+        usage.input_tokens as i64,
+        usage.output_tokens as i64,
+        usage.cost_usd,
+        &metadata,
+    )
+    .await;
+    let _ = db::update_session_metrics(
+        &state.db,
+        &task.session_id,
+        (usage.input_tokens + usage.output_tokens) as i64,
+        usage.cost_usd,
+    )
+    .await;
+
+    // Staging picks up new files; the diff is against the base commit, so
+    // commits the run made are included too
+    git(worktree, ["add", "-A", "--", "."].iter().chain(GATEWAY_PATHS)).await?;
+    let diff = git(worktree, ["diff", "--cached", "--binary", &task.base_commit]).await?;
+    let files = git(worktree, ["diff", "--cached", "--name-status", &task.base_commit]).await?;
+    let result = json!({
+        "summary": summary,
+        "files": changed_files(&files),
+        "usage": {
+            "prompt_tokens": usage.input_tokens,
+            "completion_tokens": usage.output_tokens,
+            "total_tokens": usage.input_tokens + usage.output_tokens,
+        },
+        "cost_usd": usage.cost_usd,
+    });
+    Ok((result, diff))
+}
+
+/// Run git in `dir`, returning its output.
+pub(crate) async fn git<I, S>(dir: &Path, args: I) -> Result<String, AppError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    git_env(dir, args, &[]).await
+}
+
+/// [`git`] with extra environment variables.
+pub(crate) async fn git_env<I, S>(dir: &Path, args: I, env: &[(&str, &str)]) -> Result<String, AppError>
+where
+    I: IntoIterator<Item = S>,
+    S: AsRef<OsStr>,
+{
+    let args: Vec<_> = args.into_iter().map(|a| a.as_ref().to_os_string()).collect();
+    let output = tokio::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(&args)
+        .envs(env.iter().copied())
+        .output()
+        .await
+        .map_err(|e| AppError::Internal(format!("Failed to run git: {e}")))?;
+    if !output.status.success() {
+        return Err(AppError::Internal(format!(
+            "git {} failed: {}",
+            args.first().map(|a| a.to_string_lossy()).unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )));
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// `git diff --name-status` output as `{status, path}` objects; renames
+/// and copies also have `from`.
+fn changed_files(name_status: &str) -> Vec<Value> {
+    name_status
+        .lines()
+        .filter_map(|line| {
+            let mut fields = line.split('\t');
+            let code = fields.next()?;
+            let paths: Vec<&str> = fields.collect();
+            let status = match code.chars().next()? {
+                'A' => "added",
+                'D' => "deleted",
+                'R' => "renamed",
+                'C' => "copied",
+                'T' => "type_changed",
+                _ => "modified",
+            };
+            let mut file = json!({ "status": status, "path": paths.last()? });
+            if paths.len() == 2 {
+                file["from"] = json!(paths[0]);
+            }
+            Some(file)
+        })
+        .collect()
+}
+
+/// Fail tasks orphaned by a restart and remove their worktrees.
+pub async fn recover(state: &AppState) {
+    let interrupted = match db::fail_interrupted_tasks(&state.db).await {
+        Ok(interrupted) => interrupted,
+        Err(e) => {
+            tracing::warn!(error = %e, "Failed to recover interrupted tasks");
+            return;
+        }
+    };
+    if interrupted.is_empty() {
+        return;
+    }
+    tracing::warn!(tasks = interrupted.len(), "Marked tasks interrupted by restart as failed");
+    let config = state.config();
+    for task in interrupted {
+        let _ = tokio::fs::remove_dir_all(config.task_worktree_root.join(&task.id)).await;
+        if let Ok(workspace) = create_project_directory(&config.project_root, &task.project_id) {
+            let _ = git(&workspace, ["worktree", "prune"]).await;
+        }
+    }
+}
+
+/// An API `task` object; `result` (with the diff) or `error` is set once
+/// the task has finished.
+pub fn task_object(task: &TaskRow) -> Value {
+    let response = task.result.as_ref().map(|r| &r.0);
+    let (result, error) = match task.status.as_str() {
+        "succeeded" => {
+            let mut result = response.cloned().unwrap_or_else(|| json!({}));
+            result["diff"] = json!(task.diff);
+            (Some(result), None)
+        }
+        _ => (None, response.and_then(|r| r.get("error")).cloned()),
+    };
+    json!({
+        "id": task.id,
+        "object": "task",
+        "project_id": task.project_id,
+        "instruction": task.instruction,
+        "model": task.model,
+        "status": task.status,
+        "base_commit": task.base_commit,
+        "session_id": task.session_id,
+        "transcript_url": format!("/v1/sessions/{}/transcript", task.session_id),
+        "created_at": unix_time(Some(&task.created_at)),
+        "started_at": unix_time(task.started_at.as_deref()),
+        "completed_at": unix_time(task.completed_at.as_deref()),
+        "result": result,
+        "error": error,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use crate::claude::process::testing::run_cli;
+
+    #[test]
+    fn test_changed_files() {
+        let files = changed_files("M\tsrc/lib.rs\nA\tsrc/new.rs\nR087\told.rs\tnew.rs\nD\tgone.txt\n");
+        assert_eq!(
+            files,
+            [
+                json!({"status": "modified", "path": "src/lib.rs"}),
+                json!({"status": "added", "path": "src/new.rs"}),
+                json!({"status": "renamed", "path": "new.rs", "from": "old.rs"}),
+                json!({"status": "deleted", "path": "gone.txt"}),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_git_diff_of_worktree() {
+        let dir = tempfile::tempdir().unwrap();
+        let repo = dir.path().join("repo");
+        std::fs::create_dir(&repo).unwrap();
+        let run = |args: &[&str]| {
+            let status = std::process::Command::new("git").arg("-C").arg(&repo).args(args).output().unwrap();
+            assert!(status.status.success(), "git {args:?}");
+        };
+        run(&["init", "-q"]);
+        std::fs::write(repo.join("a.txt"), "one\n").unwrap();
+        run(&["add", "."]);
+        run(&["-c", "user.name=t", "-c", "user.email=t@t", "commit", "-qm", "init"]);
+
+        let base = git(&repo, ["rev-parse", "HEAD"]).await.unwrap();
+        let worktree = dir.path().join("wt");
+        git(&repo, [OsStr::new("worktree"), "add".as_ref(), "--detach".as_ref(), worktree.as_os_str(), base.trim().as_ref()])
+            .await
+            .unwrap();
+        std::fs::write(worktree.join("a.txt"), "two\n").unwrap();
+        std::fs::create_dir_all(worktree.join(".claude/agents")).unwrap();
+        std::fs::write(worktree.join(".claude/agents/x.md"), "agent").unwrap();
+        std::fs::write(worktree.join("b.txt"), "new\n").unwrap();
+
+        git(&worktree, ["add", "-A", "--", "."].iter().chain(GATEWAY_PATHS)).await.unwrap();
+        let files = git(&worktree, ["diff", "--cached", "--name-status", base.trim()]).await.unwrap();
+        assert_eq!(files, "M\ta.txt\nA\tb.txt\n");
+        let diff = git(&worktree, ["diff", "--cached", base.trim()]).await.unwrap();
+        assert!(diff.contains("-one\n+two\n"));
+        // The workspace is untouched
+        assert_eq!(std::fs::read_to_string(repo.join("a.txt")).unwrap(), "one\n");
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_agent_runs_in_worktree() {
+        let dir = tempfile::tempdir().unwrap();
+        let worktree = dir.path().canonicalize().unwrap();
+        let cwd = run_cli(&worktree, "pwd; echo made > made.txt").await;
+        assert_eq!(cwd, worktree.to_string_lossy());
+        assert_eq!(std::fs::read_to_string(worktree.join("made.txt")).unwrap(), "made\n");
+    }
+}
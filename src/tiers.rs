@@ -0,0 +1,148 @@
+//! Key tiers: named bundles of entitlements (`TIERS=free,standard,power`)
+//! assigned to API keys with `KEY_TIERS`, or to every other key with
+//! `DEFAULT_TIER`, instead of configuring each key on its own. A tier can
+//! set the key's rate limit (applied by [`crate::auth`]), the models it may
+//! use (matched after aliases are resolved), a ceiling on `max_tokens` (also the default when a request sets
+//! none) and the features it may use:
+//!
+//! - `tools`: requests with `tools`;
+//! - `vision`: messages with image parts;
+//! - `batch`: background completion jobs (`?async=true`).
+//!
+//! It also caps the `X-Priority` the key may ask for (see
+//! [`crate::priority`]). Tasks and slash commands are held to the model
+//! allowlist too, and refused for tiers with a `max_tokens` ceiling, since
+//! their CLI runs can't be bounded by one. Requests outside their tier fail
+//! with `403 forbidden`. Keys without a tier are unrestricted.
+
+use crate::config::Tier;
+use crate::error::AppError;
+use crate::models::openai::ChatCompletionRequest;
+
+/// The features a tier can allow.
+pub const FEATURES: &[&str] = &["tools", "vision", "batch"];
+
+/// Fail with `forbidden` unless `tier` allows `feature`.
+pub fn check_feature(tier: Option<&Tier>, feature: &str) -> Result<(), AppError> {
+    match tier {
+        Some(tier) if !tier.features.iter().any(|f| f == feature) => Err(AppError::Forbidden(format!(
+            "The {} tier does not include {feature}",
+            tier.name
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Check a completion request, routed to `claude_model`, against `tier`,
+/// and bound its `max_tokens` by the tier's ceiling. The model allowlist
+/// applies to `claude_model` alone: the requested name may be an alias, or
+/// unknown and routed to the fallback model.
+pub fn enforce(tier: Option<&Tier>, request: &mut ChatCompletionRequest, claude_model: &str) -> Result<(), AppError> {
+    let Some(tier) = tier else {
+        return Ok(());
+    };
+    check_model(tier, &request.model, claude_model)?;
+    if let Some(ceiling) = tier.max_tokens {
+        match request.max_tokens {
+            Some(requested) if requested > ceiling => {
+                return Err(AppError::Forbidden(format!(
+                    "The {} tier allows max_tokens up to {ceiling}",
+                    tier.name
+                )));
+            }
+            Some(_) => {}
+            None => request.max_tokens = Some(ceiling),
+        }
+    }
+    if request.tools.as_ref().is_some_and(|t| !t.is_empty()) {
+        check_feature(Some(tier), "tools")?;
+    }
+    if has_images(request) {
+        check_feature(Some(tier), "vision")?;
+    }
+    Ok(())
+}
+
+/// Check a CLI run outside a completion (`what`, e.g. "Tasks"), for
+/// `requested` routed to `claude_model`, against `tier`. These runs take no
+/// `max_tokens`, so tiers with a ceiling may not start them.
+pub fn enforce_run(tier: Option<&Tier>, what: &str, requested: &str, claude_model: &str) -> Result<(), AppError> {
+    let Some(tier) = tier else {
+        return Ok(());
+    };
+    check_model(tier, requested, claude_model)?;
+    if tier.max_tokens.is_some() {
+        return Err(AppError::Forbidden(format!(
+            "{what} can't be held to the max_tokens ceiling of the {} tier",
+            tier.name
+        )));
+    }
+    Ok(())
+}
+
+fn check_model(tier: &Tier, requested: &str, claude_model: &str) -> Result<(), AppError> {
+    let allowed = |model: &str| tier.models.iter().any(|prefix| model.starts_with(prefix.as_str()));
+    if !tier.models.is_empty() && !allowed(claude_model) {
+        return Err(AppError::Forbidden(format!(
+            "The {} tier may not use model {requested} ({claude_model})",
+            tier.name
+        )));
+    }
+    Ok(())
+}
+
+fn has_images(request: &ChatCompletionRequest) -> bool {
+    request.messages.iter().any(|m| match m.content {
+        Some(serde_json::Value::Array(ref parts)) => parts
+            .iter()
+            .any(|p| p.get("type").and_then(|t| t.as_str()) == Some("image_url")),
+        _ => false,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::priority::Priority;
+    use serde_json::json;
+
+    #[test]
+    fn test_enforce() {
+        let tier = Tier {
+            name: "free".to_string(),
+            requests_per_minute: Some(10),
+            models: vec!["claude-haiku".to_string()],
+            max_tokens: Some(1024),
+            features: vec!["tools".to_string()],
+            max_priority: Priority::Normal,
+        };
+        let request = |body: serde_json::Value| -> ChatCompletionRequest { serde_json::from_value(body).unwrap() };
+        let text = json!([{"role": "user", "content": "hi"}]);
+
+        let mut ok = request(json!({"model": "cc-haiku-45", "messages": text}));
+        enforce(Some(&tier), &mut ok, "claude-haiku-4-5").unwrap();
+        assert_eq!(ok.max_tokens, Some(1024));
+        let mut opus = request(json!({"model": "cc-opus-4", "messages": text}));
+        assert!(matches!(enforce(Some(&tier), &mut opus, "claude-opus-4-1"), Err(AppError::Forbidden(_))));
+        enforce(None, &mut opus, "claude-opus-4-1").unwrap();
+        // An unknown name matching a prefix is judged by the model it falls back to
+        let mut unknown = request(json!({"model": "claude-haiku-x", "messages": text}));
+        assert!(matches!(enforce(Some(&tier), &mut unknown, "claude-sonnet-4-5"), Err(AppError::Forbidden(_))));
+
+        let mut long = request(json!({"model": "cc-haiku-45", "messages": text, "max_tokens": 4096}));
+        assert!(enforce(Some(&tier), &mut long, "claude-haiku-4-5").is_err());
+        let image = json!([{"role": "user", "content": [{"type": "image_url", "image_url": {"url": "data:image/png;base64,AA=="}}]}]);
+        let mut vision = request(json!({"model": "cc-haiku-45", "messages": image}));
+        assert!(enforce(Some(&tier), &mut vision, "claude-haiku-4-5").is_err());
+
+        assert!(check_feature(Some(&tier), "tools").is_ok());
+        assert!(check_feature(Some(&tier), "batch").is_err());
+        assert!(check_feature(None, "batch").is_ok());
+
+        assert!(matches!(enforce_run(Some(&tier), "Tasks", "cc-haiku-45", "claude-haiku-4-5"), Err(AppError::Forbidden(_))));
+        let uncapped = Tier { max_tokens: None, ..tier };
+        enforce_run(Some(&uncapped), "Tasks", "cc-haiku-45", "claude-haiku-4-5").unwrap();
+        assert!(enforce_run(Some(&uncapped), "Tasks", "claude-haiku-x", "claude-sonnet-4-5").is_err());
+        enforce_run(None, "Tasks", "cc-opus-4", "claude-opus-4-1").unwrap();
+    }
+}
@@ -0,0 +1,118 @@
+//! Per-completion timings, so client-side latency can be split into
+//! gateway overhead, CLI startup and model time. They are returned as a
+//! `Server-Timing` header and as `metadata.timing`; aggregates are in the
+//! `/admin/metrics` histograms.
+
+use std::time::Instant;
+
+use axum::http::HeaderValue;
+use axum::response::Response;
+
+use crate::models::openai::Timing;
+
+/// Time to first token in milliseconds, for clients that don't parse
+/// `Server-Timing`.
+pub const TTFT_HEADER: &str = "x-ttft-ms";
+
+/// Marks taken while a completion runs.
+pub struct Stopwatch {
+    started: Instant,
+    spawned: Option<u64>,
+    first_event: Option<u64>,
+    first_token: Option<u64>,
+}
+
+impl Stopwatch {
+    /// Time from `started`, when the request arrived.
+    pub fn new(started: Instant) -> Self {
+        Self { started, spawned: None, first_event: None, first_token: None }
+    }
+
+    fn elapsed(&self) -> u64 {
+        self.started.elapsed().as_millis() as u64
+    }
+
+    /// The CLI was started, or a run already going was joined.
+    pub fn spawned(&mut self) {
+        let now = self.elapsed();
+        self.spawned.get_or_insert(now);
+    }
+
+    /// The CLI printed a line.
+    pub fn event(&mut self) {
+        let now = self.elapsed();
+        self.first_event.get_or_insert(now);
+    }
+
+    /// Completion text arrived.
+    pub fn token(&mut self) {
+        let now = self.elapsed();
+        self.first_token.get_or_insert(now);
+    }
+
+    /// The timings so far, the completion being done.
+    pub fn finish(&self) -> Timing {
+        let total_ms = self.elapsed();
+        let gateway_ms = self.spawned.unwrap_or(total_ms);
+        Timing {
+            gateway_ms,
+            spawn_ms: self.first_event.map(|t| t.saturating_sub(gateway_ms)),
+            ttft_ms: self.first_token,
+            generation_ms: self.first_token.map(|t| total_ms.saturating_sub(t)),
+            total_ms,
+        }
+    }
+}
+
+/// `timing` as a `Server-Timing` header value.
+pub fn server_timing(timing: &Timing) -> String {
+    let marks = [
+        ("gateway", Some(timing.gateway_ms)),
+        ("spawn", timing.spawn_ms),
+        ("ttft", timing.ttft_ms),
+        ("generation", timing.generation_ms),
+        ("total", Some(timing.total_ms)),
+    ];
+    marks
+        .iter()
+        .filter_map(|(name, ms)| ms.map(|ms| format!("{name};dur={ms}")))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+/// Add the `Server-Timing` and [`TTFT_HEADER`] headers to `response`.
+pub fn tag(mut response: Response, timing: &Timing) -> Response {
+    let headers = response.headers_mut();
+    if let Ok(value) = HeaderValue::from_str(&server_timing(timing)) {
+        headers.insert("server-timing", value);
+    }
+    if let Some(ttft) = timing.ttft_ms {
+        headers.insert(TTFT_HEADER, HeaderValue::from(ttft));
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_server_timing() {
+        let timing = Timing {
+            gateway_ms: 12,
+            spawn_ms: Some(340),
+            ttft_ms: None,
+            generation_ms: None,
+            total_ms: 400,
+        };
+        assert_eq!(server_timing(&timing), "gateway;dur=12, spawn;dur=340, total;dur=400");
+
+        let mut watch = Stopwatch::new(Instant::now());
+        watch.spawned();
+        watch.token();
+        let timing = watch.finish();
+        assert!(timing.spawn_ms.is_none());
+        assert_eq!(timing.ttft_ms.map(|t| t >= timing.gateway_ms), Some(true));
+        assert!(timing.total_ms >= timing.gateway_ms);
+    }
+}
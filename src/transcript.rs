@@ -0,0 +1,62 @@
+use futures::StreamExt;
+use serde_json::Value;
+use sqlx::SqlitePool;
+use tokio::sync::mpsc;
+
+use crate::claude::process::MessageStream;
+use crate::db;
+
+/// Pass `stream` through unchanged while appending every event to the
+/// session's transcript (`STORE_TRANSCRIPTS=true`).
+///
+/// Writes happen on a background task, batched by whatever has arrived since
+/// the last insert, so a slow database never stalls the response.
+pub fn record(db: SqlitePool, session_id: String, stream: MessageStream) -> MessageStream {
+    let (tx, mut rx) = mpsc::unbounded_channel::<Value>();
+    tokio::spawn(async move {
+        while let Some(first) = rx.recv().await {
+            let mut batch = vec![first];
+            while let Ok(event) = rx.try_recv() {
+                batch.push(event);
+            }
+            if let Err(e) = db::add_transcript_events(&db, &session_id, &batch).await {
+                tracing::warn!(error = %e, session_id = %session_id, "Failed to store transcript events");
+            }
+        }
+    });
+    Box::pin(stream.inspect(move |event| {
+        let _ = tx.send(event.clone());
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_record_passes_through_and_stores() {
+        let dir = tempfile::tempdir().unwrap();
+        let url = format!("sqlite:{}", dir.path().join("t.db").display());
+        let pool = db::init_db(&url).await.unwrap();
+
+        let events = vec![
+            serde_json::json!({"type": "system", "subtype": "init"}),
+            serde_json::json!({"type": "assistant", "message": {"content": [{"type": "thinking"}]}}),
+        ];
+        let stream = record(pool.clone(), "s1".into(), Box::pin(futures::stream::iter(events.clone())));
+        let seen: Vec<Value> = stream.collect().await;
+        assert_eq!(seen, events);
+
+        // The writer finishes once the stream (and its sender) is dropped
+        let mut stored = Vec::new();
+        for _ in 0..50 {
+            stored = db::get_transcript(&pool, "s1").await.unwrap();
+            if stored.len() == events.len() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let stored: Vec<Value> = stored.iter().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(stored, events);
+    }
+}
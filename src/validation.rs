@@ -0,0 +1,350 @@
+//! Up-front checks on chat completion requests, reported like OpenAI does:
+//! an `invalid_request_error` whose `param` points at the offending field.
+
+use std::collections::HashSet;
+
+use serde_json::Value;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::models::openai::ChatCompletionRequest;
+
+/// Roles a client may send; `summary` rows only come from compaction.
+const ROLES: &[&str] = &["system", "developer", "user", "assistant", "tool", "function"];
+/// OpenAI accepts at most this many stop sequences.
+const MAX_STOP: usize = 4;
+/// Longest end user ID, the `user` field.
+const MAX_USER_LEN: usize = 256;
+
+fn invalid(param: impl Into<String>, message: impl Into<String>) -> AppError {
+    AppError::InvalidParam { param: param.into(), message: message.into() }
+}
+
+fn check_range(param: &str, value: Option<f64>, min: f64, max: f64) -> Result<(), AppError> {
+    match value {
+        Some(v) if !(min..=max).contains(&v) => {
+            Err(invalid(param, format!("{v} is not between {min} and {max}")))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Fail unless `project_id` can name a directory under `PROJECT_ROOT`:
+/// letters, digits, `.`, `_` and `-` only, not starting with `.` (which
+/// covers `..` and the gateway's own directories such as `.files`).
+pub fn check_project_id(project_id: &str) -> Result<(), AppError> {
+    let safe = project_id.bytes().all(|b| b.is_ascii_alphanumeric() || matches!(b, b'.' | b'_' | b'-'));
+    if !safe || project_id.is_empty() || project_id.starts_with('.') {
+        return Err(invalid(
+            "project_id",
+            "may only contain letters, digits, '.', '_' and '-', and must not start with '.'",
+        ));
+    }
+    Ok(())
+}
+
+/// Validate `request` against the API's parameter rules and the
+/// `MAX_MESSAGES` / `MAX_MESSAGE_BYTES` / `MAX_PROMPT_BYTES` limits.
+pub fn validate_chat_request(request: &ChatCompletionRequest, config: &Config) -> Result<(), AppError> {
+    if request.model.trim().is_empty() {
+        return Err(invalid("model", "must not be empty"));
+    }
+    check_range("temperature", request.temperature, 0.0, 2.0)?;
+    check_range("top_p", request.top_p, 0.0, 1.0)?;
+    check_range("frequency_penalty", request.frequency_penalty, -2.0, 2.0)?;
+    check_range("presence_penalty", request.presence_penalty, -2.0, 2.0)?;
+    if request.max_tokens == Some(0) {
+        return Err(invalid("max_tokens", "must be at least 1"));
+    }
+    if request.user.as_ref().is_some_and(|u| u.len() > MAX_USER_LEN) {
+        return Err(invalid("user", format!("must be at most {MAX_USER_LEN} bytes")));
+    }
+    if let Some(ref project_id) = request.project_id {
+        check_project_id(project_id)?;
+    }
+    if !matches!(request.mode.as_deref(), None | Some("default" | "plan")) {
+        return Err(invalid("mode", "must be 'default' or 'plan'"));
+    }
+    validate_stop(request.stop.as_ref())?;
+    validate_messages(request, config)?;
+    validate_tools(request)
+}
+
+fn validate_stop(stop: Option<&Value>) -> Result<(), AppError> {
+    match stop {
+        None | Some(Value::Null) | Some(Value::String(_)) => Ok(()),
+        Some(Value::Array(items)) => {
+            if items.len() > MAX_STOP {
+                return Err(invalid("stop", format!("at most {MAX_STOP} stop sequences are allowed")));
+            }
+            match items.iter().position(|s| !s.is_string()) {
+                Some(i) => Err(invalid(format!("stop[{i}]"), "must be a string")),
+                None => Ok(()),
+            }
+        }
+        Some(_) => Err(invalid("stop", "must be a string or an array of strings")),
+    }
+}
+
+fn validate_messages(request: &ChatCompletionRequest, config: &Config) -> Result<(), AppError> {
+    let messages = &request.messages;
+    if messages.is_empty() {
+        return Err(invalid("messages", "at least one message is required"));
+    }
+    if config.max_messages > 0 && messages.len() > config.max_messages {
+        return Err(invalid(
+            "messages",
+            format!("{} messages exceed the limit of {}", messages.len(), config.max_messages),
+        ));
+    }
+    let mut total = 0;
+    for (i, msg) in messages.iter().enumerate() {
+        if !ROLES.contains(&msg.role.as_str()) {
+            return Err(invalid(
+                format!("messages[{i}].role"),
+                format!("'{}' is not one of {}", msg.role, ROLES.join(", ")),
+            ));
+        }
+        let size = match &msg.content {
+            None | Some(Value::Null) => 0,
+            Some(Value::String(s)) => s.len(),
+            Some(content @ Value::Array(_)) => content.to_string().len(),
+            Some(_) => {
+                return Err(invalid(
+                    format!("messages[{i}].content"),
+                    "must be a string or an array of content parts",
+                ));
+            }
+        };
+        if config.max_message_bytes > 0 && size > config.max_message_bytes {
+            return Err(invalid(
+                format!("messages[{i}].content"),
+                format!("{size} bytes exceed the limit of {}", config.max_message_bytes),
+            ));
+        }
+        total += size;
+        if msg.role == "tool" && msg.tool_call_id.as_deref().is_none_or(str::is_empty) {
+            return Err(invalid(
+                format!("messages[{i}].tool_call_id"),
+                "is required for tool messages",
+            ));
+        }
+    }
+    if !messages.iter().any(|m| m.role == "user") {
+        return Err(invalid("messages", "at least one user message is required"));
+    }
+    if config.max_prompt_bytes > 0 && total > config.max_prompt_bytes {
+        return Err(invalid(
+            "messages",
+            format!("{total} bytes of message content exceed the prompt limit of {}", config.max_prompt_bytes),
+        ));
+    }
+    Ok(())
+}
+
+/// Enforce `MAX_PROMPT_BYTES` on the prompt as rendered for the CLI, which
+/// adds the system prompt, tool definitions and retrieved context to the
+/// messages.
+pub fn check_prompt_size(prompt: &str, config: &Config) -> Result<(), AppError> {
+    if config.max_prompt_bytes > 0 && prompt.len() > config.max_prompt_bytes {
+        return Err(invalid(
+            "messages",
+            format!("the rendered prompt is {} bytes, over the limit of {}", prompt.len(), config.max_prompt_bytes),
+        ));
+    }
+    Ok(())
+}
+
+fn valid_function_name(name: &str) -> bool {
+    (1..=64).contains(&name.len())
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn validate_tools(request: &ChatCompletionRequest) -> Result<(), AppError> {
+    let tools = request.tools.as_deref().unwrap_or_default();
+    let mut names = HashSet::new();
+    for (i, tool) in tools.iter().enumerate() {
+        if tool.tool_type != "function" {
+            return Err(invalid(
+                format!("tools[{i}].type"),
+                format!("'{}' is not supported; expected 'function'", tool.tool_type),
+            ));
+        }
+        let name = &tool.function.name;
+        if !valid_function_name(name) {
+            return Err(invalid(
+                format!("tools[{i}].function.name"),
+                "must be 1-64 characters of a-z, A-Z, 0-9, underscores and dashes",
+            ));
+        }
+        if !names.insert(name.as_str()) {
+            return Err(invalid(
+                format!("tools[{i}].function.name"),
+                format!("duplicate function name '{name}'"),
+            ));
+        }
+        if let Some(params) = &tool.function.parameters {
+            let is_object_schema = params
+                .as_object()
+                .is_some_and(|o| o.get("type").is_none_or(|t| t == "object"));
+            if !is_object_schema {
+                return Err(invalid(
+                    format!("tools[{i}].function.parameters"),
+                    "must be a JSON Schema object with \"type\": \"object\"",
+                ));
+            }
+        }
+    }
+
+    match &request.tool_choice {
+        None | Some(Value::Null) => Ok(()),
+        Some(Value::String(s)) if matches!(s.as_str(), "none" | "auto" | "required") => {
+            if s == "required" && tools.is_empty() {
+                Err(invalid("tool_choice", "'required' needs at least one tool"))
+            } else {
+                Ok(())
+            }
+        }
+        Some(choice @ Value::Object(_)) => {
+            match choice.pointer("/function/name").and_then(Value::as_str) {
+                Some(name) if names.contains(name) => Ok(()),
+                Some(name) => Err(invalid(
+                    "tool_choice.function.name",
+                    format!("'{name}' is not one of the request's tools"),
+                )),
+                None => Err(invalid("tool_choice.function.name", "is required")),
+            }
+        }
+        Some(_) => Err(invalid(
+            "tool_choice",
+            "must be 'none', 'auto', 'required' or a function object",
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::claude::manager::create_project_directory;
+
+    fn request(body: Value) -> ChatCompletionRequest {
+        serde_json::from_value(body).unwrap()
+    }
+
+    fn param(body: Value, config: &Config) -> Option<String> {
+        match validate_chat_request(&request(body), config) {
+            Ok(()) => None,
+            Err(AppError::InvalidParam { param, .. }) => Some(param),
+            Err(e) => panic!("unexpected error {e}"),
+        }
+    }
+
+    #[test]
+    fn test_validate_chat_request() {
+        let mut config = Config::for_test();
+        config.max_messages = 3;
+        config.max_message_bytes = 16;
+        config.max_prompt_bytes = 40;
+        let user = serde_json::json!({"role": "user", "content": "hi"});
+        let check = |extra: Value| {
+            let mut body = serde_json::json!({"model": "cc-sonnet-45", "messages": [user]});
+            body.as_object_mut().unwrap().extend(extra.as_object().unwrap().clone());
+            param(body, &config)
+        };
+
+        assert_eq!(check(serde_json::json!({"temperature": 1.5})), None);
+        assert_eq!(check(serde_json::json!({"temperature": 2.5})).as_deref(), Some("temperature"));
+        assert_eq!(check(serde_json::json!({"stop": ["a", 1]})).as_deref(), Some("stop[1]"));
+        assert_eq!(check(serde_json::json!({"user": "u".repeat(257)})).as_deref(), Some("user"));
+        assert_eq!(check(serde_json::json!({"mode": "plan"})), None);
+        assert_eq!(check(serde_json::json!({"mode": "yolo"})).as_deref(), Some("mode"));
+        assert_eq!(
+            check(serde_json::json!({"messages": [user, {"role": "robot", "content": "x"}]})).as_deref(),
+            Some("messages[1].role")
+        );
+        assert_eq!(
+            check(serde_json::json!({"messages": [{"role": "user", "content": "x".repeat(17)}]})).as_deref(),
+            Some("messages[0].content")
+        );
+        assert_eq!(
+            check(serde_json::json!({"messages": [user, user, user, user]})).as_deref(),
+            Some("messages")
+        );
+        let long = serde_json::json!({"role": "user", "content": "x".repeat(15)});
+        assert_eq!(check(serde_json::json!({"messages": [long, long]})), None);
+        assert_eq!(check(serde_json::json!({"messages": [long, long, long]})).as_deref(), Some("messages"));
+        assert!(check_prompt_size(&"x".repeat(40), &config).is_ok());
+        assert!(check_prompt_size(&"x".repeat(41), &config).is_err());
+        assert_eq!(
+            check(serde_json::json!({"messages": [user, {"role": "tool", "content": "42"}]})).as_deref(),
+            Some("messages[1].tool_call_id")
+        );
+    }
+
+    #[test]
+    fn test_project_id_traversal() {
+        let config = Config::for_test();
+        let check = |project_id: &str| {
+            param(
+                serde_json::json!({"model": "cc-sonnet-45", "messages": [{"role": "user", "content": "hi"}], "project_id": project_id}),
+                &config,
+            )
+        };
+        for id in ["default", "acme-web_2", "v1.2"] {
+            assert_eq!(check(id), None, "{id:?}");
+        }
+        for id in ["", ".", "..", ".files", "../..", "../etc", "/etc", "a/b", "a b", "a\\b"] {
+            assert_eq!(check(id).as_deref(), Some("project_id"), "{id:?}");
+        }
+
+        let root = tempfile::tempdir().unwrap();
+        let projects = root.path().join("projects");
+        assert!(create_project_directory(&projects, "../escape").is_err());
+        assert!(create_project_directory(&projects, "/tmp").is_err());
+        assert!(!root.path().join("escape").exists());
+        assert_eq!(create_project_directory(&projects, "default").unwrap(), projects.join("default"));
+    }
+
+    #[test]
+    fn test_validate_tools() {
+        let config = Config::for_test();
+        let tool = |name: &str, params: Value| {
+            serde_json::json!({"type": "function", "function": {"name": name, "parameters": params}})
+        };
+        let check = |tools: Vec<Value>, choice: Value| {
+            param(
+                serde_json::json!({
+                    "model": "cc-sonnet-45",
+                    "messages": [{"role": "user", "content": "hi"}],
+                    "tools": tools,
+                    "tool_choice": choice,
+                }),
+                &config,
+            )
+        };
+        let schema = serde_json::json!({"type": "object", "properties": {}});
+
+        assert_eq!(check(vec![tool("get_weather", schema.clone())], serde_json::json!("auto")), None);
+        assert_eq!(
+            check(vec![tool("get weather", schema.clone())], Value::Null).as_deref(),
+            Some("tools[0].function.name")
+        );
+        assert_eq!(
+            check(vec![tool("a", schema.clone()), tool("a", schema.clone())], Value::Null).as_deref(),
+            Some("tools[1].function.name")
+        );
+        assert_eq!(
+            check(vec![tool("a", serde_json::json!({"type": "array"}))], Value::Null).as_deref(),
+            Some("tools[0].function.parameters")
+        );
+        assert_eq!(
+            check(
+                vec![tool("a", schema)],
+                serde_json::json!({"type": "function", "function": {"name": "b"}})
+            )
+            .as_deref(),
+            Some("tool_choice.function.name")
+        );
+        assert_eq!(check(vec![], serde_json::json!("required")).as_deref(), Some("tool_choice"));
+    }
+}
@@ -0,0 +1,240 @@
+//! Webhooks for gateway events, for incident and analytics pipelines. Each
+//! event is POSTed as JSON to every `WEBHOOK_URLS` endpoint (optionally
+//! only the `WEBHOOK_EVENTS` types):
+//!
+//! - `session.created`: a new session was stored (`source` is `chat`,
+//!   `api` or `task`).
+//! - `session.finished` / `session.failed`: a CLI run ended; it failed if
+//!   it exited non-zero, was killed by a signal or a resource limit, or was
+//!   stopped (`reason`).
+//! - `budget.threshold_crossed`: today's or this month's spend passed one
+//!   of `BUDGET_ALERT_PERCENTS` of its ceiling.
+//! - `budget.breaker_opened` / `budget.breaker_closed`: runs started
+//!   failing with `budget_exceeded`, or are let through again. The
+//!   `reason` is `budget_exceeded` or `restored` (an admin ended an
+//!   override) for the first, `reset`, `disabled` or `override` for the
+//!   second.
+//! - `profile.logged_out` / `profile.logged_in`: a profile's CLI started
+//!   failing every run on its login, or works again.
+//!
+//! The body is `{"id", "type", "created_at", "instance_id", "data"}`. With
+//! `WEBHOOK_SECRET` set, `X-Webhook-Signature: t=<unix time>,v1=<hex>`
+//! carries the HMAC-SHA256 of `<t>.<body>` under the secret; receivers
+//! should recompute it and reject stale timestamps. Deliveries run in the
+//! background and are retried with exponential backoff on network errors,
+//! 429s and 5xx, up to `WEBHOOK_MAX_ATTEMPTS` times; at most
+//! [`MAX_PENDING`] wait at once, events beyond that are dropped and counted.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use ring::hmac;
+use serde_json::{json, Value};
+
+use crate::config::Config;
+
+/// Deliveries allowed to wait or be in flight at once.
+pub const MAX_PENDING: usize = 1000;
+/// Longest wait between two attempts.
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Default)]
+struct Settings {
+    urls: Vec<String>,
+    secret: Option<String>,
+    events: Vec<String>,
+    max_attempts: u32,
+    timeout: Duration,
+    instance_id: String,
+}
+
+impl Settings {
+    fn from_config(config: &Config) -> Self {
+        Self {
+            urls: config.webhook_urls.clone(),
+            secret: config.webhook_secret.clone(),
+            events: config.webhook_events.clone(),
+            max_attempts: config.webhook_max_attempts.max(1),
+            timeout: Duration::from_secs(config.webhook_timeout_seconds),
+            instance_id: config.instance_id.clone(),
+        }
+    }
+
+    fn wants(&self, event: &str) -> bool {
+        !self.urls.is_empty() && (self.events.is_empty() || self.events.iter().any(|e| e == event))
+    }
+}
+
+/// The webhook sender; clones share settings and counters.
+#[derive(Clone)]
+pub struct Webhooks(Arc<Inner>);
+
+struct Inner {
+    settings: RwLock<Arc<Settings>>,
+    http: reqwest::Client,
+    pending: AtomicUsize,
+    delivered: AtomicU64,
+    retried: AtomicU64,
+    failed: AtomicU64,
+    dropped: AtomicU64,
+}
+
+impl Webhooks {
+    pub fn new(config: &Config) -> Self {
+        Self(Arc::new(Inner {
+            settings: RwLock::new(Arc::new(Settings::from_config(config))),
+            http: reqwest::Client::new(),
+            pending: AtomicUsize::new(0),
+            delivered: AtomicU64::new(0),
+            retried: AtomicU64::new(0),
+            failed: AtomicU64::new(0),
+            dropped: AtomicU64::new(0),
+        }))
+    }
+
+    /// Apply reloaded settings; deliveries under way keep the old ones.
+    pub fn configure(&self, config: &Config) {
+        *self.0.settings.write().unwrap_or_else(|e| e.into_inner()) = Arc::new(Settings::from_config(config));
+    }
+
+    fn settings(&self) -> Arc<Settings> {
+        Arc::clone(&self.0.settings.read().unwrap_or_else(|e| e.into_inner()))
+    }
+
+    /// Send `event` to every endpoint in the background. Never blocks.
+    pub fn emit(&self, event: &str, data: Value) {
+        let settings = self.settings();
+        if !settings.wants(event) {
+            return;
+        }
+        let id = format!("evt_{}", uuid::Uuid::new_v4().as_simple());
+        let body = json!({
+            "id": id,
+            "type": event,
+            "created_at": chrono::Utc::now().timestamp(),
+            "instance_id": settings.instance_id,
+            "data": data,
+        });
+        let body: Arc<str> = body.to_string().into();
+        for url in &settings.urls {
+            if self.0.pending.fetch_add(1, Ordering::Relaxed) >= MAX_PENDING {
+                self.0.pending.fetch_sub(1, Ordering::Relaxed);
+                self.0.dropped.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!(event, url, "Webhook queue full, dropping event");
+                continue;
+            }
+            let delivery = Delivery {
+                inner: Arc::clone(&self.0),
+                settings: Arc::clone(&settings),
+                url: url.clone(),
+                event: event.to_string(),
+                id: id.clone(),
+                body: Arc::clone(&body),
+            };
+            tokio::spawn(delivery.run());
+        }
+    }
+
+    /// Delivery counters, for `/admin/metrics`.
+    pub fn stats(&self) -> Value {
+        let settings = self.settings();
+        json!({
+            "endpoints": settings.urls.len(),
+            "pending": self.0.pending.load(Ordering::Relaxed),
+            "delivered_total": self.0.delivered.load(Ordering::Relaxed),
+            "retried_total": self.0.retried.load(Ordering::Relaxed),
+            "failed_total": self.0.failed.load(Ordering::Relaxed),
+            "dropped_total": self.0.dropped.load(Ordering::Relaxed),
+        })
+    }
+}
+
+struct Delivery {
+    inner: Arc<Inner>,
+    settings: Arc<Settings>,
+    url: String,
+    event: String,
+    id: String,
+    body: Arc<str>,
+}
+
+impl Delivery {
+    async fn run(self) {
+        let delivered = self.attempts().await;
+        let counter = if delivered { &self.inner.delivered } else { &self.inner.failed };
+        counter.fetch_add(1, Ordering::Relaxed);
+        self.inner.pending.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    /// Whether an attempt succeeded.
+    async fn attempts(&self) -> bool {
+        let max = self.settings.max_attempts;
+        for attempt in 1..=max {
+            let timestamp = chrono::Utc::now().timestamp();
+            let mut request = self
+                .inner
+                .http
+                .post(&self.url)
+                .timeout(self.settings.timeout)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header("x-webhook-id", &self.id)
+                .header("x-webhook-event", &self.event)
+                .header("x-webhook-attempt", attempt.to_string())
+                .body(self.body.to_string());
+            if let Some(ref secret) = self.settings.secret {
+                request = request.header("x-webhook-signature", signature(secret, timestamp, &self.body));
+            }
+            let outcome = match request.send().await {
+                Ok(res) if res.status().is_success() => return true,
+                Ok(res) => {
+                    let status = res.status();
+                    if !(status.is_server_error() || status == reqwest::StatusCode::TOO_MANY_REQUESTS) {
+                        tracing::warn!(url = %self.url, event = %self.event, %status, "Webhook rejected; not retrying");
+                        return false;
+                    }
+                    status.to_string()
+                }
+                Err(e) => e.to_string(),
+            };
+            if attempt == max {
+                tracing::warn!(url = %self.url, event = %self.event, attempts = max, error = %outcome, "Webhook delivery failed");
+                break;
+            }
+            tracing::debug!(url = %self.url, event = %self.event, attempt, error = %outcome, "Webhook delivery failed; retrying");
+            self.inner.retried.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(backoff(attempt)).await;
+        }
+        false
+    }
+}
+
+/// `t=<timestamp>,v1=<hex HMAC-SHA256 of "<timestamp>.<body>">`.
+pub fn signature(secret: &str, timestamp: i64, body: &str) -> String {
+    let key = hmac::Key::new(hmac::HMAC_SHA256, secret.as_bytes());
+    let tag = hmac::sign(&key, format!("{timestamp}.{body}").as_bytes());
+    let hex: String = tag.as_ref().iter().map(|b| format!("{b:02x}")).collect();
+    format!("t={timestamp},v1={hex}")
+}
+
+/// The wait after failed attempt `attempt`: 1s, 2s, 4s, … up to a minute.
+fn backoff(attempt: u32) -> Duration {
+    Duration::from_secs(1u64 << attempt.saturating_sub(1).min(6)).min(MAX_BACKOFF)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        // Reference value from `printf '1700000000.{}' | openssl dgst -sha256 -hmac secret`
+        assert_eq!(
+            signature("secret", 1_700_000_000, "{}"),
+            "t=1700000000,v1=b8569b78799ff9e3cbff0fc2d63a33a2b57f3282abd07c37ae5e8e7d79a5f163"
+        );
+        assert_eq!(backoff(1), Duration::from_secs(1));
+        assert_eq!(backoff(3), Duration::from_secs(4));
+        assert_eq!(backoff(20), MAX_BACKOFF);
+    }
+}